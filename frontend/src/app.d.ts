@@ -8,6 +8,11 @@ declare global {
 		// interface PageState {}
 		// interface Platform {}
 	}
+
+	interface Window {
+		// Injected by the Rust server into index.html when it serves the dashboard
+		__WALLET_API_BASE__?: string;
+	}
 }
 
 export {};
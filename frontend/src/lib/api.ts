@@ -1,4 +1,4 @@
-import type { Health, Transaction, User } from './types';
+import type { Health, Money, Period, Problem, Transaction, User } from './types';
 
 async function readJson(res: Response) {
 	const text = await res.text();
@@ -23,6 +23,10 @@ async function api<T>(baseUrl: string, path: string, init?: RequestInit): Promis
 
 	if (!res.ok) {
 		const body = await readJson(res);
+		if (body && typeof body === 'object' && 'code' in body) {
+			const problem = body as Problem;
+			throw new Error(`HTTP ${res.status} ${problem.code} — ${problem.detail ?? problem.title}`);
+		}
 		throw new Error(
 			`HTTP ${res.status} ${res.statusText}${body ? ` — ${typeof body === 'string' ? body : JSON.stringify(body)}` : ''}`
 		);
@@ -38,20 +42,30 @@ export function createApiClient(baseUrl = '') {
 		getHealth: async () => api<Health>(baseUrl, '/health'),
 		getDbHealth: async () => api<Health>(baseUrl, '/health/db'),
 
-		createUser: async (input: { email: string; name: string; password: string }) =>
+		createUser: async (input: {
+			email: string;
+			name: string;
+			password: string;
+			timezone?: string;
+			// Needed when the server only lets invited users sign up
+			invite_token?: string;
+		}) =>
 			api<{ message: string; name: string }>(baseUrl, '/api/users', {
 				method: 'POST',
 				body: JSON.stringify(input)
 			}),
 
-		getUsers: async () => api<{ message: string; users: User[] }>(baseUrl, '/api/users'),
+		getUsers: async () =>
+			api<{ message: string; users: User[]; next_cursor: string | null }>(baseUrl, '/api/users'),
 
 		createTransaction: async (input: {
 			user_email: string;
 			transaction_type: 'Expense' | 'Income';
-			amount: number;
+			amount: number | string;
+			currency?: string;
 			category?: string;
 			description?: string;
+			account_id?: string;
 		}) =>
 			api<{ message: string }>(baseUrl, '/api/transactions', {
 				method: 'POST',
@@ -66,6 +80,10 @@ export function createApiClient(baseUrl = '') {
 			amount_max?: string;
 			start_timestamp?: string;
 			end_timestamp?: string;
+			period?: Period;
+			// ISO 8601 duration looking back from now, e.g. "P30D"
+			since?: string;
+			last_n_days?: number;
 		}) => {
 			const qs = new URLSearchParams();
 			for (const [k, v] of Object.entries(params)) {
@@ -88,6 +106,10 @@ export function createApiClient(baseUrl = '') {
 			transaction_type?: string;
 			start_timestamp?: string;
 			end_timestamp?: string;
+			period?: Period;
+			// ISO 8601 duration looking back from now, e.g. "P30D"
+			since?: string;
+			last_n_days?: number;
 		}) => {
 			const qs = new URLSearchParams();
 			for (const [k, v] of Object.entries(params)) {
@@ -100,13 +122,19 @@ export function createApiClient(baseUrl = '') {
 				`/api/transactions/amount${qs.size ? `?${qs}` : ''}`
 			);
 
-			return { message: (data['message'] as string) || '', amount: data['amount'] as number };
+			// The backend returns one total per currency, amounts as exact decimal strings
+			const amounts = (data['amounts'] || []) as Money[];
+			const amount = amounts.reduce((sum, m) => sum + Number(m.amount), 0);
+			return { message: (data['message'] as string) || '', amount, amounts };
 		} 
 	};
 }
 
-// Browser default (relative paths, uses Vite dev proxy)
-const defaultClient = createApiClient('');
+// Browser default: the API base injected by the Rust server when it serves the dashboard,
+// otherwise relative paths (uses Vite dev proxy)
+const defaultClient = createApiClient(
+	(typeof window !== 'undefined' && window.__WALLET_API_BASE__) || ''
+);
 
 export const getHealth = defaultClient.getHealth;
 export const getDbHealth = defaultClient.getDbHealth;
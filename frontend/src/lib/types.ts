@@ -1,10 +1,23 @@
 export type ApiOk<T> = T & { message?: string };
 
+// Errors are answered as problem details (RFC 7807), codes listed at /api/meta/errors
+export type Problem = {
+	type: string;
+	title: string;
+	status: number;
+	detail?: string;
+	instance?: string;
+	code: string;
+	[member: string]: unknown;
+};
+
 export type User = {
 	id: string;
 	email: string;
 	name: string;
 	password?: string;
+	// IANA time zone name, e.g. "Europe/Athens"
+	timezone?: string;
 	created_at?: string;
 	updated_at?: string;
 };
@@ -13,7 +26,9 @@ export type Transaction = {
 	id: string;
 	user_id: string;
 	transaction_type: 'Expense' | 'Income';
-	amount: string | number;
+	// Exact decimal string, e.g. "-12.50"
+	amount: string;
+	currency: string;
 	category:
 		| 'Groceries'
 		| 'Restaurant'
@@ -21,12 +36,24 @@ export type Transaction = {
 		| 'Holidays'
 		| 'Shopping'
 		| 'Entertainment'
-		| 'Other';
+		| 'Other'
+		| 'Uncategorized';
 	description: string;
+	source?: 'Manual' | 'Import' | 'BankSync' | 'Api';
+	external_id?: string | null;
+	account_id?: string | null;
 	created_at?: string;
 	last_updated_at?: string;
 };
 
+export type Money = {
+	amount: string;
+	currency: string;
+};
+
+// Date presets accepted instead of start_timestamp/end_timestamp
+export type Period = 'this_month' | 'last_month' | 'last_30_days' | 'ytd' | 'last_year';
+
 export type Health = {
 	status: string;
 	message?: string;
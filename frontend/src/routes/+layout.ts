@@ -0,0 +1,3 @@
+// The dashboard is a client-side SPA served by the Rust backend, there is no server to render pages
+export const ssr = false;
+export const prerender = false;
@@ -0,0 +1,16 @@
+-- Migration: Add external id and source tracking to transactions
+-- Lets imports and bank syncs be re-run without duplicating rows
+
+CREATE TYPE transaction_source AS ENUM ('Manual', 'Import', 'BankSync', 'Api');
+
+-- Where the transaction came from, existing rows were all entered by hand
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS source transaction_source NOT NULL DEFAULT 'Manual';
+
+-- Identifier of the transaction in the originating system (bank, CSV row, API client)
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS external_id VARCHAR(255);
+
+-- One row per external id for each user and source
+-- NULL external ids never conflict, so manual entries are unaffected
+ALTER TABLE transactions ADD CONSTRAINT uq_transactions_user_source_external_id UNIQUE (user_id, source, external_id);
+
+COMMENT ON COLUMN transactions.external_id IS 'Identifier of the transaction in its source system, used for idempotent imports';
@@ -0,0 +1,45 @@
+-- Migration: Create bank_accounts table
+-- Stores bank accounts linked to a user, which synced transactions reference
+
+CREATE TYPE bank_sync_status AS ENUM ('Pending', 'Active', 'Failed', 'Disconnected');
+
+CREATE TABLE IF NOT EXISTS bank_accounts (
+    -- Primary key: UUID v4 for unique bank account identification
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    -- Foreign key reference to the user who owns this bank account
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Name of the bank or card issuer
+    institution VARCHAR(255) NOT NULL,
+
+    -- Display name chosen by the user (e.g. "Joint checking")
+    name VARCHAR(255) NOT NULL,
+
+    -- Full IBAN, never returned by the API unmasked
+    iban VARCHAR(34),
+
+    -- Last 4 characters of the IBAN or account/card number, safe to display
+    last4 VARCHAR(4),
+
+    -- Sync state with the bank, plus the last error reported by the sync
+    sync_status bank_sync_status NOT NULL DEFAULT 'Pending',
+    sync_error TEXT,
+    last_synced_at TIMESTAMPTZ,
+
+    -- Timestamps for tracking when records are created/updated
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Index on user_id for listing a user's bank accounts
+CREATE INDEX IF NOT EXISTS idx_bank_accounts_user_id ON bank_accounts(user_id);
+
+-- Transactions synced from a bank point at the account they came from
+-- Deleting the bank account keeps the transactions
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS bank_account_id UUID REFERENCES bank_accounts(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_transactions_bank_account_id ON transactions(bank_account_id);
+
+COMMENT ON TABLE bank_accounts IS 'Bank accounts linked to a user for transaction syncing';
+COMMENT ON COLUMN bank_accounts.iban IS 'Full IBAN, masked in every API response';
@@ -0,0 +1,13 @@
+-- Migration: Prepare columns for application-level encryption
+-- Encrypted values are longer than the plaintext, and can no longer be searched directly
+
+-- Ciphertext of a 255 character email does not fit in VARCHAR(255)
+ALTER TABLE users ALTER COLUMN email TYPE TEXT;
+
+-- Keyed hash of the email, used for lookups and uniqueness when emails are encrypted
+-- NULL for rows written without encryption
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_hash VARCHAR(64);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email_hash ON users(email_hash);
+
+COMMENT ON COLUMN users.email_hash IS 'HMAC blind index of the email, set when column encryption is enabled';
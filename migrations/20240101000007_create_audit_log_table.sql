@@ -0,0 +1,36 @@
+-- Migration: Create audit_log table
+-- Append-only, hash-chained record of changes to financial data
+-- Each entry stores the hash of the previous one, so edits or deletions break the chain
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    -- Position in the chain, contiguous starting at 1 (gaps mean deleted entries)
+    seq BIGINT PRIMARY KEY,
+
+    -- When the audited change happened
+    occurred_at TIMESTAMPTZ NOT NULL,
+
+    -- User who performed the change, if known
+    -- No foreign key: entries must outlive the users they mention
+    actor_id UUID,
+
+    -- What happened (e.g. 'create') and to what (e.g. 'transaction', id)
+    action VARCHAR(64) NOT NULL,
+    entity_type VARCHAR(64) NOT NULL,
+    entity_id UUID,
+
+    -- JSON details, stored as text so the hashed bytes are preserved exactly
+    details TEXT NOT NULL,
+
+    -- SHA-256 (hex) of the previous entry and of this entry
+    prev_hash CHAR(64) NOT NULL,
+    hash CHAR(64) NOT NULL UNIQUE
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_entity ON audit_log(entity_type, entity_id);
+
+-- Make the table append-only at the database level
+-- UPDATE and DELETE statements silently do nothing
+CREATE OR REPLACE RULE audit_log_no_update AS ON UPDATE TO audit_log DO INSTEAD NOTHING;
+CREATE OR REPLACE RULE audit_log_no_delete AS ON DELETE TO audit_log DO INSTEAD NOTHING;
+
+COMMENT ON TABLE audit_log IS 'Append-only hash-chained audit trail of changes to financial records';
@@ -0,0 +1,30 @@
+-- Migration: Create debug_captures table
+-- Request and response bodies captured by the opt-in debug layer
+-- Used to diagnose client integration issues, bodies are redacted before they are stored
+
+CREATE TABLE IF NOT EXISTS debug_captures (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    captured_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Why the request was captured: 'sampled' or 'header'
+    reason VARCHAR(16) NOT NULL,
+
+    -- Route template (e.g. 'GET /api/users/:email'), never the raw path
+    endpoint VARCHAR(255) NOT NULL,
+
+    -- Redacted query string, if any
+    query TEXT,
+
+    status INTEGER NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    user_agent TEXT,
+
+    -- Redacted bodies (JSON bodies keep their shape, other bodies are summarized)
+    request_body TEXT,
+    response_body TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_debug_captures_captured_at ON debug_captures(captured_at);
+
+COMMENT ON TABLE debug_captures IS 'Redacted request/response bodies captured for debugging client integrations';
@@ -0,0 +1,7 @@
+-- Migration: Add currency to transactions
+-- Amounts are handled as exact Money (minor units + currency) by the application
+-- Existing rows get the same default currency as wallets
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+COMMENT ON COLUMN transactions.currency IS 'ISO 4217 code of the amount';
@@ -0,0 +1,12 @@
+-- Migration: Enforce the amount sign convention
+-- Expenses are stored as negative amounts and income as positive amounts,
+-- so balances and reports are plain sums
+
+-- Backfill rows written before the convention was applied everywhere
+UPDATE transactions SET amount = -ABS(amount) WHERE transaction_type = 'Expense' AND amount > 0;
+UPDATE transactions SET amount = ABS(amount) WHERE transaction_type = 'Income' AND amount < 0;
+
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS chk_transactions_amount_sign;
+ALTER TABLE transactions ADD CONSTRAINT chk_transactions_amount_sign CHECK (
+    (transaction_type = 'Expense' AND amount <= 0) OR (transaction_type = 'Income' AND amount >= 0)
+);
@@ -0,0 +1,20 @@
+-- Migration: Bound amounts and free text at the database level
+-- Mirrors the limits enforced by the API (see src/validation.rs)
+-- NOT VALID: only new and updated rows are checked, existing data is left as is
+
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS chk_transactions_amount_range;
+ALTER TABLE transactions ADD CONSTRAINT chk_transactions_amount_range
+    CHECK (ABS(amount) <= 1000000000) NOT VALID;
+
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS chk_transactions_category_length;
+ALTER TABLE transactions ADD CONSTRAINT chk_transactions_category_length
+    CHECK (char_length(category) <= 50) NOT VALID;
+
+-- Descriptions may be stored encrypted, leave room for the ciphertext of 1000 characters
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS chk_transactions_description_length;
+ALTER TABLE transactions ADD CONSTRAINT chk_transactions_description_length
+    CHECK (char_length(description) <= 8192) NOT VALID;
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS chk_users_name_not_blank;
+ALTER TABLE users ADD CONSTRAINT chk_users_name_not_blank
+    CHECK (char_length(btrim(name)) > 0) NOT VALID;
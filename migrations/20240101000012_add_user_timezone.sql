@@ -0,0 +1,4 @@
+-- Migration: Store each user's time zone
+-- Date presets such as "this_month" are resolved in this zone (IANA name, e.g. 'Europe/Athens')
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone VARCHAR(64) NOT NULL DEFAULT 'UTC';
@@ -0,0 +1,46 @@
+-- Migration: Create pending_transactions table
+-- Transactions parsed from receipt and bank notification emails, waiting for the user to confirm them
+
+-- Confirmed email transactions are stored with their own source
+ALTER TYPE transaction_source ADD VALUE IF NOT EXISTS 'Email';
+
+CREATE TABLE IF NOT EXISTS pending_transactions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    transaction_type transaction_type NOT NULL,
+
+    -- Signed like transactions.amount: negative for expenses, positive for income
+    amount DECIMAL(19,4) NOT NULL,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    -- Guessed category, NULL when the parser could not tell
+    category VARCHAR(50),
+    description TEXT,
+
+    -- When the email says the transaction happened
+    occurred_at TIMESTAMPTZ,
+
+    -- Message-ID of the email, becomes the external id of the confirmed transaction
+    source_ref VARCHAR(255) NOT NULL,
+
+    -- Name of the parser that recognised the email
+    parser VARCHAR(50) NOT NULL,
+
+    -- 'pending' until the user confirms or rejects it
+    status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'confirmed', 'rejected')),
+
+    -- Transaction created on confirmation
+    transaction_id UUID REFERENCES transactions(id) ON DELETE SET NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ,
+
+    -- The same email is only ever ingested once per user
+    CONSTRAINT uq_pending_transactions_user_source_ref UNIQUE (user_id, source_ref)
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_transactions_user_status ON pending_transactions(user_id, status);
+
+COMMENT ON TABLE pending_transactions IS 'Transactions parsed from ingested emails, awaiting user confirmation';
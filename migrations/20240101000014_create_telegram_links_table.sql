@@ -0,0 +1,21 @@
+-- Migration: Create telegram_links table
+-- Links a Telegram chat to a wallet user so expenses can be entered by messaging the bot
+
+-- Transactions entered through the bot
+ALTER TYPE transaction_source ADD VALUE IF NOT EXISTS 'Telegram';
+
+CREATE TABLE IF NOT EXISTS telegram_links (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Private chat with the bot, NULL until the user sends the link code
+    chat_id BIGINT UNIQUE,
+
+    -- One-time code the user sends as "/start <code>", cleared once used
+    link_code VARCHAR(32) UNIQUE,
+    link_code_expires_at TIMESTAMPTZ,
+
+    linked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE telegram_links IS 'Telegram chats linked to wallet users for quick expense entry';
@@ -0,0 +1,4 @@
+-- Migration: Add Slack transaction source
+-- Transactions recorded with the /wallet slash command in a connected Slack workspace
+
+ALTER TYPE transaction_source ADD VALUE IF NOT EXISTS 'Slack';
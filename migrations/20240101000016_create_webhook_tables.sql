@@ -0,0 +1,45 @@
+-- Migration: Create webhook_endpoints and webhook_deliveries tables
+-- Outbound webhooks: users register URLs that are notified of changes to their wallet
+
+CREATE TABLE IF NOT EXISTS webhook_endpoints (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    url VARCHAR(2048) NOT NULL,
+
+    -- Key signing every delivery, encrypted like other sensitive columns
+    secret TEXT NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_endpoints_user_id ON webhook_endpoints(user_id);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    endpoint_id UUID NOT NULL REFERENCES webhook_endpoints(id) ON DELETE CASCADE,
+
+    -- e.g. 'transaction.created'
+    event VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+
+    -- 'pending' while attempts remain, 'failed' once they are used up (the dead letter queue)
+    status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Outcome of the latest attempt
+    last_status_code INTEGER,
+    last_error TEXT,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+-- The worker only ever looks for pending deliveries that are due
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_endpoint_status ON webhook_deliveries(endpoint_id, status);
+
+COMMENT ON TABLE webhook_deliveries IS 'Outbound webhook deliveries, retried with exponential backoff';
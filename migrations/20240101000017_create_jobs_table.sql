@@ -0,0 +1,36 @@
+-- Migration: Create jobs table
+-- Background work (re-encryption, mailbox polling, webhook delivery, ...) queued for the worker pool
+
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    -- Name of the handler that runs the job, e.g. 'webhooks.deliver'
+    kind VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+
+    -- 'queued' until a worker takes it, 'running' while a worker holds it
+    status VARCHAR(16) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'running', 'succeeded', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5 CHECK (max_attempts > 0),
+
+    -- Not run before this time, pushed back after every failed attempt
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Visibility timeout: a running job whose worker disappeared is taken again after this time
+    locked_until TIMESTAMPTZ,
+
+    -- At most one queued or running job per kind and key, NULL for jobs that may pile up
+    dedupe_key VARCHAR(255),
+
+    last_error TEXT,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_queued_run_at ON jobs(run_at) WHERE status = 'queued';
+CREATE INDEX IF NOT EXISTS idx_jobs_running_locked_until ON jobs(locked_until) WHERE status = 'running';
+CREATE UNIQUE INDEX IF NOT EXISTS uq_jobs_active_dedupe_key ON jobs(kind, dedupe_key) WHERE status IN ('queued', 'running');
+
+COMMENT ON TABLE jobs IS 'Background job queue, processed by the worker pool with retries and visibility timeouts';
@@ -0,0 +1,22 @@
+-- Migration: Create job_schedules table
+-- Cron schedules queueing periodic jobs (snapshots, reports, rate refreshes, purges, ...)
+
+CREATE TABLE IF NOT EXISTS job_schedules (
+    -- Kind of the job queued, one schedule per kind
+    kind VARCHAR(64) PRIMARY KEY,
+
+    -- Standard five field cron expression, evaluated in UTC
+    cron VARCHAR(255) NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+
+    -- Claimed by moving it forward, so only one server queues each run
+    next_run_at TIMESTAMPTZ NOT NULL,
+    last_enqueued_at TIMESTAMPTZ,
+
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_schedules_next_run_at ON job_schedules(next_run_at) WHERE enabled;
+
+COMMENT ON TABLE job_schedules IS 'Cron schedules of periodic background jobs';
@@ -0,0 +1,6 @@
+-- Migration: Allow cancelling jobs
+-- Operators can cancel queued or running jobs from the admin API
+
+ALTER TABLE jobs DROP CONSTRAINT IF EXISTS jobs_status_check;
+
+ALTER TABLE jobs ADD CONSTRAINT jobs_status_check CHECK (status IN ('queued', 'running', 'succeeded', 'failed', 'cancelled'));
@@ -0,0 +1,16 @@
+-- Migration: Create sync tombstones table
+-- Mobile clients sync incrementally from a cursor over last_updated_at, rows that are gone
+-- no longer have one, so whatever deletes a row records a tombstone in the same transaction
+
+CREATE TABLE IF NOT EXISTS sync_tombstones (
+    -- 'transaction' for now, other synced entities later
+    entity_type VARCHAR(32) NOT NULL,
+    entity_id UUID NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (entity_type, entity_id)
+);
+
+-- Changes are read per user in cursor order
+CREATE INDEX IF NOT EXISTS idx_sync_tombstones_user_deleted ON sync_tombstones(user_id, deleted_at, entity_id);
+CREATE INDEX IF NOT EXISTS idx_transactions_user_updated ON transactions(user_id, last_updated_at, id);
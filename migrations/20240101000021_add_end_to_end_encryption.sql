@@ -0,0 +1,23 @@
+-- Migration: Optional end-to-end encrypted descriptions
+-- Clients encrypting descriptions themselves store the ciphertext in encrypted_description
+-- and leave description empty, the server never sees the key nor the plaintext
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS encrypted_description TEXT;
+
+-- Same room as for descriptions
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS chk_transactions_encrypted_description_length;
+ALTER TABLE transactions ADD CONSTRAINT chk_transactions_encrypted_description_length
+    CHECK (char_length(encrypted_description) <= 8192) NOT VALID;
+
+-- The user's data key, wrapped (encrypted) on the client with a key derived from a secret
+-- only the user has. Stored so other devices of the user can unwrap it, opaque to the server
+CREATE TABLE IF NOT EXISTS user_encryption_keys (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    wrapped_key TEXT NOT NULL CHECK (char_length(wrapped_key) <= 8192),
+    -- How the key was wrapped, e.g. 'AES-256-GCM', with the key derivation parameters
+    -- (salt, iterations, ...) the client needs to derive the wrapping key again
+    algorithm VARCHAR(64) NOT NULL,
+    kdf_params JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,14 @@
+-- Migration: Plans and usage counters for quotas
+-- The limits of each plan are configured in PLANS, users only carry the plan name
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS plan VARCHAR(64) NOT NULL DEFAULT 'free';
+
+-- Usage per user, metric ('transactions', 'api_calls') and period (first day of the
+-- month or the day the usage is counted for)
+CREATE TABLE IF NOT EXISTS usage_counters (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    metric VARCHAR(32) NOT NULL,
+    period_start DATE NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0 CHECK (count >= 0),
+    PRIMARY KEY (user_id, metric, period_start)
+);
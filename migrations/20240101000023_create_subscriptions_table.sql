@@ -0,0 +1,17 @@
+-- Migration: Stripe subscriptions of hosted deployments
+-- One row per user who went through checkout, kept in step by Stripe webhooks.
+-- users.plan stays the plan quotas are enforced with, this is where it came from
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    stripe_customer_id VARCHAR(255) NOT NULL UNIQUE,
+    stripe_subscription_id VARCHAR(255) UNIQUE,
+    plan VARCHAR(64) NOT NULL,
+    -- Stripe's subscription status: active, trialing, past_due, canceled, unpaid, ...
+    status VARCHAR(32) NOT NULL,
+    current_period_end TIMESTAMPTZ,
+    -- Creation time of the last applied event, older events delivered late are ignored
+    last_event_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
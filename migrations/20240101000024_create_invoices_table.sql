@@ -0,0 +1,28 @@
+-- Migration: Invoices of subscription payments, synced from Stripe
+-- Stripe issues the invoices and hosts their PDFs, this is a copy for listing them
+
+CREATE TABLE IF NOT EXISTS invoices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    stripe_invoice_id VARCHAR(255) NOT NULL UNIQUE,
+    stripe_subscription_id VARCHAR(255),
+    -- Invoice number as printed on the PDF, set once the invoice is finalized
+    number VARCHAR(255),
+    -- draft, open, paid, void or uncollectible
+    status VARCHAR(32) NOT NULL,
+    -- In minor units of the currency, like Stripe
+    total BIGINT NOT NULL,
+    amount_paid BIGINT NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+    hosted_invoice_url TEXT,
+    invoice_pdf_url TEXT,
+    period_start TIMESTAMPTZ,
+    period_end TIMESTAMPTZ,
+    paid_at TIMESTAMPTZ,
+    issued_at TIMESTAMPTZ NOT NULL,
+    last_event_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_invoices_user_issued ON invoices(user_id, issued_at DESC);
@@ -0,0 +1,62 @@
+-- Migration: Shared wallets with approval of large transactions
+-- Owners share their wallet with other users. Editors record transactions in it, those above
+-- the owner's approval threshold wait in transaction_approvals until the owner decides
+
+CREATE TABLE IF NOT EXISTS wallet_members (
+    -- User whose wallet is shared
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    member_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 'editor' records transactions, 'viewer' only reads
+    role VARCHAR(16) NOT NULL CHECK (role IN ('editor', 'viewer')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (owner_id, member_id),
+    CONSTRAINT chk_wallet_members_not_owner CHECK (owner_id <> member_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_wallet_members_member_id ON wallet_members(member_id);
+
+-- Transactions of editors above this amount need the owner's approval, none without a row
+CREATE TABLE IF NOT EXISTS approval_policies (
+    owner_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    threshold DECIMAL(19,4) NOT NULL CHECK (threshold >= 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS transaction_approvals (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Editor who recorded the transaction
+    requested_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    transaction_type transaction_type NOT NULL,
+
+    -- Signed like transactions.amount: negative for expenses, positive for income
+    amount DECIMAL(19,4) NOT NULL,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    category VARCHAR(50) NOT NULL,
+    description TEXT,
+    encrypted_description TEXT CHECK (char_length(encrypted_description) <= 8192),
+    source transaction_source NOT NULL DEFAULT 'Manual',
+    external_id VARCHAR(255),
+
+    -- 'pending' until the owner approves or rejects it
+    status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+
+    -- Why the owner rejected it, shown to the editor
+    reason TEXT CHECK (char_length(reason) <= 1000),
+
+    -- Transaction created on approval
+    transaction_id UUID REFERENCES transactions(id) ON DELETE SET NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_transaction_approvals_owner_status ON transaction_approvals(owner_id, status);
+
+COMMENT ON TABLE transaction_approvals IS 'Transactions recorded by editors of a shared wallet, awaiting the owner''s approval';
@@ -0,0 +1,30 @@
+-- Migration: Create spending_limits table
+-- Hard caps on a user's expenses per day, week or month, e.g. for a child's account.
+-- Unlike budgets they are enforced: a transaction going over the cap is refused, or
+-- needs an explicit confirmation when the limit allows overriding it
+
+CREATE TABLE IF NOT EXISTS spending_limits (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Category capped, NULL caps all of the user's expenses
+    category VARCHAR(50),
+
+    -- 'day', 'week' (from Monday) or 'month', in the user's time zone
+    period VARCHAR(16) NOT NULL CHECK (period IN ('day', 'week', 'month')),
+
+    -- Most that may be spent in the period, only expenses in this currency count
+    amount DECIMAL(19,4) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    -- Whether a transaction going over it can still be recorded by confirming it
+    allow_override BOOLEAN NOT NULL DEFAULT FALSE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT uq_spending_limits_user_category_period UNIQUE NULLS NOT DISTINCT (user_id, category, period, currency)
+);
+
+COMMENT ON TABLE spending_limits IS 'Enforced caps on expenses per period, distinct from advisory budgets';
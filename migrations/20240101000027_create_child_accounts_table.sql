@@ -0,0 +1,26 @@
+-- Migration: Parent/child accounts
+-- A child user linked to a parent, who sees the child's transactions, may restrict the
+-- categories the child spends in and gives a weekly allowance credited by the scheduler
+
+-- Allowances are recorded with their own source, one per child and ISO week
+ALTER TYPE transaction_source ADD VALUE IF NOT EXISTS 'Allowance';
+
+CREATE TABLE IF NOT EXISTS child_accounts (
+    -- A child has a single parent
+    child_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    parent_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Credited every week, no allowance when NULL
+    allowance DECIMAL(19,4) CHECK (allowance > 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    -- Categories the child may record expenses in, NULL allows all of them
+    allowed_categories TEXT[],
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_child_accounts_not_parent CHECK (child_id <> parent_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_child_accounts_parent_id ON child_accounts(parent_id);
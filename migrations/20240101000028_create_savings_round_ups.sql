@@ -0,0 +1,43 @@
+-- Migration: Savings goals and round-ups
+-- With a round-up rule each expense is rounded up to the rule's unit and the difference is
+-- set aside in a savings goal. round_ups records those transfers, one per expense
+
+CREATE TABLE IF NOT EXISTS savings_goals (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    -- Amount being saved for, NULL when open ended
+    target DECIMAL(19,4) CHECK (target > 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_savings_goals_user_id ON savings_goals(user_id);
+
+-- At most one rule per user, only expenses in the goal's currency are rounded up
+CREATE TABLE IF NOT EXISTS round_up_rules (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    goal_id UUID NOT NULL REFERENCES savings_goals(id) ON DELETE CASCADE,
+    -- Expenses are rounded up to a multiple of this, e.g. 1 or 5
+    unit DECIMAL(19,4) NOT NULL DEFAULT 1 CHECK (unit > 0),
+    -- Expenses that happened before are left alone
+    starts_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS round_ups (
+    -- Expense rounded up, deleting it gives the round-up back
+    transaction_id UUID PRIMARY KEY REFERENCES transactions(id) ON DELETE CASCADE,
+    goal_id UUID NOT NULL REFERENCES savings_goals(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Difference set aside, always positive
+    amount DECIMAL(19,4) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(3) NOT NULL,
+    -- When the expense happened
+    occurred_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_round_ups_goal_occurred ON round_ups(goal_id, occurred_at);
@@ -0,0 +1,21 @@
+-- Migration: Card reward rules
+-- Cashback a card (bank account) earns on expenses, per category or on everything,
+-- to estimate the cashback of each statement period and pick the best card per category
+
+-- Day of the month statements start on, calendar months when NULL
+ALTER TABLE bank_accounts ADD COLUMN IF NOT EXISTS statement_day SMALLINT CHECK (statement_day BETWEEN 1 AND 28);
+
+CREATE TABLE IF NOT EXISTS reward_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    bank_account_id UUID NOT NULL REFERENCES bank_accounts(id) ON DELETE CASCADE,
+    -- Category earning the rate, NULL for the card's rate on everything else
+    category VARCHAR(50),
+    -- Percentage of the expense paid back, e.g. 2 for 2%
+    rate DECIMAL(7,4) NOT NULL CHECK (rate > 0 AND rate <= 100),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT uq_reward_rules_account_category UNIQUE NULLS NOT DISTINCT (bank_account_id, category)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reward_rules_user_id ON reward_rules(user_id);
@@ -0,0 +1,25 @@
+-- Migration: Transaction attachments
+-- Receipts and other files uploaded for a transaction, stored in the database. Images get a
+-- small thumbnail generated in the background for list views
+
+CREATE TABLE IF NOT EXISTS attachments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    transaction_id UUID NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Name of the uploaded file, if the client sent one
+    file_name VARCHAR(255),
+    content_type VARCHAR(100) NOT NULL,
+    -- Counted against the storage quota of the user's plan
+    size_bytes BIGINT NOT NULL CHECK (size_bytes > 0),
+    -- Hex SHA-256 of the contents
+    sha256 VARCHAR(64) NOT NULL,
+    data BYTEA NOT NULL,
+    -- PNG thumbnail, set once generated
+    thumbnail BYTEA,
+    -- pending, ready or failed, NULL for files without thumbnails (e.g. PDFs)
+    thumbnail_status VARCHAR(20),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_attachments_transaction_id ON attachments(transaction_id);
+CREATE INDEX IF NOT EXISTS idx_attachments_user_id ON attachments(user_id);
@@ -0,0 +1,7 @@
+-- Migration: Attachments in the blob store
+-- Files uploaded from now on are kept in the configured blob store under storage_key,
+-- data only holds the files uploaded before
+
+ALTER TABLE attachments ADD COLUMN IF NOT EXISTS storage_key VARCHAR(512);
+ALTER TABLE attachments ALTER COLUMN data DROP NOT NULL;
+ALTER TABLE attachments ADD CONSTRAINT chk_attachments_contents CHECK (data IS NOT NULL OR storage_key IS NOT NULL);
@@ -0,0 +1,8 @@
+-- Migration: Malware scanning of attachments
+-- Attachments are scanned when uploaded if a scanner is configured. Infected ones are kept
+-- but quarantined, they are neither served nor thumbnailed
+
+-- clean or quarantined, NULL when no scanner was configured at upload
+ALTER TABLE attachments ADD COLUMN IF NOT EXISTS scan_status VARCHAR(20);
+-- Signature the scanner reported for quarantined attachments
+ALTER TABLE attachments ADD COLUMN IF NOT EXISTS malware_signature VARCHAR(255);
@@ -0,0 +1,35 @@
+-- Migration: Background imports
+-- Exports of other budgeting apps are imported by a background job, the row tracks its
+-- progress and outcome for clients polling GET /api/imports/:id
+
+CREATE TABLE IF NOT EXISTS imports (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- App the export comes from: ynab or mint
+    format VARCHAR(20) NOT NULL,
+    -- Currency of the export's amounts
+    currency VARCHAR(3) NOT NULL,
+    bank_account_id UUID REFERENCES bank_accounts(id) ON DELETE SET NULL,
+    -- The uploaded file in the blob store, deleted once the import has run
+    storage_key TEXT NOT NULL,
+    -- queued, running, succeeded or failed
+    status VARCHAR(20) NOT NULL DEFAULT 'queued',
+    -- Transactions found in the export, known once it has been read
+    total_rows INTEGER,
+    -- Of those, how many were inserted or skipped so far
+    rows_processed INTEGER NOT NULL DEFAULT 0,
+    inserted INTEGER NOT NULL DEFAULT 0,
+    duplicates_skipped INTEGER NOT NULL DEFAULT 0,
+    transfers_skipped INTEGER NOT NULL DEFAULT 0,
+    other_skipped INTEGER NOT NULL DEFAULT 0,
+    -- Rows that could not be read, as [{"line": .., "message": ..}]
+    errors JSONB NOT NULL DEFAULT '[]',
+    -- Why the import failed as a whole
+    failure TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    CONSTRAINT chk_imports_status CHECK (status IN ('queued', 'running', 'succeeded', 'failed'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_imports_user_id ON imports(user_id, created_at DESC);
@@ -0,0 +1,36 @@
+-- Migration: Change feed of transactions
+-- Every insert and visible update of a transaction is announced with NOTIFY on the
+-- transaction_changes channel, so all server instances learn about writes made by their
+-- peers (and by anything else writing to the database). Notifications are only sent
+-- once the writing transaction commits
+
+CREATE OR REPLACE FUNCTION notify_transaction_change() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('transaction_changes', json_build_object(
+        'op', CASE TG_OP WHEN 'INSERT' THEN 'created' ELSE 'updated' END,
+        'id', NEW.id,
+        'user_id', NEW.user_id,
+        'transaction_type', NEW.transaction_type,
+        'amount', NEW.amount,
+        'currency', NEW.currency,
+        'category', NEW.category,
+        'source', NEW.source,
+        'at', NOW()
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trg_transactions_notify_insert ON transactions;
+CREATE TRIGGER trg_transactions_notify_insert
+    AFTER INSERT ON transactions
+    FOR EACH ROW EXECUTE FUNCTION notify_transaction_change();
+
+-- Writes that leave last_updated_at alone (e.g. re-encrypting descriptions) change
+-- nothing clients see
+DROP TRIGGER IF EXISTS trg_transactions_notify_update ON transactions;
+CREATE TRIGGER trg_transactions_notify_update
+    AFTER UPDATE ON transactions
+    FOR EACH ROW
+    WHEN (OLD.last_updated_at IS DISTINCT FROM NEW.last_updated_at)
+    EXECUTE FUNCTION notify_transaction_change();
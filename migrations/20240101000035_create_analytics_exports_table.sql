@@ -0,0 +1,17 @@
+-- Migration: Analytics export cursors
+-- Changed transactions are copied to an analytics store (ClickHouse, NDJSON files) by a
+-- background job, the row remembers how far each sink got so every run resumes there
+
+CREATE TABLE IF NOT EXISTS analytics_exports (
+    -- Sink the cursor belongs to, e.g. 'clickhouse' or 'ndjson'
+    sink VARCHAR(50) PRIMARY KEY,
+    -- last_updated_at and id of the last transaction exported, rows are read in that order
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT 'epoch',
+    last_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+    -- Transactions exported so far
+    exported BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Changed transactions are read in (last_updated_at, id) order across all users
+CREATE INDEX IF NOT EXISTS idx_transactions_last_updated_at_id ON transactions(last_updated_at, id);
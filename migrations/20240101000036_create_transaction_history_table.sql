@@ -0,0 +1,86 @@
+-- Migration: Transaction history
+-- Every version of every transaction, valid from the write that made it until the write
+-- that replaced or deleted it, so reports can be run as the data looked at a past moment
+-- (?as_of= on the transaction list, sum and export endpoints). Kept by a trigger, so
+-- corrections made outside the application are recorded too
+
+CREATE TABLE IF NOT EXISTS transaction_history (
+    history_id BIGSERIAL PRIMARY KEY,
+    -- The transaction's columns as they were
+    id UUID NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    transaction_type transaction_type NOT NULL,
+    amount DECIMAL(19,4) NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+    category VARCHAR(255) NOT NULL,
+    description TEXT,
+    encrypted_description TEXT,
+    source transaction_source NOT NULL,
+    external_id VARCHAR(255),
+    -- No foreign key, the account may be gone while the version still refers to it
+    bank_account_id UUID,
+    created_at TIMESTAMPTZ NOT NULL,
+    last_updated_at TIMESTAMPTZ NOT NULL,
+    -- When this version was written, and replaced (NULL while it is the current one)
+    valid_from TIMESTAMPTZ NOT NULL,
+    valid_to TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_transaction_history_user_valid ON transaction_history(user_id, valid_from);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_transaction_history_current ON transaction_history(id) WHERE valid_to IS NULL;
+
+CREATE OR REPLACE FUNCTION record_transaction_history() RETURNS trigger AS $$
+BEGIN
+    -- Re-encrypting a description leaves last_updated_at and everything else alone, the
+    -- current version is rewritten instead of a new one being made
+    IF TG_OP = 'UPDATE'
+        AND OLD.last_updated_at IS NOT DISTINCT FROM NEW.last_updated_at
+        AND (OLD.user_id, OLD.transaction_type, OLD.amount, OLD.currency, OLD.category,
+             OLD.encrypted_description, OLD.source, OLD.external_id, OLD.bank_account_id, OLD.created_at)
+            IS NOT DISTINCT FROM
+            (NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+             NEW.encrypted_description, NEW.source, NEW.external_id, NEW.bank_account_id, NEW.created_at)
+    THEN
+        UPDATE transaction_history SET description = NEW.description
+            WHERE id = NEW.id AND valid_to IS NULL;
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE transaction_history SET valid_to = NOW()
+            WHERE id = OLD.id AND valid_to IS NULL;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO transaction_history (
+            id, user_id, transaction_type, amount, currency, category, description,
+            encrypted_description, source, external_id, bank_account_id, created_at,
+            last_updated_at, valid_from
+        ) VALUES (
+            NEW.id, NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+            NEW.description, NEW.encrypted_description, NEW.source, NEW.external_id,
+            NEW.bank_account_id, NEW.created_at, NEW.last_updated_at, NOW()
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trg_transactions_history ON transactions;
+CREATE TRIGGER trg_transactions_history
+    AFTER INSERT OR UPDATE OR DELETE ON transactions
+    FOR EACH ROW EXECUTE FUNCTION record_transaction_history();
+
+-- Earlier versions of existing transactions were never recorded, their current version
+-- stands in for them since they were created
+INSERT INTO transaction_history (
+    id, user_id, transaction_type, amount, currency, category, description,
+    encrypted_description, source, external_id, bank_account_id, created_at,
+    last_updated_at, valid_from
+)
+SELECT t.id, t.user_id, t.transaction_type, t.amount, t.currency, t.category, t.description,
+    t.encrypted_description, t.source, t.external_id, t.bank_account_id, t.created_at,
+    t.last_updated_at, LEAST(t.created_at, t.last_updated_at)
+FROM transactions t
+WHERE NOT EXISTS (
+    SELECT 1 FROM transaction_history h WHERE h.id = t.id AND h.valid_to IS NULL
+);
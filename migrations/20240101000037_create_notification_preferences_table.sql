@@ -0,0 +1,16 @@
+-- Migration: Notification preferences
+-- What users opted into hearing about and how it reaches them. Only the weekly digest of
+-- shared wallets so far, delivered as a webhook event or a Telegram message
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- Weekly summary of the activity in the shared wallets the user owns or belongs to
+    weekly_digest BOOLEAN NOT NULL DEFAULT FALSE,
+    -- 'webhook' (digest.weekly event) or 'telegram' (message to the linked chat)
+    channel VARCHAR(16) NOT NULL DEFAULT 'webhook' CHECK (channel IN ('webhook', 'telegram')),
+    -- Start of the last week a digest was sent for, so a repeated run sends nothing more
+    last_digest_week DATE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_preferences_weekly_digest ON notification_preferences(user_id) WHERE weekly_digest;
@@ -0,0 +1,59 @@
+-- Migration: Merchant enrichment
+-- Transactions are matched to a merchant by their raw descriptor (the description) through
+-- an external merchant-data API, which gives them a clean name, a logo and, for those still
+-- in Other, a category. Lookups are cached, unmatched descriptors included
+
+CREATE TABLE IF NOT EXISTS merchant_lookups (
+    -- SHA-256 (hex) of the normalized descriptor, descriptors themselves are not kept
+    descriptor_hash CHAR(64) PRIMARY KEY,
+    -- NULL when the API knew no merchant for the descriptor
+    name VARCHAR(255),
+    logo_url TEXT CHECK (char_length(logo_url) <= 2048),
+    category VARCHAR(50),
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS merchant_name VARCHAR(255);
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS merchant_logo_url TEXT;
+ALTER TABLE transaction_history ADD COLUMN IF NOT EXISTS merchant_name VARCHAR(255);
+ALTER TABLE transaction_history ADD COLUMN IF NOT EXISTS merchant_logo_url TEXT;
+
+-- Versions carry the merchant too
+CREATE OR REPLACE FUNCTION record_transaction_history() RETURNS trigger AS $$
+BEGIN
+    -- Re-encrypting a description leaves last_updated_at and everything else alone, the
+    -- current version is rewritten instead of a new one being made
+    IF TG_OP = 'UPDATE'
+        AND OLD.last_updated_at IS NOT DISTINCT FROM NEW.last_updated_at
+        AND (OLD.user_id, OLD.transaction_type, OLD.amount, OLD.currency, OLD.category,
+             OLD.encrypted_description, OLD.source, OLD.external_id, OLD.bank_account_id, OLD.created_at,
+             OLD.merchant_name, OLD.merchant_logo_url)
+            IS NOT DISTINCT FROM
+            (NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+             NEW.encrypted_description, NEW.source, NEW.external_id, NEW.bank_account_id, NEW.created_at,
+             NEW.merchant_name, NEW.merchant_logo_url)
+    THEN
+        UPDATE transaction_history SET description = NEW.description
+            WHERE id = NEW.id AND valid_to IS NULL;
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE transaction_history SET valid_to = NOW()
+            WHERE id = OLD.id AND valid_to IS NULL;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO transaction_history (
+            id, user_id, transaction_type, amount, currency, category, description,
+            encrypted_description, source, external_id, bank_account_id, created_at,
+            last_updated_at, merchant_name, merchant_logo_url, valid_from
+        ) VALUES (
+            NEW.id, NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+            NEW.description, NEW.encrypted_description, NEW.source, NEW.external_id,
+            NEW.bank_account_id, NEW.created_at, NEW.last_updated_at, NEW.merchant_name,
+            NEW.merchant_logo_url, NOW()
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
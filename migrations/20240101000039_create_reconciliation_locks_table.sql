@@ -0,0 +1,20 @@
+-- Migration: Reconciliation locks
+-- Once a statement period of an account has been reconciled against the bank's statement,
+-- its transactions are locked: edits to them, or moving another transaction into the
+-- period, are refused until the account's owner unlocks it again
+
+CREATE TABLE IF NOT EXISTS reconciliation_locks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    bank_account_id UUID NOT NULL REFERENCES bank_accounts(id) ON DELETE CASCADE,
+    -- The reconciled period, from period_start up to but excluding period_end
+    period_start TIMESTAMPTZ NOT NULL,
+    period_end TIMESTAMPTZ NOT NULL,
+    -- Owner who reconciled it
+    locked_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT chk_reconciliation_locks_period CHECK (period_end > period_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reconciliation_locks_account_period ON reconciliation_locks(bank_account_id, period_start);
+
+COMMENT ON TABLE reconciliation_locks IS 'Reconciled statement periods whose transactions may not be changed';
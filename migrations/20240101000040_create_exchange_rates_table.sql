@@ -0,0 +1,18 @@
+-- Migration: Exchange rates
+-- Daily reference rates, used to revalue foreign-currency balances. Every rate is quoted
+-- against one reference currency (FX_RATES_BASE, EUR like the ECB's reference rates),
+-- which is stored with a rate of 1, so a cross rate is the ratio of two rates of a day
+
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    rate_date DATE NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+    -- Units of the currency per unit of the reference currency
+    rate DECIMAL(24,10) NOT NULL CHECK (rate > 0),
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (currency, rate_date)
+);
+
+-- Foreign-currency transactions of a user are read per currency
+CREATE INDEX IF NOT EXISTS idx_transactions_user_currency ON transactions(user_id, currency);
+
+COMMENT ON TABLE exchange_rates IS 'Daily exchange rates against a single reference currency';
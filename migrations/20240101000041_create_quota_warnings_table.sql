@@ -0,0 +1,13 @@
+-- Migration: Quota warnings
+-- Users are warned once per quota and period when their usage reaches 80% of a limit of
+-- their plan, before writes start being refused
+
+CREATE TABLE IF NOT EXISTS quota_warnings (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 'transactions', 'api_calls' or 'storage'
+    metric VARCHAR(32) NOT NULL,
+    -- Period of the usage counter warned about, the month for storage
+    period_start DATE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, metric, period_start)
+);
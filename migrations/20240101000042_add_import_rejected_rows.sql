@@ -0,0 +1,7 @@
+-- Migration: Rejected rows of imports
+-- Rows of an export that were not imported (bad date, invalid amount, duplicate...) are
+-- kept as a CSV in the blob store, downloadable at GET /api/imports/:id/rejected.csv to
+-- fix and upload again
+
+-- Rows in the report, none when there is no report
+ALTER TABLE imports ADD COLUMN IF NOT EXISTS rejected_rows INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,24 @@
+-- Migration: Refresh tokens
+-- Long-lived tokens exchanged at POST /api/auth/refresh for a new access token. Each use
+-- rotates the token: it is marked used and a new one of the same family (the tokens
+-- descending from one login) is issued. A used token coming back means it was copied,
+-- and the whole family is revoked
+
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Shared by every token rotated from the same login
+    family_id UUID NOT NULL,
+    -- SHA-256 of the token, hex, the token itself is only known to the client
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    -- When it was exchanged, and for which token
+    used_at TIMESTAMPTZ,
+    replaced_by UUID,
+    -- Logged out, or revoked with its family on reuse
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family_id ON refresh_tokens(family_id);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user_id ON refresh_tokens(user_id);
@@ -0,0 +1,71 @@
+-- Migration: Soft delete of transactions
+-- Deleting a transaction sets deleted_at instead of removing the row, so it can be
+-- restored. Listings, sums and reports leave deleted transactions out
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+-- Queries read the transactions that are not deleted
+CREATE INDEX IF NOT EXISTS idx_transactions_user_live ON transactions(user_id, created_at) WHERE deleted_at IS NULL;
+
+-- A deleted transaction has no current version: deleting closes it, restoring opens a new one
+CREATE OR REPLACE FUNCTION record_transaction_history() RETURNS trigger AS $$
+BEGIN
+    -- Re-encrypting a description leaves last_updated_at and everything else alone, the
+    -- current version is rewritten instead of a new one being made
+    IF TG_OP = 'UPDATE'
+        AND OLD.last_updated_at IS NOT DISTINCT FROM NEW.last_updated_at
+        AND (OLD.user_id, OLD.transaction_type, OLD.amount, OLD.currency, OLD.category,
+             OLD.encrypted_description, OLD.source, OLD.external_id, OLD.bank_account_id, OLD.created_at,
+             OLD.merchant_name, OLD.merchant_logo_url, OLD.deleted_at)
+            IS NOT DISTINCT FROM
+            (NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+             NEW.encrypted_description, NEW.source, NEW.external_id, NEW.bank_account_id, NEW.created_at,
+             NEW.merchant_name, NEW.merchant_logo_url, NEW.deleted_at)
+    THEN
+        UPDATE transaction_history SET description = NEW.description
+            WHERE id = NEW.id AND valid_to IS NULL;
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE transaction_history SET valid_to = NOW()
+            WHERE id = OLD.id AND valid_to IS NULL;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND NEW.deleted_at IS NULL THEN
+        INSERT INTO transaction_history (
+            id, user_id, transaction_type, amount, currency, category, description,
+            encrypted_description, source, external_id, bank_account_id, created_at,
+            last_updated_at, merchant_name, merchant_logo_url, valid_from
+        ) VALUES (
+            NEW.id, NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+            NEW.description, NEW.encrypted_description, NEW.source, NEW.external_id,
+            NEW.bank_account_id, NEW.created_at, NEW.last_updated_at, NEW.merchant_name,
+            NEW.merchant_logo_url, NOW()
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Deleting and restoring are announced as such rather than as updates
+CREATE OR REPLACE FUNCTION notify_transaction_change() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('transaction_changes', json_build_object(
+        'op', CASE
+            WHEN TG_OP = 'INSERT' THEN 'created'
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'deleted'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restored'
+            ELSE 'updated'
+        END,
+        'id', NEW.id,
+        'user_id', NEW.user_id,
+        'transaction_type', NEW.transaction_type,
+        'amount', NEW.amount,
+        'currency', NEW.currency,
+        'category', NEW.category,
+        'source', NEW.source,
+        'at', NOW()
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,4 @@
+-- Migration: Keyset pagination of users
+-- GET /api/users pages through users in (created_at, id) order from a cursor
+
+CREATE INDEX IF NOT EXISTS idx_users_created_id ON users(created_at, id);
@@ -0,0 +1,15 @@
+-- Migration: Password setup tokens
+-- Users provisioned without a password (bulk imports sending an invitation) get a
+-- one-time token by email, exchanged at POST /api/users/password-setup for the password
+-- they choose. Setting the password uses up every token of the user
+
+CREATE TABLE IF NOT EXISTS password_setup_tokens (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- SHA-256 of the token, hex, the token itself is only in the email
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_setup_tokens_user_id ON password_setup_tokens(user_id);
@@ -0,0 +1,25 @@
+-- Migration: Invitations
+-- Invite tokens pre-authorize a signup, so public registration can be turned off
+-- (PUBLIC_SIGNUP=false) on private instances. Admins and wallet owners create them, one
+-- may be bound to an email and carry a role in the owner's wallet, joined on signup.
+-- Each works once and until it expires
+
+CREATE TABLE IF NOT EXISTS invitations (
+    id UUID PRIMARY KEY,
+    -- SHA-256 of the token, hex, the token itself is only handed to its creator
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    -- Only this email may sign up with it, anyone holding the token when NULL
+    email TEXT,
+    -- Wallet joined on signup, with this role
+    wallet_owner_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20),
+    -- NULL when created by an admin
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    used_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    CONSTRAINT chk_invitations_role CHECK ((wallet_owner_id IS NULL) = (role IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS idx_invitations_wallet_owner_id ON invitations(wallet_owner_id);
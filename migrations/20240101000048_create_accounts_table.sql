@@ -0,0 +1,65 @@
+-- Migration: Accounts
+-- A user keeps their money in one or more accounts, e.g. Checking, Cash and Savings, each in
+-- one currency. Transactions may be booked to one of them, the balance of an account is its
+-- opening balance plus the amounts of the transactions booked to it
+
+CREATE TABLE IF NOT EXISTS accounts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    -- Balance before the first transaction booked to the account
+    opening_balance DECIMAL(19,4) NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT uq_accounts_user_name UNIQUE (user_id, name)
+);
+
+-- An account with transactions booked to it, deleted ones included, cannot be deleted
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS account_id UUID REFERENCES accounts(id);
+CREATE INDEX IF NOT EXISTS idx_transactions_account_id ON transactions(account_id) WHERE account_id IS NOT NULL;
+
+ALTER TABLE transaction_history ADD COLUMN IF NOT EXISTS account_id UUID;
+
+-- Transactions held for approval keep their account until approved
+ALTER TABLE transaction_approvals ADD COLUMN IF NOT EXISTS account_id UUID REFERENCES accounts(id) ON DELETE CASCADE;
+
+-- Versions record the account too, moving a transaction to another account makes a new one
+CREATE OR REPLACE FUNCTION record_transaction_history() RETURNS trigger AS $$
+BEGIN
+    -- Re-encrypting a description leaves last_updated_at and everything else alone, the
+    -- current version is rewritten instead of a new one being made
+    IF TG_OP = 'UPDATE'
+        AND OLD.last_updated_at IS NOT DISTINCT FROM NEW.last_updated_at
+        AND (OLD.user_id, OLD.transaction_type, OLD.amount, OLD.currency, OLD.category,
+             OLD.encrypted_description, OLD.source, OLD.external_id, OLD.bank_account_id, OLD.created_at,
+             OLD.merchant_name, OLD.merchant_logo_url, OLD.deleted_at, OLD.account_id)
+            IS NOT DISTINCT FROM
+            (NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+             NEW.encrypted_description, NEW.source, NEW.external_id, NEW.bank_account_id, NEW.created_at,
+             NEW.merchant_name, NEW.merchant_logo_url, NEW.deleted_at, NEW.account_id)
+    THEN
+        UPDATE transaction_history SET description = NEW.description
+            WHERE id = NEW.id AND valid_to IS NULL;
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE transaction_history SET valid_to = NOW()
+            WHERE id = OLD.id AND valid_to IS NULL;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') AND NEW.deleted_at IS NULL THEN
+        INSERT INTO transaction_history (
+            id, user_id, transaction_type, amount, currency, category, description,
+            encrypted_description, source, external_id, bank_account_id, account_id, created_at,
+            last_updated_at, merchant_name, merchant_logo_url, valid_from
+        ) VALUES (
+            NEW.id, NEW.user_id, NEW.transaction_type, NEW.amount, NEW.currency, NEW.category,
+            NEW.description, NEW.encrypted_description, NEW.source, NEW.external_id,
+            NEW.bank_account_id, NEW.account_id, NEW.created_at, NEW.last_updated_at,
+            NEW.merchant_name, NEW.merchant_logo_url, NOW()
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,22 @@
+-- Migration: Create budgets table
+-- What a user plans to spend on a category each month. Unlike spending limits they are
+-- advisory: transactions are never refused, the status only reports spent and remaining
+
+CREATE TABLE IF NOT EXISTS budgets (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    category VARCHAR(50) NOT NULL,
+
+    -- Planned for each calendar month in the user's time zone, only expenses in this currency count
+    amount DECIMAL(19,4) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT uq_budgets_user_category_currency UNIQUE (user_id, category, currency)
+);
+
+COMMENT ON TABLE budgets IS 'Advisory monthly amounts per category, distinct from enforced spending limits';
@@ -0,0 +1,29 @@
+-- Migration: Create budget_alerts table
+-- Recorded when a budget's spending in a month crosses a share of its amount (80% and 100%),
+-- at most once per budget, threshold and month. Clients list them and acknowledge them
+
+CREATE TABLE IF NOT EXISTS budget_alerts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    budget_id UUID NOT NULL REFERENCES budgets(id) ON DELETE CASCADE,
+    category VARCHAR(50) NOT NULL,
+
+    -- Percentage of the budget crossed, e.g. 80 or 100
+    threshold SMALLINT NOT NULL CHECK (threshold > 0),
+
+    -- First day of the month, in the user's time zone
+    period_start DATE NOT NULL,
+
+    -- Spent in the month and the budget's amount when the alert was recorded
+    spent DECIMAL(19,4) NOT NULL,
+    amount DECIMAL(19,4) NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    acknowledged_at TIMESTAMPTZ,
+
+    CONSTRAINT uq_budget_alerts_budget_threshold_period UNIQUE (budget_id, threshold, period_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_budget_alerts_user_id ON budget_alerts(user_id, created_at);
@@ -0,0 +1,38 @@
+-- Migration: Create transfers table
+-- Money moved between two of a user's accounts. Transfers are neither income nor expenses:
+-- they only change the balances of the two accounts. Between accounts in different currencies
+-- the amount taken from one, the amount put into the other and the rate applied are all kept
+
+CREATE TABLE IF NOT EXISTS transfers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- Accounts with transfers cannot be deleted, like those with transactions
+    from_account_id UUID NOT NULL REFERENCES accounts(id),
+    to_account_id UUID NOT NULL REFERENCES accounts(id),
+
+    -- Taken from the source account, in its currency
+    amount DECIMAL(19,4) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(3) NOT NULL,
+
+    -- Put into the destination account, in its currency
+    to_amount DECIMAL(19,4) NOT NULL CHECK (to_amount > 0),
+    to_currency VARCHAR(3) NOT NULL,
+
+    -- Units of to_currency per unit of currency, 1 between accounts in the same currency
+    rate DECIMAL(19,10) NOT NULL CHECK (rate > 0),
+
+    description VARCHAR(1000) NOT NULL DEFAULT '',
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_transfers_accounts CHECK (from_account_id <> to_account_id),
+    CONSTRAINT chk_transfers_same_currency CHECK (
+        currency <> to_currency OR (amount = to_amount AND rate = 1)
+    )
+);
+
+CREATE INDEX IF NOT EXISTS idx_transfers_user_id ON transfers(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_transfers_from_account_id ON transfers(from_account_id);
+CREATE INDEX IF NOT EXISTS idx_transfers_to_account_id ON transfers(to_account_id);
@@ -0,0 +1,21 @@
+-- Migration: Share links
+-- A user may share a report (e.g. the summary of a trip) with people without an account
+-- through a link. The report is computed when the link is created and kept as it was, the
+-- link shows that snapshot without authentication until it expires or its owner revokes it
+
+CREATE TABLE IF NOT EXISTS share_links (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- SHA-256 of the token, hex, the token itself is only handed to the user once
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    title VARCHAR(255) NOT NULL,
+    -- What was reported, the report's kind and parameters
+    report JSONB NOT NULL,
+    -- The report as it was when the link was created
+    snapshot JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_links_user_id ON share_links(user_id, created_at);
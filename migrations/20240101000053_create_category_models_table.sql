@@ -0,0 +1,16 @@
+-- Migration: Category models
+-- Models of how each user categorizes their merchants, rebuilt by the categories.train job
+-- and used to suggest categories. Each rebuild is a new version, the latest few are kept
+
+CREATE TABLE IF NOT EXISTS category_models (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL CHECK (version > 0),
+    -- Transactions changed after this are not in the model
+    trained_at TIMESTAMPTZ NOT NULL,
+    -- Categorized transactions the model learned from
+    transactions INTEGER NOT NULL,
+    -- Transactions per category by normalized merchant name
+    model JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, version)
+);
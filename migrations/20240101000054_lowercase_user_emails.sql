@@ -0,0 +1,10 @@
+-- Migration: Case-insensitive emails
+-- Emails are stored and looked up lowercased, so one address cannot sign up twice in
+-- different cases. Encrypted emails are lowercased by the crypto.reencrypt job, which also
+-- recomputes their blind index
+
+-- Fails when two users differ only by the case of their email, they have to be merged first
+UPDATE users SET email = LOWER(TRIM(email))
+WHERE email_hash IS NULL AND email <> LOWER(TRIM(email));
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email_lower ON users(LOWER(email));
@@ -0,0 +1,632 @@
+use crate::handlers::{self, AppState};
+use crate::jobs::{self, JobHandler};
+use crate::models::analytics_models::{self, AnalyticsParameters};
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::fx_models::PutExchangeRatesRequest;
+use crate::models::invitation_models::CreateInvitationRequest;
+use crate::models::job_models::{JobCreate, JobGetParameters};
+use crate::models::transaction_models::{ReassignTransactionsRequest, RecategorizeRequest};
+use crate::models::user_models::{SetPlanRequest, UserImportParameters};
+use crate::onboarding::{self, RosterOutcome};
+use crate::queries::{
+    analytics_queries, fx_queries, invitation_queries, job_queries, schedule_queries, user_queries,
+};
+use crate::quotas;
+use crate::repairs;
+use crate::signatures::constant_time_eq;
+use crate::validation::{ValidJson, ValidationErrors};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Most jobs listed in one response
+const MAX_JOBS_LIMIT: i64 = 1000;
+
+/// Operator endpoints under /api/admin, only served when an admin token is configured
+pub struct Admin {
+    pub state: AppState,
+    /// Expected as "Authorization: Bearer <token>"
+    pub token: String,
+    /// Fewest distinct users an analytics figure may be computed from (the k of k-anonymity)
+    pub min_group_size: i64,
+    /// Whether users imported without a password can be emailed an invitation
+    pub invitations: bool,
+    /// Whether transactions are enriched with merchants, which recategorizing relies on
+    pub enrichment: bool,
+}
+
+impl Admin {
+    pub fn router<S>(self) -> Router<S> {
+        let token = Arc::new(self.token.clone());
+        Router::new()
+            .route("/api/admin/jobs", get(get_jobs))
+            .route("/api/admin/jobs/:id/retry", post(retry_job))
+            .route("/api/admin/jobs/:id/cancel", post(cancel_job))
+            .route("/api/admin/job-schedules", get(get_job_schedules))
+            .route("/api/admin/plans", get(get_plans))
+            .route("/api/admin/users/:id/plan", put(set_user_plan))
+            .route("/api/admin/users/import", post(import_users))
+            .route(
+                "/api/admin/invitations",
+                get(get_invitations).post(create_invitation),
+            )
+            .route("/api/admin/invitations/:id", delete(delete_invitation))
+            .route("/api/admin/exchange-rates", put(put_exchange_rates))
+            .route(
+                "/api/admin/repairs/reassign-transactions",
+                post(reassign_transactions),
+            )
+            .route("/api/admin/repairs/recategorize", post(recategorize))
+            .route("/api/admin/analytics/users", get(get_user_analytics))
+            .route(
+                "/api/admin/analytics/transactions",
+                get(get_transaction_analytics),
+            )
+            .route(
+                "/api/admin/analytics/categories",
+                get(get_category_analytics),
+            )
+            .route_layer(middleware::from_fn_with_state(token, require_admin_token))
+            .with_state(Arc::new(self))
+    }
+}
+
+/// Reject requests without the admin bearer token with a 401
+async fn require_admin_token(
+    State(token): State<Arc<String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(given.as_bytes(), token.as_bytes()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(req).await
+}
+
+/// GET /api/admin/jobs - jobs with their durations and errors, plus how many are in each status
+/// Lists queued, running and failed jobs unless a status is given
+async fn get_jobs(
+    State(admin): State<Arc<Admin>>,
+    Query(params): Query<JobGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_JOBS_LIMIT);
+    let jobs = job_queries::get_jobs(
+        &admin.state.db,
+        params.status,
+        params.kind.as_deref(),
+        limit,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching jobs: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let counts = job_queries::count_by_status(&admin.state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Error counting jobs: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let counts: Map<String, Value> = counts
+        .into_iter()
+        .map(|(status, count)| (status.to_string(), json!(count)))
+        .collect();
+
+    Ok(Json(json!({
+        "message": "Jobs retrieved successfully",
+        "counts": counts,
+        "jobs": jobs
+    })))
+}
+
+/// POST /api/admin/jobs/:id/retry - queue a failed or cancelled job again
+async fn retry_job(
+    State(admin): State<Arc<Admin>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    job_exists(&admin, id).await?;
+    let job = job_queries::retry(&admin.state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error retrying job {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        // Not failed or cancelled, or the same job is already queued
+        .ok_or(StatusCode::CONFLICT)?;
+
+    handlers::record_audit(
+        &admin.state,
+        AuditEntryCreate::new("retry", "job", Some(id)).details(json!({ "kind": job.kind })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Job queued for retry",
+        "job": job
+    })))
+}
+
+/// POST /api/admin/jobs/:id/cancel - cancel a queued or running job
+async fn cancel_job(
+    State(admin): State<Arc<Admin>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    job_exists(&admin, id).await?;
+    let job = job_queries::cancel(&admin.state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error cancelling job {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        // Already finished
+        .ok_or(StatusCode::CONFLICT)?;
+
+    handlers::record_audit(
+        &admin.state,
+        AuditEntryCreate::new("cancel", "job", Some(id)).details(json!({ "kind": job.kind })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Job cancelled",
+        "job": job
+    })))
+}
+
+async fn job_exists(admin: &Admin, id: Uuid) -> Result<(), StatusCode> {
+    match job_queries::get_job(&admin.state.db, id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Error fetching job {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// GET /api/admin/job-schedules - cron schedules with their last and next runs
+async fn get_job_schedules(State(admin): State<Arc<Admin>>) -> Result<Json<Value>, StatusCode> {
+    let schedules = schedule_queries::get_schedules(&admin.state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching job schedules: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Job schedules retrieved successfully",
+        "schedules": schedules
+    })))
+}
+
+/// GET /api/admin/plans - the configured plans and their quotas, empty when quotas are disabled
+async fn get_plans() -> Json<Value> {
+    let mut plans: Vec<&quotas::Plan> = quotas::plans()
+        .map(|plans| plans.values().collect())
+        .unwrap_or_default();
+    plans.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(json!({
+        "message": "Plans retrieved successfully",
+        "default_plan": quotas::DEFAULT_PLAN,
+        "plans": plans
+    }))
+}
+
+/// PUT /api/admin/users/:id/plan - move a user to another of the configured plans
+async fn set_user_plan(
+    State(admin): State<Arc<Admin>>,
+    Path(id): Path<Uuid>,
+    ValidJson(req): ValidJson<SetPlanRequest>,
+) -> Result<Json<Value>, Response> {
+    let known = quotas::plans().is_some_and(|plans| plans.contains_key(&req.plan));
+    if !known {
+        let mut errors = ValidationErrors::default();
+        errors.add("plan", "must be one of the configured plans");
+        return Err(errors.into_response());
+    }
+    let updated = user_queries::set_plan(&admin.state.db, id, &req.plan)
+        .await
+        .map_err(|e| {
+            eprintln!("Error setting the plan of user {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+    if !updated {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+
+    handlers::record_audit(
+        &admin.state,
+        AuditEntryCreate::new("update", "user", Some(id)).details(json!({ "plan": req.plan })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Plan updated successfully",
+        "plan": req.plan
+    })))
+}
+
+/// POST /api/admin/users/import - provision the users of a roster CSV (email, name, role,
+/// password), inviting those without a password and adding those with a role to the wallet
+/// of wallet_owner. Every row is reported with what became of it
+async fn import_users(
+    State(admin): State<Arc<Admin>>,
+    Query(params): Query<UserImportParameters>,
+    body: String,
+) -> Result<Json<Value>, Response> {
+    let (rows, mut results) = onboarding::parse_roster(&body).map_err(|e| {
+        eprintln!("Error reading roster: {}", e);
+        (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response()
+    })?;
+    if let Some(owner_id) = params.wallet_owner {
+        let exists = user_queries::user_exists(&admin.state.db, owner_id)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching user {}: {}", owner_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            })?;
+        if !exists {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        }
+    }
+
+    // Rows already provisioned stay so when a later one fails, importing the roster again
+    // finds them as existing
+    for row in rows {
+        let line = row.line;
+        let result =
+            onboarding::provision(&admin.state, row, params.wallet_owner, admin.invitations)
+                .await
+                .map_err(|e| {
+                    eprintln!("Error provisioning the user of roster line {}: {}", line, e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                })?;
+        results.push(result);
+    }
+    results.sort_by_key(|result| result.line);
+    let count = |outcome: RosterOutcome| {
+        results
+            .iter()
+            .filter(|result| result.outcome == outcome)
+            .count()
+    };
+
+    Ok(Json(json!({
+        "message": "Users imported successfully",
+        "created": count(RosterOutcome::Created),
+        "invited": count(RosterOutcome::Invited),
+        "existing": count(RosterOutcome::Existing),
+        "rejected": count(RosterOutcome::Rejected),
+        "users": results
+    })))
+}
+
+/// Queue a repair job and audit it, 409 while the same repair is queued or running
+async fn queue_repair(admin: &Admin, kind: &str, payload: Value) -> Result<Response, StatusCode> {
+    // Payloads name what is repaired, a repair of something else may run alongside
+    let job = JobCreate::new(kind, payload.clone())
+        .dedupe_key(payload.to_string())
+        .max_attempts(3);
+    let id = jobs::enqueue(&admin.state.db, job)
+        .await
+        .map_err(|e| {
+            eprintln!("Error queueing {}: {}", kind, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    handlers::record_audit(
+        &admin.state,
+        AuditEntryCreate::new("queue", "job", Some(id))
+            .details(json!({ "kind": kind, "payload": payload })),
+    )
+    .await;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "Repair queued successfully",
+            "job_id": id
+        })),
+    )
+        .into_response())
+}
+
+/// POST /api/admin/repairs/reassign-transactions - move transactions recorded under the wrong
+/// user to the right one, in a background job (see repairs.rs)
+async fn reassign_transactions(
+    State(admin): State<Arc<Admin>>,
+    ValidJson(req): ValidJson<ReassignTransactionsRequest>,
+) -> Result<Response, StatusCode> {
+    let mut errors = ValidationErrors::default();
+    for (field, id) in [
+        ("from_user_id", req.from_user_id),
+        ("to_user_id", req.to_user_id),
+    ] {
+        let exists = user_queries::user_exists(&admin.state.db, id)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching user {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        if !exists {
+            errors.add(field, "must be an existing user");
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(errors.into_response());
+    }
+
+    let payload = serde_json::to_value(&req).map_err(|e| {
+        eprintln!("Error writing repair payload: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    queue_repair(&admin, repairs::ReassignTransactionsJob.kind(), payload).await
+}
+
+/// POST /api/admin/repairs/recategorize - look up the merchant of the transactions still in
+/// Other or Uncategorized again, of one user or everyone, in a background job
+async fn recategorize(
+    State(admin): State<Arc<Admin>>,
+    Json(req): Json<RecategorizeRequest>,
+) -> Result<Response, StatusCode> {
+    if !admin.enrichment {
+        return Ok((
+            StatusCode::CONFLICT,
+            Json(json!({ "message": "Merchant enrichment is not configured (MERCHANT_API_URL)" })),
+        )
+            .into_response());
+    }
+    if let Some(user_id) = req.user_id {
+        let exists = user_queries::user_exists(&admin.state.db, user_id)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching user {}: {}", user_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        if !exists {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    let payload = serde_json::to_value(&req).map_err(|e| {
+        eprintln!("Error writing repair payload: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    queue_repair(&admin, repairs::RecategorizeJob.kind(), payload).await
+}
+
+/// GET /api/admin/invitations - invitations not used yet and not expired, newest first
+async fn get_invitations(State(admin): State<Arc<Admin>>) -> Result<Json<Value>, StatusCode> {
+    let invitations = invitation_queries::get_open(&admin.state.db, None)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching invitations: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Invitations retrieved successfully",
+        "invitations": invitations
+    })))
+}
+
+/// POST /api/admin/invitations - invite someone to sign up, optionally bound to their email
+/// and into the wallet of wallet_owner with a role. The token is in the answer only
+async fn create_invitation(
+    State(admin): State<Arc<Admin>>,
+    ValidJson(req): ValidJson<CreateInvitationRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let wallet = match (req.wallet_owner, req.role) {
+        (Some(owner_id), Some(role)) => {
+            let exists = user_queries::user_exists(&admin.state.db, owner_id)
+                .await
+                .map_err(|e| {
+                    eprintln!("Error fetching user {}: {}", owner_id, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            if !exists {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            Some((owner_id, role))
+        }
+        _ => None,
+    };
+    let (token, invitation) = handlers::create_invitation(
+        &admin.state,
+        req.invitee_email,
+        wallet,
+        None,
+        req.expires_in_days,
+    )
+    .await?;
+
+    Ok(Json(json!({
+        "message": "Invitation created successfully",
+        "token": token,
+        "invitation": invitation
+    })))
+}
+
+/// DELETE /api/admin/invitations/:id - withdraw an invitation not used yet
+async fn delete_invitation(
+    State(admin): State<Arc<Admin>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let deleted = invitation_queries::delete_open(&admin.state.db, id, None)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting invitation {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    handlers::record_audit(
+        &admin.state,
+        AuditEntryCreate::new("delete", "invitation", Some(id)),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Invitation withdrawn successfully"
+    })))
+}
+
+/// PUT /api/admin/exchange-rates - load one day's exchange rates, replacing those fetched
+/// Useful without FX_RATES_URL, or to correct a day
+async fn put_exchange_rates(
+    State(admin): State<Arc<Admin>>,
+    ValidJson(req): ValidJson<PutExchangeRatesRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut rates = req.rates;
+    rates.insert(req.base, rust_decimal::Decimal::ONE);
+    fx_queries::upsert_rates(&admin.state.db, req.date, &rates)
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing exchange rates of {}: {}", req.date, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    handlers::record_audit(
+        &admin.state,
+        AuditEntryCreate::new("update", "exchange_rates", None)
+            .details(json!({ "date": req.date, "base": req.base, "rates": rates })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Exchange rates stored successfully",
+        "date": req.date,
+        "rates": rates
+    })))
+}
+
+/// The [start, end) range of the requested analytics window, whole UTC days up to today
+fn analytics_window(
+    params: &AnalyticsParameters,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), StatusCode> {
+    let days = params.days.unwrap_or(30);
+    if !analytics_models::WINDOWS.contains(&days) {
+        eprintln!("Unsupported analytics window: {} days", days);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Today is left out, a partial day is a much smaller group
+    let end = Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc();
+    Ok((end - Duration::days(days), end))
+}
+
+/// GET /api/admin/analytics/users - active and newly signed up users over the window
+/// Counts from fewer users than the minimum group size are reported as null
+async fn get_user_analytics(
+    State(admin): State<Arc<Admin>>,
+    Query(params): Query<AnalyticsParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let (start, end) = analytics_window(&params)?;
+    let internal_error = |e: anyhow::Error| {
+        eprintln!("Error computing user analytics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let db = &admin.state.db;
+    let active_users = analytics_queries::count_active_users(db, start, end, admin.min_group_size)
+        .await
+        .map_err(internal_error)?;
+    let new_users = analytics_queries::count_new_users(db, start, end, admin.min_group_size)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(json!({
+        "message": "User analytics retrieved successfully",
+        "start": start,
+        "end": end,
+        "min_group_size": admin.min_group_size,
+        "active_users": active_users,
+        "new_users": new_users
+    })))
+}
+
+/// GET /api/admin/analytics/transactions - active users and transactions per UTC day
+/// Days with fewer active users than the minimum group size are left out
+async fn get_transaction_analytics(
+    State(admin): State<Arc<Admin>>,
+    Query(params): Query<AnalyticsParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let (start, end) = analytics_window(&params)?;
+    let days =
+        analytics_queries::get_daily_activity(&admin.state.db, start, end, admin.min_group_size)
+            .await
+            .map_err(|e| {
+                eprintln!("Error computing transaction analytics: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+    Ok(Json(json!({
+        "message": "Transaction analytics retrieved successfully",
+        "start": start,
+        "end": end,
+        "min_group_size": admin.min_group_size,
+        "days": days
+    })))
+}
+
+/// GET /api/admin/analytics/categories - how transactions are spread over the categories
+/// Categories used by fewer users than the minimum group size are left out, shares are
+/// of the reported transactions of the same type
+async fn get_category_analytics(
+    State(admin): State<Arc<Admin>>,
+    Query(params): Query<AnalyticsParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let (start, end) = analytics_window(&params)?;
+    let usage =
+        analytics_queries::get_category_usage(&admin.state.db, start, end, admin.min_group_size)
+            .await
+            .map_err(|e| {
+                eprintln!("Error computing category analytics: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    let categories: Vec<Value> = usage
+        .iter()
+        .map(|u| {
+            let total: i64 = usage
+                .iter()
+                .filter(|other| other.transaction_type == u.transaction_type)
+                .map(|other| other.transactions)
+                .sum();
+            json!({
+                "transaction_type": u.transaction_type,
+                "category": u.category,
+                "transactions": u.transactions,
+                "users": u.users,
+                "share": u.transactions as f64 / total as f64,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "message": "Category analytics retrieved successfully",
+        "start": start,
+        "end": end,
+        "min_group_size": admin.min_group_size,
+        "categories": categories
+    })))
+}
@@ -0,0 +1,83 @@
+// Weekly allowances of child accounts
+//
+// The scheduler queues "allowances.credit" every Monday morning (see DEFAULT_SCHEDULES).
+// Each child with an allowance gets it as an income transaction whose external id names the
+// ISO week, so a run repeated in the same week, by a retry or a changed schedule, credits nothing more.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::transaction_models::{
+    TransactionCategory, TransactionCreate, TransactionSource, TransactionType,
+};
+use crate::queries::{audit_queries, family_queries, transaction_queries, webhook_queries};
+use axum::async_trait;
+use chrono::{Datelike, Utc};
+use serde_json::{Value, json};
+
+/// Job crediting the allowances of the current week
+pub struct CreditAllowancesJob;
+
+#[async_trait]
+impl JobHandler for CreditAllowancesJob {
+    fn kind(&self) -> &'static str {
+        "allowances.credit"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let week = Utc::now().iso_week();
+        let week = format!("{}-W{:02}", week.year(), week.week());
+        let mut credited = 0;
+        for child in family_queries::get_allowances(db).await? {
+            let Some(allowance) = child.allowance else {
+                continue;
+            };
+            let transaction = TransactionCreate::new(
+                child.child_id,
+                TransactionType::Income,
+                allowance,
+                Some(TransactionCategory::Other),
+                Some(format!("Weekly allowance {}", week)),
+            )
+            .with_origin(
+                TransactionSource::Allowance,
+                Some(format!("allowance-{}", week)),
+            );
+            let Some(transaction_id) =
+                transaction_queries::create_transaction(db, &transaction).await?
+            else {
+                continue;
+            };
+            credited += 1;
+
+            let entry = AuditEntryCreate::new("create", "transaction", Some(transaction_id))
+                .actor(child.parent_id)
+                .details(json!({
+                    "transaction_type": transaction.transaction_type,
+                    "amount": transaction.amount,
+                    "category": transaction.category,
+                    "source": transaction.source,
+                }));
+            if let Err(e) = audit_queries::append(db, &entry).await {
+                tracing::error!(error = %e, "Recording audit entry for allowance failed");
+            }
+            let data = json!({
+                "id": transaction_id,
+                "child_id": child.child_id,
+                "amount": transaction.amount,
+                "week": week,
+            });
+            for user_id in [child.child_id, child.parent_id] {
+                if let Err(e) =
+                    webhook_queries::enqueue(db, user_id, "allowance.credited", &data).await
+                {
+                    tracing::error!(error = %e, "Queueing allowance.credited webhook failed");
+                }
+            }
+        }
+        if credited > 0 {
+            tracing::info!(credited, %week, "Allowances credited");
+        }
+        Ok(())
+    }
+}
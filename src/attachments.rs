@@ -0,0 +1,301 @@
+// Attachment contents and thumbnails
+//
+// Attachments are kept in the blob store, those uploaded before it existed in the database.
+// Uploads are streamed into the blob store as they arrive, measured and hashed on the way:
+// each type has its own size limit and files must start like files of their type do.
+// Receipts are uploaded at full camera resolution, far too large for list views. After an
+// image is uploaded a job renders a small PNG thumbnail of it with an external command
+// (ImageMagick by default, see THUMBNAIL_COMMAND), which reads the image on stdin and
+// writes the thumbnail to stdout. Until the job has run the thumbnail endpoint answers 404.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::models::attachment_models::{self, AttachmentQuery, ThumbnailStatus};
+use crate::queries::attachment_queries;
+use crate::storage::{self, BlobStore, ByteStream};
+use axum::async_trait;
+use axum::body::Bytes;
+use futures_util::stream::{self, StreamExt};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Content type of generated thumbnails
+pub const THUMBNAIL_CONTENT_TYPE: &str = "image/png";
+
+/// Why an upload was refused while it was read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadRejected {
+    /// Larger than the limit of its type, in bytes
+    TooLarge(u64),
+    /// Does not look like a file of its declared type
+    WrongType,
+}
+
+impl fmt::Display for UploadRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadRejected::TooLarge(limit) => {
+                write!(f, "Attachments of this type are limited to {} bytes", limit)
+            }
+            UploadRejected::WrongType => f.write_str("The file is not of its declared type"),
+        }
+    }
+}
+
+impl std::error::Error for UploadRejected {}
+
+/// Size, checksum and type check of an upload, updated chunk by chunk
+pub struct UploadMeter {
+    content_type: String,
+    limit: u64,
+    size: u64,
+    hasher: Sha256,
+    /// First bytes of the file, for the signature check
+    head: Vec<u8>,
+    checked: bool,
+    rejected: Option<UploadRejected>,
+}
+
+impl UploadMeter {
+    /// None when the type is not accepted
+    pub fn new(content_type: &str) -> Option<Self> {
+        Some(Self {
+            content_type: content_type.to_string(),
+            limit: attachment_models::max_size(content_type)?,
+            size: 0,
+            hasher: Sha256::new(),
+            head: Vec::new(),
+            checked: false,
+            rejected: None,
+        })
+    }
+
+    fn check_signature(&mut self) -> Result<(), UploadRejected> {
+        self.checked = true;
+        if attachment_models::matches_signature(&self.content_type, &self.head) {
+            Ok(())
+        } else {
+            Err(UploadRejected::WrongType)
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) -> Result<(), UploadRejected> {
+        self.size += chunk.len() as u64;
+        if self.size > self.limit {
+            return Err(UploadRejected::TooLarge(self.limit));
+        }
+        self.hasher.update(chunk);
+        if !self.checked {
+            let wanted = attachment_models::SIGNATURE_BYTES - self.head.len();
+            self.head
+                .extend_from_slice(&chunk[..wanted.min(chunk.len())]);
+            if self.head.len() == attachment_models::SIGNATURE_BYTES {
+                self.check_signature()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Account for the next chunk, remembering why the upload was refused if it was
+    pub fn add(&mut self, chunk: &[u8]) -> Result<(), UploadRejected> {
+        if let Some(rejected) = &self.rejected {
+            return Err(rejected.clone());
+        }
+        let added = self.update(chunk);
+        if let Err(rejected) = &added {
+            self.rejected = Some(rejected.clone());
+        }
+        added
+    }
+
+    /// Why the upload was refused, if it was
+    pub fn rejected(&self) -> Option<&UploadRejected> {
+        self.rejected.as_ref()
+    }
+
+    /// Size and hex SHA-256 of the whole file, once the body has been read
+    ///
+    /// # Errors
+    /// Returns an error when a file too short to have been checked is not of its type
+    pub fn finish(&mut self) -> Result<(u64, String), UploadRejected> {
+        // Empty files are of no type, refused as such by the caller
+        if !self.checked && self.size > 0 {
+            self.check_signature()?;
+        }
+        let sha256 = std::mem::take(&mut self.hasher)
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok((self.size, sha256))
+    }
+}
+
+/// An upload passed through a meter, and copied to a scanner if there is one. The stream
+/// fails as soon as the meter refuses the upload
+pub fn metered(
+    body: ByteStream,
+    meter: Arc<Mutex<UploadMeter>>,
+    scan: Option<mpsc::Sender<Bytes>>,
+) -> ByteStream {
+    stream::unfold(Some((body, meter, scan)), |state| async move {
+        let (mut body, meter, scan) = state?;
+        let chunk = match body.next().await? {
+            Ok(chunk) => chunk,
+            Err(e) => return Some((Err(e), None)),
+        };
+        let added = meter.lock().expect("upload meter poisoned").add(&chunk);
+        if let Err(rejected) = added {
+            return Some((Err(std::io::Error::other(rejected)), None));
+        }
+        if let Some(scan) = &scan {
+            // A scanner that gave up reports its own error
+            let _ = scan.send(chunk.clone()).await;
+        }
+        Some((Ok(chunk), Some((body, meter, scan))))
+    })
+    .boxed()
+}
+
+/// Chunks sent through a channel, as a stream
+pub fn channel_stream(receiver: mpsc::Receiver<Bytes>) -> ByteStream {
+    stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((Ok(chunk), receiver))
+    })
+    .boxed()
+}
+
+/// Move a blob to another key, by copying it through the server
+pub async fn move_blob(
+    store: &dyn BlobStore,
+    from: &str,
+    to: &str,
+    content_type: &str,
+) -> anyhow::Result<()> {
+    let body = store
+        .get(from)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Blob {} is missing", from))?;
+    store.put(to, content_type, body).await?;
+    store.delete(from).await
+}
+
+/// Contents of an attachment, None when they are gone from the blob store
+pub async fn open(
+    db: &DbPool,
+    store: &dyn BlobStore,
+    attachment: &AttachmentQuery,
+) -> anyhow::Result<Option<ByteStream>> {
+    match &attachment.storage_key {
+        Some(key) => store.get(key).await,
+        None => Ok(attachment_queries::get_data(db, attachment.id)
+            .await?
+            .map(storage::bytes_stream)),
+    }
+}
+
+/// Job generating the thumbnail of one attachment, queued when an image is uploaded
+pub struct GenerateThumbnailJob {
+    /// Program and arguments, e.g. ["convert", "-[0]", "-thumbnail", "320x320>", "png:-"]
+    pub command: Vec<String>,
+    pub store: Arc<dyn BlobStore>,
+}
+
+impl GenerateThumbnailJob {
+    /// Split a command line on whitespace, None when it is empty
+    pub fn from_command_line(command: &str, store: Arc<dyn BlobStore>) -> Option<Self> {
+        let command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        (!command.is_empty()).then_some(Self { command, store })
+    }
+
+    async fn render(&self, image: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("No thumbnail command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Running {} failed: {}", program, e))?;
+
+        // Written while the output is read, large images do not fit in the pipe buffers
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("No stdin for {}", program))?;
+        let writer = tokio::spawn(async move {
+            let written = stdin.write_all(&image).await;
+            drop(stdin);
+            written
+        });
+        let output = child.wait_with_output().await?;
+        // The command may stop reading early (e.g. only the first frame of a GIF)
+        let _ = writer.await;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "{} exited with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        if output.stdout.is_empty() {
+            return Err(anyhow::anyhow!("{} wrote no thumbnail", program));
+        }
+        Ok(output.stdout)
+    }
+}
+
+#[async_trait]
+impl JobHandler for GenerateThumbnailJob {
+    fn kind(&self) -> &'static str {
+        "attachments.thumbnail"
+    }
+
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()> {
+        let id: Uuid = payload
+            .get("attachment_id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("Missing attachment_id"))?
+            .parse()?;
+        // Deleted along with its transaction in the meantime
+        let Some(attachment) = attachment_queries::get_attachment(db, id).await? else {
+            return Ok(());
+        };
+        let image = open(db, self.store.as_ref(), &attachment)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Contents of attachment {} are missing", id))?;
+        let limit = attachment_models::max_size(&attachment.content_type).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Attachments of type {} are not accepted",
+                attachment.content_type
+            )
+        })?;
+        let image = storage::read_to_end(image, limit as usize).await?;
+
+        match self.render(image).await {
+            Ok(thumbnail) => {
+                attachment_queries::set_thumbnail(db, id, Some(&thumbnail), ThumbnailStatus::Ready)
+                    .await
+            }
+            Err(e) => {
+                // Marked failed right away, a retry that succeeds marks it ready again
+                attachment_queries::set_thumbnail(db, id, None, ThumbnailStatus::Failed).await?;
+                Err(e)
+            }
+        }
+    }
+}
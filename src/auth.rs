@@ -0,0 +1,809 @@
+// Password hashing and access tokens
+//
+// Passwords are hashed with argon2 (default parameters, random salt) as soon as they come in,
+// models and queries only ever carry the PHC string ("$argon2id$v=19$..."). Verification
+// parses the stored hash, so its own parameters apply even after the defaults change.
+//
+// With JWT_SECRET set, POST /api/auth/login exchanges an email and password for a JWT access
+// token (HS256, valid JWT_TTL_SECS) and every request under /api needs one as
+// "Authorization: Bearer <token>", but for signing up, checking an email is free, invited users
+// setting their password, logging in, share links, the routes with their own authentication
+// (admin token, Firefly III access tokens) and the signed webhooks. New routes are protected
+// unless they are added to is_protected's exceptions. A request may only name the token's user:
+// the user_email and user_id of its query string and JSON body and the email in
+// /api/users/<email>/... must all be theirs. An editor recording a transaction in a shared
+// wallet names themselves in created_by_email, the owner in user_email is then checked by
+// the handler against the wallet's members.
+//
+// Logging in also issues a refresh token (valid JWT_REFRESH_TTL_SECS), exchanged at
+// POST /api/auth/refresh for a new access token and a new refresh token: each refresh token
+// works once. Presenting one again means it leaked, so every token descending from the same
+// login (its family) is revoked and the user has to log in again. POST /api/auth/logout
+// revokes the family, access tokens already issued stay valid until they expire.
+
+use crate::handlers::{self, AppState};
+use crate::importers;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::auth_models::{
+    AuthUser, Claims, LoginRequest, RefreshRequest, RefreshTokenCreate, RefreshTokenQuery,
+};
+use crate::models::user_models;
+use crate::queries::{refresh_token_queries, user_queries};
+use crate::redact;
+use crate::validation::ValidJson;
+use argon2::{
+    Argon2, PasswordHasher, PasswordVerifier,
+    password_hash::{PasswordHash, SaltString, rand_core::OsRng},
+};
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    extract::{Query, Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Header of every token issued, the only one accepted
+const TOKEN_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Largest JSON body read to check whose data a request names: axum's default limit, the
+/// one every route taking JSON is served with, batches included
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Hash a password for storage
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("password hashing failed: {e}"))?
+        .to_string())
+}
+
+/// Hash of a random password nobody knows, for users who cannot log in with a password
+/// until one is set
+pub fn unusable_password_hash() -> anyhow::Result<String> {
+    hash_password(&Uuid::new_v4().to_string())
+}
+
+/// Check a password against a stored hash, false when the hash cannot be parsed
+pub fn verify_password(password: &str, hashed: &str) -> bool {
+    PasswordHash::new(hashed)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Why an access token was rejected
+#[derive(Debug, PartialEq)]
+pub enum TokenError {
+    Malformed,
+    /// Another algorithm than HS256, "none" included
+    UnsupportedAlgorithm,
+    BadSignature,
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "Malformed access token"),
+            TokenError::UnsupportedAlgorithm => write!(f, "Unsupported token algorithm"),
+            TokenError::BadSignature => write!(f, "Invalid access token signature"),
+            TokenError::Expired => write!(f, "Access token expired"),
+        }
+    }
+}
+
+/// Issues and verifies HS256 access tokens
+pub struct TokenIssuer {
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+impl TokenIssuer {
+    pub fn new(secret: &str, ttl_secs: u64) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+            ttl: Duration::seconds(i64::try_from(ttl_secs).unwrap_or(i64::MAX / 1000)),
+        }
+    }
+
+    fn mac(&self, signing_input: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(signing_input.as_bytes());
+        mac
+    }
+
+    /// Token of the user valid for the configured lifetime from `now`
+    pub fn issue(&self, user_id: Uuid, email: &str, now: DateTime<Utc>) -> (String, Claims) {
+        let claims = Claims {
+            sub: user_id,
+            email: email.to_string(),
+            iat: now.timestamp(),
+            exp: (now + self.ttl).timestamp(),
+        };
+        // Serializing a struct of plain fields cannot fail
+        let payload = serde_json::to_vec(&claims).unwrap_or_default();
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(TOKEN_HEADER),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&signing_input).finalize().into_bytes());
+        (format!("{}.{}", signing_input, signature), claims)
+    }
+
+    /// Claims of a token signed with our secret and not expired at `now`
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<Claims, TokenError> {
+        let (signing_input, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let (header, payload) = signing_input.split_once('.').ok_or(TokenError::Malformed)?;
+
+        let header: Value = URL_SAFE_NO_PAD
+            .decode(header)
+            .ok()
+            .and_then(|h| serde_json::from_slice(&h).ok())
+            .ok_or(TokenError::Malformed)?;
+        if header.get("alg").and_then(Value::as_str) != Some("HS256") {
+            return Err(TokenError::UnsupportedAlgorithm);
+        }
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TokenError::Malformed)?;
+        // verify_slice compares in constant time
+        self.mac(signing_input)
+            .verify_slice(&signature)
+            .map_err(|_| TokenError::BadSignature)?;
+
+        let claims: Claims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|p| serde_json::from_slice(&p).ok())
+            .ok_or(TokenError::Malformed)?;
+        if claims.exp <= now.timestamp() {
+            return Err(TokenError::Expired);
+        }
+        Ok(claims)
+    }
+}
+
+/// Whether the request goes to the routes only the data's own user may call
+/// Every API route is, but for the public ones and those with their own authentication
+fn is_protected(method: &Method, path: &str) -> bool {
+    let under = |prefix: &str| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    // Health checks, metrics, the frontend and the UI, which has its own session
+    if !under("/api") {
+        return false;
+    }
+    // Signing up, checking an email is free beforehand and setting the password of an
+    // invitation need no token
+    if (method == Method::POST && path == "/api/users")
+        || (method == Method::GET && path == "/api/users/availability")
+        || (method == Method::POST && path == "/api/users/password-setup")
+    {
+        return false;
+    }
+    let public = under("/api/meta")
+        || under("/api/auth")
+        // Share links carry their own token
+        || under("/api/shared")
+        // Admin token and Firefly III access tokens
+        || under("/api/admin")
+        || under("/api/v1")
+        // Webhooks, signed by their sender
+        || path == "/api/integrations/telegram/webhook"
+        || path == "/api/integrations/slack"
+        || path == "/api/billing/stripe/webhook";
+    !public
+}
+
+/// Where users get a code linking a Telegram chat to their wallet
+const TELEGRAM_LINK_PATH: &str = "/api/integrations/telegram/link";
+
+/// Whether the request acts on the token's user, who then need not be named
+fn acts_on_token_user(path: &str) -> bool {
+    path.starts_with("/api/users/me/") || path == TELEGRAM_LINK_PATH
+}
+
+/// Users a request names, in its path, query string and JSON body
+#[derive(Debug, Default, PartialEq)]
+struct NamedUsers {
+    emails: Vec<String>,
+    ids: Vec<Uuid>,
+    /// Ids that are not ids, answered like someone else's
+    invalid: bool,
+}
+
+impl NamedUsers {
+    fn add_id(&mut self, value: &str) {
+        match value.parse() {
+            Ok(id) => self.ids.push(id),
+            Err(_) => self.invalid = true,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.emails.is_empty() && self.ids.is_empty() && !self.invalid
+    }
+
+    /// Every user named is the token's
+    fn only(&self, user: &AuthUser) -> bool {
+        !self.invalid
+            && self.ids.iter().all(|id| *id == user.id)
+            && self
+                .emails
+                .iter()
+                .all(|email| user_models::normalize_email(email) == user.email)
+    }
+}
+
+fn named_users(
+    method: &Method,
+    path: &str,
+    query: &[(String, String)],
+    body: Option<&Value>,
+) -> NamedUsers {
+    let mut named = NamedUsers::default();
+    // /api/users/<email>/..., where "me" is the token's user
+    if let Some(rest) = path.strip_prefix("/api/users/") {
+        let segment = rest.split('/').next().unwrap_or_default();
+        if !segment.is_empty() && segment != "me" {
+            named
+                .emails
+                .push(percent_decode_str(segment).decode_utf8_lossy().into_owned());
+        }
+    }
+    for (key, value) in query {
+        match key.as_str() {
+            "user_email" => named.emails.push(value.clone()),
+            "user_id" => named.add_id(value),
+            _ => {}
+        }
+    }
+
+    let Some(Value::Object(body)) = body else {
+        return named;
+    };
+    let text = |key: &str| body.get(key).and_then(Value::as_str);
+    let editor = text("created_by_email");
+    // An editor records a transaction in the owner's wallet, the handler checks they may
+    // Other requests only ever act on the token's user, whoever else they name
+    let in_owners_wallet =
+        method == Method::POST && path == "/api/transactions" && editor.is_some();
+    if !in_owners_wallet {
+        named.emails.extend(text("user_email").map(str::to_string));
+    }
+    named.emails.extend(editor.map(str::to_string));
+    named.emails.extend(text("email").map(str::to_string));
+    if let Some(id) = body.get("user_id").filter(|id| !id.is_null()) {
+        match id.as_str() {
+            Some(id) => named.add_id(id),
+            None => named.invalid = true,
+        }
+    }
+    named
+}
+
+/// Whether axum's `Json` would read a body of this Content-Type: application/json or any
+/// application/<name>+json, in any case
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence
+        .strip_prefix("application/")
+        .is_some_and(|subtype| subtype == "json" || subtype.ends_with("+json"))
+}
+
+/// Largest body the route of a path accepts, statements and exports being imported are
+/// larger than other requests
+fn body_limit(path: &str) -> usize {
+    if path == "/api/transactions/import" || path.starts_with("/api/imports/") {
+        importers::MAX_BODY_BYTES
+    } else {
+        MAX_BODY_BYTES
+    }
+}
+
+fn unauthorized(message: &str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(json!({ "message": message })),
+    )
+        .into_response()
+}
+
+fn forbidden() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({ "message": "Requests may only name the user of the access token" })),
+    )
+        .into_response()
+}
+
+/// Require a valid access token on the protected routes, and that the request only names
+/// its user, who is added to the request's extensions as `AuthUser`
+pub async fn require_token(
+    State(issuer): State<Arc<TokenIssuer>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !is_protected(req.method(), req.uri().path()) {
+        return next.run(req).await;
+    }
+    let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return unauthorized("Missing access token");
+    };
+    let claims = match issuer.verify(token.trim(), Utc::now()) {
+        Ok(claims) => claims,
+        Err(e) => return unauthorized(&e.to_string()),
+    };
+    let user = AuthUser {
+        id: claims.sub,
+        email: claims.email,
+    };
+
+    let Ok(Query(query)) = Query::<Vec<(String, String)>>::try_from_uri(req.uri()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let json_body = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(is_json);
+    let (mut parts, body) = req.into_parts();
+    let (body, parsed) = if json_body {
+        let Ok(bytes) = to_bytes(body, body_limit(parts.uri.path())).await else {
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        };
+        // Malformed bodies are left to the handler to reject
+        let parsed: Option<Value> = serde_json::from_slice(&bytes).ok();
+        (Body::from(bytes), parsed)
+    } else {
+        (body, None)
+    };
+
+    let named = named_users(&parts.method, parts.uri.path(), &query, parsed.as_ref());
+    // Naming nobody would list every user's data
+    if (named.is_empty() && !acts_on_token_user(parts.uri.path())) || !named.only(&user) {
+        return forbidden();
+    }
+    parts.extensions.insert(user);
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// Characters of the tokens handed out, alphanumeric so about 256 random bits
+const TOKEN_LEN: usize = 43;
+
+fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// SHA-256 of a token handed out, hex, as stored in place of the token
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// A token to hand out, for password setup links or invitations, with the hash to store
+/// in its place
+pub fn new_token() -> (String, String) {
+    let token = random_token();
+    let token_hash = hash_token(&token);
+    (token, token_hash)
+}
+
+/// Login, refresh and logout endpoints, only served when a signing secret is configured
+pub struct Auth {
+    pub state: AppState,
+    pub issuer: Arc<TokenIssuer>,
+    /// Seconds a refresh token is valid
+    pub refresh_ttl_secs: u64,
+}
+
+impl Auth {
+    pub fn router<S>(self) -> Router<S> {
+        Router::new()
+            .route("/api/auth/login", post(login))
+            .route("/api/auth/refresh", post(refresh))
+            .route("/api/auth/logout", post(logout))
+            .with_state(Arc::new(self))
+    }
+
+    /// A new refresh token of the family, with the record storing its hash
+    fn new_refresh_token(
+        &self,
+        user_id: Uuid,
+        family_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> (String, RefreshTokenCreate) {
+        let token = random_token();
+        let ttl =
+            Duration::seconds(i64::try_from(self.refresh_ttl_secs).unwrap_or(i64::MAX / 1000));
+        let create = RefreshTokenCreate {
+            id: Uuid::new_v4(),
+            user_id,
+            family_id,
+            token_hash: hash_token(&token),
+            expires_at: now + ttl,
+        };
+        (token, create)
+    }
+
+    /// The answer of login and refresh: an access token and the refresh token to get the
+    /// next one with
+    fn tokens(
+        &self,
+        message: &str,
+        user_id: Uuid,
+        email: &str,
+        refresh_token: &str,
+        now: DateTime<Utc>,
+    ) -> Response {
+        let (token, claims) = self.issuer.issue(user_id, email, now);
+        Json(json!({
+            "message": message,
+            "access_token": token,
+            "token_type": "Bearer",
+            "expires_in": claims.exp - claims.iat,
+            "refresh_token": refresh_token,
+            "refresh_expires_in": self.refresh_ttl_secs
+        }))
+        .into_response()
+    }
+
+    /// A refresh token came back after it was used: revoke its whole family
+    async fn revoke_reused(&self, token: &RefreshTokenQuery) {
+        match refresh_token_queries::revoke_family(&self.state.db, token.family_id).await {
+            Ok(revoked) => {
+                handlers::record_audit(
+                    &self.state,
+                    AuditEntryCreate::new("revoke", "refresh_token", Some(token.family_id))
+                        .actor(token.user_id)
+                        .details(json!({ "reason": "reuse", "revoked": revoked })),
+                )
+                .await;
+            }
+            Err(e) => eprintln!(
+                "Error revoking refresh token family {}: {}",
+                token.family_id, e
+            ),
+        }
+    }
+}
+
+/// POST /api/auth/login - exchange an email and password for an access token
+async fn login(State(auth): State<Arc<Auth>>, ValidJson(req): ValidJson<LoginRequest>) -> Response {
+    // Unknown users and wrong passwords get the same answer
+    let user = match user_queries::get_user(&auth.state.db, &req.email).await {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("Error fetching user '{}': {}", redact::email(&req.email), e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Some(user) = user.filter(|user| verify_password(&req.password, &user.password)) else {
+        return unauthorized("Invalid email or password");
+    };
+
+    let now = Utc::now();
+    let (refresh_token, create) = auth.new_refresh_token(user.id, Uuid::new_v4(), now);
+    if let Err(e) = refresh_token_queries::create(&auth.state.db, &create).await {
+        eprintln!("Error recording refresh token: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    handlers::record_audit(
+        &auth.state,
+        AuditEntryCreate::new("login", "user", Some(user.id)).actor(user.id),
+    )
+    .await;
+
+    auth.tokens(
+        "Logged in successfully",
+        user.id,
+        &user.email,
+        &refresh_token,
+        now,
+    )
+}
+
+/// POST /api/auth/refresh - exchange a refresh token for a new access token and a new
+/// refresh token, the one sent is used up
+async fn refresh(
+    State(auth): State<Arc<Auth>>,
+    ValidJson(req): ValidJson<RefreshRequest>,
+) -> Response {
+    let token_hash = hash_token(req.refresh_token.trim());
+    let token = match refresh_token_queries::get_by_hash(&auth.state.db, &token_hash).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return unauthorized("Invalid refresh token"),
+        Err(e) => {
+            eprintln!("Error fetching refresh token: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let now = Utc::now();
+    if token.revoked_at.is_some() {
+        return unauthorized("Refresh token revoked");
+    }
+    if token.used_at.is_some() {
+        auth.revoke_reused(&token).await;
+        return unauthorized("Refresh token already used, log in again");
+    }
+    if token.expires_at <= now {
+        return unauthorized("Refresh token expired");
+    }
+    let user = match user_queries::get_user_by_id(&auth.state.db, token.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return unauthorized("Invalid refresh token"),
+        Err(e) => {
+            eprintln!("Error fetching user {}: {}", token.user_id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let (refresh_token, next) = auth.new_refresh_token(user.id, token.family_id, now);
+    match refresh_token_queries::rotate(&auth.state.db, token.id, &next).await {
+        Ok(true) => {}
+        // Used by a concurrent request, which is a reuse all the same
+        Ok(false) => {
+            auth.revoke_reused(&token).await;
+            return unauthorized("Refresh token already used, log in again");
+        }
+        Err(e) => {
+            eprintln!("Error rotating refresh token {}: {}", token.id, e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    auth.tokens(
+        "Tokens refreshed successfully",
+        user.id,
+        &user.email,
+        &refresh_token,
+        now,
+    )
+}
+
+/// POST /api/auth/logout - revoke a refresh token and every token rotated from the same
+/// login, unknown tokens are answered the same
+async fn logout(
+    State(auth): State<Arc<Auth>>,
+    ValidJson(req): ValidJson<RefreshRequest>,
+) -> Response {
+    let token_hash = hash_token(req.refresh_token.trim());
+    let token = match refresh_token_queries::get_by_hash(&auth.state.db, &token_hash).await {
+        Ok(token) => token,
+        Err(e) => {
+            eprintln!("Error fetching refresh token: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if let Some(token) = token {
+        if let Err(e) = refresh_token_queries::revoke_family(&auth.state.db, token.family_id).await
+        {
+            eprintln!(
+                "Error revoking refresh token family {}: {}",
+                token.family_id, e
+            );
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        handlers::record_audit(
+            &auth.state,
+            AuditEntryCreate::new("logout", "user", Some(token.user_id)).actor(token.user_id),
+        )
+        .await;
+    }
+
+    Json(json!({
+        "message": "Logged out successfully"
+    }))
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuer() -> TokenIssuer {
+        TokenIssuer::new("test-secret-of-at-least-32-bytes!", 900)
+    }
+
+    fn user() -> AuthUser {
+        AuthUser {
+            id: Uuid::new_v4(),
+            email: "ann@example.com".to_string(),
+        }
+    }
+
+    #[test]
+    fn verifies_issued_tokens() {
+        let now = Utc::now();
+        let id = Uuid::new_v4();
+        let (token, claims) = issuer().issue(id, "ann@example.com", now);
+        assert_eq!(claims.exp - claims.iat, 900);
+        assert_eq!(issuer().verify(&token, now), Ok(claims));
+    }
+
+    #[test]
+    fn rejects_expired_tokens() {
+        let now = Utc::now();
+        let (token, _) = issuer().issue(Uuid::new_v4(), "ann@example.com", now);
+        assert_eq!(
+            issuer().verify(&token, now + Duration::seconds(900)),
+            Err(TokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let now = Utc::now();
+        let (token, _) = issuer().issue(Uuid::new_v4(), "ann@example.com", now);
+        let other = TokenIssuer::new("another-secret-of-at-least-32-bytes", 900);
+        assert_eq!(other.verify(&token, now), Err(TokenError::BadSignature));
+
+        // Someone else's id in a payload signed for another one
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+        let (forged, _) = issuer().issue(Uuid::new_v4(), "eve@example.com", now);
+        let payload = forged.split('.').nth(1).unwrap();
+        let spliced = format!("{}.{}.{}", header, payload, signature);
+        assert_eq!(
+            issuer().verify(&spliced, now),
+            Err(TokenError::BadSignature)
+        );
+
+        assert_eq!(issuer().verify("garbage", now), Err(TokenError::Malformed));
+    }
+
+    #[test]
+    fn rejects_other_algorithms() {
+        let now = Utc::now();
+        let (token, _) = issuer().issue(Uuid::new_v4(), "ann@example.com", now);
+        let (_, rest) = token.split_once('.').unwrap();
+        let (payload, _) = rest.split_once('.').unwrap();
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#),
+            payload
+        );
+        assert_eq!(
+            issuer().verify(&unsigned, now),
+            Err(TokenError::UnsupportedAlgorithm)
+        );
+    }
+
+    #[test]
+    fn protects_every_api_route_but_the_public_ones() {
+        for path in [
+            "/api/transactions",
+            "/api/transactions/batch",
+            "/api/reports/timeseries",
+            "/api/sync/push",
+            "/api/sync/changes",
+            "/api/imports/ynab",
+            "/api/exports/ynab",
+            "/api/events",
+            "/api/budgets",
+            "/api/alerts",
+            "/api/attachments/1",
+            "/api/bank-accounts/1",
+            "/api/integrations/telegram/link",
+            "/api/billing/subscription",
+            "/api/unknown",
+        ] {
+            assert!(is_protected(&Method::GET, path), "{}", path);
+            assert!(is_protected(&Method::POST, path), "{}", path);
+        }
+        assert!(is_protected(&Method::PUT, "/api/users"));
+        assert!(is_protected(&Method::GET, "/api/users"));
+        assert!(is_protected(&Method::GET, "/api/users/ann@example.com"));
+        assert!(!is_protected(&Method::POST, "/api/users"));
+        assert!(!is_protected(&Method::GET, "/api/users/availability"));
+        assert!(!is_protected(&Method::POST, "/api/users/password-setup"));
+
+        for path in [
+            "/health",
+            "/metrics",
+            "/app/index.html",
+            "/ui/transactions",
+            "/api/meta",
+            "/api/meta/errors",
+            "/api/auth/login",
+            "/api/shared/token",
+            "/api/admin/jobs",
+            "/api/v1/transactions",
+            "/api/integrations/telegram/webhook",
+            "/api/integrations/slack",
+            "/api/billing/stripe/webhook",
+        ] {
+            assert!(!is_protected(&Method::POST, path), "{}", path);
+        }
+        assert!(is_protected(&Method::GET, "/api/adminx"));
+    }
+
+    #[test]
+    fn accepts_requests_naming_only_the_token_user() {
+        let user = user();
+        let query = vec![("user_id".to_string(), user.id.to_string())];
+        assert!(named_users(&Method::GET, "/api/transactions", &query, None).only(&user));
+
+        // Emails are looked up lowercased
+        let named = named_users(
+            &Method::GET,
+            "/api/users/ANN%40example.com/wallets",
+            &[],
+            None,
+        );
+        assert_eq!(named.emails, vec!["ANN@example.com".to_string()]);
+        assert!(named.only(&user));
+
+        let body =
+            json!({ "user_email": "owner@example.com", "created_by_email": "ann@example.com" });
+        assert!(named_users(&Method::POST, "/api/transactions", &[], Some(&body)).only(&user));
+
+        let named = named_users(&Method::GET, "/api/users/me/usage", &[], None);
+        assert!(named.is_empty());
+    }
+
+    #[test]
+    fn refuses_requests_naming_someone_else() {
+        let user = user();
+        let query = vec![("user_id".to_string(), Uuid::new_v4().to_string())];
+        assert!(!named_users(&Method::GET, "/api/transactions", &query, None).only(&user));
+
+        let query = vec![("user_id".to_string(), "not-an-id".to_string())];
+        assert!(!named_users(&Method::GET, "/api/transactions", &query, None).only(&user));
+
+        let body = json!({ "user_email": "bob@example.com" });
+        assert!(!named_users(&Method::POST, "/api/transactions", &[], Some(&body)).only(&user));
+
+        // Their own id does not make up for someone else's email
+        let query = vec![("user_id".to_string(), user.id.to_string())];
+        let body = json!({ "user_email": "bob@example.com" });
+        assert!(!named_users(&Method::POST, "/api/transactions", &query, Some(&body)).only(&user));
+
+        // Only a single transaction may be recorded in someone else's wallet, as their editor
+        let body =
+            json!({ "user_email": "owner@example.com", "created_by_email": "ann@example.com" });
+        for path in ["/api/transactions/batch", "/api/sync/push"] {
+            assert!(!named_users(&Method::POST, path, &[], Some(&body)).only(&user));
+        }
+        let body =
+            json!({ "user_email": "ann@example.com", "created_by_email": "bob@example.com" });
+        assert!(!named_users(&Method::POST, "/api/transactions", &[], Some(&body)).only(&user));
+
+        assert!(named_users(&Method::GET, "/api/users", &[], None).is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+// Paid plans through Stripe, for hosted deployments
+//
+// Users pick a plan with a Stripe price and are sent to a Stripe Checkout page. Stripe then
+// reports the subscription's lifecycle (created, renewed, past due, cancelled, ...) to the
+// webhook, which moves the user to the plan their subscription pays for, or back to the
+// default plan once it ends. Quotas follow the plan, see `quotas`. The invoices Stripe issues
+// for the payments are copied as they change, for users to list and download.
+
+use crate::handlers::{self, AppState};
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::billing_models::{
+    CheckoutRequest, InvoiceUpdate, SubscriptionParameters, SubscriptionUpdate,
+};
+use crate::models::money_models::{Currency, Money};
+use crate::queries::{billing_queries, user_queries};
+use crate::quotas;
+use crate::signatures::{self, WebhookVerifier};
+use crate::validation::{ValidJson, ValidationErrors};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const CHECKOUT_SESSIONS_URL: &str = "https://api.stripe.com/v1/checkout/sessions";
+
+/// Subscription statuses that keep the paid plan, past_due while Stripe retries the payment
+const PAYING_STATUSES: &[&str] = &["active", "trialing", "past_due"];
+
+/// Stripe Checkout and subscription webhooks, only served when Stripe is configured
+pub struct Billing {
+    pub state: AppState,
+    /// Secret API key (sk_...)
+    pub secret_key: String,
+    /// Checks webhooks against the endpoint's signing secret
+    pub verifier: Arc<WebhookVerifier>,
+    /// Stripe price of each paid plan, as (plan, price id)
+    pub prices: Vec<(String, String)>,
+    /// Where Stripe sends the user after paying, or after giving up
+    pub success_url: String,
+    pub cancel_url: String,
+    pub client: reqwest::Client,
+}
+
+impl Billing {
+    /// Parse "plan:price_id,plan:price_id", every plan must be one of the configured plans
+    ///
+    /// # Errors
+    /// Returns an error if an entry is malformed or names a plan that is not configured
+    pub fn parse_prices(raw: &str) -> anyhow::Result<Vec<(String, String)>> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (plan, price) = entry
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("Stripe prices must be plan:price_id"))?;
+                let plan = plan.trim();
+                if !quotas::plans().is_some_and(|plans| plans.contains_key(plan)) {
+                    return Err(anyhow::anyhow!(
+                        "Stripe price for {} which is not one of the PLANS",
+                        plan
+                    ));
+                }
+                Ok((plan.to_string(), price.trim().to_string()))
+            })
+            .collect()
+    }
+
+    /// Routes for starting checkouts, and the webhook behind the signature check
+    pub fn router<S>(self) -> Router<S> {
+        let verifier = self.verifier.clone();
+        Router::new()
+            .route("/api/billing/stripe/webhook", post(stripe_webhook))
+            .route_layer(middleware::from_fn_with_state(
+                verifier,
+                signatures::require_signature,
+            ))
+            .route("/api/billing/checkout", post(create_checkout))
+            .route("/api/billing/subscription", get(get_subscription))
+            .route("/api/billing/invoices", get(get_invoices))
+            .with_state(Arc::new(self))
+    }
+
+    fn price_of(&self, plan: &str) -> Option<&str> {
+        self.prices
+            .iter()
+            .find(|(p, _)| p == plan)
+            .map(|(_, price)| price.as_str())
+    }
+
+    fn plan_of(&self, price: &str) -> Option<&str> {
+        self.prices
+            .iter()
+            .find(|(_, p)| p == price)
+            .map(|(plan, _)| plan.as_str())
+    }
+}
+
+/// POST /api/billing/checkout - start a Stripe Checkout for a plan, returns the page to send the user to
+async fn create_checkout(
+    State(billing): State<Arc<Billing>>,
+    ValidJson(req): ValidJson<CheckoutRequest>,
+) -> Result<Json<Value>, Response> {
+    let Some(price) = billing.price_of(&req.plan) else {
+        let mut errors = ValidationErrors::default();
+        errors.add("plan", "must be a plan with a Stripe price");
+        return Err(errors.into_response());
+    };
+    let internal_error = |e: anyhow::Error| {
+        eprintln!("Error starting checkout: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    };
+    let user = handlers::require_user(&billing.state, &req.user_email)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let subscription = billing_queries::get_subscription(&billing.state.db, user.id)
+        .await
+        .map_err(internal_error)?;
+
+    let user_id = user.id.to_string();
+    let mut form = vec![
+        ("mode", "subscription"),
+        ("line_items[0][price]", price),
+        ("line_items[0][quantity]", "1"),
+        ("success_url", billing.success_url.as_str()),
+        ("cancel_url", billing.cancel_url.as_str()),
+        ("client_reference_id", user_id.as_str()),
+        ("metadata[plan]", req.plan.as_str()),
+        // Copied onto the subscription, so its events can be tied back to the user
+        ("subscription_data[metadata][user_id]", user_id.as_str()),
+        ("subscription_data[metadata][plan]", req.plan.as_str()),
+    ];
+    // Returning customers keep their Stripe customer (and payment methods)
+    match &subscription {
+        Some(subscription) => form.push(("customer", subscription.stripe_customer_id.as_str())),
+        None => form.push(("customer_email", user.email.as_str())),
+    }
+
+    let res = billing
+        .client
+        .post(CHECKOUT_SESSIONS_URL)
+        .bearer_auth(&billing.secret_key)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| {
+            eprintln!("Error reaching Stripe: {}", e);
+            StatusCode::BAD_GATEWAY.into_response()
+        })?;
+    let status = res.status();
+    let session: Value = res.json().await.map_err(|e| internal_error(e.into()))?;
+    if !status.is_success() {
+        eprintln!(
+            "Stripe refused the checkout session ({}): {}",
+            status, session["error"]["message"]
+        );
+        return Err(StatusCode::BAD_GATEWAY.into_response());
+    }
+
+    Ok(Json(json!({
+        "message": "Checkout session created successfully",
+        "id": session["id"],
+        "url": session["url"]
+    })))
+}
+
+/// GET /api/billing/subscription - the user's subscription as last reported by Stripe
+async fn get_subscription(
+    State(billing): State<Arc<Billing>>,
+    Query(params): Query<SubscriptionParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = handlers::require_user(&billing.state, &params.user_email).await?;
+    let internal_error = |e: anyhow::Error| {
+        eprintln!("Error fetching subscription: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let plan = quotas::user_plan(&billing.state.db, user.id)
+        .await
+        .map_err(internal_error)?;
+    let subscription = billing_queries::get_subscription(&billing.state.db, user.id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(json!({
+        "message": "Subscription retrieved successfully",
+        "plan": plan,
+        "subscription": subscription
+    })))
+}
+
+/// GET /api/billing/invoices - the user's invoices, newest first, with their PDF download links
+async fn get_invoices(
+    State(billing): State<Arc<Billing>>,
+    Query(params): Query<SubscriptionParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = handlers::require_user(&billing.state, &params.user_email).await?;
+    let invoices = billing_queries::get_invoices(&billing.state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching invoices: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Invoices retrieved successfully",
+        "invoices": invoices
+    })))
+}
+
+/// POST /api/billing/stripe/webhook - apply subscription lifecycle and invoice events
+/// Other event types are acknowledged and ignored, errors are answered with a 500 so
+/// Stripe delivers the event again later
+async fn stripe_webhook(
+    State(billing): State<Arc<Billing>>,
+    Json(event): Json<Value>,
+) -> Result<Json<Value>, StatusCode> {
+    let event_type = event["type"].as_str().unwrap_or_default();
+    let event_at = event["created"]
+        .as_i64()
+        .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0))
+        .unwrap_or_else(Utc::now);
+    let object = &event["data"]["object"];
+
+    let applied = match event_type {
+        "checkout.session.completed" => checkout_completed(&billing, object, event_at).await,
+        "customer.subscription.created"
+        | "customer.subscription.updated"
+        | "customer.subscription.deleted" => {
+            subscription_changed(&billing, event_type, object, event_at).await
+        }
+        "invoice.finalized"
+        | "invoice.updated"
+        | "invoice.paid"
+        | "invoice.payment_failed"
+        | "invoice.voided"
+        | "invoice.marked_uncollectible" => invoice_changed(&billing, object, event_at).await,
+        _ => Ok(false),
+    }
+    .map_err(|e| {
+        eprintln!(
+            "Error handling Stripe event {} ({}): {}",
+            event["id"], event_type, e
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(json!({ "received": true, "applied": applied })))
+}
+
+/// A checkout was paid for, the user gets the plan right away without waiting for the
+/// subscription events
+async fn checkout_completed(
+    billing: &Billing,
+    session: &Value,
+    event_at: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    if session["mode"] != "subscription" {
+        return Ok(false);
+    }
+    let user_id: Uuid = session["client_reference_id"]
+        .as_str()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Checkout session without a user: {}", e))?;
+    let customer = session["customer"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Checkout session without a customer"))?;
+    let plan = session["metadata"]["plan"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Checkout session without a plan"))?;
+
+    let update = SubscriptionUpdate {
+        stripe_customer_id: customer.to_string(),
+        stripe_subscription_id: session["subscription"].as_str().map(str::to_string),
+        plan: plan.to_string(),
+        status: "active".to_string(),
+        current_period_end: None,
+        event_at,
+    };
+    apply(billing, user_id, &update).await
+}
+
+/// A subscription was created, renewed, changed plan, fell behind on payments or ended
+async fn subscription_changed(
+    billing: &Billing,
+    event_type: &str,
+    subscription: &Value,
+    event_at: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    let customer = subscription["customer"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Subscription without a customer"))?;
+    let user_id = match subscription["metadata"]["user_id"]
+        .as_str()
+        .and_then(|id| id.parse().ok())
+    {
+        Some(user_id) => user_id,
+        None => match billing_queries::get_user_by_customer(&billing.state.db, customer).await? {
+            Some(user_id) => user_id,
+            // Not created through our checkout, nothing to do with it
+            None => return Ok(false),
+        },
+    };
+    // The price tells the plan even after a change made in Stripe's customer portal
+    let plan = subscription["items"]["data"][0]["price"]["id"]
+        .as_str()
+        .and_then(|price| billing.plan_of(price))
+        .or_else(|| subscription["metadata"]["plan"].as_str())
+        .ok_or_else(|| anyhow::anyhow!("Subscription for a price without a plan"))?;
+    let status = if event_type == "customer.subscription.deleted" {
+        "canceled"
+    } else {
+        subscription["status"].as_str().unwrap_or("incomplete")
+    };
+
+    let update = SubscriptionUpdate {
+        stripe_customer_id: customer.to_string(),
+        stripe_subscription_id: subscription["id"].as_str().map(str::to_string),
+        plan: plan.to_string(),
+        status: status.to_string(),
+        current_period_end: subscription["current_period_end"]
+            .as_i64()
+            .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+        event_at,
+    };
+    apply(billing, user_id, &update).await
+}
+
+/// An invoice was issued, paid, failed to be paid or voided
+async fn invoice_changed(
+    billing: &Billing,
+    invoice: &Value,
+    event_at: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    let customer = invoice["customer"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Invoice without a customer"))?;
+    let Some(user_id) = billing_queries::get_user_by_customer(&billing.state.db, customer).await?
+    else {
+        // Not a customer of ours, e.g. another product on the same Stripe account
+        return Ok(false);
+    };
+    let currency = invoice["currency"].as_str().unwrap_or_default();
+    let Ok(currency) = Currency::from_str(&currency.to_uppercase()) else {
+        eprintln!(
+            "Ignoring Stripe invoice in unsupported currency {}",
+            currency
+        );
+        return Ok(false);
+    };
+    let timestamp = |value: &Value| {
+        value
+            .as_i64()
+            .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0))
+    };
+    let text = |value: &Value| value.as_str().map(str::to_string);
+
+    let update = InvoiceUpdate {
+        stripe_invoice_id: text(&invoice["id"])
+            .ok_or_else(|| anyhow::anyhow!("Invoice without an id"))?,
+        stripe_subscription_id: text(&invoice["subscription"]),
+        number: text(&invoice["number"]),
+        status: text(&invoice["status"]).unwrap_or_else(|| "open".to_string()),
+        // Stripe amounts are in minor units already, zero-decimal currencies included
+        total: Money::new(invoice["total"].as_i64().unwrap_or_default(), currency),
+        amount_paid: Money::new(
+            invoice["amount_paid"].as_i64().unwrap_or_default(),
+            currency,
+        ),
+        hosted_invoice_url: text(&invoice["hosted_invoice_url"]),
+        invoice_pdf_url: text(&invoice["invoice_pdf"]),
+        period_start: timestamp(&invoice["period_start"]),
+        period_end: timestamp(&invoice["period_end"]),
+        paid_at: timestamp(&invoice["status_transitions"]["paid_at"]),
+        issued_at: timestamp(&invoice["created"]).unwrap_or(event_at),
+        event_at,
+    };
+    billing_queries::upsert_invoice(&billing.state.db, user_id, &update).await
+}
+
+/// Store the subscription and move the user to the plan it pays for, if any
+async fn apply(
+    billing: &Billing,
+    user_id: Uuid,
+    update: &SubscriptionUpdate,
+) -> anyhow::Result<bool> {
+    if !billing_queries::upsert_subscription(&billing.state.db, user_id, update).await? {
+        // An older event delivered late
+        return Ok(false);
+    }
+    let plan = if PAYING_STATUSES.contains(&update.status.as_str()) {
+        update.plan.as_str()
+    } else {
+        quotas::DEFAULT_PLAN
+    };
+    user_queries::set_plan(&billing.state.db, user_id, plan).await?;
+
+    handlers::record_audit(
+        &billing.state,
+        AuditEntryCreate::new("update", "user", Some(user_id))
+            .actor(user_id)
+            .details(json!({
+                "plan": plan,
+                "subscription_status": update.status,
+                "source": "stripe",
+            })),
+    )
+    .await;
+    Ok(true)
+}
@@ -0,0 +1,34 @@
+// Budget alerts
+//
+// Like round-ups, alerts are recorded by a scheduled job rather than when expenses are
+// created, so expenses from every source count the same way. Each run compares this month's
+// spending on every budget with the thresholds, an alert is recorded once per budget,
+// threshold and month. Clients fetch them from GET /api/alerts and acknowledge them.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::queries::budget_queries;
+use axum::async_trait;
+use serde_json::Value;
+
+/// Percentages of a budget that are alerted on when spending crosses them
+pub const ALERT_THRESHOLDS: &[i16] = &[80, 100];
+
+/// Job recording the alerts of budgets crossing a threshold, scheduled every five minutes
+/// by default
+pub struct RecordBudgetAlertsJob;
+
+#[async_trait]
+impl JobHandler for RecordBudgetAlertsJob {
+    fn kind(&self) -> &'static str {
+        "budgets.alerts"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let recorded = budget_queries::record_alerts(db, ALERT_THRESHOLDS).await?;
+        if recorded > 0 {
+            tracing::info!(recorded, "Budget alerts recorded");
+        }
+        Ok(())
+    }
+}
@@ -6,12 +6,142 @@ use std::env;
 pub struct Config {
     /// PostgreSQL database connection URL
     pub database_url: String,
-    /// Server port to listen on
+    /// Server port to listen on (unused on Lambda)
+    #[cfg_attr(feature = "lambda", allow(dead_code))]
     pub port: u16,
-    /// Server host address to bind to
+    /// Server host address to bind to (unused on Lambda)
+    #[cfg_attr(feature = "lambda", allow(dead_code))]
     pub host: String,
+    /// File written once the server takes requests, for orchestration (unused on Lambda)
+    #[cfg_attr(feature = "lambda", allow(dead_code))]
+    pub ready_file: Option<String>,
     /// Logging level (e.g., "debug", "info", "warn")
     pub rust_log: String,
+    /// Column encryption keys as "id:base64key,id:base64key" (encryption disabled when unset)
+    pub encryption_keys: Option<String>,
+    /// Id of the key used to encrypt new values; older keys are kept for decryption only
+    pub encryption_active_key: Option<String>,
+    /// Base64 key for the blind index used to look up encrypted emails
+    pub encryption_index_key: Option<String>,
+    /// How emails, amounts and descriptions appear in logs: "mask", "hash" or "off"
+    pub log_redaction: String,
+    /// Queries taking longer than this many milliseconds are logged
+    pub slow_query_threshold_ms: u64,
+    /// Field names of JSON responses, "snake" or "camel", unless a request asks otherwise
+    pub json_case: String,
+    /// Timestamps in JSON responses, "rfc3339" or "epoch-millis", unless a request asks otherwise
+    pub json_dates: String,
+    /// Capture redacted request/response bodies for debugging (off by default)
+    pub debug_capture_enabled: bool,
+    /// Share of requests captured when debug capture is enabled, between 0.0 and 1.0
+    pub debug_capture_sample_rate: f64,
+    /// Serve the dashboard from this directory instead of the copy embedded in the binary
+    pub dashboard_dir: Option<String>,
+    /// API origin injected into the dashboard, empty when the API is on the same origin
+    pub dashboard_api_base: String,
+    /// Key signing the HTML views' session cookies (random per process when unset)
+    pub session_secret: Option<String>,
+    /// IMAP server polled for receipt and bank notification emails (ingestion disabled when unset)
+    pub imap_host: Option<String>,
+    /// IMAP over TLS port
+    pub imap_port: u16,
+    pub imap_username: String,
+    pub imap_password: String,
+    /// Mailbox (folder) polled for new emails
+    pub imap_mailbox: String,
+    /// Seconds between two polls of the mailbox
+    pub imap_poll_interval_secs: u64,
+    /// Token of the Telegram bot used for quick expense entry (bot disabled when unset)
+    pub telegram_bot_token: Option<String>,
+    /// Secret Telegram sends with every webhook call, set with setWebhook's secret_token,
+    /// required with the bot token
+    pub telegram_webhook_secret: Option<String>,
+    /// Signing secret of the Slack app behind the /wallet slash command (Slack disabled when unset)
+    pub slack_signing_secret: Option<String>,
+    /// Wallet user of each Slack workspace as "team_id:user_id,team_id:user_id"
+    pub slack_teams: String,
+    /// Personal access tokens of the Firefly III compatible API as "token:user_id,token:user_id"
+    /// (API disabled when empty)
+    pub firefly_tokens: String,
+    /// Secret signing the JWT access tokens of /api/auth/login, at least 32 bytes (login and the
+    /// access token requirement on the API routes disabled when unset)
+    pub jwt_secret: Option<String>,
+    /// Seconds an access token is valid
+    pub jwt_ttl_secs: u64,
+    /// Seconds a refresh token is valid
+    pub jwt_refresh_ttl_secs: u64,
+    /// Bearer token of the operator endpoints under /api/admin (admin API disabled when unset)
+    pub admin_token: Option<String>,
+    /// Fewest distinct users an admin analytics figure may be computed from
+    pub analytics_min_group_size: i64,
+    /// Plans and their quotas as "plan:limit=value,...;plan:...", quotas disabled when empty
+    pub plans: String,
+    /// Stripe secret API key (billing disabled when unset)
+    pub stripe_secret_key: Option<String>,
+    /// Stripe price of each paid plan as "plan:price_id,plan:price_id"
+    pub stripe_prices: String,
+    /// Pages Stripe Checkout returns the user to after paying or cancelling
+    pub billing_success_url: String,
+    pub billing_cancel_url: String,
+    /// Background job workers run by this process
+    pub job_workers: usize,
+    /// Seconds a job may run before another worker may take it over
+    pub job_visibility_timeout_secs: u64,
+    /// Cron schedules of periodic jobs as "kind=cron;kind=cron", overriding the built-in ones
+    pub job_schedules: String,
+    /// Where attachments and exports are stored: "local", "s3", "gcs" or "azure"
+    pub blob_store: String,
+    /// Directory of the local blob store
+    pub blob_store_dir: String,
+    /// Bucket (S3, GCS) or container (Azure) of remote blob stores
+    pub blob_store_bucket: Option<String>,
+    pub blob_store_region: Option<String>,
+    /// Endpoint of S3 compatible services or emulators, the provider's own when unset
+    pub blob_store_endpoint: Option<String>,
+    /// Access key id and secret (S3, GCS HMAC keys), or account name and key (Azure)
+    pub blob_store_access_key: Option<String>,
+    pub blob_store_secret_key: Option<String>,
+    /// Malware scanner of uploaded attachments, "clamav://host:port" or
+    /// "icap://host:port/service" (scanning disabled when unset)
+    pub malware_scanner: Option<String>,
+    /// Command rendering attachment thumbnails, reading the image on stdin and writing a PNG
+    /// to stdout (thumbnails disabled when empty)
+    pub thumbnail_command: String,
+    /// Analytics store changed transactions are exported to, "clickhouse://..." or
+    /// "ndjson://prefix" (export disabled when unset)
+    pub analytics_sink: Option<String>,
+    /// Seconds between two analytics exports
+    pub analytics_export_interval_secs: u64,
+    /// Merchant-data API transactions are enriched from (enrichment disabled when unset)
+    pub merchant_api_url: Option<String>,
+    /// Bearer token sent to the merchant-data API
+    pub merchant_api_key: Option<String>,
+    /// Seconds a merchant lookup, or a lookup that found none, is cached
+    pub merchant_cache_ttl_secs: u64,
+    /// Frankfurter compatible exchange-rate API daily rates are fetched from (fetching
+    /// disabled when unset)
+    pub fx_rates_url: Option<String>,
+    /// Reference currency rates are fetched against
+    pub fx_rates_base: String,
+    /// Seconds between two fetches of exchange rates
+    pub fx_rates_interval_secs: u64,
+    /// Signing secrets of other inbound webhooks as "name:secret" or "name:scheme:secret"
+    pub webhook_secrets: String,
+    /// Category of imported transactions that come without one
+    pub import_default_category: String,
+    /// Whether anyone may sign up with POST /api/users, only invited users otherwise
+    pub public_signup: bool,
+    /// HTTP email API emails are sent through (email, and so invitations, disabled when unset)
+    pub mailer_url: Option<String>,
+    /// Bearer token sent to the email API
+    pub mailer_api_key: Option<String>,
+    /// Sender of the emails
+    pub mailer_from: String,
+    /// Page of the app where invited users choose their password, the token is added as
+    /// ?token=...
+    pub invitation_url: String,
+    /// Seconds an invitation link works
+    pub invitation_ttl_secs: u64,
 }
 
 impl Config {
@@ -35,13 +165,251 @@ impl Config {
             .map_err(|e| anyhow::anyhow!("Invalid PORT value: {}", e))?;
 
         let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let ready_file = env::var("READY_FILE").ok().filter(|v| !v.is_empty());
         let rust_log = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
 
+        // Optional application-level encryption of PII columns
+        let encryption_keys = env::var("ENCRYPTION_KEYS").ok();
+        let encryption_active_key = env::var("ENCRYPTION_ACTIVE_KEY").ok();
+        let encryption_index_key = env::var("ENCRYPTION_INDEX_KEY").ok();
+
+        // Personal data is masked in logs unless explicitly turned off
+        let log_redaction = env::var("LOG_REDACTION").unwrap_or_else(|_| "mask".to_string());
+
+        let slow_query_threshold_ms = env::var("SLOW_QUERY_THRESHOLD_MS")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid SLOW_QUERY_THRESHOLD_MS value: {}", e))?;
+
+        // Responses keep the field names and dates of the models unless configured otherwise
+        let json_case = env::var("JSON_CASE").unwrap_or_else(|_| "snake".to_string());
+        let json_dates = env::var("JSON_DATES").unwrap_or_else(|_| "rfc3339".to_string());
+
+        // Body capture is opt-in, with only header-tagged requests captured by default
+        let debug_capture_enabled = env::var("DEBUG_CAPTURE")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let debug_capture_sample_rate = env::var("DEBUG_CAPTURE_SAMPLE_RATE")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<f64>()
+            .map_err(|e| anyhow::anyhow!("Invalid DEBUG_CAPTURE_SAMPLE_RATE value: {}", e))?;
+        if !(0.0..=1.0).contains(&debug_capture_sample_rate) {
+            return Err(anyhow::anyhow!(
+                "DEBUG_CAPTURE_SAMPLE_RATE must be between 0 and 1"
+            ));
+        }
+
+        let dashboard_dir = env::var("DASHBOARD_DIR").ok();
+        let dashboard_api_base = env::var("DASHBOARD_API_BASE").unwrap_or_default();
+
+        let session_secret = env::var("SESSION_SECRET").ok();
+
+        // Email ingestion only runs when an IMAP server is configured
+        let imap_host = env::var("IMAP_HOST").ok();
+        let imap_port = env::var("IMAP_PORT")
+            .unwrap_or_else(|_| "993".to_string())
+            .parse::<u16>()
+            .map_err(|e| anyhow::anyhow!("Invalid IMAP_PORT value: {}", e))?;
+        let imap_username = env::var("IMAP_USERNAME").unwrap_or_default();
+        let imap_password = env::var("IMAP_PASSWORD").unwrap_or_default();
+        let imap_mailbox = env::var("IMAP_MAILBOX").unwrap_or_else(|_| "INBOX".to_string());
+        let imap_poll_interval_secs = env::var("IMAP_POLL_INTERVAL_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid IMAP_POLL_INTERVAL_SECS value: {}", e))?;
+
+        let telegram_bot_token = env::var("TELEGRAM_BOT_TOKEN").ok();
+        let telegram_webhook_secret = env::var("TELEGRAM_WEBHOOK_SECRET").ok();
+
+        let slack_signing_secret = env::var("SLACK_SIGNING_SECRET").ok();
+        let slack_teams = env::var("SLACK_TEAMS").unwrap_or_default();
+
+        let job_schedules = env::var("JOB_SCHEDULES").unwrap_or_default();
+
+        let webhook_secrets = env::var("WEBHOOK_SECRETS").unwrap_or_default();
+
+        let import_default_category =
+            env::var("IMPORT_DEFAULT_CATEGORY").unwrap_or_else(|_| "Uncategorized".to_string());
+
+        // Private instances turn this off and invite their users
+        let public_signup = env::var("PUBLIC_SIGNUP")
+            .map(|v| !(v == "0" || v.eq_ignore_ascii_case("false")))
+            .unwrap_or(true);
+
+        let mailer_url = env::var("MAILER_URL").ok().filter(|v| !v.is_empty());
+        let mailer_api_key = env::var("MAILER_API_KEY").ok().filter(|v| !v.is_empty());
+        let mailer_from =
+            env::var("MAILER_FROM").unwrap_or_else(|_| "Wallet <no-reply@localhost>".to_string());
+        let invitation_url = env::var("INVITATION_URL")
+            .unwrap_or_else(|_| "http://localhost:3000/setup-password".to_string());
+        let invitation_ttl_secs = env::var("INVITATION_TTL_SECS")
+            .unwrap_or_else(|_| "604800".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid INVITATION_TTL_SECS value: {}", e))?;
+
+        let blob_store = env::var("BLOB_STORE").unwrap_or_else(|_| "local".to_string());
+        let blob_store_dir =
+            env::var("BLOB_STORE_DIR").unwrap_or_else(|_| "data/blobs".to_string());
+        let blob_store_bucket = env::var("BLOB_STORE_BUCKET").ok().filter(|v| !v.is_empty());
+        let blob_store_region = env::var("BLOB_STORE_REGION").ok().filter(|v| !v.is_empty());
+        let blob_store_endpoint = env::var("BLOB_STORE_ENDPOINT")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let blob_store_access_key = env::var("BLOB_STORE_ACCESS_KEY")
+            .ok()
+            .filter(|v| !v.is_empty());
+        let blob_store_secret_key = env::var("BLOB_STORE_SECRET_KEY")
+            .ok()
+            .filter(|v| !v.is_empty());
+
+        let malware_scanner = env::var("MALWARE_SCANNER").ok().filter(|v| !v.is_empty());
+
+        let thumbnail_command = env::var("THUMBNAIL_COMMAND")
+            .unwrap_or_else(|_| "convert -[0] -auto-orient -thumbnail 320x320> png:-".to_string());
+
+        let analytics_sink = env::var("ANALYTICS_SINK").ok().filter(|v| !v.is_empty());
+        let analytics_export_interval_secs = env::var("ANALYTICS_EXPORT_INTERVAL_SECS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid ANALYTICS_EXPORT_INTERVAL_SECS value: {}", e))?;
+        if analytics_export_interval_secs == 0 {
+            return Err(anyhow::anyhow!(
+                "ANALYTICS_EXPORT_INTERVAL_SECS must be at least 1"
+            ));
+        }
+
+        let merchant_api_url = env::var("MERCHANT_API_URL").ok().filter(|v| !v.is_empty());
+        let merchant_api_key = env::var("MERCHANT_API_KEY").ok().filter(|v| !v.is_empty());
+        let merchant_cache_ttl_secs = env::var("MERCHANT_CACHE_TTL_SECS")
+            .unwrap_or_else(|_| "2592000".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid MERCHANT_CACHE_TTL_SECS value: {}", e))?;
+
+        let fx_rates_url = env::var("FX_RATES_URL").ok().filter(|v| !v.is_empty());
+        let fx_rates_base = env::var("FX_RATES_BASE").unwrap_or_else(|_| "EUR".to_string());
+        let fx_rates_interval_secs = env::var("FX_RATES_INTERVAL_SECS")
+            .unwrap_or_else(|_| "21600".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid FX_RATES_INTERVAL_SECS value: {}", e))?;
+        if fx_rates_interval_secs == 0 {
+            return Err(anyhow::anyhow!("FX_RATES_INTERVAL_SECS must be at least 1"));
+        }
+
+        let firefly_tokens = env::var("FIREFLY_TOKENS").unwrap_or_default();
+
+        let jwt_secret = env::var("JWT_SECRET").ok().filter(|s| !s.is_empty());
+        if jwt_secret.as_ref().is_some_and(|s| s.len() < 32) {
+            return Err(anyhow::anyhow!("JWT_SECRET must be at least 32 bytes"));
+        }
+        let jwt_ttl_secs = env::var("JWT_TTL_SECS")
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid JWT_TTL_SECS value: {}", e))?;
+        if jwt_ttl_secs == 0 {
+            return Err(anyhow::anyhow!("JWT_TTL_SECS must be at least 1"));
+        }
+        let jwt_refresh_ttl_secs = env::var("JWT_REFRESH_TTL_SECS")
+            .unwrap_or_else(|_| "2592000".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid JWT_REFRESH_TTL_SECS value: {}", e))?;
+        if jwt_refresh_ttl_secs == 0 {
+            return Err(anyhow::anyhow!("JWT_REFRESH_TTL_SECS must be at least 1"));
+        }
+
+        let admin_token = env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+        let analytics_min_group_size = env::var("ANALYTICS_MIN_GROUP_SIZE")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse::<i64>()
+            .map_err(|e| anyhow::anyhow!("Invalid ANALYTICS_MIN_GROUP_SIZE value: {}", e))?;
+        if analytics_min_group_size < 2 {
+            return Err(anyhow::anyhow!(
+                "ANALYTICS_MIN_GROUP_SIZE must be at least 2"
+            ));
+        }
+
+        let plans = env::var("PLANS").unwrap_or_default();
+
+        let stripe_secret_key = env::var("STRIPE_SECRET_KEY").ok().filter(|k| !k.is_empty());
+        let stripe_prices = env::var("STRIPE_PRICES").unwrap_or_default();
+        let billing_success_url = env::var("BILLING_SUCCESS_URL").unwrap_or_default();
+        let billing_cancel_url = env::var("BILLING_CANCEL_URL").unwrap_or_default();
+
+        let job_workers = env::var("JOB_WORKERS")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse::<usize>()
+            .map_err(|e| anyhow::anyhow!("Invalid JOB_WORKERS value: {}", e))?;
+        let job_visibility_timeout_secs = env::var("JOB_VISIBILITY_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .map_err(|e| anyhow::anyhow!("Invalid JOB_VISIBILITY_TIMEOUT_SECS value: {}", e))?;
+
         Ok(Config {
             database_url,
             port,
             host,
+            ready_file,
             rust_log,
+            encryption_keys,
+            encryption_active_key,
+            encryption_index_key,
+            log_redaction,
+            slow_query_threshold_ms,
+            json_case,
+            json_dates,
+            debug_capture_enabled,
+            debug_capture_sample_rate,
+            dashboard_dir,
+            dashboard_api_base,
+            session_secret,
+            imap_host,
+            imap_port,
+            imap_username,
+            imap_password,
+            imap_mailbox,
+            imap_poll_interval_secs,
+            telegram_bot_token,
+            telegram_webhook_secret,
+            slack_signing_secret,
+            slack_teams,
+            firefly_tokens,
+            jwt_secret,
+            jwt_ttl_secs,
+            jwt_refresh_ttl_secs,
+            admin_token,
+            analytics_min_group_size,
+            plans,
+            stripe_secret_key,
+            stripe_prices,
+            billing_success_url,
+            billing_cancel_url,
+            job_workers,
+            job_visibility_timeout_secs,
+            job_schedules,
+            blob_store,
+            blob_store_dir,
+            blob_store_bucket,
+            blob_store_region,
+            blob_store_endpoint,
+            blob_store_access_key,
+            blob_store_secret_key,
+            malware_scanner,
+            thumbnail_command,
+            analytics_sink,
+            analytics_export_interval_secs,
+            merchant_api_url,
+            merchant_api_key,
+            merchant_cache_ttl_secs,
+            fx_rates_url,
+            fx_rates_base,
+            fx_rates_interval_secs,
+            webhook_secrets,
+            import_default_category,
+            public_signup,
+            mailer_url,
+            mailer_api_key,
+            mailer_from,
+            invitation_url,
+            invitation_ttl_secs,
         })
     }
 }
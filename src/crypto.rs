@@ -0,0 +1,299 @@
+use crate::config::Config;
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::queries::encryption_queries;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::anyhow;
+use axum::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Prefix marking a column value as ciphertext
+/// Values without it are legacy plaintext and are returned as-is
+const CIPHERTEXT_PREFIX: &str = "enc:v1:";
+
+/// AES-GCM nonce length in bytes
+const NONCE_LEN: usize = 12;
+
+/// Process-wide cipher, installed once at startup when encryption is configured
+static CIPHER: OnceLock<FieldCipher> = OnceLock::new();
+
+/// Encrypts sensitive column values (emails, descriptions) before they reach Postgres
+///
+/// Keys are identified by a short id that is stored alongside each ciphertext,
+/// so old keys keep decrypting existing rows after a new active key is rolled out.
+/// Exact-match lookups on encrypted columns use a keyed HMAC "blind index" instead.
+pub struct FieldCipher {
+    keys: HashMap<String, Aes256Gcm>,
+    active_key_id: String,
+    index_key: Vec<u8>,
+}
+
+impl FieldCipher {
+    /// Build the cipher from configuration
+    /// Returns None when no encryption keys are configured (encryption disabled)
+    ///
+    /// # Errors
+    /// Returns an error if a key is malformed or the active/index keys are missing
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(raw_keys) = &config.encryption_keys else {
+            return Ok(None);
+        };
+
+        // Keys are given as "id:base64key,id:base64key"
+        let mut keys = HashMap::new();
+        for entry in raw_keys.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, encoded) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid ENCRYPTION_KEYS entry, expected id:base64key"))?;
+            let bytes = BASE64
+                .decode(encoded)
+                .map_err(|e| anyhow!("Invalid base64 for encryption key '{}': {}", id, e))?;
+            if bytes.len() != 32 {
+                return Err(anyhow!(
+                    "Encryption key '{}' must be 32 bytes, got {}",
+                    id,
+                    bytes.len()
+                ));
+            }
+            let key = Key::<Aes256Gcm>::from_slice(&bytes);
+            keys.insert(id.to_string(), Aes256Gcm::new(key));
+        }
+
+        let active_key_id = config.encryption_active_key.clone().ok_or_else(|| {
+            anyhow!("ENCRYPTION_ACTIVE_KEY is required when ENCRYPTION_KEYS is set")
+        })?;
+        if !keys.contains_key(&active_key_id) {
+            return Err(anyhow!(
+                "ENCRYPTION_ACTIVE_KEY '{}' is not listed in ENCRYPTION_KEYS",
+                active_key_id
+            ));
+        }
+
+        let index_key = config
+            .encryption_index_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("ENCRYPTION_INDEX_KEY is required when ENCRYPTION_KEYS is set"))
+            .and_then(|k| {
+                BASE64
+                    .decode(k)
+                    .map_err(|e| anyhow!("Invalid base64 for ENCRYPTION_INDEX_KEY: {}", e))
+            })?;
+
+        Ok(Some(Self {
+            keys,
+            active_key_id,
+            index_key,
+        }))
+    }
+
+    /// Encrypt a value with the active key
+    pub fn encrypt(&self, plaintext: &str) -> anyhow::Result<String> {
+        let cipher = &self.keys[&self.active_key_id];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|e| anyhow!("encryption failed: {e}"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(format!(
+            "{}{}:{}",
+            CIPHERTEXT_PREFIX,
+            self.active_key_id,
+            BASE64.encode(payload)
+        ))
+    }
+
+    /// Decrypt a value produced by `encrypt`, with whichever key it was written with
+    pub fn decrypt(&self, value: &str) -> anyhow::Result<String> {
+        let Some(rest) = value.strip_prefix(CIPHERTEXT_PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let (key_id, encoded) = rest
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed ciphertext"))?;
+        let cipher = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| anyhow!("Unknown encryption key id '{}'", key_id))?;
+        let payload = BASE64.decode(encoded)?;
+        if payload.len() < NONCE_LEN {
+            return Err(anyhow!("Malformed ciphertext"));
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| anyhow!("decryption failed: {e}"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    /// Whether a stored value should be rewritten with the active key
+    /// True for legacy plaintext and for ciphertext under a retired key
+    pub fn needs_reencryption(&self, value: &str) -> bool {
+        match value.strip_prefix(CIPHERTEXT_PREFIX) {
+            Some(rest) => rest.split(':').next() != Some(self.active_key_id.as_str()),
+            None => true,
+        }
+    }
+
+    /// Deterministic keyed hash used to look up and enforce uniqueness on encrypted values
+    pub fn blind_index(&self, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Install the process-wide cipher
+/// Must be called once at startup before any query runs
+pub fn init(cipher: FieldCipher) {
+    if CIPHER.set(cipher).is_err() {
+        tracing::warn!("Field cipher already initialized, ignoring");
+    }
+}
+
+/// The configured cipher, if encryption is enabled
+pub fn cipher() -> Option<&'static FieldCipher> {
+    CIPHER.get()
+}
+
+/// Encrypt a column value if encryption is enabled, otherwise pass it through
+pub fn encrypt_field(value: &str) -> anyhow::Result<String> {
+    match cipher() {
+        Some(cipher) => cipher.encrypt(value),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Decrypt a column value if it is ciphertext, otherwise pass it through
+pub fn decrypt_field(value: String) -> anyhow::Result<String> {
+    match cipher() {
+        Some(cipher) => cipher.decrypt(&value),
+        None if value.starts_with(CIPHERTEXT_PREFIX) => Err(anyhow!(
+            "Found encrypted value but no encryption keys are configured"
+        )),
+        None => Ok(value),
+    }
+}
+
+/// Blind index for a value, or None when encryption is disabled
+pub fn blind_index(value: &str) -> Option<String> {
+    cipher().map(|cipher| cipher.blind_index(value))
+}
+
+/// Job rewriting columns that are plaintext or under a retired key, queued at startup
+pub struct ReencryptColumnsJob;
+
+#[async_trait]
+impl JobHandler for ReencryptColumnsJob {
+    fn kind(&self) -> &'static str {
+        "crypto.reencrypt"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let Some(cipher) = cipher() else {
+            return Ok(());
+        };
+        let updated = encryption_queries::reencrypt_all(db, cipher).await?;
+        if updated > 0 {
+            tracing::info!(updated, "Rows re-encrypted with the active key");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cipher with the given key ids, each key filled with its own byte
+    fn test_cipher(keys: &[(&str, u8)], active_key_id: &str) -> FieldCipher {
+        FieldCipher {
+            keys: keys
+                .iter()
+                .map(|(id, byte)| {
+                    let key = [*byte; 32];
+                    (
+                        id.to_string(),
+                        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+                    )
+                })
+                .collect(),
+            active_key_id: active_key_id.to_string(),
+            index_key: b"index-key".to_vec(),
+        }
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypts() {
+        let cipher = test_cipher(&[("k1", 1)], "k1");
+        let encrypted = cipher.encrypt("ann@example.com").unwrap();
+        assert!(encrypted.starts_with("enc:v1:k1:"));
+        assert!(!encrypted.contains("ann"));
+        // A random nonce each time
+        assert_ne!(encrypted, cipher.encrypt("ann@example.com").unwrap());
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "ann@example.com");
+        // Legacy plaintext is returned as it is
+        assert_eq!(cipher.decrypt("Groceries").unwrap(), "Groceries");
+    }
+
+    #[test]
+    fn decrypts_with_retired_keys_only_when_listed() {
+        let old = test_cipher(&[("k1", 1)], "k1");
+        let encrypted = old.encrypt("Rent").unwrap();
+        let rotated = test_cipher(&[("k1", 1), ("k2", 2)], "k2");
+        assert_eq!(rotated.decrypt(&encrypted).unwrap(), "Rent");
+        assert!(rotated.needs_reencryption(&encrypted));
+        assert!(!rotated.needs_reencryption(&rotated.encrypt("Rent").unwrap()));
+
+        let without_old = test_cipher(&[("k2", 2)], "k2");
+        assert!(without_old.decrypt(&encrypted).is_err());
+        // The same key id with other key material
+        let wrong_key = test_cipher(&[("k1", 9)], "k1");
+        assert!(wrong_key.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let cipher = test_cipher(&[("k1", 1)], "k1");
+        let encrypted = cipher.encrypt("Salary").unwrap();
+        let (prefix, encoded) = encrypted.rsplit_once(':').unwrap();
+        let mut payload = BASE64.decode(encoded).unwrap();
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+        let tampered = format!("{}:{}", prefix, BASE64.encode(&payload));
+        assert!(cipher.decrypt(&tampered).is_err());
+        let truncated = format!("{}:{}", prefix, BASE64.encode(&payload[..NONCE_LEN - 1]));
+        assert!(cipher.decrypt(&truncated).is_err());
+        assert!(cipher.decrypt("enc:v1:k1").is_err());
+    }
+
+    #[test]
+    fn blind_indexes_are_deterministic_and_keyed() {
+        let cipher = test_cipher(&[("k1", 1)], "k1");
+        assert_eq!(
+            cipher.blind_index("ann@example.com"),
+            cipher.blind_index("ann@example.com")
+        );
+        assert_ne!(
+            cipher.blind_index("ann@example.com"),
+            cipher.blind_index("bob@example.com")
+        );
+        let other = FieldCipher {
+            index_key: b"another-index-key".to_vec(),
+            ..test_cipher(&[("k1", 1)], "k1")
+        };
+        assert_ne!(
+            cipher.blind_index("ann@example.com"),
+            other.blind_index("ann@example.com")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use axum::{
+    Router,
+    body::Body,
+    extract::State,
+    http::{StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use rust_embed::RustEmbed;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower_http::services::ServeDir;
+
+/// The SvelteKit build of `frontend/`, embedded in the binary
+/// In debug builds files are read from disk, so a rebuilt frontend shows up without recompiling
+#[derive(RustEmbed)]
+#[folder = "frontend/build"]
+#[allow_missing = true]
+struct Assets;
+
+/// SPA entry point, also served for every client-side route
+const INDEX_HTML: &str = "index.html";
+
+/// Where the dashboard files come from and how the API is reached
+pub struct Dashboard {
+    /// Serve files from this directory instead of the embedded build
+    pub dir: Option<PathBuf>,
+    /// Origin (and optional path prefix) of the API as seen by the browser, empty for same-origin
+    pub api_base: String,
+}
+
+impl Dashboard {
+    /// Router serving the dashboard, to be nested under `/app`
+    pub fn router(self) -> Router {
+        let dir = self.dir.clone();
+        let dashboard = Arc::new(self);
+        match dir {
+            Some(dir) => {
+                // Unknown paths and the root fall back to the injected index page
+                let index = get(serve_index).with_state(dashboard);
+                Router::new().fallback_service(
+                    ServeDir::new(dir)
+                        .append_index_html_on_directories(false)
+                        .fallback(index),
+                )
+            }
+            None => Router::new().fallback(serve_embedded).with_state(dashboard),
+        }
+    }
+
+    /// The raw index page, from the override directory or the embedded build
+    fn raw_index(&self) -> Option<String> {
+        match &self.dir {
+            Some(dir) => std::fs::read_to_string(dir.join(INDEX_HTML)).ok(),
+            None => Assets::get(INDEX_HTML).map(|f| String::from_utf8_lossy(&f.data).into_owned()),
+        }
+    }
+
+    /// Index page with the API base injected for the frontend's API client
+    fn index_page(&self) -> Response {
+        let Some(html) = self.raw_index() else {
+            return (
+                StatusCode::NOT_FOUND,
+                "Dashboard not built, run `npm run build` in frontend/ and rebuild the server",
+            )
+                .into_response();
+        };
+
+        // JSON-encode the value and keep "</script>" from closing the tag early
+        let api_base = serde_json::to_string(&self.api_base)
+            .unwrap_or_else(|_| "\"\"".to_string())
+            .replace("</", "<\\/");
+        let script = format!(
+            "<script>window.__WALLET_API_BASE__ = {};</script>",
+            api_base
+        );
+        let html = match html.find("</head>") {
+            Some(pos) => format!("{}{}{}", &html[..pos], script, &html[pos..]),
+            None => format!("{}{}", script, html),
+        };
+
+        (
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                // Always revalidate so a new deployment is picked up immediately
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            html,
+        )
+            .into_response()
+    }
+}
+
+async fn serve_index(State(dashboard): State<Arc<Dashboard>>) -> Response {
+    dashboard.index_page()
+}
+
+async fn serve_embedded(State(dashboard): State<Arc<Dashboard>>, uri: Uri) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    if path.is_empty() || path == INDEX_HTML {
+        return dashboard.index_page();
+    }
+
+    match Assets::get(path) {
+        Some(file) => {
+            // SvelteKit fingerprints everything under _app/immutable
+            let cache_control = if path.starts_with("_app/immutable/") {
+                "public, max-age=31536000, immutable"
+            } else {
+                "no-cache"
+            };
+            (
+                [
+                    (header::CONTENT_TYPE, file.metadata.mimetype().to_string()),
+                    (header::CACHE_CONTROL, cache_control.to_string()),
+                ],
+                Body::from(file.data.into_owned()),
+            )
+                .into_response()
+        }
+        // Client-side routes have no file, let the SPA router handle them
+        None if !path.rsplit('/').next().unwrap_or("").contains('.') => dashboard.index_page(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -1,16 +1,31 @@
-use sqlx::{PgPool, Pool, Postgres};
+use chrono::{DateTime, Utc};
+use log::LevelFilter;
+use serde::Serialize;
+#[cfg(not(feature = "lambda"))]
+use sqlx::PgPool;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{ConnectOptions, Pool, Postgres, Row};
+#[cfg(not(feature = "lambda"))]
 use std::fs;
+#[cfg(not(feature = "lambda"))]
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 pub type DbPool = Pool<Postgres>;
 
-pub async fn create_pool(database_url: &str) -> anyhow::Result<DbPool> {
+#[cfg(not(feature = "lambda"))]
+pub async fn create_pool(
+    database_url: &str,
+    slow_query_threshold: Duration,
+) -> anyhow::Result<DbPool> {
+    // Parse the database URL into connection options
+    // Every statement slower than the threshold is logged by sqlx (SQL only, no bind values)
+    let options = database_url
+        .parse::<PgConnectOptions>()?
+        .log_slow_statements(LevelFilter::Warn, slow_query_threshold);
+
     // Create a connection pool with configuration
-    let pool = PgPool::connect_with(
-        // Parse the database URL into connection options
-        database_url.parse()?,
-    )
-    .await?;
+    let pool = PgPool::connect_with(options).await?;
 
     // Verify the connection by running a simple query
     // This ensures the database is accessible before proceeding
@@ -19,11 +34,151 @@ pub async fn create_pool(database_url: &str) -> anyhow::Result<DbPool> {
     Ok(pool)
 }
 
-pub async fn health_check(pool: &DbPool) -> anyhow::Result<()> {
+/// Create a pool that only connects when the first query runs
+/// Used on AWS Lambda, where cold starts should not wait on Postgres and
+/// a function instance only ever serves one request at a time
+#[cfg(feature = "lambda")]
+pub fn create_lazy_pool(
+    database_url: &str,
+    slow_query_threshold: Duration,
+) -> anyhow::Result<DbPool> {
+    let options = database_url
+        .parse::<PgConnectOptions>()?
+        .log_slow_statements(LevelFilter::Warn, slow_query_threshold);
+
+    Ok(sqlx::postgres::PgPoolOptions::new()
+        .max_connections(2)
+        .connect_lazy_with(options))
+}
+
+/// Connection pool usage at the time of the health check
+#[derive(Debug, Serialize)]
+pub struct PoolStats {
+    pub size: u32,
+    pub in_use: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+/// Most recent successfully applied migration
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: DateTime<Utc>,
+}
+
+/// Lag of one standby, as seen from the primary
+#[derive(Debug, Serialize)]
+pub struct ReplicaLag {
+    pub name: String,
+    pub state: String,
+    pub replay_lag_seconds: Option<f64>,
+}
+
+/// Replication state of the database we are connected to
+#[derive(Debug, Serialize)]
+pub struct ReplicationStatus {
+    /// "primary" or "replica"
+    pub role: String,
+    /// Primary: lag of each attached standby
+    pub replicas: Vec<ReplicaLag>,
+    /// Replica: seconds since the last replayed transaction
+    pub lag_seconds: Option<f64>,
+}
+
+/// Structured database diagnostics returned by /health/db
+#[derive(Debug, Serialize)]
+pub struct DbHealth {
+    pub round_trip_ms: f64,
+    pub pool: PoolStats,
+    pub last_migration: Option<MigrationStatus>,
+    pub replication: ReplicationStatus,
+}
+
+pub async fn health_check(pool: &DbPool) -> anyhow::Result<DbHealth> {
+    // Round-trip latency of the simplest possible query
+    let started = Instant::now();
     sqlx::query("SELECT 1").execute(pool).await?;
-    Ok(())
+    let round_trip_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+    let pool_stats = PoolStats {
+        size,
+        in_use: size.saturating_sub(idle),
+        idle,
+        max: pool.options().get_max_connections(),
+    };
+
+    let last_migration = sqlx::query(
+        "SELECT version, description, installed_on FROM _sqlx_migrations
+         WHERE success ORDER BY version DESC LIMIT 1",
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| -> anyhow::Result<MigrationStatus> {
+        Ok(MigrationStatus {
+            version: row.try_get("version")?,
+            description: row.try_get("description")?,
+            installed_on: row.try_get("installed_on")?,
+        })
+    })
+    .transpose()?;
+
+    Ok(DbHealth {
+        round_trip_ms,
+        pool: pool_stats,
+        last_migration,
+        replication: replication_status(pool).await?,
+    })
+}
+
+async fn replication_status(pool: &DbPool) -> anyhow::Result<ReplicationStatus> {
+    let (in_recovery,): (bool,) = sqlx::query_as("SELECT pg_is_in_recovery()")
+        .fetch_one(pool)
+        .await?;
+
+    if in_recovery {
+        // Connected to a standby: report how far behind the primary we are
+        let (lag_seconds,): (Option<f64>,) = sqlx::query_as(
+            "SELECT EXTRACT(EPOCH FROM (NOW() - pg_last_xact_replay_timestamp()))::float8",
+        )
+        .fetch_one(pool)
+        .await?;
+        return Ok(ReplicationStatus {
+            role: "replica".to_string(),
+            replicas: Vec::new(),
+            lag_seconds,
+        });
+    }
+
+    // Connected to a primary: list attached standbys, if any
+    let replicas = sqlx::query(
+        "SELECT COALESCE(application_name, client_addr::text, 'unknown') AS name, state,
+                EXTRACT(EPOCH FROM replay_lag)::float8 AS replay_lag_seconds
+         FROM pg_stat_replication",
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| -> anyhow::Result<ReplicaLag> {
+        Ok(ReplicaLag {
+            name: row.try_get("name")?,
+            state: row.try_get("state")?,
+            replay_lag_seconds: row.try_get("replay_lag_seconds")?,
+        })
+    })
+    .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(ReplicationStatus {
+        role: "primary".to_string(),
+        replicas,
+        lag_seconds: None,
+    })
 }
 
+#[cfg(not(feature = "lambda"))]
 pub async fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
     // Create migrations tracking table if it doesn't exist
     // This table keeps track of which migrations have been applied
@@ -43,7 +198,7 @@ pub async fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
     // Read migrations directory
     let migrations_dir = Path::new("migrations");
     if !migrations_dir.exists() {
-        println!("⚠️  No migrations directory found, skipping migrations");
+        tracing::warn!("No migrations directory found, skipping migrations");
         return Ok(());
     }
 
@@ -62,7 +217,7 @@ pub async fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
         .collect();
     migration_files.sort();
 
-    println!("📦 Found {} migration file(s)", migration_files.len());
+    tracing::info!(files = migration_files.len(), "Running database migrations");
 
     // Apply each migration
     for migration_file in migration_files {
@@ -88,13 +243,13 @@ pub async fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
                 .await?;
 
         if let Some((true,)) = already_applied {
-            println!("⏭️  Skipping already applied migration: {}", filename);
+            tracing::debug!(migration = %filename, "Skipping already applied migration");
             continue;
         }
 
         // Read migration SQL
         let sql = fs::read_to_string(&migration_file)?;
-        println!("🔄 Applying migration: {}", filename);
+        tracing::info!(migration = %filename, "Applying migration");
 
         // Execute migration within a transaction
         // If migration fails, transaction is rolled back
@@ -104,10 +259,10 @@ pub async fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
         // PostgreSQL requires each statement to be executed separately
         // We split by semicolon and filter out empty/whitespace-only statements
         // Note: This simple approach works for DDL statements (CREATE, ALTER, etc.)
-        // which typically don't have semicolons inside string literals
-        let statements: Vec<String> = sql
-            .split(';')
-            .map(|s| s.trim().to_string())
+        // which typically don't have semicolons inside string literals. Function bodies
+        // quoted with dollar signs ($$ ... $$) are kept whole
+        let statements: Vec<String> = split_statements(&sql)
+            .into_iter()
             .filter(|s| {
                 // Filter out empty strings and pure comment blocks
                 let trimmed = s.trim();
@@ -166,9 +321,46 @@ pub async fn run_migrations(pool: &DbPool) -> anyhow::Result<()> {
         .await?;
 
         tx.commit().await?;
-        println!("✅ Successfully applied migration: {}", filename);
+        tracing::info!(migration = %filename, "Applied migration");
     }
 
-    println!("✅ All migrations applied successfully");
+    tracing::info!("All migrations applied");
     Ok(())
 }
+
+/// Split a migration on the semicolons ending its statements, those inside dollar-quoted
+/// strings such as "$$ ... $$" or "$body$ ... $body$" (PL/pgSQL bodies) excepted
+#[cfg(not(feature = "lambda"))]
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    // The tag of the dollar-quoted string we are in, e.g. "$$"
+    let mut quote: Option<String> = None;
+    let mut rest = sql;
+    while let Some(c) = rest.chars().next() {
+        if c == '$'
+            && let Some(end) = rest[1..].find('$')
+            && rest[1..=end]
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_')
+        {
+            let tag = &rest[..end + 2];
+            match &quote {
+                Some(open) if open == tag => quote = None,
+                Some(_) => {}
+                None => quote = Some(tag.to_string()),
+            }
+            current.push_str(tag);
+            rest = &rest[tag.len()..];
+            continue;
+        }
+        if c == ';' && quote.is_none() {
+            statements.push(std::mem::take(&mut current).trim().to_string());
+        } else {
+            current.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    statements.push(current.trim().to_string());
+    statements
+}
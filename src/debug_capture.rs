@@ -0,0 +1,233 @@
+use crate::database::DbPool;
+use crate::models::bank_account_models::MaskedIban;
+use crate::models::debug_capture_models::DebugCaptureCreate;
+use crate::queries::debug_capture_queries;
+use crate::redact;
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::time::Instant;
+
+/// Requests carrying this header (with "1" or "true") are always captured
+pub const DEBUG_HEADER: &str = "x-debug-capture";
+
+/// Largest request body buffered for capture, same as axum's default extractor limit
+const MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Stored bodies are cut off after this many bytes
+const MAX_STORED_BODY_BYTES: usize = 64 * 1024;
+
+/// Settings for the debug capture layer
+#[derive(Clone)]
+pub struct DebugCapture {
+    pub db: DbPool,
+    /// Capture is opt-in, when disabled the layer passes requests straight through
+    pub enabled: bool,
+    /// Share of requests captured at random, between 0.0 and 1.0
+    pub sample_rate: f64,
+}
+
+/// Kinds of JSON fields that are redacted before a body is stored
+enum SensitiveField {
+    Secret,
+    Email,
+    Amount,
+    Text,
+    Iban,
+}
+
+/// Decide from a JSON key or query parameter name whether its value is sensitive
+fn classify(key: &str) -> Option<SensitiveField> {
+    let key = key.to_ascii_lowercase();
+    if ["password", "token", "secret", "authorization"]
+        .iter()
+        .any(|k| key.contains(k))
+    {
+        Some(SensitiveField::Secret)
+    } else if key.contains("email") {
+        Some(SensitiveField::Email)
+    } else if key.contains("amount") || key.contains("balance") {
+        Some(SensitiveField::Amount)
+    } else if key.contains("iban") || key.contains("account_number") {
+        Some(SensitiveField::Iban)
+    } else if ["description", "name", "note", "memo"]
+        .iter()
+        .any(|k| key.contains(k))
+    {
+        Some(SensitiveField::Text)
+    } else {
+        None
+    }
+}
+
+fn redact_scalar(kind: &SensitiveField, value: &str) -> String {
+    match kind {
+        // Credentials are never stored, whatever the log redaction policy
+        SensitiveField::Secret => "[REDACTED]".to_string(),
+        SensitiveField::Email => redact::email(value).to_string(),
+        SensitiveField::Amount => redact::amount(value).to_string(),
+        SensitiveField::Text => redact::text(value).to_string(),
+        SensitiveField::Iban => MaskedIban::new(value.to_string()).masked(),
+    }
+}
+
+/// Redact sensitive fields anywhere in a JSON document, keeping its shape
+fn redact_json(key: Option<&str>, value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let v = redact_json(Some(&k), v);
+                    (k, v)
+                })
+                .collect(),
+        ),
+        // Array elements inherit the key, e.g. a list of emails
+        Value::Array(items) => {
+            Value::Array(items.into_iter().map(|v| redact_json(key, v)).collect())
+        }
+        Value::Null => Value::Null,
+        scalar => match key.and_then(classify) {
+            Some(kind) => {
+                let raw = match &scalar {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                Value::String(redact_scalar(&kind, &raw))
+            }
+            None => scalar,
+        },
+    }
+}
+
+/// Redact the values of sensitive query parameters
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => match classify(key) {
+                Some(kind) => format!("{}={}", key, redact_scalar(&kind, value)),
+                None => pair.to_string(),
+            },
+            None => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Redacted form of a body for storage
+/// Only JSON can be redacted field by field, other bodies are reduced to their size
+fn redact_body(bytes: &Bytes) -> Option<String> {
+    if bytes.is_empty() {
+        return None;
+    }
+    let mut body = match serde_json::from_slice::<Value>(bytes) {
+        Ok(json) => redact_json(None, json).to_string(),
+        Err(_) => format!("<{} bytes, not JSON>", bytes.len()),
+    };
+    if body.len() > MAX_STORED_BODY_BYTES {
+        let mut cut = MAX_STORED_BODY_BYTES;
+        while !body.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        body.truncate(cut);
+        body.push_str("...(truncated)");
+    }
+    Some(body)
+}
+
+/// Why the current request should be captured, if at all
+fn capture_reason(capture: &DebugCapture, req: &Request) -> Option<&'static str> {
+    if !capture.enabled {
+        return None;
+    }
+    // Large uploads (e.g. imported exports) are not buffered, which would reject them
+    let too_large = req
+        .headers()
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|len| len > MAX_REQUEST_BODY_BYTES);
+    if too_large {
+        return None;
+    }
+    let forced = req
+        .headers()
+        .get(DEBUG_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+    if forced {
+        Some("header")
+    } else if capture.sample_rate > 0.0 && rand::random::<f64>() < capture.sample_rate {
+        Some("sampled")
+    } else {
+        None
+    }
+}
+
+/// Middleware recording redacted request and response bodies for a sample of requests,
+/// or for any request carrying the debug header
+/// Captures are written in the background so they never slow down or fail the request
+pub async fn capture_bodies(
+    State(capture): State<DebugCapture>,
+    matched_path: Option<MatchedPath>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(reason) = capture_reason(&capture, &req) else {
+        return next.run(req).await;
+    };
+
+    let started = Instant::now();
+    let path = matched_path
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let endpoint = format!("{} {}", req.method(), path);
+    let query = req.uri().query().map(redact_query);
+    let user_agent = req
+        .headers()
+        .get(axum::http::header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    // Buffer the request body so it can be both stored and handed to the handler
+    let (parts, body) = req.into_parts();
+    let request_bytes = match to_bytes(body, MAX_REQUEST_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let req = Request::from_parts(parts, Body::from(request_bytes.clone()));
+
+    let response = next.run(req).await;
+
+    let (parts, body) = response.into_parts();
+    let response_bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to buffer response for debug capture: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let record = DebugCaptureCreate {
+        reason,
+        endpoint,
+        query,
+        status: parts.status.as_u16(),
+        duration_ms: started.elapsed().as_millis() as i64,
+        user_agent,
+        request_body: redact_body(&request_bytes),
+        response_body: redact_body(&response_bytes),
+    };
+    tokio::spawn(async move {
+        if let Err(e) = debug_capture_queries::insert_capture(&capture.db, &record).await {
+            eprintln!("Failed to store debug capture: {}", e);
+        }
+    });
+
+    Response::from_parts(parts, Body::from(response_bytes))
+}
@@ -0,0 +1,319 @@
+use crate::database::DbPool;
+use crate::models::transaction_models::{
+    TransactionCreate, TransactionFilter, TransactionQuery, TransactionSource,
+};
+use crate::queries::transaction_queries;
+use crate::request_tx::Tx;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// How far apart (in days) two transactions may be and still count as the same one
+/// Banks often book a card payment a day or two after it was made
+const FUZZY_DATE_WINDOW_DAYS: i64 = 3;
+
+/// Minimum share of description words two transactions must have in common to match
+const FUZZY_DESCRIPTION_THRESHOLD: f64 = 0.5;
+
+/// Why an import candidate was not inserted
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// The same external id was already imported from this source
+    ExternalId,
+    /// The same external id appears earlier in the same import
+    RepeatedInBatch,
+    /// An existing transaction has the same amount, a close date and a similar description
+    Fuzzy,
+}
+
+/// A candidate that was skipped during an import
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedDuplicate {
+    /// Position of the candidate in the import (0-based)
+    pub row: usize,
+    pub external_id: Option<String>,
+    pub reason: DuplicateReason,
+    /// The existing transaction the candidate matched, when known
+    pub matched_transaction_id: Option<Uuid>,
+}
+
+/// Outcome of an import run, returned to the client
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub skipped_duplicates: Vec<SkippedDuplicate>,
+}
+
+/// Lowercase a description and split it into alphanumeric words
+fn normalize_description(description: &str) -> HashSet<String> {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Jaccard similarity between the word sets of two descriptions
+/// An empty description is like no other, encrypted descriptions are stored empty
+fn description_similarity(a: &str, b: &str) -> f64 {
+    let a = normalize_description(a);
+    let b = normalize_description(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let common = a.intersection(&b).count() as f64;
+    let total = a.union(&b).count() as f64;
+    common / total
+}
+
+/// Check whether an existing transaction looks like the same real-world transaction as a candidate
+/// A candidate with an external id new to its source is a transaction of its own for that
+/// source, it only matches ones entered otherwise: by hand, or from another source
+fn is_fuzzy_match(
+    candidate: &TransactionCreate,
+    source: TransactionSource,
+    occurred_at: DateTime<Utc>,
+    existing: &TransactionQuery,
+) -> bool {
+    if candidate.external_id.is_some()
+        && existing.source == source
+        && existing.external_id.is_some()
+    {
+        return false;
+    }
+    if candidate.amount != existing.amount {
+        return false;
+    }
+    if (occurred_at - existing.created_at).abs() > Duration::days(FUZZY_DATE_WINDOW_DAYS) {
+        return false;
+    }
+    description_similarity(&candidate.description, &existing.description)
+        >= FUZZY_DESCRIPTION_THRESHOLD
+}
+
+/// Split import candidates into new transactions and duplicates of existing ones
+/// Candidates are matched first by external id, then by (date, amount, description) heuristics
+/// Returns the indices of the candidates to insert along with the skipped duplicates
+pub async fn find_duplicates(
+    pool: &DbPool,
+    user_id: Uuid,
+    source: TransactionSource,
+    candidates: &[TransactionCreate],
+) -> anyhow::Result<(Vec<usize>, Vec<SkippedDuplicate>)> {
+    let external_ids: Vec<String> = candidates
+        .iter()
+        .filter_map(|c| c.external_id.clone())
+        .collect();
+    let known_external_ids =
+        transaction_queries::get_existing_external_ids(pool, user_id, source, &external_ids)
+            .await?;
+
+    // Undated candidates will be stored with the current time, so match them against that
+    let now = Utc::now();
+    let dates: Vec<DateTime<Utc>> = candidates
+        .iter()
+        .map(|c| c.occurred_at.unwrap_or(now))
+        .collect();
+
+    // Load the user's transactions around the imported date range once,
+    // instead of querying per candidate
+    let window = Duration::days(FUZZY_DATE_WINDOW_DAYS);
+    let existing = match (dates.iter().min(), dates.iter().max()) {
+        (Some(start), Some(end)) => {
+            let filter = TransactionFilter::for_user(user_id)
+                .period(Some(*start - window), Some(*end + window));
+            transaction_queries::get_transactions(pool, &filter).await?
+        }
+        _ => Vec::new(),
+    };
+
+    let mut to_insert = Vec::new();
+    let mut skipped = Vec::new();
+    let mut seen_in_batch = HashSet::new();
+    // An existing transaction can only absorb one candidate, so two identical
+    // coffees on the same day in a statement are not collapsed into one
+    let mut claimed = HashSet::new();
+
+    for (row, candidate) in candidates.iter().enumerate() {
+        if let Some(external_id) = &candidate.external_id {
+            if known_external_ids.contains(external_id) {
+                skipped.push(SkippedDuplicate {
+                    row,
+                    external_id: Some(external_id.clone()),
+                    reason: DuplicateReason::ExternalId,
+                    matched_transaction_id: None,
+                });
+                continue;
+            }
+            if !seen_in_batch.insert(external_id.clone()) {
+                skipped.push(SkippedDuplicate {
+                    row,
+                    external_id: Some(external_id.clone()),
+                    reason: DuplicateReason::RepeatedInBatch,
+                    matched_transaction_id: None,
+                });
+                continue;
+            }
+        }
+
+        let fuzzy_match = existing
+            .iter()
+            .find(|e| !claimed.contains(&e.id) && is_fuzzy_match(candidate, source, dates[row], e));
+        if let Some(matched) = fuzzy_match {
+            claimed.insert(matched.id);
+            skipped.push(SkippedDuplicate {
+                row,
+                external_id: candidate.external_id.clone(),
+                reason: DuplicateReason::Fuzzy,
+                matched_transaction_id: Some(matched.id),
+            });
+            continue;
+        }
+
+        to_insert.push(row);
+    }
+
+    Ok((to_insert, skipped))
+}
+
+/// Insert import candidates for a user in the request's transaction, skipping duplicates of
+/// existing transactions, so either all of them are stored or none
+pub async fn import_transactions(
+    pool: &DbPool,
+    tx: &mut Tx,
+    user_id: Uuid,
+    source: TransactionSource,
+    candidates: &[TransactionCreate],
+) -> anyhow::Result<ImportSummary> {
+    let (to_insert, skipped_duplicates) =
+        find_duplicates(pool, user_id, source, candidates).await?;
+
+    let mut summary = ImportSummary {
+        inserted: 0,
+        skipped_duplicates,
+    };
+    for row in to_insert {
+        let candidate = &candidates[row];
+        match transaction_queries::insert_transaction(tx.conn().await?, candidate).await? {
+            Some(id) => {
+                summary.inserted += 1;
+                tx.publish_on_commit(transaction_queries::created_event(id, candidate));
+            }
+            None => summary.skipped_duplicates.push(lost_race(row, candidate)),
+        }
+    }
+    summary.skipped_duplicates.sort_by_key(|s| s.row);
+
+    Ok(summary)
+}
+
+/// Insert the candidates at `rows`, counting them in the summary
+/// Used by imports that report progress as they insert, each transaction stored on its own
+pub async fn insert_candidates(
+    pool: &DbPool,
+    candidates: &[TransactionCreate],
+    rows: &[usize],
+    summary: &mut ImportSummary,
+) -> anyhow::Result<()> {
+    for &row in rows {
+        if transaction_queries::create_transaction(pool, &candidates[row])
+            .await?
+            .is_some()
+        {
+            summary.inserted += 1;
+        } else {
+            summary
+                .skipped_duplicates
+                .push(lost_race(row, &candidates[row]));
+        }
+    }
+    Ok(())
+}
+
+/// A candidate that lost a race with a concurrent import of the same external id
+fn lost_race(row: usize, candidate: &TransactionCreate) -> SkippedDuplicate {
+    SkippedDuplicate {
+        row,
+        external_id: candidate.external_id.clone(),
+        reason: DuplicateReason::ExternalId,
+        matched_transaction_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::transaction_models::{TransactionCategory, TransactionType};
+
+    fn candidate(external_id: Option<&str>, description: &str) -> TransactionCreate {
+        TransactionCreate::new(
+            Uuid::nil(),
+            TransactionType::Expense,
+            Money::new(450, Currency::EUR),
+            None,
+            Some(description.to_string()),
+        )
+        .with_origin(TransactionSource::BankSync, external_id.map(str::to_string))
+    }
+
+    fn existing(
+        source: TransactionSource,
+        external_id: Option<&str>,
+        description: &str,
+    ) -> TransactionQuery {
+        TransactionQuery::new(
+            Uuid::new_v4(),
+            Uuid::nil(),
+            TransactionType::Expense,
+            Money::new(-450, Currency::EUR),
+            TransactionCategory::Other,
+            description.to_string(),
+            None,
+            source,
+            external_id.map(str::to_string),
+            None,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn new_external_ids_only_match_transactions_entered_otherwise() {
+        let coffee = candidate(Some("tx-2"), "Coffee corner");
+        let now = Utc::now();
+        let synced = existing(TransactionSource::BankSync, Some("tx-1"), "Coffee corner");
+        assert!(!is_fuzzy_match(
+            &coffee,
+            TransactionSource::BankSync,
+            now,
+            &synced
+        ));
+        let typed = existing(TransactionSource::Manual, None, "Coffee corner");
+        assert!(is_fuzzy_match(
+            &coffee,
+            TransactionSource::BankSync,
+            now,
+            &typed
+        ));
+        // Without an external id the candidate may be any of them
+        let coffee = candidate(None, "Coffee corner");
+        assert!(is_fuzzy_match(
+            &coffee,
+            TransactionSource::BankSync,
+            now,
+            &synced
+        ));
+    }
+    #[test]
+    fn empty_descriptions_are_not_similar() {
+        assert_eq!(description_similarity("", ""), 0.0);
+        assert_eq!(description_similarity("Coffee", " - "), 0.0);
+        assert_eq!(
+            description_similarity("Coffee corner", "coffee CORNER"),
+            1.0
+        );
+    }
+}
@@ -0,0 +1,244 @@
+// Weekly digest of shared wallets
+//
+// The scheduler queues "digests.weekly" every Monday morning (see DEFAULT_SCHEDULES). Users
+// who opted in through their notification preferences get a summary of the previous week,
+// Monday to Sunday in their timezone, of every shared wallet they own or belong to: what each
+// member recorded and where the money went by category. It goes out as a "digest.weekly"
+// webhook event or as a message to the linked Telegram chat, falling back to the webhook when
+// the bot is not configured or no chat is linked. The week is recorded once delivered, so a
+// repeated run sends nothing more.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::models::money_models::Money;
+use crate::models::notification_models::{
+    CategoryActivity, DigestRecipient, MemberActivity, NotificationChannel, WalletActivityRow,
+    WalletDigest,
+};
+use crate::models::transaction_models::{self, TransactionSum};
+use crate::queries::{notification_queries, telegram_queries, webhook_queries};
+use axum::async_trait;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Members and categories listed per wallet in Telegram messages
+const MAX_MESSAGE_ROWS: usize = 5;
+
+/// Job sending the digest of the previous week
+pub struct WeeklyDigestJob {
+    /// Token of the Telegram bot, digests go to webhooks when unset
+    pub telegram_token: Option<String>,
+    pub client: reqwest::Client,
+}
+
+/// Summarize a wallet's activity rows
+fn build_digest(
+    owner_id: Uuid,
+    owner_name: String,
+    rows: &[WalletActivityRow],
+) -> anyhow::Result<WalletDigest> {
+    let mut totals = TransactionSum::default();
+    let mut transactions = 0;
+    let mut members: BTreeMap<Uuid, MemberActivity> = BTreeMap::new();
+    let mut categories: BTreeMap<&str, CategoryActivity> = BTreeMap::new();
+    for row in rows {
+        let amount =
+            Money::from_decimal_rounded(row.total, row.currency).map_err(|e| anyhow::anyhow!(e))?;
+        totals.add(&row.transaction_type, amount);
+        transactions += row.transactions;
+
+        let member = members
+            .entry(row.member_id)
+            .or_insert_with(|| MemberActivity {
+                member_id: row.member_id,
+                name: row.member_name.clone(),
+                transactions: 0,
+                totals: TransactionSum::default(),
+            });
+        member.totals.add(&row.transaction_type, amount);
+        member.transactions += row.transactions;
+
+        let category = categories
+            .entry(&row.category)
+            .or_insert_with(|| CategoryActivity {
+                category: row.category.clone(),
+                transactions: 0,
+                totals: TransactionSum::default(),
+            });
+        category.totals.add(&row.transaction_type, amount);
+        category.transactions += row.transactions;
+    }
+
+    let mut members: Vec<MemberActivity> = members.into_values().collect();
+    members.sort_by_key(|member| std::cmp::Reverse(member.transactions));
+    // Compared on the first currency's expenses, enough to order a mostly single currency wallet
+    let spent = |totals: &TransactionSum| {
+        totals
+            .expenses
+            .to_vec()
+            .first()
+            .map_or(0, |money| money.minor_units)
+    };
+    let mut categories: Vec<CategoryActivity> = categories.into_values().collect();
+    categories.sort_by_key(|category| std::cmp::Reverse(spent(&category.totals)));
+
+    Ok(WalletDigest {
+        owner_id,
+        owner_name,
+        transactions,
+        totals,
+        members,
+        categories,
+    })
+}
+
+/// Plain text of the digest for a chat message
+fn format_message(week_start: NaiveDate, week_end: NaiveDate, wallets: &[WalletDigest]) -> String {
+    let mut text = format!(
+        "Your week in shared wallets, {} to {}\n",
+        week_start.format("%b %-d"),
+        week_end.format("%b %-d")
+    );
+    for wallet in wallets {
+        let _ = write!(
+            text,
+            "\n{}'s wallet: {} transaction(s), spent {}, earned {}\n",
+            wallet.owner_name, wallet.transactions, wallet.totals.expenses, wallet.totals.income
+        );
+        for member in wallet.members.iter().take(MAX_MESSAGE_ROWS) {
+            let _ = writeln!(
+                text,
+                "• {}: {} transaction(s), spent {}",
+                member.name, member.transactions, member.totals.expenses
+            );
+        }
+        for category in wallet
+            .categories
+            .iter()
+            .filter(|category| !category.totals.expenses.to_vec().is_empty())
+            .take(MAX_MESSAGE_ROWS)
+        {
+            let _ = writeln!(
+                text,
+                "• {}: {}",
+                category.category, category.totals.expenses
+            );
+        }
+    }
+    text
+}
+
+impl WeeklyDigestJob {
+    async fn send_telegram(&self, token: &str, chat_id: i64, text: &str) -> anyhow::Result<()> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", token);
+        // reqwest errors include the URL, which contains the token
+        let response = self
+            .client
+            .post(url)
+            .json(&json!({ "chat_id": chat_id, "text": text }))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Telegram sendMessage failed: {}", e.without_url()))?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Telegram sendMessage failed with {}",
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Send the recipient the digest of the week before the current one in their timezone
+    /// Returns false when it was already sent
+    async fn send_digest(&self, db: &DbPool, recipient: &DigestRecipient) -> anyhow::Result<bool> {
+        let tz: Tz = recipient.timezone.parse().unwrap_or(Tz::UTC);
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let this_week = today - Duration::days(today.weekday().num_days_from_monday().into());
+        let week_start = this_week - Duration::days(7);
+        if recipient
+            .last_digest_week
+            .is_some_and(|sent| sent >= week_start)
+        {
+            return Ok(false);
+        }
+        let start = transaction_models::local_midnight(week_start, tz);
+        let end = transaction_models::local_midnight(this_week, tz);
+
+        let mut wallets = Vec::new();
+        for (owner_id, owner_name) in
+            notification_queries::get_shared_wallets(db, recipient.user_id).await?
+        {
+            let rows = notification_queries::get_wallet_activity(db, owner_id, start, end).await?;
+            if !rows.is_empty() {
+                wallets.push(build_digest(owner_id, owner_name, &rows)?);
+            }
+        }
+
+        // Nothing to tell about a quiet week
+        if !wallets.is_empty() {
+            let week_end = this_week - Duration::days(1);
+            let chat = match (&self.telegram_token, recipient.channel) {
+                (Some(token), NotificationChannel::Telegram) => {
+                    telegram_queries::get_user_chat(db, recipient.user_id)
+                        .await?
+                        .map(|chat_id| (token, chat_id))
+                }
+                _ => None,
+            };
+            match chat {
+                Some((token, chat_id)) => {
+                    let text = format_message(week_start, week_end, &wallets);
+                    self.send_telegram(token, chat_id, &text).await?;
+                }
+                None => {
+                    let data = json!({
+                        "week_start": week_start,
+                        "week_end": week_end,
+                        "wallets": wallets,
+                    });
+                    webhook_queries::enqueue(db, recipient.user_id, "digest.weekly", &data).await?;
+                }
+            }
+        }
+        notification_queries::mark_digest_sent(db, recipient.user_id, week_start).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl JobHandler for WeeklyDigestJob {
+    fn kind(&self) -> &'static str {
+        "digests.weekly"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let mut sent = 0;
+        let mut failed = 0;
+        for recipient in notification_queries::get_digest_recipients(db).await? {
+            match self.send_digest(db, &recipient).await {
+                Ok(true) => sent += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::error!(
+                        user_id = %recipient.user_id,
+                        error = %e,
+                        "Sending weekly digest failed"
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        if sent > 0 {
+            tracing::info!(sent, "Weekly digests sent");
+        }
+        // Retried, the users who got theirs are skipped
+        if failed > 0 {
+            return Err(anyhow::anyhow!("{} weekly digest(s) failed", failed));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+// Merchant enrichment of transactions
+//
+// When MERCHANT_API_URL is set, every transaction created with a plaintext description is
+// queued for enrichment. The description is the raw descriptor (e.g. "POS 4411 STARBUCKS
+// #123 SEATTLE WA"), looked up with GET <MERCHANT_API_URL>?descriptor=... and, with
+// MERCHANT_API_KEY, an "Authorization: Bearer" header. The API answers
+// {"name": ..., "logo_url": ..., "category": ...} or 404 when it knows no merchant. A
+// match attaches the clean name and logo to the transaction, and its category when the
+// transaction is still in Other or Uncategorized. Answers, misses included, are cached for
+// MERCHANT_CACHE_TTL_SECS under a hash of the normalized descriptor, so descriptors
+// differing only in store numbers or dates share one lookup.
+
+use crate::database::DbPool;
+use crate::events::{ChangeEvent, ChangeKind, Subscriber};
+use crate::jobs::{self, JobHandler};
+use crate::models::job_models::JobCreate;
+use crate::models::merchant_models::Merchant;
+use crate::models::reconciliation_models::TransactionLocked;
+use crate::models::transaction_models::TransactionCategory;
+use crate::queries::{merchant_queries, transaction_queries};
+use axum::async_trait;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longest a lookup may take
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Answer of the merchant-data API
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    name: Option<String>,
+    logo_url: Option<String>,
+    category: Option<String>,
+}
+
+/// Descriptor reduced to its words: lowercase letters, without the numbers and punctuation
+/// that vary between payments at the same merchant
+pub fn normalize_descriptor(descriptor: &str) -> String {
+    descriptor
+        .to_lowercase()
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Looks merchants up in the merchant-data API, through the cache
+pub struct MerchantEnricher {
+    pub endpoint: Url,
+    pub api_key: Option<String>,
+    pub cache_ttl: Duration,
+    pub client: reqwest::Client,
+}
+
+impl MerchantEnricher {
+    /// Merchant behind a raw descriptor, None when the API knows none
+    pub async fn lookup(&self, db: &DbPool, descriptor: &str) -> anyhow::Result<Option<Merchant>> {
+        let normalized = normalize_descriptor(descriptor);
+        if normalized.is_empty() {
+            return Ok(None);
+        }
+        let hash = format!("{:x}", Sha256::digest(normalized.as_bytes()));
+        if let Some(cached) = merchant_queries::get_lookup(db, &hash, self.cache_ttl).await? {
+            return Ok(cached);
+        }
+
+        let merchant = self.fetch(descriptor.trim()).await?;
+        merchant_queries::store_lookup(db, &hash, merchant.as_ref()).await?;
+        Ok(merchant)
+    }
+
+    async fn fetch(&self, descriptor: &str) -> anyhow::Result<Option<Merchant>> {
+        let mut request = self
+            .client
+            .get(self.endpoint.clone())
+            .query(&[("descriptor", descriptor)])
+            .timeout(LOOKUP_TIMEOUT);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Merchant lookup failed with {}",
+                response.status()
+            ));
+        }
+
+        let found: LookupResponse = response.json().await?;
+        Ok(found
+            .name
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| Merchant {
+                name: name.trim().chars().take(255).collect(),
+                logo_url: found
+                    .logo_url
+                    .filter(|url| url.starts_with("https://") && url.len() <= 2048),
+                category: found
+                    .category
+                    .and_then(|c| TransactionCategory::from_str(&c).ok()),
+            }))
+    }
+}
+
+/// Queues the enrichment of every transaction created
+pub struct QueueEnrichment;
+
+#[async_trait]
+impl Subscriber for QueueEnrichment {
+    fn name(&self) -> &'static str {
+        "enrichment"
+    }
+
+    async fn handle(&self, db: &DbPool, event: &ChangeEvent) -> anyhow::Result<()> {
+        if event.kind != ChangeKind::Created {
+            return Ok(());
+        }
+        let job = JobCreate::new(
+            "transactions.enrich",
+            json!({ "transaction_id": event.transaction_id, "user_id": event.user_id }),
+        )
+        .dedupe_key(event.transaction_id.to_string());
+        jobs::enqueue(db, job).await?;
+        Ok(())
+    }
+}
+
+/// Job attaching the merchant to one transaction
+pub struct EnrichTransactionJob {
+    pub enricher: Arc<MerchantEnricher>,
+}
+
+#[async_trait]
+impl JobHandler for EnrichTransactionJob {
+    fn kind(&self) -> &'static str {
+        "transactions.enrich"
+    }
+
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()> {
+        let transaction_id: Uuid = payload
+            .get("transaction_id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("Missing transaction_id"))?
+            .parse()?;
+        let user_id: Uuid = payload
+            .get("user_id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("Missing user_id"))?
+            .parse()?;
+
+        // Deleted in the meantime
+        let Some(transaction) =
+            transaction_queries::get_transaction(db, transaction_id, user_id).await?
+        else {
+            return Ok(());
+        };
+        // Encrypted on the client, the descriptor is not ours to read
+        if transaction.encrypted_description.is_some() || transaction.merchant_name.is_some() {
+            return Ok(());
+        }
+        let Some(merchant) = self.enricher.lookup(db, &transaction.description).await? else {
+            return Ok(());
+        };
+        match transaction_queries::set_merchant(db, transaction_id, user_id, &merchant).await {
+            // Reconciled in the meantime, left as it was
+            Err(e) if e.is::<TransactionLocked>() => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
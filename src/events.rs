@@ -0,0 +1,240 @@
+// Change events of transactions
+//
+// Writes of transactions publish a `ChangeEvent` once they are stored (see
+// transaction_queries), so data derived from transactions is kept up to date in one place
+// instead of from every write path. Subscribers registered on the `EventBus` run inline,
+// before the write returns: the webhook dispatcher queues transaction.* deliveries there.
+//
+// Listeners such as the server-sent events of GET /api/events must also hear about writes
+// made by other server instances. They are fed by the change feed: a trigger NOTIFYs every
+// committed change on the transaction_changes channel, which each instance LISTENs to and
+// broadcasts in process. Listeners may miss events when they fall behind or while the
+// feed reconnects.
+
+use crate::database::DbPool;
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::{TransactionCategory, TransactionSource, TransactionType};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sqlx::postgres::PgListener;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Events a listener may lag behind before it starts missing them
+const BROADCAST_CAPACITY: usize = 4096;
+
+/// Channel the database announces changes of transactions on
+const CHANNEL: &str = "transaction_changes";
+
+/// Wait before listening again after the change feed's connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+static BUS: OnceLock<EventBus> = OnceLock::new();
+
+/// What happened to a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    /// Soft deleted, the row is kept and can be restored
+    Deleted,
+    Restored,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Restored => "restored",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A stored change of one transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub transaction_id: Uuid,
+    pub user_id: Uuid,
+    /// Where the transaction came from, when the write knew
+    pub source: Option<TransactionSource>,
+    /// The changed fields, as sent in webhooks
+    pub data: Value,
+    pub at: DateTime<Utc>,
+}
+
+impl ChangeEvent {
+    pub fn new(kind: ChangeKind, transaction_id: Uuid, user_id: Uuid, data: Value) -> Self {
+        Self {
+            kind,
+            transaction_id,
+            user_id,
+            source: None,
+            data,
+            at: Utc::now(),
+        }
+    }
+
+    pub fn source(mut self, source: TransactionSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Name of the event, e.g. "transaction.created"
+    pub fn name(&self) -> String {
+        format!("transaction.{}", self.kind)
+    }
+}
+
+/// Keeps derived data in step with transactions
+#[async_trait]
+pub trait Subscriber: Send + Sync {
+    /// Name in logs, e.g. "webhooks"
+    fn name(&self) -> &'static str;
+
+    /// React to a change, an error is logged and does not fail the write
+    async fn handle(&self, db: &DbPool, event: &ChangeEvent) -> anyhow::Result<()>;
+}
+
+/// Subscribers and listeners of change events
+pub struct EventBus {
+    subscribers: Vec<Box<dyn Subscriber>>,
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            sender: broadcast::channel(BROADCAST_CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    /// Add a subscriber, run in the order they were added
+    pub fn subscribe(mut self, subscriber: impl Subscriber + 'static) -> Self {
+        self.subscribers.push(Box::new(subscriber));
+        self
+    }
+}
+
+/// Set the bus for the lifetime of the process, events are dropped until it is set
+pub fn init(bus: EventBus) {
+    if BUS.set(bus).is_err() {
+        eprintln!("📣 Event bus already initialized");
+    }
+}
+
+/// Hand a change to the subscribers, listeners hear of it through the change feed
+pub async fn publish(db: &DbPool, event: ChangeEvent) {
+    let Some(bus) = BUS.get() else {
+        return;
+    };
+    for subscriber in &bus.subscribers {
+        if let Err(e) = subscriber.handle(db, &event).await {
+            eprintln!(
+                "📣 {} failed to handle {} of {}: {}",
+                subscriber.name(),
+                event.name(),
+                event.transaction_id,
+                e
+            );
+        }
+    }
+}
+
+/// Receive the events published from now on, None when there is no bus
+pub fn listen() -> Option<broadcast::Receiver<ChangeEvent>> {
+    BUS.get().map(|bus| bus.sender.subscribe())
+}
+
+/// A change as announced by the notify_transaction_change trigger
+#[derive(Deserialize)]
+struct Notification {
+    op: ChangeKind,
+    id: Uuid,
+    user_id: Uuid,
+    transaction_type: String,
+    amount: Decimal,
+    currency: String,
+    category: String,
+    source: String,
+    at: DateTime<Utc>,
+}
+
+impl Notification {
+    fn into_event(self) -> anyhow::Result<ChangeEvent> {
+        let currency = Currency::from_str(&self.currency).map_err(|e| anyhow::anyhow!(e))?;
+        let amount =
+            Money::from_decimal_rounded(self.amount, currency).map_err(|e| anyhow::anyhow!(e))?;
+        let transaction_type =
+            TransactionType::from_str(&self.transaction_type).map_err(|e| anyhow::anyhow!(e))?;
+        let category =
+            TransactionCategory::from_str(&self.category).map_err(|e| anyhow::anyhow!(e))?;
+        let source = TransactionSource::from_str(&self.source).map_err(|e| anyhow::anyhow!(e))?;
+        Ok(ChangeEvent {
+            kind: self.op,
+            transaction_id: self.id,
+            user_id: self.user_id,
+            source: Some(source),
+            data: json!({
+                "id": self.id,
+                "transaction_type": transaction_type,
+                "amount": amount,
+                "category": category,
+                "source": source,
+            }),
+            at: self.at,
+        })
+    }
+}
+
+/// Broadcast the changes the database announces, made by this instance or its peers, for
+/// as long as the server runs
+pub fn spawn_change_feed(pool: DbPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = follow_change_feed(&pool).await {
+                eprintln!("📣 Change feed interrupted, listening again: {}", e);
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn follow_change_feed(pool: &DbPool) -> anyhow::Result<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    loop {
+        // Changes made while the connection is down are missed
+        let notification = listener
+            .try_recv()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("connection lost"))?;
+        let Some(bus) = BUS.get() else {
+            continue;
+        };
+        match serde_json::from_str::<Notification>(notification.payload())
+            .map_err(anyhow::Error::from)
+            .and_then(Notification::into_event)
+        {
+            // No listeners is not an error
+            Ok(event) => {
+                let _ = bus.sender.send(event);
+            }
+            Err(e) => eprintln!("📣 Unreadable change notification: {}", e),
+        }
+    }
+}
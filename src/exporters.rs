@@ -0,0 +1,111 @@
+// Exports in the CSV formats other apps import, so users can take their data
+// elsewhere (and bring it back with `importers`)
+//
+// Firefly III: "date","description","amount","currency_code","category","type","external_id"
+//   with signed amounts, the columns map one to one onto the Firefly III data importer roles
+// YNAB: "Date","Payee","Memo","Outflow","Inflow", the file import format of YNAB
+
+use crate::models::transaction_models::{TransactionQuery, TransactionType};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// The apps exports can be produced for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportTarget {
+    Firefly,
+    Ynab,
+}
+
+impl fmt::Display for ExportTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ExportTarget::Firefly => "firefly",
+            ExportTarget::Ynab => "ynab",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for ExportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "firefly" | "firefly-iii" => Ok(ExportTarget::Firefly),
+            "ynab" => Ok(ExportTarget::Ynab),
+            _ => Err(format!("Unknown export target: {}", s)),
+        }
+    }
+}
+
+impl ExportTarget {
+    pub const ALL: [ExportTarget; 2] = [ExportTarget::Firefly, ExportTarget::Ynab];
+
+    /// Name of the downloaded file
+    pub fn file_name(self) -> String {
+        format!("wallet-{}.csv", self)
+    }
+}
+
+/// CSV of the transactions, oldest first, dated in `tz`
+pub fn write_csv(
+    target: ExportTarget,
+    transactions: &mut [TransactionQuery],
+    tz: Tz,
+) -> anyhow::Result<Vec<u8>> {
+    transactions.sort_by_key(|t| t.created_at);
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    match target {
+        ExportTarget::Firefly => {
+            writer.write_record([
+                "date",
+                "description",
+                "amount",
+                "currency_code",
+                "category",
+                "type",
+                "external_id",
+            ])?;
+            for t in transactions.iter() {
+                writer.write_record([
+                    t.created_at.with_timezone(&tz).date_naive().to_string(),
+                    t.description.clone(),
+                    t.amount.to_decimal().to_string(),
+                    t.amount.currency.to_string(),
+                    t.category.to_string(),
+                    match t.transaction_type {
+                        TransactionType::Income => "deposit".to_string(),
+                        TransactionType::Expense => "withdrawal".to_string(),
+                    },
+                    // Lets Firefly III skip rows it already has when the export is imported again
+                    format!("wallet:{}", t.id),
+                ])?;
+            }
+        }
+        ExportTarget::Ynab => {
+            writer.write_record(["Date", "Payee", "Memo", "Outflow", "Inflow"])?;
+            for t in transactions.iter() {
+                let amount = t.amount.abs().to_decimal().to_string();
+                let (outflow, inflow) = match t.transaction_type {
+                    TransactionType::Income => (String::new(), amount),
+                    TransactionType::Expense => (amount, String::new()),
+                };
+                writer.write_record([
+                    t.created_at
+                        .with_timezone(&tz)
+                        .format("%m/%d/%Y")
+                        .to_string(),
+                    t.description.clone(),
+                    // YNAB categories are the user's own, ours are kept as a hint
+                    t.category.to_string(),
+                    outflow,
+                    inflow,
+                ])?;
+            }
+        }
+    }
+    Ok(writer.into_inner()?)
+}
@@ -0,0 +1,692 @@
+// Subset of the Firefly III REST API (v1) over this wallet's data, so mobile
+// clients written for Firefly III can be pointed at this server
+//
+// Firefly III concepts are mapped as follows:
+// - asset accounts are the user's bank accounts, plus a "Wallet" account (id "cash")
+//   for transactions that are not linked to one
+// - withdrawals and deposits are expenses and income, transfers are not supported
+// - transaction groups have exactly one split, whose journal id is the transaction id
+// - categories are the fixed transaction categories, with their name as id
+//
+// Clients authenticate with a personal access token, configured per user in FIREFLY_TOKENS.
+
+use crate::handlers::{self, AppState};
+use crate::importers;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::bank_account_models::BankAccountQuery;
+use crate::models::money_models::{Currency, Money, MoneyTotals};
+use crate::models::transaction_models::{
+    self, TransactionCategory, TransactionCreate, TransactionFilter, TransactionQuery,
+    TransactionSort, TransactionSource, TransactionType,
+};
+use crate::queries::{bank_account_queries, transaction_queries, user_queries};
+use crate::quotas;
+use crate::restrictions;
+use crate::signatures::constant_time_eq;
+use crate::validation::{MAX_DESCRIPTION_LEN, MAX_EXTERNAL_ID_LEN, ValidationErrors};
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Firefly III version reported to clients, some refuse to talk to older servers
+const FIREFLY_VERSION: &str = "6.1.0";
+const FIREFLY_API_VERSION: &str = "2.0.0";
+
+/// Account id of transactions without a bank account
+const CASH_ACCOUNT_ID: &str = "cash";
+
+/// Page size when the client does not ask for one, same as Firefly III
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Firefly III compatible API under /api/v1, only served when tokens are configured
+pub struct FireflyApi {
+    pub state: AppState,
+    /// Personal access tokens and the wallet user each one acts as
+    pub tokens: Vec<(String, Uuid)>,
+}
+
+/// The user a request was authenticated as
+#[derive(Clone, Copy)]
+struct FireflyUser(Uuid);
+
+impl FireflyApi {
+    /// Parse "token:user-uuid,token:user-uuid" into the token list
+    pub fn parse_tokens(raw: &str) -> anyhow::Result<Vec<(String, Uuid)>> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (token, user) = entry
+                    .rsplit_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("Firefly tokens must be token:user_id"))?;
+                let user = user
+                    .trim()
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid user id for a Firefly token: {}", e))?;
+                Ok((token.trim().to_string(), user))
+            })
+            .collect()
+    }
+
+    pub fn router<S>(self) -> Router<S> {
+        let api = Arc::new(self);
+        Router::new()
+            .route("/api/v1/about", get(about))
+            .route("/api/v1/about/user", get(about_user))
+            .route("/api/v1/accounts", get(get_accounts))
+            .route(
+                "/api/v1/transactions",
+                get(get_transactions).post(create_transaction),
+            )
+            .route("/api/v1/transactions/:id", get(get_transaction))
+            .route("/api/v1/categories", get(get_categories))
+            .route("/api/v1/currencies", get(get_currencies))
+            .route("/api/v1/currencies/default", get(get_default_currency))
+            .route_layer(middleware::from_fn_with_state(api.clone(), require_token))
+            .with_state(api)
+    }
+}
+
+/// Resolve the bearer token to its user, 401 for unknown tokens
+async fn require_token(
+    State(api): State<Arc<FireflyApi>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Every token is compared, so the response time does not tell which one was close
+    let user = api.tokens.iter().fold(None, |found, (token, user_id)| {
+        if constant_time_eq(given.as_bytes(), token.as_bytes()) {
+            Some(*user_id)
+        } else {
+            found
+        }
+    });
+    let Some(user_id) = user else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "message": "Unauthenticated." })),
+        )
+            .into_response();
+    };
+    req.extensions_mut().insert(FireflyUser(user_id));
+    next.run(req).await
+}
+
+fn internal_error(context: &str, e: anyhow::Error) -> StatusCode {
+    eprintln!("Error {} for the Firefly API: {}", context, e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// GET /api/v1/about
+async fn about() -> Json<Value> {
+    Json(json!({
+        "data": {
+            "version": FIREFLY_VERSION,
+            "api_version": FIREFLY_API_VERSION,
+            "php_version": "",
+            "os": std::env::consts::OS,
+            "driver": "pgsql"
+        }
+    }))
+}
+
+/// GET /api/v1/about/user
+async fn about_user(
+    State(api): State<Arc<FireflyApi>>,
+    Extension(FireflyUser(user_id)): Extension<FireflyUser>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = user_queries::get_user_by_id(&api.state.db, user_id)
+        .await
+        .map_err(|e| internal_error("fetching the user", e))?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    Ok(Json(json!({
+        "data": {
+            "type": "users",
+            "id": user.id.to_string(),
+            "attributes": {
+                "email": user.email,
+                "blocked": false,
+                "role": "owner",
+                "created_at": user.created_at,
+                "updated_at": user.updated_at
+            }
+        }
+    })))
+}
+
+/// GET /api/v1/accounts - the user's asset accounts with their balances
+/// An account's currency is the one of its most recent transaction
+async fn get_accounts(
+    State(api): State<Arc<FireflyApi>>,
+    Extension(FireflyUser(user_id)): Extension<FireflyUser>,
+) -> Result<Json<Value>, StatusCode> {
+    let bank_accounts = bank_account_queries::get_bank_accounts(&api.state.db, user_id)
+        .await
+        .map_err(|e| internal_error("fetching bank accounts", e))?;
+    let transactions = load_transactions(&api, user_id, None, None, None).await?;
+
+    let mut currencies: HashMap<Option<Uuid>, Currency> = HashMap::new();
+    let mut balances: HashMap<Option<Uuid>, MoneyTotals> = HashMap::new();
+    for t in &transactions {
+        currencies
+            .entry(t.bank_account_id)
+            .or_insert(t.amount.currency);
+        balances.entry(t.bank_account_id).or_default().add(t.amount);
+    }
+    let balance = |account: Option<Uuid>| {
+        let currency = currencies.get(&account).copied().unwrap_or_default();
+        let amount = balances
+            .get(&account)
+            .and_then(|totals| totals.to_vec().into_iter().find(|m| m.currency == currency))
+            .unwrap_or(Money::new(0, currency));
+        (currency, amount)
+    };
+
+    let (currency, amount) = balance(None);
+    let mut accounts = vec![account_resource(
+        CASH_ACCOUNT_ID,
+        "Wallet",
+        currency,
+        amount,
+        None,
+    )];
+    for account in &bank_accounts {
+        let (currency, amount) = balance(Some(account.id));
+        accounts.push(account_resource(
+            &account.id.to_string(),
+            &account_name(account),
+            currency,
+            amount,
+            Some(account),
+        ));
+    }
+    let total = accounts.len();
+
+    Ok(Json(json!({
+        "data": accounts,
+        "meta": { "pagination": pagination(total, total, total.max(1), 1) }
+    })))
+}
+
+fn account_name(account: &BankAccountQuery) -> String {
+    format!("{} {}", account.institution, account.name)
+}
+
+fn account_resource(
+    id: &str,
+    name: &str,
+    currency: Currency,
+    balance: Money,
+    bank_account: Option<&BankAccountQuery>,
+) -> Value {
+    json!({
+        "type": "accounts",
+        "id": id,
+        "attributes": {
+            "name": name,
+            "type": "asset",
+            "account_role": if bank_account.is_some() { "defaultAsset" } else { "cashWalletAsset" },
+            "active": true,
+            "currency_code": currency.to_string(),
+            "currency_decimal_places": currency.minor_unit_digits(),
+            "current_balance": balance.to_decimal().to_string(),
+            "current_balance_date": Utc::now(),
+            "iban": bank_account.and_then(|a| a.iban.as_ref()),
+            "created_at": bank_account.map(|a| a.created_at),
+            "updated_at": bank_account.map(|a| a.updated_at)
+        }
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionListParameters {
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    /// "withdrawal", "deposit" or "all" (also the plural forms Firefly III accepts)
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    page: Option<usize>,
+    limit: Option<usize>,
+}
+
+/// GET /api/v1/transactions - the user's transactions, newest first, paginated
+async fn get_transactions(
+    State(api): State<Arc<FireflyApi>>,
+    Extension(FireflyUser(user_id)): Extension<FireflyUser>,
+    Query(params): Query<TransactionListParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let transaction_type = match params.kind.as_deref().unwrap_or("all") {
+        "all" | "default" => None,
+        "withdrawal" | "withdrawals" | "expense" => Some(TransactionType::Expense),
+        "deposit" | "deposits" | "income" => Some(TransactionType::Income),
+        other => {
+            eprintln!("Unsupported Firefly transaction type filter: {}", other);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    let tz = user_tz(&api, user_id).await?;
+    // Firefly III dates are inclusive days in the user's time zone
+    let start = params
+        .start
+        .map(|date| transaction_models::local_midnight(date, tz));
+    let end = params.end.and_then(|date| date.succ_opt()).map(|date| {
+        transaction_models::local_midnight(date, tz) - chrono::Duration::nanoseconds(1)
+    });
+
+    let transactions = load_transactions(&api, user_id, transaction_type, start, end).await?;
+    let accounts = account_names(&api, user_id).await?;
+
+    let per_page = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let page = params.page.unwrap_or(1).max(1);
+    let total = transactions.len();
+    let data: Vec<Value> = transactions
+        .iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .map(|t| transaction_resource(t, &accounts, tz))
+        .collect();
+    let count = data.len();
+
+    Ok(Json(json!({
+        "data": data,
+        "meta": { "pagination": pagination(total, count, per_page, page) }
+    })))
+}
+
+/// GET /api/v1/transactions/:id
+async fn get_transaction(
+    State(api): State<Arc<FireflyApi>>,
+    Extension(FireflyUser(user_id)): Extension<FireflyUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let transaction = transaction_queries::get_transaction(&api.state.db, id, user_id)
+        .await
+        .map_err(|e| internal_error("fetching a transaction", e))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let tz = user_tz(&api, user_id).await?;
+    let accounts = account_names(&api, user_id).await?;
+    Ok(Json(json!({
+        "data": transaction_resource(&transaction, &accounts, tz)
+    })))
+}
+
+/// One split of a new transaction group, as Firefly III clients send it
+#[derive(Debug, Deserialize)]
+struct SplitRequest {
+    #[serde(rename = "type")]
+    kind: String,
+    /// "2024-01-31" or a full timestamp such as "2024-01-31T12:00:00+02:00"
+    date: String,
+    amount: String,
+    description: String,
+    currency_code: Option<String>,
+    category_name: Option<String>,
+    source_id: Option<String>,
+    destination_id: Option<String>,
+    external_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionGroupRequest {
+    transactions: Vec<SplitRequest>,
+}
+
+/// POST /api/v1/transactions - record a withdrawal or deposit
+async fn create_transaction(
+    State(api): State<Arc<FireflyApi>>,
+    Extension(FireflyUser(user_id)): Extension<FireflyUser>,
+    Json(req): Json<TransactionGroupRequest>,
+) -> Result<Json<Value>, Response> {
+    let tz = user_tz(&api, user_id)
+        .await
+        .map_err(|s| s.into_response())?;
+    let bank_accounts = bank_account_queries::get_bank_accounts(&api.state.db, user_id)
+        .await
+        .map_err(|e| internal_error("fetching bank accounts", e).into_response())?;
+    let transaction = split_to_transaction(user_id, &req, &bank_accounts, tz)
+        .map_err(IntoResponse::into_response)?;
+
+    let checked = restrictions::check(&api.state.db, std::slice::from_ref(&transaction), false)
+        .await
+        .map_err(|e| internal_error("checking restrictions", e).into_response())?;
+    if let Some(refusal) = checked.refusal() {
+        return Err(refusal.into_response());
+    }
+    if let Some(exceeded) = handlers::reserve_quota(&api.state, user_id, 1)
+        .await
+        .map_err(IntoResponse::into_response)?
+    {
+        return Err(exceeded);
+    }
+    let id = transaction_queries::create_transaction(&api.state.db, &transaction).await;
+    if !matches!(id, Ok(Some(_))) {
+        quotas::release_transactions(&api.state.db, user_id, 1).await;
+    }
+    let id = id
+        .map_err(|e| internal_error("creating a transaction", e).into_response())?
+        // The external id was already used, Firefly III reports duplicates as a validation error
+        .ok_or_else(|| {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "transactions.0.external_id",
+                "Duplicate of an existing transaction",
+            );
+            errors.into_response()
+        })?;
+
+    handlers::record_audit(
+        &api.state,
+        AuditEntryCreate::new("create", "transaction", Some(id))
+            .actor(user_id)
+            .details(json!({
+                "transaction_type": transaction.transaction_type,
+                "amount": transaction.amount,
+                "category": transaction.category,
+                "source": transaction.source,
+                "client": "firefly",
+            })),
+    )
+    .await;
+
+    let created = transaction_queries::get_transaction(&api.state.db, id, user_id)
+        .await
+        .map_err(|e| internal_error("fetching a transaction", e).into_response())?
+        .ok_or_else(|| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+    let accounts = account_names(&api, user_id)
+        .await
+        .map_err(|s| s.into_response())?;
+    Ok(Json(json!({
+        "data": transaction_resource(&created, &accounts, tz)
+    })))
+}
+
+/// Check the single split of a new group and turn it into a transaction
+fn split_to_transaction(
+    user_id: Uuid,
+    req: &TransactionGroupRequest,
+    bank_accounts: &[BankAccountQuery],
+    tz: Tz,
+) -> Result<TransactionCreate, ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    let [split] = req.transactions.as_slice() else {
+        errors.add("transactions", "must contain exactly one split");
+        return Err(errors);
+    };
+
+    let transaction_type = match split.kind.as_str() {
+        "withdrawal" => Some(TransactionType::Expense),
+        "deposit" => Some(TransactionType::Income),
+        _ => {
+            errors.add(
+                "transactions.0.type",
+                "must be withdrawal or deposit, transfers are not supported",
+            );
+            None
+        }
+    };
+    let currency = match split.currency_code.as_deref() {
+        Some(code) => Currency::from_str(code).map_err(|e| {
+            errors.add("transactions.0.currency_code", e);
+        }),
+        None => Ok(Currency::default()),
+    };
+    let amount = match (Decimal::from_str(split.amount.trim()), currency) {
+        (Ok(amount), Ok(currency)) => Money::from_decimal(amount.abs(), currency)
+            .map_err(|e| errors.add("transactions.0.amount", e))
+            .ok(),
+        (Err(_), _) => {
+            errors.add("transactions.0.amount", "must be a decimal number");
+            None
+        }
+        _ => None,
+    };
+    if let Some(amount) = amount {
+        errors.check_amount("transactions.0.amount", amount);
+    }
+    let occurred_at = parse_date(&split.date, tz);
+    if occurred_at.is_none() {
+        errors.add(
+            "transactions.0.date",
+            "must be a date or an ISO 8601 timestamp",
+        );
+    }
+    errors.check_required(
+        "transactions.0.description",
+        &split.description,
+        MAX_DESCRIPTION_LEN,
+    );
+    if let Some(external_id) = &split.external_id {
+        errors.check_length(
+            "transactions.0.external_id",
+            external_id,
+            MAX_EXTERNAL_ID_LEN,
+        );
+    }
+
+    // The asset side is the source of a withdrawal and the destination of a deposit
+    let (field, account_id) = match transaction_type {
+        Some(TransactionType::Income) => ("transactions.0.destination_id", &split.destination_id),
+        _ => ("transactions.0.source_id", &split.source_id),
+    };
+    let bank_account_id = match account_id.as_deref() {
+        None | Some(CASH_ACCOUNT_ID) => None,
+        Some(id) => {
+            let found = Uuid::parse_str(id)
+                .ok()
+                .filter(|id| bank_accounts.iter().any(|a| a.id == *id));
+            if found.is_none() {
+                errors.add(field, "must be the id of one of your asset accounts");
+            }
+            found
+        }
+    };
+
+    let (Some(transaction_type), Some(amount), Some(occurred_at)) =
+        (transaction_type, amount, occurred_at)
+    else {
+        return Err(errors);
+    };
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let category = split.category_name.as_deref().map(|name| {
+        TransactionCategory::from_str(name)
+            .ok()
+            .or_else(|| importers::guess_category(name))
+            .unwrap_or(TransactionCategory::Other)
+    });
+    Ok(TransactionCreate::new(
+        user_id,
+        transaction_type,
+        amount,
+        category,
+        Some(split.description.trim().to_string()),
+    )
+    .with_origin(TransactionSource::Manual, split.external_id.clone())
+    .occurred_at(Some(occurred_at))
+    .bank_account(bank_account_id))
+}
+
+/// A plain date is the start of that day in the user's time zone
+fn parse_date(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| transaction_models::local_midnight(date, tz))
+        })
+}
+
+/// GET /api/v1/categories
+async fn get_categories() -> Json<Value> {
+    let data: Vec<Value> = TransactionCategory::ALL
+        .iter()
+        .map(|category| {
+            json!({
+                "type": "categories",
+                "id": category.to_string(),
+                "attributes": { "name": category.to_string(), "notes": null }
+            })
+        })
+        .collect();
+    let total = data.len();
+    Json(json!({
+        "data": data,
+        "meta": { "pagination": pagination(total, total, total, 1) }
+    }))
+}
+
+/// GET /api/v1/currencies
+async fn get_currencies() -> Json<Value> {
+    let data: Vec<Value> = Currency::ALL
+        .iter()
+        .map(|c| currency_resource(*c))
+        .collect();
+    let total = data.len();
+    Json(json!({
+        "data": data,
+        "meta": { "pagination": pagination(total, total, total, 1) }
+    }))
+}
+
+/// GET /api/v1/currencies/default - the currency amounts default to
+async fn get_default_currency() -> Json<Value> {
+    Json(json!({ "data": currency_resource(Currency::default()) }))
+}
+
+fn currency_resource(currency: Currency) -> Value {
+    json!({
+        "type": "currencies",
+        "id": currency.to_string(),
+        "attributes": {
+            "code": currency.to_string(),
+            "name": currency.to_string(),
+            "symbol": currency.to_string(),
+            "decimal_places": currency.minor_unit_digits(),
+            "enabled": true,
+            "default": currency == Currency::default()
+        }
+    })
+}
+
+async fn user_tz(api: &FireflyApi, user_id: Uuid) -> Result<Tz, StatusCode> {
+    user_queries::get_user_timezone(&api.state.db, user_id)
+        .await
+        .map_err(|e| internal_error("loading the time zone", e))
+}
+
+/// The user's transactions, newest first
+async fn load_transactions(
+    api: &FireflyApi,
+    user_id: Uuid,
+    transaction_type: Option<TransactionType>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<TransactionQuery>, StatusCode> {
+    let filter = TransactionFilter::for_user(user_id)
+        .transaction_types(transaction_type)
+        .period(start, end)
+        .sort(TransactionSort::CreatedAtDesc);
+    transaction_queries::get_transactions(&api.state.db, &filter)
+        .await
+        .map_err(|e| internal_error("fetching transactions", e))
+}
+
+/// Asset account names by bank account id
+async fn account_names(
+    api: &FireflyApi,
+    user_id: Uuid,
+) -> Result<HashMap<Uuid, String>, StatusCode> {
+    let accounts = bank_account_queries::get_bank_accounts(&api.state.db, user_id)
+        .await
+        .map_err(|e| internal_error("fetching bank accounts", e))?;
+    Ok(accounts.iter().map(|a| (a.id, account_name(a))).collect())
+}
+
+/// A transaction as a Firefly III transaction group with a single split
+fn transaction_resource(t: &TransactionQuery, accounts: &HashMap<Uuid, String>, tz: Tz) -> Value {
+    let (asset_id, asset_name) = match t.bank_account_id {
+        Some(id) => (
+            id.to_string(),
+            accounts.get(&id).cloned().unwrap_or_default(),
+        ),
+        None => (CASH_ACCOUNT_ID.to_string(), "Wallet".to_string()),
+    };
+    // The other side is an expense or revenue account named after the counterparty
+    let counterparty = if t.description.is_empty() {
+        "(no name)".to_string()
+    } else {
+        t.description.clone()
+    };
+    let (kind, source_id, source_name, destination_id, destination_name) = match t.transaction_type
+    {
+        TransactionType::Expense => ("withdrawal", Some(asset_id), asset_name, None, counterparty),
+        TransactionType::Income => ("deposit", None, counterparty, Some(asset_id), asset_name),
+    };
+    json!({
+        "type": "transactions",
+        "id": t.id.to_string(),
+        "attributes": {
+            "created_at": t.created_at,
+            "updated_at": t.last_updated_at,
+            "user": t.user_id.to_string(),
+            "group_title": null,
+            "transactions": [{
+                "transaction_journal_id": t.id.to_string(),
+                "type": kind,
+                "date": t.created_at.with_timezone(&tz).to_rfc3339(),
+                "amount": t.amount.abs().to_decimal().to_string(),
+                "currency_code": t.amount.currency.to_string(),
+                "currency_decimal_places": t.amount.currency.minor_unit_digits(),
+                "description": t.description,
+                "category_id": t.category.to_string(),
+                "category_name": t.category.to_string(),
+                "source_id": source_id,
+                "source_name": source_name,
+                "destination_id": destination_id,
+                "destination_name": destination_name,
+                "external_id": t.external_id,
+                "notes": null,
+                "tags": []
+            }]
+        }
+    })
+}
+
+fn pagination(total: usize, count: usize, per_page: usize, current_page: usize) -> Value {
+    json!({
+        "total": total,
+        "count": count,
+        "per_page": per_page,
+        "current_page": current_page,
+        "total_pages": total.div_ceil(per_page).max(1)
+    })
+}
@@ -0,0 +1,166 @@
+// Exchange rates and the FX revaluation report
+//
+// With FX_RATES_URL set, "fx.rates" runs every FX_RATES_INTERVAL_SECS and fetches the daily
+// rates published since the last stored day (or since the oldest transaction) from a
+// Frankfurter compatible API: GET <FX_RATES_URL>/<start>..<end>?from=<FX_RATES_BASE>&to=...,
+// answering {"rates": {"2024-01-02": {"USD": 1.0956, ...}, ...}}. Rates are stored against
+// that reference currency, so any two currencies convert through it; days without rates,
+// weekends and holidays, use the last rate before them.
+//
+// The revaluation report values a user's balance in each foreign currency twice in their
+// base currency: at the rate of each transaction's day, as it was booked, and at the latest
+// rate. The difference is the gain or loss from exchange-rate movements since.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::models::fx_models::{BookedBalance, CurrencyRevaluation};
+use crate::models::money_models::{Currency, Money};
+use crate::queries::fx_queries;
+use axum::async_trait;
+use chrono::{Days, NaiveDate, Utc};
+use reqwest::Url;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Longest a fetch of rates may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Days of rates fetched on the first run when there are no transactions yet
+const INITIAL_HISTORY_DAYS: u64 = 365;
+
+/// Answer of the exchange-rate API
+#[derive(Debug, Deserialize)]
+struct RatesResponse {
+    /// Units of each currency per unit of the requested base, by day
+    rates: BTreeMap<NaiveDate, BTreeMap<String, Decimal>>,
+}
+
+/// Job fetching the rates published since the last run
+pub struct FetchRatesJob {
+    pub endpoint: Url,
+    /// Reference currency rates are quoted against
+    pub base: Currency,
+    pub client: reqwest::Client,
+}
+
+impl FetchRatesJob {
+    async fn fetch(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> anyhow::Result<BTreeMap<NaiveDate, BTreeMap<String, Decimal>>> {
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("FX_RATES_URL cannot be a base URL"))?
+            .pop_if_empty()
+            .push(&format!("{}..{}", start, end));
+        let symbols: Vec<String> = Currency::ALL
+            .iter()
+            .filter(|currency| **currency != self.base)
+            .map(ToString::to_string)
+            .collect();
+
+        let response = self
+            .client
+            .get(url)
+            .query(&[("from", self.base.to_string()), ("to", symbols.join(","))])
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Exchange rate fetch failed with {}",
+                response.status()
+            ));
+        }
+        let found: RatesResponse = response.json().await?;
+        Ok(found.rates)
+    }
+}
+
+#[async_trait]
+impl JobHandler for FetchRatesJob {
+    fn kind(&self) -> &'static str {
+        "fx.rates"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let today = Utc::now().date_naive();
+        let start = match fx_queries::get_latest_rate_date(db).await? {
+            Some(latest) => latest + Days::new(1),
+            None => fx_queries::get_earliest_transaction_date(db)
+                .await?
+                .unwrap_or(today - Days::new(INITIAL_HISTORY_DAYS)),
+        };
+        if start > today {
+            return Ok(());
+        }
+
+        let mut stored = 0;
+        for (date, quotes) in self.fetch(start, today).await? {
+            let mut rates = BTreeMap::from([(self.base, Decimal::ONE)]);
+            for (code, rate) in quotes {
+                // Currencies amounts cannot be in are of no use
+                if let Ok(currency) = Currency::from_str(&code)
+                    && rate > Decimal::ZERO
+                {
+                    rates.insert(currency, rate);
+                }
+            }
+            fx_queries::upsert_rates(db, date, &rates).await?;
+            stored += 1;
+        }
+        if stored > 0 {
+            tracing::info!(days = stored, "Exchange rates stored");
+        }
+        Ok(())
+    }
+}
+
+/// Units of `base` per unit of `currency` from rates against a common reference currency
+pub fn cross_rate(
+    rates: &BTreeMap<Currency, (NaiveDate, Decimal)>,
+    currency: Currency,
+    base: Currency,
+) -> Option<Decimal> {
+    let (_, rate) = rates.get(&currency)?;
+    let (_, base_rate) = rates.get(&base)?;
+    base_rate
+        .checked_div(*rate)
+        .map(|rate| rate.round_dp(10).normalize())
+}
+
+/// Revalue the booked balances at the latest rates, currencies without a rate are listed
+/// without a current value
+pub fn revalue(
+    balances: Vec<BookedBalance>,
+    base: Currency,
+    latest: &BTreeMap<Currency, (NaiveDate, Decimal)>,
+) -> Result<Vec<CurrencyRevaluation>, String> {
+    balances
+        .into_iter()
+        .map(|booked| {
+            let rate = cross_rate(latest, booked.currency, base);
+            let current_value = rate
+                .map(|rate| Money::from_decimal_rounded(booked.priced_balance * rate, base))
+                .transpose()?;
+            let booked_value = Money::from_decimal_rounded(booked.booked_value, base)?;
+            Ok(CurrencyRevaluation {
+                currency: booked.currency,
+                transactions: booked.transactions,
+                unpriced_transactions: booked.unpriced_transactions,
+                balance: Money::from_decimal_rounded(booked.balance, booked.currency)?,
+                booked_value,
+                rate,
+                current_value,
+                gain: current_value.map(|current| {
+                    Money::new(current.minor_units - booked_value.minor_units, base)
+                }),
+            })
+        })
+        .collect()
+}
@@ -1,38 +1,426 @@
+use crate::attachments;
+use crate::auth;
 use crate::database::DbPool;
+use crate::dedup;
+use crate::events;
+use crate::exporters;
+use crate::fx;
+use crate::importers;
+use crate::jobs;
+use crate::models::account_models;
+use crate::models::attachment_models;
+use crate::models::audit_models;
+use crate::models::auth_models;
+use crate::models::bank_account_models;
+use crate::models::budget_models;
+use crate::models::family_models;
+use crate::models::fx_models;
+use crate::models::import_models;
+use crate::models::invitation_models;
+use crate::models::job_models;
+use crate::models::limit_models;
+use crate::models::money_models::{Money, MoneyTotals};
+use crate::models::notification_models;
+use crate::models::pending_models;
+use crate::models::reconciliation_models;
+use crate::models::reward_models;
+use crate::models::savings_models;
+use crate::models::share_link_models;
+use crate::models::sharing_models;
+use crate::models::suggestion_models;
+use crate::models::sync_models;
 use crate::models::transaction_models;
 use crate::models::user_models;
+use crate::models::webhook_models;
+use crate::multipart;
+use crate::problems;
+use crate::queries::account_queries;
+use crate::queries::attachment_queries;
+use crate::queries::audit_queries;
+use crate::queries::bank_account_queries;
+use crate::queries::budget_queries;
+use crate::queries::family_queries;
+use crate::queries::fx_queries;
+use crate::queries::import_queries;
+use crate::queries::invitation_queries;
+use crate::queries::limit_queries;
+use crate::queries::notification_queries;
+use crate::queries::password_setup_queries;
+use crate::queries::pending_queries;
+use crate::queries::reconciliation_queries;
+use crate::queries::reward_queries;
+use crate::queries::savings_queries;
+use crate::queries::share_link_queries;
+use crate::queries::sharing_queries;
+use crate::queries::suggestion_queries;
+use crate::queries::sync_queries;
 use crate::queries::transaction_queries;
 use crate::queries::user_queries;
+use crate::queries::webhook_queries;
+use crate::quotas;
+use crate::redact;
+use crate::request_tx;
+use crate::restrictions;
+use crate::rewards;
+use crate::scanning;
+use crate::storage::{self, BlobStore};
+use crate::throttle;
+use crate::validation::{MAX_BATCH_SIZE, ValidJson, ValidationErrors};
+use crate::webhooks;
+use chrono::Datelike;
+use futures_util::{Stream, stream};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
 use serde_json::{Value, json};
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    body::Body,
+    extract::{ConnectInfo, Extension, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Json, Response},
 };
-/// Create a new user endpoint
-/// Accepts a JSON body with email, name, and password
-/// Returns the created user's name on success
+/// Application state shared across all req handlers
+/// This allows handlers to access the database pool without global variables
 #[derive(Clone)]
 pub struct AppState {
     pub db: DbPool,
+    /// Where attachments and exports are stored
+    pub blobs: Arc<dyn BlobStore>,
+    /// Malware scanner of uploads, None when scanning is disabled
+    pub scanner: Option<Arc<scanning::Scanner>>,
+    /// Category of imported transactions that come without one
+    pub default_category: transaction_models::TransactionCategory,
+    /// Whether anyone may sign up, only invited users otherwise
+    pub public_signup: bool,
+}
+
+/// Append an entry to the audit log
+/// A failure is logged but does not fail the request, the change itself already happened
+pub async fn record_audit(state: &AppState, entry: audit_models::AuditEntryCreate) {
+    if let Err(e) = audit_queries::append(&state.db, &entry).await {
+        eprintln!(
+            "Error recording audit entry {} {}: {}",
+            entry.action, entry.entity_type, e
+        );
+    }
+}
+
+/// The user of an email, a 404 when there is none
+pub async fn require_user(
+    state: &AppState,
+    email: &str,
+) -> Result<user_models::UserQuery, StatusCode> {
+    user_queries::get_user(&state.db, email)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching user '{}': {}", redact::email(email), e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Count a write recording up to `transactions` transactions against the user's quotas
+/// Returns the 402/429 response to send instead when a quota would be exceeded
+pub async fn reserve_quota(
+    state: &AppState,
+    user_id: Uuid,
+    transactions: i64,
+) -> Result<Option<Response>, StatusCode> {
+    let exceeded = quotas::reserve_write(&state.db, user_id, transactions)
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking quotas: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(exceeded.map(IntoResponse::into_response))
 }
+
+/// Create a new user endpoint
+/// Accepts a JSON body with email, name, and password
+/// Returns the created user's name on success
 pub async fn create_user_handler(
     State(state): State<AppState>,
-    Json(req): Json<user_models::CreateUserRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    // Create a User instance from the req
-    let user = user_models::UserCreate::new(req.email, req.name, req.password);
+    ValidJson(req): ValidJson<user_models::CreateUserRequest>,
+) -> Result<Response, StatusCode> {
+    // With public signup off, only invited users may sign up
+    let invitation = match &req.invite_token {
+        Some(token) => {
+            let invitation =
+                invitation_queries::get_by_hash(&state.db, &auth::hash_token(token.trim()))
+                    .await
+                    .map_err(|e| {
+                        eprintln!("Error fetching invitation: {}", e);
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    })?
+                    .filter(|invitation| invitation.is_open(chrono::Utc::now()));
+            let mut errors = ValidationErrors::default();
+            match &invitation {
+                None => errors.add("invite_token", "is unknown, used or expired"),
+                Some(invitation)
+                    if invitation.email.as_ref().is_some_and(|email| {
+                        user_models::normalize_email(email)
+                            != user_models::normalize_email(&req.email)
+                    }) =>
+                {
+                    errors.add("email", "is not the email the invitation is for")
+                }
+                Some(_) => {}
+            }
+            if !errors.is_empty() {
+                return Ok(errors.into_response());
+            }
+            invitation
+        }
+        None if !state.public_signup => {
+            return Ok((
+                StatusCode::FORBIDDEN,
+                Json(json!({
+                    "message": "Signing up needs an invitation",
+                    "code": problems::ErrorCode::InvitationRequired
+                })),
+            )
+                .into_response());
+        }
+        None => None,
+    };
+
+    // The password is hashed here, it goes no further in plaintext
+    let password_hash = auth::hash_password(&req.password).map_err(|e| {
+        eprintln!("Error hashing password: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let user =
+        user_models::UserCreate::new(req.email, req.name, password_hash).timezone(req.timezone);
 
     // Insert the user into the database
-    let name = user_queries::create_user(&state.db, &user)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let user_id = match &invitation {
+        Some(invitation) => invitation_queries::redeem(&state.db, invitation.id, &user)
+            .await
+            .map_err(|e| {
+                eprintln!("Error signing up with invitation {}: {}", invitation.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            // Used by someone else in the meantime
+            .ok_or(StatusCode::CONFLICT)?,
+        None => user_queries::create_user(&state.db, &user)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+
+    let mut entry =
+        audit_models::AuditEntryCreate::new("create", "user", Some(user_id)).actor(user_id);
+    if let Some(invitation) = &invitation {
+        entry = entry.details(json!({
+            "invitation_id": invitation.id,
+            "wallet_owner_id": invitation.wallet_owner_id,
+            "role": invitation.role,
+        }));
+    }
+    record_audit(&state, entry).await;
 
     Ok(Json(json!({
         "message": "User created successfully",
-        "name": name
+        "name": user.name
+    }))
+    .into_response())
+}
+
+/// Record an invitation, returning it with its token, which is not stored and only known
+/// to the caller from here on
+pub async fn create_invitation(
+    state: &AppState,
+    email: Option<String>,
+    wallet: Option<(Uuid, sharing_models::WalletRole)>,
+    created_by: Option<Uuid>,
+    expires_in_days: Option<i64>,
+) -> Result<(String, invitation_models::InvitationQuery), StatusCode> {
+    let days = expires_in_days.unwrap_or(invitation_models::DEFAULT_INVITATION_DAYS);
+    let (token, token_hash) = auth::new_token();
+    let create = invitation_models::InvitationCreate {
+        token_hash,
+        email: email.map(|email| email.trim().to_string()),
+        wallet,
+        created_by,
+        expires_at: chrono::Utc::now() + chrono::Duration::days(days),
+    };
+    let invitation = invitation_queries::create(&state.db, &create)
+        .await
+        .map_err(|e| {
+            eprintln!("Error creating invitation: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut entry =
+        audit_models::AuditEntryCreate::new("create", "invitation", Some(invitation.id)).details(
+            json!({
+                "wallet_owner_id": invitation.wallet_owner_id,
+                "role": invitation.role,
+                "expires_at": invitation.expires_at,
+            }),
+        );
+    if let Some(created_by) = created_by {
+        entry = entry.actor(created_by);
+    }
+    record_audit(state, entry).await;
+
+    Ok((token, invitation))
+}
+
+/// Calls of the email availability check allowed per client address and minute
+const AVAILABILITY_CHECKS_PER_MINUTE: u32 = 10;
+
+/// Shortest time the availability check takes to answer, so taken and free emails
+/// cannot be told apart by how fast the answer comes
+const AVAILABILITY_MIN_DURATION: Duration = Duration::from_millis(250);
+
+static AVAILABILITY_THROTTLE: LazyLock<throttle::Throttle> = LazyLock::new(|| {
+    throttle::Throttle::new(AVAILABILITY_CHECKS_PER_MINUTE, Duration::from_secs(60))
+});
+
+/// Whether an email is still free to sign up with, for signup forms to check inline
+/// Needs no token: it answers nothing but a boolean, throttled per client address
+pub async fn get_email_availability_handler(
+    client: Option<ConnectInfo<SocketAddr>>,
+    State(state): State<AppState>,
+    Query(params): Query<user_models::EmailAvailabilityParameters>,
+) -> Result<Response, StatusCode> {
+    let started = tokio::time::Instant::now();
+    if let Some(throttled) = AVAILABILITY_THROTTLE.check(client.map(|c| c.0.ip())) {
+        return Ok(throttled);
+    }
+    let mut errors = ValidationErrors::default();
+    errors.check_email("email", &params.email);
+    if !errors.is_empty() {
+        return Ok(errors.into_response());
+    }
+
+    let taken = user_queries::email_exists(&state.db, &params.email)
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking email availability: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    tokio::time::sleep_until(started + AVAILABILITY_MIN_DURATION).await;
+
+    Ok(Json(json!({
+        "message": "Email availability checked successfully",
+        "available": !taken
+    }))
+    .into_response())
+}
+
+/// An invited user chooses their password with the token of the invitation link
+/// Needs no access token, the setup token is used up by it
+pub async fn password_setup_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<user_models::PasswordSetupRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let password_hash = auth::hash_password(&req.password).map_err(|e| {
+        eprintln!("Error hashing password: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let token_hash = auth::hash_token(req.token.trim());
+    let user_id = password_setup_queries::set_password(&state.db, &token_hash, &password_hash)
+        .await
+        .map_err(|e| {
+            eprintln!("Error setting up password: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        // Unknown, used or expired
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "user", Some(user_id))
+            .actor(user_id)
+            .details(json!({ "password": "set up" })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Password set up successfully"
+    })))
+}
+
+/// The user's plan and their usage of each quota, with where it stands against the limits
+/// ("ok", "warning" from 80% of a limit on, "exceeded" at the limit)
+pub async fn get_usage_handler(
+    State(state): State<AppState>,
+    Query(params): Query<user_models::UsageParameters>,
+    auth_user: Option<Extension<auth_models::AuthUser>>,
+) -> Result<Json<Value>, StatusCode> {
+    let user_id = match (auth_user, params.user_email) {
+        (Some(Extension(user)), _) => user.id,
+        (None, Some(email)) => require_user(&state, &email).await?.id,
+        (None, None) => return Err(StatusCode::BAD_REQUEST),
+    };
+    let (plan, usage) = quotas::usage(&state.db, user_id).await.map_err(|e| {
+        eprintln!("Error fetching usage: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(json!({
+        "message": "Usage retrieved successfully",
+        "enforced": quotas::plans().is_some(),
+        "plan": plan.name,
+        "usage": usage
+    })))
+}
+
+/// Create-or-update a user by email
+/// Used for provisioning from an external identity system; the password is
+/// only changed when one is provided
+pub async fn upsert_user_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<user_models::UpsertUserRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let password_hash = req
+        .password
+        .as_deref()
+        .map(auth::hash_password)
+        .transpose()
+        .map_err(|e| {
+            eprintln!("Error hashing password: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let user = user_models::UserUpsert {
+        email: req.email,
+        name: req.name,
+        password_hash,
+        timezone: req.timezone,
+    };
+
+    let (user_id, created) = user_queries::upsert_user(&state.db, &user)
+        .await
+        .map_err(|e| {
+            eprintln!(
+                "Error upserting user '{}': {}",
+                redact::email(&user.email),
+                e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let action = if created { "create" } else { "update" };
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new(action, "user", Some(user_id)).actor(user_id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": if created { "User created successfully" } else { "User updated successfully" },
+        "id": user_id,
+        "name": user.name,
+        "created": created
     })))
 }
 
@@ -45,14 +433,9 @@ pub async fn get_user_handler(
 ) -> Result<Json<Value>, StatusCode> {
     // Axum's Path extractor automatically URL-decodes the parameter
     // So "John%20Doe" becomes "John Doe"
-    eprintln!("Looking for user with email: '{}'", email);
+    eprintln!("Looking for user with email: '{}'", redact::email(&email));
 
-    let user = user_queries::get_user(&state.db, &email)
-        .await
-        .map_err(|e| {
-            eprintln!("Error fetching user '{}': {}", email, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    let user = require_user(&state, &email).await?;
 
     Ok(Json(json!({
         "message": "User retrieved successfully",
@@ -65,170 +448,4213 @@ pub async fn get_user_handler(
     })))
 }
 
-pub async fn get_users_handler(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
-    // Axum's Path extractor automatically URL-decodes the parameter
-    // So "John%20Doe" becomes "John Doe"
-    eprintln!("Fetching all users");
+/// A page of users in sign-up order, with the cursor of the next page (null on the last one)
+pub async fn get_users_handler(
+    State(state): State<AppState>,
+    Query(params): Query<user_models::UserListParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let after = params
+        .after
+        .as_deref()
+        .map(user_models::UserCursor::from_str)
+        .transpose()
+        .map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+
+    // One extra row tells whether there is another page
+    let mut users = user_queries::get_users_page(&state.db, after, limit + 1)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching users: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let has_more = users.len() > limit as usize;
+    users.truncate(limit as usize);
+    let next_cursor = users
+        .last()
+        .filter(|_| has_more)
+        .map(|user| user_models::UserCursor::after(user).to_string());
+
+    Ok(Json(json!({
+        "message": "Users retrieved successfully",
+        "users": users,
+        "next_cursor": next_cursor
+    })))
+}
+
+/// Fetch the user's wrapped encryption key, for a new device to unwrap with the user's secret
+pub async fn get_encryption_key_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let key = user_queries::get_encryption_key(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching encryption key: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Encryption key retrieved successfully",
+        "encryption_key": key
+    })))
+}
 
-    let users = user_queries::get_all_users(&state.db).await.map_err(|e| {
-        eprintln!("Error fetching users: {}", e);
+/// Store (or replace) the user's wrapped encryption key
+/// The server keeps the key exactly as sent and cannot unwrap it
+pub async fn put_encryption_key_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<user_models::PutEncryptionKeyRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let kdf_params = req.kdf_params.unwrap_or_else(|| json!({}));
+    user_queries::set_encryption_key(
+        &state.db,
+        user.id,
+        &req.wrapped_key,
+        &req.algorithm,
+        &kdf_params,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error storing encryption key: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "encryption_key", Some(user.id))
+            .actor(user.id)
+            .details(json!({ "algorithm": req.algorithm })),
+    )
+    .await;
+
     Ok(Json(json!({
-        "message": "Users retrieved successfully",
-        "users": users
+        "message": "Encryption key stored successfully"
     })))
 }
 
+/// Record a transaction of the user
+/// With `dry_run` every check is made and nothing is stored: the answer tells whether the
+/// transaction would be created or held for approval, the transactions it may duplicate
+/// and what it would do to this month's budgets. Refusals are answered as they would be
 pub async fn create_transaction_handler(
     State(state): State<AppState>,
-    Json(req): Json<transaction_models::CreateTransactionRequest>,
-) -> Result<Json<Value>, StatusCode> {
+    Query(params): Query<transaction_models::DryRunParameters>,
+    ValidJson(req): ValidJson<transaction_models::CreateTransactionRequest>,
+) -> Result<Response, StatusCode> {
     eprintln!("Received transaction request: {:?}", req);
 
-    // Validate and convert transaction type
-    let transaction_type = transaction_models::TransactionType::from_str(&req.transaction_type)
-        .map_err(|e| {
-            eprintln!("Invalid transaction type: {} - {}", req.transaction_type, e);
-            StatusCode::BAD_REQUEST
-        })?;
-
-    // Validate and convert category (default to Other if not provided)
-    let category = match req.category {
-        Some(cat_str) => Some(
-            transaction_models::TransactionCategory::from_str(&cat_str).map_err(|e| {
-                eprintln!("Invalid category: {} - {}", cat_str, e);
+    // Validate and convert source (default to Manual if not provided)
+    let source = match req.source {
+        Some(source_str) => {
+            transaction_models::TransactionSource::from_str(&source_str).map_err(|e| {
+                eprintln!("Invalid source: {} - {}", source_str, e);
                 StatusCode::BAD_REQUEST
-            })?,
-        ),
-        None => None,
+            })?
+        }
+        None => transaction_models::TransactionSource::Manual,
     };
 
     eprintln!(
-        "Parsed transaction_type: {:?}, category: {:?}",
-        transaction_type, category
+        "Parsed transaction_type: {:?}, category: {:?}, source: {:?}",
+        req.transaction_type, req.category, source
     );
 
     // Get user
-    let user = user_queries::get_user(&state.db, &req.user_email)
-        .await
-        .map_err(|e| {
-            eprintln!("Error fetching user '{}': {}", req.user_email, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    let user = require_user(&state, &req.user_email).await?;
+
+    // Editors of a shared wallet record transactions in the owner's wallet
+    let editor = match req.created_by_email.as_deref() {
+        Some(email) if email != req.user_email => {
+            Some(wallet_editor(&state, user.id, email).await?)
+        }
+        _ => None,
+    };
 
     // Create transaction with validated enums
     let transaction = transaction_models::TransactionCreate::new(
         user.id,
-        transaction_type,
+        req.transaction_type,
         req.amount,
-        category,
+        req.category,
         req.description,
-    );
+    )
+    .encrypted_description(req.encrypted_description)
+    .with_origin(source, req.external_id)
+    .account(req.account_id);
+
+    let checked = check_restrictions(
+        &state,
+        std::slice::from_ref(&transaction),
+        req.confirm_over_limit,
+    )
+    .await?;
+    if let Some(refusal) = checked.refusal() {
+        return Ok(refusal.into_response());
+    }
+
+    let mut requires_approval = false;
+    if let Some(editor_id) = editor {
+        let policy = sharing_queries::get_policy(&state.db, user.id)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching approval policy: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        requires_approval = policy.is_some_and(|p| p.requires_approval(transaction.amount));
+        if requires_approval && !params.dry_run {
+            return hold_for_approval(&state, editor_id, &transaction).await;
+        }
+    }
+
+    if params.dry_run {
+        let candidates = [transaction];
+        let (_, duplicates) = dedup::find_duplicates(&state.db, user.id, source, &candidates)
+            .await
+            .map_err(|e| {
+                eprintln!("Error checking for duplicates: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        // Only a repeated external id keeps the transaction from being created, it is
+        // recorded next to look-alikes
+        let would_create = !duplicates
+            .iter()
+            .any(|d| matches!(d.reason, dedup::DuplicateReason::ExternalId));
+        let created = if would_create { &candidates[..] } else { &[] };
+        let budget_impact = budget_impact(&state, user.id, created).await?;
+        return Ok(Json(json!({
+            "message": "Transaction checked, nothing was stored",
+            "dry_run": true,
+            "would_create": would_create,
+            "requires_approval": requires_approval,
+            "duplicates": duplicates,
+            "budget_impact": budget_impact
+        }))
+        .into_response());
+    }
+
+    if let Some(exceeded) = reserve_quota(&state, user.id, 1).await? {
+        return Ok(exceeded);
+    }
+    let transaction_id = transaction_queries::create_transaction(&state.db, &transaction).await;
+    if !matches!(transaction_id, Ok(Some(_))) {
+        quotas::release_transactions(&state.db, user.id, 1).await;
+    }
+    let transaction_id = transaction_id.map_err(|e| {
+        eprintln!("Error creating transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if let Some(transaction_id) = transaction_id {
+        restrictions::record_overrides(
+            &state.db,
+            editor.unwrap_or(user.id),
+            std::slice::from_ref(&transaction),
+            &checked,
+        )
+        .await;
+        record_audit(
+            &state,
+            audit_models::AuditEntryCreate::new("create", "transaction", Some(transaction_id))
+                .actor(editor.unwrap_or(user.id))
+                .details(json!({
+                    "transaction_type": transaction.transaction_type,
+                    "amount": transaction.amount,
+                    "category": transaction.category,
+                    "source": transaction.source,
+                })),
+        )
+        .await;
+    }
+
+    Ok(Json(json!({
+        "message": "Transaction created successfully"
+    }))
+    .into_response())
+}
+
+/// Check new transactions of a user against the restrictions on what they may record
+async fn check_restrictions(
+    state: &AppState,
+    transactions: &[transaction_models::TransactionCreate],
+    confirm_over_limit: bool,
+) -> Result<restrictions::Checked, StatusCode> {
+    restrictions::check(&state.db, transactions, confirm_over_limit)
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking restrictions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
 
-    transaction_queries::create_transaction(&state.db, &transaction)
+/// What recording the transactions would do to the user's budgets this month, for dry runs
+/// Only expenses of this month in the user's time zone count, as in GET /api/budgets/status
+async fn budget_impact(
+    state: &AppState,
+    user_id: Uuid,
+    transactions: &[transaction_models::TransactionCreate],
+) -> Result<Vec<budget_models::BudgetImpact>, StatusCode> {
+    let tz = user_queries::get_user_timezone(&state.db, user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching timezone of user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let now = chrono::Utc::now();
+    let (first_day, last_day) =
+        limit_models::LimitPeriod::Month.days(now.with_timezone(&tz).date_naive());
+    let start = transaction_models::local_midnight(first_day, tz);
+    let end = transaction_models::local_midnight(last_day + chrono::Duration::days(1), tz);
+    let budgets = budget_queries::get_budgets_spent(&state.db, user_id, start, end)
         .await
         .map_err(|e| {
-            eprintln!("Error creating transaction: {}", e);
+            eprintln!("Error fetching budget spending: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    Ok(Json(json!({
-        "message": "Transaction created successfully"
-    })))
+    let this_month: Vec<_> = transactions
+        .iter()
+        .filter(|t| {
+            let at = t.occurred_at.unwrap_or(now);
+            at >= start && at < end
+        })
+        .collect();
+    Ok(budget_models::BudgetImpact::of(&budgets, &this_month))
 }
 
-pub async fn get_transactions_handler(
+/// Set a hard cap on a user's expenses, replacing the one of the same category, period and currency
+pub async fn put_spending_limit_handler(
     State(state): State<AppState>,
-    where_clause_params: Query<transaction_models::TransactionGetParameters>,
+    ValidJson(req): ValidJson<limit_models::PutSpendingLimitRequest>,
 ) -> Result<Json<Value>, StatusCode> {
-    let transaction_get_params = where_clause_params.0;
-    let user_id = transaction_get_params.user_id;
-    let category = match transaction_get_params.category {
-        Some(strr) => match transaction_models::TransactionCategory::from_str(&strr) {
-            Ok(cat) => Some(cat),
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        },
-        None => None,
-    };
-    let transaction_type = match transaction_get_params.transaction_type {
-        Some(strr) => match transaction_models::TransactionType::from_str(&strr) {
-            Ok(trans) => Some(trans),
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        },
-        None => None,
-    };
-    let amount_min = transaction_get_params.amount_min;
-    let amount_max = transaction_get_params.amount_max;
-
-    let start_timestamp = transaction_get_params.start_timestamp;
-    let end_timestamp = transaction_get_params.end_timestamp;
-
-    let transactions = transaction_queries::get_transactions(
+    let user = require_user(&state, &req.user_email).await?;
+    let limit = limit_queries::upsert_limit(
         &state.db,
-        user_id,
-        category,
-        transaction_type,
-        amount_min,
-        amount_max,
-        start_timestamp,
-        end_timestamp,
+        user.id,
+        req.category.as_ref(),
+        req.period,
+        req.amount,
+        req.allow_override,
     )
     .await
     .map_err(|e| {
-        eprintln!("{}", e);
+        eprintln!("Error storing spending limit: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
-    println!("{transactions:?}");
-    return Ok(Json(json!({
-        "message": "Transactions retrieved successfully",
-        "users": transactions
-    })));
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "spending_limit", Some(limit.id))
+            .actor(user.id)
+            .details(json!({
+                "category": limit.category,
+                "period": limit.period,
+                "amount": limit.amount,
+                "allow_override": limit.allow_override,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Spending limit stored successfully",
+        "spending_limit": limit
+    })))
 }
 
-pub async fn get_amount_handler(
+pub async fn get_spending_limits_handler(
     State(state): State<AppState>,
-    where_clause_params: Query<transaction_models::TransactionGetParameters>,
+    Query(params): Query<limit_models::SpendingLimitGetParameters>,
 ) -> Result<Json<Value>, StatusCode> {
-    let transaction_get_params = where_clause_params.0;
-    let user_id = transaction_get_params.user_id;
-    if let None = user_id {
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let limits = limit_queries::get_limits(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching spending limits: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Spending limits retrieved successfully",
+        "spending_limits": limits
+    })))
+}
+
+/// Burn-down chart data of the user's spending limits: per limit (a category, or all
+/// expenses) what was spent each day of the period and in total so far, against the
+/// budget line of spending the cap evenly. The periods are those `date` is in, today's
+/// by default
+pub async fn get_spending_limits_burn_down_handler(
+    State(state): State<AppState>,
+    Query(params): Query<limit_models::BurnDownParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let tz = user_queries::get_user_timezone(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching timezone of user {}: {}", params.user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let limits = limit_queries::get_limits(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching spending limits: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let today = chrono::Utc::now().with_timezone(&tz).date_naive();
+    let day = params.date.unwrap_or(today);
+
+    let mut burn_downs = Vec::new();
+    for limit in limits
+        .iter()
+        .filter(|l| params.period.is_none_or(|period| l.period == period))
+    {
+        let (first_day, last_day) = limit.period.days(day);
+        let daily = limit_queries::get_daily_spent(
+            &state.db,
+            params.user_id,
+            limit.category.as_ref(),
+            limit.amount.currency,
+            first_day,
+            last_day,
+            tz,
+        )
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching daily spending of limit {}: {}", limit.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        burn_downs.push(limit_models::LimitBurnDown::new(limit, day, today, &daily));
     }
-    let category = match transaction_get_params.category {
-        Some(strr) => match transaction_models::TransactionCategory::from_str(&strr) {
-            Ok(cat) => Some(cat),
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        },
-        None => None,
-    };
-    let transaction_type = match transaction_get_params.transaction_type {
-        Some(strr) => match transaction_models::TransactionType::from_str(&strr) {
-            Ok(trans) => Some(trans),
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        },
-        None => None,
-    };
 
-    let start_timestamp = transaction_get_params.start_timestamp;
-    let end_timestamp = transaction_get_params.end_timestamp;
-    let money_sum = transaction_queries::get_user_transaction_sum(
-        &state.db,
-        user_id.unwrap(),
-        category,
-        transaction_type,
-        start_timestamp,
-        end_timestamp,
-    )
-    .await
-    .map_err(|e| {
-        eprintln!("{}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    Ok(Json(json!({
+        "message": "Spending limit burn-down retrieved successfully",
+        "date": day,
+        "burn_down": burn_downs
+    })))
+}
 
-    return Ok(Json(json!({
-        "message": "Transactions sum retrieved successfully",
-        "amount": money_sum
-    })));
+pub async fn delete_spending_limit_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let user_id = limit_queries::delete_limit(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting spending limit {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "spending_limit", Some(id)).actor(user_id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Spending limit deleted successfully"
+    })))
+}
+
+/// Set what a user plans to spend on a category each month, replacing the budget of the
+/// same category and currency
+pub async fn create_budget_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<budget_models::CreateBudgetRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &req.user_email).await?;
+    let budget = budget_queries::upsert_budget(&state.db, user.id, &req.category, req.amount)
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing budget: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "budget", Some(budget.id))
+            .actor(user.id)
+            .details(json!({
+                "category": budget.category,
+                "amount": budget.amount,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Budget stored successfully",
+        "budget": budget
+    })))
+}
+
+pub async fn get_budgets_handler(
+    State(state): State<AppState>,
+    Query(params): Query<budget_models::BudgetGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let budgets = budget_queries::get_budgets(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching budgets: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Budgets retrieved successfully",
+        "budgets": budgets
+    })))
+}
+
+/// Spent and remaining of each of the user's budgets over the month `date` is in, the
+/// current month in the user's time zone by default
+pub async fn get_budget_status_handler(
+    State(state): State<AppState>,
+    Query(params): Query<budget_models::BudgetStatusParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let tz = user_queries::get_user_timezone(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching timezone of user {}: {}", params.user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let day = params
+        .date
+        .unwrap_or_else(|| chrono::Utc::now().with_timezone(&tz).date_naive());
+    let (first_day, last_day) = limit_models::LimitPeriod::Month.days(day);
+    let budgets = budget_queries::get_budgets_spent(
+        &state.db,
+        params.user_id,
+        transaction_models::local_midnight(first_day, tz),
+        transaction_models::local_midnight(last_day + chrono::Duration::days(1), tz),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching budget spending: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let status: Vec<_> = budgets
+        .iter()
+        .map(|(budget, spent)| budget_models::BudgetStatus::new(budget, *spent))
+        .collect();
+
+    Ok(Json(json!({
+        "message": "Budget status retrieved successfully",
+        "start": first_day,
+        "end": last_day,
+        "budgets": status
+    })))
+}
+
+pub async fn delete_budget_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let user_id = budget_queries::delete_budget(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting budget {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "budget", Some(id)).actor(user_id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Budget deleted successfully"
+    })))
+}
+
+/// Alerts of the user's budgets crossing 80% and 100% of their amount, newest first
+pub async fn get_alerts_handler(
+    State(state): State<AppState>,
+    Query(params): Query<budget_models::AlertGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let alerts = budget_queries::get_alerts(&state.db, params.user_id, params.include_acknowledged)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching alerts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Alerts retrieved successfully",
+        "alerts": alerts
+    })))
+}
+
+/// Mark an alert as seen, it is no longer listed by default
+pub async fn acknowledge_alert_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let alert = budget_queries::acknowledge_alert(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error acknowledging alert {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Alert acknowledged successfully",
+        "alert": alert
+    })))
+}
+
+/// Import many transactions for a user in one request, all of them or none
+/// Candidates that duplicate existing transactions (same external id, or same
+/// amount with a close date and similar description) are skipped and reported
+/// With `dry_run` nothing is stored, the answer tells which would be and what they would do
+/// to this month's budgets
+pub async fn batch_create_transactions_handler(
+    State(state): State<AppState>,
+    mut tx: request_tx::Tx,
+    Query(params): Query<transaction_models::DryRunParameters>,
+    ValidJson(req): ValidJson<transaction_models::BatchTransactionRequest>,
+) -> Result<Response, StatusCode> {
+    // Batch imports default to the Import source so they never collide with manual entries
+    let source = match req.source {
+        Some(source_str) => {
+            transaction_models::TransactionSource::from_str(&source_str).map_err(|e| {
+                eprintln!("Invalid source: {} - {}", source_str, e);
+                StatusCode::BAD_REQUEST
+            })?
+        }
+        None => transaction_models::TransactionSource::Import,
+    };
+
+    let user = require_user(&state, &req.user_email).await?;
+
+    let mut candidates = Vec::with_capacity(req.transactions.len());
+    for item in req.transactions {
+        candidates.push(
+            transaction_models::TransactionCreate::new(
+                user.id,
+                item.transaction_type,
+                item.amount,
+                item.category
+                    .or_else(|| Some(state.default_category.clone())),
+                item.description,
+            )
+            .with_origin(source, item.external_id)
+            .occurred_at(item.occurred_at)
+            .bank_account(req.bank_account_id),
+        );
+    }
+
+    let (to_insert, skipped_duplicates) =
+        dedup::find_duplicates(&state.db, user.id, source, &candidates)
+            .await
+            .map_err(|e| {
+                eprintln!("Error checking for duplicates: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    // Duplicates already count against the spending limits, the others are checked together
+    let checked =
+        restrictions::check_rows(&state.db, &candidates, &to_insert, req.confirm_over_limit)
+            .await
+            .map_err(|e| {
+                eprintln!("Error checking restrictions: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    if let Some(refusal) = checked.refusal() {
+        return Ok(refusal.into_response());
+    }
+
+    if params.dry_run {
+        let inserted: Vec<_> = to_insert
+            .iter()
+            .map(|&row| candidates[row].clone())
+            .collect();
+        let budget_impact = budget_impact(&state, user.id, &inserted).await?;
+        return Ok(Json(json!({
+            "message": "Transactions checked, nothing was stored",
+            "dry_run": true,
+            "summary": {
+                "would_insert": to_insert.len(),
+                "skipped_duplicates": skipped_duplicates,
+            },
+            "budget_impact": budget_impact
+        }))
+        .into_response());
+    }
+
+    let reserved = candidates.len() as i64;
+    if let Some(exceeded) = reserve_quota(&state, user.id, reserved).await? {
+        return Ok(exceeded);
+    }
+    let summary =
+        dedup::import_transactions(&state.db, &mut tx, user.id, source, &candidates).await;
+    let inserted = summary.as_ref().map_or(0, |s| s.inserted as i64);
+    quotas::release_transactions(&state.db, user.id, reserved - inserted).await;
+    let summary = summary.map_err(|e| {
+        eprintln!("Error importing transactions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Recorded once the transactions are committed
+    for entry in restrictions::override_entries(user.id, &candidates, &checked) {
+        tx.audit_on_commit(entry);
+    }
+    tx.audit_on_commit(
+        audit_models::AuditEntryCreate::new("import", "transaction", None)
+            .actor(user.id)
+            .details(json!({
+                "source": source,
+                "inserted": summary.inserted,
+                "skipped_duplicates": summary.skipped_duplicates.len(),
+            })),
+    );
+    if summary.inserted > 0 {
+        tx.enqueue_on_commit(
+            user.id,
+            "transactions.imported",
+            json!({
+                "source": source,
+                "inserted": summary.inserted,
+            }),
+        );
+    }
+
+    Ok(Json(json!({
+        "message": "Transactions imported successfully",
+        "summary": summary
+    }))
+    .into_response())
+}
+
+/// Import a bank statement (OFX, QIF or CSV) for a user, within the request
+/// The body is multipart/form-data with the file in a "file" part. CSV files are read with
+/// the columns named in an optional "mapping" part before it, as JSON, see
+/// `importers::mapped::ColumnMapping`. Rows that cannot be read, or go over the user's
+/// spending limits, are reported by line and left out, the others are stored in one database
+/// transaction, all of them or none, and importing the same statement again skips them. With
+/// `dry_run` nothing is stored, the answer tells what would be
+pub async fn import_statement_handler(
+    State(state): State<AppState>,
+    mut tx: request_tx::Tx,
+    Query(params): Query<transaction_models::StatementImportParameters>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, StatusCode> {
+    let bad_request = |message: String| {
+        Ok((StatusCode::BAD_REQUEST, Json(json!({ "message": message }))).into_response())
+    };
+    let invalid = |field: &str, message: String| {
+        let mut errors = ValidationErrors::default();
+        errors.add(field, message);
+        Ok(errors.into_response())
+    };
+    let mut multipart = match multipart::Multipart::new(&headers, body) {
+        Ok(multipart) => multipart,
+        Err(e) => {
+            return Ok((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                Json(json!({ "message": e.to_string() })),
+            )
+                .into_response());
+        }
+    };
+
+    let format = match params
+        .format
+        .as_deref()
+        .map(importers::StatementFormat::from_str)
+    {
+        Some(Ok(format)) => Some(format),
+        Some(Err(e)) => return invalid("format", e),
+        None => None,
+    };
+
+    let mut mapping = importers::mapped::ColumnMapping::default();
+    let part = loop {
+        match multipart.next_part().await {
+            Ok(Some(part)) if part.name == "file" => break part,
+            Ok(Some(part)) if part.name == "mapping" => {
+                let mut json = Vec::new();
+                loop {
+                    match multipart.chunk().await {
+                        Ok(Some(chunk)) => json.extend_from_slice(&chunk),
+                        Ok(None) => break,
+                        Err(e) => return bad_request(e.to_string()),
+                    }
+                    if json.len() > importers::mapped::MAX_MAPPING_BYTES {
+                        return invalid("mapping", "is too large".to_string());
+                    }
+                }
+                mapping = match serde_json::from_slice(&json) {
+                    Ok(mapping) => mapping,
+                    Err(e) => return invalid("mapping", e.to_string()),
+                };
+            }
+            Ok(Some(_)) => continue,
+            Ok(None) => return bad_request("Missing \"file\" part".to_string()),
+            Err(e) => return bad_request(e.to_string()),
+        }
+    };
+    let format = format
+        .or_else(|| {
+            part.file_name
+                .as_deref()
+                .and_then(importers::StatementFormat::from_file_name)
+        })
+        .unwrap_or(importers::StatementFormat::Csv);
+    let file = match storage::read_to_end(multipart.into_stream(), importers::MAX_BODY_BYTES).await
+    {
+        Ok(file) => file,
+        Err(e) => return bad_request(e.to_string()),
+    };
+    let Ok(text) = String::from_utf8(file) else {
+        return bad_request("The file is not UTF-8 text".to_string());
+    };
+    // Stored in a single transaction, so as many rows as a batch
+    let mut parsed = match importers::parse_statement(
+        format,
+        &text,
+        &mapping,
+        params.currency,
+        MAX_BATCH_SIZE,
+    ) {
+        Ok(parsed) => parsed,
+        Err(reason) => return invalid("file", reason),
+    };
+
+    let user = require_user(&state, &params.user_email).await?;
+    let tz = user_queries::get_user_timezone(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching time zone: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let mut rejected = std::mem::take(&mut parsed.errors);
+    let (transfers, ignored) = (parsed.transfers, parsed.ignored);
+    let lines: Vec<u64> = parsed.rows.iter().map(|(line, _)| *line).collect();
+    let candidates = parsed.into_transactions(
+        format,
+        user.id,
+        tz,
+        params.bank_account_id,
+        &state.default_category,
+    );
+    let source = transaction_models::TransactionSource::Import;
+
+    let (to_insert, skipped_duplicates) =
+        dedup::find_duplicates(&state.db, user.id, source, &candidates)
+            .await
+            .map_err(|e| {
+                eprintln!("Error checking imported transactions: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    // Rows refused by the user's spending limits are rejected, the others imported
+    let checked = restrictions::check_rows(
+        &state.db,
+        &candidates,
+        &to_insert,
+        params.confirm_over_limit,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error checking restrictions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    rejected.extend(
+        checked
+            .refused
+            .iter()
+            .map(|r| importers::RowError::refused(r, &lines)),
+    );
+
+    if params.dry_run {
+        rejected.extend(
+            skipped_duplicates
+                .iter()
+                .map(|d| importers::RowError::duplicate(d, &lines)),
+        );
+        rejected.sort_by_key(|e| e.line);
+        return Ok(Json(json!({
+            "message": "Import checked, nothing was stored",
+            "dry_run": true,
+            "summary": {
+                "would_insert": to_insert.len() - checked.refused.len(),
+                "skipped_duplicates": skipped_duplicates.len(),
+                "skipped_transfers": transfers,
+                "ignored": ignored,
+            },
+            "rejected": rejected
+        }))
+        .into_response());
+    }
+
+    let (admitted, admitted_lines): (Vec<_>, Vec<_>) = candidates
+        .iter()
+        .cloned()
+        .zip(lines)
+        .enumerate()
+        .filter(|(row, _)| !checked.is_refused(*row))
+        .map(|(_, admitted)| admitted)
+        .unzip();
+    let reserved = admitted.len() as i64;
+    if let Some(exceeded) = reserve_quota(&state, user.id, reserved).await? {
+        return Ok(exceeded);
+    }
+    let summary = dedup::import_transactions(&state.db, &mut tx, user.id, source, &admitted).await;
+    let inserted = summary.as_ref().map_or(0, |s| s.inserted as i64);
+    quotas::release_transactions(&state.db, user.id, reserved - inserted).await;
+    let summary = summary.map_err(|e| {
+        eprintln!("Error importing transactions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    rejected.extend(
+        summary
+            .skipped_duplicates
+            .iter()
+            .map(|d| importers::RowError::duplicate(d, &admitted_lines)),
+    );
+    rejected.sort_by_key(|e| e.line);
+
+    // Recorded once the transactions are committed
+    for entry in restrictions::override_entries(user.id, &candidates, &checked) {
+        tx.audit_on_commit(entry);
+    }
+    tx.audit_on_commit(
+        audit_models::AuditEntryCreate::new("import", "transaction", None)
+            .actor(user.id)
+            .details(json!({
+                "source": source,
+                "format": format,
+                "inserted": summary.inserted,
+                "skipped_duplicates": summary.skipped_duplicates.len(),
+                "rejected": rejected.len(),
+            })),
+    );
+    if summary.inserted > 0 {
+        tx.enqueue_on_commit(
+            user.id,
+            "transactions.imported",
+            json!({
+                "source": source,
+                "format": format,
+                "inserted": summary.inserted,
+            }),
+        );
+    }
+
+    Ok(Json(json!({
+        "message": "Transactions imported successfully",
+        "dry_run": false,
+        "summary": {
+            "inserted": summary.inserted,
+            "skipped_duplicates": summary.skipped_duplicates.len(),
+            "skipped_transfers": transfers,
+            "ignored": ignored,
+        },
+        "rejected": rejected
+    }))
+    .into_response())
+}
+
+/// Import the CSV export of another budgeting app (`ynab` or `mint`) for a user
+/// The export is stored and imported by a background job, the answer carries the import
+/// whose progress `get_import_handler` reports. Transfers between the user's own accounts
+/// are left out, since both sides are in the export, and rows that cannot be read are
+/// reported by line
+pub async fn import_export_handler(
+    State(state): State<AppState>,
+    Path(format): Path<String>,
+    Query(params): Query<transaction_models::ExportImportParameters>,
+    body: String,
+) -> Result<Response, StatusCode> {
+    let format = importers::ExportFormat::from_str(&format).map_err(|e| {
+        eprintln!("{}", e);
+        StatusCode::NOT_FOUND
+    })?;
+    if body.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let user = require_user(&state, &params.user_email).await?;
+    // Transactions are counted once the job has read the export
+    if let Some(exceeded) = reserve_quota(&state, user.id, 0).await? {
+        return Ok(exceeded);
+    }
+
+    let create = import_models::ImportCreate::new(
+        user.id,
+        format.to_string(),
+        params.currency,
+        params.bank_account_id,
+    );
+    state
+        .blobs
+        .put(
+            &create.storage_key,
+            "text/csv; charset=utf-8",
+            storage::bytes_stream(body),
+        )
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing {} export: {}", format, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let import = import_queries::insert(&state.db, &create)
+        .await
+        .map_err(|e| {
+            eprintln!("Error recording {} import: {}", format, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let job = job_models::JobCreate::new("imports.run", json!({ "import_id": import.id }))
+        .dedupe_key(import.id.to_string())
+        .max_attempts(1);
+    if let Err(e) = jobs::enqueue(&state.db, job).await {
+        eprintln!("Error queueing import {}: {}", import.id, e);
+        let failure = "The import could not be queued, try again";
+        if let Err(e) = import_queries::finish(&state.db, import.id, Some(failure)).await {
+            eprintln!("Error recording failure of import {}: {}", import.id, e);
+        }
+        if let Err(e) = state.blobs.delete(&create.storage_key).await {
+            eprintln!("Error deleting upload of import {}: {}", import.id, e);
+        }
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "Export queued for import",
+            "import": import
+        })),
+    )
+        .into_response())
+}
+
+/// Progress of an import: rows processed, duplicates skipped, errors per row and its status
+pub async fn get_import_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let import = import_queries::get_import(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching import {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "import": import
+    })))
+}
+
+/// Download the rows of an import that were not imported as CSV: the export's columns with
+/// the line, reason (bad_date, invalid_amount, duplicate, refused or invalid) and message of
+/// each, so they can be fixed and the file uploaded again. 404 until the import ran or when
+/// every row was imported
+pub async fn get_import_rejected_rows_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let import = import_queries::get_import(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching import {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if import.rejected_rows == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let data = state
+        .blobs
+        .get(&import.report_key())
+        .await
+        .map_err(|e| {
+            eprintln!("Error reading rejected rows of import {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}-rejected.csv\"", import.format),
+            ),
+        ],
+        Body::from_stream(data),
+    )
+        .into_response())
+}
+
+/// Download a user's transactions as a CSV importable by another app (`firefly` or `ynab`)
+/// With `link=true` the CSV goes to the blob store and a download link is returned instead
+pub async fn export_handler(
+    State(state): State<AppState>,
+    Path(target): Path<String>,
+    Query(params): Query<transaction_models::ExportParameters>,
+) -> Result<Response, StatusCode> {
+    let target = exporters::ExportTarget::from_str(&target).map_err(|e| {
+        eprintln!("{}", e);
+        StatusCode::NOT_FOUND
+    })?;
+
+    let user = require_user(&state, &params.user_email).await?;
+    let tz = user.timezone.parse().unwrap_or(chrono_tz::Tz::UTC);
+
+    let filter = transaction_models::TransactionFilter::for_user(user.id)
+        .period(params.start_timestamp, params.end_timestamp)
+        .at(params.as_of);
+    let mut transactions = transaction_queries::get_transactions(&state.db, &filter)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transactions to export: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if let Some(currency) = params.currency {
+        transactions.retain(|t| t.amount.currency == currency);
+    }
+
+    let csv = exporters::write_csv(target, &mut transactions, tz).map_err(|e| {
+        eprintln!("Error writing {} export: {}", target, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("export", "transaction", None)
+            .actor(user.id)
+            .details(json!({
+                "target": target,
+                "exported": transactions.len(),
+            })),
+    )
+    .await;
+
+    if params.link {
+        let key = format!(
+            "exports/{}/{}/{}",
+            user.id,
+            Uuid::new_v4(),
+            target.file_name()
+        );
+        // Nothing is stored when no link can be made
+        if matches!(state.blobs.presigned_url(&key, storage::LINK_TTL), Ok(None)) {
+            return Ok(no_download_links());
+        }
+        state
+            .blobs
+            .put(&key, "text/csv; charset=utf-8", storage::bytes_stream(csv))
+            .await
+            .map_err(|e| {
+                eprintln!("Error storing {} export: {}", target, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        return download_link(&state, &key);
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", target.file_name()),
+            ),
+        ],
+        csv,
+    )
+        .into_response())
+}
+
+/// Attach a file (e.g. a receipt photo) to one of the user's transactions
+/// The body is multipart/form-data with the file in a "file" part, of a type given by the
+/// part's Content-Type. It is streamed to the blob store, never held in memory. Images get
+/// a thumbnail generated in the background, see `get_thumbnail_handler`
+pub async fn upload_attachment_handler(
+    State(state): State<AppState>,
+    Path(transaction_id): Path<Uuid>,
+    Query(params): Query<attachment_models::UploadAttachmentParameters>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, StatusCode> {
+    let accepted_types = || {
+        attachment_models::CONTENT_TYPES
+            .iter()
+            .map(|(content_type, _)| *content_type)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut multipart = match multipart::Multipart::new(&headers, body) {
+        Ok(multipart) => multipart,
+        Err(e) => {
+            return Ok((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                Json(json!({ "message": e.to_string() })),
+            )
+                .into_response());
+        }
+    };
+
+    // Everything that can refuse the upload is checked before its body is read
+    let user = require_user(&state, &params.user_email).await?;
+    transaction_queries::get_transaction(&state.db, transaction_id, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transaction {}: {}", transaction_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(exceeded) = reserve_quota(&state, user.id, 0).await? {
+        return Ok(exceeded);
+    }
+    let plan = quotas::enforced_plan(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking quotas: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let storage_limit = plan.as_ref().and_then(|p| p.storage_bytes);
+
+    let part = loop {
+        match multipart.next_part().await {
+            Ok(Some(part)) if part.name == "file" => break part,
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "message": "Missing \"file\" part" })),
+                )
+                    .into_response());
+            }
+            Err(e) => {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "message": e.to_string() })),
+                )
+                    .into_response());
+            }
+        }
+    };
+    let content_type = part
+        .content_type
+        .as_deref()
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_lowercase())
+        .unwrap_or_default();
+    let Some(meter) = attachments::UploadMeter::new(&content_type) else {
+        return Ok((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(json!({
+                "message": format!("Attachments must be one of {}", accepted_types())
+            })),
+        )
+            .into_response());
+    };
+    let file_name = params
+        .file_name
+        .or(part.file_name)
+        .map(|name| name.trim().chars().take(255).collect::<String>())
+        .filter(|name| !name.is_empty());
+
+    let mut create =
+        attachment_models::AttachmentCreate::new(transaction_id, user.id, file_name, content_type);
+    let key = create.storage_key.clone();
+    let meter = Arc::new(Mutex::new(meter));
+    let (scan_sender, scan_receiver) = match &state.scanner {
+        Some(_) => {
+            let (sender, receiver) = tokio::sync::mpsc::channel(16);
+            (Some(sender), Some(receiver))
+        }
+        None => (None, None),
+    };
+    let upload = attachments::metered(multipart.into_stream(), meter.clone(), scan_sender);
+    let (stored, verdict) =
+        tokio::join!(state.blobs.put(&key, &create.content_type, upload), async {
+            match (&state.scanner, scan_receiver) {
+                (Some(scanner), Some(receiver)) => Some(
+                    scanner
+                        .scan(&create.content_type, attachments::channel_stream(receiver))
+                        .await,
+                ),
+                _ => None,
+            }
+        });
+
+    let rejected = |rejected: attachments::UploadRejected| {
+        let status = match rejected {
+            attachments::UploadRejected::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            attachments::UploadRejected::WrongType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        };
+        Some((status, rejected.to_string()))
+    };
+    let refused = {
+        let mut meter = meter.lock().expect("upload meter poisoned");
+        if let Some(reason) = meter.rejected() {
+            rejected(reason.clone())
+        } else if let Err(e) = &stored {
+            eprintln!("Error storing attachment: {}", e);
+            None
+        } else {
+            match meter.finish() {
+                Err(reason) => rejected(reason),
+                Ok((0, _)) => Some((StatusCode::BAD_REQUEST, "The file is empty".to_string())),
+                Ok((size, sha256)) => {
+                    create.size_bytes = size as i64;
+                    create.sha256 = sha256;
+                    params
+                        .sha256
+                        .as_ref()
+                        .filter(|expected| !expected.trim().eq_ignore_ascii_case(&create.sha256))
+                        .map(|_| {
+                            (
+                                StatusCode::BAD_REQUEST,
+                                "The file does not match its SHA-256 checksum".to_string(),
+                            )
+                        })
+                }
+            }
+        }
+    };
+    let verdict = match verdict {
+        Some(Err(e)) if stored.is_ok() && refused.is_none() => {
+            // Nothing unscanned gets in while the scanner is down
+            eprintln!("Error scanning attachment: {}", e);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+        Some(Ok(verdict)) => Ok(Some(verdict)),
+        _ => Ok(None),
+    };
+    if stored.is_err() || refused.is_some() || verdict.is_err() {
+        // Partly written or refused, the blob would never be found again
+        if let Err(e) = state.blobs.delete(&key).await {
+            eprintln!("Error deleting refused attachment: {}", e);
+        }
+        if let Some((status, message)) = refused {
+            return Ok((status, Json(json!({ "message": message }))).into_response());
+        }
+        return Err(verdict.err().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+    match verdict? {
+        Some(scanning::Verdict::Clean) => {
+            create.scan_status = Some(attachment_models::ScanStatus::Clean);
+        }
+        Some(scanning::Verdict::Infected(signature)) => {
+            create.quarantine(signature);
+            attachments::move_blob(
+                state.blobs.as_ref(),
+                &key,
+                &create.storage_key,
+                &create.content_type,
+            )
+            .await
+            .map_err(|e| {
+                eprintln!("Error quarantining attachment: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        }
+        None => {}
+    }
+    let inserted = attachment_queries::insert(&state.db, &create, storage_limit).await;
+    // Over the storage quota or not recorded, the blob would never be found again
+    if !matches!(inserted, Ok(Some(_)))
+        && let Err(e) = state.blobs.delete(&create.storage_key).await
+    {
+        eprintln!("Error deleting unrecorded attachment: {}", e);
+    }
+    let Some(attachment) = inserted.map_err(|e| {
+        eprintln!("Error recording attachment: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    else {
+        let exceeded = quotas::QuotaExceeded::Storage {
+            plan: plan.map(|p| p.name).unwrap_or_default(),
+            limit: storage_limit.unwrap_or_default(),
+        };
+        return Ok(exceeded.into_response());
+    };
+
+    if attachment.thumbnail_status.is_some() {
+        let job = job_models::JobCreate::new(
+            "attachments.thumbnail",
+            json!({ "attachment_id": attachment.id }),
+        )
+        .dedupe_key(attachment.id.to_string());
+        if let Err(e) = jobs::enqueue(&state.db, job).await {
+            // Left pending, the original can still be downloaded
+            eprintln!(
+                "Error queueing thumbnail of attachment {}: {}",
+                attachment.id, e
+            );
+        }
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "attachment", Some(attachment.id))
+            .actor(user.id)
+            .details(json!({
+                "transaction_id": transaction_id,
+                "content_type": attachment.content_type,
+                "size_bytes": attachment.size_bytes,
+                "scan_status": attachment.scan_status,
+                "malware_signature": attachment.malware_signature,
+            })),
+    )
+    .await;
+
+    if let Some(plan) = &plan {
+        quotas::storage_added(&state.db, user.id, plan, attachment.size_bytes).await;
+    }
+
+    if attachment.is_quarantined() {
+        webhooks::enqueue(
+            &state,
+            user.id,
+            "attachment.quarantined",
+            json!({
+                "attachment_id": attachment.id,
+                "transaction_id": transaction_id,
+                "malware_signature": attachment.malware_signature,
+            }),
+        )
+        .await;
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({
+                "message": "The file was flagged as malware and quarantined",
+                "attachment": attachment
+            })),
+        )
+            .into_response());
+    }
+
+    Ok(Json(json!({
+        "message": "Attachment uploaded successfully",
+        "attachment": attachment
+    }))
+    .into_response())
+}
+
+/// Answer to requests for the contents of a quarantined attachment
+fn quarantined() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({
+            "message": "The attachment was flagged as malware and is quarantined",
+            "code": problems::ErrorCode::AttachmentQuarantined
+        })),
+    )
+        .into_response()
+}
+
+pub async fn get_attachments_handler(
+    State(state): State<AppState>,
+    Path(transaction_id): Path<Uuid>,
+    Query(params): Query<attachment_models::AttachmentGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &params.user_email).await?;
+    transaction_queries::get_transaction(&state.db, transaction_id, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transaction {}: {}", transaction_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let attachments = attachment_queries::get_attachments(&state.db, transaction_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching attachments: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Attachments retrieved successfully",
+        "attachments": attachments
+    })))
+}
+
+/// Download an attachment as uploaded, streamed from the blob store
+pub async fn get_attachment_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let attachment = attachment_queries::get_attachment(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching attachment {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if attachment.is_quarantined() {
+        return Ok(quarantined());
+    }
+    let data = attachments::open(&state.db, state.blobs.as_ref(), &attachment)
+        .await
+        .map_err(|e| {
+            eprintln!("Error reading attachment {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Quotes would end the header value early
+    let file_name = attachment
+        .file_name
+        .as_deref()
+        .unwrap_or("attachment")
+        .replace(['"', '\\', '\r', '\n'], "_");
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}\"", file_name),
+            ),
+        ],
+        Body::from_stream(data),
+    )
+        .into_response())
+}
+
+/// Time-limited URL downloading an attachment straight from the blob store, so large files
+/// do not go through the server. 501 when the blob store cannot sign URLs
+pub async fn get_attachment_link_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let attachment = attachment_queries::get_attachment(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching attachment {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if attachment.is_quarantined() {
+        return Ok(quarantined());
+    }
+    // Files uploaded before the blob store are only in the database
+    let Some(key) = attachment.storage_key else {
+        return Ok(no_download_links());
+    };
+    download_link(&state, &key)
+}
+
+/// JSON response with a download link of a blob, or the 501 of `no_download_links`
+fn download_link(state: &AppState, key: &str) -> Result<Response, StatusCode> {
+    let url = state
+        .blobs
+        .presigned_url(key, storage::LINK_TTL)
+        .map_err(|e| {
+            eprintln!("Error signing download link: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let Some(url) = url else {
+        return Ok(no_download_links());
+    };
+    let expires_at =
+        chrono::Utc::now() + chrono::Duration::from_std(storage::LINK_TTL).unwrap_or_default();
+
+    Ok(Json(json!({
+        "message": "Download link created successfully",
+        "url": url,
+        "expires_at": expires_at
+    }))
+    .into_response())
+}
+
+fn no_download_links() -> Response {
+    (
+        StatusCode::NOT_IMPLEMENTED,
+        Json(json!({
+            "message": "The configured blob store cannot create download links, download through the API instead"
+        })),
+    )
+        .into_response()
+}
+
+/// Small PNG of an image attachment for list views, 404 until it has been generated
+/// Thumbnails never change once generated, so clients may cache them for a long time
+pub async fn get_thumbnail_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let thumbnail = attachment_queries::get_thumbnail(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching thumbnail of attachment {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                attachments::THUMBNAIL_CONTENT_TYPE.to_string(),
+            ),
+            (
+                header::CACHE_CONTROL,
+                "private, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        thumbnail,
+    )
+        .into_response())
+}
+
+/// Changes of a user's transactions as they happen, as server-sent events named after the
+/// change (e.g. "transaction.created") with the changed fields as data. A "resync" event
+/// means changes were missed, clients then catch up with `sync_changes_handler`
+pub async fn stream_events_handler(
+    State(state): State<AppState>,
+    Query(params): Query<sync_models::EventStreamParameters>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    let user = require_user(&state, &params.user_email).await?;
+    let receiver = events::listen().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let stream = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(change) if change.user_id == user.id => Event::default()
+                    .event(change.name())
+                    .id(change.transaction_id.to_string())
+                    .json_data(&change.data)
+                    .unwrap_or_default(),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(_)) => Event::default().event("resync"),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), receiver));
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Changes to a user's data since a sync cursor, for offline-first clients
+/// `changed` holds created and updated transactions to upsert by id, `deleted` the ids
+/// to drop. The fixed categories are only sent on a first sync (without `since`).
+/// Clients keep calling with `next_cursor` while `has_more` is true
+pub async fn sync_changes_handler(
+    State(state): State<AppState>,
+    Query(params): Query<sync_models::SyncParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let since = match params.since.as_deref() {
+        Some(cursor) => cursor.parse::<sync_models::SyncCursor>().map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::BAD_REQUEST
+        })?,
+        None => sync_models::SyncCursor::start(),
+    };
+    let limit = params.limit.unwrap_or(500).clamp(1, 1000);
+
+    let user = require_user(&state, &params.user_email).await?;
+
+    // One extra row tells whether there is another page
+    let mut changed =
+        transaction_queries::get_changed_transactions(&state.db, user.id, since, limit + 1)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching changed transactions: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    let has_more = changed.len() > limit as usize;
+    changed.truncate(limit as usize);
+    let last_changed = changed.last().map(|t| sync_models::SyncCursor {
+        changed_at: t.last_updated_at,
+        id: t.id,
+    });
+
+    // Deletions are returned up to the end of this page, so none fall between two pages
+    let deleted = sync_queries::get_tombstones(
+        &state.db,
+        user.id,
+        "transaction",
+        since,
+        last_changed.filter(|_| has_more),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching deleted transactions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let last_deleted = deleted.last().map(|t| sync_models::SyncCursor {
+        changed_at: t.deleted_at,
+        id: t.id,
+    });
+    let next_cursor = [Some(since), last_changed, last_deleted]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(since);
+
+    let mut body = json!({
+        "message": "Changes retrieved successfully",
+        "transactions": {
+            "changed": changed,
+            "deleted": deleted
+        },
+        "next_cursor": next_cursor.to_string(),
+        "has_more": has_more
+    });
+    if params.since.is_none() {
+        body["categories"] = json!(transaction_models::TransactionCategory::ALL);
+    }
+    Ok(Json(body))
+}
+
+/// Apply transactions created or edited on a client, possibly while offline
+/// Clients pick the ids of new transactions themselves. An edit of a transaction that
+/// changed on the server since `base_updated_at` (or `client_updated_at` when not given)
+/// is not applied but returned as a conflict with both versions, and applied once pushed
+/// again with `force`. Edits of transactions deleted on the server, or in a reconciled
+/// period of their account, always conflict, and so do new transactions going over the
+/// user's spending limits. With `dry_run` nothing is stored: the answer tells which changes
+/// would be applied and which would conflict
+pub async fn sync_push_handler(
+    State(state): State<AppState>,
+    Query(params): Query<transaction_models::DryRunParameters>,
+    ValidJson(req): ValidJson<sync_models::SyncPushRequest>,
+) -> Result<Response, StatusCode> {
+    let user = require_user(&state, &req.user_email).await?;
+    let internal_error = |e: anyhow::Error| {
+        eprintln!("Error applying pushed changes: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    // New transactions are counted one by one below, as they are found to be new
+    if !params.dry_run
+        && let Some(exceeded) = reserve_quota(&state, user.id, 0).await?
+    {
+        return Ok(exceeded);
+    }
+
+    let mut applied = Vec::new();
+    let mut conflicts = Vec::new();
+    for change in req.transactions {
+        let transaction = transaction_models::TransactionCreate::new(
+            user.id,
+            change.transaction_type.clone(),
+            change.amount,
+            change.category.clone(),
+            change.description.clone(),
+        )
+        .encrypted_description(change.encrypted_description.clone())
+        .id(change.id)
+        .occurred_at(change.occurred_at);
+        let conflict = |reason, server| sync_models::SyncConflict {
+            id: change.id,
+            reason,
+            server,
+            client: change.clone(),
+            reconciliation: None,
+            message: None,
+        };
+
+        let owner = transaction_queries::get_transaction_owner(&state.db, change.id)
+            .await
+            .map_err(internal_error)?;
+        match owner {
+            Some(owner) if owner != user.id => {
+                conflicts.push(conflict(sync_models::ConflictReason::IdInUse, None));
+            }
+            None => {
+                if sync_queries::is_deleted(&state.db, "transaction", change.id)
+                    .await
+                    .map_err(internal_error)?
+                {
+                    conflicts.push(conflict(sync_models::ConflictReason::DeletedOnServer, None));
+                    continue;
+                }
+                let checked = restrictions::check(
+                    &state.db,
+                    std::slice::from_ref(&transaction),
+                    change.confirm_over_limit,
+                )
+                .await
+                .map_err(internal_error)?;
+                if let Some(refusal) = checked.refusal() {
+                    conflicts.push(sync_models::SyncConflict {
+                        message: Some(refusal.to_string()),
+                        ..conflict(sync_models::ConflictReason::Refused, None)
+                    });
+                    continue;
+                }
+                if params.dry_run {
+                    applied.push(json!({ "id": change.id, "status": "created" }));
+                    continue;
+                }
+                if quotas::reserve_transactions(&state.db, user.id, 1)
+                    .await
+                    .map_err(internal_error)?
+                    .is_some()
+                {
+                    conflicts.push(conflict(sync_models::ConflictReason::QuotaExceeded, None));
+                    continue;
+                }
+                if let Err(e) =
+                    transaction_queries::create_transaction(&state.db, &transaction).await
+                {
+                    quotas::release_transactions(&state.db, user.id, 1).await;
+                    return Err(internal_error(e));
+                }
+                restrictions::record_overrides(
+                    &state.db,
+                    user.id,
+                    std::slice::from_ref(&transaction),
+                    &checked,
+                )
+                .await;
+                record_audit(
+                    &state,
+                    audit_models::AuditEntryCreate::new("create", "transaction", Some(change.id))
+                        .actor(user.id)
+                        .details(json!({
+                            "transaction_type": transaction.transaction_type,
+                            "amount": transaction.amount,
+                            "category": transaction.category,
+                            "source": transaction.source,
+                            "client_updated_at": change.client_updated_at,
+                        })),
+                )
+                .await;
+                applied.push(json!({ "id": change.id, "status": "created" }));
+            }
+            Some(_) => {
+                let Some(server) =
+                    transaction_queries::get_transaction(&state.db, change.id, user.id)
+                        .await
+                        .map_err(internal_error)?
+                else {
+                    // Soft deleted, it stays deleted until restored
+                    conflicts.push(conflict(sync_models::ConflictReason::DeletedOnServer, None));
+                    continue;
+                };
+                let unchanged = server.transaction_type == transaction.transaction_type
+                    && server.amount == transaction.amount
+                    && server.category == transaction.category
+                    && server.description == transaction.description
+                    && server.encrypted_description == transaction.encrypted_description
+                    && change.occurred_at.is_none_or(|t| t == server.created_at);
+                if unchanged {
+                    applied.push(json!({
+                        "id": change.id,
+                        "status": "unchanged",
+                        "last_updated_at": server.last_updated_at
+                    }));
+                    continue;
+                }
+                let base = change.base_updated_at.unwrap_or(change.client_updated_at);
+                if !change.force && server.last_updated_at > base {
+                    conflicts.push(conflict(
+                        sync_models::ConflictReason::ModifiedOnServer,
+                        Some(server),
+                    ));
+                    continue;
+                }
+                let updated = if params.dry_run {
+                    transaction_queries::ensure_unlocked(
+                        &state.db,
+                        change.id,
+                        user.id,
+                        transaction.occurred_at,
+                    )
+                    .await
+                    .map(|()| None)
+                } else {
+                    transaction_queries::update_transaction(&state.db, change.id, &transaction)
+                        .await
+                };
+                let last_updated_at = match updated {
+                    Ok(last_updated_at) => last_updated_at,
+                    Err(e) => match e.downcast::<reconciliation_models::TransactionLocked>() {
+                        Ok(locked) => {
+                            conflicts.push(sync_models::SyncConflict {
+                                reconciliation: Some(locked.lock),
+                                ..conflict(sync_models::ConflictReason::Reconciled, Some(server))
+                            });
+                            continue;
+                        }
+                        Err(e) => return Err(internal_error(e)),
+                    },
+                };
+                if params.dry_run {
+                    applied.push(json!({ "id": change.id, "status": "updated" }));
+                    continue;
+                }
+                record_audit(
+                    &state,
+                    audit_models::AuditEntryCreate::new("update", "transaction", Some(change.id))
+                        .actor(user.id)
+                        .details(json!({
+                            "transaction_type": transaction.transaction_type,
+                            "amount": transaction.amount,
+                            "category": transaction.category,
+                            "client_updated_at": change.client_updated_at,
+                            "forced": change.force,
+                        })),
+                )
+                .await;
+                applied.push(json!({
+                    "id": change.id,
+                    "status": "updated",
+                    "last_updated_at": last_updated_at
+                }));
+            }
+        }
+    }
+
+    if params.dry_run {
+        return Ok(Json(json!({
+            "message": "Changes checked, nothing was stored",
+            "dry_run": true,
+            "applied": applied,
+            "conflicts": conflicts
+        }))
+        .into_response());
+    }
+    Ok(Json(json!({
+        "message": "Changes pushed successfully",
+        "applied": applied,
+        "conflicts": conflicts
+    }))
+    .into_response())
+}
+
+/// Replace the relative date filters (`period`, `since`, `last_n_days`) with the
+/// timestamps they stand for, at most one of them may be given
+/// Day boundaries are taken in the time zone of the filtered user, UTC when listing everyone
+async fn resolve_relative_dates(
+    state: &AppState,
+    params: &mut transaction_models::TransactionGetParameters,
+) -> Result<(), StatusCode> {
+    let relative_filters = [
+        params.period.is_some(),
+        params.since.is_some(),
+        params.last_n_days.is_some(),
+    ];
+    match relative_filters.iter().filter(|given| **given).count() {
+        0 => return Ok(()),
+        1 => {}
+        _ => {
+            eprintln!("Only one of period, since and last_n_days may be given");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    if params.start_timestamp.is_some()
+        || (params.period.is_some() && params.end_timestamp.is_some())
+    {
+        eprintln!(
+            "Relative date filters cannot be combined with start_timestamp (nor period with end_timestamp)"
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if params.last_n_days == Some(0) {
+        eprintln!("last_n_days must be at least 1");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = chrono::Utc::now();
+    if let Some(since) = params.since {
+        params.start_timestamp = Some(since.before(now).ok_or(StatusCode::BAD_REQUEST)?);
+        return Ok(());
+    }
+
+    let tz = match params.user_id {
+        Some(user_id) => user_queries::get_user_timezone(&state.db, user_id)
+            .await
+            .map_err(|e| {
+                eprintln!("Error loading time zone for user {}: {}", user_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?,
+        None => chrono_tz::Tz::UTC,
+    };
+    if let Some(days) = params.last_n_days {
+        let today = now.with_timezone(&tz).date_naive();
+        let first_day = today
+            .checked_sub_days(chrono::Days::new(u64::from(days) - 1))
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        params.start_timestamp = Some(transaction_models::local_midnight(first_day, tz));
+    }
+    if let Some(period) = params.period {
+        let (start, end) = period.range(now, tz);
+        params.start_timestamp = Some(start);
+        params.end_timestamp = Some(end);
+    }
+    Ok(())
+}
+
+pub async fn get_transactions_handler(
+    State(state): State<AppState>,
+    where_clause_params: Query<transaction_models::TransactionGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut transaction_get_params = where_clause_params.0;
+    resolve_relative_dates(&state, &mut transaction_get_params).await?;
+    let filter = transaction_get_params.filter();
+
+    let transactions = transaction_queries::get_transactions(&state.db, &filter)
+        .await
+        .map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    println!("Retrieved {} transaction(s)", transactions.len());
+    let mut response = json!({
+        "message": "Transactions retrieved successfully",
+        "users": transactions
+    });
+    if transaction_get_params.include_total {
+        let total = transaction_queries::count_transactions(&state.db, &filter)
+            .await
+            .map_err(|e| {
+                eprintln!("Error counting transactions: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        response["total"] = json!(total);
+    }
+    Ok(Json(response))
+}
+
+/// Number of transactions matching the same filters as the listing
+pub async fn get_transaction_count_handler(
+    State(state): State<AppState>,
+    Query(mut params): Query<transaction_models::TransactionGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    resolve_relative_dates(&state, &mut params).await?;
+    let count = transaction_queries::count_transactions(&state.db, &params.filter())
+        .await
+        .map_err(|e| {
+            eprintln!("Error counting transactions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(json!({
+        "message": "Transactions counted successfully",
+        "count": count
+    })))
+}
+
+/// The user's transactions in Uncategorized, oldest first, for sorting them out one by one
+pub async fn get_uncategorized_transactions_handler(
+    State(state): State<AppState>,
+    Query(params): Query<transaction_models::UncategorizedGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let filter = transaction_models::TransactionFilter::for_user(params.user_id)
+        .categories([transaction_models::TransactionCategory::Uncategorized])
+        .page(
+            Some(
+                params
+                    .limit
+                    .unwrap_or(100)
+                    .clamp(1, transaction_models::MAX_PAGE_SIZE),
+            ),
+            params.offset,
+        );
+    let count = transaction_queries::count_transactions(&state.db, &filter)
+        .await
+        .map_err(|e| {
+            eprintln!("Error counting uncategorized transactions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let transactions = transaction_queries::get_transactions(&state.db, &filter)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching uncategorized transactions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(json!({
+        "message": "Uncategorized transactions retrieved successfully",
+        "count": count,
+        "transactions": transactions
+    })))
+}
+
+/// The category the user most often puts transactions of the description's merchant in,
+/// from the latest model of their categorizations, see suggestions.rs
+/// "suggestion" is null when the model knows no such merchant or none was trained yet
+pub async fn get_category_suggestion_handler(
+    State(state): State<AppState>,
+    Query(params): Query<suggestion_models::SuggestionParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let model = suggestion_queries::get_latest(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching category model: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let suggestion = model
+        .as_ref()
+        .and_then(|model| model.suggest(&params.description));
+
+    Ok(Json(json!({
+        "message": "Category suggestion retrieved successfully",
+        "suggestion": suggestion,
+        "model": model
+    })))
+}
+
+/// Soft delete one of the user's transactions, it is left out of listings and sums from
+/// then on and can be restored
+pub async fn delete_transaction_handler(
+    State(state): State<AppState>,
+    Path(transaction_id): Path<Uuid>,
+    Query(params): Query<transaction_models::TransactionDeleteParameters>,
+) -> Result<Response, StatusCode> {
+    let user = require_user(&state, &params.user_email).await?;
+    let deleted =
+        match transaction_queries::delete_transaction(&state.db, transaction_id, user.id).await {
+            Ok(deleted) => deleted.ok_or(StatusCode::NOT_FOUND)?,
+            Err(e) => return locked_or_internal_error(e, "deleting"),
+        };
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "transaction", Some(transaction_id))
+            .actor(user.id)
+            .details(json!({
+                "transaction_type": deleted.transaction_type,
+                "amount": deleted.amount,
+                "category": deleted.category,
+            })),
+    )
+    .await;
+    Ok(Json(json!({
+        "message": "Transaction deleted successfully",
+        "id": transaction_id,
+        "deleted_at": deleted.deleted_at
+    }))
+    .into_response())
+}
+
+/// Bring back one of the user's deleted transactions
+pub async fn restore_transaction_handler(
+    State(state): State<AppState>,
+    Path(transaction_id): Path<Uuid>,
+    Query(params): Query<transaction_models::TransactionDeleteParameters>,
+) -> Result<Response, StatusCode> {
+    let user = require_user(&state, &params.user_email).await?;
+    let restored =
+        match transaction_queries::restore_transaction(&state.db, transaction_id, user.id).await {
+            Ok(restored) => restored.ok_or(StatusCode::NOT_FOUND)?,
+            Err(e) => return locked_or_internal_error(e, "restoring"),
+        };
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("restore", "transaction", Some(transaction_id))
+            .actor(user.id),
+    )
+    .await;
+    Ok(Json(json!({
+        "message": "Transaction restored successfully",
+        "transaction": restored
+    }))
+    .into_response())
+}
+
+/// 409 with the lock for transactions in a reconciled period, 500 for any other error
+fn locked_or_internal_error(e: anyhow::Error, doing: &str) -> Result<Response, StatusCode> {
+    match e.downcast::<reconciliation_models::TransactionLocked>() {
+        Ok(locked) => Ok((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": locked.to_string(),
+                "code": problems::ErrorCode::PeriodLocked,
+                "reconciliation_lock": locked.lock
+            })),
+        )
+            .into_response()),
+        Err(e) => {
+            eprintln!("Error {} transaction: {}", doing, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn get_amount_handler(
+    State(state): State<AppState>,
+    where_clause_params: Query<transaction_models::TransactionGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut transaction_get_params = where_clause_params.0;
+    let user_id = transaction_get_params.user_id;
+    if user_id.is_none() {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    resolve_relative_dates(&state, &mut transaction_get_params).await?;
+    let filter = transaction_get_params.filter();
+    let money_sum = transaction_queries::get_user_transaction_sum(&state.db, &filter)
+        .await
+        .map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // One total per currency, amounts in different currencies are never added up
+    // "amounts" is the net total, as answered before the breakdown was added
+    Ok(Json(json!({
+        "message": "Transactions sum retrieved successfully",
+        "income": money_sum.income,
+        "expenses": money_sum.expenses,
+        "net": money_sum.net,
+        "amounts": money_sum.net
+    })))
+}
+
+/// The sums of several filters of the user's transactions, in the order of the filters
+pub async fn get_amounts_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<transaction_models::AmountsRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut amounts = Vec::with_capacity(req.filters.len());
+    for mut params in req.filters {
+        params.user_id = Some(req.user_id);
+        resolve_relative_dates(&state, &mut params).await?;
+        let money_sum = transaction_queries::get_user_transaction_sum(&state.db, &params.filter())
+            .await
+            .map_err(|e| {
+                eprintln!("{}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        amounts.push(money_sum);
+    }
+
+    Ok(Json(json!({
+        "message": "Transaction sums retrieved successfully",
+        "amounts": amounts
+    })))
+}
+
+/// Sums of the user's transactions by day, week or month, for charts of spending over time
+/// Every bucket from the start to the end is listed, those without transactions included
+pub async fn get_timeseries_handler(
+    State(state): State<AppState>,
+    Query(params): Query<transaction_models::TimeseriesParameters>,
+) -> Result<Response, StatusCode> {
+    let tz = user_queries::get_user_timezone(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching user timezone: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let granularity = params.granularity;
+    let end = params.end_timestamp.unwrap_or_else(chrono::Utc::now);
+    let start = match params.start_timestamp {
+        Some(start) => start,
+        None => {
+            let (last_bucket, _) = granularity.days(end.with_timezone(&tz).date_naive());
+            let first_bucket = match granularity {
+                limit_models::LimitPeriod::Day => {
+                    last_bucket.checked_sub_days(chrono::Days::new(29))
+                }
+                limit_models::LimitPeriod::Week => {
+                    last_bucket.checked_sub_days(chrono::Days::new(77))
+                }
+                limit_models::LimitPeriod::Month => {
+                    last_bucket.checked_sub_months(chrono::Months::new(11))
+                }
+            }
+            .ok_or(StatusCode::BAD_REQUEST)?;
+            transaction_models::local_midnight(first_bucket, tz)
+        }
+    };
+    if start > end {
+        let mut errors = ValidationErrors::default();
+        errors.add("start_timestamp", "must not be after end_timestamp");
+        return Ok(errors.into_response());
+    }
+
+    let filter = transaction_models::TransactionFilter::for_user(params.user_id)
+        .categories(params.category)
+        .transaction_types(params.transaction_type)
+        .period(Some(start), Some(end));
+    let sums = transaction_queries::get_timeseries(&state.db, &filter, granularity, tz)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transaction time series: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let Some(buckets) = transaction_models::TimeseriesBucket::fill(
+        granularity,
+        start.with_timezone(&tz).date_naive(),
+        end.with_timezone(&tz).date_naive(),
+        sums,
+    ) else {
+        let mut errors = ValidationErrors::default();
+        errors.add(
+            "start_timestamp",
+            format!(
+                "must be at most {} {}s before end_timestamp",
+                transaction_models::MAX_TIMESERIES_BUCKETS,
+                granularity
+            ),
+        );
+        return Ok(errors.into_response());
+    };
+
+    Ok(Json(json!({
+        "message": "Time series retrieved successfully",
+        "granularity": granularity,
+        "start": start,
+        "end": end,
+        "buckets": buckets
+    }))
+    .into_response())
+}
+
+/// Each category's average monthly expenses over the last 12 months, and how every month
+/// compares to it
+pub async fn get_seasonality_handler(
+    State(state): State<AppState>,
+    Query(params): Query<transaction_models::SeasonalityParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let tz = user_queries::get_user_timezone(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching user timezone: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let today = chrono::Utc::now().with_timezone(&tz).date_naive();
+    let (this_month, _) = limit_models::LimitPeriod::Month.days(today);
+    let first_month = this_month
+        .checked_sub_months(chrono::Months::new(transaction_models::SEASONALITY_MONTHS))
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let spends = transaction_queries::get_monthly_category_spend(
+        &state.db,
+        params.user_id,
+        tz.name(),
+        transaction_models::local_midnight(first_month, tz),
+        transaction_models::local_midnight(this_month, tz),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching monthly expenses: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let categories = transaction_models::CategorySeasonality::report(first_month, &spends);
+
+    Ok(Json(json!({
+        "message": "Seasonality retrieved successfully",
+        "first_month": first_month,
+        "months": transaction_models::SEASONALITY_MONTHS,
+        "categories": categories
+    })))
+}
+
+/// Gains and losses on the user's foreign-currency balances from exchange-rate movements
+/// between each transaction's day and the latest rates, in the base currency
+pub async fn get_fx_revaluation_handler(
+    State(state): State<AppState>,
+    Query(params): Query<fx_models::RevaluationParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let tz = user_queries::get_user_timezone(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching user timezone: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let balances = fx_queries::get_booked_balances(&state.db, params.user_id, params.base, tz)
+        .await
+        .map_err(|e| {
+            eprintln!("Error computing booked values: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let latest = fx_queries::get_latest_rates(&state.db).await.map_err(|e| {
+        eprintln!("Error fetching exchange rates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let currencies = fx::revalue(balances, params.base, &latest).map_err(|e| {
+        eprintln!("Error revaluing balances: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let total_gain = currencies
+        .iter()
+        .filter_map(|currency| currency.gain)
+        .fold(Money::new(0, params.base), |total, gain| {
+            Money::new(total.minor_units + gain.minor_units, params.base)
+        });
+    let rates_as_of = latest.values().map(|(date, _)| *date).max();
+
+    Ok(Json(json!({
+        "message": "FX revaluation computed successfully",
+        "base": params.base,
+        "rates_as_of": rates_as_of,
+        "currencies": currencies,
+        "total_gain": total_gain
+    })))
+}
+
+/// Link a bank account to a user
+/// The IBAN/account number is stored but only ever returned masked
+pub async fn create_bank_account_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<bank_account_models::CreateBankAccountRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &req.user_email).await?;
+
+    let account = bank_account_models::BankAccountCreate::new(
+        user.id,
+        req.institution,
+        req.name,
+        req.iban,
+        req.account_number,
+    );
+
+    let bank_account = bank_account_queries::create_bank_account(&state.db, &account)
+        .await
+        .map_err(|e| {
+            eprintln!("Error creating bank account: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "bank_account", Some(bank_account.id))
+            .actor(user.id)
+            .details(json!({ "institution": bank_account.institution })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Bank account created successfully",
+        "bank_account": bank_account
+    })))
+}
+
+pub async fn get_bank_accounts_handler(
+    State(state): State<AppState>,
+    Query(params): Query<bank_account_models::BankAccountGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let bank_accounts = bank_account_queries::get_bank_accounts(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching bank accounts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Bank accounts retrieved successfully",
+        "bank_accounts": bank_accounts
+    })))
+}
+
+/// Record the result of a bank sync run for an account
+pub async fn update_bank_account_sync_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<bank_account_models::UpdateSyncStatusRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let status = bank_account_models::BankSyncStatus::from_str(&req.sync_status).map_err(|e| {
+        eprintln!("Invalid sync status: {} - {}", req.sync_status, e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let bank_account =
+        bank_account_queries::update_sync_status(&state.db, id, status, req.sync_error.as_deref())
+            .await
+            .map_err(|e| {
+                eprintln!("Error updating bank account sync status: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Bank account sync status updated successfully",
+        "bank_account": bank_account
+    })))
+}
+
+/// Set the day of the month the card's statements start on
+pub async fn update_statement_day_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    ValidJson(req): ValidJson<bank_account_models::UpdateStatementDayRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let bank_account = bank_account_queries::update_statement_day(&state.db, id, req.statement_day)
+        .await
+        .map_err(|e| {
+            eprintln!("Error updating bank account statement day: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Bank account statement day updated successfully",
+        "bank_account": bank_account
+    })))
+}
+
+/// Reconciled periods of a bank account, whose transactions are locked
+pub async fn get_reconciliation_locks_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let locks = reconciliation_queries::get_locks(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching reconciliation locks: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Reconciliation locks retrieved successfully",
+        "reconciliation_locks": locks
+    })))
+}
+
+/// Lock a reconciled statement period of a bank account, changes to its transactions are
+/// refused until the owner unlocks it
+pub async fn create_reconciliation_lock_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    ValidJson(req): ValidJson<reconciliation_models::CreateReconciliationLockRequest>,
+) -> Result<Response, StatusCode> {
+    let bank_account = bank_account_queries::get_bank_account(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching bank account {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let overlapping = reconciliation_queries::get_overlapping_lock(
+        &state.db,
+        id,
+        req.period_start,
+        req.period_end,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching reconciliation locks: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let Some(lock) = overlapping {
+        return Ok((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": "The period overlaps one that is already locked",
+                "code": problems::ErrorCode::PeriodLocked,
+                "reconciliation_lock": lock
+            })),
+        )
+            .into_response());
+    }
+    let lock = reconciliation_queries::create_lock(
+        &state.db,
+        id,
+        req.period_start,
+        req.period_end,
+        bank_account.user_id,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error storing reconciliation lock: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("lock", "reconciliation", Some(lock.id))
+            .actor(bank_account.user_id)
+            .details(json!({
+                "bank_account_id": id,
+                "period_start": lock.period_start,
+                "period_end": lock.period_end,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Period locked successfully",
+        "reconciliation_lock": lock
+    }))
+    .into_response())
+}
+
+/// Unlock a reconciled period so its transactions can be corrected, by the account's owner
+pub async fn delete_reconciliation_lock_handler(
+    State(state): State<AppState>,
+    Path((id, lock_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Value>, StatusCode> {
+    let bank_account = bank_account_queries::get_bank_account(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching bank account {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let lock = reconciliation_queries::delete_lock(&state.db, id, lock_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting reconciliation lock {}: {}", lock_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("unlock", "reconciliation", Some(lock.id))
+            .actor(bank_account.user_id)
+            .details(json!({
+                "bank_account_id": id,
+                "period_start": lock.period_start,
+                "period_end": lock.period_end,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Period unlocked successfully"
+    })))
+}
+
+/// Set the cashback rate of a card in a category, or on everything else
+pub async fn put_reward_rule_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<reward_models::PutRewardRuleRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &req.user_email).await?;
+    let rule = reward_queries::upsert_rule(
+        &state.db,
+        user.id,
+        req.bank_account_id,
+        req.category.as_ref(),
+        req.rate,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error storing reward rule: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    // Not one of the user's bank accounts
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "reward_rule", Some(rule.id))
+            .actor(user.id)
+            .details(json!({
+                "bank_account_id": rule.bank_account_id,
+                "category": rule.category,
+                "rate": rule.rate,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Reward rule stored successfully",
+        "reward_rule": rule
+    })))
+}
+
+pub async fn get_reward_rules_handler(
+    State(state): State<AppState>,
+    Query(params): Query<reward_models::RewardRuleGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let rules = reward_queries::get_rules(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching reward rules: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Reward rules retrieved successfully",
+        "reward_rules": rules
+    })))
+}
+
+pub async fn delete_reward_rule_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let user_id = reward_queries::delete_rule(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting reward rule {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "reward_rule", Some(id)).actor(user_id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Reward rule deleted successfully"
+    })))
+}
+
+/// The card to pay with in each category, the one with the highest cashback rate
+pub async fn get_best_cards_handler(
+    State(state): State<AppState>,
+    Query(params): Query<reward_models::RewardRuleGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let rules = reward_queries::get_rules(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching reward rules: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Best cards retrieved successfully",
+        "best_cards": rewards::best_cards(&rules)
+    })))
+}
+
+/// Expected cashback of a card per statement period, newest first
+pub async fn get_cashback_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<reward_models::CashbackParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let bank_account = bank_account_queries::get_bank_account(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching bank account {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let periods = params.periods.unwrap_or(3);
+    if !(1..=24).contains(&periods) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let rules = reward_queries::get_rules(&state.db, bank_account.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching reward rules: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let tz = user_queries::get_user_timezone(&state.db, bank_account.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching user timezone: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut statements = Vec::new();
+    for (start, end) in
+        rewards::statement_periods(bank_account.statement_day, chrono::Utc::now(), tz, periods)
+    {
+        let expenses = reward_queries::get_card_expenses(&state.db, id, start, end)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching card expenses: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let mut spent = MoneyTotals::default();
+        let mut cashback = MoneyTotals::default();
+        for (category, amount) in expenses {
+            spent.add(amount);
+            if let Some(rate) = rewards::rate_for(&rules, id, &category) {
+                cashback.add(rewards::cashback(amount, rate));
+            }
+        }
+        statements.push(reward_models::StatementCashback {
+            start,
+            end,
+            spent,
+            cashback,
+        });
+    }
+
+    Ok(Json(json!({
+        "message": "Cashback retrieved successfully",
+        "bank_account_id": id,
+        "statements": statements
+    })))
+}
+
+/// Verify the audit log hash chain
+/// Reports the first entry that was edited, removed or re-linked, if any
+pub async fn verify_audit_log_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    let verification = audit_queries::verify_chain(&state.db).await.map_err(|e| {
+        eprintln!("Error verifying audit log: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(json!({
+        "message": "Audit log verified",
+        "verification": verification
+    })))
+}
+
+/// List a user's transactions parsed from emails, pending review by default
+pub async fn get_pending_transactions_handler(
+    State(state): State<AppState>,
+    Query(params): Query<pending_models::PendingTransactionGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let status = params
+        .status
+        .unwrap_or(pending_models::PendingStatus::Pending);
+    let pending = pending_queries::get_pending(&state.db, params.user_id, status)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching pending transactions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Pending transactions retrieved successfully",
+        "pending_transactions": pending
+    })))
+}
+
+/// Turn a pending transaction into a real one, optionally correcting its category and description
+pub async fn confirm_pending_transaction_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    ValidJson(req): ValidJson<pending_models::ConfirmPendingRequest>,
+) -> Result<Response, StatusCode> {
+    let pending = pending_queries::get_pending_by_id(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching pending transaction {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if pending.status != pending_models::PendingStatus::Pending {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    // The email's Message-ID is the external id, so confirming twice cannot duplicate it
+    let transaction = transaction_models::TransactionCreate::new(
+        pending.user_id,
+        pending.transaction_type,
+        pending.amount.abs(),
+        req.category.or(pending.category),
+        req.description.or(pending.description),
+    )
+    .occurred_at(pending.occurred_at)
+    .with_origin(
+        transaction_models::TransactionSource::Email,
+        Some(pending.source_ref),
+    );
+
+    let checked = check_restrictions(
+        &state,
+        std::slice::from_ref(&transaction),
+        req.confirm_over_limit,
+    )
+    .await?;
+    if let Some(refusal) = checked.refusal() {
+        return Ok(refusal.into_response());
+    }
+    if let Some(exceeded) = reserve_quota(&state, pending.user_id, 1).await? {
+        return Ok(exceeded);
+    }
+    let transaction_id = transaction_queries::create_transaction(&state.db, &transaction).await;
+    if !matches!(transaction_id, Ok(Some(_))) {
+        quotas::release_transactions(&state.db, pending.user_id, 1).await;
+    }
+    let transaction_id = transaction_id.map_err(|e| {
+        eprintln!("Error creating transaction from pending {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let resolved = pending_queries::resolve_pending(
+        &state.db,
+        id,
+        pending_models::PendingStatus::Confirmed,
+        transaction_id,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error confirming pending transaction {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if !resolved {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    if let Some(transaction_id) = transaction_id {
+        restrictions::record_overrides(
+            &state.db,
+            pending.user_id,
+            std::slice::from_ref(&transaction),
+            &checked,
+        )
+        .await;
+        record_audit(
+            &state,
+            audit_models::AuditEntryCreate::new("create", "transaction", Some(transaction_id))
+                .actor(pending.user_id)
+                .details(json!({
+                    "transaction_type": transaction.transaction_type,
+                    "amount": transaction.amount,
+                    "category": transaction.category,
+                    "source": transaction.source,
+                    "pending_transaction_id": id,
+                })),
+        )
+        .await;
+    }
+
+    Ok(Json(json!({
+        "message": "Pending transaction confirmed",
+        "transaction_id": transaction_id
+    }))
+    .into_response())
+}
+
+/// Dismiss a pending transaction, e.g. an email that was not a real payment
+pub async fn reject_pending_transaction_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let resolved = pending_queries::resolve_pending(
+        &state.db,
+        id,
+        pending_models::PendingStatus::Rejected,
+        None,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error rejecting pending transaction {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if !resolved {
+        // Either unknown or already confirmed/rejected
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(json!({
+        "message": "Pending transaction rejected"
+    })))
+}
+
+/// The user recording a transaction in the owner's wallet, who must be an editor of it
+async fn wallet_editor(state: &AppState, owner_id: Uuid, email: &str) -> Result<Uuid, StatusCode> {
+    let editor = require_user(state, email).await?;
+    let role = sharing_queries::get_role(&state.db, owner_id, editor.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching wallet role: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    match role {
+        Some(sharing_models::WalletRole::Editor) => Ok(editor.id),
+        _ => Err(StatusCode::FORBIDDEN),
+    }
+}
+
+/// Keep an editor's transaction aside until the owner approves it, and let the owner know
+async fn hold_for_approval(
+    state: &AppState,
+    editor_id: Uuid,
+    transaction: &transaction_models::TransactionCreate,
+) -> Result<Response, StatusCode> {
+    let approval_id = sharing_queries::insert_approval(&state.db, editor_id, transaction)
+        .await
+        .map_err(|e| {
+            eprintln!("Error holding transaction for approval: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let details = json!({
+        "id": approval_id,
+        "requested_by": editor_id,
+        "transaction_type": transaction.transaction_type,
+        "amount": transaction.amount,
+        "category": transaction.category,
+        "source": transaction.source,
+    });
+    record_audit(
+        state,
+        audit_models::AuditEntryCreate::new("create", "transaction_approval", Some(approval_id))
+            .actor(editor_id)
+            .details(details.clone()),
+    )
+    .await;
+    webhooks::enqueue(
+        state,
+        transaction.user_id,
+        "transaction.approval_requested",
+        details,
+    )
+    .await;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "Transaction is awaiting the owner's approval",
+            "approval_id": approval_id
+        })),
+    )
+        .into_response())
+}
+
+/// List the users the wallet is shared with
+pub async fn get_wallet_members_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let members = sharing_queries::get_members(&state.db, owner.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching wallet members: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Wallet members retrieved successfully",
+        "members": members
+    })))
+}
+
+/// The user's wallets, their own and those shared with them, with nested bank accounts and
+/// balances, everything a home screen shows in one request
+pub async fn get_wallets_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let wallets = sharing_queries::get_wallet_overviews(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching wallets: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Wallets retrieved successfully",
+        "wallets": wallets
+    })))
+}
+
+/// Share the wallet with another user, or change their role
+pub async fn put_wallet_member_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<sharing_models::PutWalletMemberRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let member = require_user(&state, &req.member_email).await?;
+    if member.id == owner.id {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    sharing_queries::upsert_member(&state.db, owner.id, member.id, req.role)
+        .await
+        .map_err(|e| {
+            eprintln!("Error sharing wallet: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "wallet_member", Some(member.id))
+            .actor(owner.id)
+            .details(json!({ "role": req.role })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Wallet shared successfully",
+        "member_id": member.id,
+        "role": req.role
+    })))
+}
+
+/// Stop sharing the wallet with a user
+pub async fn delete_wallet_member_handler(
+    State(state): State<AppState>,
+    Path((email, member_email)): Path<(String, String)>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let member = require_user(&state, &member_email).await?;
+    let deleted = sharing_queries::delete_member(&state.db, owner.id, member.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error unsharing wallet: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "wallet_member", Some(member.id))
+            .actor(owner.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Wallet member removed successfully"
+    })))
+}
+
+/// Open invitations to the owner's wallet
+pub async fn get_wallet_invitations_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let invitations = invitation_queries::get_open(&state.db, Some(owner.id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching invitations: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Invitations retrieved successfully",
+        "invitations": invitations
+    })))
+}
+
+/// Invite someone to sign up and join the owner's wallet with a role
+/// The token is in the answer only, for the owner to pass on
+pub async fn create_wallet_invitation_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<invitation_models::CreateWalletInvitationRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let (token, invitation) = create_invitation(
+        &state,
+        req.invitee_email,
+        Some((owner.id, req.role)),
+        Some(owner.id),
+        req.expires_in_days,
+    )
+    .await?;
+
+    Ok(Json(json!({
+        "message": "Invitation created successfully",
+        "token": token,
+        "invitation": invitation
+    })))
+}
+
+/// Withdraw an open invitation to the owner's wallet
+pub async fn delete_wallet_invitation_handler(
+    State(state): State<AppState>,
+    Path((email, id)): Path<(String, Uuid)>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let deleted = invitation_queries::delete_open(&state.db, id, Some(owner.id))
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting invitation {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "invitation", Some(id)).actor(owner.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Invitation withdrawn successfully"
+    })))
+}
+
+/// The user's share links that still work
+pub async fn get_share_links_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let links = share_link_queries::get_active(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching share links: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Share links retrieved successfully",
+        "share_links": links
+    })))
+}
+
+/// Share a snapshot of a report with people without an account
+/// The report is computed now, the link shows it as it is until it expires or is revoked,
+/// whatever happens to the transactions. The token is in the answer only, for the user to
+/// pass on
+pub async fn create_share_link_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<share_link_models::CreateShareLinkRequest>,
+) -> Result<Response, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let filter = req.report.filter(user.id);
+    let snapshot = match &req.report {
+        share_link_models::SharedReport::Summary { .. } => {
+            let total = transaction_queries::get_user_transaction_sum(&state.db, &filter)
+                .await
+                .map_err(|e| {
+                    eprintln!("Error adding up transactions: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            let categories = transaction_queries::get_category_sums(&state.db, &filter)
+                .await
+                .map_err(|e| {
+                    eprintln!("Error adding up transactions by category: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            json!({
+                "income": total.income,
+                "expenses": total.expenses,
+                "net": total.net,
+                "categories": categories
+            })
+        }
+        share_link_models::SharedReport::Timeseries { granularity, .. } => {
+            let tz = user_queries::get_user_timezone(&state.db, user.id)
+                .await
+                .map_err(|e| {
+                    eprintln!("Error fetching user timezone: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            let sums = transaction_queries::get_timeseries(&state.db, &filter, *granularity, tz)
+                .await
+                .map_err(|e| {
+                    eprintln!("Error fetching transaction time series: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            let (start, end) = req.report.period();
+            let Some(buckets) = transaction_models::TimeseriesBucket::fill(
+                *granularity,
+                start.with_timezone(&tz).date_naive(),
+                end.with_timezone(&tz).date_naive(),
+                sums,
+            ) else {
+                let mut errors = ValidationErrors::default();
+                errors.add(
+                    "report.start_timestamp",
+                    format!(
+                        "must be at most {} {}s before end_timestamp",
+                        transaction_models::MAX_TIMESERIES_BUCKETS,
+                        granularity
+                    ),
+                );
+                return Ok(errors.into_response());
+            };
+            json!({ "buckets": buckets })
+        }
+    };
+
+    let days = req
+        .expires_in_days
+        .unwrap_or(share_link_models::DEFAULT_SHARE_LINK_DAYS);
+    let (token, token_hash) = auth::new_token();
+    let create = share_link_models::ShareLinkCreate {
+        user_id: user.id,
+        token_hash,
+        title: req.title.trim().to_string(),
+        report: req.report,
+        snapshot,
+        expires_at: chrono::Utc::now() + chrono::Duration::days(days),
+    };
+    let link = share_link_queries::create(&state.db, &create)
+        .await
+        .map_err(|e| {
+            eprintln!("Error creating share link: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "share_link", Some(link.id))
+            .actor(user.id)
+            .details(json!({
+                "report": link.report,
+                "expires_at": link.expires_at,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Share link created successfully",
+        "token": token,
+        "url": format!("/api/shared/{}", token),
+        "share_link": link
+    }))
+    .into_response())
+}
+
+/// Stop a share link of the user from working
+pub async fn revoke_share_link_handler(
+    State(state): State<AppState>,
+    Path((email, id)): Path<(String, Uuid)>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let revoked = share_link_queries::revoke(&state.db, id, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error revoking share link {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("revoke", "share_link", Some(id)).actor(user.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Share link revoked successfully"
+    })))
+}
+
+/// The report shared through a link, for anyone holding its token, no account needed
+/// Only the title, the report's parameters and its snapshot are shown, nothing about the
+/// user. 410 once the link expired or was revoked
+pub async fn get_shared_report_handler(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, StatusCode> {
+    let link = share_link_queries::get_by_hash(&state.db, &auth::hash_token(token.trim()))
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching share link: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if !link.is_active(chrono::Utc::now()) {
+        return Ok((
+            StatusCode::GONE,
+            Json(json!({
+                "message": "This share link expired or was revoked",
+                "code": problems::ErrorCode::ShareLinkExpired
+            })),
+        )
+            .into_response());
+    }
+
+    // Revoking must take effect at once, the report is not to be kept by caches
+    Ok((
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(json!({
+            "message": "Shared report retrieved successfully",
+            "title": link.title,
+            "report": link.report,
+            "snapshot_at": link.created_at,
+            "expires_at": link.expires_at,
+            "snapshot": link.snapshot
+        })),
+    )
+        .into_response())
+}
+
+/// Amount above which transactions of editors need the owner's approval
+pub async fn get_approval_policy_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let policy = sharing_queries::get_policy(&state.db, owner.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching approval policy: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Approval policy retrieved successfully",
+        "approval_policy": policy
+    })))
+}
+
+/// Require approval of editors' transactions above an amount, 0 requires it for all of them
+pub async fn put_approval_policy_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(policy): ValidJson<sharing_models::ApprovalPolicy>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    sharing_queries::set_policy(&state.db, owner.id, &policy)
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing approval policy: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "approval_policy", Some(owner.id))
+            .actor(owner.id)
+            .details(json!({ "threshold": policy.threshold })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Approval policy stored successfully",
+        "approval_policy": policy
+    })))
+}
+
+/// Let editors record transactions of any amount without approval again
+pub async fn delete_approval_policy_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = require_user(&state, &email).await?;
+    let deleted = sharing_queries::delete_policy(&state.db, owner.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting approval policy: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "approval_policy", Some(owner.id))
+            .actor(owner.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Approval policy deleted successfully"
+    })))
+}
+
+/// List transactions of editors held for the owner's approval, pending ones by default
+pub async fn get_transaction_approvals_handler(
+    State(state): State<AppState>,
+    Query(params): Query<sharing_models::ApprovalGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let status = params
+        .status
+        .unwrap_or(sharing_models::ApprovalStatus::Pending);
+    let approvals = sharing_queries::get_approvals(&state.db, params.user_id, status)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transaction approvals: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Transaction approvals retrieved successfully",
+        "approvals": approvals
+    })))
+}
+
+/// Record a held transaction in the owner's wallet
+pub async fn approve_transaction_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let approval = sharing_queries::get_approval_by_id(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transaction approval {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if approval.status != sharing_models::ApprovalStatus::Pending {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    // Spending may have grown since the editor recorded it, approving is the owner
+    // confirming any override
+    let transaction = transaction_models::TransactionCreate::new(
+        approval.owner_id,
+        approval.transaction_type.clone(),
+        approval.amount,
+        Some(approval.category.clone()),
+        approval.description.clone(),
+    )
+    .occurred_at(Some(approval.created_at))
+    .account(approval.account_id);
+    let checked = check_restrictions(&state, std::slice::from_ref(&transaction), true).await?;
+    if let Some(refusal) = checked.refusal() {
+        return Ok(refusal.into_response());
+    }
+    if let Some(exceeded) = reserve_quota(&state, approval.owner_id, 1).await? {
+        return Ok(exceeded);
+    }
+    let approved = sharing_queries::approve(&state.db, id).await;
+    if !matches!(approved, Ok(Some(Some(_)))) {
+        quotas::release_transactions(&state.db, approval.owner_id, 1).await;
+    }
+    let transaction_id = approved
+        .map_err(|e| {
+            eprintln!("Error approving transaction {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    let details = json!({
+        "id": id,
+        "transaction_id": transaction_id,
+        "transaction_type": approval.transaction_type,
+        "amount": approval.amount,
+        "category": approval.category,
+        "source": approval.source,
+    });
+    restrictions::record_overrides(
+        &state.db,
+        approval.owner_id,
+        std::slice::from_ref(&transaction),
+        &checked,
+    )
+    .await;
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("approve", "transaction_approval", Some(id))
+            .actor(approval.owner_id)
+            .details(details.clone()),
+    )
+    .await;
+    webhooks::enqueue(
+        &state,
+        approval.requested_by,
+        "transaction.approved",
+        details,
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Transaction approved",
+        "transaction_id": transaction_id
+    }))
+    .into_response())
+}
+
+/// Turn down a held transaction, telling the editor why if a reason is given
+pub async fn reject_transaction_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    ValidJson(req): ValidJson<sharing_models::RejectApprovalRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let approval = sharing_queries::get_approval_by_id(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transaction approval {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let rejected = sharing_queries::reject(&state.db, id, req.reason.as_deref())
+        .await
+        .map_err(|e| {
+            eprintln!("Error rejecting transaction {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !rejected {
+        // Already approved or rejected
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let details = json!({
+        "id": id,
+        "transaction_type": approval.transaction_type,
+        "amount": approval.amount,
+        "category": approval.category,
+        "reason": req.reason,
+    });
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("reject", "transaction_approval", Some(id))
+            .actor(approval.owner_id)
+            .details(details.clone()),
+    )
+    .await;
+    webhooks::enqueue(
+        &state,
+        approval.requested_by,
+        "transaction.rejected",
+        details,
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Transaction rejected"
+    })))
+}
+
+/// List the parent's child accounts
+pub async fn get_children_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let parent = require_user(&state, &email).await?;
+    let children = family_queries::get_children(&state.db, parent.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching child accounts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Child accounts retrieved successfully",
+        "children": children
+    })))
+}
+
+/// Link a child account to the parent, or change its allowance and allowed categories
+pub async fn put_child_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<family_models::PutChildAccountRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let parent = require_user(&state, &email).await?;
+    let child = require_user(&state, &req.child_email).await?;
+    if child.id == parent.id {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let linked = family_queries::upsert_child(
+        &state.db,
+        parent.id,
+        child.id,
+        req.allowance,
+        req.allowed_categories.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error linking child account: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if !linked {
+        // The child already has another parent
+        return Err(StatusCode::CONFLICT);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "child_account", Some(child.id))
+            .actor(parent.id)
+            .details(json!({
+                "allowance": req.allowance,
+                "allowed_categories": req.allowed_categories,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Child account stored successfully",
+        "child_id": child.id
+    })))
+}
+
+/// Unlink a child account, which keeps its transactions
+pub async fn delete_child_handler(
+    State(state): State<AppState>,
+    Path((email, child_email)): Path<(String, String)>,
+) -> Result<Json<Value>, StatusCode> {
+    let parent = require_user(&state, &email).await?;
+    let child = require_user(&state, &child_email).await?;
+    let deleted = family_queries::delete_child(&state.db, parent.id, child.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error unlinking child account: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "child_account", Some(child.id))
+            .actor(parent.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Child account unlinked successfully"
+    })))
+}
+
+/// A child's transactions, for their parent
+pub async fn get_child_transactions_handler(
+    State(state): State<AppState>,
+    Path((email, child_email)): Path<(String, String)>,
+) -> Result<Json<Value>, StatusCode> {
+    let parent = require_user(&state, &email).await?;
+    let child = require_user(&state, &child_email).await?;
+    let link = family_queries::get_child(&state.db, child.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching child account: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if link.is_none_or(|link| link.parent_id != parent.id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let filter = transaction_models::TransactionFilter::for_user(child.id);
+    let transactions = transaction_queries::get_transactions(&state.db, &filter)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching child transactions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Transactions retrieved successfully",
+        "transactions": transactions
+    })))
+}
+
+pub async fn get_accounts_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let accounts = account_queries::get_accounts(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching accounts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Accounts retrieved successfully",
+        "accounts": accounts
+    })))
+}
+
+/// Open an account, e.g. Checking, Cash or Savings, its name must be new to the user
+pub async fn create_account_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<account_models::CreateAccountRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let account = account_queries::create_account(
+        &state.db,
+        user.id,
+        req.name.trim(),
+        req.currency.unwrap_or_default(),
+        req.opening_balance.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error creating account: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::CONFLICT)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "account", Some(account.id))
+            .actor(user.id)
+            .details(json!({ "opening_balance": account.opening_balance })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Account created successfully",
+        "account": account
+    })))
+}
+
+pub async fn get_account_handler(
+    State(state): State<AppState>,
+    Path((email, id)): Path<(String, Uuid)>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let account = account_queries::get_account(&state.db, id, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching account {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Account retrieved successfully",
+        "account": account
+    })))
+}
+
+/// Close an account, only possible while no transactions are booked to it
+pub async fn delete_account_handler(
+    State(state): State<AppState>,
+    Path((email, id)): Path<(String, Uuid)>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    account_queries::get_account(&state.db, id, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching account {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let deleted = account_queries::delete_account(&state.db, id, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting account {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "account", Some(id)).actor(user.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Account deleted successfully"
+    })))
+}
+
+/// Move money between two of the user's accounts, converting it when their currencies differ
+/// at the amount that arrived, the rate given, or else the latest known exchange rate
+pub async fn create_transfer_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<account_models::CreateTransferRequest>,
+) -> Result<Response, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let mut currencies = Vec::with_capacity(2);
+    let mut errors = ValidationErrors::default();
+    for (field, id) in [
+        ("from_account_id", req.from_account_id),
+        ("to_account_id", req.to_account_id),
+    ] {
+        let account = account_queries::get_account(&state.db, id, user.id)
+            .await
+            .map_err(|e| {
+                eprintln!("Error fetching account {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        match account {
+            Some(account) => currencies.push(account.balance.currency),
+            None => errors.add(field, "must be one of the user's accounts"),
+        }
+    }
+    let [from, to] = currencies[..] else {
+        return Ok(errors.into_response());
+    };
+
+    // Rates are only looked up when the client gave neither the amount arrived nor the rate
+    let latest_rate = if from != to && req.to_amount.is_none() && req.rate.is_none() {
+        let rates = fx_queries::get_latest_rates(&state.db).await.map_err(|e| {
+            eprintln!("Error fetching exchange rates: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        fx::cross_rate(&rates, from, to)
+    } else {
+        None
+    };
+    let Some((amount, to_amount, rate)) = req.amounts(from, to, latest_rate, &mut errors) else {
+        return Ok(errors.into_response());
+    };
+
+    let transfer = account_models::TransferCreate {
+        user_id: user.id,
+        from_account_id: req.from_account_id,
+        to_account_id: req.to_account_id,
+        amount,
+        to_amount,
+        rate,
+        description: req.description.unwrap_or_default(),
+    };
+    // The accounts were the user's a moment ago, only a concurrent deletion gets here
+    let transfer = account_queries::create_transfer(&state.db, &transfer)
+        .await
+        .map_err(|e| {
+            eprintln!("Error recording transfer: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "transfer", Some(transfer.id))
+            .actor(user.id)
+            .details(json!({
+                "amount": transfer.amount,
+                "to_amount": transfer.to_amount,
+                "rate": transfer.rate
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Transfer recorded successfully",
+        "transfer": transfer
+    }))
+    .into_response())
+}
+
+pub async fn get_transfers_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Query(params): Query<account_models::TransferGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let transfers = account_queries::get_transfers(&state.db, user.id, params.account_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching transfers: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Transfers retrieved successfully",
+        "transfers": transfers
+    })))
+}
+
+pub async fn create_savings_goal_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<savings_models::CreateSavingsGoalRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &req.user_email).await?;
+    let goal = savings_queries::create_goal(
+        &state.db,
+        user.id,
+        req.name.trim(),
+        req.currency.unwrap_or_default(),
+        req.target,
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("Error creating savings goal: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "savings_goal", Some(goal.id))
+            .actor(user.id)
+            .details(json!({ "target": goal.target })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Savings goal created successfully",
+        "savings_goal": goal
+    })))
+}
+
+pub async fn get_savings_goals_handler(
+    State(state): State<AppState>,
+    Query(params): Query<savings_models::SavingsGoalGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let goals = savings_queries::get_goals(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching savings goals: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Savings goals retrieved successfully",
+        "savings_goals": goals
+    })))
+}
+
+/// Round-ups set aside in a goal per month of a year, in the user's time zone
+pub async fn get_round_up_report_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<savings_models::RoundUpReportParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let goal = savings_queries::get_goal(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching savings goal {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let tz = user_queries::get_user_timezone(&state.db, goal.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching user timezone: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let year = params
+        .year
+        .unwrap_or_else(|| chrono::Utc::now().with_timezone(&tz).year());
+
+    let currency = goal.balance.currency;
+    let months = savings_queries::get_monthly_round_ups(&state.db, id, currency, tz.name(), year)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching round-ups of goal {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let total = Money::new(months.iter().map(|m| m.amount.minor_units).sum(), currency);
+
+    Ok(Json(json!({
+        "message": "Round-ups retrieved successfully",
+        "savings_goal": goal,
+        "year": year,
+        "months": months,
+        "total": total
+    })))
+}
+
+pub async fn get_round_up_rule_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let rule = savings_queries::get_rule(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching round-up rule: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "message": "Round-up rule retrieved successfully",
+        "round_up": rule
+    })))
+}
+
+/// Round up the user's expenses from now on into one of their savings goals
+pub async fn put_round_up_rule_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    ValidJson(req): ValidJson<savings_models::PutRoundUpRuleRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let unit = req.unit.unwrap_or(rust_decimal::Decimal::ONE);
+    let rule = savings_queries::upsert_rule(&state.db, user.id, req.goal_id, unit)
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing round-up rule: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        // Not one of the user's goals
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "round_up_rule", Some(user.id))
+            .actor(user.id)
+            .details(json!({ "goal_id": rule.goal_id, "unit": rule.unit })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Round-up rule stored successfully",
+        "round_up": rule
+    })))
+}
+
+pub async fn delete_round_up_rule_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let deleted = savings_queries::delete_rule(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting round-up rule: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "round_up_rule", Some(user.id))
+            .actor(user.id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Round-up rule deleted successfully"
+    })))
+}
+
+/// Register a URL to be notified of changes to the user's wallet
+/// The signing secret is only returned here, deliveries carry its signature in X-Wallet-Signature
+pub async fn create_webhook_endpoint_handler(
+    State(state): State<AppState>,
+    ValidJson(req): ValidJson<webhook_models::CreateWebhookEndpointRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let exists = user_queries::user_exists(&state.db, req.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking user for webhook endpoint: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let secret = format!("whsec_{}", secret);
+    let endpoint = webhook_queries::create_endpoint(&state.db, req.user_id, &req.url, &secret)
+        .await
+        .map_err(|e| {
+            eprintln!("Error creating webhook endpoint: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("create", "webhook_endpoint", Some(endpoint.id))
+            .actor(req.user_id),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Webhook endpoint created successfully",
+        "endpoint": endpoint,
+        "secret": secret
+    })))
+}
+
+pub async fn get_webhook_endpoints_handler(
+    State(state): State<AppState>,
+    Query(params): Query<webhook_models::WebhookEndpointGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let endpoints = webhook_queries::get_endpoints(&state.db, params.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching webhook endpoints: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Webhook endpoints retrieved successfully",
+        "endpoints": endpoints
+    })))
+}
+
+/// Remove an endpoint, its queued and failed deliveries go with it
+pub async fn delete_webhook_endpoint_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let deleted = webhook_queries::delete_endpoint(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting webhook endpoint {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("delete", "webhook_endpoint", Some(id)),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Webhook endpoint deleted"
+    })))
+}
+
+/// List deliveries to a user's endpoints, the failed ones (dead letters) by default
+pub async fn get_webhook_deliveries_handler(
+    State(state): State<AppState>,
+    Query(params): Query<webhook_models::WebhookDeliveryGetParameters>,
+) -> Result<Json<Value>, StatusCode> {
+    let status = params
+        .status
+        .unwrap_or(webhook_models::DeliveryStatus::Failed);
+    let deliveries = webhook_queries::get_deliveries(&state.db, params.user_id, status)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching webhook deliveries: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Webhook deliveries retrieved successfully",
+        "deliveries": deliveries
+    })))
+}
+
+/// Queue a failed delivery again, with a fresh set of attempts
+pub async fn retry_webhook_delivery_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let retried = webhook_queries::retry_delivery(&state.db, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error retrying webhook delivery {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !retried {
+        // Either unknown or not failed (still pending or already delivered)
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(json!({
+        "message": "Webhook delivery queued for retry"
+    })))
+}
+
+pub async fn get_notification_preferences_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let preferences = notification_queries::get_preferences(&state.db, user.id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching notification preferences: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Notification preferences retrieved successfully",
+        "notification_preferences": preferences
+    })))
+}
+
+/// Opt into or out of the weekly digest of shared wallets, and pick where it is delivered
+pub async fn put_notification_preferences_handler(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(req): Json<notification_models::PutNotificationPreferencesRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user = require_user(&state, &email).await?;
+    let preferences = notification_queries::upsert_preferences(&state.db, user.id, &req)
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing notification preferences: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    record_audit(
+        &state,
+        audit_models::AuditEntryCreate::new("update", "notification_preferences", Some(user.id))
+            .actor(user.id)
+            .details(json!({
+                "weekly_digest": preferences.weekly_digest,
+                "channel": preferences.channel,
+            })),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "message": "Notification preferences stored successfully",
+        "notification_preferences": preferences
+    })))
 }
@@ -0,0 +1,805 @@
+// Importers for the CSV exports of other budgeting apps (YNAB, Mint), so people
+// switching over can bring years of history along in one request
+//
+// Each app lives in its own module turning one CSV row into a `RowKind`. Rows
+// become transactions in the user's time zone with an external id derived from
+// the row, so importing the same export twice does not duplicate anything.
+//
+// Exports can hold years of history, so the upload is only stored and an
+// "imports.run" job does the import, recording its progress on the import for
+// clients polling GET /api/imports/:id. Rows that were not imported (unreadable,
+// duplicates or refused by the user's spending limits) are kept as a CSV to fix and
+// upload again, GET /api/imports/:id/rejected.csv.
+//
+// Bank statements are imported within the request, POST /api/transactions/import: OFX
+// and QIF downloads (`ofx`, `qif`) and any other CSV file with a column mapping (`mapped`).
+
+pub mod mapped;
+mod mint;
+pub mod ofx;
+pub mod qif;
+mod ynab;
+
+use crate::database::DbPool;
+use crate::dedup::{self, DuplicateReason, ImportSummary, SkippedDuplicate};
+use crate::ingest::parsers::parse_number;
+use crate::jobs::JobHandler;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::import_models::{ImportProgress, ImportQuery, ImportStatus};
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::{
+    self, TransactionCategory, TransactionCreate, TransactionSource, TransactionType,
+};
+use crate::queries::{audit_queries, import_queries, user_queries, webhook_queries};
+use crate::quotas;
+use crate::restrictions::{self, Refusal};
+use crate::storage::{self, BlobStore};
+use crate::validation::{MAX_DESCRIPTION_LEN, ValidationErrors};
+use axum::async_trait;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Largest export accepted in one request
+pub const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// Most rows accepted in one export, a decade of daily spending fits comfortably
+pub const MAX_ROWS: usize = 100_000;
+
+/// Transactions inserted between two progress updates of an import
+const PROGRESS_BATCH_ROWS: usize = 500;
+
+/// Columns added to the rows of a rejected rows report
+const REPORT_COLUMNS: &[&str] = &["Rejected Line", "Rejected Reason", "Rejected Message"];
+
+/// The apps whose exports can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// The "Export budget data" register CSV of YNAB (and the older YNAB 4)
+    Ynab,
+    /// The transactions.csv download of Mint
+    Mint,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Ynab, ExportFormat::Mint];
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ExportFormat::Ynab => "ynab",
+            ExportFormat::Mint => "mint",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ynab" => Ok(ExportFormat::Ynab),
+            "mint" => Ok(ExportFormat::Mint),
+            _ => Err(format!("Unknown export format: {}", s)),
+        }
+    }
+}
+
+/// Formats of the bank statements imported within the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    /// Any CSV file, read with a `mapped::ColumnMapping`
+    Csv,
+    /// OFX 1.x and 2.x, also as the .qfx of Quicken
+    Ofx,
+    Qif,
+}
+
+impl StatementFormat {
+    pub const ALL: [StatementFormat; 3] = [
+        StatementFormat::Csv,
+        StatementFormat::Ofx,
+        StatementFormat::Qif,
+    ];
+
+    /// The format a file's name tells, by its extension
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        Self::from_str(extension).ok()
+    }
+}
+
+impl fmt::Display for StatementFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            StatementFormat::Csv => "csv",
+            StatementFormat::Ofx => "ofx",
+            StatementFormat::Qif => "qif",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(StatementFormat::Csv),
+            "ofx" | "qfx" => Ok(StatementFormat::Ofx),
+            "qif" => Ok(StatementFormat::Qif),
+            _ => Err(format!("Unknown statement format: {}", s)),
+        }
+    }
+}
+
+/// A spending or income row of an export
+#[derive(Debug, Clone)]
+pub struct ExportRow {
+    pub date: NaiveDate,
+    pub transaction_type: TransactionType,
+    /// Unsigned, the sign follows from the type
+    pub amount: Money,
+    /// None when the row had no category, or none we could tell
+    pub category: Option<TransactionCategory>,
+    pub description: String,
+    /// Account the row was booked on in the other app, part of the row's identity
+    pub account: String,
+    /// Id the bank gave the row (the FITID of OFX), unique within its account
+    pub external_id: Option<String>,
+}
+
+/// What the row starting on a line stands for, or why it cannot be imported
+type LineRow = (u64, Result<RowKind, (RejectReason, String)>);
+
+/// What one row of an export stands for
+#[derive(Debug)]
+pub enum RowKind {
+    Transaction(ExportRow),
+    /// Money moved between the user's own accounts, neither income nor spending
+    Transfer,
+    /// Bookkeeping rows such as opening balances, which are not transactions
+    Ignored,
+}
+
+/// Why a row of an export was not imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    BadDate,
+    InvalidAmount,
+    /// Already imported, by an earlier upload or as an existing transaction
+    Duplicate,
+    /// Readable but not allowed, e.g. an expense going over a spending limit
+    Refused,
+    /// Anything else that makes the row unreadable, e.g. an unknown transaction type
+    Invalid,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RejectReason::BadDate => "bad_date",
+            RejectReason::InvalidAmount => "invalid_amount",
+            RejectReason::Duplicate => "duplicate",
+            RejectReason::Refused => "refused",
+            RejectReason::Invalid => "invalid",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A row that was not imported, reported back by its line in the file
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    pub line: u64,
+    pub reason: RejectReason,
+    pub message: String,
+}
+
+impl RowError {
+    /// A candidate skipped as a duplicate, `lines` being the line of each candidate
+    pub fn duplicate(skipped: &SkippedDuplicate, lines: &[u64]) -> Self {
+        let message = match (&skipped.reason, skipped.matched_transaction_id) {
+            (DuplicateReason::Fuzzy, Some(id)) => {
+                format!("looks like existing transaction {}", id)
+            }
+            (DuplicateReason::Fuzzy, None) => "looks like an existing transaction".to_string(),
+            (DuplicateReason::RepeatedInBatch, _) => "repeats an earlier row".to_string(),
+            (DuplicateReason::ExternalId, _) => "already imported".to_string(),
+        };
+        Self {
+            line: lines.get(skipped.row).copied().unwrap_or_default(),
+            reason: RejectReason::Duplicate,
+            message,
+        }
+    }
+}
+
+impl RowError {
+    /// A candidate refused by `restrictions::check`, `lines` being the line of each candidate
+    pub fn refused(refusal: &Refusal, lines: &[u64]) -> Self {
+        Self {
+            line: lines.get(refusal.row()).copied().unwrap_or_default(),
+            reason: RejectReason::Refused,
+            message: refusal.to_string(),
+        }
+    }
+}
+
+/// The readable rows of an export, and what happened to the others
+#[derive(Debug, Default)]
+pub struct ParsedExport {
+    /// Rows with the line they were read from
+    pub rows: Vec<(u64, ExportRow)>,
+    pub transfers: usize,
+    pub ignored: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Column positions by header name, looked up case-insensitively
+pub struct Columns(HashMap<String, usize>);
+
+impl Columns {
+    pub fn new(headers: &StringRecord) -> Self {
+        Self(
+            headers
+                .iter()
+                .enumerate()
+                // Excel and YNAB write a byte order mark before the first header
+                .map(|(i, name)| (name.trim_start_matches('\u{feff}').trim().to_lowercase(), i))
+                .collect(),
+        )
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.0.contains_key(&name.to_lowercase())
+    }
+
+    /// The trimmed value of a column, empty when the column or the cell is missing
+    pub fn get<'a>(&self, record: &'a StringRecord, name: &str) -> &'a str {
+        self.0
+            .get(&name.to_lowercase())
+            .and_then(|i| record.get(*i))
+            .map(str::trim)
+            .unwrap_or_default()
+    }
+}
+
+/// Read an export, amounts are taken to be in `currency`
+///
+/// # Errors
+/// Returns an error when the file is not a CSV export of `format`, unreadable
+/// rows are reported in `ParsedExport::errors` instead
+pub fn parse(format: ExportFormat, text: &str, currency: Currency) -> Result<ParsedExport, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns = Columns::new(reader.headers().map_err(|e| e.to_string())?);
+    let required = match format {
+        ExportFormat::Ynab => ynab::REQUIRED_COLUMNS,
+        ExportFormat::Mint => mint::REQUIRED_COLUMNS,
+    };
+    let missing: Vec<&str> = required
+        .iter()
+        .copied()
+        .filter(|name| !columns.has(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Not a {} export, missing column(s): {}",
+            format,
+            missing.join(", ")
+        ));
+    }
+
+    read_rows(&mut reader, MAX_ROWS, |record| match format {
+        ExportFormat::Ynab => ynab::parse_row(&columns, record, currency),
+        ExportFormat::Mint => mint::parse_row(&columns, record, currency),
+    })
+}
+
+/// Read a CSV with the columns of `mapping`, amounts are taken to be in `currency`
+///
+/// # Errors
+/// Returns an error when a mapped column is missing or the file has more than `max_rows`
+/// rows, unreadable rows are reported in `ParsedExport::errors` instead
+pub fn parse_mapped(
+    text: &str,
+    mapping: &mapped::ColumnMapping,
+    currency: Currency,
+    max_rows: usize,
+) -> Result<ParsedExport, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns = Columns::new(reader.headers().map_err(|e| e.to_string())?);
+    let missing: Vec<&str> = mapping
+        .required_columns()
+        .into_iter()
+        .filter(|name| !columns.has(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Missing column(s): {}", missing.join(", ")));
+    }
+
+    read_rows(&mut reader, max_rows, |record| {
+        mapped::parse_row(&columns, record, mapping, currency)
+    })
+}
+
+/// Read a bank statement, amounts are taken to be in `currency` unless the file tells
+/// theirs, CSV files are read with `mapping`
+///
+/// # Errors
+/// Returns an error when the file is not a statement of `format` or has more than
+/// `max_rows` rows, unreadable rows are reported in `ParsedExport::errors` instead
+pub fn parse_statement(
+    format: StatementFormat,
+    text: &str,
+    mapping: &mapped::ColumnMapping,
+    currency: Currency,
+    max_rows: usize,
+) -> Result<ParsedExport, String> {
+    let rows = match format {
+        StatementFormat::Csv => return parse_mapped(text, mapping, currency, max_rows),
+        StatementFormat::Ofx => ofx::parse_rows(text, currency)?,
+        StatementFormat::Qif => qif::parse_rows(text, currency)?,
+    };
+    collect_rows(rows.into_iter(), max_rows)
+}
+
+/// Turn each row after the headers into a `RowKind` with `parse_row`, skipping blank ones
+fn read_rows(
+    reader: &mut csv::Reader<&[u8]>,
+    max_rows: usize,
+    parse_row: impl Fn(&StringRecord) -> Result<RowKind, (RejectReason, String)>,
+) -> Result<ParsedExport, String> {
+    let rows = reader.records().filter_map(|record| match record {
+        Ok(record) if record.iter().all(|field| field.trim().is_empty()) => None,
+        Ok(record) => {
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            Some((line, parse_row(&record)))
+        }
+        Err(e) => {
+            let line = e.position().map(|p| p.line()).unwrap_or_default();
+            Some((line, Err((RejectReason::Invalid, e.to_string()))))
+        }
+    });
+    collect_rows(rows, max_rows)
+}
+
+/// Sort what each row of a file stands for, by the line it starts on, into a `ParsedExport`
+fn collect_rows(
+    rows: impl Iterator<Item = (u64, Result<RowKind, (RejectReason, String)>)>,
+    max_rows: usize,
+) -> Result<ParsedExport, String> {
+    let mut parsed = ParsedExport::default();
+    for (i, (line, kind)) in rows.enumerate() {
+        if i >= max_rows {
+            return Err(format!("Files may have at most {} rows", max_rows));
+        }
+        match kind.and_then(check_row) {
+            Ok(RowKind::Transaction(row)) => parsed.rows.push((line, row)),
+            Ok(RowKind::Transfer) => parsed.transfers += 1,
+            Ok(RowKind::Ignored) => parsed.ignored += 1,
+            Err((reason, message)) => parsed.errors.push(RowError {
+                line,
+                reason,
+                message,
+            }),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Apply the bounds every transaction has to the row
+fn check_row(kind: RowKind) -> Result<RowKind, (RejectReason, String)> {
+    if let RowKind::Transaction(row) = &kind {
+        let mut errors = ValidationErrors::default();
+        errors.check_amount("amount", row.amount);
+        if !errors.is_empty() {
+            return Err((
+                RejectReason::InvalidAmount,
+                format!("amount {} is out of range", row.amount.to_decimal()),
+            ));
+        }
+        errors.check_length("description", &row.description, MAX_DESCRIPTION_LEN);
+        if !errors.is_empty() {
+            return Err((
+                RejectReason::Invalid,
+                format!(
+                    "description is longer than {} characters",
+                    MAX_DESCRIPTION_LEN
+                ),
+            ));
+        }
+    }
+    Ok(kind)
+}
+
+/// The rejected rows of an export as a CSV to fix and upload again: the export's own
+/// columns, then the line each row was on and why it was rejected, which imports ignore
+pub fn rejected_rows_csv(text: &str, rejected: &[RowError]) -> Result<Vec<u8>, csv::Error> {
+    let by_line: HashMap<u64, &RowError> = rejected.iter().map(|e| (e.line, e)).collect();
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(headers.iter().chain(REPORT_COLUMNS.iter().copied()))?;
+
+    for record in reader.records() {
+        // Rows the csv reader could not make out are reported without their cells
+        let (line, record) = match record {
+            Ok(record) => (record.position().map(|p| p.line()), record),
+            Err(e) => (e.position().map(|p| p.line()), StringRecord::new()),
+        };
+        let Some(error) = line.and_then(|line| by_line.get(&line)) else {
+            continue;
+        };
+        let cells = (0..headers.len()).map(|i| record.get(i).unwrap_or_default());
+        let line = error.line.to_string();
+        let reason = error.reason.to_string();
+        writer.write_record(cells.chain([
+            line.as_str(),
+            reason.as_str(),
+            error.message.as_str(),
+        ]))?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+impl ParsedExport {
+    /// Transactions for the rows, dated at the start of their day in `tz`, in
+    /// `default_category` when the row's category could not be told
+    /// External ids hash the row's id at the bank when it has one, the row itself otherwise
+    /// with a counter for identical rows (two coffees on the same day), so the same rows
+    /// always get the same ids
+    pub fn into_transactions(
+        self,
+        format: impl fmt::Display,
+        user_id: Uuid,
+        tz: Tz,
+        bank_account_id: Option<Uuid>,
+        default_category: &TransactionCategory,
+    ) -> Vec<TransactionCreate> {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        self.rows
+            .into_iter()
+            .map(|(_, row)| {
+                let external_id = match &row.external_id {
+                    // Unique per account at the bank
+                    Some(id) => format!(
+                        "{}:{:x}",
+                        format,
+                        Sha256::digest(format!("{}|{}", row.account, id).as_bytes())
+                    ),
+                    None => {
+                        let digest = format!(
+                            "{:x}",
+                            Sha256::digest(
+                                format!(
+                                    "{}|{}|{}|{}|{}",
+                                    row.date,
+                                    row.account,
+                                    row.transaction_type,
+                                    row.amount.to_decimal(),
+                                    row.description
+                                )
+                                .as_bytes()
+                            )
+                        );
+                        let occurrence = seen.entry(digest.clone()).or_default();
+                        *occurrence += 1;
+                        format!("{}:{}:{}", format, digest, occurrence)
+                    }
+                };
+
+                TransactionCreate::new(
+                    user_id,
+                    row.transaction_type,
+                    row.amount,
+                    Some(row.category.unwrap_or_else(|| default_category.clone())),
+                    Some(row.description),
+                )
+                .with_origin(TransactionSource::Import, Some(external_id))
+                .occurred_at(Some(transaction_models::local_midnight(row.date, tz)))
+                .bank_account(bank_account_id)
+            })
+            .collect()
+    }
+}
+
+/// Job importing one uploaded export, queued by `POST /api/imports/:format`
+pub struct RunImportJob {
+    pub store: Arc<dyn BlobStore>,
+    /// Category of rows without one, or with one we could not tell
+    pub default_category: TransactionCategory,
+}
+
+impl RunImportJob {
+    /// Import the export, an inner error is a reason to show the user why it was refused
+    async fn import(
+        &self,
+        db: &DbPool,
+        import: &ImportQuery,
+        progress: &mut ImportProgress,
+    ) -> anyhow::Result<Result<(), String>> {
+        let format = ExportFormat::from_str(&import.format).map_err(|e| anyhow::anyhow!(e))?;
+        let body = self
+            .store
+            .get(&import.storage_key)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Upload of import {} is missing", import.id))?;
+        let body = storage::read_to_end(body, MAX_BODY_BYTES).await?;
+        let Ok(text) = String::from_utf8(body) else {
+            return Ok(Err("The export is not UTF-8 text".to_string()));
+        };
+        let mut parsed = match parse(format, &text, import.currency) {
+            Ok(parsed) => parsed,
+            Err(reason) => return Ok(Err(reason)),
+        };
+
+        let tz = user_queries::get_user_timezone(db, import.user_id).await?;
+        let mut rejected = std::mem::take(&mut parsed.errors);
+        progress.transfers_skipped = parsed.transfers as i32;
+        progress.other_skipped = parsed.ignored as i32;
+        let lines: Vec<u64> = parsed.rows.iter().map(|(line, _)| *line).collect();
+        let candidates = parsed.into_transactions(
+            format,
+            import.user_id,
+            tz,
+            import.bank_account_id,
+            &self.default_category,
+        );
+        progress.total_rows = candidates.len() as i32;
+        import_queries::set_progress(db, import.id, progress, Some(&json!(rejected))).await?;
+
+        let reserved = candidates.len() as i64;
+        if let Some(exceeded) = quotas::reserve_transactions(db, import.user_id, reserved).await? {
+            return Ok(Err(exceeded.to_string()));
+        }
+        let inserted = self.insert(db, import, &candidates, progress).await;
+        quotas::release_transactions(db, import.user_id, reserved - progress.inserted as i64).await;
+        let (duplicates, refused) = inserted?;
+
+        rejected.extend(duplicates.iter().map(|d| RowError::duplicate(d, &lines)));
+        rejected.extend(refused.iter().map(|r| RowError::refused(r, &lines)));
+        rejected.sort_by_key(|e| e.line);
+        if let Err(e) = self.store_report(db, import, &text, &rejected).await {
+            eprintln!("Error storing rejected rows of import {}: {}", import.id, e);
+        }
+        Ok(Ok(()))
+    }
+
+    /// Keep the rows that were not imported for `GET /api/imports/:id/rejected.csv`
+    async fn store_report(
+        &self,
+        db: &DbPool,
+        import: &ImportQuery,
+        text: &str,
+        rejected: &[RowError],
+    ) -> anyhow::Result<()> {
+        if rejected.is_empty() {
+            return Ok(());
+        }
+        let report = rejected_rows_csv(text, rejected)?;
+        let key = import.report_key();
+        self.store
+            .put(
+                &key,
+                "text/csv; charset=utf-8",
+                storage::bytes_stream(report),
+            )
+            .await?;
+        import_queries::set_rejected_rows(db, import.id, rejected.len() as i32).await
+    }
+
+    /// Insert the transactions that are neither duplicates nor refused by the user's
+    /// spending limits, batch by batch, returning the candidates skipped and refused
+    async fn insert(
+        &self,
+        db: &DbPool,
+        import: &ImportQuery,
+        candidates: &[TransactionCreate],
+        progress: &mut ImportProgress,
+    ) -> anyhow::Result<(Vec<SkippedDuplicate>, Vec<Refusal>)> {
+        // Duplicates are found over the whole export first, as a single import would
+        let (mut to_insert, skipped_duplicates) =
+            dedup::find_duplicates(db, import.user_id, TransactionSource::Import, candidates)
+                .await?;
+        // Exports are imported without confirming overrides, rows over a limit are rejected
+        let checked = restrictions::check_rows(db, candidates, &to_insert, false).await?;
+        to_insert.retain(|&row| !checked.is_refused(row));
+        let refused = checked.refused.len() as i32;
+        let mut summary = ImportSummary {
+            inserted: 0,
+            skipped_duplicates,
+        };
+        progress.duplicates_skipped = summary.skipped_duplicates.len() as i32;
+        progress.rows_processed = progress.duplicates_skipped + refused;
+        import_queries::set_progress(db, import.id, progress, None).await?;
+
+        for batch in to_insert.chunks(PROGRESS_BATCH_ROWS) {
+            let inserted = dedup::insert_candidates(db, candidates, batch, &mut summary).await;
+            progress.inserted = summary.inserted as i32;
+            progress.duplicates_skipped = summary.skipped_duplicates.len() as i32;
+            progress.rows_processed = progress.inserted + progress.duplicates_skipped + refused;
+            inserted?;
+            import_queries::set_progress(db, import.id, progress, None).await?;
+        }
+        Ok((summary.skipped_duplicates, checked.refused))
+    }
+}
+
+#[async_trait]
+impl JobHandler for RunImportJob {
+    fn kind(&self) -> &'static str {
+        "imports.run"
+    }
+
+    /// Not retried: a failed import is reported as such and can be uploaded again, which
+    /// imports only what is still missing
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()> {
+        let id: Uuid = payload
+            .get("import_id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("Missing import_id"))?
+            .parse()?;
+        // Deleted along with its user in the meantime
+        let Some(import) = import_queries::get_import(db, id).await? else {
+            return Ok(());
+        };
+        import_queries::start(db, id).await?;
+
+        let mut progress = ImportProgress::default();
+        let failure = match self.import(db, &import, &mut progress).await {
+            Ok(Ok(())) => None,
+            Ok(Err(reason)) => Some(reason),
+            Err(e) => {
+                eprintln!("Error running import {}: {}", id, e);
+                Some(
+                    "The import failed unexpectedly, uploading the export again is safe"
+                        .to_string(),
+                )
+            }
+        };
+        if let Err(e) = import_queries::set_progress(db, id, &progress, None).await {
+            eprintln!("Error recording progress of import {}: {}", id, e);
+        }
+        import_queries::finish(db, id, failure.as_deref()).await?;
+        if let Err(e) = self.store.delete(&import.storage_key).await {
+            eprintln!("Error deleting upload of import {}: {}", id, e);
+        }
+
+        let entry = AuditEntryCreate::new("import", "transaction", Some(id))
+            .actor(import.user_id)
+            .details(json!({
+                "source": TransactionSource::Import,
+                "format": import.format,
+                "inserted": progress.inserted,
+                "skipped_duplicates": progress.duplicates_skipped,
+                "skipped_transfers": progress.transfers_skipped,
+                "failure": failure,
+            }));
+        if let Err(e) = audit_queries::append(db, &entry).await {
+            eprintln!("Error recording audit entry for import {}: {}", id, e);
+        }
+        let mut events = vec![(
+            "import.completed",
+            json!({
+                "id": id,
+                "status": if failure.is_some() {
+                    ImportStatus::Failed
+                } else {
+                    ImportStatus::Succeeded
+                },
+                "inserted": progress.inserted,
+                "failure": failure,
+            }),
+        )];
+        if progress.inserted > 0 {
+            events.push((
+                "transactions.imported",
+                json!({
+                    "source": TransactionSource::Import,
+                    "format": import.format,
+                    "inserted": progress.inserted,
+                }),
+            ));
+        }
+        for (event, data) in events {
+            if let Err(e) = webhook_queries::enqueue(db, import.user_id, event, &data).await {
+                eprintln!("Error queueing {} webhook: {}", event, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Dates as both apps write them, "01/31/2024" by default and ISO when configured so
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    // chrono would read the "24" of "01/31/24" as the year 24
+    let formats: &[&str] = match value.rsplit_once('/') {
+        Some((_, year)) if year.len() == 2 => &["%m/%d/%y"],
+        _ => &["%m/%d/%Y", "%Y-%m-%d"],
+    };
+    formats
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .ok_or_else(|| format!("unreadable date: {:?}", value))
+}
+
+/// Amounts such as "$1,234.56", "-12.50" or "(12.50)", an empty cell is zero
+pub fn parse_amount(value: &str, currency: Currency) -> Result<Money, String> {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '(' | ')' | '\''))
+        .collect();
+    if cleaned.is_empty() {
+        return Ok(Money::new(0, currency));
+    }
+    let negative = cleaned.starts_with('-') || cleaned.starts_with('(');
+    let number = parse_number(cleaned.trim_matches(['-', '(', ')']))
+        .ok_or_else(|| format!("unreadable amount: {:?}", value))?;
+    let money = Money::from_decimal_rounded(number, currency)?;
+    Ok(if negative { -money } else { money })
+}
+
+/// Best guess at the category of a user-named budget category, None when unsure
+pub fn guess_category(name: &str) -> Option<TransactionCategory> {
+    const KEYWORDS: &[(&str, TransactionCategory)] = &[
+        ("grocer", TransactionCategory::Groceries),
+        ("supermarket", TransactionCategory::Groceries),
+        ("restaurant", TransactionCategory::Restaurant),
+        ("dining", TransactionCategory::Restaurant),
+        ("eating", TransactionCategory::Restaurant),
+        ("coffee", TransactionCategory::Restaurant),
+        ("takeaway", TransactionCategory::Restaurant),
+        ("rent", TransactionCategory::Housing),
+        ("mortgage", TransactionCategory::Housing),
+        ("utilit", TransactionCategory::Housing),
+        ("electric", TransactionCategory::Housing),
+        ("internet", TransactionCategory::Housing),
+        ("home", TransactionCategory::Housing),
+        ("housing", TransactionCategory::Housing),
+        ("travel", TransactionCategory::Holidays),
+        ("vacation", TransactionCategory::Holidays),
+        ("holiday", TransactionCategory::Holidays),
+        ("hotel", TransactionCategory::Holidays),
+        ("shopping", TransactionCategory::Shopping),
+        ("clothing", TransactionCategory::Shopping),
+        ("clothes", TransactionCategory::Shopping),
+        ("electronics", TransactionCategory::Shopping),
+        ("gift", TransactionCategory::Shopping),
+        ("entertainment", TransactionCategory::Entertainment),
+        ("fun", TransactionCategory::Entertainment),
+        ("movie", TransactionCategory::Entertainment),
+        ("music", TransactionCategory::Entertainment),
+        ("streaming", TransactionCategory::Entertainment),
+        ("subscription", TransactionCategory::Entertainment),
+        ("hobb", TransactionCategory::Entertainment),
+        ("game", TransactionCategory::Entertainment),
+    ];
+    // Whole words only, so "Current account" is not rent
+    let name = name.to_lowercase();
+    name.split(|c: char| !c.is_alphanumeric()).find_map(|word| {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| word.starts_with(keyword))
+            .map(|(_, category)| category.clone())
+    })
+}
@@ -0,0 +1,122 @@
+// Bank statements and spreadsheets, any CSV with a header row
+//
+// Which column holds what is given by a `ColumnMapping`, columns named like the fields
+// ("Date", "Amount", "Description", "Category", "Type") are found without one. Amounts
+// are signed (negative for spending) unless a type column tells the direction.
+
+use super::{Columns, ExportRow, RejectReason, RowKind, guess_category, parse_amount, parse_date};
+use crate::models::money_models::Currency;
+use crate::models::transaction_models::{TransactionCategory, TransactionType};
+use chrono::NaiveDate;
+use csv::StringRecord;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Largest column mapping accepted, a few header names
+pub const MAX_MAPPING_BYTES: usize = 8 * 1024;
+
+/// Header names of the columns to read, by what they hold
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnMapping {
+    pub date: String,
+    pub amount: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    /// Column telling expenses from income ("expense"/"income" or "debit"/"credit")
+    pub transaction_type: Option<String>,
+    /// Account the row was booked on, told apart in the external ids of the rows
+    pub account: Option<String>,
+    /// chrono format of the dates (e.g. "%d.%m.%Y"), the usual US and ISO dates by default
+    pub date_format: Option<String>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            date: "Date".to_string(),
+            amount: "Amount".to_string(),
+            description: None,
+            category: None,
+            transaction_type: None,
+            account: None,
+            date_format: None,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Columns the file must have: the mapped ones, the optional ones only when named
+    pub fn required_columns(&self) -> Vec<&str> {
+        [
+            Some(&self.date),
+            Some(&self.amount),
+            self.description.as_ref(),
+            self.category.as_ref(),
+            self.transaction_type.as_ref(),
+            self.account.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect()
+    }
+}
+
+/// A value of an optional column, the mapped one or the one named by default
+fn optional<'a>(
+    columns: &Columns,
+    record: &'a StringRecord,
+    mapped: &Option<String>,
+    default: &str,
+) -> &'a str {
+    columns.get(record, mapped.as_deref().unwrap_or(default))
+}
+
+pub fn parse_row(
+    columns: &Columns,
+    record: &StringRecord,
+    mapping: &ColumnMapping,
+    currency: Currency,
+) -> Result<RowKind, (RejectReason, String)> {
+    let value = columns.get(record, &mapping.date);
+    let date = match &mapping.date_format {
+        Some(format) => NaiveDate::parse_from_str(value, format)
+            .map_err(|_| format!("date {:?} is not in format {:?}", value, format)),
+        None => parse_date(value),
+    }
+    .map_err(|e| (RejectReason::BadDate, e))?;
+    // Statements have an amount on every row, a cell without one is not read as zero
+    let value = columns.get(record, &mapping.amount);
+    if !value.chars().any(|c| c.is_ascii_digit()) {
+        return Err((
+            RejectReason::InvalidAmount,
+            format!("unreadable amount: {:?}", value),
+        ));
+    }
+    let amount = parse_amount(value, currency).map_err(|e| (RejectReason::InvalidAmount, e))?;
+    if amount.is_zero() {
+        return Ok(RowKind::Ignored);
+    }
+
+    let transaction_type = match optional(columns, record, &mapping.transaction_type, "Type") {
+        "" if amount.minor_units < 0 => TransactionType::Expense,
+        "" => TransactionType::Income,
+        value => TransactionType::from_str(value).map_err(|e| (RejectReason::Invalid, e))?,
+    };
+
+    let category_name = optional(columns, record, &mapping.category, "Category");
+    let category = TransactionCategory::from_str(category_name)
+        .ok()
+        .or_else(|| guess_category(category_name));
+
+    Ok(RowKind::Transaction(ExportRow {
+        date,
+        transaction_type,
+        amount: amount.abs(),
+        category,
+        description: optional(columns, record, &mapping.description, "Description").to_string(),
+        account: optional(columns, record, &mapping.account, "Account").to_string(),
+        external_id: None,
+    }))
+}
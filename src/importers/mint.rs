@@ -0,0 +1,123 @@
+// Mint transactions.csv downloads
+//
+// "Date","Description","Original Description","Amount","Transaction Type","Category","Account Name","Labels","Notes"
+// Amounts are unsigned, the direction is in Transaction Type ("debit" or "credit").
+
+use super::{Columns, ExportRow, RejectReason, RowKind, guess_category, parse_amount, parse_date};
+use crate::models::money_models::Currency;
+use crate::models::transaction_models::{TransactionCategory, TransactionType};
+use csv::StringRecord;
+
+pub const REQUIRED_COLUMNS: &[&str] = &[
+    "Date",
+    "Description",
+    "Amount",
+    "Transaction Type",
+    "Category",
+];
+
+/// Categories Mint files money moved between the user's own accounts under
+/// Both sides of a transfer are in the export, importing them would count it twice
+const TRANSFER_CATEGORIES: &[&str] = &[
+    "transfer",
+    "credit card payment",
+    "transfer for cash spending",
+];
+
+/// Mint's built-in categories (and subcategories) with a counterpart here,
+/// everything else falls back to `guess_category`
+const CATEGORIES: &[(&str, TransactionCategory)] = &[
+    ("groceries", TransactionCategory::Groceries),
+    ("food & dining", TransactionCategory::Restaurant),
+    ("restaurants", TransactionCategory::Restaurant),
+    ("fast food", TransactionCategory::Restaurant),
+    ("coffee shops", TransactionCategory::Restaurant),
+    ("alcohol & bars", TransactionCategory::Restaurant),
+    ("mortgage & rent", TransactionCategory::Housing),
+    ("home", TransactionCategory::Housing),
+    ("home improvement", TransactionCategory::Housing),
+    ("home services", TransactionCategory::Housing),
+    ("home supplies", TransactionCategory::Housing),
+    ("furnishings", TransactionCategory::Housing),
+    ("lawn & garden", TransactionCategory::Housing),
+    ("bills & utilities", TransactionCategory::Housing),
+    ("utilities", TransactionCategory::Housing),
+    ("internet", TransactionCategory::Housing),
+    ("home phone", TransactionCategory::Housing),
+    ("mobile phone", TransactionCategory::Housing),
+    ("television", TransactionCategory::Housing),
+    ("travel", TransactionCategory::Holidays),
+    ("air travel", TransactionCategory::Holidays),
+    ("hotel", TransactionCategory::Holidays),
+    ("rental car & taxi", TransactionCategory::Holidays),
+    ("vacation", TransactionCategory::Holidays),
+    ("shopping", TransactionCategory::Shopping),
+    ("clothing", TransactionCategory::Shopping),
+    ("books", TransactionCategory::Shopping),
+    ("electronics & software", TransactionCategory::Shopping),
+    ("sporting goods", TransactionCategory::Shopping),
+    ("hobbies", TransactionCategory::Shopping),
+    ("entertainment", TransactionCategory::Entertainment),
+    ("amusement", TransactionCategory::Entertainment),
+    ("arts", TransactionCategory::Entertainment),
+    ("movies & dvds", TransactionCategory::Entertainment),
+    ("music", TransactionCategory::Entertainment),
+    ("newspapers & magazines", TransactionCategory::Entertainment),
+];
+
+pub fn parse_row(
+    columns: &Columns,
+    record: &StringRecord,
+    currency: Currency,
+) -> Result<RowKind, (RejectReason, String)> {
+    let category_name = columns.get(record, "Category").to_lowercase();
+    if TRANSFER_CATEGORIES.contains(&category_name.as_str()) {
+        return Ok(RowKind::Transfer);
+    }
+
+    let date = parse_date(columns.get(record, "Date")).map_err(|e| (RejectReason::BadDate, e))?;
+    let amount = parse_amount(columns.get(record, "Amount"), currency)
+        .map_err(|e| (RejectReason::InvalidAmount, e))?
+        .abs();
+    if amount.is_zero() {
+        return Ok(RowKind::Ignored);
+    }
+    let transaction_type = match columns
+        .get(record, "Transaction Type")
+        .to_lowercase()
+        .as_str()
+    {
+        "debit" => TransactionType::Expense,
+        "credit" => TransactionType::Income,
+        other => {
+            return Err((
+                RejectReason::Invalid,
+                format!("unknown transaction type: {:?}", other),
+            ));
+        }
+    };
+
+    let category = CATEGORIES
+        .iter()
+        .find(|(name, _)| *name == category_name)
+        .map(|(_, category)| category.clone())
+        .or_else(|| guess_category(&category_name));
+
+    let description = columns.get(record, "Description");
+    let notes = columns.get(record, "Notes");
+    let description = if notes.is_empty() {
+        description.to_string()
+    } else {
+        format!("{} - {}", description, notes)
+    };
+
+    Ok(RowKind::Transaction(ExportRow {
+        date,
+        transaction_type,
+        amount,
+        category,
+        description,
+        account: columns.get(record, "Account Name").to_string(),
+        external_id: None,
+    }))
+}
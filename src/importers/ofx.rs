@@ -0,0 +1,147 @@
+// OFX statements (Open Financial Exchange), the "Money" or "Quicken" download of most banks
+//
+// OFX 1.x is SGML whose elements have no end tag, OFX 2.x is XML, both are read as a stream
+// of tags each followed by its text. Every <STMTTRN> of a bank or credit card statement is a
+// row, identified across downloads by its FITID, which the bank keeps unique per account.
+//
+//     <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240131120000[-5:EST]<TRNAMT>-12.50
+//     <FITID>2024013101<NAME>CORNER BAKERY<MEMO>Card 1234</STMTTRN>
+//
+// Amounts are signed, the currency is the statement's CURDEF.
+
+use super::{ExportRow, LineRow, RejectReason, RowKind, parse_amount};
+use crate::models::money_models::Currency;
+use crate::models::transaction_models::TransactionType;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A tag with the text up to the next tag
+struct Element<'a> {
+    /// Upper case, "/STMTTRN" for an end tag
+    name: String,
+    value: &'a str,
+    /// Byte offset of the tag
+    offset: usize,
+}
+
+/// The tags of a document in order, declarations and comments left out
+fn elements(text: &str) -> impl Iterator<Item = Element<'_>> {
+    text.match_indices('<').filter_map(|(offset, _)| {
+        let rest = &text[offset + 1..];
+        let end = rest.find('>')?;
+        let name = rest[..end].trim();
+        if name.starts_with(['?', '!']) {
+            return None;
+        }
+        let after = &rest[end + 1..];
+        let value = after[..after.find('<').unwrap_or(after.len())].trim();
+        Some(Element {
+            name: name.to_ascii_uppercase(),
+            value,
+            offset,
+        })
+    })
+}
+
+/// Text with the entities of SGML and XML replaced
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Dates such as "20240131", "20240131120000" or "20240131120000.000[-5:EST]", the day is
+/// the one at the bank
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    value
+        .get(..8)
+        .and_then(|day| NaiveDate::parse_from_str(day, "%Y%m%d").ok())
+        .ok_or_else(|| format!("unreadable date: {:?}", value))
+}
+
+/// The transactions of a statement with the line each starts on
+///
+/// # Errors
+/// Returns an error when the file is not an OFX statement or of another currency than the
+/// supported ones
+pub fn parse_rows(text: &str, currency: Currency) -> Result<Vec<LineRow>, String> {
+    let line_of = |offset: usize| text[..offset].matches('\n').count() as u64 + 1;
+    let mut currency = currency;
+    let mut account = String::new();
+    let mut statement = false;
+    let mut current: Option<(u64, HashMap<String, String>)> = None;
+    let mut rows = Vec::new();
+
+    for element in elements(text) {
+        match element.name.as_str() {
+            "STMTRS" | "CCSTMTRS" => statement = true,
+            "CURDEF" => currency = Currency::from_str(element.value)?,
+            "ACCTID" => account = unescape(element.value),
+            "STMTTRN" => current = Some((line_of(element.offset), HashMap::new())),
+            "/STMTTRN" => {
+                if let Some((line, fields)) = current.take() {
+                    rows.push((line, parse_transaction(&fields, &account, currency)));
+                }
+            }
+            name => {
+                if let Some((_, fields)) = &mut current {
+                    // The first of a name, the <NAME> of a <PAYEE> comes after the row's own
+                    fields
+                        .entry(name.to_string())
+                        .or_insert_with(|| unescape(element.value));
+                }
+            }
+        }
+    }
+    if !statement {
+        return Err("Not an OFX bank or credit card statement".to_string());
+    }
+    Ok(rows)
+}
+
+fn parse_transaction(
+    fields: &HashMap<String, String>,
+    account: &str,
+    currency: Currency,
+) -> Result<RowKind, (RejectReason, String)> {
+    let field = |name: &str| fields.get(name).map(String::as_str).unwrap_or_default();
+    let date = parse_date(field("DTPOSTED")).map_err(|e| (RejectReason::BadDate, e))?;
+    let amount = field("TRNAMT");
+    if !amount.chars().any(|c| c.is_ascii_digit()) {
+        return Err((
+            RejectReason::InvalidAmount,
+            format!("unreadable amount: {:?}", amount),
+        ));
+    }
+    let amount = parse_amount(amount, currency).map_err(|e| (RejectReason::InvalidAmount, e))?;
+    if amount.is_zero() {
+        return Ok(RowKind::Ignored);
+    }
+    let transaction_type = if amount.minor_units < 0 {
+        TransactionType::Expense
+    } else {
+        TransactionType::Income
+    };
+
+    let (name, memo) = (field("NAME"), field("MEMO"));
+    let description = match (name.is_empty(), memo.is_empty() || memo == name) {
+        (false, false) => format!("{} - {}", name, memo),
+        (false, true) => name.to_string(),
+        (true, _) => memo.to_string(),
+    };
+
+    Ok(RowKind::Transaction(ExportRow {
+        date,
+        transaction_type,
+        amount: amount.abs(),
+        category: None,
+        description,
+        account: account.to_string(),
+        external_id: Some(field("FITID").to_string()).filter(|id| !id.is_empty()),
+    }))
+}
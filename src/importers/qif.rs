@@ -0,0 +1,160 @@
+// QIF files (Quicken Interchange Format), still offered by many banks and older apps
+//
+// A "!Type:Bank" line (or CCard, Cash, Oth A, Oth L) starts the transactions of an account,
+// one record each, a line per field starting with its code, "^" ending the record:
+//
+//     D12/31'24        date, month first, an apostrophe before two digit years from 2000
+//     T-12.50          amount, signed
+//     PCorner Bakery   payee
+//     MCard 1234       memo
+//     LGroceries       category, "[Savings]" when moved to another account
+//     ^
+//
+// The lines of splits (S, E, $) are left out, the record's total is imported. Records have
+// no id, they are told apart by their contents. Other sections (investments, lists of
+// categories or classes) are skipped, "!Account" records name the account of the
+// transactions that follow.
+
+use super::{ExportRow, LineRow, RejectReason, RowKind, guess_category, parse_amount, parse_date};
+use crate::models::money_models::Currency;
+use crate::models::transaction_models::{TransactionCategory, TransactionType};
+use std::str::FromStr;
+
+/// Types of the sections holding an account's transactions
+const TRANSACTION_TYPES: &[&str] = &["bank", "ccard", "cash", "oth a", "oth l"];
+
+/// What the records of the current section are
+#[derive(PartialEq)]
+enum Section {
+    Transactions,
+    Account,
+    Other,
+}
+
+/// The transactions of the file with the line each starts on
+///
+/// # Errors
+/// Returns an error when the file has no section of transactions
+pub fn parse_rows(text: &str, currency: Currency) -> Result<Vec<LineRow>, String> {
+    let mut section = Section::Other;
+    let mut has_transactions = false;
+    let mut account = String::new();
+    let mut record: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut rows = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim_end();
+        if let Some(header) = line.strip_prefix('!') {
+            let header = header.to_lowercase();
+            if let Some(kind) = header.strip_prefix("type:") {
+                section = if TRANSACTION_TYPES.contains(&kind.trim()) {
+                    has_transactions = true;
+                    Section::Transactions
+                } else {
+                    Section::Other
+                };
+            } else if header == "account" {
+                section = Section::Account;
+            }
+            // Options such as "!Option:AutoSwitch" leave the section as it is
+            record.clear();
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if record.is_empty() {
+            start = i as u64 + 1;
+        }
+        if line.trim() != "^" {
+            record.push(line);
+            continue;
+        }
+
+        match section {
+            Section::Transactions => rows.push((start, parse_record(&record, &account, currency))),
+            Section::Account => {
+                if let Some(name) = record.iter().find_map(|l| l.strip_prefix('N')) {
+                    account = name.trim().to_string();
+                }
+            }
+            Section::Other => {}
+        }
+        record.clear();
+    }
+    if !has_transactions {
+        return Err("Not a QIF file of bank, card or cash transactions".to_string());
+    }
+    Ok(rows)
+}
+
+fn parse_record(
+    record: &[&str],
+    account: &str,
+    currency: Currency,
+) -> Result<RowKind, (RejectReason, String)> {
+    let field = |code: char| {
+        record
+            .iter()
+            .find_map(|line| line.strip_prefix(code))
+            .map(str::trim)
+            .unwrap_or_default()
+    };
+
+    let category_name = field('L');
+    if category_name.starts_with('[') {
+        return Ok(RowKind::Transfer);
+    }
+
+    // "12/31'24" and " 1/ 2/2024" are written by Quicken
+    let date: String = field('D')
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == '\'' { '/' } else { c })
+        .collect();
+    let date = parse_date(&date).map_err(|e| (RejectReason::BadDate, e))?;
+    let value = match field('T') {
+        "" => field('U'),
+        value => value,
+    };
+    if !value.chars().any(|c| c.is_ascii_digit()) {
+        return Err((
+            RejectReason::InvalidAmount,
+            format!("unreadable amount: {:?}", value),
+        ));
+    }
+    let amount = parse_amount(value, currency).map_err(|e| (RejectReason::InvalidAmount, e))?;
+    if amount.is_zero() {
+        return Ok(RowKind::Ignored);
+    }
+    let transaction_type = if amount.minor_units < 0 {
+        TransactionType::Expense
+    } else {
+        TransactionType::Income
+    };
+
+    // "Food:Groceries" is a subcategory of Food
+    let category = category_name
+        .split(':')
+        .next()
+        .and_then(|name| TransactionCategory::from_str(name).ok())
+        .or_else(|| guess_category(category_name));
+
+    let (payee, memo) = (field('P'), field('M'));
+    let description = match (payee.is_empty(), memo.is_empty()) {
+        (false, false) => format!("{} - {}", payee, memo),
+        (false, true) => payee.to_string(),
+        (true, _) => memo.to_string(),
+    };
+
+    Ok(RowKind::Transaction(ExportRow {
+        date,
+        transaction_type,
+        amount: amount.abs(),
+        category,
+        description,
+        account: account.to_string(),
+        external_id: None,
+    }))
+}
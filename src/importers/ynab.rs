@@ -0,0 +1,73 @@
+// YNAB register exports
+//
+// "Account","Flag","Date","Payee","Category Group/Category","Category Group","Category","Memo","Outflow","Inflow","Cleared"
+// YNAB 4 exports have "Master Category"/"Sub Category" instead of the group columns,
+// and the YNAB file import format (also what `exporters` writes) has no account or
+// category, only the columns they all share are relied on.
+
+use super::{Columns, ExportRow, RejectReason, RowKind, guess_category, parse_amount, parse_date};
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::TransactionType;
+use csv::StringRecord;
+
+pub const REQUIRED_COLUMNS: &[&str] = &["Date", "Payee", "Outflow", "Inflow"];
+
+/// Transfers have the other account as payee, e.g. "Transfer : Savings"
+const TRANSFER_PAYEE_PREFIX: &str = "Transfer :";
+
+/// Payee of the row opening each account
+const STARTING_BALANCE_PAYEE: &str = "Starting Balance";
+
+pub fn parse_row(
+    columns: &Columns,
+    record: &StringRecord,
+    currency: Currency,
+) -> Result<RowKind, (RejectReason, String)> {
+    let payee = columns.get(record, "Payee");
+    if payee.starts_with(TRANSFER_PAYEE_PREFIX) {
+        return Ok(RowKind::Transfer);
+    }
+    if payee.eq_ignore_ascii_case(STARTING_BALANCE_PAYEE) {
+        return Ok(RowKind::Ignored);
+    }
+
+    let date = parse_date(columns.get(record, "Date")).map_err(|e| (RejectReason::BadDate, e))?;
+    let outflow = parse_amount(columns.get(record, "Outflow"), currency)
+        .map_err(|e| (RejectReason::InvalidAmount, e))?;
+    let inflow = parse_amount(columns.get(record, "Inflow"), currency)
+        .map_err(|e| (RejectReason::InvalidAmount, e))?;
+    let net = inflow.minor_units - outflow.minor_units;
+    let (transaction_type, amount) = match net {
+        0 => return Ok(RowKind::Ignored),
+        n if n > 0 => (TransactionType::Income, n),
+        n => (TransactionType::Expense, -n),
+    };
+
+    // Inflows to "Ready to Assign" (YNAB 4: "Income") match no category and end up in
+    // the import's default one, refunds keep the spending category they were assigned to
+    let category_name = [
+        columns.get(record, "Category Group/Category"),
+        columns.get(record, "Category"),
+        columns.get(record, "Sub Category"),
+    ]
+    .into_iter()
+    .find(|name| !name.is_empty())
+    .unwrap_or_default();
+
+    let memo = columns.get(record, "Memo");
+    let description = match (payee.is_empty(), memo.is_empty()) {
+        (false, false) => format!("{} - {}", payee, memo),
+        (false, true) => payee.to_string(),
+        (true, _) => memo.to_string(),
+    };
+
+    Ok(RowKind::Transaction(ExportRow {
+        date,
+        transaction_type,
+        amount: Money::new(amount, currency),
+        category: guess_category(category_name),
+        description,
+        account: columns.get(record, "Account").to_string(),
+        external_id: None,
+    }))
+}
@@ -0,0 +1,270 @@
+pub mod parsers;
+
+use crate::config::Config;
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::models::pending_models::PendingTransactionCreate;
+use crate::queries::{pending_queries, user_queries};
+use crate::redact;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use mail_parser::MessageParser;
+use parsers::ParserRegistry;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Most emails fetched in one poll, the rest are picked up by the next one
+const MAX_EMAILS_PER_POLL: usize = 100;
+
+/// Where to find the mailbox emails are ingested from
+#[derive(Debug, Clone)]
+pub struct ImapSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub mailbox: String,
+    pub poll_interval: Duration,
+}
+
+impl ImapSettings {
+    /// None when no IMAP server is configured
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            host: config.imap_host.clone()?,
+            port: config.imap_port,
+            username: config.imap_username.clone(),
+            password: config.imap_password.clone(),
+            mailbox: config.imap_mailbox.clone(),
+            poll_interval: Duration::from_secs(config.imap_poll_interval_secs),
+        })
+    }
+}
+
+/// The parts of an email the parsers look at
+#[derive(Debug)]
+pub struct Email {
+    /// Lowercased From address
+    pub sender: String,
+    /// Lowercased To, Cc and Delivered-To addresses
+    pub recipients: Vec<String>,
+    pub subject: String,
+    /// Plain text body, HTML-only emails are converted to text
+    pub body: String,
+    /// Message-ID, or a hash of the raw email when there is none
+    pub message_id: String,
+    pub date: Option<DateTime<Utc>>,
+}
+
+impl Email {
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        let message = MessageParser::default().parse(raw)?;
+        let sender = message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address())?
+            .to_lowercase();
+
+        let mut recipients: Vec<String> = [message.to(), message.cc()]
+            .into_iter()
+            .flatten()
+            .flat_map(|address| address.iter())
+            .filter_map(|addr| addr.address())
+            .map(str::to_lowercase)
+            .collect();
+        recipients.extend(
+            message
+                .header_values("Delivered-To")
+                .filter_map(|value| value.as_text())
+                .map(|value| value.trim().to_lowercase()),
+        );
+
+        let message_id = match message.message_id() {
+            Some(id) => id.to_string(),
+            None => format!("sha256:{:x}", Sha256::digest(raw)),
+        };
+
+        Some(Self {
+            sender,
+            recipients,
+            subject: message.subject().unwrap_or_default().to_string(),
+            body: message.body_text(0).unwrap_or_default().into_owned(),
+            message_id,
+            date: message
+                .date()
+                .and_then(|date| DateTime::from_timestamp(date.to_timestamp(), 0)),
+        })
+    }
+
+    /// Subject and body, for parsers that do not care where the text is
+    pub fn text(&self) -> String {
+        format!("{}\n{}", self.subject, self.body)
+    }
+}
+
+/// Id in a "+<user id>" tag of a recipient, e.g. wallet+<uuid>@example.com
+fn tagged_user_id(recipient: &str) -> Option<Uuid> {
+    let local = recipient.split('@').next()?;
+    let (_, tag) = local.split_once('+')?;
+    tag.parse().ok()
+}
+
+/// The user an email belongs to: the one tagged in a recipient address, else the sender
+async fn find_user(db: &DbPool, email: &Email) -> anyhow::Result<Option<Uuid>> {
+    for user_id in email.recipients.iter().filter_map(|r| tagged_user_id(r)) {
+        if user_queries::user_exists(db, user_id).await? {
+            return Ok(Some(user_id));
+        }
+    }
+    Ok(user_queries::get_user(db, &email.sender)
+        .await?
+        .map(|u| u.id))
+}
+
+/// Turn one raw email into a pending transaction
+/// Emails that cannot be parsed or matched are skipped, only database errors are returned
+/// Shared by every way emails come in, so they all end up in the same review queue
+pub async fn ingest_email(
+    db: &DbPool,
+    registry: &ParserRegistry,
+    raw: &[u8],
+) -> anyhow::Result<()> {
+    let Some(email) = Email::parse(raw) else {
+        tracing::info!("Skipping email that could not be parsed");
+        return Ok(());
+    };
+    let Some(user_id) = find_user(db, &email).await? else {
+        tracing::info!(
+            sender = %redact::email(&email.sender),
+            "Skipping email, no matching user"
+        );
+        return Ok(());
+    };
+    let Some((parser, parsed)) = registry.parse(&email) else {
+        tracing::info!(
+            sender = %redact::email(&email.sender),
+            "Skipping email, no transaction recognised"
+        );
+        return Ok(());
+    };
+
+    let pending = PendingTransactionCreate {
+        user_id,
+        amount: parsed.transaction_type.signed(parsed.amount),
+        transaction_type: parsed.transaction_type,
+        category: None,
+        description: parsed.description,
+        occurred_at: email.date,
+        source_ref: email.message_id,
+        parser,
+    };
+    if let Some(id) = pending_queries::insert_pending(db, &pending).await? {
+        tracing::info!(pending = %id, parser, "Pending transaction created from email");
+    }
+    Ok(())
+}
+
+type ImapSession = imap::Session<native_tls::TlsStream<TcpStream>>;
+
+fn connect(settings: &ImapSettings) -> anyhow::Result<ImapSession> {
+    let tls = native_tls::TlsConnector::new()?;
+    let client = imap::connect(
+        (settings.host.as_str(), settings.port),
+        &settings.host,
+        &tls,
+    )?;
+    let mut session = client
+        .login(&settings.username, &settings.password)
+        .map_err(|(e, _)| e)?;
+    session.select(&settings.mailbox)?;
+    Ok(session)
+}
+
+/// Unread emails, oldest first, left unread until they are processed
+fn fetch_unseen(settings: &ImapSettings) -> anyhow::Result<Vec<(u32, Vec<u8>)>> {
+    let mut session = connect(settings)?;
+    let mut uids: Vec<u32> = session.uid_search("UNSEEN")?.into_iter().collect();
+    uids.sort_unstable();
+    uids.truncate(MAX_EMAILS_PER_POLL);
+
+    let mut emails = Vec::new();
+    if !uids.is_empty() {
+        for fetch in session
+            .uid_fetch(uid_set(&uids), "(UID BODY.PEEK[])")?
+            .iter()
+        {
+            if let (Some(uid), Some(body)) = (fetch.uid, fetch.body()) {
+                emails.push((uid, body.to_vec()));
+            }
+        }
+    }
+    session.logout()?;
+    Ok(emails)
+}
+
+fn mark_seen(settings: &ImapSettings, uids: &[u32]) -> anyhow::Result<()> {
+    let mut session = connect(settings)?;
+    session.uid_store(uid_set(uids), "+FLAGS (\\Seen)")?;
+    session.logout()?;
+    Ok(())
+}
+
+fn uid_set(uids: &[u32]) -> String {
+    uids.iter()
+        .map(|uid| uid.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Fetch and ingest new emails once, returning how many were processed
+/// Emails hitting a database error stay unread and are retried on the next poll
+async fn poll_once(
+    settings: &ImapSettings,
+    db: &DbPool,
+    registry: &ParserRegistry,
+) -> anyhow::Result<usize> {
+    let fetch_settings = settings.clone();
+    let emails = tokio::task::spawn_blocking(move || fetch_unseen(&fetch_settings)).await??;
+
+    let mut processed = Vec::new();
+    for (uid, raw) in emails {
+        match ingest_email(db, registry, &raw).await {
+            Ok(()) => processed.push(uid),
+            Err(e) => tracing::error!(uid, error = %e, "Ingesting email failed"),
+        }
+    }
+
+    let count = processed.len();
+    if count > 0 {
+        let mark_settings = settings.clone();
+        tokio::task::spawn_blocking(move || mark_seen(&mark_settings, &processed)).await??;
+    }
+    Ok(count)
+}
+
+/// Job fetching and ingesting new emails, queued every poll interval
+pub struct PollMailboxJob {
+    pub settings: ImapSettings,
+    pub registry: Arc<ParserRegistry>,
+}
+
+#[async_trait]
+impl JobHandler for PollMailboxJob {
+    fn kind(&self) -> &'static str {
+        "ingest.poll_mailbox"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let ingested = poll_once(&self.settings, db, &self.registry)
+            .await
+            .map_err(|e| anyhow::anyhow!("polling {} failed: {}", self.settings.host, e))?;
+        if ingested > 0 {
+            tracing::info!(ingested, "Emails ingested");
+        }
+        Ok(())
+    }
+}
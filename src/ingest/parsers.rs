@@ -0,0 +1,190 @@
+// Parsers turning bank notification and receipt emails into transactions
+//
+// Each bank lives in its own module implementing `NotificationParser`, and is
+// added to `ParserRegistry::with_defaults`. Other parsers can be registered on
+// top of (or instead of) those with `ParserRegistry::register`.
+
+mod chase;
+mod generic;
+mod n26;
+mod paypal;
+mod revolut;
+mod wise;
+
+use super::Email;
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::TransactionType;
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+pub use generic::GenericParser;
+
+/// A transaction recognised in an email, before it is matched to a user
+#[derive(Debug, Clone)]
+pub struct ParsedNotification {
+    pub transaction_type: TransactionType,
+    /// Unsigned, the sign follows from the type
+    pub amount: Money,
+    pub description: Option<String>,
+}
+
+/// Recognises the emails of one bank or service
+pub trait NotificationParser: Send + Sync {
+    /// Short name stored with the pending transactions this parser creates
+    fn name(&self) -> &'static str;
+
+    /// Whether the email comes from what this parser handles, checked before `parse`
+    fn matches(&self, email: &Email) -> bool;
+
+    /// The transaction in the email, None when it does not describe one (e.g. a newsletter)
+    fn parse(&self, email: &Email) -> Option<ParsedNotification>;
+}
+
+/// Parsers in the order they are tried, the first one matching and parsing an email wins
+#[derive(Default)]
+pub struct ParserRegistry {
+    parsers: Vec<Box<dyn NotificationParser>>,
+}
+
+impl ParserRegistry {
+    /// The built-in bank parsers, followed by the generic receipt parser as a fallback
+    pub fn with_defaults() -> Self {
+        Self::default()
+            .register(revolut::RevolutParser)
+            .register(wise::WiseParser)
+            .register(n26::N26Parser)
+            .register(paypal::PayPalParser)
+            .register(chase::ChaseParser)
+            .register(GenericParser)
+    }
+
+    /// Add a parser, tried after the ones already registered
+    pub fn register(mut self, parser: impl NotificationParser + 'static) -> Self {
+        self.parsers.push(Box::new(parser));
+        self
+    }
+
+    /// The first parser recognising the email and what it found
+    pub fn parse(&self, email: &Email) -> Option<(&'static str, ParsedNotification)> {
+        self.parsers
+            .iter()
+            .filter(|parser| parser.matches(email))
+            .find_map(|parser| parser.parse(email).map(|parsed| (parser.name(), parsed)))
+    }
+}
+
+static AMOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?x)
+        (?: (?P<symbol>[€$£¥]) \s? | \b(?P<code>[A-Z]{3}) \s )
+        (?P<number>\d[\d.,']*\d|\d)
+        |
+        (?P<number_before>\d[\d.,']*\d|\d) \s?
+        (?: (?P<symbol_after>[€$£¥]) | (?P<code_after>[A-Z]{3})\b )",
+    )
+    .expect("amount regex is valid")
+});
+
+static INCOMING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(received|refund(ed)?|credited|deposit(ed)?|incoming|salary)\b")
+        .expect("incoming regex is valid")
+});
+
+static COUNTERPARTY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:at|to|from|with)\s+(?P<name>[\p{L}\p{N}&'][^\n\r.,;:!?()]{0,59})")
+        .expect("counterparty regex is valid")
+});
+
+/// Parse "1,234.56", "1.234,56", "12,50" or "1'000" into a decimal
+/// A separator followed by one or two digits at the end is the decimal separator
+pub fn parse_number(number: &str) -> Option<Decimal> {
+    let number: String = number.chars().filter(|c| *c != '\'').collect();
+    let normalized = match number.rfind(['.', ',']) {
+        Some(pos) if (1..=2).contains(&(number.len() - pos - 1)) => {
+            let (whole, fraction) = number.split_at(pos);
+            format!("{}.{}", whole.replace(['.', ','], ""), &fraction[1..])
+        }
+        _ => number.replace(['.', ','], ""),
+    };
+    Decimal::from_str(&normalized).ok()
+}
+
+pub fn symbol_currency(symbol: &str) -> Option<Currency> {
+    match symbol {
+        "€" => Some(Currency::EUR),
+        "$" => Some(Currency::USD),
+        "£" => Some(Currency::GBP),
+        "¥" => Some(Currency::JPY),
+        _ => None,
+    }
+}
+
+/// Every amount with a currency in the text, with its byte offset, in order of appearance
+pub fn find_amounts(text: &str) -> Vec<(usize, Money)> {
+    AMOUNT
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let number = caps.name("number").or(caps.name("number_before"))?;
+            let currency = match (
+                caps.name("symbol").or(caps.name("symbol_after")),
+                caps.name("code").or(caps.name("code_after")),
+            ) {
+                (Some(symbol), _) => symbol_currency(symbol.as_str())?,
+                (_, Some(code)) => Currency::from_str(code.as_str()).ok()?,
+                _ => return None,
+            };
+            let amount = parse_number(number.as_str())?;
+            let money = Money::from_decimal_rounded(amount, currency).ok()?;
+            (!money.is_zero()).then_some((number.start(), money))
+        })
+        .collect()
+}
+
+/// Income when the text talks about money coming in, expense otherwise
+pub fn direction(text: &str) -> TransactionType {
+    if INCOMING.is_match(text) {
+        TransactionType::Income
+    } else {
+        TransactionType::Expense
+    }
+}
+
+/// Merchant or sender named after "at", "to", "from" or "with"
+pub fn counterparty(text: &str) -> Option<String> {
+    COUNTERPARTY
+        .captures(text)
+        .map(|caps| caps["name"].trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Whether the email was sent from `domain` (or a subdomain), directly or forwarded
+/// Forwarded emails keep the original sender in their body
+pub fn from_domain(email: &Email, domain: &str) -> bool {
+    let direct = email
+        .sender
+        .rsplit_once('@')
+        .is_some_and(|(_, host)| host == domain || host.ends_with(&format!(".{}", domain)));
+    direct || email.body.to_lowercase().contains(&format!("@{}", domain))
+}
+
+/// Parse the first match of `sentence` in the email, the usual shape of a bank notification
+/// The amount, direction and counterparty are taken from the matched text only,
+/// a `merchant` capture group takes precedence over the guessed counterparty
+pub fn parse_sentence(email: &Email, sentence: &Regex) -> Option<ParsedNotification> {
+    let text = email.text();
+    let caps = sentence.captures(&text)?;
+    let matched = caps.get(0)?.as_str();
+    let (_, amount) = find_amounts(matched).into_iter().next()?;
+    let description = caps
+        .name("merchant")
+        .map(|m| m.as_str().trim().to_string())
+        .filter(|m| !m.is_empty())
+        .or_else(|| counterparty(matched));
+    Some(ParsedNotification {
+        transaction_type: direction(matched),
+        amount,
+        description,
+    })
+}
@@ -0,0 +1,40 @@
+use super::{NotificationParser, ParsedNotification, from_domain, parse_number};
+use crate::ingest::Email;
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::TransactionType;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static SENTENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(charge\s+of\s+\(\$USD\)\s+|\$)(?P<amount>[\d.,]+)\s+(transaction\s+)?(at|with)\s+(?P<merchant>[^\n.]+?)(\s+has\s+been|\s+was|\.|\n|$)",
+    )
+    .expect("chase regex is valid")
+});
+
+/// Chase card alerts
+/// e.g. "A charge of ($USD) 12.50 at ACME has been authorized" or
+/// "Your $12.50 transaction with ACME"
+pub struct ChaseParser;
+
+impl NotificationParser for ChaseParser {
+    fn name(&self) -> &'static str {
+        "chase"
+    }
+
+    fn matches(&self, email: &Email) -> bool {
+        from_domain(email, "chase.com")
+    }
+
+    // Alerts are always for card charges in dollars
+    fn parse(&self, email: &Email) -> Option<ParsedNotification> {
+        let text = email.text();
+        let caps = SENTENCE.captures(&text)?;
+        let amount = parse_number(&caps["amount"])?;
+        Some(ParsedNotification {
+            transaction_type: TransactionType::Expense,
+            amount: Money::from_decimal_rounded(amount, Currency::USD).ok()?,
+            description: Some(caps["merchant"].trim().to_string()),
+        })
+    }
+}
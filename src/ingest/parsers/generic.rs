@@ -0,0 +1,46 @@
+use super::{NotificationParser, ParsedNotification, counterparty, direction, find_amounts};
+use crate::ingest::Email;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static TRANSACTIONAL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(spent|paid|payment|purchase|charged|debited|received|refund|credited|receipt|total|transaction)\b",
+    )
+    .expect("transactional regex is valid")
+});
+
+static TOTAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\btotal\b").expect("total regex is valid"));
+
+/// Any receipt or notification with an amount and currency, the fallback for unknown senders
+/// Receipts list several amounts, the one after the last "total" wins
+pub struct GenericParser;
+
+impl NotificationParser for GenericParser {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn matches(&self, email: &Email) -> bool {
+        TRANSACTIONAL.is_match(&email.text())
+    }
+
+    fn parse(&self, email: &Email) -> Option<ParsedNotification> {
+        let text = email.text();
+        let amounts = find_amounts(&text);
+        let (_, amount) = match TOTAL.find_iter(&text).last().map(|m| m.end()) {
+            Some(total_at) => amounts
+                .iter()
+                .find(|(pos, _)| *pos > total_at)
+                .or(amounts.first())
+                .copied()?,
+            None => amounts.first().copied()?,
+        };
+        Some(ParsedNotification {
+            transaction_type: direction(&text),
+            amount,
+            description: counterparty(&email.subject).or_else(|| counterparty(&email.body)),
+        })
+    }
+}
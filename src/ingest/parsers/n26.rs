@@ -0,0 +1,29 @@
+use super::{NotificationParser, ParsedNotification, from_domain, parse_sentence};
+use crate::ingest::Email;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static SENTENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(payment|transfer|card\s+transaction)\s+of\s+[^\n]+?\s+(to|at|from)\s+(?P<merchant>[^\n.,;]+?)(\s+was\b|[.,;\n]|$)|you('ve)?\s+received\s+[^\n]+",
+    )
+    .expect("n26 regex is valid")
+});
+
+/// N26 push notifications forwarded by email
+/// e.g. "A payment of €12.50 to Tesco was made" or "You've received €50.00 from Jane"
+pub struct N26Parser;
+
+impl NotificationParser for N26Parser {
+    fn name(&self) -> &'static str {
+        "n26"
+    }
+
+    fn matches(&self, email: &Email) -> bool {
+        from_domain(email, "n26.com")
+    }
+
+    fn parse(&self, email: &Email) -> Option<ParsedNotification> {
+        parse_sentence(email, &SENTENCE)
+    }
+}
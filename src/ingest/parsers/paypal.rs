@@ -0,0 +1,35 @@
+use super::{NotificationParser, ParsedNotification, from_domain, parse_sentence};
+use crate::ingest::Email;
+use crate::models::transaction_models::TransactionType;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static SENTENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)you\s+(sent\s+a\s+payment\s+of|paid|sent|received)\s+[^\n]+|(?P<merchant>[^\n]{1,60}?)\s+sent\s+you\s+[^\n]+",
+    )
+    .expect("paypal regex is valid")
+});
+
+/// PayPal payment receipts
+/// e.g. "You sent a payment of $12.50 USD to Acme", "You received $20.00 USD from Jane"
+/// or "Jane sent you $20.00 USD"
+pub struct PayPalParser;
+
+impl NotificationParser for PayPalParser {
+    fn name(&self) -> &'static str {
+        "paypal"
+    }
+
+    fn matches(&self, email: &Email) -> bool {
+        from_domain(email, "paypal.com")
+    }
+
+    fn parse(&self, email: &Email) -> Option<ParsedNotification> {
+        let mut parsed = parse_sentence(email, &SENTENCE)?;
+        if email.text().to_lowercase().contains("sent you") {
+            parsed.transaction_type = TransactionType::Income;
+        }
+        Some(parsed)
+    }
+}
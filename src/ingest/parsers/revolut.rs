@@ -0,0 +1,26 @@
+use super::{NotificationParser, ParsedNotification, from_domain, parse_sentence};
+use crate::ingest::Email;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static SENTENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)you\s+(spent|paid|sent|received)\s+[^\n]+").expect("revolut regex is valid")
+});
+
+/// Revolut card payment and transfer notifications
+/// e.g. "You spent €12.50 at Tesco" or "You received £20.00 from Jane"
+pub struct RevolutParser;
+
+impl NotificationParser for RevolutParser {
+    fn name(&self) -> &'static str {
+        "revolut"
+    }
+
+    fn matches(&self, email: &Email) -> bool {
+        from_domain(email, "revolut.com")
+    }
+
+    fn parse(&self, email: &Email) -> Option<ParsedNotification> {
+        parse_sentence(email, &SENTENCE)
+    }
+}
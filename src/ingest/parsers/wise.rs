@@ -0,0 +1,39 @@
+use super::{NotificationParser, ParsedNotification, from_domain, parse_sentence};
+use crate::ingest::Email;
+use crate::models::transaction_models::TransactionType;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static SENTENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(you\s+(spent|sent|paid)|you've\s+received|you\s+received|(?P<sender>[^\n]{1,60})\s+sent\s+you)\s+[^\n]+",
+    )
+    .expect("wise regex is valid")
+});
+
+/// Wise card payments and transfers
+/// e.g. "You spent 12.50 EUR at Tesco", "You sent 100 GBP to Jane" or "Jane sent you 50 EUR"
+pub struct WiseParser;
+
+impl NotificationParser for WiseParser {
+    fn name(&self) -> &'static str {
+        "wise"
+    }
+
+    fn matches(&self, email: &Email) -> bool {
+        from_domain(email, "wise.com") || from_domain(email, "transferwise.com")
+    }
+
+    fn parse(&self, email: &Email) -> Option<ParsedNotification> {
+        let mut parsed = parse_sentence(email, &SENTENCE)?;
+        // "Jane sent you ..." names the sender before the verb
+        if let Some(sender) = SENTENCE
+            .captures(&email.text())
+            .and_then(|caps| caps.name("sender").map(|s| s.as_str().trim().to_string()))
+        {
+            parsed.transaction_type = TransactionType::Income;
+            parsed.description = Some(sender);
+        }
+        Some(parsed)
+    }
+}
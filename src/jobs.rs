@@ -0,0 +1,183 @@
+// Database-backed background jobs
+//
+// Work that should not run inside a request (re-encryption, mailbox polling, webhook
+// delivery, ...) is queued in the jobs table and picked up by a pool of workers. Each kind
+// of job has a `JobHandler` registered in the `JobRegistry` under its name. A job that
+// fails is retried with exponential backoff until it runs out of attempts; a job whose
+// worker disappears becomes visible again once its visibility timeout passes.
+
+use crate::database::DbPool;
+use crate::models::job_models::{JobCreate, JobQuery};
+use crate::queries::job_queries;
+use axum::async_trait;
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long an idle worker waits before looking for jobs again
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delay before the first retry, doubled after every failed attempt
+const BASE_BACKOFF_SECS: i64 = 30;
+
+/// Longest delay between two attempts
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+
+/// Runs one kind of job
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    /// Name the jobs are queued under, e.g. "webhooks.deliver"
+    fn kind(&self) -> &'static str;
+
+    /// Do the work, an error schedules a retry while attempts remain
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()>;
+}
+
+/// Handlers by the kind of job they run
+#[derive(Default)]
+pub struct JobRegistry {
+    handlers: HashMap<&'static str, Box<dyn JobHandler>>,
+}
+
+impl JobRegistry {
+    /// Add a handler, replacing any earlier one for the same kind
+    pub fn register(mut self, handler: impl JobHandler + 'static) -> Self {
+        self.handlers.insert(handler.kind(), Box::new(handler));
+        self
+    }
+
+    fn get(&self, kind: &str) -> Option<&dyn JobHandler> {
+        self.handlers.get(kind).map(Box::as_ref)
+    }
+}
+
+/// Queue a job, returning its id, or None when an identical one is already queued or running
+pub async fn enqueue(db: &DbPool, job: JobCreate) -> anyhow::Result<Option<Uuid>> {
+    job_queries::enqueue(db, &job).await
+}
+
+/// Delay before the attempt following `attempts` failed ones
+fn backoff(attempts: i32) -> chrono::Duration {
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    chrono::Duration::seconds((BASE_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS))
+}
+
+/// Workers taking jobs from the queue
+pub struct JobWorkerPool {
+    pub db: DbPool,
+    pub registry: Arc<JobRegistry>,
+    pub workers: usize,
+    /// Longest a job may run, after which another worker may take it
+    pub visibility_timeout: Duration,
+}
+
+impl JobWorkerPool {
+    /// Start the workers in the background for as long as the server runs
+    pub fn spawn(self) {
+        let pool = Arc::new(self);
+        for _ in 0..pool.workers.max(1) {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                loop {
+                    match job_queries::claim(&pool.db, pool.visibility_timeout.as_secs() as i64)
+                        .await
+                    {
+                        Ok(Some(job)) => pool.run(job).await,
+                        Ok(None) => tokio::time::sleep(POLL_INTERVAL).await,
+                        Err(e) => {
+                            tracing::error!(error = %e, "Claiming a job failed");
+                            tokio::time::sleep(POLL_INTERVAL).await;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    async fn run(&self, job: JobQuery) {
+        let result = match self.registry.get(&job.kind) {
+            // Taken again after its worker disappeared on the last attempt
+            _ if job.attempts > job.max_attempts => Err(anyhow::anyhow!(
+                "timed out on every one of {} attempts",
+                job.max_attempts
+            )),
+            Some(handler) => {
+                // Stop before the job becomes visible to other workers again
+                match tokio::time::timeout(
+                    self.visibility_timeout,
+                    handler.run(&self.db, &job.payload),
+                )
+                .await
+                {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow::anyhow!(
+                        "timed out after {}s",
+                        self.visibility_timeout.as_secs()
+                    )),
+                }
+            }
+            None => Err(anyhow::anyhow!("no handler for job kind {}", job.kind)),
+        };
+
+        let recorded = match result {
+            Ok(()) => job_queries::complete(&self.db, job.id).await,
+            Err(e) => {
+                let retry_at = (job.attempts < job.max_attempts
+                    && self.registry.get(&job.kind).is_some())
+                .then(|| Utc::now() + backoff(job.attempts));
+                if retry_at.is_none() {
+                    tracing::error!(
+                        job = %job.id,
+                        kind = %job.kind,
+                        attempts = job.attempts,
+                        error = %e,
+                        "Job failed"
+                    );
+                }
+                job_queries::fail(&self.db, job.id, &e.to_string(), retry_at).await
+            }
+        };
+        if let Err(e) = recorded {
+            // The visibility timeout hands the job to another worker later
+            tracing::error!(job = %job.id, error = %e, "Recording the outcome of a job failed");
+        }
+    }
+}
+
+/// Succeeded jobs are deleted after this long, recurring ones would otherwise pile up
+const KEEP_SUCCEEDED: chrono::Duration = chrono::Duration::days(1);
+
+/// Job deleting old succeeded jobs, scheduled hourly by default
+pub struct PruneJobsJob;
+
+#[async_trait]
+impl JobHandler for PruneJobsJob {
+    fn kind(&self) -> &'static str {
+        "jobs.prune"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        job_queries::prune_succeeded(db, Utc::now() - KEEP_SUCCEEDED).await?;
+        Ok(())
+    }
+}
+
+/// Queue a job of this kind every `every`, skipped while the previous one is still pending
+/// A failed run is not retried, the next one is only `every` away
+pub fn spawn_recurring(db: DbPool, kind: &'static str, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            let job = JobCreate::new(kind, json!({}))
+                .dedupe_key(kind)
+                .max_attempts(1);
+            if let Err(e) = enqueue(&db, job).await {
+                tracing::error!(kind, error = %e, "Queueing job failed");
+            }
+        }
+    });
+}
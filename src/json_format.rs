@@ -0,0 +1,206 @@
+// Field casing and date format of JSON responses
+//
+// Responses have snake_case field names and RFC 3339 dates. Clients wanting camelCase names
+// or dates as milliseconds since the epoch ask for them with parameters of the Accept header:
+//
+//     Accept: application/json; case=camel; dates=epoch-millis
+//
+// (either parameter on its own works too), or the defaults of the server are changed with
+// JSON_CASE and JSON_DATES. Responses are rewritten once the handler answered, handlers only
+// ever produce the snake_case RFC 3339 form. Keys of every object are converted, those of
+// maps such as audit entry details included. Dates are the strings holding a full RFC 3339
+// timestamp, days without a time (e.g. "2026-10-01") are left alone.
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::DateTime;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// Casing of field names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldCase {
+    #[default]
+    Snake,
+    Camel,
+}
+
+impl fmt::Display for FieldCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            FieldCase::Snake => "snake",
+            FieldCase::Camel => "camel",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for FieldCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "snake" => Ok(FieldCase::Snake),
+            "camel" => Ok(FieldCase::Camel),
+            _ => Err(format!("Invalid field case: {}", s)),
+        }
+    }
+}
+
+/// Format of timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    #[default]
+    Rfc3339,
+    EpochMillis,
+}
+
+impl fmt::Display for DateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DateFormat::Rfc3339 => "rfc3339",
+            DateFormat::EpochMillis => "epoch-millis",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rfc3339" => Ok(DateFormat::Rfc3339),
+            "epoch-millis" => Ok(DateFormat::EpochMillis),
+            _ => Err(format!("Invalid date format: {}", s)),
+        }
+    }
+}
+
+/// How JSON responses are written, the server's defaults or a request's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JsonFormat {
+    pub case: FieldCase,
+    pub dates: DateFormat,
+}
+
+impl JsonFormat {
+    /// The format a request asks for in its Accept header, these defaults otherwise
+    /// Unknown values are ignored rather than refused
+    pub fn for_request(self, headers: &HeaderMap) -> Self {
+        let mut format = self;
+        let params = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split([',', ';']))
+            .filter_map(|param| param.split_once('='));
+        for (name, value) in params {
+            let value = value.trim().trim_matches('"');
+            match name.trim().to_ascii_lowercase().as_str() {
+                "case" => format.case = value.parse().unwrap_or(format.case),
+                "dates" => format.dates = value.parse().unwrap_or(format.dates),
+                _ => {}
+            }
+        }
+        format
+    }
+
+    /// Rewrite a value written in the default format
+    pub fn apply(self, value: Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| {
+                        let key = match self.case {
+                            FieldCase::Snake => key,
+                            FieldCase::Camel => camel_case(&key),
+                        };
+                        (key, self.apply(value))
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.into_iter().map(|v| self.apply(v)).collect()),
+            Value::String(s) if self.dates == DateFormat::EpochMillis => {
+                match DateTime::parse_from_rfc3339(&s) {
+                    Ok(date) => Value::from(date.timestamp_millis()),
+                    Err(_) => Value::String(s),
+                }
+            }
+            value => value,
+        }
+    }
+}
+
+/// "created_at" to "createdAt", keys without underscores are left alone
+fn camel_case(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Whether a response is JSON, problem details included
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|media_type| {
+            let media_type = media_type.trim();
+            media_type == "application/json" || media_type.ends_with("+json")
+        })
+}
+
+/// Middleware writing JSON responses in the format the request asks for
+pub async fn format_responses(
+    State(defaults): State<JsonFormat>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let format = defaults.for_request(req.headers());
+    let mut response = next.run(req).await;
+    // Caches keep one copy per format
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    if format == JsonFormat::default() || !is_json(response.headers()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to buffer response for formatting: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Ok(value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let body = match serde_json::to_vec(&format.apply(value)) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to write formatted response: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
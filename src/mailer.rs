@@ -0,0 +1,49 @@
+// Outgoing email
+//
+// When MAILER_URL is set, emails are sent through an HTTP email API: a POST of
+// {"from": ..., "to": ..., "subject": ..., "text": ...} as JSON to MAILER_URL with, when
+// MAILER_API_KEY is set, an "Authorization: Bearer" header. Any 2xx answer counts as sent.
+// Most transactional email services take such a request directly or through a small relay.
+// Emails go out from background jobs, whose retries cover an API that is briefly down.
+
+use reqwest::Url;
+use serde_json::json;
+use std::time::Duration;
+
+/// Longest the email API may take to accept an email
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Sends plain text emails through the email API
+pub struct Mailer {
+    pub endpoint: Url,
+    pub api_key: Option<String>,
+    /// Sender address, e.g. "Wallet <no-reply@example.com>"
+    pub from: String,
+    pub client: reqwest::Client,
+}
+
+impl Mailer {
+    pub async fn send(&self, to: &str, subject: &str, text: &str) -> anyhow::Result<()> {
+        let mut request = self
+            .client
+            .post(self.endpoint.clone())
+            .json(&json!({
+                "from": self.from,
+                "to": to,
+                "subject": subject,
+                "text": text,
+            }))
+            .timeout(SEND_TIMEOUT);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Sending email failed with {}",
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+}
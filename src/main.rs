@@ -1,46 +1,71 @@
 // Module declarations - these tell Rust where to find our code modules
+mod admin;
+mod allowances;
+mod analytics_export;
+mod attachments;
+mod auth;
+mod billing;
+mod budgets;
 mod config;
+mod crypto;
+mod dashboard;
 mod database;
+mod debug_capture;
+mod dedup;
+mod digests;
+mod enrichment;
+mod events;
+mod exporters;
+mod firefly;
+mod fx;
 mod handlers;
+mod importers;
+mod ingest;
+mod jobs;
+mod json_format;
+mod mailer;
+mod meta;
 mod models;
+mod multipart;
+mod onboarding;
+mod problems;
 mod queries;
+mod quick_entry;
+mod quotas;
+#[cfg(not(feature = "lambda"))]
+mod readiness;
+mod redact;
+mod repairs;
+mod request_tx;
+mod restrictions;
+mod rewards;
+mod routes;
+mod savings;
+mod scanning;
+mod scheduler;
+#[cfg(not(feature = "lambda"))]
+mod schema;
+mod signatures;
+mod slack;
+mod storage;
+mod suggestions;
+mod telegram;
+mod telemetry;
+mod throttle;
+mod validation;
+mod views;
+mod webhooks;
 
-use axum::{
-    Router,
-    extract::State,
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
-};
-use serde_json::{Value, json};
-use std::net::SocketAddr;
-use tower_http::cors::CorsLayer;
+#[cfg(not(feature = "lambda"))]
+use axum::Router;
+use serde_json::json;
+use std::time::Duration;
+use tracing::info;
 // Import our modules
 use crate::config::Config;
-use crate::database::{create_pool, health_check, run_migrations};
-/// Application state shared across all req handlers
-/// This allows handlers to access the database pool without global variables
-
-/// Health check endpoint - returns 200 OK if the server is running
-/// This is useful for load balancers and monitoring systems
-async fn health() -> Json<Value> {
-    Json(json!({
-        "status": "ok",
-        "message": "Wallet API is running"
-    }))
-}
-
-/// Database health check endpoint - verifies database connectivity
-/// Returns 200 OK if database is accessible, 503 Service Unavailable otherwise
-async fn db_health(State(state): State<handlers::AppState>) -> Result<Json<Value>, StatusCode> {
-    match health_check(&state.db).await {
-        Ok(_) => Ok(Json(json!({
-            "status": "ok",
-            "database": "connected"
-        }))),
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
-    }
-}
+#[cfg(not(feature = "lambda"))]
+use crate::database::{create_pool, run_migrations};
+use crate::models::transaction_models::TransactionCategory;
 
 /// Main entry point of the application
 /// Sets up the Axum web server, routes, middleware, and starts listening
@@ -54,64 +79,461 @@ async fn main() -> anyhow::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(&config.rust_log))
         .init();
 
-    println!("🚀 Starting Wallet API server...");
-    println!("📊 Connecting to database...");
+    // Mask personal data in everything we log from here on
+    let redaction_policy = config
+        .log_redaction
+        .parse::<redact::RedactionPolicy>()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    redact::init(redaction_policy);
+    views::init_session_key(config.session_secret.as_deref());
+
+    info!(
+        version = env!("CARGO_PKG_VERSION"),
+        "Starting Wallet API server"
+    );
+    #[cfg(not(feature = "lambda"))]
+    readiness::clear(config.ready_file.as_deref())?;
 
     // Create database connection pool
     // The pool manages multiple connections efficiently
-    let db_pool = create_pool(&config.database_url).await?;
-    println!("✅ Database connection established");
+    let slow_query_threshold = Duration::from_millis(config.slow_query_threshold_ms);
+    telemetry::init_slow_query_threshold(slow_query_threshold);
+    #[cfg(not(feature = "lambda"))]
+    let db_pool = {
+        let db_pool = create_pool(&config.database_url, slow_query_threshold).await?;
+        info!("Database connection established");
+
+        // Run database migrations
+        // Migrations create and update database schema (tables, indexes, etc.)
+        run_migrations(&db_pool).await?;
 
-    // Run database migrations
-    // Migrations create and update database schema (tables, indexes, etc.)
-    println!("📦 Running database migrations...");
-    run_migrations(&db_pool).await?;
+        // Stop here rather than fail requests when the schema is not what the queries expect
+        schema::check(&db_pool).await?;
+        info!("Database schema checked");
+        db_pool
+    };
+
+    // On Lambda, connect on the first query so cold starts stay fast
+    // Migrations are left to a regular server start as part of the deployment
+    #[cfg(feature = "lambda")]
+    let db_pool = database::create_lazy_pool(&config.database_url, slow_query_threshold)?;
+
+    // Set up column encryption if keys are configured
+    if let Some(cipher) = crypto::FieldCipher::from_config(&config)? {
+        crypto::init(cipher);
+        info!("Column encryption enabled");
+    }
+    // Attachments and exports go to the configured blob store
+    let blobs = storage::from_config(&config)?;
+    info!(blob_store = %config.blob_store, "Storing files in the blob store");
+    let scanner = config
+        .malware_scanner
+        .as_deref()
+        .map(scanning::Scanner::from_url)
+        .transpose()?
+        .map(std::sync::Arc::new);
+    if scanner.is_some() {
+        info!("Scanning uploads for malware");
+    }
+    let default_category: TransactionCategory = config
+        .import_default_category
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid IMPORT_DEFAULT_CATEGORY value: {}", e))?;
+    // Enforce per-plan quotas if plans are configured
+    let plans = quotas::parse_plans(&config.plans)?;
+    if !plans.is_empty() {
+        info!(plans = plans.len(), "Quotas enforced");
+        quotas::init(plans);
+    }
+    let enricher = match &config.merchant_api_url {
+        Some(url) => {
+            info!(url = %url, "Enriching transactions with merchants");
+            Some(std::sync::Arc::new(enrichment::MerchantEnricher {
+                endpoint: reqwest::Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("Invalid MERCHANT_API_URL {}: {}", url, e))?,
+                api_key: config.merchant_api_key.clone(),
+                cache_ttl: std::time::Duration::from_secs(config.merchant_cache_ttl_secs),
+                client: reqwest::Client::new(),
+            }))
+        }
+        None => None,
+    };
+    // Derived data follows every write of a transaction
+    let mut bus = events::EventBus::default().subscribe(webhooks::TransactionWebhooks);
+    if enricher.is_some() {
+        bus = bus.subscribe(enrichment::QueueEnrichment);
+    }
+    events::init(bus);
+    // Background jobs run on a server, Lambda instances are frozen between requests
+    // Jobs queued by Lambda functions wait for a server to pick them up
+    if cfg!(not(feature = "lambda")) {
+        let mut registry = jobs::JobRegistry::default()
+            .register(jobs::PruneJobsJob)
+            .register(crypto::ReencryptColumnsJob)
+            .register(allowances::CreditAllowancesJob)
+            .register(savings::RecordRoundUpsJob)
+            .register(budgets::RecordBudgetAlertsJob)
+            .register(suggestions::TrainCategoryModelsJob)
+            .register(repairs::ReassignTransactionsJob)
+            .register(repairs::RecategorizeJob)
+            .register(digests::WeeklyDigestJob {
+                telegram_token: config.telegram_bot_token.clone(),
+                client: reqwest::Client::new(),
+            })
+            .register(importers::RunImportJob {
+                store: blobs.clone(),
+                default_category: default_category.clone(),
+            })
+            .register(webhooks::DeliverWebhooksJob {
+                client: reqwest::Client::new(),
+            });
+        match attachments::GenerateThumbnailJob::from_command_line(
+            &config.thumbnail_command,
+            blobs.clone(),
+        ) {
+            Some(job) => registry = registry.register(job),
+            None => info!("THUMBNAIL_COMMAND is empty, attachment thumbnails disabled"),
+        }
+        if let Some(enricher) = enricher {
+            registry = registry.register(enrichment::EnrichTransactionJob { enricher });
+        }
+        if let Some(url) = &config.mailer_url {
+            info!(url = %url, "Sending emails through the email API");
+            let mailer = mailer::Mailer {
+                endpoint: reqwest::Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("Invalid MAILER_URL {}: {}", url, e))?,
+                api_key: config.mailer_api_key.clone(),
+                from: config.mailer_from.clone(),
+                client: reqwest::Client::new(),
+            };
+            registry = registry.register(onboarding::InviteUserJob {
+                mailer,
+                invitation_url: reqwest::Url::parse(&config.invitation_url).map_err(|e| {
+                    anyhow::anyhow!("Invalid INVITATION_URL {}: {}", config.invitation_url, e)
+                })?,
+                ttl: chrono::Duration::seconds(
+                    i64::try_from(config.invitation_ttl_secs).unwrap_or(i64::MAX / 1000),
+                ),
+            });
+        }
+        if let Some(sink) = &config.analytics_sink {
+            let sink = analytics_export::AnalyticsSink::from_url(sink)?;
+            info!(
+                sink = sink.name(),
+                interval_secs = config.analytics_export_interval_secs,
+                "Exporting transaction changes"
+            );
+            registry = registry.register(analytics_export::ExportAnalyticsJob {
+                sink,
+                store: blobs.clone(),
+                client: reqwest::Client::new(),
+            });
+            jobs::spawn_recurring(
+                db_pool.clone(),
+                "analytics.export",
+                Duration::from_secs(config.analytics_export_interval_secs),
+            );
+        }
+        if let Some(url) = &config.fx_rates_url {
+            let base = config
+                .fx_rates_base
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid FX_RATES_BASE value: {}", e))?;
+            info!(
+                base = %base,
+                url = %url,
+                interval_secs = config.fx_rates_interval_secs,
+                "Fetching exchange rates"
+            );
+            registry = registry.register(fx::FetchRatesJob {
+                endpoint: reqwest::Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("Invalid FX_RATES_URL {}: {}", url, e))?,
+                base,
+                client: reqwest::Client::new(),
+            });
+            jobs::spawn_recurring(
+                db_pool.clone(),
+                "fx.rates",
+                Duration::from_secs(config.fx_rates_interval_secs),
+            );
+        }
+        jobs::spawn_recurring(
+            db_pool.clone(),
+            "webhooks.deliver",
+            webhooks::DELIVERY_INTERVAL,
+        );
+        events::spawn_change_feed(db_pool.clone());
+
+        // Poll the receipts mailbox
+        if let Some(settings) = ingest::ImapSettings::from_config(&config) {
+            info!(
+                mailbox = %settings.mailbox,
+                host = %settings.host,
+                interval_secs = settings.poll_interval.as_secs(),
+                "Polling the mailbox for receipts"
+            );
+            jobs::spawn_recurring(
+                db_pool.clone(),
+                "ingest.poll_mailbox",
+                settings.poll_interval,
+            );
+            registry = registry.register(ingest::PollMailboxJob {
+                settings,
+                registry: std::sync::Arc::new(ingest::parsers::ParserRegistry::with_defaults()),
+            });
+        }
+
+        // Rows still in plaintext or under a retired key are re-encrypted in the background
+        if crypto::cipher().is_some() {
+            let job = models::job_models::JobCreate::new("crypto.reencrypt", json!({}))
+                .dedupe_key("crypto.reencrypt");
+            jobs::enqueue(&db_pool, job).await?;
+        }
+
+        let schedules = scheduler::parse_schedules(&config.job_schedules)?;
+        scheduler::sync_schedules(&db_pool, &schedules).await?;
+        scheduler::spawn_scheduler(db_pool.clone());
+
+        info!(
+            workers = config.job_workers,
+            "Running background job workers"
+        );
+        jobs::JobWorkerPool {
+            db: db_pool.clone(),
+            registry: std::sync::Arc::new(registry),
+            workers: config.job_workers,
+            visibility_timeout: Duration::from_secs(config.job_visibility_timeout_secs),
+        }
+        .spawn();
+    }
 
     // Create application state with the database pool
     // This state will be shared across all req handlers
-    let app_state = handlers::AppState { db: db_pool };
-
-    // Build the Axum router
-    // Routes define which handler functions respond to which URL paths
-    let app = Router::new()
-        // Health check endpoint - no database required
-        .route("/health", get(health))
-        // Database health check - tests database connectivity
-        .route("/health/db", get(db_health))
-        // Create user endpoint
-        .route("/api/users", post(handlers::create_user_handler))
-        .route("/api/users/:email", get(handlers::get_user_handler))
-        .route("/api/users", get(handlers::get_users_handler))
-        .route(
-            "/api/transactions",
-            post(handlers::create_transaction_handler),
-        )
-        .route("/api/transactions", get(handlers::get_transactions_handler))
-        .route(
-            "/api/transactions/amount",
-            get(handlers::get_amount_handler),
-        )
-        // Add CORS middleware to allow cross-origin requests
-        // This is important for web applications making API calls
-        .layer(CorsLayer::permissive())
-        // Attach application state to the router
-        // This makes the database pool available to all handlers
-        .with_state(app_state);
+    let app_state = handlers::AppState {
+        db: db_pool.clone(),
+        blobs,
+        scanner,
+        default_category,
+        public_signup: config.public_signup,
+    };
+    if !config.public_signup {
+        info!("Signing up needs an invitation");
+    }
+
+    let debug_capture = debug_capture::DebugCapture {
+        db: db_pool,
+        enabled: config.debug_capture_enabled,
+        sample_rate: config.debug_capture_sample_rate,
+    };
+    if debug_capture.enabled {
+        info!(
+            sample_rate = debug_capture.sample_rate,
+            "Debug body capture enabled"
+        );
+    }
+
+    let json_format = json_format::JsonFormat {
+        case: config
+            .json_case
+            .parse::<json_format::FieldCase>()
+            .map_err(|e| anyhow::anyhow!(e))?,
+        dates: config
+            .json_dates
+            .parse::<json_format::DateFormat>()
+            .map_err(|e| anyhow::anyhow!(e))?,
+    };
+    if json_format != json_format::JsonFormat::default() {
+        info!(
+            case = %config.json_case,
+            dates = %config.json_dates,
+            "JSON responses formatted by default"
+        );
+    }
 
+    let dashboard = dashboard::Dashboard {
+        dir: config.dashboard_dir.clone().map(Into::into),
+        api_base: config.dashboard_api_base.clone(),
+    };
+
+    let webhook_verifiers = signatures::WebhookVerifiers::from_config(&config)?;
+
+    let telegram = match (
+        config.telegram_bot_token.clone(),
+        webhook_verifiers.get("telegram"),
+    ) {
+        (Some(token), Some(verifier)) => Some(telegram::TelegramBot {
+            state: app_state.clone(),
+            token,
+            verifier,
+            client: reqwest::Client::new(),
+        }),
+        // Anyone reaching the webhook could otherwise write to linked wallets
+        (Some(_), None) => {
+            return Err(anyhow::anyhow!(
+                "TELEGRAM_WEBHOOK_SECRET (or a telegram entry in WEBHOOK_SECRETS) is required when TELEGRAM_BOT_TOKEN is set"
+            ));
+        }
+        (None, _) => None,
+    };
+    if telegram.is_some() {
+        info!("Telegram bot enabled");
+    }
+
+    let slack = match webhook_verifiers.get("slack") {
+        Some(verifier) => Some(slack::SlackIntegration {
+            state: app_state.clone(),
+            verifier,
+            teams: slack::SlackIntegration::parse_teams(&config.slack_teams)?,
+        }),
+        None => None,
+    };
+    if let Some(slack) = &slack {
+        info!(
+            workspaces = slack.teams.len(),
+            "Slack slash command enabled"
+        );
+    }
+
+    let firefly_tokens = firefly::FireflyApi::parse_tokens(&config.firefly_tokens)?;
+    let firefly = (!firefly_tokens.is_empty()).then(|| firefly::FireflyApi {
+        state: app_state.clone(),
+        tokens: firefly_tokens,
+    });
+    if let Some(firefly) = &firefly {
+        info!(
+            tokens = firefly.tokens.len(),
+            "Firefly III compatible API enabled"
+        );
+    }
+
+    let billing = match &config.stripe_secret_key {
+        Some(secret_key) => {
+            let verifier = webhook_verifiers.get("stripe").ok_or_else(|| {
+                anyhow::anyhow!("Stripe billing needs the stripe signing secret in WEBHOOK_SECRETS")
+            })?;
+            if quotas::plans().is_none() {
+                return Err(anyhow::anyhow!("Stripe billing needs PLANS"));
+            }
+            if config.billing_success_url.is_empty() || config.billing_cancel_url.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Stripe billing needs BILLING_SUCCESS_URL and BILLING_CANCEL_URL"
+                ));
+            }
+            Some(billing::Billing {
+                state: app_state.clone(),
+                secret_key: secret_key.clone(),
+                verifier,
+                prices: billing::Billing::parse_prices(&config.stripe_prices)?,
+                success_url: config.billing_success_url.clone(),
+                cancel_url: config.billing_cancel_url.clone(),
+                client: reqwest::Client::new(),
+            })
+        }
+        None => None,
+    };
+    if let Some(billing) = &billing {
+        info!(paid_plans = billing.prices.len(), "Stripe billing enabled");
+    }
+
+    let admin = config.admin_token.clone().map(|token| admin::Admin {
+        state: app_state.clone(),
+        token,
+        min_group_size: config.analytics_min_group_size,
+        invitations: config.mailer_url.is_some(),
+        enrichment: config.merchant_api_url.is_some(),
+    });
+    if admin.is_some() {
+        info!("Admin API enabled");
+    }
+
+    let auth = config.jwt_secret.as_ref().map(|secret| auth::Auth {
+        state: app_state.clone(),
+        issuer: std::sync::Arc::new(auth::TokenIssuer::new(secret, config.jwt_ttl_secs)),
+        refresh_ttl_secs: config.jwt_refresh_ttl_secs,
+    });
+    if auth.is_some() {
+        info!("Access tokens required on /api/transactions, /api/reports and /api/users");
+    }
+
+    let background_jobs = cfg!(not(feature = "lambda"));
+    let meta = meta::Meta {
+        features: meta::Features {
+            bank_sync: true,
+            attachments: true,
+            attachment_thumbnails: background_jobs && !config.thumbnail_command.trim().is_empty(),
+            malware_scanning: app_state.scanner.is_some(),
+            multi_currency: true,
+            exchange_rates: background_jobs && config.fx_rates_url.is_some(),
+            merchant_enrichment: background_jobs && config.merchant_api_url.is_some(),
+            access_tokens: auth.is_some(),
+            public_signup: config.public_signup,
+            invitation_emails: background_jobs && config.mailer_url.is_some(),
+            email_receipts: background_jobs && ingest::ImapSettings::from_config(&config).is_some(),
+            billing: billing.is_some(),
+            telegram: telegram.is_some(),
+            slack: slack.is_some(),
+            firefly_api: firefly.is_some(),
+            background_jobs,
+        },
+        json_format,
+    };
+
+    let app = routes::router(
+        app_state,
+        routes::Integrations {
+            meta,
+            json_format,
+            debug_capture,
+            dashboard,
+            telegram,
+            slack,
+            firefly,
+            billing,
+            admin,
+            auth,
+        },
+    );
+
+    #[cfg(feature = "lambda")]
+    {
+        info!("Running as an AWS Lambda function");
+        lambda_http::run(app)
+            .await
+            .map_err(|e| anyhow::anyhow!("Lambda runtime error: {}", e))?;
+    }
+
+    #[cfg(not(feature = "lambda"))]
+    serve(&config, app).await?;
+
+    Ok(())
+}
+
+/// Run the standalone HTTP server
+#[cfg(not(feature = "lambda"))]
+async fn serve(config: &Config, app: Router) -> anyhow::Result<()> {
     // Create socket address from host and port
     // Parse the host string (e.g., "0.0.0.0") into an IP address
-    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
+    let addr: std::net::SocketAddr = format!("{}:{}", config.host, config.port)
         .parse()
         .map_err(|e| anyhow::anyhow!("Invalid address {}:{} - {}", config.host, config.port, e))?;
-    println!("🌐 Server listening on http://{}", addr);
 
     // Create a listener for graceful shutdown
     // This allows the server to finish handling requests before shutting down
     let listener = tokio::net::TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+    info!(address = %addr, "Server listening");
+
+    // Migrations ran before we got here, the server is ready once it listens
+    readiness::signal(config.ready_file.as_deref(), addr)?;
 
     // Start the server with graceful shutdown support
     // The server will run until it receives a shutdown signal (Ctrl+C)
-    axum::serve(listener, app).await?;
+    // Client addresses are handed to handlers that throttle per client
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
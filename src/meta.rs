@@ -0,0 +1,106 @@
+// Capability discovery
+//
+// GET /api/meta tells clients what this deployment offers, so they can show or hide parts
+// of their UI instead of assuming every server is configured alike: the server version,
+// which optional features are enabled, the limits requests are held to and the formats
+// accepted and produced. Everything reported is fixed at startup, the answer can be cached
+// for as long as the server runs.
+
+use crate::exporters::ExportTarget;
+use crate::importers::{self, ExportFormat, StatementFormat};
+use crate::json_format::JsonFormat;
+use crate::models::attachment_models;
+use crate::models::money_models::Currency;
+use crate::models::transaction_models::{
+    MAX_AMOUNT_FILTERS, MAX_PAGE_SIZE, MAX_TIMESERIES_BUCKETS,
+};
+use crate::problems;
+use crate::validation::{MAX_AMOUNT, MAX_BATCH_SIZE, MAX_DESCRIPTION_LEN};
+use axum::{Json, Router, extract::State, routing::get};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Optional features, whether the deployment has them enabled
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Features {
+    /// Bank accounts whose transactions a sync service records, with their sync status
+    pub bank_sync: bool,
+    pub attachments: bool,
+    pub attachment_thumbnails: bool,
+    pub malware_scanning: bool,
+    /// Amounts in any of `currencies`, with conversions
+    pub multi_currency: bool,
+    /// Latest exchange rates fetched on a schedule, used when none is given
+    pub exchange_rates: bool,
+    pub merchant_enrichment: bool,
+    pub access_tokens: bool,
+    pub public_signup: bool,
+    pub invitation_emails: bool,
+    pub email_receipts: bool,
+    pub billing: bool,
+    pub telegram: bool,
+    pub slack: bool,
+    pub firefly_api: bool,
+    /// Background jobs (imports of exports, thumbnails, webhooks, ...) run on this server
+    pub background_jobs: bool,
+}
+
+/// What GET /api/meta reports
+#[derive(Debug, Clone, Default)]
+pub struct Meta {
+    pub features: Features,
+    /// Default format of JSON responses
+    pub json_format: JsonFormat,
+}
+
+impl Meta {
+    pub fn router<S>(self) -> Router<S> {
+        Router::new()
+            .route("/api/meta", get(get_meta))
+            .with_state(Arc::new(self))
+    }
+}
+
+/// GET /api/meta - version, features, limits and formats of this deployment
+async fn get_meta(State(meta): State<Arc<Meta>>) -> Json<Value> {
+    let attachment_types: serde_json::Map<String, Value> = attachment_models::CONTENT_TYPES
+        .iter()
+        .map(|(content_type, max_bytes)| (content_type.to_string(), json!(max_bytes)))
+        .collect();
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "features": meta.features,
+        "currencies": Currency::ALL,
+        "limits": {
+            "batch_size": MAX_BATCH_SIZE,
+            "page_size": MAX_PAGE_SIZE,
+            "amount_filters": MAX_AMOUNT_FILTERS,
+            "timeseries_buckets": MAX_TIMESERIES_BUCKETS,
+            "description_length": MAX_DESCRIPTION_LEN,
+            "amount": MAX_AMOUNT,
+            "import_bytes": importers::MAX_BODY_BYTES,
+            "import_rows": importers::MAX_ROWS,
+        },
+        "formats": {
+            // POST /api/imports/<format>
+            "imports": ExportFormat::ALL.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            // POST /api/transactions/import
+            "statements": StatementFormat::ALL
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            // GET /api/exports/<target>
+            "exports": ExportTarget::ALL.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            // Largest size accepted of each type, in bytes
+            "attachments": attachment_types,
+            "json": {
+                "cases": ["snake", "camel"],
+                "dates": ["rfc3339", "epoch-millis"],
+                "default_case": meta.json_format.case.to_string(),
+                "default_dates": meta.json_format.dates.to_string(),
+            },
+            "errors": problems::CONTENT_TYPE,
+        },
+    }))
+}
@@ -1,23 +1,47 @@
 pub mod user_models {
+    use crate::validation::{
+        MAX_CATEGORY_LEN, MAX_CIPHERTEXT_LEN, MAX_NAME_LEN, MAX_PASSWORD_LEN, Validate,
+        ValidationErrors,
+    };
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
     use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use std::fmt;
+    use std::str::FromStr;
     use uuid::Uuid;
 
+    /// Emails are stored and looked up trimmed and lowercased, so "Ann@Example.com" and
+    /// "ann@example.com" are the same user
+    pub fn normalize_email(email: &str) -> String {
+        email.trim().to_lowercase()
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct UserCreate {
         pub email: String,
         pub name: String,
-        pub password: String,
+        /// argon2 hash from auth::hash_password, never the password itself
+        pub password_hash: String,
+        /// IANA time zone name, UTC when not given
+        pub timezone: Option<String>,
     }
 
     impl UserCreate {
-        pub fn new(email: String, name: String, password: String) -> Self {
+        pub fn new(email: String, name: String, password_hash: String) -> Self {
             Self {
                 email,
                 name,
-                password,
+                password_hash,
+                timezone: None,
             }
         }
+
+        pub fn timezone(mut self, timezone: Option<String>) -> Self {
+            self.timezone = timezone;
+            self
+        }
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +50,7 @@ pub mod user_models {
         pub email: String,
         pub name: String,
         pub password: String,
+        pub timezone: String,
         pub created_at: DateTime<Utc>,
         pub updated_at: DateTime<Utc>,
     }
@@ -35,6 +60,7 @@ pub mod user_models {
             email: String,
             name: String,
             password: String,
+            timezone: String,
             created_at: DateTime<Utc>,
             updated_at: DateTime<Utc>,
         ) -> Self {
@@ -43,40 +69,531 @@ pub mod user_models {
                 email,
                 name,
                 password,
+                timezone,
                 created_at,
                 updated_at,
             }
         }
     }
+    /// Position in the list of users, ordered by sign-up time then id
+    /// Handed to clients as an opaque string
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct UserCursor {
+        pub created_at: DateTime<Utc>,
+        pub id: Uuid,
+    }
+
+    impl UserCursor {
+        /// Right after the user, where the next page starts
+        pub fn after(user: &UserQuery) -> Self {
+            Self {
+                created_at: user.created_at,
+                id: user.id,
+            }
+        }
+    }
+
+    impl fmt::Display for UserCursor {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let raw = format!("{}:{}", self.created_at.timestamp_micros(), self.id);
+            f.write_str(&BASE64.encode(raw))
+        }
+    }
+
+    impl FromStr for UserCursor {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || format!("Invalid users cursor: {}", s);
+            let raw = BASE64.decode(s).map_err(|_| invalid())?;
+            let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+            let (micros, id) = raw.split_once(':').ok_or_else(invalid)?;
+            Ok(Self {
+                created_at: micros
+                    .parse()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_micros)
+                    .ok_or_else(invalid)?,
+                id: id.parse().map_err(|_| invalid())?,
+            })
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct UserListParameters {
+        /// Cursor returned with the previous page, the first page when not given
+        pub after: Option<String>,
+        /// Page size, at most 1000, 100 when not given
+        pub limit: Option<i64>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct EmailAvailabilityParameters {
+        pub email: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct UserImportParameters {
+        /// User whose wallet the rows with a role join
+        pub wallet_owner: Option<Uuid>,
+    }
+
+    // API request struct for an invited user choosing their password
+    #[derive(Deserialize)]
+    pub struct PasswordSetupRequest {
+        /// Token of the invitation link
+        pub token: String,
+        pub password: String,
+    }
+
+    // Hand-written so request logging never prints the token or the password
+    impl fmt::Debug for PasswordSetupRequest {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("PasswordSetupRequest")
+                .finish_non_exhaustive()
+        }
+    }
+
+    /// Longest password setup token looked up, those issued are 43 characters
+    const MAX_SETUP_TOKEN_LEN: usize = 256;
+
+    impl Validate for PasswordSetupRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_required("token", &self.token, MAX_SETUP_TOKEN_LEN);
+            errors.check_required("password", &self.password, MAX_PASSWORD_LEN);
+        }
+    }
+
     #[derive(serde::Deserialize)]
     pub struct CreateUserRequest {
         pub email: String,
         pub name: String,
         pub password: String,
+        pub timezone: Option<String>,
+        /// Token of an invitation, needed when public signup is off
+        pub invite_token: Option<String>,
+    }
+
+    impl Validate for CreateUserRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("email", &self.email);
+            errors.check_required("name", &self.name, MAX_NAME_LEN);
+            errors.check_required("password", &self.password, MAX_PASSWORD_LEN);
+            if let Some(timezone) = &self.timezone {
+                errors.check_timezone("timezone", timezone);
+            }
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct UsageParameters {
+        /// Not needed with an access token, whose user is "me"
+        pub user_email: Option<String>,
+    }
+
+    // Create-or-update by email, used when provisioning from an external identity system
+    #[derive(Debug, Clone)]
+    pub struct UserUpsert {
+        pub email: String,
+        pub name: String,
+        /// argon2 hash of the new password, the password is left unchanged when not given
+        pub password_hash: Option<String>,
+        /// Left unchanged on update when not given
+        pub timezone: Option<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct UpsertUserRequest {
+        pub email: String,
+        pub name: String,
+        pub password: Option<String>,
+        pub timezone: Option<String>,
+    }
+
+    impl Validate for UpsertUserRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("email", &self.email);
+            errors.check_required("name", &self.name, MAX_NAME_LEN);
+            if let Some(password) = &self.password {
+                errors.check_required("password", password, MAX_PASSWORD_LEN);
+            }
+            if let Some(timezone) = &self.timezone {
+                errors.check_timezone("timezone", timezone);
+            }
+        }
+    }
+
+    // API request struct for moving a user to another plan
+    #[derive(Deserialize, Debug)]
+    pub struct SetPlanRequest {
+        pub plan: String,
+    }
+
+    impl Validate for SetPlanRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_required("plan", &self.plan, MAX_CATEGORY_LEN);
+        }
+    }
+
+    /// Key a user's clients encrypt descriptions with, wrapped by a key derived on the
+    /// client from a secret the server never sees. Kept only so every device of the user
+    /// can fetch and unwrap it, the server cannot
+    #[derive(Debug, Clone, Serialize)]
+    pub struct UserEncryptionKey {
+        pub wrapped_key: String,
+        /// How the key is wrapped, e.g. "AES-256-GCM"
+        pub algorithm: String,
+        /// Whatever the client needs to derive the wrapping key again (salt, iterations, ...)
+        pub kdf_params: Value,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct PutEncryptionKeyRequest {
+        pub wrapped_key: String,
+        pub algorithm: String,
+        /// Defaults to an empty object
+        pub kdf_params: Option<Value>,
+    }
+
+    impl Validate for PutEncryptionKeyRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_ciphertext("wrapped_key", &self.wrapped_key, MAX_CIPHERTEXT_LEN);
+            errors.check_required("algorithm", &self.algorithm, MAX_CATEGORY_LEN);
+            if let Some(kdf_params) = &self.kdf_params {
+                if !kdf_params.is_object() {
+                    errors.add("kdf_params", "must be an object");
+                } else if kdf_params.to_string().len() > MAX_CIPHERTEXT_LEN {
+                    errors.add(
+                        "kdf_params",
+                        format!("must be at most {} bytes", MAX_CIPHERTEXT_LEN),
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub mod money_models {
+    use rust_decimal::prelude::ToPrimitive;
+    use rust_decimal::{Decimal, RoundingStrategy};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::ops::Neg;
+    use std::str::FromStr;
+
+    /// ISO 4217 currencies supported for amounts
+    /// Variants are named after their codes, which is also how they are serialized
+    #[allow(clippy::upper_case_acronyms)]
+    #[derive(
+        Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+    )]
+    pub enum Currency {
+        // Same default as the wallets table
+        #[default]
+        USD,
+        EUR,
+        GBP,
+        CHF,
+        JPY,
+        CAD,
+        AUD,
+        SEK,
+        NOK,
+        DKK,
+        PLN,
+    }
+
+    impl Currency {
+        /// Every supported currency
+        pub const ALL: [Currency; 11] = [
+            Currency::USD,
+            Currency::EUR,
+            Currency::GBP,
+            Currency::CHF,
+            Currency::JPY,
+            Currency::CAD,
+            Currency::AUD,
+            Currency::SEK,
+            Currency::NOK,
+            Currency::DKK,
+            Currency::PLN,
+        ];
+
+        /// Number of decimal places of the currency's minor unit (cents, pence, ...)
+        pub fn minor_unit_digits(self) -> u32 {
+            match self {
+                Currency::JPY => 0,
+                _ => 2,
+            }
+        }
+    }
+
+    impl fmt::Display for Currency {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                Currency::USD => "USD",
+                Currency::EUR => "EUR",
+                Currency::GBP => "GBP",
+                Currency::CHF => "CHF",
+                Currency::JPY => "JPY",
+                Currency::CAD => "CAD",
+                Currency::AUD => "AUD",
+                Currency::SEK => "SEK",
+                Currency::NOK => "NOK",
+                Currency::DKK => "DKK",
+                Currency::PLN => "PLN",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for Currency {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim() {
+                "USD" => Ok(Currency::USD),
+                "EUR" => Ok(Currency::EUR),
+                "GBP" => Ok(Currency::GBP),
+                "CHF" => Ok(Currency::CHF),
+                "JPY" => Ok(Currency::JPY),
+                "CAD" => Ok(Currency::CAD),
+                "AUD" => Ok(Currency::AUD),
+                "SEK" => Ok(Currency::SEK),
+                "NOK" => Ok(Currency::NOK),
+                "DKK" => Ok(Currency::DKK),
+                "PLN" => Ok(Currency::PLN),
+                _ => Err(format!("Invalid currency: {}", s)),
+            }
+        }
+    }
+
+    /// An exact amount of money, counted in the currency's minor unit (e.g. cents)
+    ///
+    /// Serialized as `{"amount": "-12.50", "currency": "EUR"}` with the amount as a string,
+    /// so JavaScript clients never round it through a float. Usually flattened into the
+    /// surrounding object. Requests may send the amount as a string or a number and may
+    /// leave out the currency, which then defaults to USD.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(into = "MoneyRepr", try_from = "MoneyInput")]
+    pub struct Money {
+        pub minor_units: i64,
+        pub currency: Currency,
+    }
+
+    impl Money {
+        pub fn new(minor_units: i64, currency: Currency) -> Self {
+            Self {
+                minor_units,
+                currency,
+            }
+        }
+
+        /// Convert an exact decimal amount
+        /// Fails when it has more decimal places than the currency allows (e.g. 1.005 EUR)
+        pub fn from_decimal(amount: Decimal, currency: Currency) -> Result<Self, String> {
+            let digits = currency.minor_unit_digits();
+            if amount.normalize().scale() > digits {
+                return Err(format!(
+                    "Amount {} has more than {} decimal place(s) for {}",
+                    amount, digits, currency
+                ));
+            }
+            Self::from_decimal_rounded(amount, currency)
+        }
+
+        /// Convert a decimal amount, rounding half away from zero to the currency's minor unit
+        /// Used for stored values, which may carry more precision than the currency
+        pub fn from_decimal_rounded(amount: Decimal, currency: Currency) -> Result<Self, String> {
+            let digits = currency.minor_unit_digits();
+            let minor_units = amount
+                .round_dp_with_strategy(digits, RoundingStrategy::MidpointAwayFromZero)
+                .checked_mul(Decimal::from(10i64.pow(digits)))
+                .and_then(|minor| minor.to_i64())
+                .ok_or_else(|| format!("Amount {} is out of range", amount))?;
+            Ok(Self::new(minor_units, currency))
+        }
+
+        pub fn to_decimal(self) -> Decimal {
+            Decimal::new(self.minor_units, self.currency.minor_unit_digits())
+        }
+
+        pub fn abs(self) -> Self {
+            Self::new(self.minor_units.abs(), self.currency)
+        }
+
+        pub fn is_zero(self) -> bool {
+            self.minor_units == 0
+        }
+    }
+
+    impl Neg for Money {
+        type Output = Money;
+
+        fn neg(self) -> Money {
+            Money::new(-self.minor_units, self.currency)
+        }
+    }
+
+    impl fmt::Display for Money {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} {}", self.to_decimal(), self.currency)
+        }
+    }
+
+    /// Wire format of `Money`
+    #[derive(Serialize)]
+    struct MoneyRepr {
+        amount: String,
+        currency: Currency,
+    }
+
+    impl From<Money> for MoneyRepr {
+        fn from(money: Money) -> Self {
+            Self {
+                amount: money.to_decimal().to_string(),
+                currency: money.currency,
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AmountInput {
+        Text(String),
+        Number(serde_json::Number),
+    }
+
+    #[derive(Deserialize)]
+    struct MoneyInput {
+        amount: AmountInput,
+        #[serde(default)]
+        currency: Currency,
+    }
+
+    impl TryFrom<MoneyInput> for Money {
+        type Error = String;
+
+        fn try_from(input: MoneyInput) -> Result<Self, Self::Error> {
+            // Numbers are parsed from their JSON text, never through f64 arithmetic
+            let text = match input.amount {
+                AmountInput::Text(text) => text,
+                AmountInput::Number(number) => number.to_string(),
+            };
+            let amount = Decimal::from_str(text.trim())
+                .or_else(|_| Decimal::from_scientific(text.trim()))
+                .map_err(|_| format!("Invalid amount: {}", text))?;
+            Money::from_decimal(amount, input.currency)
+        }
+    }
+
+    /// Running totals per currency, amounts in different currencies are never added together
+    #[derive(Debug, Clone, Default)]
+    pub struct MoneyTotals(BTreeMap<Currency, i64>);
+
+    impl MoneyTotals {
+        pub fn add(&mut self, money: Money) {
+            *self.0.entry(money.currency).or_default() += money.minor_units;
+        }
+
+        /// One total per currency, ordered by currency
+        pub fn to_vec(&self) -> Vec<Money> {
+            self.0
+                .iter()
+                .map(|(currency, minor_units)| Money::new(*minor_units, *currency))
+                .collect()
+        }
+    }
+
+    impl Serialize for MoneyTotals {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.to_vec().serialize(serializer)
+        }
+    }
+
+    impl fmt::Display for MoneyTotals {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let totals = self.to_vec();
+            if totals.is_empty() {
+                return f.write_str("0");
+            }
+            let parts: Vec<String> = totals.iter().map(ToString::to_string).collect();
+            f.write_str(&parts.join(", "))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn amounts_out_of_range_are_errors() {
+            let huge: Result<Money, _> = serde_json::from_str(
+                r#"{"amount":"79228162514264337593543950335","currency":"EUR"}"#,
+            );
+            assert!(huge.is_err());
+            let huge = Decimal::MAX;
+            assert!(Money::from_decimal_rounded(huge, Currency::EUR).is_err());
+            assert!(Money::from_decimal_rounded(-huge, Currency::JPY).is_err());
+            assert_eq!(
+                Money::from_decimal_rounded(Decimal::new(12345, 3), Currency::EUR),
+                Ok(Money::new(1235, Currency::EUR))
+            );
+        }
     }
 }
 
 pub mod transaction_models {
-    use chrono::{DateTime, Utc};
+    use crate::models::limit_models::LimitPeriod;
+    use crate::models::money_models::{Money, MoneyTotals};
+    use crate::redact;
+    use crate::validation::{
+        MAX_BATCH_SIZE, MAX_CATEGORY_LEN, MAX_CIPHERTEXT_LEN, MAX_DESCRIPTION_LEN,
+        MAX_EXTERNAL_ID_LEN, Validate, ValidationErrors,
+    };
+    use chrono::{
+        DateTime, Datelike, Duration, LocalResult, Months, NaiveDate, NaiveTime, TimeZone, Utc,
+    };
+    use chrono_tz::Tz;
     use rust_decimal::Decimal;
+    use serde::de::{self, IntoDeserializer};
     use serde::{Deserialize, Serialize};
     use sqlx;
+    use std::collections::BTreeMap;
+    use std::fmt;
     use std::str::FromStr;
     use uuid::Uuid;
 
     // Simple enums for internal type safety
-    #[derive(sqlx::Type, Debug, Clone, Serialize, Deserialize)]
+    // Names are matched case-insensitively along with a few synonyms, in FromStr, which JSON
+    // bodies, query params and stored values all go through
+    #[derive(sqlx::Type, Debug, Clone, PartialEq, Eq, Serialize)]
     #[sqlx(type_name = "transaction_type")]
     pub enum TransactionType {
         Expense,
         Income,
     }
 
-    impl ToString for TransactionType {
-        fn to_string(&self) -> String {
+    impl fmt::Display for TransactionType {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                TransactionType::Expense => "Expense",
+                TransactionType::Income => "Income",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl TransactionType {
+        /// Apply the sign convention: expenses are stored negative, income positive
+        /// Clients may send either sign, only the magnitude of the amount is kept
+        pub fn signed(&self, amount: Money) -> Money {
             match self {
-                TransactionType::Expense => "Expense".to_string(),
-                TransactionType::Income => "Income".to_string(),
+                TransactionType::Expense => -amount.abs(),
+                TransactionType::Income => amount.abs(),
             }
         }
     }
@@ -85,15 +602,73 @@ pub mod transaction_models {
         type Err = String;
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
-            match s {
-                "Expense" => Ok(TransactionType::Expense),
-                "Income" => Ok(TransactionType::Income),
+            match s.trim().to_lowercase().as_str() {
+                "expense" | "debit" => Ok(TransactionType::Expense),
+                "income" | "credit" => Ok(TransactionType::Income),
                 _ => Err(format!("Invalid transaction type: {}", s)),
             }
         }
     }
 
-    #[derive(Debug, Clone, Deserialize, Serialize)]
+    impl<'de> Deserialize<'de> for TransactionType {
+        fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(de::Error::custom)
+        }
+    }
+
+    /// Where a transaction originated from
+    /// Together with `external_id` this makes imports and bank syncs idempotent
+    #[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[sqlx(type_name = "transaction_source")]
+    pub enum TransactionSource {
+        Manual,
+        Import,
+        BankSync,
+        Api,
+        Email,
+        Telegram,
+        Slack,
+        /// Weekly allowance of a child account
+        Allowance,
+    }
+
+    impl fmt::Display for TransactionSource {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                TransactionSource::Manual => "Manual",
+                TransactionSource::Import => "Import",
+                TransactionSource::BankSync => "BankSync",
+                TransactionSource::Api => "Api",
+                TransactionSource::Email => "Email",
+                TransactionSource::Telegram => "Telegram",
+                TransactionSource::Slack => "Slack",
+                TransactionSource::Allowance => "Allowance",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for TransactionSource {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "Manual" => Ok(TransactionSource::Manual),
+                "Import" => Ok(TransactionSource::Import),
+                "BankSync" => Ok(TransactionSource::BankSync),
+                "Api" => Ok(TransactionSource::Api),
+                "Email" => Ok(TransactionSource::Email),
+                "Telegram" => Ok(TransactionSource::Telegram),
+                "Slack" => Ok(TransactionSource::Slack),
+                "Allowance" => Ok(TransactionSource::Allowance),
+                _ => Err(format!("Invalid transaction source: {}", s)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
     pub enum TransactionCategory {
         Groceries,
         Restaurant,
@@ -102,75 +677,338 @@ pub mod transaction_models {
         Shopping,
         Entertainment,
         Other,
+        /// Imported without a category, waiting to be sorted out
+        Uncategorized,
     }
 
-    impl ToString for TransactionCategory {
-        fn to_string(&self) -> String {
-            match self {
-                TransactionCategory::Groceries => "Groceries".to_string(),
-                TransactionCategory::Restaurant => "Restaurant".to_string(),
-                TransactionCategory::Housing => "Housing".to_string(),
-                TransactionCategory::Holidays => "Holidays".to_string(),
-                TransactionCategory::Shopping => "Shopping".to_string(),
-                TransactionCategory::Entertainment => "Entertainment".to_string(),
-                TransactionCategory::Other => "Other".to_string(),
-            }
+    impl fmt::Display for TransactionCategory {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                TransactionCategory::Groceries => "Groceries",
+                TransactionCategory::Restaurant => "Restaurant",
+                TransactionCategory::Housing => "Housing",
+                TransactionCategory::Holidays => "Holidays",
+                TransactionCategory::Shopping => "Shopping",
+                TransactionCategory::Entertainment => "Entertainment",
+                TransactionCategory::Other => "Other",
+                TransactionCategory::Uncategorized => "Uncategorized",
+            };
+            f.write_str(s)
         }
     }
 
+    impl TransactionCategory {
+        /// Every category, in display order
+        pub const ALL: [TransactionCategory; 8] = [
+            TransactionCategory::Groceries,
+            TransactionCategory::Restaurant,
+            TransactionCategory::Housing,
+            TransactionCategory::Holidays,
+            TransactionCategory::Shopping,
+            TransactionCategory::Entertainment,
+            TransactionCategory::Other,
+            TransactionCategory::Uncategorized,
+        ];
+    }
+
     impl FromStr for TransactionCategory {
         type Err = String;
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
-            match s {
-                "Groceries" => Ok(TransactionCategory::Groceries),
-                "Restaurant" => Ok(TransactionCategory::Restaurant),
-                "Housing" => Ok(TransactionCategory::Housing),
-                "Holidays" => Ok(TransactionCategory::Holidays),
-                "Shopping" => Ok(TransactionCategory::Shopping),
-                "Entertainment" => Ok(TransactionCategory::Entertainment),
-                "Other" => Ok(TransactionCategory::Other),
+            match s.trim().to_lowercase().as_str() {
+                "groceries" | "grocery" | "food" | "supermarket" => {
+                    Ok(TransactionCategory::Groceries)
+                }
+                "restaurant" | "restaurants" | "dining" | "eating_out" => {
+                    Ok(TransactionCategory::Restaurant)
+                }
+                "housing" | "rent" | "mortgage" | "utilities" => Ok(TransactionCategory::Housing),
+                "holidays" | "holiday" | "travel" | "vacation" => Ok(TransactionCategory::Holidays),
+                "shopping" | "clothes" => Ok(TransactionCategory::Shopping),
+                "entertainment" | "fun" | "leisure" => Ok(TransactionCategory::Entertainment),
+                "other" | "misc" => Ok(TransactionCategory::Other),
+                "uncategorized" | "uncategorised" => Ok(TransactionCategory::Uncategorized),
                 _ => Err(format!("Invalid transaction category: {}", s)),
             }
         }
     }
 
+    impl<'de> Deserialize<'de> for TransactionCategory {
+        fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(de::Error::custom)
+        }
+    }
+
     // Internal struct with type-safe enums
     #[derive(Debug, Clone)]
     pub struct TransactionCreate {
+        /// Generated by the database when None, clients working offline pick their own
+        pub id: Option<Uuid>,
         pub user_id: Uuid,
         pub transaction_type: TransactionType,
-        pub amount: f64,
+        /// Signed by type: negative for expenses, positive for income
+        pub amount: Money,
         pub category: TransactionCategory,
         pub description: String,
+        /// Description encrypted on the client, stored as is, `description` is then empty
+        pub encrypted_description: Option<String>,
+        pub source: TransactionSource,
+        pub external_id: Option<String>,
+        /// When the transaction happened, defaults to insertion time when not given
+        pub occurred_at: Option<DateTime<Utc>>,
+        /// Bank account the transaction was synced from
+        pub bank_account_id: Option<Uuid>,
+        /// Account of the user the transaction is booked to
+        pub account_id: Option<Uuid>,
     }
 
     impl TransactionCreate {
         pub fn new(
             user_id: Uuid,
             transaction_type: TransactionType,
-            amount: f64,
+            amount: Money,
             category: Option<TransactionCategory>,
             description: Option<String>,
         ) -> Self {
             Self {
+                id: None,
                 user_id,
+                amount: transaction_type.signed(amount),
                 transaction_type,
-                amount,
                 category: category.unwrap_or(TransactionCategory::Other),
                 description: description.unwrap_or_default(),
+                encrypted_description: None,
+                source: TransactionSource::Manual,
+                external_id: None,
+                occurred_at: None,
+                bank_account_id: None,
+                account_id: None,
+            }
+        }
+
+        /// Use an id chosen by the client instead of a generated one
+        pub fn id(mut self, id: Uuid) -> Self {
+            self.id = Some(id);
+            self
+        }
+
+        /// Store a description encrypted on the client instead of a plaintext one
+        pub fn encrypted_description(mut self, encrypted_description: Option<String>) -> Self {
+            if encrypted_description.is_some() {
+                self.description = String::new();
             }
+            self.encrypted_description = encrypted_description;
+            self
+        }
+
+        /// Link the transaction to the bank account it was synced from
+        pub fn bank_account(mut self, bank_account_id: Option<Uuid>) -> Self {
+            self.bank_account_id = bank_account_id;
+            self
+        }
+
+        /// Book the transaction to one of the user's accounts
+        pub fn account(mut self, account_id: Option<Uuid>) -> Self {
+            self.account_id = account_id;
+            self
+        }
+
+        /// Set the date the transaction happened (e.g. the booking date of an imported row)
+        pub fn occurred_at(mut self, occurred_at: Option<DateTime<Utc>>) -> Self {
+            self.occurred_at = occurred_at;
+            self
+        }
+
+        /// Tag the transaction with its origin and the id it has in that system
+        /// Re-inserting the same (source, external_id) pair for a user is a no-op
+        pub fn with_origin(
+            mut self,
+            source: TransactionSource,
+            external_id: Option<String>,
+        ) -> Self {
+            self.source = source;
+            self.external_id = external_id;
+            self
         }
     }
 
     // API request struct - accepts simple strings
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize)]
     pub struct CreateTransactionRequest {
         pub user_email: String,
-        pub transaction_type: String,
-        pub amount: f64,
-        pub category: Option<String>,
+        pub transaction_type: TransactionType,
+        /// Amount and currency, flattened as `"amount"` and `"currency"`
+        #[serde(flatten)]
+        pub amount: Money,
+        /// Defaults to Other
+        pub category: Option<TransactionCategory>,
+        pub description: Option<String>,
+        /// Description encrypted on the client (see the user's encryption key), instead of `description`
+        pub encrypted_description: Option<String>,
+        pub source: Option<String>,
+        pub external_id: Option<String>,
+        /// Editor of `user_email`'s shared wallet recording the transaction, when not the owner
+        pub created_by_email: Option<String>,
+        /// Account of `user_email` to book it to, in the same currency as the amount
+        pub account_id: Option<Uuid>,
+        /// Record it even though it goes over spending limits that allow overriding
+        #[serde(default)]
+        pub confirm_over_limit: bool,
+    }
+
+    // Hand-written so request logging never prints the email, amount or description in clear
+    impl fmt::Debug for CreateTransactionRequest {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("CreateTransactionRequest")
+                .field("user_email", &redact::email(&self.user_email))
+                .field("transaction_type", &self.transaction_type)
+                .field("amount", &redact::amount(self.amount))
+                .field("category", &self.category)
+                .field(
+                    "description",
+                    &self.description.as_deref().map(redact::text),
+                )
+                .field(
+                    "encrypted_description",
+                    &self.encrypted_description.as_ref().map(String::len),
+                )
+                .field("source", &self.source)
+                .field("external_id", &self.external_id)
+                .field(
+                    "created_by_email",
+                    &self.created_by_email.as_deref().map(redact::email),
+                )
+                .field("account_id", &self.account_id)
+                .field("confirm_over_limit", &self.confirm_over_limit)
+                .finish()
+        }
+    }
+
+    impl Validate for CreateTransactionRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            if let Some(created_by_email) = &self.created_by_email {
+                errors.check_email("created_by_email", created_by_email);
+            }
+            errors.check_amount("amount", self.amount);
+            if let Some(description) = &self.description {
+                errors.check_length("description", description, MAX_DESCRIPTION_LEN);
+            }
+            if let Some(encrypted_description) = &self.encrypted_description {
+                errors.check_ciphertext(
+                    "encrypted_description",
+                    encrypted_description,
+                    MAX_CIPHERTEXT_LEN,
+                );
+                if self.description.as_deref().is_some_and(|d| !d.is_empty()) {
+                    errors.add(
+                        "encrypted_description",
+                        "must not be given together with description",
+                    );
+                }
+            }
+            if let Some(source) = &self.source {
+                errors.check_length("source", source, MAX_CATEGORY_LEN);
+            }
+            if let Some(external_id) = &self.external_id {
+                errors.check_length("external_id", external_id, MAX_EXTERNAL_ID_LEN);
+            }
+        }
+    }
+
+    // One row of a batch import, same shape as a single create minus the user
+    #[derive(Deserialize, Debug)]
+    pub struct BatchTransactionItem {
+        pub transaction_type: TransactionType,
+        #[serde(flatten)]
+        pub amount: Money,
+        pub category: Option<TransactionCategory>,
         pub description: Option<String>,
+        pub external_id: Option<String>,
+        pub occurred_at: Option<DateTime<Utc>>,
+    }
+
+    // API request struct for importing many transactions at once
+    #[derive(Deserialize, Debug)]
+    pub struct BatchTransactionRequest {
+        pub user_email: String,
+        pub source: Option<String>,
+        pub bank_account_id: Option<Uuid>,
+        pub transactions: Vec<BatchTransactionItem>,
+        /// Record them even though they go over spending limits that allow overriding
+        #[serde(default)]
+        pub confirm_over_limit: bool,
+    }
+
+    impl Validate for BatchTransactionRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            if let Some(source) = &self.source {
+                errors.check_length("source", source, MAX_CATEGORY_LEN);
+            }
+            if self.transactions.len() > MAX_BATCH_SIZE {
+                errors.add(
+                    "transactions",
+                    format!("must contain at most {} items", MAX_BATCH_SIZE),
+                );
+                return;
+            }
+            for (i, item) in self.transactions.iter().enumerate() {
+                let field = |name: &str| format!("transactions[{}].{}", i, name);
+                errors.check_amount(&field("amount"), item.amount);
+                if let Some(description) = &item.description {
+                    errors.check_length(&field("description"), description, MAX_DESCRIPTION_LEN);
+                }
+                if let Some(external_id) = &item.external_id {
+                    errors.check_length(&field("external_id"), external_id, MAX_EXTERNAL_ID_LEN);
+                }
+            }
+        }
+    }
+
+    // Query parameters of an import of another app's CSV export, the body is the CSV itself
+    #[derive(Deserialize, Debug)]
+    pub struct ExportImportParameters {
+        pub user_email: String,
+        /// Currency of the amounts in the export, defaults to USD
+        #[serde(default)]
+        pub currency: crate::models::money_models::Currency,
+        pub bank_account_id: Option<Uuid>,
+    }
+
+    // Query parameters of a bank statement file imported within the request
+    #[derive(Deserialize, Debug)]
+    pub struct StatementImportParameters {
+        pub user_email: String,
+        /// csv, ofx or qif, told by the file's extension when not given, csv otherwise
+        pub format: Option<String>,
+        /// Currency of the amounts in the file, defaults to USD, OFX files tell their own
+        #[serde(default)]
+        pub currency: crate::models::money_models::Currency,
+        pub bank_account_id: Option<Uuid>,
+        /// Check the file and report what would be imported, storing nothing
+        #[serde(default)]
+        pub dry_run: bool,
+        /// Import rows going over spending limits that allow overriding, instead of rejecting them
+        #[serde(default)]
+        pub confirm_over_limit: bool,
+    }
+
+    // Query parameters of an export of a user's transactions
+    #[derive(Deserialize, Debug)]
+    pub struct ExportParameters {
+        pub user_email: String,
+        pub start_timestamp: Option<DateTime<Utc>>,
+        pub end_timestamp: Option<DateTime<Utc>>,
+        /// Only transactions in this currency, for apps keeping one currency per budget (YNAB)
+        pub currency: Option<crate::models::money_models::Currency>,
+        /// Export the transactions as they were at this moment, before later corrections
+        pub as_of: Option<DateTime<Utc>>,
+        /// Answer with a download link of the export in the blob store instead of the file
+        #[serde(default)]
+        pub link: bool,
     }
 
     #[derive(Deserialize, Debug, Serialize)]
@@ -178,20 +1016,41 @@ pub mod transaction_models {
         pub id: Uuid,
         pub user_id: Uuid,
         pub transaction_type: TransactionType,
-        pub amount: Decimal,
+        /// Negative for expenses, positive for income (enforced by the database)
+        #[serde(flatten)]
+        pub amount: Money,
         pub category: TransactionCategory,
+        /// Empty when the description is encrypted on the client
         pub description: String,
+        /// Ciphertext only the user's clients can decrypt, None for plaintext descriptions
+        pub encrypted_description: Option<String>,
+        pub source: TransactionSource,
+        pub external_id: Option<String>,
+        pub bank_account_id: Option<Uuid>,
+        /// Account of the user the transaction is booked to
+        pub account_id: Option<Uuid>,
         pub created_at: DateTime<Utc>,
         pub last_updated_at: DateTime<Utc>,
+        /// Clean name of the merchant found for the description, None until enriched
+        pub merchant_name: Option<String>,
+        pub merchant_logo_url: Option<String>,
+        /// When the transaction was deleted, only listed with `include_deleted`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub deleted_at: Option<DateTime<Utc>>,
     }
     impl TransactionQuery {
+        #[allow(clippy::too_many_arguments)]
         pub fn new(
             id: Uuid,
             user_id: Uuid,
             transaction_type: TransactionType,
-            amount: Decimal,
+            amount: Money,
             category: TransactionCategory,
             description: String,
+            encrypted_description: Option<String>,
+            source: TransactionSource,
+            external_id: Option<String>,
+            bank_account_id: Option<Uuid>,
             created_at: DateTime<Utc>,
             last_updated_at: DateTime<Utc>,
         ) -> Self {
@@ -202,20 +1061,3834 @@ pub mod transaction_models {
                 amount,
                 category,
                 description,
+                encrypted_description,
+                source,
+                external_id,
+                bank_account_id,
+                account_id: None,
                 created_at,
                 last_updated_at,
+                merchant_name: None,
+                merchant_logo_url: None,
+                deleted_at: None,
             }
         }
     }
 
-    #[derive(Deserialize, Debug, Serialize)]
-    pub struct TransactionGetParameters {
-        pub user_id: Option<Uuid>,
-        pub category: Option<String>,
-        pub transaction_type: Option<String>,
-        pub amount_min: Option<Decimal>,
-        pub amount_max: Option<Decimal>,
-        pub start_timestamp: Option<DateTime<Utc>>,
-        pub end_timestamp: Option<DateTime<Utc>>,
+    /// Named time range, resolved against the current time in the user's time zone
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Period {
+        ThisMonth,
+        LastMonth,
+        #[serde(rename = "last_30_days")]
+        Last30Days,
+        Ytd,
+        LastYear,
+    }
+
+    impl fmt::Display for Period {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                Period::ThisMonth => "This month",
+                Period::LastMonth => "Last month",
+                Period::Last30Days => "Last 30 days",
+                Period::Ytd => "Year to date",
+                Period::LastYear => "Last year",
+            };
+            write!(f, "{}", s)
+        }
+    }
+
+    impl FromStr for Period {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Self::deserialize(s.trim().into_deserializer())
+                .map_err(|_: de::value::Error| format!("Invalid period: {}", s))
+        }
+    }
+
+    /// Midnight at the start of `date` in `tz`
+    /// Days starting inside a DST gap begin at the first valid instant
+    pub fn local_midnight(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+        let midnight = date.and_time(NaiveTime::MIN);
+        match tz.from_local_datetime(&midnight) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.with_timezone(&Utc),
+            LocalResult::None => tz
+                .from_local_datetime(&(midnight + Duration::hours(1)))
+                .earliest()
+                .map(|t| t.with_timezone(&Utc))
+                .unwrap_or_else(|| midnight.and_utc()),
+        }
+    }
+
+    impl Period {
+        /// Start and (inclusive) end of the period as of `now`, with day boundaries in `tz`
+        pub fn range(self, now: DateTime<Utc>, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+            let today = now.with_timezone(&tz).date_naive();
+            let month_start = today.with_day(1).unwrap_or(today);
+            let year_start = today.with_ordinal(1).unwrap_or(today);
+            let end_of_today =
+                local_midnight(today + Duration::days(1), tz) - Duration::microseconds(1);
+
+            match self {
+                Period::ThisMonth => (local_midnight(month_start, tz), end_of_today),
+                Period::LastMonth => {
+                    let previous = month_start
+                        .checked_sub_months(Months::new(1))
+                        .unwrap_or(month_start);
+                    (
+                        local_midnight(previous, tz),
+                        local_midnight(month_start, tz) - Duration::microseconds(1),
+                    )
+                }
+                // Today and the 29 days before it
+                Period::Last30Days => {
+                    (local_midnight(today - Duration::days(29), tz), end_of_today)
+                }
+                Period::Ytd => (local_midnight(year_start, tz), end_of_today),
+                Period::LastYear => {
+                    let previous = year_start
+                        .with_year(year_start.year() - 1)
+                        .unwrap_or(year_start);
+                    (
+                        local_midnight(previous, tz),
+                        local_midnight(year_start, tz) - Duration::microseconds(1),
+                    )
+                }
+            }
+        }
+    }
+
+    /// ISO 8601 duration such as `P30D`, `P2W`, `P1M` or `PT12H`, used to look back from now
+    /// Years and months are calendar units, the rest fixed lengths
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct IsoDuration {
+        months: u32,
+        duration: Duration,
+    }
+
+    impl IsoDuration {
+        /// The instant this long before `t`, None if out of range
+        pub fn before(self, t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+            t.checked_sub_months(Months::new(self.months))?
+                .checked_sub_signed(self.duration)
+        }
+    }
+
+    impl FromStr for IsoDuration {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || format!("Invalid ISO 8601 duration: {}", s);
+            let rest = s
+                .trim()
+                .to_ascii_uppercase()
+                .strip_prefix('P')
+                .map(str::to_string)
+                .ok_or_else(invalid)?;
+            let (date_part, time_part) = match rest.split_once('T') {
+                Some((date, time)) if !time.is_empty() => {
+                    (date.to_string(), Some(time.to_string()))
+                }
+                Some(_) => return Err(invalid()),
+                None => (rest, None),
+            };
+
+            let mut months: u32 = 0;
+            let mut seconds: i64 = 0;
+            let mut components = 0;
+            for (part, is_time) in [(Some(date_part), false), (time_part, true)] {
+                let Some(part) = part else { continue };
+                let mut number = String::new();
+                for c in part.chars() {
+                    if c.is_ascii_digit() {
+                        number.push(c);
+                        continue;
+                    }
+                    let n: u32 = number.parse().map_err(|_| invalid())?;
+                    number.clear();
+                    components += 1;
+                    let n64 = i64::from(n);
+                    match (c, is_time) {
+                        ('Y', false) => {
+                            months = n
+                                .checked_mul(12)
+                                .and_then(|m| months.checked_add(m))
+                                .ok_or_else(invalid)?
+                        }
+                        ('M', false) => months = months.checked_add(n).ok_or_else(invalid)?,
+                        ('W', false) => seconds += n64 * 7 * 86_400,
+                        ('D', false) => seconds += n64 * 86_400,
+                        ('H', true) => seconds += n64 * 3_600,
+                        ('M', true) => seconds += n64 * 60,
+                        ('S', true) => seconds += n64,
+                        _ => return Err(invalid()),
+                    }
+                }
+                if !number.is_empty() {
+                    return Err(invalid());
+                }
+            }
+            if components == 0 {
+                return Err(invalid());
+            }
+
+            Ok(Self {
+                months,
+                duration: Duration::seconds(seconds),
+            })
+        }
+    }
+
+    impl TryFrom<String> for IsoDuration {
+        type Error = String;
+
+        fn try_from(s: String) -> Result<Self, Self::Error> {
+            s.parse()
+        }
+    }
+
+    impl fmt::Display for IsoDuration {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let days = self.duration.num_days();
+            let seconds = self.duration.num_seconds() - days * 86_400;
+            write!(f, "P")?;
+            if self.months > 0 {
+                write!(f, "{}M", self.months)?;
+            }
+            if days > 0 {
+                write!(f, "{}D", days)?;
+            }
+            if seconds > 0 || (self.months == 0 && days == 0) {
+                write!(f, "T{}S", seconds)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl From<IsoDuration> for String {
+        fn from(duration: IsoDuration) -> Self {
+            duration.to_string()
+        }
+    }
+
+    #[derive(Deserialize, Debug, Serialize)]
+    pub struct TransactionGetParameters {
+        pub user_id: Option<Uuid>,
+        // Parsed by the query extractor, unknown values are rejected with 400
+        pub category: Option<TransactionCategory>,
+        pub transaction_type: Option<TransactionType>,
+        pub amount_min: Option<Decimal>,
+        pub amount_max: Option<Decimal>,
+        pub start_timestamp: Option<DateTime<Utc>>,
+        pub end_timestamp: Option<DateTime<Utc>>,
+        /// Shorthand for a start and end timestamp, cannot be combined with them
+        pub period: Option<Period>,
+        /// Start this long before now, e.g. `P30D`, instead of at `start_timestamp`
+        pub since: Option<IsoDuration>,
+        /// Start at midnight `n - 1` days ago in the user's time zone, so today counts as one
+        pub last_n_days: Option<u32>,
+        /// Report the transactions as they were at this moment, before later corrections
+        pub as_of: Option<DateTime<Utc>>,
+        /// Page size, everything when not given
+        pub limit: Option<i64>,
+        pub offset: Option<i64>,
+        #[serde(default)]
+        pub sort: TransactionSort,
+        /// Also count every transaction matching the filters, for pagination
+        #[serde(default)]
+        pub include_total: bool,
+        /// Also list, count and add up deleted transactions
+        #[serde(default)]
+        pub include_deleted: bool,
+    }
+
+    impl TransactionGetParameters {
+        /// The filter these parameters select, once relative dates are resolved
+        pub fn filter(&self) -> TransactionFilter {
+            let filter = TransactionFilter {
+                user_id: self.user_id,
+                ..TransactionFilter::default()
+            };
+            filter
+                .categories(self.category.clone())
+                .transaction_types(self.transaction_type.clone())
+                .amount_range(self.amount_min, self.amount_max)
+                .period(self.start_timestamp, self.end_timestamp)
+                .at(self.as_of)
+                .page(
+                    self.limit.map(|limit| limit.clamp(1, MAX_PAGE_SIZE)),
+                    self.offset,
+                )
+                .sort(self.sort)
+                .include_deleted(self.include_deleted)
+        }
+    }
+
+    /// `?dry_run=true` on writes: everything is checked and nothing stored, the answer tells
+    /// what would be
+    #[derive(Deserialize)]
+    pub struct DryRunParameters {
+        #[serde(default)]
+        pub dry_run: bool,
+    }
+
+    #[derive(Deserialize)]
+    pub struct UncategorizedGetParameters {
+        pub user_id: Uuid,
+        /// Page size, at most `MAX_PAGE_SIZE`, 100 when not given
+        pub limit: Option<i64>,
+        pub offset: Option<i64>,
+    }
+
+    /// Whose transaction is deleted or restored
+    #[derive(Deserialize)]
+    pub struct TransactionDeleteParameters {
+        pub user_email: String,
+    }
+
+    /// Transactions recorded under the wrong user, moved to the right one by a repair job
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct ReassignTransactionsRequest {
+        pub from_user_id: Uuid,
+        pub to_user_id: Uuid,
+        /// Only these, every one of `from_user_id` when not given
+        pub transaction_ids: Option<Vec<Uuid>>,
+    }
+
+    impl Validate for ReassignTransactionsRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.from_user_id == self.to_user_id {
+                errors.add("to_user_id", "must be another user than from_user_id");
+            }
+            match &self.transaction_ids {
+                Some(ids) if ids.is_empty() => errors.add("transaction_ids", "must not be empty"),
+                Some(ids) if ids.len() > MAX_BATCH_SIZE => errors.add(
+                    "transaction_ids",
+                    format!("must contain at most {} items", MAX_BATCH_SIZE),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    /// What a reassignment moved, and what it left with the previous user
+    #[derive(Debug, Clone, Default, Serialize)]
+    pub struct ReassignOutcome {
+        pub moved: u64,
+        /// In a reconciled period of their bank account, or with a client-encrypted description
+        pub left: i64,
+    }
+
+    /// Transactions whose category is looked up again by a repair job
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct RecategorizeRequest {
+        /// Every user's when not given
+        pub user_id: Option<Uuid>,
+    }
+
+    /// Largest page of transactions listed at once
+    pub const MAX_PAGE_SIZE: i64 = 1000;
+
+    /// Order transactions are listed in, ties broken by id so pages never overlap
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+    pub enum TransactionSort {
+        /// Oldest first
+        #[default]
+        #[serde(rename = "created_at")]
+        CreatedAt,
+        #[serde(rename = "-created_at")]
+        CreatedAtDesc,
+        /// By signed amount, so the largest expense comes first
+        #[serde(rename = "amount")]
+        Amount,
+        #[serde(rename = "-amount")]
+        AmountDesc,
+    }
+
+    impl TransactionSort {
+        /// ORDER BY clause of the sort
+        pub fn order_by(self) -> &'static str {
+            match self {
+                TransactionSort::CreatedAt => "created_at, id",
+                TransactionSort::CreatedAtDesc => "created_at DESC, id DESC",
+                TransactionSort::Amount => "amount, id",
+                TransactionSort::AmountDesc => "amount DESC, id DESC",
+            }
+        }
+    }
+
+    /// Which transactions a query reads, and in what order
+    /// Shared by the listing, sum, export and report queries so they all filter alike
+    #[derive(Debug, Clone, Default)]
+    pub struct TransactionFilter {
+        /// Every user's transactions when not given
+        pub user_id: Option<Uuid>,
+        /// Any of these, every category when empty
+        pub categories: Vec<TransactionCategory>,
+        /// Any of these, both types when empty
+        pub transaction_types: Vec<TransactionType>,
+        /// Signed amounts, expenses are negative
+        pub amount_min: Option<Decimal>,
+        pub amount_max: Option<Decimal>,
+        /// Created from `start` to `end`, both inclusive
+        pub start: Option<DateTime<Utc>>,
+        pub end: Option<DateTime<Utc>>,
+        /// Read the transactions as they were at this moment
+        pub as_of: Option<DateTime<Utc>>,
+        pub limit: Option<i64>,
+        pub offset: Option<i64>,
+        pub sort: TransactionSort,
+        /// Deleted transactions too, there are none in past versions
+        pub include_deleted: bool,
+    }
+
+    impl TransactionFilter {
+        pub fn for_user(user_id: Uuid) -> Self {
+            Self {
+                user_id: Some(user_id),
+                ..Self::default()
+            }
+        }
+
+        pub fn categories(
+            mut self,
+            categories: impl IntoIterator<Item = TransactionCategory>,
+        ) -> Self {
+            self.categories.extend(categories);
+            self
+        }
+
+        pub fn transaction_types(
+            mut self,
+            transaction_types: impl IntoIterator<Item = TransactionType>,
+        ) -> Self {
+            self.transaction_types.extend(transaction_types);
+            self
+        }
+
+        pub fn amount_range(mut self, min: Option<Decimal>, max: Option<Decimal>) -> Self {
+            self.amount_min = min;
+            self.amount_max = max;
+            self
+        }
+
+        pub fn period(mut self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Self {
+            self.start = start;
+            self.end = end;
+            self
+        }
+
+        /// Read the transactions as they were at `as_of`
+        pub fn at(mut self, as_of: Option<DateTime<Utc>>) -> Self {
+            self.as_of = as_of;
+            self
+        }
+
+        pub fn page(mut self, limit: Option<i64>, offset: Option<i64>) -> Self {
+            self.limit = limit;
+            self.offset = offset;
+            self
+        }
+
+        pub fn sort(mut self, sort: TransactionSort) -> Self {
+            self.sort = sort;
+            self
+        }
+
+        pub fn include_deleted(mut self, include_deleted: bool) -> Self {
+            self.include_deleted = include_deleted;
+            self
+        }
+    }
+
+    /// Income, expenses and what is left of the income, each totalled per currency
+    #[derive(Debug, Clone, Default, Serialize)]
+    pub struct TransactionSum {
+        pub income: MoneyTotals,
+        /// Positive, although expenses are stored negative
+        pub expenses: MoneyTotals,
+        /// Income minus expenses
+        pub net: MoneyTotals,
+    }
+
+    impl TransactionSum {
+        pub fn add(&mut self, transaction_type: &TransactionType, amount: Money) {
+            match transaction_type {
+                TransactionType::Income => self.income.add(amount),
+                TransactionType::Expense => self.expenses.add(-amount),
+            }
+            self.net.add(amount);
+        }
+    }
+
+    /// Sums of the transactions of a category, totalled per currency
+    #[derive(Debug, Clone, Serialize)]
+    pub struct CategorySum {
+        pub category: TransactionCategory,
+        #[serde(flatten)]
+        pub sum: TransactionSum,
+    }
+
+    /// Most filters added up in one request
+    pub const MAX_AMOUNT_FILTERS: usize = 20;
+
+    /// Several sums of one user's transactions in one round trip, e.g. a dashboard's tiles
+    /// Each filter takes the query parameters of GET /api/transactions/amount
+    #[derive(Deserialize, Debug)]
+    pub struct AmountsRequest {
+        pub user_id: Uuid,
+        pub filters: Vec<TransactionGetParameters>,
+    }
+
+    impl Validate for AmountsRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.filters.is_empty() {
+                errors.add("filters", "must not be empty");
+            }
+            if self.filters.len() > MAX_AMOUNT_FILTERS {
+                errors.add(
+                    "filters",
+                    format!("must contain at most {} items", MAX_AMOUNT_FILTERS),
+                );
+                return;
+            }
+            // Access tokens are checked against the request's user_id only
+            for (i, filter) in self.filters.iter().enumerate() {
+                if filter.user_id.is_some_and(|id| id != self.user_id) {
+                    errors.add(
+                        format!("filters[{}].user_id", i),
+                        "must be the request's user_id",
+                    );
+                }
+            }
+        }
+    }
+
+    /// Most buckets a time series is split into
+    pub const MAX_TIMESERIES_BUCKETS: usize = 1000;
+
+    #[derive(Deserialize)]
+    pub struct TimeseriesParameters {
+        pub user_id: Uuid,
+        /// Length of the buckets, starting at midnight in the user's time zone
+        pub granularity: LimitPeriod,
+        pub category: Option<TransactionCategory>,
+        pub transaction_type: Option<TransactionType>,
+        /// Defaults to the start of the 30 days, 12 weeks or 12 months up to `end_timestamp`
+        pub start_timestamp: Option<DateTime<Utc>>,
+        /// Defaults to now
+        pub end_timestamp: Option<DateTime<Utc>>,
+    }
+
+    /// Sums of the transactions in a bucket of a time series, each totalled per currency
+    #[derive(Debug, Clone, Serialize)]
+    pub struct TimeseriesBucket {
+        /// First day of the bucket, e.g. "2026-10-01"
+        pub start: NaiveDate,
+        #[serde(flatten)]
+        pub sum: TransactionSum,
+    }
+
+    impl TimeseriesBucket {
+        /// Every bucket from the one `first` is in to the one `last` is in, those without
+        /// transactions included, None when there are more than `MAX_TIMESERIES_BUCKETS`
+        pub fn fill(
+            granularity: LimitPeriod,
+            first: NaiveDate,
+            last: NaiveDate,
+            mut sums: BTreeMap<NaiveDate, TransactionSum>,
+        ) -> Option<Vec<Self>> {
+            let mut buckets = Vec::new();
+            let mut start = granularity.days(first).0;
+            while start <= last {
+                if buckets.len() == MAX_TIMESERIES_BUCKETS {
+                    return None;
+                }
+                buckets.push(TimeseriesBucket {
+                    start,
+                    sum: sums.remove(&start).unwrap_or_default(),
+                });
+                start = granularity.days(start).1.succ_opt()?;
+            }
+            Some(buckets)
+        }
+    }
+
+    /// Months of expenses a seasonality report covers, ending with last month
+    /// The current month is left out, it is not over yet
+    pub const SEASONALITY_MONTHS: u32 = 12;
+
+    #[derive(Deserialize)]
+    pub struct SeasonalityParameters {
+        pub user_id: Uuid,
+    }
+
+    /// Expenses of a category in a month, in one currency
+    #[derive(Debug, Clone)]
+    pub struct CategoryMonthSpend {
+        pub category: TransactionCategory,
+        /// First day of the month
+        pub month: NaiveDate,
+        pub spent: Money,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct SeasonalMonth {
+        /// First day of the month, e.g. "2025-12-01"
+        pub month: NaiveDate,
+        #[serde(flatten)]
+        pub spent: Money,
+        /// Spent over the average month, 1.5 is half more than usual, 0 nothing spent
+        pub index: Decimal,
+    }
+
+    /// A category's expenses in one currency over the months of a seasonality report
+    #[derive(Debug, Clone, Serialize)]
+    pub struct CategorySeasonality {
+        pub category: TransactionCategory,
+        pub average_monthly: Money,
+        pub months: Vec<SeasonalMonth>,
+    }
+
+    impl CategorySeasonality {
+        /// One report per category and currency with expenses in the `SEASONALITY_MONTHS`
+        /// months from `first_month`, months without any counting as nothing spent
+        /// `spends` are ordered by category, currency and month
+        pub fn report(first_month: NaiveDate, spends: &[CategoryMonthSpend]) -> Vec<Self> {
+            spends
+                .chunk_by(|a, b| a.category == b.category && a.spent.currency == b.spent.currency)
+                .filter_map(|group| {
+                    let first = group.first()?;
+                    let currency = first.spent.currency;
+                    let total: i64 = group.iter().map(|spend| spend.spent.minor_units).sum();
+                    // Refunds can outweigh expenses, there is no usual month then
+                    if total <= 0 {
+                        return None;
+                    }
+                    let count = i64::from(SEASONALITY_MONTHS);
+                    let months = (0..SEASONALITY_MONTHS)
+                        .filter_map(|i| first_month.checked_add_months(Months::new(i)))
+                        .map(|month| {
+                            let spent = group
+                                .iter()
+                                .find(|spend| spend.month == month)
+                                .map_or(0, |spend| spend.spent.minor_units);
+                            SeasonalMonth {
+                                month,
+                                spent: Money::new(spent, currency),
+                                index: (Decimal::from(spent * count) / Decimal::from(total))
+                                    .round_dp(2),
+                            }
+                        })
+                        .collect();
+                    Some(CategorySeasonality {
+                        category: first.category.clone(),
+                        average_monthly: Money::new((total + count / 2) / count, currency),
+                        months,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn types_and_categories_parse_in_any_case() {
+            let parsed: Vec<TransactionType> =
+                serde_json::from_str(r#"["iNcOmE", "EXPENSE", " Debit "]"#).unwrap();
+            assert_eq!(
+                parsed,
+                [
+                    TransactionType::Income,
+                    TransactionType::Expense,
+                    TransactionType::Expense
+                ]
+            );
+            let parsed: Vec<TransactionCategory> =
+                serde_json::from_str(r#"["gRoCeRiEs", "EATING_OUT", "Uncategorised"]"#).unwrap();
+            assert_eq!(
+                parsed,
+                [
+                    TransactionCategory::Groceries,
+                    TransactionCategory::Restaurant,
+                    TransactionCategory::Uncategorized
+                ]
+            );
+            assert_eq!(
+                TransactionCategory::from_str("hOlIdAyS"),
+                Ok(TransactionCategory::Holidays)
+            );
+            assert!(serde_json::from_str::<TransactionType>(r#""transfer""#).is_err());
+        }
+    }
+}
+
+pub mod bank_account_models {
+    use crate::validation::{MAX_NAME_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize, Serializer};
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// State of the link between a bank account and its bank
+    #[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[sqlx(type_name = "bank_sync_status")]
+    pub enum BankSyncStatus {
+        Pending,
+        Active,
+        Failed,
+        Disconnected,
+    }
+
+    impl fmt::Display for BankSyncStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                BankSyncStatus::Pending => "Pending",
+                BankSyncStatus::Active => "Active",
+                BankSyncStatus::Failed => "Failed",
+                BankSyncStatus::Disconnected => "Disconnected",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for BankSyncStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "Pending" => Ok(BankSyncStatus::Pending),
+                "Active" => Ok(BankSyncStatus::Active),
+                "Failed" => Ok(BankSyncStatus::Failed),
+                "Disconnected" => Ok(BankSyncStatus::Disconnected),
+                _ => Err(format!("Invalid bank sync status: {}", s)),
+            }
+        }
+    }
+
+    /// An IBAN that never leaves the process in clear text
+    /// Serialization and Debug output only show the country code and the last 4 characters
+    #[derive(Clone)]
+    pub struct MaskedIban(String);
+
+    impl MaskedIban {
+        pub fn new(iban: String) -> Self {
+            // IBANs are commonly written in groups of 4, store them compact
+            Self(iban.split_whitespace().collect::<String>().to_uppercase())
+        }
+
+        /// The full IBAN, only for talking to the bank
+        pub fn expose(&self) -> &str {
+            &self.0
+        }
+
+        pub fn last4(&self) -> String {
+            last4(&self.0)
+        }
+
+        pub fn masked(&self) -> String {
+            let chars: Vec<char> = self.0.chars().collect();
+            if chars.len() <= 6 {
+                return "*".repeat(chars.len());
+            }
+            let country: String = chars[..2].iter().collect();
+            let hidden = "*".repeat(chars.len() - 6);
+            format!("{}{}{}", country, hidden, self.last4())
+        }
+    }
+
+    impl fmt::Debug for MaskedIban {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.masked())
+        }
+    }
+
+    impl Serialize for MaskedIban {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&self.masked())
+        }
+    }
+
+    /// Last 4 characters of an account identifier
+    pub fn last4(account_number: &str) -> String {
+        let chars: Vec<char> = account_number
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        chars[chars.len().saturating_sub(4)..].iter().collect()
+    }
+
+    // Internal struct for inserting a bank account
+    #[derive(Debug, Clone)]
+    pub struct BankAccountCreate {
+        pub user_id: Uuid,
+        pub institution: String,
+        pub name: String,
+        pub iban: Option<MaskedIban>,
+        pub last4: Option<String>,
+    }
+
+    impl BankAccountCreate {
+        pub fn new(
+            user_id: Uuid,
+            institution: String,
+            name: Option<String>,
+            iban: Option<String>,
+            account_number: Option<String>,
+        ) -> Self {
+            let iban = iban.map(MaskedIban::new);
+            // Prefer the IBAN for the last 4 digits, fall back to a plain account/card number
+            let last4 = match (&iban, account_number) {
+                (Some(iban), _) => Some(iban.last4()),
+                (None, Some(number)) => Some(last4(&number)),
+                (None, None) => None,
+            };
+            Self {
+                user_id,
+                name: name.unwrap_or_else(|| institution.clone()),
+                institution,
+                iban,
+                last4,
+            }
+        }
+    }
+
+    // API request struct - the full account number is only used to derive last4
+    #[derive(Deserialize)]
+    pub struct CreateBankAccountRequest {
+        pub user_email: String,
+        pub institution: String,
+        pub name: Option<String>,
+        pub iban: Option<String>,
+        pub account_number: Option<String>,
+    }
+
+    impl Validate for CreateBankAccountRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            errors.check_required("institution", &self.institution, MAX_NAME_LEN);
+            if let Some(name) = &self.name {
+                errors.check_length("name", name, MAX_NAME_LEN);
+            }
+            // Longest IBAN is 34 characters, formatting spaces aside
+            if let Some(iban) = &self.iban {
+                errors.check_length("iban", &iban.replace(' ', ""), 34);
+            }
+            if let Some(account_number) = &self.account_number {
+                errors.check_length("account_number", account_number, 34);
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct UpdateSyncStatusRequest {
+        pub sync_status: String,
+        pub sync_error: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BankAccountGetParameters {
+        pub user_id: Uuid,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct BankAccountQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub institution: String,
+        pub name: String,
+        pub iban: Option<MaskedIban>,
+        pub last4: Option<String>,
+        pub sync_status: BankSyncStatus,
+        pub sync_error: Option<String>,
+        pub last_synced_at: Option<DateTime<Utc>>,
+        /// Day of the month statements start on, None for calendar months
+        pub statement_day: Option<i16>,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct UpdateStatementDayRequest {
+        /// 1 to 28, leave out for calendar months
+        pub statement_day: Option<i16>,
+    }
+
+    impl Validate for UpdateStatementDayRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self
+                .statement_day
+                .is_some_and(|day| !(1..=28).contains(&day))
+            {
+                errors.add("statement_day", "must be between 1 and 28");
+            }
+        }
+    }
+}
+
+pub mod audit_models {
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
+
+    /// prev_hash of the very first entry in the chain
+    pub const GENESIS_HASH: &str =
+        "0000000000000000000000000000000000000000000000000000000000000000";
+
+    // Internal struct describing something that happened, before it is chained
+    #[derive(Debug, Clone)]
+    pub struct AuditEntryCreate {
+        pub actor_id: Option<Uuid>,
+        pub action: String,
+        pub entity_type: String,
+        pub entity_id: Option<Uuid>,
+        pub details: Value,
+    }
+
+    impl AuditEntryCreate {
+        pub fn new(action: &str, entity_type: &str, entity_id: Option<Uuid>) -> Self {
+            Self {
+                actor_id: None,
+                action: action.to_string(),
+                entity_type: entity_type.to_string(),
+                entity_id,
+                details: Value::Null,
+            }
+        }
+
+        pub fn actor(mut self, actor_id: Uuid) -> Self {
+            self.actor_id = Some(actor_id);
+            self
+        }
+
+        pub fn details(mut self, details: Value) -> Self {
+            self.details = details;
+            self
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AuditEntryQuery {
+        /// Position in the chain, contiguous from 1
+        pub seq: i64,
+        pub occurred_at: DateTime<Utc>,
+        pub actor_id: Option<Uuid>,
+        pub action: String,
+        pub entity_type: String,
+        pub entity_id: Option<Uuid>,
+        /// JSON text exactly as hashed (kept as text so key order is preserved)
+        pub details: String,
+        pub prev_hash: String,
+        pub hash: String,
+    }
+
+    impl AuditEntryQuery {
+        /// SHA-256 over the previous hash and every field of the entry
+        /// Any edit to a stored entry, or to the entry before it, changes this value
+        pub fn compute_hash(&self) -> String {
+            let mut hasher = Sha256::new();
+            let fields = [
+                self.seq.to_string(),
+                self.prev_hash.clone(),
+                self.occurred_at.timestamp_micros().to_string(),
+                self.actor_id.map(|id| id.to_string()).unwrap_or_default(),
+                self.action.clone(),
+                self.entity_type.clone(),
+                self.entity_id.map(|id| id.to_string()).unwrap_or_default(),
+                self.details.clone(),
+            ];
+            for field in fields {
+                // Length-prefix every field so "ab|c" and "a|bc" cannot collide
+                hasher.update((field.len() as u64).to_be_bytes());
+                hasher.update(field.as_bytes());
+            }
+            hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect()
+        }
+    }
+
+    /// Result of walking the whole chain
+    #[derive(Debug, Serialize)]
+    pub struct AuditVerification {
+        pub valid: bool,
+        pub entries_checked: i64,
+        /// First entry where the chain breaks, if any
+        pub broken_at_seq: Option<i64>,
+        pub reason: Option<String>,
+    }
+}
+
+pub mod debug_capture_models {
+    // Internal struct for storing a captured request/response pair
+    // Every text field must already be redacted
+    #[derive(Debug, Clone)]
+    pub struct DebugCaptureCreate {
+        pub reason: &'static str,
+        pub endpoint: String,
+        pub query: Option<String>,
+        pub status: u16,
+        pub duration_ms: i64,
+        pub user_agent: Option<String>,
+        pub request_body: Option<String>,
+        pub response_body: Option<String>,
+    }
+}
+
+pub mod pending_models {
+    use crate::models::money_models::Money;
+    use crate::models::transaction_models::{TransactionCategory, TransactionType};
+    use crate::validation::{MAX_DESCRIPTION_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Where a pending transaction is in its review
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum PendingStatus {
+        Pending,
+        Confirmed,
+        Rejected,
+    }
+
+    impl fmt::Display for PendingStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                PendingStatus::Pending => "pending",
+                PendingStatus::Confirmed => "confirmed",
+                PendingStatus::Rejected => "rejected",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for PendingStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "pending" => Ok(PendingStatus::Pending),
+                "confirmed" => Ok(PendingStatus::Confirmed),
+                "rejected" => Ok(PendingStatus::Rejected),
+                _ => Err(format!("Invalid pending transaction status: {}", s)),
+            }
+        }
+    }
+
+    // Internal struct for a transaction parsed from an email
+    #[derive(Debug, Clone)]
+    pub struct PendingTransactionCreate {
+        pub user_id: Uuid,
+        pub transaction_type: TransactionType,
+        /// Signed by type, like `TransactionCreate::amount`
+        pub amount: Money,
+        pub category: Option<TransactionCategory>,
+        pub description: Option<String>,
+        pub occurred_at: Option<DateTime<Utc>>,
+        /// Message-ID of the email
+        pub source_ref: String,
+        /// Name of the parser that recognised the email
+        pub parser: &'static str,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct PendingTransactionQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub transaction_type: TransactionType,
+        #[serde(flatten)]
+        pub amount: Money,
+        pub category: Option<TransactionCategory>,
+        pub description: Option<String>,
+        pub occurred_at: Option<DateTime<Utc>>,
+        pub source_ref: String,
+        pub parser: String,
+        pub status: PendingStatus,
+        pub transaction_id: Option<Uuid>,
+        pub created_at: DateTime<Utc>,
+        pub resolved_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct PendingTransactionGetParameters {
+        pub user_id: Uuid,
+        /// Defaults to pending
+        pub status: Option<PendingStatus>,
+    }
+
+    /// Corrections applied when confirming, both fields are optional
+    #[derive(Deserialize, Default)]
+    pub struct ConfirmPendingRequest {
+        pub category: Option<TransactionCategory>,
+        pub description: Option<String>,
+        /// Record it even though it goes over spending limits that allow overriding
+        #[serde(default)]
+        pub confirm_over_limit: bool,
+    }
+
+    impl Validate for ConfirmPendingRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if let Some(description) = &self.description {
+                errors.check_length("description", description, MAX_DESCRIPTION_LEN);
+            }
+        }
+    }
+}
+
+pub mod webhook_models {
+    use crate::validation::{MAX_URL_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Where a webhook delivery is, failed ones stay until retried by hand
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum DeliveryStatus {
+        Pending,
+        Delivered,
+        Failed,
+    }
+
+    impl fmt::Display for DeliveryStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                DeliveryStatus::Pending => "pending",
+                DeliveryStatus::Delivered => "delivered",
+                DeliveryStatus::Failed => "failed",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for DeliveryStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "pending" => Ok(DeliveryStatus::Pending),
+                "delivered" => Ok(DeliveryStatus::Delivered),
+                "failed" => Ok(DeliveryStatus::Failed),
+                _ => Err(format!("Invalid webhook delivery status: {}", s)),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct CreateWebhookEndpointRequest {
+        pub user_id: Uuid,
+        pub url: String,
+    }
+
+    impl Validate for CreateWebhookEndpointRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_required("url", &self.url, MAX_URL_LEN);
+            if !self.url.starts_with("https://") && !self.url.starts_with("http://") {
+                errors.add("url", "must be an http or https URL");
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct WebhookEndpointGetParameters {
+        pub user_id: Uuid,
+    }
+
+    /// A registered endpoint, the secret is only returned when it is created
+    #[derive(Debug, Serialize)]
+    pub struct WebhookEndpointQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub url: String,
+        pub created_at: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct WebhookDeliveryQuery {
+        pub id: Uuid,
+        pub endpoint_id: Uuid,
+        pub event: String,
+        pub payload: Value,
+        pub status: DeliveryStatus,
+        pub attempts: i32,
+        pub next_attempt_at: DateTime<Utc>,
+        pub last_status_code: Option<i32>,
+        pub last_error: Option<String>,
+        pub created_at: DateTime<Utc>,
+        pub delivered_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct WebhookDeliveryGetParameters {
+        pub user_id: Uuid,
+        /// Defaults to failed, the deliveries needing attention
+        pub status: Option<DeliveryStatus>,
+    }
+
+    /// A delivery claimed by the worker, with what it needs to send it
+    #[derive(Debug)]
+    pub struct DueDelivery {
+        pub id: Uuid,
+        pub url: String,
+        /// Decrypted signing secret of the endpoint
+        pub secret: String,
+        pub event: String,
+        pub payload: Value,
+        pub attempts: i32,
+        pub created_at: DateTime<Utc>,
+    }
+}
+
+pub mod job_models {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Where a background job is in its life
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum JobStatus {
+        Queued,
+        Running,
+        Succeeded,
+        Failed,
+        Cancelled,
+    }
+
+    impl fmt::Display for JobStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                JobStatus::Queued => "queued",
+                JobStatus::Running => "running",
+                JobStatus::Succeeded => "succeeded",
+                JobStatus::Failed => "failed",
+                JobStatus::Cancelled => "cancelled",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for JobStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "queued" => Ok(JobStatus::Queued),
+                "running" => Ok(JobStatus::Running),
+                "succeeded" => Ok(JobStatus::Succeeded),
+                "failed" => Ok(JobStatus::Failed),
+                "cancelled" => Ok(JobStatus::Cancelled),
+                _ => Err(format!("Invalid job status: {}", s)),
+            }
+        }
+    }
+
+    // Internal struct for queueing a job
+    #[derive(Debug, Clone)]
+    pub struct JobCreate {
+        pub kind: String,
+        pub payload: Value,
+        /// Run as soon as a worker is free when None
+        pub run_at: Option<DateTime<Utc>>,
+        pub dedupe_key: Option<String>,
+        pub max_attempts: i32,
+    }
+
+    impl JobCreate {
+        pub fn new(kind: impl Into<String>, payload: Value) -> Self {
+            Self {
+                kind: kind.into(),
+                payload,
+                run_at: None,
+                dedupe_key: None,
+                max_attempts: 5,
+            }
+        }
+
+        /// Skip queueing while a job of the same kind and key is still queued or running
+        pub fn dedupe_key(mut self, key: impl Into<String>) -> Self {
+            self.dedupe_key = Some(key.into());
+            self
+        }
+
+        pub fn max_attempts(mut self, max_attempts: i32) -> Self {
+            self.max_attempts = max_attempts.max(1);
+            self
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct JobQuery {
+        pub id: Uuid,
+        pub kind: String,
+        pub payload: Value,
+        pub status: JobStatus,
+        pub attempts: i32,
+        pub max_attempts: i32,
+        pub run_at: DateTime<Utc>,
+        pub locked_until: Option<DateTime<Utc>>,
+        pub dedupe_key: Option<String>,
+        pub last_error: Option<String>,
+        pub created_at: DateTime<Utc>,
+        pub started_at: Option<DateTime<Utc>>,
+        pub finished_at: Option<DateTime<Utc>>,
+        /// Time spent on the latest attempt so far, None until the job starts
+        pub duration_ms: Option<i64>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct JobGetParameters {
+        /// Queued, running and failed jobs when not given
+        pub status: Option<JobStatus>,
+        pub kind: Option<String>,
+        /// Defaults to 100
+        pub limit: Option<i64>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct JobScheduleQuery {
+        pub kind: String,
+        pub cron: String,
+        pub payload: Value,
+        pub enabled: bool,
+        pub next_run_at: DateTime<Utc>,
+        pub last_enqueued_at: Option<DateTime<Utc>>,
+    }
+}
+
+pub mod sync_models {
+    use crate::models::money_models::Money;
+    use crate::models::reconciliation_models::ReconciliationLock;
+    use crate::models::transaction_models::{
+        TransactionCategory, TransactionQuery, TransactionType,
+    };
+    use crate::validation::{
+        MAX_BATCH_SIZE, MAX_CIPHERTEXT_LEN, MAX_DESCRIPTION_LEN, Validate, ValidationErrors,
+    };
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Position in a user's stream of changes, ordered by change time then id
+    /// Handed to clients as an opaque string
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct SyncCursor {
+        pub changed_at: DateTime<Utc>,
+        pub id: Uuid,
+    }
+
+    impl SyncCursor {
+        /// Before every change, where a first sync starts
+        pub fn start() -> Self {
+            Self {
+                changed_at: DateTime::UNIX_EPOCH,
+                id: Uuid::nil(),
+            }
+        }
+    }
+
+    impl fmt::Display for SyncCursor {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let raw = format!("{}:{}", self.changed_at.timestamp_micros(), self.id);
+            f.write_str(&BASE64.encode(raw))
+        }
+    }
+
+    impl FromStr for SyncCursor {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || format!("Invalid sync cursor: {}", s);
+            let raw = BASE64.decode(s).map_err(|_| invalid())?;
+            let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+            let (micros, id) = raw.split_once(':').ok_or_else(invalid)?;
+            Ok(Self {
+                changed_at: micros
+                    .parse()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_micros)
+                    .ok_or_else(invalid)?,
+                id: id.parse().map_err(|_| invalid())?,
+            })
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct SyncParameters {
+        pub user_email: String,
+        /// Cursor returned by the previous sync, everything when not given
+        pub since: Option<String>,
+        /// Most changed transactions returned, defaults to 500
+        pub limit: Option<i64>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct EventStreamParameters {
+        pub user_email: String,
+    }
+
+    /// A synced row that was deleted
+    #[derive(Debug, Serialize)]
+    pub struct Tombstone {
+        pub id: Uuid,
+        pub deleted_at: DateTime<Utc>,
+    }
+
+    /// A transaction created or edited on a client, possibly while offline
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct TransactionChange {
+        /// Chosen by the client for new transactions
+        pub id: Uuid,
+        /// When the edit was made on the client
+        pub client_updated_at: DateTime<Utc>,
+        /// `last_updated_at` of the server version the edit started from, as last synced
+        /// Edits of transactions the client has never synced leave it out
+        pub base_updated_at: Option<DateTime<Utc>>,
+        /// Overwrite the server version even when it changed, after the user picked this one
+        #[serde(default)]
+        pub force: bool,
+        pub transaction_type: TransactionType,
+        #[serde(flatten)]
+        pub amount: Money,
+        pub category: Option<TransactionCategory>,
+        pub description: Option<String>,
+        /// Description encrypted on the client, instead of `description`
+        pub encrypted_description: Option<String>,
+        pub occurred_at: Option<DateTime<Utc>>,
+        /// Record a new transaction even though it goes over spending limits that allow overriding
+        #[serde(default)]
+        pub confirm_over_limit: bool,
+    }
+
+    // API request struct for pushing a client's pending edits
+    #[derive(Deserialize, Debug)]
+    pub struct SyncPushRequest {
+        pub user_email: String,
+        pub transactions: Vec<TransactionChange>,
+    }
+
+    impl Validate for SyncPushRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            if self.transactions.len() > MAX_BATCH_SIZE {
+                errors.add(
+                    "transactions",
+                    format!("must contain at most {} items", MAX_BATCH_SIZE),
+                );
+                return;
+            }
+            for (i, change) in self.transactions.iter().enumerate() {
+                let field = |name: &str| format!("transactions[{}].{}", i, name);
+                errors.check_amount(&field("amount"), change.amount);
+                if let Some(description) = &change.description {
+                    errors.check_length(&field("description"), description, MAX_DESCRIPTION_LEN);
+                }
+                if let Some(encrypted_description) = &change.encrypted_description {
+                    errors.check_ciphertext(
+                        &field("encrypted_description"),
+                        encrypted_description,
+                        MAX_CIPHERTEXT_LEN,
+                    );
+                    if change.description.as_deref().is_some_and(|d| !d.is_empty()) {
+                        errors.add(
+                            field("encrypted_description"),
+                            "must not be given together with description",
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Why a pushed change was not applied
+    #[derive(Debug, Clone, Copy, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ConflictReason {
+        /// The transaction changed on the server after the version the edit started from
+        ModifiedOnServer,
+        /// The transaction was deleted on the server
+        DeletedOnServer,
+        /// The id is already used by a transaction the user cannot see
+        IdInUse,
+        /// Recording the new transaction would exceed the user's monthly quota, pushing
+        /// it again once the quota allows (next month or a bigger plan) applies it
+        QuotaExceeded,
+        /// The transaction is in (or would be moved into) a reconciled period of its
+        /// account, forcing does not help until the owner unlocks the period
+        Reconciled,
+        /// Recording the new transaction is refused, e.g. it goes over a spending limit,
+        /// `message` tells why
+        Refused,
+    }
+
+    /// A pushed change that was not applied, with both versions for the client to pick from
+    /// Pushing the change again with `force` keeps the client's version
+    #[derive(Debug, Serialize)]
+    pub struct SyncConflict {
+        pub id: Uuid,
+        pub reason: ConflictReason,
+        pub server: Option<TransactionQuery>,
+        pub client: TransactionChange,
+        /// The lock of a Reconciled conflict
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub reconciliation: Option<ReconciliationLock>,
+        /// Why a Refused change was refused
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub message: Option<String>,
+    }
+}
+
+pub mod analytics_models {
+    use crate::models::money_models::Currency;
+    use crate::models::transaction_models::{
+        TransactionCategory, TransactionSource, TransactionType,
+    };
+    use chrono::{DateTime, NaiveDate, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// Windows statistics can be computed over, in days ending at the start of today (UTC)
+    /// Fixed so that subtracting two overlapping windows cannot single out a few days
+    pub const WINDOWS: &[i64] = &[7, 30, 90, 365];
+
+    #[derive(Deserialize)]
+    pub struct AnalyticsParameters {
+        /// One of `WINDOWS`, defaults to 30
+        pub days: Option<i64>,
+    }
+
+    /// Activity of one UTC day, only reported for days with enough active users
+    #[derive(Debug, Serialize)]
+    pub struct DailyActivity {
+        pub day: NaiveDate,
+        /// Users with at least one transaction that day
+        pub active_users: i64,
+        pub transactions: i64,
+    }
+
+    /// How much a category is used, only reported when enough users use it
+    #[derive(Debug, Serialize)]
+    pub struct CategoryUsage {
+        pub transaction_type: TransactionType,
+        pub category: TransactionCategory,
+        pub transactions: i64,
+        pub users: i64,
+    }
+
+    /// A transaction as copied to the analytics store, without its description
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ExportedTransaction {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub transaction_type: TransactionType,
+        pub amount: Decimal,
+        pub currency: Currency,
+        /// As stored, custom categories included
+        pub category: String,
+        pub source: TransactionSource,
+        pub bank_account_id: Option<Uuid>,
+        pub created_at: DateTime<Utc>,
+        pub last_updated_at: DateTime<Utc>,
+        /// Set once the transaction is deleted, cleared again when it is restored
+        pub deleted_at: Option<DateTime<Utc>>,
+    }
+
+    /// How far a sink has exported, transactions are exported in (last_updated_at, id) order
+    #[derive(Debug, Clone)]
+    pub struct ExportCursor {
+        pub changed_at: DateTime<Utc>,
+        pub last_id: Uuid,
+        pub exported: i64,
+    }
+}
+
+pub mod billing_models {
+    use crate::models::money_models::Money;
+    use crate::validation::{MAX_CATEGORY_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    // API request struct for starting a Stripe checkout
+    #[derive(Deserialize, Debug)]
+    pub struct CheckoutRequest {
+        pub user_email: String,
+        /// One of the plans with a Stripe price
+        pub plan: String,
+    }
+
+    impl Validate for CheckoutRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            errors.check_required("plan", &self.plan, MAX_CATEGORY_LEN);
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct SubscriptionParameters {
+        pub user_email: String,
+    }
+
+    /// A user's Stripe subscription as last reported by Stripe
+    #[derive(Debug, Clone, Serialize)]
+    pub struct SubscriptionQuery {
+        pub stripe_customer_id: String,
+        pub stripe_subscription_id: Option<String>,
+        pub plan: String,
+        pub status: String,
+        pub current_period_end: Option<DateTime<Utc>>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    /// New state of a user's subscription, from a Stripe event created at `event_at`
+    #[derive(Debug)]
+    pub struct SubscriptionUpdate {
+        pub stripe_customer_id: String,
+        pub stripe_subscription_id: Option<String>,
+        pub plan: String,
+        pub status: String,
+        pub current_period_end: Option<DateTime<Utc>>,
+        pub event_at: DateTime<Utc>,
+    }
+
+    /// A Stripe invoice of a subscription payment, with links to Stripe's copies
+    #[derive(Debug, Clone, Serialize)]
+    pub struct InvoiceQuery {
+        pub id: Uuid,
+        pub number: Option<String>,
+        pub status: String,
+        pub total: Money,
+        pub amount_paid: Money,
+        /// Stripe's invoice page, where open invoices can also be paid
+        pub hosted_invoice_url: Option<String>,
+        /// Download link of the PDF
+        pub invoice_pdf_url: Option<String>,
+        pub period_start: Option<DateTime<Utc>>,
+        pub period_end: Option<DateTime<Utc>>,
+        pub paid_at: Option<DateTime<Utc>>,
+        pub issued_at: DateTime<Utc>,
+    }
+
+    /// New state of an invoice, from a Stripe event created at `event_at`
+    #[derive(Debug)]
+    pub struct InvoiceUpdate {
+        pub stripe_invoice_id: String,
+        pub stripe_subscription_id: Option<String>,
+        pub number: Option<String>,
+        pub status: String,
+        pub total: Money,
+        pub amount_paid: Money,
+        pub hosted_invoice_url: Option<String>,
+        pub invoice_pdf_url: Option<String>,
+        pub period_start: Option<DateTime<Utc>>,
+        pub period_end: Option<DateTime<Utc>>,
+        pub paid_at: Option<DateTime<Utc>>,
+        pub issued_at: DateTime<Utc>,
+        pub event_at: DateTime<Utc>,
+    }
+}
+
+pub mod sharing_models {
+    use crate::models::bank_account_models::BankSyncStatus;
+    use crate::models::money_models::{Money, MoneyTotals};
+    use crate::models::transaction_models::{
+        TransactionCategory, TransactionSource, TransactionType,
+    };
+    use crate::validation::{MAX_DESCRIPTION_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// What a member may do in a wallet shared with them
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum WalletRole {
+        /// Records transactions, large ones subject to the owner's approval
+        Editor,
+        Viewer,
+    }
+
+    impl fmt::Display for WalletRole {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                WalletRole::Editor => "editor",
+                WalletRole::Viewer => "viewer",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for WalletRole {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "editor" => Ok(WalletRole::Editor),
+                "viewer" => Ok(WalletRole::Viewer),
+                _ => Err(format!("Invalid wallet role: {}", s)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct WalletMemberQuery {
+        pub member_id: Uuid,
+        pub email: String,
+        pub role: WalletRole,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for sharing a wallet, or changing the role of a member
+    #[derive(Deserialize, Debug)]
+    pub struct PutWalletMemberRequest {
+        pub member_email: String,
+        pub role: WalletRole,
+    }
+
+    impl Validate for PutWalletMemberRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("member_email", &self.member_email);
+        }
+    }
+
+    /// Transactions of editors above `threshold` need the owner's approval
+    /// Transactions in another currency than the threshold's always do, they cannot be compared
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ApprovalPolicy {
+        #[serde(flatten)]
+        pub threshold: Money,
+    }
+
+    impl ApprovalPolicy {
+        pub fn requires_approval(&self, amount: Money) -> bool {
+            amount.currency != self.threshold.currency
+                || amount.minor_units.abs() > self.threshold.minor_units
+        }
+    }
+
+    impl Validate for ApprovalPolicy {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.threshold.minor_units < 0 {
+                errors.add("amount", "must not be negative");
+            }
+        }
+    }
+
+    /// Where a transaction recorded by an editor is in the owner's review
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ApprovalStatus {
+        Pending,
+        Approved,
+        Rejected,
+    }
+
+    impl fmt::Display for ApprovalStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                ApprovalStatus::Pending => "pending",
+                ApprovalStatus::Approved => "approved",
+                ApprovalStatus::Rejected => "rejected",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for ApprovalStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "pending" => Ok(ApprovalStatus::Pending),
+                "approved" => Ok(ApprovalStatus::Approved),
+                "rejected" => Ok(ApprovalStatus::Rejected),
+                _ => Err(format!("Invalid approval status: {}", s)),
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct TransactionApprovalQuery {
+        pub id: Uuid,
+        pub owner_id: Uuid,
+        pub requested_by: Uuid,
+        pub transaction_type: TransactionType,
+        /// Signed by type, like `TransactionCreate::amount`
+        #[serde(flatten)]
+        pub amount: Money,
+        pub category: TransactionCategory,
+        pub description: Option<String>,
+        pub encrypted_description: Option<String>,
+        pub source: TransactionSource,
+        pub external_id: Option<String>,
+        pub account_id: Option<Uuid>,
+        pub status: ApprovalStatus,
+        pub reason: Option<String>,
+        pub transaction_id: Option<Uuid>,
+        pub created_at: DateTime<Utc>,
+        pub resolved_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ApprovalGetParameters {
+        /// Owner of the wallet
+        pub user_id: Uuid,
+        /// Defaults to pending
+        pub status: Option<ApprovalStatus>,
+    }
+
+    /// Optional explanation for the editor when rejecting
+    #[derive(Deserialize, Default)]
+    pub struct RejectApprovalRequest {
+        pub reason: Option<String>,
+    }
+
+    impl Validate for RejectApprovalRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if let Some(reason) = &self.reason {
+                errors.check_length("reason", reason, MAX_DESCRIPTION_LEN);
+            }
+        }
+    }
+
+    /// Transactions of a wallet, or of one of its accounts, and their balance
+    #[derive(Debug, Clone, Default, Serialize)]
+    pub struct AccountActivity {
+        pub transactions: i64,
+        pub balance: MoneyTotals,
+        pub last_transaction_at: Option<DateTime<Utc>>,
+    }
+
+    /// A bank account of a wallet, without its IBAN
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AccountOverview {
+        pub id: Uuid,
+        pub institution: String,
+        pub name: String,
+        pub last4: Option<String>,
+        pub sync_status: BankSyncStatus,
+        #[serde(flatten)]
+        pub activity: AccountActivity,
+    }
+
+    /// A wallet the user owns or is a member of, with its accounts and their balances
+    #[derive(Debug, Clone, Serialize)]
+    pub struct WalletOverview {
+        pub owner_id: Uuid,
+        pub owner_name: String,
+        /// The user's role in the wallet, None in their own
+        pub role: Option<WalletRole>,
+        #[serde(flatten)]
+        pub activity: AccountActivity,
+        pub accounts: Vec<AccountOverview>,
+        /// Transactions not linked to any of the accounts, entered by hand for instance
+        pub unassigned: AccountActivity,
+    }
+}
+
+pub mod limit_models {
+    use crate::models::money_models::Money;
+    use crate::models::transaction_models::{TransactionCategory, local_midnight};
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+    use chrono_tz::Tz;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Period a spending limit caps, starting at midnight in the user's time zone
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LimitPeriod {
+        Day,
+        /// From Monday
+        Week,
+        Month,
+    }
+
+    impl fmt::Display for LimitPeriod {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                LimitPeriod::Day => "day",
+                LimitPeriod::Week => "week",
+                LimitPeriod::Month => "month",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for LimitPeriod {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "day" => Ok(LimitPeriod::Day),
+                "week" => Ok(LimitPeriod::Week),
+                "month" => Ok(LimitPeriod::Month),
+                _ => Err(format!("Invalid limit period: {}", s)),
+            }
+        }
+    }
+
+    impl LimitPeriod {
+        /// Start of the current period as of `now`
+        pub fn start(self, now: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+            let today = now.with_timezone(&tz).date_naive();
+            local_midnight(self.days(today).0, tz)
+        }
+
+        /// First and last day of the period `day` is in
+        pub fn days(self, day: NaiveDate) -> (NaiveDate, NaiveDate) {
+            match self {
+                LimitPeriod::Day => (day, day),
+                LimitPeriod::Week => {
+                    let monday = day - Duration::days(day.weekday().num_days_from_monday().into());
+                    (monday, monday + Duration::days(6))
+                }
+                LimitPeriod::Month => {
+                    let first = day.with_day(1).unwrap_or(day);
+                    let last = first
+                        .checked_add_months(Months::new(1))
+                        .map(|next| next - Duration::days(1))
+                        .unwrap_or(day);
+                    (first, last)
+                }
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct SpendingLimitQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        /// None caps all expenses
+        pub category: Option<TransactionCategory>,
+        pub period: LimitPeriod,
+        #[serde(flatten)]
+        pub amount: Money,
+        pub allow_override: bool,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for setting a limit, replacing the one of the same category, period and currency
+    #[derive(Deserialize, Debug)]
+    pub struct PutSpendingLimitRequest {
+        pub user_email: String,
+        /// Leave out to cap all expenses
+        pub category: Option<TransactionCategory>,
+        pub period: LimitPeriod,
+        #[serde(flatten)]
+        pub amount: Money,
+        #[serde(default)]
+        pub allow_override: bool,
+    }
+
+    impl Validate for PutSpendingLimitRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            errors.check_amount("amount", self.amount);
+            if self.amount.minor_units < 0 {
+                errors.add("amount", "must be positive");
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct SpendingLimitGetParameters {
+        pub user_id: Uuid,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BurnDownParameters {
+        pub user_id: Uuid,
+        /// Day in the periods to chart, defaults to today in the user's time zone
+        pub date: Option<NaiveDate>,
+        /// Only limits of this period
+        pub period: Option<LimitPeriod>,
+    }
+
+    /// One day of a burn-down chart
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BurnDownDay {
+        pub date: NaiveDate,
+        /// Spent that day, None for days still to come
+        pub spent: Option<Money>,
+        /// Spent since the start of the period, None for days still to come
+        pub cumulative: Option<Money>,
+        /// The budget line: what spending the cap evenly adds up to by the end of the day
+        pub budget: Money,
+    }
+
+    /// Spending against a limit over one of its periods, day by day
+    #[derive(Debug, Clone, Serialize)]
+    pub struct LimitBurnDown {
+        pub limit_id: Uuid,
+        /// None for the limit on all expenses
+        pub category: Option<TransactionCategory>,
+        pub period: LimitPeriod,
+        pub cap: Money,
+        pub start: NaiveDate,
+        /// Last day of the period
+        pub end: NaiveDate,
+        /// Spent in the period so far
+        pub spent: Money,
+        /// Left of the cap, negative once it has been gone over
+        pub remaining: Money,
+        pub days: Vec<BurnDownDay>,
+    }
+
+    impl LimitBurnDown {
+        /// Chart `limit` over the period `day` is in from what was spent each day, days
+        /// after `today` have no spending yet
+        pub fn new(
+            limit: &SpendingLimitQuery,
+            day: NaiveDate,
+            today: NaiveDate,
+            daily: &HashMap<NaiveDate, Money>,
+        ) -> Self {
+            let (start, end) = limit.period.days(day);
+            let currency = limit.amount.currency;
+            let length = (end - start).num_days() + 1;
+            let mut cumulative = 0;
+            let days = start
+                .iter_days()
+                .take_while(|date| *date <= end)
+                .enumerate()
+                .map(|(i, date)| {
+                    let budget =
+                        Money::new(limit.amount.minor_units * (i as i64 + 1) / length, currency);
+                    if date > today {
+                        return BurnDownDay {
+                            date,
+                            spent: None,
+                            cumulative: None,
+                            budget,
+                        };
+                    }
+                    let spent = daily.get(&date).copied().unwrap_or(Money::new(0, currency));
+                    cumulative += spent.minor_units;
+                    BurnDownDay {
+                        date,
+                        spent: Some(spent),
+                        cumulative: Some(Money::new(cumulative, currency)),
+                        budget,
+                    }
+                })
+                .collect();
+
+            Self {
+                limit_id: limit.id,
+                category: limit.category.clone(),
+                period: limit.period,
+                cap: limit.amount,
+                start,
+                end,
+                spent: Money::new(cumulative, currency),
+                remaining: Money::new(limit.amount.minor_units - cumulative, currency),
+                days,
+            }
+        }
+    }
+}
+
+pub mod family_models {
+    use crate::models::money_models::Money;
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// A child linked to a parent
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ChildAccountQuery {
+        pub child_id: Uuid,
+        pub parent_id: Uuid,
+        pub email: String,
+        /// Credited every Monday
+        pub allowance: Option<Money>,
+        /// Categories the child may spend in, None allows all of them
+        pub allowed_categories: Option<Vec<TransactionCategory>>,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for linking a child, or changing their allowance and categories
+    #[derive(Deserialize, Debug)]
+    pub struct PutChildAccountRequest {
+        pub child_email: String,
+        /// `{"amount": "10", "currency": "EUR"}`, leave out for no allowance
+        pub allowance: Option<Money>,
+        /// Leave out to allow all categories
+        pub allowed_categories: Option<Vec<TransactionCategory>>,
+    }
+
+    impl Validate for PutChildAccountRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("child_email", &self.child_email);
+            if let Some(allowance) = self.allowance {
+                errors.check_amount("allowance", allowance);
+                if allowance.minor_units < 0 {
+                    errors.add("allowance", "must be positive");
+                }
+            }
+        }
+    }
+}
+
+pub mod savings_models {
+    use crate::models::money_models::{Currency, Money};
+    use crate::validation::{MAX_NAME_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct SavingsGoalQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub name: String,
+        pub target: Option<Money>,
+        /// Set aside so far
+        pub balance: Money,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for creating a savings goal
+    #[derive(Deserialize, Debug)]
+    pub struct CreateSavingsGoalRequest {
+        pub user_email: String,
+        pub name: String,
+        /// Defaults to USD
+        pub currency: Option<Currency>,
+        /// In the goal's currency, leave out for an open ended goal
+        pub target: Option<Decimal>,
+    }
+
+    impl Validate for CreateSavingsGoalRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            errors.check_required("name", &self.name, MAX_NAME_LEN);
+            if let Some(target) = self.target {
+                let currency = self.currency.unwrap_or_default();
+                match Money::from_decimal(target, currency) {
+                    Ok(target) => {
+                        errors.check_amount("target", target);
+                        if target.minor_units < 0 {
+                            errors.add("target", "must be positive");
+                        }
+                    }
+                    Err(e) => errors.add("target", e),
+                }
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct SavingsGoalGetParameters {
+        pub user_id: Uuid,
+    }
+
+    /// Expenses are rounded up to a multiple of `unit`, the difference going to the goal
+    #[derive(Debug, Clone, Serialize)]
+    pub struct RoundUpRuleQuery {
+        pub goal_id: Uuid,
+        pub unit: Money,
+        pub starts_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for turning round-ups on, or changing their goal or unit
+    #[derive(Deserialize, Debug)]
+    pub struct PutRoundUpRuleRequest {
+        pub goal_id: Uuid,
+        /// In the goal's currency, defaults to 1
+        pub unit: Option<Decimal>,
+    }
+
+    impl Validate for PutRoundUpRuleRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.unit.is_some_and(|unit| unit <= Decimal::ZERO) {
+                errors.add("unit", "must be positive");
+            }
+        }
+    }
+
+    /// Round-ups of one month
+    #[derive(Debug, Clone, Serialize)]
+    pub struct RoundUpMonth {
+        /// First day of the month, e.g. "2026-10-01"
+        pub month: chrono::NaiveDate,
+        pub transfers: i64,
+        #[serde(flatten)]
+        pub amount: Money,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RoundUpReportParameters {
+        /// Defaults to the current year
+        pub year: Option<i32>,
+    }
+}
+
+pub mod reward_models {
+    use crate::models::money_models::MoneyTotals;
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// Cashback a card earns in a category, or on every other category when None
+    #[derive(Debug, Clone, Serialize)]
+    pub struct RewardRuleQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub bank_account_id: Uuid,
+        pub category: Option<TransactionCategory>,
+        /// Percentage, e.g. 2 for 2%
+        pub rate: Decimal,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for setting a card's rate in a category, replacing the previous one
+    #[derive(Deserialize, Debug)]
+    pub struct PutRewardRuleRequest {
+        pub user_email: String,
+        pub bank_account_id: Uuid,
+        /// Leave out for the card's rate on everything else
+        pub category: Option<TransactionCategory>,
+        pub rate: Decimal,
+    }
+
+    impl Validate for PutRewardRuleRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            if self.rate <= Decimal::ZERO || self.rate > Decimal::ONE_HUNDRED {
+                errors.add("rate", "must be a percentage above 0 and at most 100");
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct RewardRuleGetParameters {
+        pub user_id: Uuid,
+    }
+
+    #[derive(Deserialize)]
+    pub struct CashbackParameters {
+        /// Statement periods to report, the current one included, defaults to 3
+        pub periods: Option<u32>,
+    }
+
+    /// Expected cashback of a card over one statement period
+    #[derive(Debug, Serialize)]
+    pub struct StatementCashback {
+        pub start: DateTime<Utc>,
+        /// Exclusive
+        pub end: DateTime<Utc>,
+        pub spent: MoneyTotals,
+        pub cashback: MoneyTotals,
+    }
+
+    /// The card earning the most in a category
+    #[derive(Debug, Serialize)]
+    pub struct BestCard {
+        pub category: TransactionCategory,
+        pub bank_account_id: Uuid,
+        pub rate: Decimal,
+    }
+}
+
+pub mod attachment_models {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Types of files accepted as attachments, with the largest size accepted of each
+    pub const CONTENT_TYPES: &[(&str, u64)] = &[
+        ("image/jpeg", 10 * MB),
+        ("image/png", 10 * MB),
+        ("image/webp", 10 * MB),
+        ("image/gif", 5 * MB),
+        ("image/heic", 10 * MB),
+        ("application/pdf", 20 * MB),
+    ];
+
+    /// Largest size accepted of a type, None when the type is not accepted
+    pub fn max_size(content_type: &str) -> Option<u64> {
+        CONTENT_TYPES
+            .iter()
+            .find(|(accepted, _)| *accepted == content_type)
+            .map(|(_, max)| *max)
+    }
+
+    /// Bytes needed by `matches_signature`
+    pub const SIGNATURE_BYTES: usize = 12;
+
+    /// Whether a file starts the way files of its type do, so a file cannot pass for
+    /// another type than it is
+    pub fn matches_signature(content_type: &str, head: &[u8]) -> bool {
+        match content_type {
+            "image/jpeg" => head.starts_with(&[0xFF, 0xD8, 0xFF]),
+            "image/png" => head.starts_with(b"\x89PNG\r\n\x1a\n"),
+            "image/webp" => head.starts_with(b"RIFF") && matches!(head.get(8..12), Some(b"WEBP")),
+            "image/gif" => head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a"),
+            // An ISO media file with a HEIF brand
+            "image/heic" => {
+                matches!(head.get(4..8), Some(b"ftyp"))
+                    && matches!(
+                        head.get(8..12),
+                        Some(b"heic" | b"heix" | b"hevc" | b"mif1" | b"msf1")
+                    )
+            }
+            "application/pdf" => head.starts_with(b"%PDF-"),
+            _ => false,
+        }
+    }
+
+    /// Whether an image thumbnail can be generated for the type
+    pub fn has_thumbnail(content_type: &str) -> bool {
+        content_type.starts_with("image/")
+    }
+
+    /// Where the thumbnail of an image attachment is
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ThumbnailStatus {
+        Pending,
+        Ready,
+        Failed,
+    }
+
+    impl fmt::Display for ThumbnailStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                ThumbnailStatus::Pending => "pending",
+                ThumbnailStatus::Ready => "ready",
+                ThumbnailStatus::Failed => "failed",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for ThumbnailStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "pending" => Ok(ThumbnailStatus::Pending),
+                "ready" => Ok(ThumbnailStatus::Ready),
+                "failed" => Ok(ThumbnailStatus::Failed),
+                _ => Err(format!("Invalid thumbnail status: {}", s)),
+            }
+        }
+    }
+
+    /// Outcome of the malware scan of an attachment
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ScanStatus {
+        Clean,
+        /// Infected, kept for review but never served
+        Quarantined,
+    }
+
+    impl fmt::Display for ScanStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                ScanStatus::Clean => "clean",
+                ScanStatus::Quarantined => "quarantined",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for ScanStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "clean" => Ok(ScanStatus::Clean),
+                "quarantined" => Ok(ScanStatus::Quarantined),
+                _ => Err(format!("Invalid scan status: {}", s)),
+            }
+        }
+    }
+
+    /// An attachment without its contents
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AttachmentQuery {
+        pub id: Uuid,
+        pub transaction_id: Uuid,
+        pub user_id: Uuid,
+        pub file_name: Option<String>,
+        pub content_type: String,
+        pub size_bytes: i64,
+        pub sha256: String,
+        /// None for files without thumbnails
+        pub thumbnail_status: Option<ThumbnailStatus>,
+        /// None when no scanner was configured at upload
+        pub scan_status: Option<ScanStatus>,
+        pub malware_signature: Option<String>,
+        /// Key in the blob store, None for files stored in the database
+        #[serde(skip)]
+        pub storage_key: Option<String>,
+        pub created_at: DateTime<Utc>,
+    }
+
+    impl AttachmentQuery {
+        pub fn is_quarantined(&self) -> bool {
+            self.scan_status == Some(ScanStatus::Quarantined)
+        }
+    }
+
+    // Internal struct for recording a file stored in the blob store
+    #[derive(Debug)]
+    pub struct AttachmentCreate {
+        pub id: Uuid,
+        pub transaction_id: Uuid,
+        pub user_id: Uuid,
+        pub file_name: Option<String>,
+        pub content_type: String,
+        pub size_bytes: i64,
+        pub sha256: String,
+        pub storage_key: String,
+        pub scan_status: Option<ScanStatus>,
+        pub malware_signature: Option<String>,
+    }
+
+    impl AttachmentCreate {
+        pub fn new(
+            transaction_id: Uuid,
+            user_id: Uuid,
+            file_name: Option<String>,
+            content_type: String,
+        ) -> Self {
+            let id = Uuid::new_v4();
+            Self {
+                id,
+                transaction_id,
+                user_id,
+                file_name,
+                content_type,
+                // Known once the upload has been read
+                size_bytes: 0,
+                sha256: String::new(),
+                storage_key: format!("attachments/{}/{}", user_id, id),
+                scan_status: None,
+                malware_signature: None,
+            }
+        }
+
+        /// Quarantined files never get a thumbnail
+        pub fn thumbnail_status(&self) -> Option<ThumbnailStatus> {
+            (has_thumbnail(&self.content_type) && !self.is_quarantined())
+                .then_some(ThumbnailStatus::Pending)
+        }
+
+        pub fn is_quarantined(&self) -> bool {
+            self.scan_status == Some(ScanStatus::Quarantined)
+        }
+
+        /// Flag the file as infected, keeping it apart from other attachments in the blob store
+        pub fn quarantine(&mut self, signature: String) {
+            self.scan_status = Some(ScanStatus::Quarantined);
+            self.malware_signature = Some(signature);
+            self.storage_key = format!("quarantine/{}/{}", self.user_id, self.id);
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct UploadAttachmentParameters {
+        pub user_email: String,
+        /// Overrides the file name of the uploaded part
+        pub file_name: Option<String>,
+        /// Hex SHA-256 the client computed, the upload is refused if it does not match
+        pub sha256: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct AttachmentGetParameters {
+        pub user_email: String,
+    }
+}
+
+pub mod import_models {
+    use crate::models::money_models::Currency;
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+    use serde_json::Value;
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Where a background import is in its life
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ImportStatus {
+        Queued,
+        Running,
+        Succeeded,
+        Failed,
+    }
+
+    impl fmt::Display for ImportStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                ImportStatus::Queued => "queued",
+                ImportStatus::Running => "running",
+                ImportStatus::Succeeded => "succeeded",
+                ImportStatus::Failed => "failed",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for ImportStatus {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "queued" => Ok(ImportStatus::Queued),
+                "running" => Ok(ImportStatus::Running),
+                "succeeded" => Ok(ImportStatus::Succeeded),
+                "failed" => Ok(ImportStatus::Failed),
+                _ => Err(format!("Invalid import status: {}", s)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ImportQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub format: String,
+        pub currency: Currency,
+        pub bank_account_id: Option<Uuid>,
+        #[serde(skip)]
+        pub storage_key: String,
+        pub status: ImportStatus,
+        /// Transactions found in the export, None until it has been read
+        pub total_rows: Option<i32>,
+        pub rows_processed: i32,
+        pub inserted: i32,
+        pub duplicates_skipped: i32,
+        pub transfers_skipped: i32,
+        pub other_skipped: i32,
+        /// Rows that could not be read, by line
+        pub errors: Value,
+        /// Rows not imported, unreadable or duplicates, in the report at `report_key`
+        pub rejected_rows: i32,
+        pub failure: Option<String>,
+        pub created_at: DateTime<Utc>,
+        pub started_at: Option<DateTime<Utc>>,
+        pub finished_at: Option<DateTime<Utc>>,
+    }
+
+    impl ImportQuery {
+        /// Where the CSV of the rows that were not imported is kept once the import ran
+        pub fn report_key(&self) -> String {
+            format!("imports/{}/{}.rejected.csv", self.user_id, self.id)
+        }
+    }
+
+    // Internal struct for recording an uploaded export
+    #[derive(Debug)]
+    pub struct ImportCreate {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub format: String,
+        pub currency: Currency,
+        pub bank_account_id: Option<Uuid>,
+        pub storage_key: String,
+    }
+
+    impl ImportCreate {
+        pub fn new(
+            user_id: Uuid,
+            format: impl Into<String>,
+            currency: Currency,
+            bank_account_id: Option<Uuid>,
+        ) -> Self {
+            let id = Uuid::new_v4();
+            Self {
+                id,
+                user_id,
+                format: format.into(),
+                currency,
+                bank_account_id,
+                storage_key: format!("imports/{}/{}", user_id, id),
+            }
+        }
+    }
+
+    /// How far a running import got
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct ImportProgress {
+        pub total_rows: i32,
+        pub rows_processed: i32,
+        pub inserted: i32,
+        pub duplicates_skipped: i32,
+        pub transfers_skipped: i32,
+        pub other_skipped: i32,
+    }
+}
+
+pub mod notification_models {
+    use crate::models::money_models::Currency;
+    use crate::models::transaction_models::{TransactionSum, TransactionType};
+    use chrono::{DateTime, NaiveDate, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// How notifications reach a user
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum NotificationChannel {
+        /// An event delivered to the user's webhook endpoints
+        #[default]
+        Webhook,
+        /// A message to the Telegram chat linked to the user
+        Telegram,
+    }
+
+    impl fmt::Display for NotificationChannel {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = match self {
+                NotificationChannel::Webhook => "webhook",
+                NotificationChannel::Telegram => "telegram",
+            };
+            f.write_str(s)
+        }
+    }
+
+    impl FromStr for NotificationChannel {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "webhook" => Ok(NotificationChannel::Webhook),
+                "telegram" => Ok(NotificationChannel::Telegram),
+                _ => Err(format!("Invalid notification channel: {}", s)),
+            }
+        }
+    }
+
+    /// A user's notification preferences, everything off for users who never set them
+    #[derive(Debug, Clone, Serialize)]
+    pub struct NotificationPreferences {
+        pub user_id: Uuid,
+        pub weekly_digest: bool,
+        pub channel: NotificationChannel,
+        /// Start of the last week a digest was sent for
+        pub last_digest_week: Option<NaiveDate>,
+        pub updated_at: Option<DateTime<Utc>>,
+    }
+
+    // API request struct for changing notification preferences
+    #[derive(Deserialize, Debug)]
+    pub struct PutNotificationPreferencesRequest {
+        pub weekly_digest: bool,
+        #[serde(default)]
+        pub channel: NotificationChannel,
+    }
+
+    /// A user who opted into the weekly digest
+    #[derive(Debug, Clone)]
+    pub struct DigestRecipient {
+        pub user_id: Uuid,
+        pub timezone: String,
+        pub channel: NotificationChannel,
+        pub last_digest_week: Option<NaiveDate>,
+    }
+
+    /// Transactions of a shared wallet in a week, by the member who recorded them, category,
+    /// type and currency
+    #[derive(Debug, Clone)]
+    pub struct WalletActivityRow {
+        pub member_id: Uuid,
+        pub member_name: String,
+        pub category: String,
+        pub transaction_type: TransactionType,
+        pub currency: Currency,
+        pub transactions: i64,
+        pub total: Decimal,
+    }
+
+    /// What one member recorded in a week
+    #[derive(Debug, Clone, Serialize)]
+    pub struct MemberActivity {
+        pub member_id: Uuid,
+        pub name: String,
+        pub transactions: i64,
+        #[serde(flatten)]
+        pub totals: TransactionSum,
+    }
+
+    /// A category's transactions in a week
+    #[derive(Debug, Clone, Serialize)]
+    pub struct CategoryActivity {
+        pub category: String,
+        pub transactions: i64,
+        #[serde(flatten)]
+        pub totals: TransactionSum,
+    }
+
+    /// The week of one shared wallet
+    #[derive(Debug, Clone, Serialize)]
+    pub struct WalletDigest {
+        pub owner_id: Uuid,
+        pub owner_name: String,
+        pub transactions: i64,
+        #[serde(flatten)]
+        pub totals: TransactionSum,
+        /// Most active first
+        pub members: Vec<MemberActivity>,
+        /// Largest expenses first
+        pub categories: Vec<CategoryActivity>,
+    }
+}
+
+pub mod merchant_models {
+    use crate::models::transaction_models::TransactionCategory;
+    use serde::Serialize;
+
+    /// What the merchant-data API knows about the merchant behind a descriptor
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Merchant {
+        pub name: String,
+        pub logo_url: Option<String>,
+        /// Given to transactions still in Other
+        pub category: Option<TransactionCategory>,
+    }
+}
+
+pub mod reconciliation_models {
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use uuid::Uuid;
+
+    /// A reconciled statement period of a bank account, [period_start, period_end)
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ReconciliationLock {
+        pub id: Uuid,
+        pub bank_account_id: Uuid,
+        pub period_start: DateTime<Utc>,
+        pub period_end: DateTime<Utc>,
+        pub locked_by: Option<Uuid>,
+        pub created_at: DateTime<Utc>,
+    }
+
+    // API request struct for locking a reconciled period
+    #[derive(Deserialize, Debug)]
+    pub struct CreateReconciliationLockRequest {
+        pub period_start: DateTime<Utc>,
+        /// Excluded, usually the start of the next statement period
+        pub period_end: DateTime<Utc>,
+    }
+
+    impl Validate for CreateReconciliationLockRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.period_end <= self.period_start {
+                errors.add("period_end", "must be after period_start");
+            }
+            // Only periods that are over can have been reconciled
+            if self.period_end > Utc::now() {
+                errors.add("period_end", "must not be in the future");
+            }
+        }
+    }
+
+    /// A change refused because the transaction is in a reconciled period
+    #[derive(Debug, Clone)]
+    pub struct TransactionLocked {
+        pub transaction_id: Uuid,
+        pub lock: ReconciliationLock,
+    }
+
+    impl fmt::Display for TransactionLocked {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Transaction {} is in the reconciled period {} to {} of its account, which must be unlocked to change it",
+                self.transaction_id,
+                self.lock.period_start.to_rfc3339(),
+                self.lock.period_end.to_rfc3339()
+            )
+        }
+    }
+
+    impl std::error::Error for TransactionLocked {}
+}
+
+pub mod fx_models {
+    use crate::models::money_models::{Currency, Money};
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    #[derive(Deserialize, Debug)]
+    pub struct RevaluationParameters {
+        pub user_id: Uuid,
+        /// Currency gains and losses are reported in, USD when left out
+        #[serde(default)]
+        pub base: Currency,
+    }
+
+    /// A user's transactions in one foreign currency, valued in the base currency at the
+    /// rate of each transaction's day
+    #[derive(Debug, Clone)]
+    pub struct BookedBalance {
+        pub currency: Currency,
+        pub transactions: i64,
+        /// Transactions dated before the first known rate, left out of the valuation
+        pub unpriced_transactions: i64,
+        pub balance: Decimal,
+        /// Balance of the priced transactions only
+        pub priced_balance: Decimal,
+        /// In the base currency
+        pub booked_value: Decimal,
+    }
+
+    /// Gain or loss on the balance in one foreign currency from exchange-rate movements
+    #[derive(Debug, Clone, Serialize)]
+    pub struct CurrencyRevaluation {
+        pub currency: Currency,
+        pub transactions: i64,
+        pub unpriced_transactions: i64,
+        /// In the foreign currency
+        pub balance: Money,
+        /// What the priced transactions were worth in the base currency when they happened
+        pub booked_value: Money,
+        /// Units of the base currency per unit of the foreign currency today, None without rates
+        pub rate: Option<Decimal>,
+        /// What the same transactions are worth at today's rate
+        pub current_value: Option<Money>,
+        /// Current minus booked value, positive for a gain
+        pub gain: Option<Money>,
+    }
+
+    // API request struct for loading one day's rates by hand
+    #[derive(Deserialize, Debug)]
+    pub struct PutExchangeRatesRequest {
+        pub date: NaiveDate,
+        /// Reference currency the rates are quoted against, the same as FX_RATES_BASE
+        pub base: Currency,
+        /// Units of each currency per unit of the reference currency
+        pub rates: BTreeMap<Currency, Decimal>,
+    }
+
+    impl Validate for PutExchangeRatesRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.rates.is_empty() {
+                errors.add("rates", "must not be empty");
+            }
+            for (currency, rate) in &self.rates {
+                if *rate <= Decimal::ZERO {
+                    errors.add(format!("rates.{}", currency), "must be positive");
+                } else if *currency == self.base && *rate != Decimal::ONE {
+                    errors.add(
+                        format!("rates.{}", currency),
+                        "must be 1 for the base currency",
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub mod auth_models {
+    use crate::validation::{MAX_PASSWORD_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+    use uuid::Uuid;
+
+    // API request struct for logging in with a password
+    #[derive(Deserialize)]
+    pub struct LoginRequest {
+        pub email: String,
+        pub password: String,
+    }
+
+    // Hand-written so request logging never prints the password
+    impl fmt::Debug for LoginRequest {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("LoginRequest")
+                .field("email", &crate::redact::email(&self.email))
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl Validate for LoginRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("email", &self.email);
+            errors.check_required("password", &self.password, MAX_PASSWORD_LEN);
+        }
+    }
+
+    /// Claims of an access token
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Claims {
+        /// Id of the user the token was issued to
+        pub sub: Uuid,
+        pub email: String,
+        /// Issued at and expiry, in seconds since the epoch
+        pub iat: i64,
+        pub exp: i64,
+    }
+
+    /// The user an authenticated request acts as, put in the request's extensions
+    #[derive(Debug, Clone)]
+    pub struct AuthUser {
+        pub id: Uuid,
+        pub email: String,
+    }
+
+    // API request struct for exchanging a refresh token, or revoking it when logging out
+    #[derive(Deserialize)]
+    pub struct RefreshRequest {
+        pub refresh_token: String,
+    }
+
+    // Hand-written so request logging never prints the token
+    impl fmt::Debug for RefreshRequest {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("RefreshRequest").finish_non_exhaustive()
+        }
+    }
+
+    impl Validate for RefreshRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_required("refresh_token", &self.refresh_token, MAX_REFRESH_TOKEN_LEN);
+        }
+    }
+
+    /// Longest refresh token looked up, those issued are 43 characters
+    const MAX_REFRESH_TOKEN_LEN: usize = 256;
+
+    #[derive(Debug, Clone)]
+    pub struct RefreshTokenQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub family_id: Uuid,
+        pub expires_at: DateTime<Utc>,
+        pub used_at: Option<DateTime<Utc>>,
+        pub revoked_at: Option<DateTime<Utc>>,
+    }
+
+    // Internal struct for recording an issued refresh token
+    #[derive(Debug)]
+    pub struct RefreshTokenCreate {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub family_id: Uuid,
+        pub token_hash: String,
+        pub expires_at: DateTime<Utc>,
+    }
+}
+
+pub mod invitation_models {
+    use crate::models::sharing_models::WalletRole;
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// Days an invitation works when the request does not say
+    pub const DEFAULT_INVITATION_DAYS: i64 = 7;
+
+    /// Longest an invitation may work
+    pub const MAX_INVITATION_DAYS: i64 = 90;
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct InvitationQuery {
+        pub id: Uuid,
+        /// Only this email may sign up with it, anyone holding the token when None
+        pub email: Option<String>,
+        pub wallet_owner_id: Option<Uuid>,
+        pub role: Option<WalletRole>,
+        /// None when created by an admin
+        pub created_by: Option<Uuid>,
+        pub created_at: DateTime<Utc>,
+        pub expires_at: DateTime<Utc>,
+        pub used_at: Option<DateTime<Utc>>,
+        pub used_by: Option<Uuid>,
+    }
+
+    impl InvitationQuery {
+        /// Not used yet and not expired
+        pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+            self.used_at.is_none() && self.expires_at > now
+        }
+    }
+
+    // Internal struct for recording an invitation
+    #[derive(Debug)]
+    pub struct InvitationCreate {
+        pub token_hash: String,
+        pub email: Option<String>,
+        /// Wallet joined on signup, with the role
+        pub wallet: Option<(Uuid, WalletRole)>,
+        pub created_by: Option<Uuid>,
+        pub expires_at: DateTime<Utc>,
+    }
+
+    fn check_days(errors: &mut ValidationErrors, expires_in_days: Option<i64>) {
+        if let Some(days) = expires_in_days
+            && !(1..=MAX_INVITATION_DAYS).contains(&days)
+        {
+            errors.add(
+                "expires_in_days",
+                format!("must be between 1 and {}", MAX_INVITATION_DAYS),
+            );
+        }
+    }
+
+    // API request struct for an admin inviting someone to the instance, and optionally to
+    // a wallet
+    #[derive(Deserialize, Debug)]
+    pub struct CreateInvitationRequest {
+        /// Only this email may sign up with it
+        pub invitee_email: Option<String>,
+        pub wallet_owner: Option<Uuid>,
+        pub role: Option<WalletRole>,
+        pub expires_in_days: Option<i64>,
+    }
+
+    impl Validate for CreateInvitationRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if let Some(email) = &self.invitee_email {
+                errors.check_email("invitee_email", email);
+            }
+            if self.wallet_owner.is_some() != self.role.is_some() {
+                errors.add("role", "must be given together with wallet_owner");
+            }
+            check_days(errors, self.expires_in_days);
+        }
+    }
+
+    // API request struct for a wallet owner inviting someone to their wallet
+    #[derive(Deserialize, Debug)]
+    pub struct CreateWalletInvitationRequest {
+        /// Only this email may sign up with it
+        pub invitee_email: Option<String>,
+        pub role: WalletRole,
+        pub expires_in_days: Option<i64>,
+    }
+
+    impl Validate for CreateWalletInvitationRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if let Some(email) = &self.invitee_email {
+                errors.check_email("invitee_email", email);
+            }
+            check_days(errors, self.expires_in_days);
+        }
+    }
+}
+
+pub mod account_models {
+    use crate::models::money_models::{Currency, Money};
+    use crate::validation::{MAX_DESCRIPTION_LEN, MAX_NAME_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// Where a user keeps money, e.g. Checking, Cash or Savings
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AccountQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub name: String,
+        pub opening_balance: Money,
+        /// Opening balance plus the transactions booked to the account, deleted ones left out,
+        /// and the transfers into it less those out of it
+        pub balance: Money,
+        pub transactions: i64,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for creating an account
+    #[derive(Deserialize, Debug)]
+    pub struct CreateAccountRequest {
+        pub name: String,
+        /// Defaults to USD
+        pub currency: Option<Currency>,
+        /// In the account's currency, defaults to 0
+        pub opening_balance: Option<Decimal>,
+    }
+
+    impl Validate for CreateAccountRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_required("name", &self.name, MAX_NAME_LEN);
+            if let Some(opening_balance) = self.opening_balance {
+                match Money::from_decimal(opening_balance, self.currency.unwrap_or_default()) {
+                    // Starting from nothing is fine, check_amount would refuse it
+                    Ok(opening_balance) if opening_balance.is_zero() => {}
+                    Ok(opening_balance) => errors.check_amount("opening_balance", opening_balance),
+                    Err(e) => errors.add("opening_balance", e),
+                }
+            }
+        }
+    }
+
+    /// Money moved between two of a user's accounts, converted when their currencies differ
+    #[derive(Debug, Clone, Serialize)]
+    pub struct TransferQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub from_account_id: Uuid,
+        pub to_account_id: Uuid,
+        /// Taken from the source account
+        pub amount: Money,
+        /// Put into the destination account
+        pub to_amount: Money,
+        /// Units of the destination currency per unit of the source currency
+        pub rate: Decimal,
+        pub description: String,
+        pub created_at: DateTime<Utc>,
+    }
+
+    // Internal struct for a transfer whose amounts are worked out
+    #[derive(Debug, Clone)]
+    pub struct TransferCreate {
+        pub user_id: Uuid,
+        pub from_account_id: Uuid,
+        pub to_account_id: Uuid,
+        pub amount: Money,
+        pub to_amount: Money,
+        pub rate: Decimal,
+        pub description: String,
+    }
+
+    /// Highest rate a transfer may give, units of the destination currency per unit of the
+    /// source one, well above those between any two currencies in use
+    pub const MAX_TRANSFER_RATE: i64 = 1_000_000;
+
+    // API request struct for moving money between two of the user's accounts
+    #[derive(Deserialize, Debug)]
+    pub struct CreateTransferRequest {
+        pub from_account_id: Uuid,
+        pub to_account_id: Uuid,
+        /// In the source account's currency
+        pub amount: Decimal,
+        /// What arrived in the destination account, when it is in another currency
+        pub to_amount: Option<Decimal>,
+        /// Or the rate applied, units of the destination currency per unit of the source one
+        /// Without either the latest known exchange rate is applied
+        pub rate: Option<Decimal>,
+        pub description: Option<String>,
+    }
+
+    impl Validate for CreateTransferRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            if self.from_account_id == self.to_account_id {
+                errors.add(
+                    "to_account_id",
+                    "must be another account than from_account_id",
+                );
+            }
+            if self.amount <= Decimal::ZERO {
+                errors.add("amount", "must be more than 0");
+            }
+            if self.to_amount.is_some() && self.rate.is_some() {
+                errors.add("rate", "cannot be given with to_amount");
+            }
+            if self
+                .to_amount
+                .is_some_and(|to_amount| to_amount <= Decimal::ZERO)
+            {
+                errors.add("to_amount", "must be more than 0");
+            }
+            match self.rate {
+                Some(rate) if rate <= Decimal::ZERO => errors.add("rate", "must be more than 0"),
+                Some(rate) if rate > Decimal::from(MAX_TRANSFER_RATE) => {
+                    errors.add("rate", format!("must be at most {}", MAX_TRANSFER_RATE))
+                }
+                _ => {}
+            }
+            if let Some(description) = &self.description {
+                errors.check_length("description", description, MAX_DESCRIPTION_LEN);
+            }
+        }
+    }
+
+    impl CreateTransferRequest {
+        /// The amounts taken and put in, and the rate between them, for accounts in `from` and
+        /// `to`, `latest_rate` being the known exchange rate from one to the other
+        /// Problems are added to `errors`, None is returned then
+        pub fn amounts(
+            &self,
+            from: Currency,
+            to: Currency,
+            latest_rate: Option<Decimal>,
+            errors: &mut ValidationErrors,
+        ) -> Option<(Money, Money, Decimal)> {
+            let amount = match Money::from_decimal(self.amount, from) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    errors.add("amount", e);
+                    return None;
+                }
+            };
+            errors.check_amount("amount", amount);
+
+            let converted = if from == to {
+                if self
+                    .to_amount
+                    .is_some_and(|to_amount| to_amount != self.amount)
+                {
+                    errors.add(
+                        "to_amount",
+                        "must be amount, both accounts are in the same currency",
+                    );
+                }
+                if self.rate.is_some_and(|rate| rate != Decimal::ONE) {
+                    errors.add("rate", "must be 1, both accounts are in the same currency");
+                }
+                Some((amount, Decimal::ONE))
+            } else if let Some(to_amount) = self.to_amount {
+                match Money::from_decimal(to_amount, to) {
+                    Ok(to_amount) => {
+                        let rate = (to_amount.to_decimal() / amount.to_decimal()).round_dp(10);
+                        Some((to_amount, rate))
+                    }
+                    Err(e) => {
+                        errors.add("to_amount", e);
+                        None
+                    }
+                }
+            } else if let Some(rate) = self.rate.or(latest_rate) {
+                let converted = amount
+                    .to_decimal()
+                    .checked_mul(rate)
+                    .ok_or_else(|| "gives an amount out of range".to_string())
+                    .and_then(|to_amount| Money::from_decimal_rounded(to_amount, to));
+                match converted {
+                    Ok(to_amount) => Some((to_amount, rate)),
+                    Err(e) => {
+                        errors.add("rate", e);
+                        None
+                    }
+                }
+            } else {
+                errors.add(
+                    "to_amount",
+                    format!(
+                        "is required, no exchange rate from {} to {} is known",
+                        from, to
+                    ),
+                );
+                None
+            };
+
+            let (to_amount, rate) = converted?;
+            errors.check_amount("to_amount", to_amount);
+            errors.is_empty().then_some((amount, to_amount, rate))
+        }
+    }
+
+    /// Transfers of one account only, all the user's when not given
+    #[derive(Deserialize)]
+    pub struct TransferGetParameters {
+        pub account_id: Option<Uuid>,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn transfer(amount: Decimal, rate: Option<Decimal>) -> CreateTransferRequest {
+            CreateTransferRequest {
+                from_account_id: Uuid::new_v4(),
+                to_account_id: Uuid::new_v4(),
+                amount,
+                to_amount: None,
+                rate,
+                description: None,
+            }
+        }
+
+        #[test]
+        fn rates_out_of_range_are_errors() {
+            let mut errors = ValidationErrors::default();
+            transfer(Decimal::ONE, Some(Decimal::MAX)).validate(&mut errors);
+            assert!(!errors.is_empty());
+
+            // A known rate is not validated, converting with it must not overflow either
+            let mut errors = ValidationErrors::default();
+            let amounts = transfer(Decimal::new(1_000_000_000, 0), None).amounts(
+                Currency::EUR,
+                Currency::JPY,
+                Some(Decimal::MAX),
+                &mut errors,
+            );
+            assert!(amounts.is_none());
+            assert!(!errors.is_empty());
+
+            let mut errors = ValidationErrors::default();
+            let amounts = transfer(Decimal::new(1000, 2), Some(Decimal::new(15, 1))).amounts(
+                Currency::EUR,
+                Currency::USD,
+                None,
+                &mut errors,
+            );
+            assert_eq!(
+                amounts.map(|(_, to_amount, _)| to_amount),
+                Some(Money::new(1500, Currency::USD))
+            );
+        }
+    }
+}
+
+pub mod budget_models {
+    use crate::models::money_models::Money;
+    use crate::models::transaction_models::{
+        TransactionCategory, TransactionCreate, TransactionType,
+    };
+    use crate::validation::{Validate, ValidationErrors};
+    use chrono::{DateTime, NaiveDate, Utc};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    /// What the user plans to spend on a category each month
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BudgetQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub category: TransactionCategory,
+        #[serde(flatten)]
+        pub amount: Money,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    // API request struct for setting a category's budget, replacing the one of the same currency
+    #[derive(Deserialize, Debug)]
+    pub struct CreateBudgetRequest {
+        pub user_email: String,
+        pub category: TransactionCategory,
+        #[serde(flatten)]
+        pub amount: Money,
+    }
+
+    impl Validate for CreateBudgetRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_email("user_email", &self.user_email);
+            errors.check_amount("amount", self.amount);
+            if self.amount.minor_units < 0 {
+                errors.add("amount", "must be positive");
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct BudgetGetParameters {
+        pub user_id: Uuid,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BudgetStatusParameters {
+        pub user_id: Uuid,
+        /// Day in the month to report, defaults to today in the user's time zone
+        pub date: Option<NaiveDate>,
+    }
+
+    /// Spending against a budget over one month
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BudgetStatus {
+        pub budget_id: Uuid,
+        pub category: TransactionCategory,
+        pub budget: Money,
+        /// Expenses of the category in the budget's currency this month
+        pub spent: Money,
+        /// Left of the budget, negative once it has been gone over
+        pub remaining: Money,
+    }
+
+    impl BudgetStatus {
+        pub fn new(budget: &BudgetQuery, spent: Money) -> Self {
+            Self {
+                budget_id: budget.id,
+                category: budget.category.clone(),
+                budget: budget.amount,
+                spent,
+                remaining: Money::new(
+                    budget.amount.minor_units - spent.minor_units,
+                    budget.amount.currency,
+                ),
+            }
+        }
+    }
+
+    /// What recording some expenses would do to a budget this month
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BudgetImpact {
+        pub budget_id: Uuid,
+        pub category: TransactionCategory,
+        pub budget: Money,
+        /// Expenses of the category in the budget's currency this month, so far
+        pub spent: Money,
+        /// The same once the expenses are recorded
+        pub spent_after: Money,
+        /// Left of the budget then, negative when it would be gone over
+        pub remaining_after: Money,
+    }
+
+    impl BudgetImpact {
+        /// The budgets `expenses` of this month count against, with this month's spending
+        /// on each, budgets none of them is in left out
+        pub fn of(budgets: &[(BudgetQuery, Money)], expenses: &[&TransactionCreate]) -> Vec<Self> {
+            budgets
+                .iter()
+                .filter_map(|(budget, spent)| {
+                    let added: i64 = expenses
+                        .iter()
+                        .filter(|e| {
+                            e.transaction_type == TransactionType::Expense
+                                && e.category == budget.category
+                                && e.amount.currency == budget.amount.currency
+                        })
+                        .map(|e| e.amount.minor_units.abs())
+                        .sum();
+                    (added > 0).then(|| {
+                        let spent_after = spent.minor_units + added;
+                        BudgetImpact {
+                            budget_id: budget.id,
+                            category: budget.category.clone(),
+                            budget: budget.amount,
+                            spent: *spent,
+                            spent_after: Money::new(spent_after, spent.currency),
+                            remaining_after: Money::new(
+                                budget.amount.minor_units - spent_after,
+                                budget.amount.currency,
+                            ),
+                        }
+                    })
+                })
+                .collect()
+        }
+    }
+
+    /// A budget's spending crossing one of the alert thresholds in a month
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BudgetAlertQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub budget_id: Uuid,
+        pub category: TransactionCategory,
+        /// Percentage of the budget crossed, e.g. 80 or 100
+        pub threshold: i16,
+        /// First day of the month
+        pub period_start: NaiveDate,
+        /// Spent in the month when the alert was recorded
+        pub spent: Money,
+        pub budget: Money,
+        pub created_at: DateTime<Utc>,
+        pub acknowledged_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct AlertGetParameters {
+        pub user_id: Uuid,
+        /// List the acknowledged alerts too
+        #[serde(default)]
+        pub include_acknowledged: bool,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::models::money_models::Currency;
+
+        fn groceries_budget(spent: i64) -> (BudgetQuery, Money) {
+            let budget = BudgetQuery {
+                id: Uuid::new_v4(),
+                user_id: Uuid::nil(),
+                category: TransactionCategory::Groceries,
+                amount: Money::new(10000, Currency::EUR),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            };
+            (budget, Money::new(spent, Currency::EUR))
+        }
+
+        fn transaction(
+            transaction_type: TransactionType,
+            category: TransactionCategory,
+            minor_units: i64,
+        ) -> TransactionCreate {
+            TransactionCreate::new(
+                Uuid::nil(),
+                transaction_type,
+                Money::new(minor_units, Currency::EUR),
+                Some(category),
+                None,
+            )
+        }
+
+        #[test]
+        fn impact_under_the_budget() {
+            let budgets = [groceries_budget(2000)];
+            let lunch = transaction(
+                TransactionType::Expense,
+                TransactionCategory::Groceries,
+                3000,
+            );
+            let impact = BudgetImpact::of(&budgets, &[&lunch]);
+            assert_eq!(impact.len(), 1);
+            assert_eq!(impact[0].budget_id, budgets[0].0.id);
+            assert_eq!(impact[0].spent, Money::new(2000, Currency::EUR));
+            assert_eq!(impact[0].spent_after, Money::new(5000, Currency::EUR));
+            assert_eq!(impact[0].remaining_after, Money::new(5000, Currency::EUR));
+        }
+
+        #[test]
+        fn impact_crossing_the_budget() {
+            let budgets = [groceries_budget(8000)];
+            let shopping = [
+                transaction(
+                    TransactionType::Expense,
+                    TransactionCategory::Groceries,
+                    1500,
+                ),
+                transaction(
+                    TransactionType::Expense,
+                    TransactionCategory::Groceries,
+                    1500,
+                ),
+            ];
+            let impact = BudgetImpact::of(&budgets, &shopping.iter().collect::<Vec<_>>());
+            assert_eq!(impact[0].spent_after, Money::new(11000, Currency::EUR));
+            assert_eq!(impact[0].remaining_after, Money::new(-1000, Currency::EUR));
+        }
+
+        #[test]
+        fn income_leaves_budgets_alone() {
+            let budgets = [groceries_budget(2000)];
+            let refund = transaction(
+                TransactionType::Income,
+                TransactionCategory::Groceries,
+                3000,
+            );
+            assert!(BudgetImpact::of(&budgets, &[&refund]).is_empty());
+        }
+
+        #[test]
+        fn categories_without_a_budget_have_no_impact() {
+            let budgets = [groceries_budget(2000)];
+            let dinner = transaction(
+                TransactionType::Expense,
+                TransactionCategory::Restaurant,
+                3000,
+            );
+            assert!(BudgetImpact::of(&budgets, &[&dinner]).is_empty());
+            assert!(BudgetImpact::of(&[], &[&dinner]).is_empty());
+        }
+    }
+}
+
+pub mod share_link_models {
+    use crate::models::limit_models::LimitPeriod;
+    use crate::models::transaction_models::{
+        TransactionCategory, TransactionFilter, TransactionType,
+    };
+    use crate::validation::{MAX_NAME_LEN, Validate, ValidationErrors};
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    /// Days a share link works when the request does not say
+    pub const DEFAULT_SHARE_LINK_DAYS: i64 = 7;
+
+    /// Longest a share link may work
+    pub const MAX_SHARE_LINK_DAYS: i64 = 90;
+
+    /// A report a share link shows, with the transactions it covers
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    pub enum SharedReport {
+        /// Income, expenses and net total of a period, e.g. of a trip, and those of each
+        /// category
+        Summary {
+            category: Option<TransactionCategory>,
+            transaction_type: Option<TransactionType>,
+            start_timestamp: DateTime<Utc>,
+            end_timestamp: DateTime<Utc>,
+        },
+        /// Sums by day, week or month, like GET /api/reports/timeseries
+        Timeseries {
+            granularity: LimitPeriod,
+            category: Option<TransactionCategory>,
+            transaction_type: Option<TransactionType>,
+            start_timestamp: DateTime<Utc>,
+            end_timestamp: DateTime<Utc>,
+        },
+    }
+
+    impl SharedReport {
+        /// First and last moment of the transactions the report covers
+        pub fn period(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+            match self {
+                SharedReport::Summary {
+                    start_timestamp,
+                    end_timestamp,
+                    ..
+                }
+                | SharedReport::Timeseries {
+                    start_timestamp,
+                    end_timestamp,
+                    ..
+                } => (*start_timestamp, *end_timestamp),
+            }
+        }
+
+        /// The user's transactions the report covers
+        pub fn filter(&self, user_id: Uuid) -> TransactionFilter {
+            let (category, transaction_type) = match self {
+                SharedReport::Summary {
+                    category,
+                    transaction_type,
+                    ..
+                }
+                | SharedReport::Timeseries {
+                    category,
+                    transaction_type,
+                    ..
+                } => (category, transaction_type),
+            };
+            let (start, end) = self.period();
+            TransactionFilter::for_user(user_id)
+                .categories(category.clone())
+                .transaction_types(transaction_type.clone())
+                .period(Some(start), Some(end))
+        }
+    }
+
+    /// A link showing a snapshot of a report to anyone holding its token
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ShareLinkQuery {
+        pub id: Uuid,
+        pub user_id: Uuid,
+        pub title: String,
+        pub report: SharedReport,
+        /// The report as it was when the link was created, only shown through the link
+        #[serde(skip)]
+        pub snapshot: Value,
+        pub created_at: DateTime<Utc>,
+        pub expires_at: DateTime<Utc>,
+        pub revoked_at: Option<DateTime<Utc>>,
+    }
+
+    impl ShareLinkQuery {
+        /// Not revoked and not expired
+        pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+            self.revoked_at.is_none() && self.expires_at > now
+        }
+    }
+
+    // Internal struct for recording a share link
+    #[derive(Debug)]
+    pub struct ShareLinkCreate {
+        pub user_id: Uuid,
+        pub token_hash: String,
+        pub title: String,
+        pub report: SharedReport,
+        pub snapshot: Value,
+        pub expires_at: DateTime<Utc>,
+    }
+
+    // API request struct for sharing a snapshot of a report
+    #[derive(Deserialize, Debug)]
+    pub struct CreateShareLinkRequest {
+        /// Shown with the report, e.g. "Lisbon, May 2026"
+        pub title: String,
+        pub report: SharedReport,
+        pub expires_in_days: Option<i64>,
+    }
+
+    impl Validate for CreateShareLinkRequest {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_required("title", &self.title, MAX_NAME_LEN);
+            let (start, end) = self.report.period();
+            if start > end {
+                errors.add("report.start_timestamp", "must not be after end_timestamp");
+            }
+            if let Some(days) = self.expires_in_days
+                && !(1..=MAX_SHARE_LINK_DAYS).contains(&days)
+            {
+                errors.add(
+                    "expires_in_days",
+                    format!("must be between 1 and {}", MAX_SHARE_LINK_DAYS),
+                );
+            }
+        }
+    }
+}
+
+pub mod suggestion_models {
+    use crate::enrichment::normalize_descriptor;
+    use crate::models::transaction_models::TransactionCategory;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    /// Most merchants a user's model keeps, those with the most transactions
+    pub const MAX_MODEL_MERCHANTS: usize = 5000;
+
+    /// How a user categorizes each merchant: transactions per category, by the merchant's
+    /// normalized name, e.g. {"starbucks": {"Restaurant": 41, "Groceries": 2}}
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct CategoryModel(pub BTreeMap<String, BTreeMap<String, u32>>);
+
+    impl CategoryModel {
+        /// The model of categorized transactions, each the merchant name enrichment found
+        /// or else the description, with its category
+        pub fn train(rows: &[(String, TransactionCategory)]) -> Self {
+            let mut merchants: BTreeMap<String, BTreeMap<String, u32>> = BTreeMap::new();
+            for (merchant, category) in rows {
+                let key = normalize_descriptor(merchant);
+                if key.is_empty() {
+                    continue;
+                }
+                *merchants
+                    .entry(key)
+                    .or_default()
+                    .entry(category.to_string())
+                    .or_default() += 1;
+            }
+            if merchants.len() > MAX_MODEL_MERCHANTS {
+                let mut totals: Vec<(u32, String)> = merchants
+                    .iter()
+                    .map(|(key, categories)| (categories.values().sum(), key.clone()))
+                    .collect();
+                totals.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+                for (_, key) in totals.split_off(MAX_MODEL_MERCHANTS) {
+                    merchants.remove(&key);
+                }
+            }
+            CategoryModel(merchants)
+        }
+    }
+
+    /// A version of a user's model
+    #[derive(Debug, Clone, Serialize)]
+    pub struct CategoryModelQuery {
+        pub user_id: Uuid,
+        /// 1 for the first model of the user, one more for each rebuild
+        pub version: i32,
+        /// Transactions changed after this are not in the model
+        pub trained_at: DateTime<Utc>,
+        /// Categorized transactions the model learned from
+        pub transactions: i32,
+        #[serde(skip)]
+        pub model: CategoryModel,
+    }
+
+    impl CategoryModelQuery {
+        /// The category most transactions of the description's merchant are in
+        /// A merchant is the description's own when their normalized forms are equal, else
+        /// the longest one whose words all appear in the description, so "Starbucks" is
+        /// found in "POS 4411 STARBUCKS #123 SEATTLE WA"
+        pub fn suggest(&self, description: &str) -> Option<CategorySuggestion> {
+            let key = normalize_descriptor(description);
+            let (merchant, categories) = match self.model.0.get_key_value(&key) {
+                Some(found) => found,
+                None => {
+                    let words: Vec<&str> = key.split(' ').collect();
+                    self.model
+                        .0
+                        .iter()
+                        .filter(|(merchant, _)| {
+                            merchant.split(' ').all(|word| words.contains(&word))
+                        })
+                        .max_by_key(|(merchant, _)| merchant.split(' ').count())?
+                }
+            };
+            let total: u32 = categories.values().sum();
+            let (category, count) = categories
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))?;
+            Some(CategorySuggestion {
+                category: category.parse().ok()?,
+                merchant: merchant.clone(),
+                confidence: f64::from(*count) / f64::from(total),
+                transactions: total,
+                model_version: self.version,
+            })
+        }
+    }
+
+    /// Category suggested for a description
+    #[derive(Debug, Clone, Serialize)]
+    pub struct CategorySuggestion {
+        pub category: TransactionCategory,
+        /// Normalized name of the merchant the description was matched with
+        pub merchant: String,
+        /// Share of the merchant's transactions in the category, from 0 to 1
+        pub confidence: f64,
+        /// Transactions of the merchant the user categorized
+        pub transactions: u32,
+        pub model_version: i32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SuggestionParameters {
+        pub user_id: Uuid,
+        pub description: String,
     }
 }
@@ -0,0 +1,233 @@
+// Streaming multipart/form-data reader
+//
+// Uploads are read part by part straight from the request body, the file part is handed
+// on as a stream so it never has to fit in memory. Only what precedes the file (part
+// headers and small form fields) is buffered, and that is capped.
+
+use crate::storage::ByteStream;
+use axum::body::{Body, BodyDataStream, Bytes};
+use axum::http::{HeaderMap, header};
+use futures_util::StreamExt;
+use futures_util::stream;
+use std::fmt;
+
+/// Longest headers of a part
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+/// Most bytes of other form fields skipped while looking for a part
+const MAX_SKIPPED_BYTES: usize = 64 * 1024;
+
+/// A request body that is not valid multipart/form-data
+#[derive(Debug)]
+pub enum MultipartError {
+    /// Not a multipart request, or one without a boundary
+    NotMultipart,
+    Malformed(&'static str),
+    /// Reading the request body failed (e.g. the client went away)
+    Body(axum::Error),
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::NotMultipart => f.write_str("Expected a multipart/form-data body"),
+            MultipartError::Malformed(reason) => write!(f, "Malformed multipart body: {}", reason),
+            MultipartError::Body(e) => write!(f, "Reading the body failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+/// Headers of a part
+#[derive(Debug, Clone)]
+pub struct Part {
+    /// Name of the form field
+    pub name: String,
+    pub file_name: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// Reads the parts of a multipart body in order
+pub struct Multipart {
+    body: BodyDataStream,
+    /// "\r\n--" followed by the boundary
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    /// Inside the body of a part
+    in_part: bool,
+    done: bool,
+}
+
+impl Multipart {
+    /// Start reading a request whose Content-Type is multipart/form-data
+    pub fn new(headers: &HeaderMap, body: Body) -> Result<Self, MultipartError> {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .ok_or(MultipartError::NotMultipart)?;
+        let mut params = content_type.split(';').map(str::trim);
+        if !params
+            .next()
+            .is_some_and(|mime| mime.eq_ignore_ascii_case("multipart/form-data"))
+        {
+            return Err(MultipartError::NotMultipart);
+        }
+        let boundary = params
+            .filter_map(|p| p.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+            .filter(|b| !b.is_empty() && b.len() <= 70)
+            .ok_or(MultipartError::NotMultipart)?;
+
+        Ok(Self {
+            body: body.into_data_stream(),
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // The first delimiter has no line break before it
+            buffer: b"\r\n".to_vec(),
+            in_part: false,
+            done: false,
+        })
+    }
+
+    /// Read more of the body into the buffer, false at its end
+    async fn fill(&mut self) -> Result<bool, MultipartError> {
+        match self.body.next().await {
+            Some(Ok(chunk)) => {
+                self.buffer.extend_from_slice(&chunk);
+                Ok(true)
+            }
+            Some(Err(e)) => Err(MultipartError::Body(e)),
+            None => Ok(false),
+        }
+    }
+
+    fn find(&self, needle: &[u8]) -> Option<usize> {
+        self.buffer
+            .windows(needle.len())
+            .position(|window| window == needle)
+    }
+
+    /// Next chunk of the current part's body, None at its end
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        if !self.in_part {
+            return Ok(None);
+        }
+        loop {
+            if let Some(end) = self.find(&self.delimiter) {
+                let chunk: Vec<u8> = self.buffer.drain(..end).collect();
+                self.in_part = false;
+                return Ok((!chunk.is_empty()).then(|| Bytes::from(chunk)));
+            }
+            // Everything but what could be the start of the delimiter
+            let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                let chunk: Vec<u8> = self.buffer.drain(..safe).collect();
+                return Ok(Some(Bytes::from(chunk)));
+            }
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("body ends inside a part"));
+            }
+        }
+    }
+
+    /// Headers of the next part, skipping what is left of the current one
+    /// None after the last part
+    pub async fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        let mut skipped = 0;
+        while let Some(chunk) = self.chunk().await? {
+            skipped += chunk.len();
+            if skipped > MAX_SKIPPED_BYTES {
+                return Err(MultipartError::Malformed("form field too large"));
+            }
+        }
+        if self.done {
+            return Ok(None);
+        }
+
+        // The delimiter, then "--" after the last part or a line break before headers
+        let start = loop {
+            if let Some(start) = self.find(&self.delimiter) {
+                break start;
+            }
+            if self.buffer.len() > MAX_SKIPPED_BYTES {
+                return Err(MultipartError::Malformed("no boundary"));
+            }
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("no boundary"));
+            }
+        };
+        self.buffer.drain(..start + self.delimiter.len());
+        while self.buffer.len() < 2 {
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("body ends after a boundary"));
+            }
+        }
+        if self.buffer.starts_with(b"--") {
+            self.done = true;
+            return Ok(None);
+        }
+
+        let end = loop {
+            if let Some(end) = self.find(b"\r\n\r\n") {
+                break end;
+            }
+            if self.buffer.len() > MAX_HEADER_BYTES {
+                return Err(MultipartError::Malformed("part headers too large"));
+            }
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("body ends inside part headers"));
+            }
+        };
+        let headers: Vec<u8> = self.buffer.drain(..end + 4).collect();
+        self.in_part = true;
+        parse_part_headers(&String::from_utf8_lossy(&headers))
+            .map(Some)
+            .ok_or(MultipartError::Malformed("part without a form field name"))
+    }
+
+    /// The rest of the current part as a stream, the parts after it are not read
+    pub fn into_stream(self) -> ByteStream {
+        stream::unfold(Some(self), |multipart| async move {
+            let mut multipart = multipart?;
+            match multipart.chunk().await {
+                Ok(Some(chunk)) => Some((Ok(chunk), Some(multipart))),
+                Ok(None) => None,
+                Err(e) => Some((Err(std::io::Error::other(e)), None)),
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Name, file name and type from the headers of a part
+fn parse_part_headers(headers: &str) -> Option<Part> {
+    let mut name = None;
+    let mut file_name = None;
+    let mut content_type = None;
+    for line in headers.split("\r\n") {
+        let Some((header, value)) = line.split_once(':') else {
+            continue;
+        };
+        if header.trim().eq_ignore_ascii_case("content-disposition") {
+            for param in value.split(';').skip(1) {
+                let Some((key, value)) = param.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"').to_string();
+                match key.trim().to_lowercase().as_str() {
+                    "name" => name = Some(value),
+                    "filename" => file_name = Some(value),
+                    _ => {}
+                }
+            }
+        } else if header.trim().eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.trim().to_string());
+        }
+    }
+    Some(Part {
+        name: name?,
+        file_name: file_name.filter(|f| !f.is_empty()),
+        content_type,
+    })
+}
@@ -0,0 +1,295 @@
+// Bulk provisioning of users, for onboarding a company or family in one go
+//
+// POST /api/admin/users/import takes a roster CSV with the columns email, name, role and
+// password (role and password may be left out or empty). Each row becomes a user:
+//
+// - with a password, the user can log in with it right away
+// - without one, the user is invited: created without a usable password, and a
+//   "users.invite" job emails them a link to choose one (POST /api/users/password-setup).
+//   Invitations need the mailer (MAILER_URL), rows to invite are rejected without it
+// - with a role (editor or viewer), the user joins the wallet of the wallet_owner given
+//   in the query string, as when the owner shares it
+//
+// Users who already exist are left as they are, bar joining the wallet. The answer reports
+// every row by its line: created, invited, existing or rejected with the reason.
+
+use crate::auth;
+use crate::database::DbPool;
+use crate::handlers::{self, AppState};
+use crate::importers::Columns;
+use crate::jobs::{self, JobHandler};
+use crate::mailer::Mailer;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::job_models::JobCreate;
+use crate::models::sharing_models::WalletRole;
+use crate::models::user_models::UserCreate;
+use crate::queries::{password_setup_queries, sharing_queries, user_queries};
+use crate::validation::{MAX_NAME_LEN, MAX_PASSWORD_LEN, ValidationErrors};
+use axum::async_trait;
+use chrono::{Duration, Utc};
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Most rows of one roster, every password is hashed while the request waits
+pub const MAX_ROSTER_ROWS: usize = 1000;
+
+/// Columns every roster has
+const REQUIRED_COLUMNS: &[&str] = &["email", "name"];
+
+/// A user to provision, from one row of the roster
+#[derive(Debug)]
+pub struct RosterRow {
+    pub line: u64,
+    pub email: String,
+    pub name: String,
+    pub role: Option<WalletRole>,
+    /// None to invite the user instead
+    pub password: Option<String>,
+}
+
+/// What became of a row of the roster
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RosterOutcome {
+    /// Created with the password of the row
+    Created,
+    /// Created without a password and sent an invitation
+    Invited,
+    /// Already a user, only the wallet role was applied
+    Existing,
+    Rejected,
+}
+
+/// A row of the roster as reported back
+#[derive(Debug, Serialize)]
+pub struct RosterResult {
+    pub line: u64,
+    pub email: String,
+    pub outcome: RosterOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl RosterResult {
+    fn rejected(line: u64, email: &str, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            email: email.to_string(),
+            outcome: RosterOutcome::Rejected,
+            user_id: None,
+            message: Some(message.into()),
+        }
+    }
+}
+
+/// Read a roster into the rows to provision and the rows rejected outright
+///
+/// # Errors
+/// Returns an error when the text is not a roster CSV or has too many rows
+pub fn parse_roster(text: &str) -> Result<(Vec<RosterRow>, Vec<RosterResult>), String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns = Columns::new(reader.headers().map_err(|e| e.to_string())?);
+    let missing: Vec<&str> = REQUIRED_COLUMNS
+        .iter()
+        .copied()
+        .filter(|name| !columns.has(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Not a roster, missing column(s): {}",
+            missing.join(", ")
+        ));
+    }
+
+    let mut rows = Vec::new();
+    let mut rejected = Vec::new();
+    let mut seen = HashSet::new();
+    for (i, record) in reader.records().enumerate() {
+        if i >= MAX_ROSTER_ROWS {
+            return Err(format!("Rosters may have at most {} rows", MAX_ROSTER_ROWS));
+        }
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                rejected.push(RosterResult::rejected(line, "", e.to_string()));
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let email = columns.get(&record, "email");
+        let name = columns.get(&record, "name");
+        let role = columns.get(&record, "role");
+        let password = columns.get(&record, "password");
+
+        let mut errors = ValidationErrors::default();
+        errors.check_email("email", email);
+        errors.check_required("name", name, MAX_NAME_LEN);
+        errors.check_length("password", password, MAX_PASSWORD_LEN);
+        let role = match role {
+            "" => None,
+            role => match WalletRole::from_str(&role.to_lowercase()) {
+                Ok(role) => Some(role),
+                Err(_) => {
+                    errors.add("role", "must be editor or viewer");
+                    None
+                }
+            },
+        };
+        if !errors.is_empty() {
+            rejected.push(RosterResult::rejected(line, email, errors.to_string()));
+            continue;
+        }
+        if !seen.insert(email.to_lowercase()) {
+            rejected.push(RosterResult::rejected(
+                line,
+                email,
+                "repeats an earlier row",
+            ));
+            continue;
+        }
+        rows.push(RosterRow {
+            line,
+            email: email.to_string(),
+            name: name.to_string(),
+            role,
+            password: (!password.is_empty()).then(|| password.to_string()),
+        });
+    }
+    Ok((rows, rejected))
+}
+
+/// Create the user of a row, or find them when they exist, and add them to the owner's
+/// wallet when the row has a role
+pub async fn provision(
+    state: &AppState,
+    row: RosterRow,
+    wallet_owner: Option<Uuid>,
+    invitations: bool,
+) -> anyhow::Result<RosterResult> {
+    let RosterRow {
+        line,
+        email,
+        name,
+        role,
+        password,
+    } = row;
+    if role.is_some() && wallet_owner.is_none() {
+        return Ok(RosterResult::rejected(
+            line,
+            &email,
+            "a role needs the wallet_owner parameter",
+        ));
+    }
+
+    let (user_id, outcome) = if let Some(user) = user_queries::get_user(&state.db, &email).await? {
+        (user.id, RosterOutcome::Existing)
+    } else {
+        let (password_hash, outcome) = match &password {
+            Some(password) => (auth::hash_password(password)?, RosterOutcome::Created),
+            None if invitations => (auth::unusable_password_hash()?, RosterOutcome::Invited),
+            None => {
+                return Ok(RosterResult::rejected(
+                    line,
+                    &email,
+                    "no password, and invitations need MAILER_URL",
+                ));
+            }
+        };
+        let user_id = user_queries::create_user(
+            &state.db,
+            &UserCreate::new(email.clone(), name, password_hash),
+        )
+        .await?;
+        handlers::record_audit(
+            state,
+            AuditEntryCreate::new("create", "user", Some(user_id)).details(
+                json!({ "source": "bulk_import", "invited": outcome == RosterOutcome::Invited }),
+            ),
+        )
+        .await;
+        if outcome == RosterOutcome::Invited {
+            let job = JobCreate::new("users.invite", json!({ "user_id": user_id }))
+                .dedupe_key(user_id.to_string());
+            jobs::enqueue(&state.db, job).await?;
+        }
+        (user_id, outcome)
+    };
+
+    if let (Some(role), Some(owner_id)) = (role, wallet_owner) {
+        if owner_id == user_id {
+            return Ok(RosterResult::rejected(
+                line,
+                &email,
+                "is the wallet owner, who cannot join their own wallet",
+            ));
+        }
+        sharing_queries::upsert_member(&state.db, owner_id, user_id, role).await?;
+        handlers::record_audit(
+            state,
+            AuditEntryCreate::new("update", "wallet_member", Some(user_id))
+                .actor(owner_id)
+                .details(json!({ "role": role, "source": "bulk_import" })),
+        )
+        .await;
+    }
+
+    Ok(RosterResult {
+        line,
+        email,
+        outcome,
+        user_id: Some(user_id),
+        message: None,
+    })
+}
+
+/// Job emailing an invited user the link to choose their password
+pub struct InviteUserJob {
+    pub mailer: Mailer,
+    /// Page of the app taking the token, the link adds it as ?token=...
+    pub invitation_url: Url,
+    /// How long the link works
+    pub ttl: Duration,
+}
+
+#[async_trait]
+impl JobHandler for InviteUserJob {
+    fn kind(&self) -> &'static str {
+        "users.invite"
+    }
+
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()> {
+        let user_id: Uuid = serde_json::from_value(payload["user_id"].clone())?;
+        // Deleted since, nobody to invite
+        let Some(user) = user_queries::get_user_by_id(db, user_id).await? else {
+            return Ok(());
+        };
+
+        let (token, token_hash) = auth::new_token();
+        password_setup_queries::create(db, user.id, &token_hash, Utc::now() + self.ttl).await?;
+        let mut link = self.invitation_url.clone();
+        link.query_pairs_mut().append_pair("token", &token);
+        let text = format!(
+            "Hi {},\n\nAn account was created for you on Wallet. Choose your password to \
+             start using it:\n\n{}\n\nThe link works for {} day(s). Your email is {}.\n",
+            user.name,
+            link,
+            self.ttl.num_days().max(1),
+            user.email
+        );
+        self.mailer
+            .send(&user.email, "You're invited to Wallet", &text)
+            .await
+    }
+}
@@ -0,0 +1,374 @@
+// Errors as problem details (RFC 7807)
+//
+// Every error of the API is answered with an `application/problem+json` body:
+//
+//     {"type": "/api/meta/errors#not_found", "title": "Not found", "status": 404,
+//      "detail": "...", "instance": "/api/transactions/...", "code": "not_found"}
+//
+// `code` is one of the registry below, listed at GET /api/meta/errors, and keeps its meaning
+// once published, so clients (and generated SDKs) branch on it instead of on `detail`, which
+// is written for people and may be reworded.
+//
+// Handlers answer errors as they always have, a bare status or a JSON body with a "message"
+// and members of their own, and `problem_responses` turns them into problems once they
+// answered: the message becomes the detail, the other members are kept as extension members
+// (the "errors" of a 422, the "quota" of a 402, ...) and a "code" member picks a code more
+// specific than the status. The Firefly III compatible API keeps answering as Firefly III.
+
+use axum::{
+    Json,
+    body::{Body, HttpBody, to_bytes},
+    extract::Request,
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize, de, de::IntoDeserializer};
+use serde_json::{Map, Value, json};
+use std::str::FromStr;
+
+/// Media type of problem details
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+/// Largest error body rewritten, longer ones and streams of unknown length are left as they are
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Paths of APIs mimicking other apps, whose errors look like those apps' own
+const FOREIGN_APIS: &[&str] = &["/api/v1/"];
+
+/// Members of a problem set here, never taken from the handler's body
+const STANDARD_MEMBERS: &[&str] = &["type", "title", "status", "detail", "instance", "code"];
+
+/// The stable codes errors are reported with
+/// Codes are only ever added, a code's meaning and usual status do not change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    QuotaExceeded,
+    Forbidden,
+    InvitationRequired,
+    CategoryNotAllowed,
+    AttachmentQuarantined,
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    SpendingLimitExceeded,
+    PeriodLocked,
+    ShareLinkExpired,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    ValidationFailed,
+    RateLimited,
+    /// Any other 4xx
+    RequestFailed,
+    InternalError,
+    NotImplemented,
+    BadGateway,
+    ServiceUnavailable,
+}
+
+impl ErrorCode {
+    /// The registry, in the order it is listed
+    pub const ALL: &[ErrorCode] = &[
+        ErrorCode::BadRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::QuotaExceeded,
+        ErrorCode::Forbidden,
+        ErrorCode::InvitationRequired,
+        ErrorCode::CategoryNotAllowed,
+        ErrorCode::AttachmentQuarantined,
+        ErrorCode::NotFound,
+        ErrorCode::MethodNotAllowed,
+        ErrorCode::Conflict,
+        ErrorCode::SpendingLimitExceeded,
+        ErrorCode::PeriodLocked,
+        ErrorCode::ShareLinkExpired,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::UnsupportedMediaType,
+        ErrorCode::ValidationFailed,
+        ErrorCode::RateLimited,
+        ErrorCode::RequestFailed,
+        ErrorCode::InternalError,
+        ErrorCode::NotImplemented,
+        ErrorCode::BadGateway,
+        ErrorCode::ServiceUnavailable,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::InvitationRequired => "invitation_required",
+            ErrorCode::CategoryNotAllowed => "category_not_allowed",
+            ErrorCode::AttachmentQuarantined => "attachment_quarantined",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::SpendingLimitExceeded => "spending_limit_exceeded",
+            ErrorCode::PeriodLocked => "period_locked",
+            ErrorCode::ShareLinkExpired => "share_link_expired",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::RequestFailed => "request_failed",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::NotImplemented => "not_implemented",
+            ErrorCode::BadGateway => "bad_gateway",
+            ErrorCode::ServiceUnavailable => "service_unavailable",
+        }
+    }
+
+    /// Status errors with this code are usually answered with
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::QuotaExceeded => StatusCode::PAYMENT_REQUIRED,
+            ErrorCode::Forbidden
+            | ErrorCode::InvitationRequired
+            | ErrorCode::CategoryNotAllowed
+            | ErrorCode::AttachmentQuarantined => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::Conflict | ErrorCode::SpendingLimitExceeded | ErrorCode::PeriodLocked => {
+                StatusCode::CONFLICT
+            }
+            ErrorCode::ShareLinkExpired => StatusCode::GONE,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RequestFailed => StatusCode::BAD_REQUEST,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+            ErrorCode::BadGateway => StatusCode::BAD_GATEWAY,
+            ErrorCode::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "Bad request",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::QuotaExceeded => "Quota exceeded",
+            ErrorCode::Forbidden => "Forbidden",
+            ErrorCode::InvitationRequired => "Invitation required",
+            ErrorCode::CategoryNotAllowed => "Category not allowed",
+            ErrorCode::AttachmentQuarantined => "Attachment quarantined",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::MethodNotAllowed => "Method not allowed",
+            ErrorCode::Conflict => "Conflict",
+            ErrorCode::SpendingLimitExceeded => "Spending limit exceeded",
+            ErrorCode::PeriodLocked => "Period locked",
+            ErrorCode::ShareLinkExpired => "Share link expired",
+            ErrorCode::PayloadTooLarge => "Payload too large",
+            ErrorCode::UnsupportedMediaType => "Unsupported media type",
+            ErrorCode::ValidationFailed => "Validation failed",
+            ErrorCode::RateLimited => "Rate limited",
+            ErrorCode::RequestFailed => "Request failed",
+            ErrorCode::InternalError => "Internal error",
+            ErrorCode::NotImplemented => "Not implemented",
+            ErrorCode::BadGateway => "Bad gateway",
+            ErrorCode::ServiceUnavailable => "Service unavailable",
+        }
+    }
+
+    /// When the code is used, and the extension members coming with it
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => {
+                "The request could not be read: malformed JSON, query parameters or upload"
+            }
+            ErrorCode::Unauthorized => "Missing, invalid or expired credentials or access token",
+            ErrorCode::QuotaExceeded => {
+                "A quota of the user's plan is used up, 429 for the daily API calls. \
+                 Members: quota, plan, limit"
+            }
+            ErrorCode::Forbidden => "The credentials do not allow this request",
+            ErrorCode::InvitationRequired => "Signing up needs an invitation on this server",
+            ErrorCode::CategoryNotAllowed => {
+                "The category is not allowed for this child account. Members: allowed_categories"
+            }
+            ErrorCode::AttachmentQuarantined => {
+                "The attachment was flagged as malware, its contents are not served"
+            }
+            ErrorCode::NotFound => {
+                "No such resource, e.g. no user with the email given, or one the caller may not see"
+            }
+            ErrorCode::MethodNotAllowed => "The resource does not support this method",
+            ErrorCode::Conflict => {
+                "The request conflicts with the current state, e.g. a resource that already exists"
+            }
+            ErrorCode::SpendingLimitExceeded => {
+                "The expense goes over a spending limit. \
+                 Members: spending_limit, spent, override_allowed"
+            }
+            ErrorCode::PeriodLocked => {
+                "The transaction or period is in a reconciled period. Members: reconciliation_lock"
+            }
+            ErrorCode::ShareLinkExpired => {
+                "The share link expired or was revoked by its owner, the report is no longer shown"
+            }
+            ErrorCode::PayloadTooLarge => "The request body is too large",
+            ErrorCode::UnsupportedMediaType => "The body or upload is of a type not accepted",
+            ErrorCode::ValidationFailed => {
+                "The request was read but has invalid values. Members: errors, a list of field and message"
+            }
+            ErrorCode::RateLimited => "Too many requests, retry after the Retry-After header",
+            ErrorCode::RequestFailed => "Any other client error, the status tells which",
+            ErrorCode::InternalError => "An unexpected error on the server, retrying may help",
+            ErrorCode::NotImplemented => "The server's configuration does not support this request",
+            ErrorCode::BadGateway => "A service the server relies on failed",
+            ErrorCode::ServiceUnavailable => "The server cannot serve this request right now",
+        }
+    }
+
+    /// The code of errors answered with a status and nothing more specific
+    pub fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::PAYMENT_REQUIRED => ErrorCode::QuotaExceeded,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::ValidationFailed,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::NOT_IMPLEMENTED => ErrorCode::NotImplemented,
+            StatusCode::BAD_GATEWAY => ErrorCode::BadGateway,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::ServiceUnavailable,
+            status if status.is_server_error() => ErrorCode::InternalError,
+            _ => ErrorCode::RequestFailed,
+        }
+    }
+
+    /// URI of the code's documentation, its entry at /api/meta/errors
+    pub fn type_uri(self) -> String {
+        format!("/api/meta/errors#{}", self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+            .map_err(|_: de::value::Error| format!("Unknown error code: {}", s))
+    }
+}
+
+/// The problem details of an error response, from the body the handler answered with
+fn problem(status: StatusCode, instance: &str, body: &[u8]) -> Value {
+    let (mut members, detail) = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(mut members)) => {
+            let detail = match members.remove("message") {
+                Some(Value::String(message)) => Some(message),
+                _ => None,
+            };
+            (members, detail)
+        }
+        Ok(_) => (Map::new(), None),
+        // Rejections of axum's extractors are plain text
+        Err(_) => {
+            let text = String::from_utf8_lossy(body).trim().to_string();
+            (Map::new(), (!text.is_empty()).then_some(text))
+        }
+    };
+    let code = members
+        .get("code")
+        .and_then(Value::as_str)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or_else(|| ErrorCode::for_status(status));
+    members.retain(|name, _| !STANDARD_MEMBERS.contains(&name.as_str()));
+
+    let mut problem = Map::new();
+    problem.insert("type".to_string(), json!(code.type_uri()));
+    problem.insert("title".to_string(), json!(code.title()));
+    problem.insert("status".to_string(), json!(status.as_u16()));
+    if let Some(detail) = detail {
+        problem.insert("detail".to_string(), json!(detail));
+    }
+    problem.insert("instance".to_string(), json!(instance));
+    problem.insert("code".to_string(), json!(code));
+    problem.extend(members);
+    Value::Object(problem)
+}
+
+/// Middleware answering the errors of the API as problem details
+pub async fn problem_responses(req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    let response = next.run(req).await;
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error())
+        || !path.starts_with("/api/")
+        || FOREIGN_APIS.iter().any(|prefix| path.starts_with(prefix))
+    {
+        return response;
+    }
+    let media_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(str::trim)
+        .unwrap_or_default();
+    // Already a problem, or a page for people
+    if media_type == CONTENT_TYPE || media_type == "text/html" {
+        return response;
+    }
+
+    let fits = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|len| len <= MAX_BODY_BYTES as u64);
+    if !fits {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to buffer error response: {}", e);
+            Default::default()
+        }
+    };
+    let body = match serde_json::to_vec(&problem(status, &path, &bytes)) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Failed to write problem details: {}", e);
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+    };
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// GET /api/meta/errors - the registry of error codes
+pub async fn list_error_codes() -> Json<Value> {
+    let codes: Vec<Value> = ErrorCode::ALL
+        .iter()
+        .map(|code| {
+            json!({
+                "code": code,
+                "type": code.type_uri(),
+                "title": code.title(),
+                "status": code.status().as_u16(),
+                "description": code.description(),
+            })
+        })
+        .collect();
+    Json(json!({ "errors": codes }))
+}
@@ -1,110 +1,668 @@
 pub mod user_queries {
+    use crate::auth;
+    use crate::crypto;
     use crate::database::DbPool;
     use crate::models::user_models as user;
+    use crate::telemetry;
     use anyhow::anyhow;
 
-    use argon2::{
-        Argon2, PasswordHasher,
-        password_hash::{SaltString, rand_core::OsRng},
-    };
-
     use chrono::{DateTime, Utc};
-    use sqlx::Row;
+    use chrono_tz::Tz;
     use sqlx::postgres::PgRow;
+    use sqlx::{PgExecutor, Row};
     use uuid::Uuid;
 
-    pub async fn create_user(pool: &DbPool, user: &user::UserCreate) -> anyhow::Result<String> {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let hashed_pwd = argon2
-            .hash_password(user.password.as_bytes(), &salt)
-            .map_err(|e| anyhow::anyhow!("password hashing failed: {e}"))?
-            .to_string();
-        sqlx::query("INSERT INTO users (email, name, password) VALUES ($1, $2, $3)")
-            .bind(&user.email)
-            .bind(&user.name)
-            .bind(&hashed_pwd)
-            .execute(pool)
-            .await?;
-        Ok(user.name.clone())
+    pub async fn create_user<'e>(
+        executor: impl PgExecutor<'e>,
+        user: &user::UserCreate,
+    ) -> anyhow::Result<Uuid> {
+        let email = user::normalize_email(&user.email);
+        let sql = "INSERT INTO users (email, email_hash, name, password, timezone) VALUES ($1, $2, $3, $4, COALESCE($5, 'UTC')) RETURNING id";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(crypto::encrypt_field(&email)?)
+                .bind(crypto::blind_index(&email))
+                .bind(&user.name)
+                .bind(&user.password_hash)
+                .bind(&user.timezone)
+                .fetch_one(executor),
+        )
+        .await?;
+        Ok(row.try_get("id")?)
     }
 
-    fn map_row_to_user(row: Option<PgRow>) -> anyhow::Result<user::UserQuery> {
-        match row {
-            Some(row) => {
-                let id: Uuid = row.try_get("id")?;
-                let email: String = row.try_get("email")?;
-                let name: String = row.try_get("name")?;
-                let password: String = row.try_get("password")?;
-                let created_at: DateTime<Utc> = row.try_get("created_at")?;
-                let updated_at: DateTime<Utc> = row.try_get("updated_at")?;
+    /// Create a user, or update the name (and password, if given) of the user with that email
+    /// Returns the user id and whether the user was newly created
+    pub async fn upsert_user(
+        pool: &DbPool,
+        user: &user::UserUpsert,
+    ) -> anyhow::Result<(Uuid, bool)> {
+        // Users provisioned without a password get a random one and cannot log in
+        // with a password until it is set
+        let password_given = user.password_hash.is_some();
+        let hashed_pwd = match &user.password_hash {
+            Some(hashed) => hashed.clone(),
+            None => auth::unusable_password_hash()?,
+        };
 
-                return Ok(user::UserQuery::new(
-                    id, email, name, password, created_at, updated_at,
-                ));
-            }
-            None => return Err(anyhow!("User could not be created from row")),
-        }
+        // Uniqueness is enforced on the blind index when emails are encrypted
+        let conflict_target = if crypto::cipher().is_some() {
+            "email_hash"
+        } else {
+            "email"
+        };
+
+        let email = user::normalize_email(&user.email);
+        // xmax is 0 only for rows inserted by this statement
+        let sql = format!(
+            "INSERT INTO users (email, email_hash, name, password, timezone) VALUES ($1, $2, $3, $4, COALESCE($6, 'UTC'))
+             ON CONFLICT ({conflict_target}) DO UPDATE SET
+                name = EXCLUDED.name,
+                password = CASE WHEN $5 THEN EXCLUDED.password ELSE users.password END,
+                timezone = COALESCE($6, users.timezone),
+                updated_at = NOW()
+             RETURNING id, (xmax = 0) AS inserted"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(crypto::encrypt_field(&email)?)
+                .bind(crypto::blind_index(&email))
+                .bind(&user.name)
+                .bind(&hashed_pwd)
+                .bind(password_given)
+                .bind(&user.timezone)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        Ok((row.try_get("id")?, row.try_get("inserted")?))
+    }
+
+    fn map_row_to_user(row: PgRow) -> anyhow::Result<user::UserQuery> {
+        let id: Uuid = row.try_get("id")?;
+        let email: String = crypto::decrypt_field(row.try_get("email")?)?;
+        let name: String = row.try_get("name")?;
+        let password: String = row.try_get("password")?;
+        let timezone: String = row.try_get("timezone")?;
+        let created_at: DateTime<Utc> = row.try_get("created_at")?;
+        let updated_at: DateTime<Utc> = row.try_get("updated_at")?;
+
+        Ok(user::UserQuery::new(
+            id, email, name, password, timezone, created_at, updated_at,
+        ))
     }
 
-    pub async fn get_user(pool: &DbPool, email: &str) -> anyhow::Result<user::UserQuery> {
-        let row = sqlx::query("SELECT id, email, name, password, created_at, updated_at FROM users WHERE email = $1 LIMIT 1")
-        .bind(email)
-        .fetch_optional(pool)
+    /// The user who signed up with the email, None when nobody did
+    pub async fn get_user(pool: &DbPool, email: &str) -> anyhow::Result<Option<user::UserQuery>> {
+        let email = &user::normalize_email(email);
+        // Encrypted emails are found through their blind index, rows written
+        // before encryption was enabled still match on the plaintext column
+        let sql = "SELECT id, email, name, password, timezone, created_at, updated_at FROM users WHERE email_hash = $1 OR (email_hash IS NULL AND email = $2) LIMIT 1";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(crypto::blind_index(email))
+                .bind(email)
+                .fetch_optional(pool),
+        )
         .await?;
 
-        return map_row_to_user(row);
+        row.map(map_row_to_user).transpose()
     }
 
-    pub async fn get_all_users(pool: &DbPool) -> anyhow::Result<Vec<user::UserQuery>> {
-        let rows =
-            sqlx::query("SELECT id, email, name, password, created_at, updated_at FROM users")
-                .fetch_all(pool)
+    /// Whether a user signed up with the email, found the way `get_user` finds them
+    pub async fn email_exists(pool: &DbPool, email: &str) -> anyhow::Result<bool> {
+        let email = &user::normalize_email(email);
+        let sql = "SELECT 1 FROM users WHERE email_hash = $1 OR (email_hash IS NULL AND email = $2) LIMIT 1";
+        let found: Option<i32> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(crypto::blind_index(email))
+                .bind(email)
+                .fetch_optional(pool),
+        )
+        .await?;
+        Ok(found.is_some())
+    }
+
+    pub async fn get_user_by_id(
+        pool: &DbPool,
+        id: Uuid,
+    ) -> anyhow::Result<Option<user::UserQuery>> {
+        let sql = "SELECT id, email, name, password, timezone, created_at, updated_at FROM users WHERE id = $1";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_user).transpose()
+    }
+
+    pub async fn user_exists(pool: &DbPool, user_id: Uuid) -> anyhow::Result<bool> {
+        let sql = "SELECT 1 FROM users WHERE id = $1";
+        let found: Option<i32> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql).bind(user_id).fetch_optional(pool),
+        )
+        .await?;
+        Ok(found.is_some())
+    }
+
+    /// The user's time zone, UTC for unknown users or zone names
+    pub async fn get_user_timezone(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Tz> {
+        let sql = "SELECT timezone FROM users WHERE id = $1";
+        let timezone: Option<String> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql).bind(user_id).fetch_optional(pool),
+        )
+        .await?;
+
+        Ok(timezone.and_then(|tz| tz.parse().ok()).unwrap_or(Tz::UTC))
+    }
+
+    /// A page of users in sign-up order, starting after the cursor
+    pub async fn get_users_page(
+        pool: &DbPool,
+        after: Option<user::UserCursor>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<user::UserQuery>> {
+        let sql = "SELECT id, email, name, password, timezone, created_at, updated_at FROM users WHERE ($1::timestamptz IS NULL OR (created_at, id) > ($1, $2)) ORDER BY created_at, id LIMIT $3";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(after.map(|cursor| cursor.created_at))
+                .bind(after.map(|cursor| cursor.id))
+                .bind(limit)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(map_row_to_user)
+            .collect::<anyhow::Result<Vec<user::UserQuery>>>()
+    }
+
+    /// Name of the user's plan
+    pub async fn get_plan(pool: &DbPool, user_id: Uuid) -> anyhow::Result<String> {
+        let sql = "SELECT plan FROM users WHERE id = $1";
+        let plan: Option<String> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql).bind(user_id).fetch_optional(pool),
+        )
+        .await?;
+        plan.ok_or_else(|| anyhow!("User {} not found", user_id))
+    }
+
+    /// Move the user to another plan, false when there is no such user
+    pub async fn set_plan(pool: &DbPool, user_id: Uuid, plan: &str) -> anyhow::Result<bool> {
+        let sql = "UPDATE users SET plan = $2, updated_at = NOW() WHERE id = $1";
+        let result =
+            telemetry::observe(sql, sqlx::query(sql).bind(user_id).bind(plan).execute(pool))
                 .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
-        return rows
-            .into_iter()
-            .map(|row| map_row_to_user(Some(row)))
-            .collect::<anyhow::Result<Vec<user::UserQuery>>>();
+    /// The user's wrapped encryption key, None when the user has not set one up
+    pub async fn get_encryption_key(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<user::UserEncryptionKey>> {
+        let sql = "SELECT wrapped_key, algorithm, kdf_params, created_at, updated_at FROM user_encryption_keys WHERE user_id = $1";
+        let row =
+            telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_optional(pool)).await?;
+        row.map(|row| {
+            Ok(user::UserEncryptionKey {
+                wrapped_key: row.try_get("wrapped_key")?,
+                algorithm: row.try_get("algorithm")?,
+                kdf_params: row.try_get("kdf_params")?,
+                created_at: row.try_get("created_at")?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Store the user's wrapped encryption key, replacing the previous one
+    /// Replacing it is how clients rotate the wrapping secret, the descriptions stay readable
+    /// as long as the wrapped key itself is the same
+    pub async fn set_encryption_key(
+        pool: &DbPool,
+        user_id: Uuid,
+        wrapped_key: &str,
+        algorithm: &str,
+        kdf_params: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO user_encryption_keys (user_id, wrapped_key, algorithm, kdf_params) VALUES ($1, $2, $3, $4) ON CONFLICT (user_id) DO UPDATE SET wrapped_key = EXCLUDED.wrapped_key, algorithm = EXCLUDED.algorithm, kdf_params = EXCLUDED.kdf_params, updated_at = NOW()";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(wrapped_key)
+                .bind(algorithm)
+                .bind(kdf_params)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
     }
 }
 
 pub mod transaction_queries {
+    use crate::crypto;
     use crate::database::DbPool;
+    use crate::events::{self, ChangeEvent, ChangeKind};
+    use crate::models::limit_models::LimitPeriod;
+    use crate::models::merchant_models::Merchant;
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::reconciliation_models::TransactionLocked;
+    use crate::models::sync_models::SyncCursor;
     use crate::models::transaction_models::{
-        self as transaction, TransactionCategory, TransactionType,
+        self as transaction, TransactionCategory, TransactionSource,
     };
+    use crate::queries::reconciliation_queries;
+    use crate::redact;
+    use crate::telemetry;
     use anyhow::anyhow;
-    use chrono::{DateTime, Utc};
+    use chrono::{DateTime, NaiveDate, Utc};
+    use chrono_tz::Tz;
     use rust_decimal::Decimal;
-    use sqlx::QueryBuilder;
+    use serde_json::json;
     use sqlx::postgres::PgRow;
-    use sqlx::{Execute, Row};
+    use sqlx::{Execute, PgExecutor, Postgres, QueryBuilder, Row};
+    use std::collections::{BTreeMap, HashSet};
     use std::str::FromStr;
     use uuid::Uuid;
 
+    /// Columns of transaction_history that make up a transaction, for reading past versions
+    /// Past versions are never deleted ones, a deleted transaction has no current version
+    const HISTORY_COLUMNS: &str = "id, user_id, transaction_type, amount, currency, category, description, encrypted_description, source, external_id, bank_account_id, account_id, created_at, last_updated_at, merchant_name, merchant_logo_url, NULL::timestamptz AS deleted_at";
+
+    /// Insert a transaction, returning its id, or None when it was skipped as an already known external id
     pub async fn create_transaction(
         pool: &DbPool,
         transaction: &transaction::TransactionCreate,
-    ) -> anyhow::Result<String> {
-        let amount = match transaction.transaction_type {
-            TransactionType::Expense => (-1.0) * transaction.amount.abs(),
-            TransactionType::Income => transaction.amount.abs(),
-        };
-        let result = sqlx::query("INSERT INTO transactions (user_id,transaction_type,amount,category,description) VALUES ($1,$2::transaction_type,$3,$4,$5)")
-            .bind(&transaction.user_id)
-            .bind(&transaction.transaction_type.to_string())
-            .bind(amount)
-            .bind(&transaction.category.to_string())
-            .bind(&transaction.description)
-            .execute(pool)
-            .await?;
+    ) -> anyhow::Result<Option<Uuid>> {
+        let id = insert_transaction(pool, transaction).await?;
+        if let Some(id) = id {
+            events::publish(pool, created_event(id, transaction)).await;
+        }
+        Ok(id)
+    }
+
+    /// Insert a transaction without publishing its change event, for writes in a
+    /// `request_tx::Tx` that publish it once committed
+    pub async fn insert_transaction<'e>(
+        executor: impl PgExecutor<'e>,
+        transaction: &transaction::TransactionCreate,
+    ) -> anyhow::Result<Option<Uuid>> {
+        // Rows carrying an external id that was already seen for this user and source
+        // are skipped, so re-running an import or bank sync does not duplicate them
+        let sql = "INSERT INTO transactions (user_id,transaction_type,amount,currency,category,description,source,external_id,created_at,bank_account_id,id,encrypted_description,account_id) VALUES ($1,$2::transaction_type,$3,$4,$5,$6,$7::transaction_source,$8,COALESCE($9, NOW()),$10,COALESCE($11, gen_random_uuid()),$12,$13) ON CONFLICT (user_id, source, external_id) DO NOTHING RETURNING id";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(transaction.user_id)
+                .bind(transaction.transaction_type.to_string())
+                .bind(transaction.amount.to_decimal())
+                .bind(transaction.amount.currency.to_string())
+                .bind(transaction.category.to_string())
+                .bind(crypto::encrypt_field(&transaction.description)?)
+                .bind(transaction.source.to_string())
+                .bind(&transaction.external_id)
+                .bind(transaction.occurred_at)
+                .bind(transaction.bank_account_id)
+                .bind(transaction.id)
+                .bind(&transaction.encrypted_description)
+                .bind(transaction.account_id)
+                .fetch_optional(executor),
+        )
+        .await?;
 
         println!(
             "Transaction inserted: {} rows affected",
-            result.rows_affected()
+            row.is_some() as u8
         );
-        Ok(transaction.user_id.to_string())
+        Ok(row.map(|r| r.try_get("id")).transpose()?)
+    }
+
+    /// The change event of the transaction inserted as `id`
+    pub fn created_event(id: Uuid, transaction: &transaction::TransactionCreate) -> ChangeEvent {
+        ChangeEvent::new(
+            ChangeKind::Created,
+            id,
+            transaction.user_id,
+            json!({
+                "id": id,
+                "transaction_type": transaction.transaction_type,
+                "amount": transaction.amount,
+                "category": transaction.category,
+                "source": transaction.source,
+            }),
+        )
+        .source(transaction.source)
+    }
+
+    /// Refuse changes to transactions in a reconciled period of their account, with a
+    /// `TransactionLocked` error, and moving them into one (to `moved_to`)
+    pub async fn ensure_unlocked(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+        moved_to: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        match reconciliation_queries::get_covering_lock(pool, id, user_id, moved_to).await? {
+            Some(lock) => Err(TransactionLocked {
+                transaction_id: id,
+                lock,
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Overwrite the type, amount, category, description and (when given) date of one of
+    /// the user's transactions, returning its new last_updated_at, None when there is no such transaction
+    /// Fails with `TransactionLocked` for transactions in a reconciled period
+    pub async fn update_transaction(
+        pool: &DbPool,
+        id: Uuid,
+        transaction: &transaction::TransactionCreate,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        ensure_unlocked(pool, id, transaction.user_id, transaction.occurred_at).await?;
+        let sql = "UPDATE transactions SET transaction_type = $3::transaction_type, amount = $4, currency = $5, category = $6, description = $7, created_at = COALESCE($8, created_at), encrypted_description = $9, last_updated_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL RETURNING last_updated_at, source::text AS source";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(transaction.user_id)
+                .bind(transaction.transaction_type.to_string())
+                .bind(transaction.amount.to_decimal())
+                .bind(transaction.amount.currency.to_string())
+                .bind(transaction.category.to_string())
+                .bind(crypto::encrypt_field(&transaction.description)?)
+                .bind(transaction.occurred_at)
+                .bind(&transaction.encrypted_description)
+                .fetch_optional(pool),
+        )
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let source: &str = row.try_get("source")?;
+        let source = TransactionSource::from_str(source).map_err(|e| anyhow!(e))?;
+        let event = ChangeEvent::new(
+            ChangeKind::Updated,
+            id,
+            transaction.user_id,
+            json!({
+                "id": id,
+                "transaction_type": transaction.transaction_type,
+                "amount": transaction.amount,
+                "category": transaction.category,
+                "source": source,
+            }),
+        )
+        .source(source);
+        events::publish(pool, event).await;
+        Ok(Some(row.try_get("last_updated_at")?))
+    }
+
+    /// Soft delete one of the user's transactions, returning it as deleted, None when there
+    /// is no such transaction or it is already deleted
+    /// Synced clients are told with a tombstone. Fails with `TransactionLocked` for
+    /// transactions in a reconciled period
+    pub async fn delete_transaction(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<transaction::TransactionQuery>> {
+        ensure_unlocked(pool, id, user_id, None).await?;
+        let mut tx = pool.begin().await?;
+        let sql = "UPDATE transactions SET deleted_at = NOW(), last_updated_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL RETURNING *";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(user_id)
+                .fetch_optional(&mut *tx),
+        )
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let deleted = map_row_to_transaction(Some(row))?;
+
+        let sql = "INSERT INTO sync_tombstones (entity_type, entity_id, user_id, deleted_at) VALUES ('transaction', $1, $2, $3) ON CONFLICT (entity_type, entity_id) DO UPDATE SET deleted_at = EXCLUDED.deleted_at";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(user_id)
+                .bind(deleted.deleted_at)
+                .execute(&mut *tx),
+        )
+        .await?;
+        tx.commit().await?;
+
+        events::publish(pool, changed_event(ChangeKind::Deleted, &deleted)).await;
+        Ok(Some(deleted))
+    }
+
+    /// Bring back one of the user's deleted transactions, returning it, None when there is
+    /// no such deleted transaction
+    /// Fails with `TransactionLocked` for transactions in a reconciled period
+    pub async fn restore_transaction(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<transaction::TransactionQuery>> {
+        ensure_unlocked(pool, id, user_id, None).await?;
+        let mut tx = pool.begin().await?;
+        // A new last_updated_at has synced clients fetch it again
+        let sql = "UPDATE transactions SET deleted_at = NULL, last_updated_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL RETURNING *";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(user_id)
+                .fetch_optional(&mut *tx),
+        )
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let restored = map_row_to_transaction(Some(row))?;
+
+        let sql =
+            "DELETE FROM sync_tombstones WHERE entity_type = 'transaction' AND entity_id = $1";
+        telemetry::observe(sql, sqlx::query(sql).bind(id).execute(&mut *tx)).await?;
+        tx.commit().await?;
+
+        events::publish(pool, changed_event(ChangeKind::Restored, &restored)).await;
+        Ok(Some(restored))
+    }
+
+    /// The change event of a stored transaction
+    fn changed_event(kind: ChangeKind, transaction: &transaction::TransactionQuery) -> ChangeEvent {
+        ChangeEvent::new(
+            kind,
+            transaction.id,
+            transaction.user_id,
+            json!({
+                "id": transaction.id,
+                "transaction_type": transaction.transaction_type,
+                "amount": transaction.amount,
+                "category": transaction.category,
+                "source": transaction.source,
+            }),
+        )
+        .source(transaction.source)
+    }
+
+    /// Owner of a transaction, whoever it belongs to, None when the id is unused
+    pub async fn get_transaction_owner(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let sql = "SELECT user_id FROM transactions WHERE id = $1";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+        row.map(|r| r.try_get("user_id"))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Change the category of one of the user's transactions, false when there is no such transaction
+    /// Fails with `TransactionLocked` for transactions in a reconciled period
+    pub async fn update_category(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+        category: &TransactionCategory,
+    ) -> anyhow::Result<bool> {
+        ensure_unlocked(pool, id, user_id, None).await?;
+        let sql = "UPDATE transactions SET category = $3, last_updated_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL RETURNING source::text AS source";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(user_id)
+                .bind(category.to_string())
+                .fetch_optional(pool),
+        )
+        .await?;
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let source: &str = row.try_get("source")?;
+        let source = TransactionSource::from_str(source).map_err(|e| anyhow!(e))?;
+        let event = ChangeEvent::new(
+            ChangeKind::Updated,
+            id,
+            user_id,
+            json!({ "id": id, "category": category, "source": source }),
+        )
+        .source(source);
+        events::publish(pool, event).await;
+        Ok(true)
+    }
+
+    /// Attach the merchant found for one of the user's transactions, moving it to the
+    /// merchant's category while it is still in Other or Uncategorized
+    /// False when there is no such transaction, fails with `TransactionLocked` for
+    /// transactions in a reconciled period
+    pub async fn set_merchant(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+        merchant: &Merchant,
+    ) -> anyhow::Result<bool> {
+        ensure_unlocked(pool, id, user_id, None).await?;
+        let sql = "UPDATE transactions SET merchant_name = $3, merchant_logo_url = $4, category = CASE WHEN category = ANY($6) THEN COALESCE($5, category) ELSE category END, last_updated_at = NOW() WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL RETURNING category, source::text AS source";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(user_id)
+                .bind(&merchant.name)
+                .bind(&merchant.logo_url)
+                .bind(merchant.category.as_ref().map(ToString::to_string))
+                .bind(
+                    [
+                        TransactionCategory::Other,
+                        TransactionCategory::Uncategorized,
+                    ]
+                    .map(|category| category.to_string()),
+                )
+                .fetch_optional(pool),
+        )
+        .await?;
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let category: &str = row.try_get("category")?;
+        let category = TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?;
+        let source: &str = row.try_get("source")?;
+        let source = TransactionSource::from_str(source).map_err(|e| anyhow!(e))?;
+        let event = ChangeEvent::new(
+            ChangeKind::Updated,
+            id,
+            user_id,
+            json!({
+                "id": id,
+                "category": category,
+                "merchant_name": merchant.name,
+                "merchant_logo_url": merchant.logo_url,
+                "source": source,
+            }),
+        )
+        .source(source);
+        events::publish(pool, event).await;
+        Ok(true)
+    }
+
+    /// Return which of the given external ids already exist for a user and source
+    pub async fn get_existing_external_ids(
+        pool: &DbPool,
+        user_id: Uuid,
+        source: TransactionSource,
+        external_ids: &[String],
+    ) -> anyhow::Result<HashSet<String>> {
+        if external_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let sql = "SELECT external_id FROM transactions WHERE user_id = $1 AND source = $2::transaction_source AND external_id = ANY($3)";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(source.to_string())
+                .bind(external_ids)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| row.try_get::<String, _>("external_id").map_err(Into::into))
+            .collect()
+    }
+
+    /// One of the user's transactions, None when there is no such transaction or it is deleted
+    pub async fn get_transaction(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<transaction::TransactionQuery>> {
+        let sql =
+            "SELECT * FROM transactions WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql).bind(id).bind(user_id).fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(|row| map_row_to_transaction(Some(row))).transpose()
+    }
+
+    /// The user's transactions created or updated after the cursor, in cursor order
+    /// Deleted transactions are synced as tombstones instead. Changes from the last second are left for the next sync, a write that started
+    /// earlier may still be committing with an older timestamp
+    pub async fn get_changed_transactions(
+        pool: &DbPool,
+        user_id: Uuid,
+        since: SyncCursor,
+        limit: i64,
+    ) -> anyhow::Result<Vec<transaction::TransactionQuery>> {
+        let sql = "SELECT * FROM transactions WHERE user_id = $1 AND deleted_at IS NULL AND (last_updated_at, id) > ($2, $3) AND last_updated_at <= NOW() - INTERVAL '1 second' ORDER BY last_updated_at, id LIMIT $4";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(since.changed_at)
+                .bind(since.id)
+                .bind(limit)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| map_row_to_transaction(Some(row)))
+            .collect()
     }
 
     fn map_row_to_transaction(row: Option<PgRow>) -> anyhow::Result<transaction::TransactionQuery> {
@@ -123,26 +681,43 @@ pub mod transaction_queries {
                         ));
                     }
                 };
-                let description: String = row.try_get("description")?;
+                let description: String = crypto::decrypt_field(row.try_get("description")?)?;
+                let encrypted_description: Option<String> = row.try_get("encrypted_description")?;
+                let source: TransactionSource = row.try_get("source")?;
+                let external_id: Option<String> = row.try_get("external_id")?;
+                let bank_account_id: Option<Uuid> = row.try_get("bank_account_id")?;
                 let created_at: DateTime<Utc> = row.try_get("created_at")?;
                 let last_updated_at: DateTime<Utc> = row.try_get("last_updated_at")?;
+                let currency_string: &str = row.try_get("currency")?;
+                let currency = Currency::from_str(currency_string).map_err(|e| anyhow!(e))?;
                 let amount: Decimal = row.try_get("amount")?;
-                return Ok(transaction::TransactionQuery::new(
+                let amount =
+                    Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?;
+                let mut transaction = transaction::TransactionQuery::new(
                     id,
                     user_id,
                     transaction_type,
                     amount,
                     category,
                     description,
+                    encrypted_description,
+                    source,
+                    external_id,
+                    bank_account_id,
                     created_at,
                     last_updated_at,
-                ));
+                );
+                transaction.account_id = row.try_get("account_id")?;
+                transaction.merchant_name = row.try_get("merchant_name")?;
+                transaction.merchant_logo_url = row.try_get("merchant_logo_url")?;
+                transaction.deleted_at = row.try_get("deleted_at")?;
+                Ok(transaction)
             }
-            None => return Err(anyhow!("Provided row is None")),
+            None => Err(anyhow!("Provided row is None")),
         }
     }
 
-    fn push_where_or_and<DB>(query: &mut QueryBuilder<DB>, where_is_inserted: &mut bool) -> ()
+    fn push_where_or_and<DB>(query: &mut QueryBuilder<DB>, where_is_inserted: &mut bool)
     where
         DB: sqlx::Database,
     {
@@ -154,84 +729,4719 @@ pub mod transaction_queries {
         }
     }
 
-    pub async fn get_transactions(
-        pool: &DbPool,
-        user_id: Option<Uuid>,
-        category: Option<TransactionCategory>,
-        transaction_type: Option<TransactionType>,
-        amount_min: Option<Decimal>,
-        amount_max: Option<Decimal>,
-        start_timestamp: Option<DateTime<Utc>>,
-        end_timestamp: Option<DateTime<Utc>>,
-    ) -> anyhow::Result<Vec<transaction::TransactionQuery>> {
-        let mut query = QueryBuilder::new("SELECT * FROM transactions");
+    /// FROM and WHERE clauses selecting the filter's transactions
+    /// Every query over filtered transactions goes through here, so they all filter alike
+    fn push_filter(
+        query: &mut QueryBuilder<'_, Postgres>,
+        filter: &transaction::TransactionFilter,
+    ) {
+        match filter.as_of {
+            // The versions current at that moment stand in for the table
+            Some(as_of) => {
+                query
+                    .push(format!(
+                        " FROM (SELECT {} FROM transaction_history WHERE valid_from <= ",
+                        HISTORY_COLUMNS
+                    ))
+                    .push_bind(as_of)
+                    .push(" AND (valid_to IS NULL OR valid_to > ")
+                    .push_bind(as_of)
+                    .push(")) AS transactions");
+            }
+            None => {
+                query.push(" FROM transactions");
+            }
+        }
         let mut where_is_inserted = false;
-        if let Some(user_id) = user_id {
-            push_where_or_and(&mut query, &mut where_is_inserted);
+        if let Some(user_id) = filter.user_id {
+            push_where_or_and(query, &mut where_is_inserted);
             query.push(" user_id = ").push_bind(user_id);
         }
-        if let Some(category) = category {
-            push_where_or_and(&mut query, &mut where_is_inserted);
-            query.push(" category = ").push_bind(category.to_string());
+        if !filter.categories.is_empty() {
+            let categories: Vec<String> =
+                filter.categories.iter().map(ToString::to_string).collect();
+            push_where_or_and(query, &mut where_is_inserted);
+            query
+                .push(" category = ANY(")
+                .push_bind(categories)
+                .push(")");
         }
-        if let Some(transaction_type) = transaction_type {
-            push_where_or_and(&mut query, &mut where_is_inserted);
+        if !filter.transaction_types.is_empty() {
+            let types: Vec<String> = filter
+                .transaction_types
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            push_where_or_and(query, &mut where_is_inserted);
             query
-                .push(" transaction_type = ")
-                .push_bind(transaction_type);
+                .push(" transaction_type::text = ANY(")
+                .push_bind(types)
+                .push(")");
         }
-        if let Some(start_timestamp) = start_timestamp {
-            push_where_or_and(&mut query, &mut where_is_inserted);
+        if let Some(start_timestamp) = filter.start {
+            push_where_or_and(query, &mut where_is_inserted);
             query.push(" created_at >= ").push_bind(start_timestamp);
         }
-
-        if let Some(end_timestamp) = end_timestamp {
-            push_where_or_and(&mut query, &mut where_is_inserted);
+        if let Some(end_timestamp) = filter.end {
+            push_where_or_and(query, &mut where_is_inserted);
             query.push(" created_at <= ").push_bind(end_timestamp);
         }
-        if let Some(amount_min) = amount_min {
-            push_where_or_and(&mut query, &mut where_is_inserted);
+        if let Some(amount_min) = filter.amount_min {
+            push_where_or_and(query, &mut where_is_inserted);
             query.push(" amount >= ").push_bind(amount_min);
         }
-        if let Some(amount_max) = amount_max {
-            push_where_or_and(&mut query, &mut where_is_inserted);
+        if let Some(amount_max) = filter.amount_max {
+            push_where_or_and(query, &mut where_is_inserted);
             query.push(" amount <= ").push_bind(amount_max);
         }
+        if !filter.include_deleted && filter.as_of.is_none() {
+            push_where_or_and(query, &mut where_is_inserted);
+            query.push(" deleted_at IS NULL");
+        }
+    }
+
+    /// The filter for the slow query log, with amounts redacted
+    fn describe_filter(filter: &transaction::TransactionFilter) -> String {
+        format!(
+            "user_id={:?} categories={:?} transaction_types={:?} amount_min={:?} amount_max={:?} start={:?} end={:?} as_of={:?} include_deleted={:?} limit={:?} offset={:?} sort={:?}",
+            filter.user_id,
+            filter
+                .categories
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            filter.transaction_types,
+            filter.amount_min.map(redact::amount),
+            filter.amount_max.map(redact::amount),
+            filter.start,
+            filter.end,
+            filter.as_of,
+            filter.include_deleted,
+            filter.limit,
+            filter.offset,
+            filter.sort
+        )
+    }
+
+    fn list_query(filter: &transaction::TransactionFilter) -> QueryBuilder<'_, Postgres> {
+        let mut query = QueryBuilder::new("SELECT *");
+        push_filter(&mut query, filter);
+        query.push(" ORDER BY ").push(filter.sort.order_by());
+        if let Some(limit) = filter.limit {
+            query.push(" LIMIT ").push_bind(limit);
+        }
+        if let Some(offset) = filter.offset {
+            query.push(" OFFSET ").push_bind(offset);
+        }
+        query
+    }
+
+    /// Pagination and order of the filter do not apply to sums
+    fn sum_query(filter: &transaction::TransactionFilter) -> QueryBuilder<'_, Postgres> {
+        let mut query =
+            QueryBuilder::new("SELECT transaction_type, currency, SUM(amount) AS total");
+        push_filter(&mut query, filter);
+        query.push(" GROUP BY transaction_type, currency");
+        query
+    }
+
+    /// Pagination and order of the filter do not apply to counts
+    fn count_query(filter: &transaction::TransactionFilter) -> QueryBuilder<'_, Postgres> {
+        let mut query = QueryBuilder::new("SELECT COUNT(*) AS total");
+        push_filter(&mut query, filter);
+        query
+    }
+
+    /// Number of transactions matching the filter, whatever page of them is listed
+    pub async fn count_transactions(
+        pool: &DbPool,
+        filter: &transaction::TransactionFilter,
+    ) -> anyhow::Result<i64> {
+        let mut query = count_query(filter);
+        let query = query.build();
+        let sql = query.sql();
+        let row = telemetry::observe_query(sql, || describe_filter(filter), query.fetch_one(pool))
+            .await?;
+        Ok(row.try_get("total")?)
+    }
+
+    /// Transactions matching the filter, in its order
+    pub async fn get_transactions(
+        pool: &DbPool,
+        filter: &transaction::TransactionFilter,
+    ) -> anyhow::Result<Vec<transaction::TransactionQuery>> {
+        let mut query = list_query(filter);
         let query = query.build();
-        println!("transaction query build {}", query.sql());
-        let transactions = query.fetch_all(pool).await?;
-        let trans = transactions
+        let sql = query.sql();
+        println!("transaction query build {}", sql);
+        let transactions =
+            telemetry::observe_query(sql, || describe_filter(filter), query.fetch_all(pool))
+                .await?;
+        transactions
             .into_iter()
             .map(|r| map_row_to_transaction(Some(r)))
-            .collect::<anyhow::Result<Vec<transaction::TransactionQuery>>>();
-        return trans;
+            .collect::<anyhow::Result<Vec<transaction::TransactionQuery>>>()
     }
 
+    /// Income, expenses and net total (income minus expenses) per currency of the
+    /// transactions matching the filter, added up in the database
+    /// Relies on amounts being stored signed by transaction type, so a plain sum is the balance
     pub async fn get_user_transaction_sum(
+        pool: &DbPool,
+        filter: &transaction::TransactionFilter,
+    ) -> anyhow::Result<transaction::TransactionSum> {
+        let mut query = sum_query(filter);
+        let query = query.build();
+        let sql = query.sql();
+        let rows = telemetry::observe_query(sql, || describe_filter(filter), query.fetch_all(pool))
+            .await?;
+
+        let mut total_sum = transaction::TransactionSum::default();
+        for row in rows {
+            let transaction_type: transaction::TransactionType = row.try_get("transaction_type")?;
+            let currency: &str = row.try_get("currency")?;
+            let currency = Currency::from_str(currency).map_err(|e| anyhow!(e))?;
+            let total: Decimal = row.try_get("total")?;
+            let total = Money::from_decimal_rounded(total, currency).map_err(|e| anyhow!(e))?;
+            total_sum.add(&transaction_type, total);
+        }
+
+        Ok(total_sum)
+    }
+
+    /// Income, expenses and net total per currency of the transactions matching the filter,
+    /// for each category with transactions, by category
+    pub async fn get_category_sums(
+        pool: &DbPool,
+        filter: &transaction::TransactionFilter,
+    ) -> anyhow::Result<Vec<transaction::CategorySum>> {
+        let mut query =
+            QueryBuilder::new("SELECT category, transaction_type, currency, SUM(amount) AS total");
+        push_filter(&mut query, filter);
+        query.push(" GROUP BY 1, 2, 3");
+        let query = query.build();
+        let sql = query.sql();
+        let rows = telemetry::observe_query(sql, || describe_filter(filter), query.fetch_all(pool))
+            .await?;
+
+        let mut categories: BTreeMap<String, transaction::TransactionSum> = BTreeMap::new();
+        for row in rows {
+            let transaction_type: transaction::TransactionType = row.try_get("transaction_type")?;
+            let currency: &str = row.try_get("currency")?;
+            let currency = Currency::from_str(currency).map_err(|e| anyhow!(e))?;
+            let total: Decimal = row.try_get("total")?;
+            let total = Money::from_decimal_rounded(total, currency).map_err(|e| anyhow!(e))?;
+            categories
+                .entry(row.try_get("category")?)
+                .or_default()
+                .add(&transaction_type, total);
+        }
+        categories
+            .into_iter()
+            .map(|(category, sum)| {
+                Ok(transaction::CategorySum {
+                    category: TransactionCategory::from_str(&category).map_err(|e| anyhow!(e))?,
+                    sum,
+                })
+            })
+            .collect()
+    }
+
+    /// Move transactions of one user to another, all of them or those of `ids`, with their
+    /// attachments. Links to accounts and bank accounts, which are the previous user's, are
+    /// cleared. Transactions in a reconciled period and those with a description encrypted
+    /// with the previous user's key are left where they are
+    pub async fn reassign_transactions(
+        pool: &DbPool,
+        from_user_id: Uuid,
+        to_user_id: Uuid,
+        ids: Option<&[Uuid]>,
+    ) -> anyhow::Result<transaction::ReassignOutcome> {
+        let mut tx = pool.begin().await?;
+        let sql = "UPDATE transactions t SET user_id = $2, account_id = NULL, bank_account_id = NULL, last_updated_at = NOW() WHERE t.user_id = $1 AND ($3::UUID[] IS NULL OR t.id = ANY($3)) AND t.encrypted_description IS NULL AND NOT EXISTS (SELECT 1 FROM reconciliation_locks l WHERE l.bank_account_id = t.bank_account_id AND t.created_at >= l.period_start AND t.created_at < l.period_end) RETURNING t.id";
+        let moved: Vec<Uuid> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(from_user_id)
+                .bind(to_user_id)
+                .bind(ids)
+                .fetch_all(&mut *tx),
+        )
+        .await?;
+
+        let sql = "UPDATE attachments SET user_id = $2 WHERE transaction_id = ANY($1)";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(&moved)
+                .bind(to_user_id)
+                .execute(&mut *tx),
+        )
+        .await?;
+
+        let sql = "SELECT COUNT(*) AS left_behind FROM transactions WHERE user_id = $1 AND ($2::UUID[] IS NULL OR id = ANY($2))";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(from_user_id)
+                .bind(ids)
+                .fetch_one(&mut *tx),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(transaction::ReassignOutcome {
+            moved: moved.len() as u64,
+            left: row.try_get("left_behind")?,
+        })
+    }
+
+    /// Ids and users of the transactions still in Other or Uncategorized that merchant
+    /// enrichment may categorize: not deleted, without a merchant and with a plaintext
+    /// description, of one user or everyone
+    pub async fn get_recategorizable(
+        pool: &DbPool,
+        user_id: Option<Uuid>,
+    ) -> anyhow::Result<Vec<(Uuid, Uuid)>> {
+        let sql = "SELECT id, user_id FROM transactions WHERE ($1::UUID IS NULL OR user_id = $1) AND category = ANY($2) AND deleted_at IS NULL AND merchant_name IS NULL AND encrypted_description IS NULL ORDER BY created_at, id";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(
+                    [
+                        TransactionCategory::Other,
+                        TransactionCategory::Uncategorized,
+                    ]
+                    .map(|category| category.to_string()),
+                )
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| Ok((row.try_get("id")?, row.try_get("user_id")?)))
+            .collect()
+    }
+
+    /// Sums of the filtered transactions in buckets of the granularity, by the bucket's first
+    /// day in `tz`, buckets without transactions left out
+    pub async fn get_timeseries(
+        pool: &DbPool,
+        filter: &transaction::TransactionFilter,
+        granularity: LimitPeriod,
+        tz: Tz,
+    ) -> anyhow::Result<BTreeMap<NaiveDate, transaction::TransactionSum>> {
+        let mut query = QueryBuilder::new("SELECT date_trunc(");
+        query
+            .push_bind(granularity.to_string())
+            .push(", created_at AT TIME ZONE ")
+            .push_bind(tz.name().to_string())
+            .push(")::DATE AS bucket, transaction_type, currency, SUM(amount) AS total");
+        push_filter(&mut query, filter);
+        query.push(" GROUP BY 1, 2, 3");
+        let query = query.build();
+        let sql = query.sql();
+        let rows = telemetry::observe_query(sql, || describe_filter(filter), query.fetch_all(pool))
+            .await?;
+
+        let mut buckets: BTreeMap<NaiveDate, transaction::TransactionSum> = BTreeMap::new();
+        for row in rows {
+            let transaction_type: transaction::TransactionType = row.try_get("transaction_type")?;
+            let currency: &str = row.try_get("currency")?;
+            let currency = Currency::from_str(currency).map_err(|e| anyhow!(e))?;
+            let total: Decimal = row.try_get("total")?;
+            let total = Money::from_decimal_rounded(total, currency).map_err(|e| anyhow!(e))?;
+            buckets
+                .entry(row.try_get("bucket")?)
+                .or_default()
+                .add(&transaction_type, total);
+        }
+        Ok(buckets)
+    }
+
+    /// The user's expenses per category, currency and month from `start` to `end` (excluded),
+    /// months in the user's time zone
+    pub async fn get_monthly_category_spend(
         pool: &DbPool,
         user_id: Uuid,
-        category: Option<TransactionCategory>,
-        transaction_type: Option<TransactionType>,
-        start_timestamp: Option<DateTime<Utc>>,
-        end_timestamp: Option<DateTime<Utc>>,
-    ) -> anyhow::Result<Decimal> {
-        let mut total_sum = Decimal::from(0);
-        let transactions = get_transactions(
-            pool,
-            Some(user_id),
-            category,
-            transaction_type,
-            None,
-            None,
-            start_timestamp,
-            end_timestamp,
+        timezone: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<transaction::CategoryMonthSpend>> {
+        let sql = "SELECT category, currency, date_trunc('month', created_at AT TIME ZONE $2)::DATE AS month, -SUM(amount) AS spent FROM transactions WHERE user_id = $1 AND deleted_at IS NULL AND transaction_type = 'Expense' AND created_at >= $3 AND created_at < $4 GROUP BY 1, 2, 3 ORDER BY 1, 2, 3";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(timezone)
+                .bind(start)
+                .bind(end)
+                .fetch_all(pool),
         )
         .await?;
 
-        for tr in transactions.iter() {
-            total_sum += tr.amount;
+        rows.into_iter()
+            .map(|row| {
+                let category: &str = row.try_get("category")?;
+                let currency: &str = row.try_get("currency")?;
+                let currency = Currency::from_str(currency).map_err(|e| anyhow!(e))?;
+                let spent: Decimal = row.try_get("spent")?;
+                Ok(transaction::CategoryMonthSpend {
+                    category: TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?,
+                    month: row.try_get("month")?,
+                    spent: Money::from_decimal_rounded(spent, currency).map_err(|e| anyhow!(e))?,
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::models::transaction_models::{
+            TransactionFilter, TransactionSort, TransactionSum,
+        };
+
+        /// WHERE clause of a query, what the listing and the sum must agree on
+        fn where_clause(sql: &str) -> &str {
+            let start = sql.find(" WHERE").unwrap_or(sql.len());
+            let end = [" ORDER BY", " GROUP BY"]
+                .iter()
+                .filter_map(|clause| sql.find(clause))
+                .min()
+                .unwrap_or(sql.len());
+            &sql[start..end]
+        }
+
+        fn amount_ranges() -> Vec<(Option<Decimal>, Option<Decimal>)> {
+            let min = Some(Decimal::new(-5000, 2));
+            let max = Some(Decimal::new(2500, 2));
+            vec![(None, None), (min, None), (None, max), (min, max)]
+        }
+
+        #[test]
+        fn sums_honor_every_amount_range_the_listing_does() {
+            let user_id = Uuid::nil();
+            for (min, max) in amount_ranges() {
+                let filter = TransactionFilter::for_user(user_id).amount_range(min, max);
+                let list = list_query(&filter);
+                let sum = sum_query(&filter);
+                assert_eq!(where_clause(list.sql()), where_clause(sum.sql()));
+                assert_eq!(sum.sql().contains("amount >= "), min.is_some());
+                assert_eq!(sum.sql().contains("amount <= "), max.is_some());
+            }
+        }
+
+        #[test]
+        fn amount_ranges_combine_with_the_other_filters() {
+            let start = DateTime::<Utc>::UNIX_EPOCH;
+            for (min, max) in amount_ranges() {
+                let filter = TransactionFilter::for_user(Uuid::nil())
+                    .categories([TransactionCategory::Groceries])
+                    .transaction_types([transaction::TransactionType::Expense])
+                    .period(Some(start), None)
+                    .amount_range(min, max);
+                let sum = sum_query(&filter);
+                let expected = match (min, max) {
+                    (None, None) => "",
+                    (Some(_), None) => " AND amount >= $5",
+                    (None, Some(_)) => " AND amount <= $5",
+                    (Some(_), Some(_)) => " AND amount >= $5 AND amount <= $6",
+                };
+                assert_eq!(
+                    where_clause(sum.sql()),
+                    format!(
+                        " WHERE user_id = $1 AND category = ANY($2) AND transaction_type::text = ANY($3) AND created_at >= $4{} AND deleted_at IS NULL",
+                        expected
+                    )
+                );
+            }
+        }
+
+        #[test]
+        fn amount_ranges_apply_to_past_versions() {
+            let filter = TransactionFilter::default()
+                .amount_range(Some(Decimal::ONE), Some(Decimal::TEN))
+                .at(Some(DateTime::<Utc>::UNIX_EPOCH));
+            let sum = sum_query(&filter);
+            assert!(
+                sum.sql()
+                    .contains("FROM transaction_history WHERE valid_from <= $1")
+            );
+            assert_eq!(
+                where_clause(sum.sql().rsplit(") AS transactions").next().unwrap()),
+                " WHERE amount >= $3 AND amount <= $4"
+            );
+        }
+
+        #[test]
+        fn deleted_transactions_are_left_out_unless_included() {
+            let filter = TransactionFilter::for_user(Uuid::nil());
+            for query in [
+                list_query(&filter),
+                sum_query(&filter),
+                count_query(&filter),
+            ] {
+                assert_eq!(
+                    where_clause(query.sql()),
+                    " WHERE user_id = $1 AND deleted_at IS NULL"
+                );
+            }
+            let filter = filter.include_deleted(true);
+            assert_eq!(
+                where_clause(sum_query(&filter).sql()),
+                " WHERE user_id = $1"
+            );
+            // Past versions are never deleted ones
+            let filter = TransactionFilter::default().at(Some(DateTime::<Utc>::UNIX_EPOCH));
+            assert!(!sum_query(&filter).sql().contains("deleted_at IS NULL"));
+        }
+
+        #[test]
+        fn sums_and_counts_ignore_pagination_and_order() {
+            let filter = TransactionFilter::for_user(Uuid::nil())
+                .amount_range(Some(Decimal::ONE), None)
+                .page(Some(10), Some(20))
+                .sort(TransactionSort::AmountDesc);
+            let sum = sum_query(&filter);
+            let count = count_query(&filter);
+            assert_eq!(where_clause(sum.sql()), where_clause(count.sql()));
+            assert!(!count.sql().contains("LIMIT"));
+            assert!(!sum.sql().contains("LIMIT"));
+            assert!(!sum.sql().contains("OFFSET"));
+            assert!(!sum.sql().contains("ORDER BY"));
+            let list = list_query(&filter);
+            assert!(
+                list.sql()
+                    .ends_with(" ORDER BY amount DESC, id DESC LIMIT $3 OFFSET $4")
+            );
+        }
+
+        #[test]
+        fn sums_keep_types_and_currencies_apart() {
+            let mut sum = TransactionSum::default();
+            sum.add(
+                &transaction::TransactionType::Income,
+                Money::new(10_000, Currency::USD),
+            );
+            sum.add(
+                &transaction::TransactionType::Expense,
+                Money::new(-2_550, Currency::USD),
+            );
+            sum.add(
+                &transaction::TransactionType::Expense,
+                Money::new(-700, Currency::EUR),
+            );
+            assert_eq!(sum.income.to_vec(), vec![Money::new(10_000, Currency::USD)]);
+            assert_eq!(
+                sum.expenses.to_vec(),
+                vec![
+                    Money::new(2_550, Currency::USD),
+                    Money::new(700, Currency::EUR)
+                ]
+            );
+            assert_eq!(
+                sum.net.to_vec(),
+                vec![
+                    Money::new(7_450, Currency::USD),
+                    Money::new(-700, Currency::EUR)
+                ]
+            );
         }
+    }
+}
 
-        return Ok(total_sum);
+pub mod telegram_queries {
+    use crate::database::DbPool;
+    use crate::telemetry;
+    use chrono::{DateTime, Utc};
+    use uuid::Uuid;
+
+    /// Store a new link code for the user, replacing any earlier unused one
+    pub async fn set_link_code(
+        pool: &DbPool,
+        user_id: Uuid,
+        code: &str,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO telegram_links (user_id, link_code, link_code_expires_at) VALUES ($1, $2, $3)
+             ON CONFLICT (user_id) DO UPDATE SET link_code = EXCLUDED.link_code, link_code_expires_at = EXCLUDED.link_code_expires_at";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(code)
+                .bind(expires_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Link the chat to the user holding this unexpired code, returning the user id
+    /// A chat is only ever linked to one user, an earlier link of the same chat is dropped
+    pub async fn link_chat(
+        pool: &DbPool,
+        code: &str,
+        chat_id: i64,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let mut tx = pool.begin().await?;
+        let sql = "UPDATE telegram_links SET chat_id = NULL WHERE chat_id = $1";
+        telemetry::observe(sql, sqlx::query(sql).bind(chat_id).execute(&mut *tx)).await?;
+
+        let sql = "UPDATE telegram_links SET chat_id = $2, link_code = NULL, link_code_expires_at = NULL, linked_at = NOW()
+             WHERE link_code = $1 AND link_code_expires_at > NOW() RETURNING user_id";
+        let user_id: Option<Uuid> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(code)
+                .bind(chat_id)
+                .fetch_optional(&mut *tx),
+        )
+        .await?;
+
+        // Leave an existing link alone when the code was wrong
+        if user_id.is_some() {
+            tx.commit().await?;
+        }
+        Ok(user_id)
+    }
+
+    /// The user linked to a chat
+    pub async fn get_chat_user(pool: &DbPool, chat_id: i64) -> anyhow::Result<Option<Uuid>> {
+        let sql = "SELECT user_id FROM telegram_links WHERE chat_id = $1";
+        Ok(telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql).bind(chat_id).fetch_optional(pool),
+        )
+        .await?)
+    }
+
+    /// Chat linked to the user, None when they have not linked one
+    pub async fn get_user_chat(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Option<i64>> {
+        let sql = "SELECT chat_id FROM telegram_links WHERE user_id = $1 AND chat_id IS NOT NULL";
+        Ok(telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql).bind(user_id).fetch_optional(pool),
+        )
+        .await?)
+    }
+}
+
+pub mod bank_account_queries {
+    use crate::database::DbPool;
+    use crate::models::bank_account_models::{self as bank_account, BankSyncStatus, MaskedIban};
+    use crate::telemetry;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use uuid::Uuid;
+
+    const BANK_ACCOUNT_COLUMNS: &str = "id, user_id, institution, name, iban, last4, sync_status, sync_error, last_synced_at, statement_day, created_at, updated_at";
+
+    pub async fn create_bank_account(
+        pool: &DbPool,
+        account: &bank_account::BankAccountCreate,
+    ) -> anyhow::Result<bank_account::BankAccountQuery> {
+        let sql = format!(
+            "INSERT INTO bank_accounts (user_id, institution, name, iban, last4) VALUES ($1, $2, $3, $4, $5) RETURNING {BANK_ACCOUNT_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(account.user_id)
+                .bind(&account.institution)
+                .bind(&account.name)
+                .bind(account.iban.as_ref().map(MaskedIban::expose))
+                .bind(&account.last4)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_bank_account(row)
+    }
+
+    fn map_row_to_bank_account(row: PgRow) -> anyhow::Result<bank_account::BankAccountQuery> {
+        let iban: Option<String> = row.try_get("iban")?;
+        Ok(bank_account::BankAccountQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            institution: row.try_get("institution")?,
+            name: row.try_get("name")?,
+            iban: iban.map(MaskedIban::new),
+            last4: row.try_get("last4")?,
+            sync_status: row.try_get("sync_status")?,
+            sync_error: row.try_get("sync_error")?,
+            last_synced_at: row.try_get("last_synced_at")?,
+            statement_day: row.try_get("statement_day")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    pub async fn get_bank_account(
+        pool: &DbPool,
+        id: Uuid,
+    ) -> anyhow::Result<Option<bank_account::BankAccountQuery>> {
+        let sql = format!("SELECT {BANK_ACCOUNT_COLUMNS} FROM bank_accounts WHERE id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_bank_account).transpose()
+    }
+
+    /// Set the day of the month statements start on, None for calendar months
+    pub async fn update_statement_day(
+        pool: &DbPool,
+        id: Uuid,
+        statement_day: Option<i16>,
+    ) -> anyhow::Result<Option<bank_account::BankAccountQuery>> {
+        let sql = format!(
+            "UPDATE bank_accounts SET statement_day = $2, updated_at = NOW() WHERE id = $1 RETURNING {BANK_ACCOUNT_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(statement_day)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_bank_account).transpose()
+    }
+
+    pub async fn get_bank_accounts(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Vec<bank_account::BankAccountQuery>> {
+        let sql = format!(
+            "SELECT {BANK_ACCOUNT_COLUMNS} FROM bank_accounts WHERE user_id = $1 ORDER BY created_at"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_bank_account).collect()
+    }
+
+    /// Record the outcome of a sync attempt
+    /// A successful (Active) sync also bumps last_synced_at and clears the previous error
+    pub async fn update_sync_status(
+        pool: &DbPool,
+        id: Uuid,
+        status: BankSyncStatus,
+        sync_error: Option<&str>,
+    ) -> anyhow::Result<Option<bank_account::BankAccountQuery>> {
+        let sql = format!(
+            "UPDATE bank_accounts SET sync_status = $2::bank_sync_status, sync_error = $3,
+                last_synced_at = CASE WHEN $2 = 'Active' THEN NOW() ELSE last_synced_at END,
+                updated_at = NOW()
+             WHERE id = $1 RETURNING {BANK_ACCOUNT_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(status.to_string())
+                .bind(sync_error)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_bank_account).transpose()
+    }
+}
+
+pub mod encryption_queries {
+    use crate::crypto::FieldCipher;
+    use crate::database::DbPool;
+    use crate::models::user_models::normalize_email;
+    use sqlx::Row;
+    use uuid::Uuid;
+
+    /// Rows fetched per round trip while re-encrypting
+    const BATCH_SIZE: i64 = 500;
+
+    /// Rewrite every encrypted column that is plaintext or under a retired key with the active key
+    /// Safe to run repeatedly and concurrently with normal traffic; returns the number of rows updated
+    pub async fn reencrypt_all(pool: &DbPool, cipher: &FieldCipher) -> anyhow::Result<u64> {
+        let mut updated = 0;
+
+        // Users: email plus its blind index
+        let mut last_id = Uuid::nil();
+        loop {
+            let rows =
+                sqlx::query("SELECT id, email FROM users WHERE id > $1 ORDER BY id LIMIT $2")
+                    .bind(last_id)
+                    .bind(BATCH_SIZE)
+                    .fetch_all(pool)
+                    .await?;
+            let Some(last) = rows.last() else { break };
+            last_id = last.try_get("id")?;
+
+            for row in rows {
+                let stored: String = row.try_get("email")?;
+                // Emails encrypted before they were lowercased are rewritten with the
+                // blind index of the lowercased email, which lookups use
+                let decrypted = cipher.decrypt(&stored)?;
+                let email = normalize_email(&decrypted);
+                if !cipher.needs_reencryption(&stored) && email == decrypted {
+                    continue;
+                }
+                sqlx::query(
+                    "UPDATE users SET email = $2, email_hash = $3 WHERE id = $1 AND email = $4",
+                )
+                .bind(row.try_get::<Uuid, _>("id")?)
+                .bind(cipher.encrypt(&email)?)
+                .bind(cipher.blind_index(&email))
+                .bind(&stored)
+                .execute(pool)
+                .await?;
+                updated += 1;
+            }
+        }
+
+        // Transactions: description
+        let mut last_id = Uuid::nil();
+        loop {
+            let rows = sqlx::query("SELECT id, description FROM transactions WHERE id > $1 AND description IS NOT NULL ORDER BY id LIMIT $2")
+                .bind(last_id)
+                .bind(BATCH_SIZE)
+                .fetch_all(pool)
+                .await?;
+            let Some(last) = rows.last() else { break };
+            last_id = last.try_get("id")?;
+
+            for row in rows {
+                let stored: String = row.try_get("description")?;
+                if !cipher.needs_reencryption(&stored) {
+                    continue;
+                }
+                let description = cipher.decrypt(&stored)?;
+                // Only overwrite if the row was not changed in the meantime
+                sqlx::query(
+                    "UPDATE transactions SET description = $2 WHERE id = $1 AND description = $3",
+                )
+                .bind(row.try_get::<Uuid, _>("id")?)
+                .bind(cipher.encrypt(&description)?)
+                .bind(&stored)
+                .execute(pool)
+                .await?;
+                updated += 1;
+            }
+        }
+
+        // Past versions of transactions: description
+        let mut last_id = 0i64;
+        loop {
+            let rows = sqlx::query("SELECT history_id, description FROM transaction_history WHERE history_id > $1 AND description IS NOT NULL ORDER BY history_id LIMIT $2")
+                .bind(last_id)
+                .bind(BATCH_SIZE)
+                .fetch_all(pool)
+                .await?;
+            let Some(last) = rows.last() else { break };
+            last_id = last.try_get("history_id")?;
+
+            for row in rows {
+                let stored: String = row.try_get("description")?;
+                if !cipher.needs_reencryption(&stored) {
+                    continue;
+                }
+                let description = cipher.decrypt(&stored)?;
+                sqlx::query(
+                    "UPDATE transaction_history SET description = $2 WHERE history_id = $1 AND description = $3",
+                )
+                .bind(row.try_get::<i64, _>("history_id")?)
+                .bind(cipher.encrypt(&description)?)
+                .bind(&stored)
+                .execute(pool)
+                .await?;
+                updated += 1;
+            }
+        }
+
+        Ok(updated)
+    }
+}
+
+pub mod audit_queries {
+    use crate::database::DbPool;
+    use crate::models::audit_models::{
+        AuditEntryCreate, AuditEntryQuery, AuditVerification, GENESIS_HASH,
+    };
+    use crate::telemetry;
+    use chrono::{DateTime, SubsecRound, Utc};
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+
+    /// Arbitrary key for the advisory lock serializing appends to the chain
+    const AUDIT_CHAIN_LOCK: i64 = 0x0061_7564_6974;
+
+    /// Rows fetched per round trip while verifying
+    const VERIFY_BATCH_SIZE: i64 = 1000;
+
+    fn map_row_to_audit_entry(row: PgRow) -> anyhow::Result<AuditEntryQuery> {
+        Ok(AuditEntryQuery {
+            seq: row.try_get("seq")?,
+            occurred_at: row.try_get("occurred_at")?,
+            actor_id: row.try_get("actor_id")?,
+            action: row.try_get("action")?,
+            entity_type: row.try_get("entity_type")?,
+            entity_id: row.try_get("entity_id")?,
+            details: row.try_get("details")?,
+            prev_hash: row.try_get("prev_hash")?,
+            hash: row.try_get("hash")?,
+        })
+    }
+
+    /// Append an entry to the end of the chain
+    pub async fn append(
+        pool: &DbPool,
+        entry: &AuditEntryCreate,
+    ) -> anyhow::Result<AuditEntryQuery> {
+        let mut tx = pool.begin().await?;
+
+        // Only one writer may extend the chain at a time, otherwise two entries
+        // could point at the same predecessor
+        let sql = "SELECT pg_advisory_xact_lock($1)";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql).bind(AUDIT_CHAIN_LOCK).execute(&mut *tx),
+        )
+        .await?;
+
+        let sql = "SELECT seq, hash FROM audit_log ORDER BY seq DESC LIMIT 1";
+        let last = telemetry::observe(sql, sqlx::query(sql).fetch_optional(&mut *tx)).await?;
+        let (prev_seq, prev_hash) = match last {
+            Some(row) => (row.try_get::<i64, _>("seq")?, row.try_get("hash")?),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+
+        // Postgres keeps microseconds, round now so the hash survives the round trip
+        let occurred_at: DateTime<Utc> = Utc::now().trunc_subsecs(6);
+        let mut stored = AuditEntryQuery {
+            seq: prev_seq + 1,
+            occurred_at,
+            actor_id: entry.actor_id,
+            action: entry.action.clone(),
+            entity_type: entry.entity_type.clone(),
+            entity_id: entry.entity_id,
+            details: entry.details.to_string(),
+            prev_hash,
+            hash: String::new(),
+        };
+        stored.hash = stored.compute_hash();
+
+        let sql = "INSERT INTO audit_log (seq, occurred_at, actor_id, action, entity_type, entity_id, details, prev_hash, hash) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(stored.seq)
+                .bind(stored.occurred_at)
+                .bind(stored.actor_id)
+                .bind(&stored.action)
+                .bind(&stored.entity_type)
+                .bind(stored.entity_id)
+                .bind(&stored.details)
+                .bind(&stored.prev_hash)
+                .bind(&stored.hash)
+                .execute(&mut *tx),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(stored)
+    }
+
+    /// Walk the chain from the start, recomputing every hash
+    /// Detects edited entries, deleted entries (gaps in seq) and re-linked entries
+    pub async fn verify_chain(pool: &DbPool) -> anyhow::Result<AuditVerification> {
+        let mut expected_seq = 1;
+        let mut expected_prev_hash = GENESIS_HASH.to_string();
+        let mut checked = 0;
+
+        loop {
+            let sql = "SELECT seq, occurred_at, actor_id, action, entity_type, entity_id, details, prev_hash, hash FROM audit_log WHERE seq >= $1 ORDER BY seq LIMIT $2";
+            let rows = telemetry::observe(
+                sql,
+                sqlx::query(sql)
+                    .bind(expected_seq)
+                    .bind(VERIFY_BATCH_SIZE)
+                    .fetch_all(pool),
+            )
+            .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let entry = map_row_to_audit_entry(row)?;
+                let problem = if entry.seq != expected_seq {
+                    Some(format!(
+                        "Missing entries: expected seq {}, found {}",
+                        expected_seq, entry.seq
+                    ))
+                } else if entry.prev_hash != expected_prev_hash {
+                    Some("prev_hash does not match the previous entry".to_string())
+                } else if entry.hash != entry.compute_hash() {
+                    Some("Entry content does not match its hash".to_string())
+                } else {
+                    None
+                };
+
+                if let Some(reason) = problem {
+                    return Ok(AuditVerification {
+                        valid: false,
+                        entries_checked: checked,
+                        broken_at_seq: Some(expected_seq),
+                        reason: Some(reason),
+                    });
+                }
+
+                checked += 1;
+                expected_seq += 1;
+                expected_prev_hash = entry.hash;
+            }
+        }
+
+        Ok(AuditVerification {
+            valid: true,
+            entries_checked: checked,
+            broken_at_seq: None,
+            reason: None,
+        })
+    }
+}
+
+pub mod debug_capture_queries {
+    use crate::database::DbPool;
+    use crate::models::debug_capture_models::DebugCaptureCreate;
+
+    pub async fn insert_capture(pool: &DbPool, capture: &DebugCaptureCreate) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO debug_captures
+                (reason, endpoint, query, status, duration_ms, user_agent, request_body, response_body)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(capture.reason)
+        .bind(&capture.endpoint)
+        .bind(&capture.query)
+        .bind(capture.status as i32)
+        .bind(capture.duration_ms)
+        .bind(&capture.user_agent)
+        .bind(&capture.request_body)
+        .bind(&capture.response_body)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod pending_queries {
+    use crate::crypto;
+    use crate::database::DbPool;
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::pending_models::{
+        PendingStatus, PendingTransactionCreate, PendingTransactionQuery,
+    };
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const PENDING_COLUMNS: &str = "id, user_id, transaction_type, amount, currency, category, description, occurred_at, source_ref, parser, status, transaction_id, created_at, resolved_at";
+
+    /// Store a parsed email, returning its id, or None when this email was already ingested for the user
+    pub async fn insert_pending(
+        pool: &DbPool,
+        pending: &PendingTransactionCreate,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let description = pending
+            .description
+            .as_deref()
+            .map(crypto::encrypt_field)
+            .transpose()?;
+        let sql = "INSERT INTO pending_transactions (user_id, transaction_type, amount, currency, category, description, occurred_at, source_ref, parser) VALUES ($1, $2::transaction_type, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (user_id, source_ref) DO NOTHING RETURNING id";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(pending.user_id)
+                .bind(pending.transaction_type.to_string())
+                .bind(pending.amount.to_decimal())
+                .bind(pending.amount.currency.to_string())
+                .bind(pending.category.as_ref().map(|c| c.to_string()))
+                .bind(description)
+                .bind(pending.occurred_at)
+                .bind(&pending.source_ref)
+                .bind(pending.parser)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(|r| r.try_get("id")).transpose().map_err(Into::into)
+    }
+
+    fn map_row_to_pending(row: PgRow) -> anyhow::Result<PendingTransactionQuery> {
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let amount: Decimal = row.try_get("amount")?;
+        let category: Option<&str> = row.try_get("category")?;
+        let description: Option<String> = row.try_get("description")?;
+        let status: &str = row.try_get("status")?;
+
+        Ok(PendingTransactionQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            transaction_type: row.try_get("transaction_type")?,
+            amount: Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+            category: category
+                .map(TransactionCategory::from_str)
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            description: description.map(crypto::decrypt_field).transpose()?,
+            occurred_at: row.try_get("occurred_at")?,
+            source_ref: row.try_get("source_ref")?,
+            parser: row.try_get("parser")?,
+            status: PendingStatus::from_str(status).map_err(|e| anyhow!(e))?,
+            transaction_id: row.try_get("transaction_id")?,
+            created_at: row.try_get("created_at")?,
+            resolved_at: row.try_get("resolved_at")?,
+        })
+    }
+
+    /// A user's pending transactions in one status, newest first
+    pub async fn get_pending(
+        pool: &DbPool,
+        user_id: Uuid,
+        status: PendingStatus,
+    ) -> anyhow::Result<Vec<PendingTransactionQuery>> {
+        let sql = format!(
+            "SELECT {PENDING_COLUMNS} FROM pending_transactions WHERE user_id = $1 AND status = $2 ORDER BY created_at DESC"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(status.to_string())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_pending).collect()
+    }
+
+    pub async fn get_pending_by_id(
+        pool: &DbPool,
+        id: Uuid,
+    ) -> anyhow::Result<Option<PendingTransactionQuery>> {
+        let sql = format!("SELECT {PENDING_COLUMNS} FROM pending_transactions WHERE id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_pending).transpose()
+    }
+
+    /// Move a pending transaction out of review
+    /// Returns false when it was no longer pending, e.g. confirmed by a concurrent request
+    pub async fn resolve_pending(
+        pool: &DbPool,
+        id: Uuid,
+        status: PendingStatus,
+        transaction_id: Option<Uuid>,
+    ) -> anyhow::Result<bool> {
+        let sql = "UPDATE pending_transactions SET status = $2, transaction_id = $3, resolved_at = NOW() WHERE id = $1 AND status = 'pending'";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(status.to_string())
+                .bind(transaction_id)
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+pub mod webhook_queries {
+    use crate::crypto;
+    use crate::database::DbPool;
+    use crate::models::webhook_models::{
+        DeliveryStatus, DueDelivery, WebhookDeliveryQuery, WebhookEndpointQuery,
+    };
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use serde_json::Value;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const DELIVERY_COLUMNS: &str = "d.id, d.endpoint_id, d.event, d.payload, d.status, d.attempts, d.next_attempt_at, d.last_status_code, d.last_error, d.created_at, d.delivered_at";
+
+    pub async fn create_endpoint(
+        pool: &DbPool,
+        user_id: Uuid,
+        url: &str,
+        secret: &str,
+    ) -> anyhow::Result<WebhookEndpointQuery> {
+        let sql = "INSERT INTO webhook_endpoints (user_id, url, secret) VALUES ($1, $2, $3) RETURNING id, user_id, url, created_at";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(url)
+                .bind(crypto::encrypt_field(secret)?)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_endpoint(row)
+    }
+
+    fn map_row_to_endpoint(row: PgRow) -> anyhow::Result<WebhookEndpointQuery> {
+        Ok(WebhookEndpointQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            url: row.try_get("url")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    pub async fn get_endpoints(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Vec<WebhookEndpointQuery>> {
+        let sql = "SELECT id, user_id, url, created_at FROM webhook_endpoints WHERE user_id = $1 ORDER BY created_at";
+        let rows = telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_endpoint).collect()
+    }
+
+    /// Remove an endpoint with its deliveries, returns false when it did not exist
+    pub async fn delete_endpoint(pool: &DbPool, id: Uuid) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM webhook_endpoints WHERE id = $1";
+        let result = telemetry::observe(sql, sqlx::query(sql).bind(id).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Queue one delivery of the event to each of the user's endpoints, returning how many were queued
+    pub async fn enqueue(
+        pool: &DbPool,
+        user_id: Uuid,
+        event: &str,
+        payload: &Value,
+    ) -> anyhow::Result<u64> {
+        let sql = "INSERT INTO webhook_deliveries (endpoint_id, event, payload) SELECT id, $2, $3 FROM webhook_endpoints WHERE user_id = $1";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(event)
+                .bind(payload)
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Take up to `limit` due deliveries, oldest first
+    /// Claimed deliveries are pushed back by `lease_secs` so another worker does not send them
+    /// too; a worker dying mid-delivery therefore only delays it
+    pub async fn claim_due(
+        pool: &DbPool,
+        limit: i64,
+        lease_secs: i64,
+    ) -> anyhow::Result<Vec<DueDelivery>> {
+        let sql = "UPDATE webhook_deliveries d SET next_attempt_at = NOW() + make_interval(secs => $2) FROM webhook_endpoints e WHERE e.id = d.endpoint_id AND d.id IN (SELECT id FROM webhook_deliveries WHERE status = 'pending' AND next_attempt_at <= NOW() ORDER BY next_attempt_at LIMIT $1 FOR UPDATE SKIP LOCKED) RETURNING d.id, e.url, e.secret, d.event, d.payload, d.attempts, d.created_at";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(limit)
+                .bind(lease_secs as f64)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(DueDelivery {
+                    id: row.try_get("id")?,
+                    url: row.try_get("url")?,
+                    secret: crypto::decrypt_field(row.try_get("secret")?)?,
+                    event: row.try_get("event")?,
+                    payload: row.try_get("payload")?,
+                    attempts: row.try_get("attempts")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn mark_delivered(pool: &DbPool, id: Uuid, status_code: u16) -> anyhow::Result<()> {
+        let sql = "UPDATE webhook_deliveries SET status = 'delivered', attempts = attempts + 1, last_status_code = $2, last_error = NULL, delivered_at = NOW() WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(i32::from(status_code))
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt, retried at `retry_at` or moved to failed when there is none
+    pub async fn mark_attempt_failed(
+        pool: &DbPool,
+        id: Uuid,
+        status_code: Option<u16>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE webhook_deliveries SET attempts = attempts + 1, last_status_code = $2, last_error = $3, status = CASE WHEN $4::timestamptz IS NULL THEN 'failed' ELSE 'pending' END, next_attempt_at = COALESCE($4, next_attempt_at) WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(status_code.map(i32::from))
+                .bind(error)
+                .bind(retry_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn map_row_to_delivery(row: PgRow) -> anyhow::Result<WebhookDeliveryQuery> {
+        let status: &str = row.try_get("status")?;
+        Ok(WebhookDeliveryQuery {
+            id: row.try_get("id")?,
+            endpoint_id: row.try_get("endpoint_id")?,
+            event: row.try_get("event")?,
+            payload: row.try_get("payload")?,
+            status: DeliveryStatus::from_str(status).map_err(|e| anyhow!(e))?,
+            attempts: row.try_get("attempts")?,
+            next_attempt_at: row.try_get("next_attempt_at")?,
+            last_status_code: row.try_get("last_status_code")?,
+            last_error: row.try_get("last_error")?,
+            created_at: row.try_get("created_at")?,
+            delivered_at: row.try_get("delivered_at")?,
+        })
+    }
+
+    /// Deliveries to a user's endpoints in one status, newest first
+    pub async fn get_deliveries(
+        pool: &DbPool,
+        user_id: Uuid,
+        status: DeliveryStatus,
+    ) -> anyhow::Result<Vec<WebhookDeliveryQuery>> {
+        let sql = format!(
+            "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries d JOIN webhook_endpoints e ON e.id = d.endpoint_id WHERE e.user_id = $1 AND d.status = $2 ORDER BY d.created_at DESC LIMIT 500"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(status.to_string())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_delivery).collect()
+    }
+
+    /// Put a failed delivery back in the queue with a fresh set of attempts
+    /// Returns false when there is no failed delivery with this id
+    pub async fn retry_delivery(pool: &DbPool, id: Uuid) -> anyhow::Result<bool> {
+        let sql = "UPDATE webhook_deliveries SET status = 'pending', attempts = 0, next_attempt_at = NOW() WHERE id = $1 AND status = 'failed'";
+        let result = telemetry::observe(sql, sqlx::query(sql).bind(id).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+pub mod job_queries {
+    use crate::database::DbPool;
+    use crate::models::job_models::{JobCreate, JobQuery, JobStatus};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const JOB_COLUMNS: &str = "id, kind, payload, status, attempts, max_attempts, run_at, locked_until, dedupe_key, last_error, created_at, started_at, finished_at";
+
+    /// Queue a job, returning its id, or None when a job with the same dedupe key is already active
+    pub async fn enqueue(pool: &DbPool, job: &JobCreate) -> anyhow::Result<Option<Uuid>> {
+        let sql = "INSERT INTO jobs (kind, payload, run_at, dedupe_key, max_attempts) VALUES ($1, $2, COALESCE($3, NOW()), $4, $5) ON CONFLICT (kind, dedupe_key) WHERE status IN ('queued', 'running') DO NOTHING RETURNING id";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(&job.kind)
+                .bind(&job.payload)
+                .bind(job.run_at)
+                .bind(&job.dedupe_key)
+                .bind(job.max_attempts)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(|r| r.try_get("id")).transpose().map_err(Into::into)
+    }
+
+    fn map_row_to_job(row: PgRow) -> anyhow::Result<JobQuery> {
+        let status: &str = row.try_get("status")?;
+        let started_at: Option<DateTime<Utc>> = row.try_get("started_at")?;
+        let finished_at: Option<DateTime<Utc>> = row.try_get("finished_at")?;
+        Ok(JobQuery {
+            id: row.try_get("id")?,
+            kind: row.try_get("kind")?,
+            payload: row.try_get("payload")?,
+            status: JobStatus::from_str(status).map_err(|e| anyhow!(e))?,
+            attempts: row.try_get("attempts")?,
+            max_attempts: row.try_get("max_attempts")?,
+            run_at: row.try_get("run_at")?,
+            locked_until: row.try_get("locked_until")?,
+            dedupe_key: row.try_get("dedupe_key")?,
+            last_error: row.try_get("last_error")?,
+            created_at: row.try_get("created_at")?,
+            started_at,
+            finished_at,
+            duration_ms: started_at
+                .map(|started| (finished_at.unwrap_or_else(Utc::now) - started).num_milliseconds()),
+        })
+    }
+
+    /// Take the next due job and hide it from other workers for `visibility_secs`
+    /// Running jobs whose visibility timeout passed are taken again, their worker is assumed gone
+    pub async fn claim(pool: &DbPool, visibility_secs: i64) -> anyhow::Result<Option<JobQuery>> {
+        let sql = format!(
+            "UPDATE jobs SET status = 'running', attempts = attempts + 1, started_at = NOW(), locked_until = NOW() + make_interval(secs => $1) WHERE id = (SELECT id FROM jobs WHERE (status = 'queued' AND run_at <= NOW()) OR (status = 'running' AND locked_until <= NOW()) ORDER BY run_at LIMIT 1 FOR UPDATE SKIP LOCKED) RETURNING {JOB_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(visibility_secs as f64)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_job).transpose()
+    }
+
+    pub async fn complete(pool: &DbPool, id: Uuid) -> anyhow::Result<()> {
+        // A job cancelled while it ran stays cancelled
+        let sql = "UPDATE jobs SET status = 'succeeded', locked_until = NULL, last_error = NULL, finished_at = NOW() WHERE id = $1 AND status = 'running'";
+        telemetry::observe(sql, sqlx::query(sql).bind(id).execute(pool)).await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt, queued again at `retry_at` or failed for good when there is none
+    pub async fn fail(
+        pool: &DbPool,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE jobs SET status = CASE WHEN $3::timestamptz IS NULL THEN 'failed' ELSE 'queued' END, run_at = COALESCE($3, run_at), locked_until = NULL, last_error = $2, finished_at = CASE WHEN $3::timestamptz IS NULL THEN NOW() END WHERE id = $1 AND status = 'running'";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(error)
+                .bind(retry_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Jobs for the admin API, the most recently created first
+    /// Without a status, the ones that are not done yet or need attention are listed
+    pub async fn get_jobs(
+        pool: &DbPool,
+        status: Option<JobStatus>,
+        kind: Option<&str>,
+        limit: i64,
+    ) -> anyhow::Result<Vec<JobQuery>> {
+        let sql = format!(
+            "SELECT {JOB_COLUMNS} FROM jobs WHERE ($1::text IS NULL AND status IN ('queued', 'running', 'failed') OR status = $1) AND ($2::text IS NULL OR kind = $2) ORDER BY created_at DESC LIMIT $3"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(status.map(|s| s.to_string()))
+                .bind(kind)
+                .bind(limit)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_job).collect()
+    }
+
+    /// Number of jobs in each status
+    pub async fn count_by_status(pool: &DbPool) -> anyhow::Result<Vec<(JobStatus, i64)>> {
+        let sql = "SELECT status, COUNT(*) AS count FROM jobs GROUP BY status ORDER BY status";
+        let rows = telemetry::observe(sql, sqlx::query(sql).fetch_all(pool)).await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let status: &str = row.try_get("status")?;
+                Ok((
+                    JobStatus::from_str(status).map_err(|e| anyhow!(e))?,
+                    row.try_get("count")?,
+                ))
+            })
+            .collect()
+    }
+
+    pub async fn get_job(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<JobQuery>> {
+        let sql = format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_job).transpose()
+    }
+
+    /// Queue a failed or cancelled job again with a fresh set of attempts
+    /// Returns None when it is not failed or cancelled, or a job with the same dedupe key is active
+    pub async fn retry(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<JobQuery>> {
+        let sql = format!(
+            "UPDATE jobs SET status = 'queued', attempts = 0, run_at = NOW(), locked_until = NULL, started_at = NULL, finished_at = NULL WHERE id = $1 AND status IN ('failed', 'cancelled') AND NOT EXISTS (SELECT 1 FROM jobs active WHERE active.kind = jobs.kind AND active.dedupe_key = jobs.dedupe_key AND active.status IN ('queued', 'running')) RETURNING {JOB_COLUMNS}"
+        );
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_job).transpose()
+    }
+
+    /// Cancel a queued or running job, a running one is left to finish but its outcome is ignored
+    /// Returns None when it is no longer queued or running
+    pub async fn cancel(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<JobQuery>> {
+        let sql = format!(
+            "UPDATE jobs SET status = 'cancelled', locked_until = NULL, finished_at = NOW() WHERE id = $1 AND status IN ('queued', 'running') RETURNING {JOB_COLUMNS}"
+        );
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_job).transpose()
+    }
+
+    /// Delete succeeded jobs finished before `before`, failed ones are kept for inspection
+    pub async fn prune_succeeded(pool: &DbPool, before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let sql = "DELETE FROM jobs WHERE status = 'succeeded' AND finished_at < $1";
+        let result = telemetry::observe(sql, sqlx::query(sql).bind(before).execute(pool)).await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+pub mod schedule_queries {
+    use crate::database::DbPool;
+    use crate::models::job_models::JobScheduleQuery;
+    use crate::telemetry;
+    use chrono::{DateTime, Utc};
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+
+    /// Create or update the schedule of a job kind
+    /// The next run is only moved when the cron expression changed, restarts keep their place
+    pub async fn upsert_schedule(
+        pool: &DbPool,
+        kind: &str,
+        cron: &str,
+        next_run_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO job_schedules (kind, cron, next_run_at) VALUES ($1, $2, $3) ON CONFLICT (kind) DO UPDATE SET cron = EXCLUDED.cron, next_run_at = EXCLUDED.next_run_at, updated_at = NOW() WHERE job_schedules.cron <> EXCLUDED.cron";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(kind)
+                .bind(cron)
+                .bind(next_run_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn map_row_to_schedule(row: PgRow) -> anyhow::Result<JobScheduleQuery> {
+        Ok(JobScheduleQuery {
+            kind: row.try_get("kind")?,
+            cron: row.try_get("cron")?,
+            payload: row.try_get("payload")?,
+            enabled: row.try_get("enabled")?,
+            next_run_at: row.try_get("next_run_at")?,
+            last_enqueued_at: row.try_get("last_enqueued_at")?,
+        })
+    }
+
+    /// Enabled schedules whose next run has come
+    pub async fn get_due(pool: &DbPool) -> anyhow::Result<Vec<JobScheduleQuery>> {
+        let sql = "SELECT kind, cron, payload, enabled, next_run_at, last_enqueued_at FROM job_schedules WHERE enabled AND next_run_at <= NOW()";
+        let rows = telemetry::observe(sql, sqlx::query(sql).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_schedule).collect()
+    }
+
+    pub async fn get_schedules(pool: &DbPool) -> anyhow::Result<Vec<JobScheduleQuery>> {
+        let sql = "SELECT kind, cron, payload, enabled, next_run_at, last_enqueued_at FROM job_schedules ORDER BY kind";
+        let rows = telemetry::observe(sql, sqlx::query(sql).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_schedule).collect()
+    }
+
+    /// Move a schedule from the run at `current` to `next`
+    /// Returns false when another server moved it first, that server then queues the run
+    pub async fn advance(
+        pool: &DbPool,
+        kind: &str,
+        current: DateTime<Utc>,
+        next: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let sql = "UPDATE job_schedules SET next_run_at = $3, last_enqueued_at = NOW() WHERE kind = $1 AND next_run_at = $2";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(kind)
+                .bind(current)
+                .bind(next)
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+pub mod sync_queries {
+    use crate::database::DbPool;
+    use crate::models::sync_models::{SyncCursor, Tombstone};
+    use crate::telemetry;
+    use sqlx::Row;
+    use uuid::Uuid;
+
+    /// Whether the row was deleted, so an offline edit of it must not bring it back
+    pub async fn is_deleted(pool: &DbPool, entity_type: &str, id: Uuid) -> anyhow::Result<bool> {
+        let sql = "SELECT 1 FROM sync_tombstones WHERE entity_type = $1 AND entity_id = $2";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(entity_type)
+                .bind(id)
+                .fetch_optional(pool),
+        )
+        .await?;
+        Ok(row.is_some())
+    }
+
+    /// Deletions of the user's rows of `entity_type` after the cursor, up to `until` when given
+    /// Like changed rows, deletions from the last second are left for the next sync
+    pub async fn get_tombstones(
+        pool: &DbPool,
+        user_id: Uuid,
+        entity_type: &str,
+        since: SyncCursor,
+        until: Option<SyncCursor>,
+    ) -> anyhow::Result<Vec<Tombstone>> {
+        let sql = "SELECT entity_id, deleted_at FROM sync_tombstones WHERE user_id = $1 AND entity_type = $2 AND (deleted_at, entity_id) > ($3, $4) AND ($5::timestamptz IS NULL OR (deleted_at, entity_id) <= ($5, $6)) AND deleted_at <= NOW() - INTERVAL '1 second' ORDER BY deleted_at, entity_id";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(entity_type)
+                .bind(since.changed_at)
+                .bind(since.id)
+                .bind(until.map(|c| c.changed_at))
+                .bind(until.map(|c| c.id))
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Tombstone {
+                    id: row.try_get("entity_id")?,
+                    deleted_at: row.try_get("deleted_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+// Aggregates for the admin analytics, every statistic is computed in the database and
+// groups (days, categories) with fewer than `min_group_size` distinct users are dropped
+// there too, so no query here ever returns a row about an identifiable user
+pub mod analytics_queries {
+    use crate::database::DbPool;
+    use crate::models::analytics_models::{CategoryUsage, DailyActivity};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use sqlx::Row;
+    use std::str::FromStr;
+
+    /// Distinct users with transactions in [start, end), None when fewer than `min_group_size`
+    pub async fn count_active_users(
+        pool: &DbPool,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_group_size: i64,
+    ) -> anyhow::Result<Option<i64>> {
+        let sql = "SELECT CASE WHEN COUNT(DISTINCT user_id) >= $3 THEN COUNT(DISTINCT user_id) END AS active_users FROM transactions WHERE deleted_at IS NULL AND created_at >= $1 AND created_at < $2";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(start)
+                .bind(end)
+                .bind(min_group_size)
+                .fetch_optional(pool),
+        )
+        .await?;
+        Ok(row
+            .map(|r| r.try_get::<Option<i64>, _>("active_users"))
+            .transpose()?
+            .flatten())
+    }
+
+    /// Users signed up in [start, end), None when fewer than `min_group_size`
+    pub async fn count_new_users(
+        pool: &DbPool,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_group_size: i64,
+    ) -> anyhow::Result<Option<i64>> {
+        let sql = "SELECT CASE WHEN COUNT(*) >= $3 THEN COUNT(*) END AS new_users FROM users WHERE created_at >= $1 AND created_at < $2";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(start)
+                .bind(end)
+                .bind(min_group_size)
+                .fetch_optional(pool),
+        )
+        .await?;
+        Ok(row
+            .map(|r| r.try_get::<Option<i64>, _>("new_users"))
+            .transpose()?
+            .flatten())
+    }
+
+    /// Active users and transactions per UTC day of [start, end), oldest first
+    pub async fn get_daily_activity(
+        pool: &DbPool,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_group_size: i64,
+    ) -> anyhow::Result<Vec<DailyActivity>> {
+        let sql = "SELECT (created_at AT TIME ZONE 'UTC')::date AS day, COUNT(DISTINCT user_id) AS active_users, COUNT(*) AS transactions FROM transactions WHERE deleted_at IS NULL AND created_at >= $1 AND created_at < $2 GROUP BY day HAVING COUNT(DISTINCT user_id) >= $3 ORDER BY day";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(start)
+                .bind(end)
+                .bind(min_group_size)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(DailyActivity {
+                    day: row.try_get("day")?,
+                    active_users: row.try_get("active_users")?,
+                    transactions: row.try_get("transactions")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Transactions and users per type and category in [start, end), most used first
+    pub async fn get_category_usage(
+        pool: &DbPool,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        min_group_size: i64,
+    ) -> anyhow::Result<Vec<CategoryUsage>> {
+        let sql = "SELECT transaction_type, category, COUNT(*) AS transactions, COUNT(DISTINCT user_id) AS users FROM transactions WHERE deleted_at IS NULL AND created_at >= $1 AND created_at < $2 GROUP BY transaction_type, category HAVING COUNT(DISTINCT user_id) >= $3 ORDER BY transactions DESC, category";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(start)
+                .bind(end)
+                .bind(min_group_size)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let category: &str = row.try_get("category")?;
+                Ok(CategoryUsage {
+                    transaction_type: row.try_get("transaction_type")?,
+                    category: FromStr::from_str(category).map_err(|e: String| anyhow!(e))?,
+                    transactions: row.try_get("transactions")?,
+                    users: row.try_get("users")?,
+                })
+            })
+            .collect()
+    }
+}
+
+// Changed transactions for the analytics export, read across all users in
+// (last_updated_at, id) order from each sink's cursor
+pub mod analytics_export_queries {
+    use crate::database::DbPool;
+    use crate::models::analytics_models::{ExportCursor, ExportedTransaction};
+    use crate::models::money_models::Currency;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use sqlx::Row;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Where `sink` left off, the start of time for a sink that never ran
+    pub async fn get_cursor(pool: &DbPool, sink: &str) -> anyhow::Result<ExportCursor> {
+        let sql = "SELECT changed_at, last_id, exported FROM analytics_exports WHERE sink = $1";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(sink).fetch_optional(pool)).await?;
+        match row {
+            Some(row) => Ok(ExportCursor {
+                changed_at: row.try_get("changed_at")?,
+                last_id: row.try_get("last_id")?,
+                exported: row.try_get("exported")?,
+            }),
+            None => Ok(ExportCursor {
+                changed_at: DateTime::<Utc>::UNIX_EPOCH,
+                last_id: Uuid::nil(),
+                exported: 0,
+            }),
+        }
+    }
+
+    /// Transactions changed after the cursor, oldest first
+    /// Changes of the last `settle_secs` are left for the next run: a transaction committed
+    /// late with an earlier last_updated_at would otherwise be skipped over by the cursor
+    pub async fn list_changed(
+        pool: &DbPool,
+        cursor: &ExportCursor,
+        settle_secs: i64,
+        limit: i64,
+    ) -> anyhow::Result<Vec<ExportedTransaction>> {
+        let sql = "SELECT id, user_id, transaction_type, amount, currency, category, source, bank_account_id, created_at, last_updated_at, deleted_at
+            FROM transactions
+            WHERE (last_updated_at, id) > ($1, $2)
+            AND last_updated_at <= NOW() - make_interval(secs => $3)
+            ORDER BY last_updated_at, id
+            LIMIT $4";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(cursor.changed_at)
+                .bind(cursor.last_id)
+                .bind(settle_secs as f64)
+                .bind(limit)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let currency: &str = row.try_get("currency")?;
+                Ok(ExportedTransaction {
+                    id: row.try_get("id")?,
+                    user_id: row.try_get("user_id")?,
+                    transaction_type: row.try_get("transaction_type")?,
+                    amount: row.try_get("amount")?,
+                    currency: Currency::from_str(currency).map_err(|e| anyhow!(e))?,
+                    category: row.try_get("category")?,
+                    source: row.try_get("source")?,
+                    bank_account_id: row.try_get("bank_account_id")?,
+                    created_at: row.try_get("created_at")?,
+                    last_updated_at: row.try_get("last_updated_at")?,
+                    deleted_at: row.try_get("deleted_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Move the cursor of `sink` past transactions it has exported
+    pub async fn advance_cursor(
+        pool: &DbPool,
+        sink: &str,
+        cursor: &ExportCursor,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO analytics_exports (sink, changed_at, last_id, exported, updated_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (sink) DO UPDATE SET
+                changed_at = EXCLUDED.changed_at,
+                last_id = EXCLUDED.last_id,
+                exported = EXCLUDED.exported,
+                updated_at = NOW()";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(sink)
+                .bind(cursor.changed_at)
+                .bind(cursor.last_id)
+                .bind(cursor.exported)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod quota_queries {
+    use crate::database::DbPool;
+    use crate::telemetry;
+    use chrono::NaiveDate;
+    use sqlx::Row;
+    use uuid::Uuid;
+
+    /// Add `amount` to a usage counter unless that takes it over `limit`, in one statement
+    /// so concurrent requests cannot both slip under the limit
+    /// Returns the new count, None when over the limit
+    pub async fn add(
+        pool: &DbPool,
+        user_id: Uuid,
+        metric: &str,
+        period_start: NaiveDate,
+        amount: i64,
+        limit: Option<i64>,
+    ) -> anyhow::Result<Option<i64>> {
+        let sql = "INSERT INTO usage_counters (user_id, metric, period_start, count) SELECT $1, $2, $3, $4 WHERE $5::bigint IS NULL OR $4 <= $5 ON CONFLICT (user_id, metric, period_start) DO UPDATE SET count = usage_counters.count + EXCLUDED.count WHERE $5::bigint IS NULL OR usage_counters.count + EXCLUDED.count <= $5 RETURNING count";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(metric)
+                .bind(period_start)
+                .bind(amount)
+                .bind(limit)
+                .fetch_optional(pool),
+        )
+        .await?;
+        row.map(|row| Ok(row.try_get("count")?)).transpose()
+    }
+
+    /// Current value of a usage counter, 0 when nothing was counted in the period
+    pub async fn get_count(
+        pool: &DbPool,
+        user_id: Uuid,
+        metric: &str,
+        period_start: NaiveDate,
+    ) -> anyhow::Result<i64> {
+        let sql = "SELECT count FROM usage_counters WHERE user_id = $1 AND metric = $2 AND period_start = $3";
+        let count: Option<i64> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(user_id)
+                .bind(metric)
+                .bind(period_start)
+                .fetch_optional(pool),
+        )
+        .await?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Total size of the user's attachments in bytes
+    pub async fn get_storage_bytes(pool: &DbPool, user_id: Uuid) -> anyhow::Result<i64> {
+        let sql = "SELECT COALESCE(SUM(size_bytes), 0)::BIGINT AS bytes FROM attachments WHERE user_id = $1";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_one(pool)).await?;
+        Ok(row.try_get("bytes")?)
+    }
+
+    /// Record that the user was warned about a quota in a period
+    /// False when they already were
+    pub async fn record_warning(
+        pool: &DbPool,
+        user_id: Uuid,
+        metric: &str,
+        period_start: NaiveDate,
+    ) -> anyhow::Result<bool> {
+        let sql = "INSERT INTO quota_warnings (user_id, metric, period_start) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(metric)
+                .bind(period_start)
+                .execute(pool),
+        )
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Take `amount` off a usage counter, never below zero
+    pub async fn subtract(
+        pool: &DbPool,
+        user_id: Uuid,
+        metric: &str,
+        period_start: NaiveDate,
+        amount: i64,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE usage_counters SET count = GREATEST(count - $4, 0) WHERE user_id = $1 AND metric = $2 AND period_start = $3";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(metric)
+                .bind(period_start)
+                .bind(amount)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod billing_queries {
+    use crate::database::DbPool;
+    use crate::models::billing_models::{
+        InvoiceQuery, InvoiceUpdate, SubscriptionQuery, SubscriptionUpdate,
+    };
+    use crate::models::money_models::{Currency, Money};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use sqlx::Row;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    pub async fn get_subscription(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<SubscriptionQuery>> {
+        let sql = "SELECT stripe_customer_id, stripe_subscription_id, plan, status, current_period_end, updated_at FROM subscriptions WHERE user_id = $1";
+        let row =
+            telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_optional(pool)).await?;
+        row.map(|row| {
+            Ok(SubscriptionQuery {
+                stripe_customer_id: row.try_get("stripe_customer_id")?,
+                stripe_subscription_id: row.try_get("stripe_subscription_id")?,
+                plan: row.try_get("plan")?,
+                status: row.try_get("status")?,
+                current_period_end: row.try_get("current_period_end")?,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    /// User a Stripe customer belongs to
+    pub async fn get_user_by_customer(
+        pool: &DbPool,
+        stripe_customer_id: &str,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let sql = "SELECT user_id FROM subscriptions WHERE stripe_customer_id = $1";
+        let user_id = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(stripe_customer_id)
+                .fetch_optional(pool),
+        )
+        .await?;
+        Ok(user_id)
+    }
+
+    /// Store the state of a user's subscription, unless a newer event was already applied
+    /// True when it was stored
+    pub async fn upsert_subscription(
+        pool: &DbPool,
+        user_id: Uuid,
+        update: &SubscriptionUpdate,
+    ) -> anyhow::Result<bool> {
+        let sql = "INSERT INTO subscriptions (user_id, stripe_customer_id, stripe_subscription_id, plan, status, current_period_end, last_event_at) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (user_id) DO UPDATE SET stripe_customer_id = EXCLUDED.stripe_customer_id, stripe_subscription_id = COALESCE(EXCLUDED.stripe_subscription_id, subscriptions.stripe_subscription_id), plan = EXCLUDED.plan, status = EXCLUDED.status, current_period_end = COALESCE(EXCLUDED.current_period_end, subscriptions.current_period_end), last_event_at = EXCLUDED.last_event_at, updated_at = NOW() WHERE subscriptions.last_event_at <= EXCLUDED.last_event_at";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(&update.stripe_customer_id)
+                .bind(&update.stripe_subscription_id)
+                .bind(&update.plan)
+                .bind(&update.status)
+                .bind(update.current_period_end)
+                .bind(update.event_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Store the state of an invoice, unless a newer event about it was already applied
+    pub async fn upsert_invoice(
+        pool: &DbPool,
+        user_id: Uuid,
+        invoice: &InvoiceUpdate,
+    ) -> anyhow::Result<bool> {
+        let sql = "INSERT INTO invoices (user_id, stripe_invoice_id, stripe_subscription_id, number, status, total, amount_paid, currency, hosted_invoice_url, invoice_pdf_url, period_start, period_end, paid_at, issued_at, last_event_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) ON CONFLICT (stripe_invoice_id) DO UPDATE SET number = COALESCE(EXCLUDED.number, invoices.number), status = EXCLUDED.status, total = EXCLUDED.total, amount_paid = EXCLUDED.amount_paid, hosted_invoice_url = COALESCE(EXCLUDED.hosted_invoice_url, invoices.hosted_invoice_url), invoice_pdf_url = COALESCE(EXCLUDED.invoice_pdf_url, invoices.invoice_pdf_url), paid_at = COALESCE(EXCLUDED.paid_at, invoices.paid_at), last_event_at = EXCLUDED.last_event_at, updated_at = NOW() WHERE invoices.last_event_at <= EXCLUDED.last_event_at";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(&invoice.stripe_invoice_id)
+                .bind(&invoice.stripe_subscription_id)
+                .bind(&invoice.number)
+                .bind(&invoice.status)
+                .bind(invoice.total.minor_units)
+                .bind(invoice.amount_paid.minor_units)
+                .bind(invoice.total.currency.to_string())
+                .bind(&invoice.hosted_invoice_url)
+                .bind(&invoice.invoice_pdf_url)
+                .bind(invoice.period_start)
+                .bind(invoice.period_end)
+                .bind(invoice.paid_at)
+                .bind(invoice.issued_at)
+                .bind(invoice.event_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The user's invoices, newest first, drafts left out
+    pub async fn get_invoices(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Vec<InvoiceQuery>> {
+        let sql = "SELECT id, number, status, total, amount_paid, currency, hosted_invoice_url, invoice_pdf_url, period_start, period_end, paid_at, issued_at FROM invoices WHERE user_id = $1 AND status <> 'draft' ORDER BY issued_at DESC";
+        let rows = telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let currency: &str = row.try_get("currency")?;
+                let currency = Currency::from_str(currency).map_err(|e| anyhow!(e))?;
+                Ok(InvoiceQuery {
+                    id: row.try_get("id")?,
+                    number: row.try_get("number")?,
+                    status: row.try_get("status")?,
+                    total: Money::new(row.try_get("total")?, currency),
+                    amount_paid: Money::new(row.try_get("amount_paid")?, currency),
+                    hosted_invoice_url: row.try_get("hosted_invoice_url")?,
+                    invoice_pdf_url: row.try_get("invoice_pdf_url")?,
+                    period_start: row.try_get("period_start")?,
+                    period_end: row.try_get("period_end")?,
+                    paid_at: row.try_get("paid_at")?,
+                    issued_at: row.try_get("issued_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+pub mod sharing_queries {
+    use crate::crypto;
+    use crate::database::DbPool;
+    use crate::events::{self, ChangeEvent, ChangeKind};
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::sharing_models::{
+        AccountActivity, AccountOverview, ApprovalPolicy, ApprovalStatus, TransactionApprovalQuery,
+        WalletMemberQuery, WalletOverview, WalletRole,
+    };
+    use crate::models::transaction_models::{TransactionCategory, TransactionCreate};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use rust_decimal::Decimal;
+    use serde_json::json;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const APPROVAL_COLUMNS: &str = "id, owner_id, requested_by, transaction_type, amount, currency, category, description, encrypted_description, source, external_id, account_id, status, reason, transaction_id, created_at, resolved_at";
+
+    /// Share the owner's wallet with a user, or change their role when already shared
+    pub async fn upsert_member(
+        pool: &DbPool,
+        owner_id: Uuid,
+        member_id: Uuid,
+        role: WalletRole,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO wallet_members (owner_id, member_id, role) VALUES ($1, $2, $3) ON CONFLICT (owner_id, member_id) DO UPDATE SET role = EXCLUDED.role, updated_at = NOW()";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(owner_id)
+                .bind(member_id)
+                .bind(role.to_string())
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Stop sharing the owner's wallet with a user, returns false when it was not shared with them
+    pub async fn delete_member(
+        pool: &DbPool,
+        owner_id: Uuid,
+        member_id: Uuid,
+    ) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM wallet_members WHERE owner_id = $1 AND member_id = $2";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(owner_id)
+                .bind(member_id)
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn get_members(
+        pool: &DbPool,
+        owner_id: Uuid,
+    ) -> anyhow::Result<Vec<WalletMemberQuery>> {
+        let sql = "SELECT m.member_id, u.email, m.role, m.created_at, m.updated_at FROM wallet_members m JOIN users u ON u.id = m.member_id WHERE m.owner_id = $1 ORDER BY m.created_at";
+        let rows = telemetry::observe(sql, sqlx::query(sql).bind(owner_id).fetch_all(pool)).await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let role: &str = row.try_get("role")?;
+                Ok(WalletMemberQuery {
+                    member_id: row.try_get("member_id")?,
+                    email: crypto::decrypt_field(row.try_get("email")?)?,
+                    role: WalletRole::from_str(role).map_err(|e| anyhow!(e))?,
+                    created_at: row.try_get("created_at")?,
+                    updated_at: row.try_get("updated_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Role of a user in the owner's wallet, None when it is not shared with them
+    pub async fn get_role(
+        pool: &DbPool,
+        owner_id: Uuid,
+        member_id: Uuid,
+    ) -> anyhow::Result<Option<WalletRole>> {
+        let sql = "SELECT role FROM wallet_members WHERE owner_id = $1 AND member_id = $2";
+        let role: Option<String> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(owner_id)
+                .bind(member_id)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        role.map(|r| WalletRole::from_str(&r).map_err(|e| anyhow!(e)))
+            .transpose()
+    }
+
+    /// The user's own wallet, then those shared with them, each with its bank accounts and the
+    /// count, balance per currency and latest date of their transactions, in a single query
+    pub async fn get_wallet_overviews(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Vec<WalletOverview>> {
+        // One row per wallet, account (NULL for transactions without one) and currency
+        let sql = "WITH w AS (
+                SELECT $1::UUID AS owner_id, NULL::VARCHAR AS role, 0 AS position
+                UNION ALL
+                SELECT owner_id, role, 1 FROM wallet_members WHERE member_id = $1
+            ), accounts AS (
+                SELECT w.owner_id, a.id AS account_id, a.institution, a.name, a.last4, a.sync_status, a.created_at
+                FROM w JOIN bank_accounts a ON a.user_id = w.owner_id
+                UNION ALL
+                SELECT w.owner_id, NULL, NULL, NULL, NULL, NULL::bank_sync_status, NULL FROM w
+            )
+            SELECT w.owner_id, w.role, u.name AS owner_name, acc.account_id, acc.institution, acc.name, acc.last4, acc.sync_status,
+                t.currency, COUNT(t.id) AS transactions, COALESCE(SUM(t.amount), 0) AS total, MAX(t.created_at) AS last_transaction_at
+            FROM w
+            JOIN users u ON u.id = w.owner_id
+            JOIN accounts acc ON acc.owner_id = w.owner_id
+            LEFT JOIN transactions t ON t.user_id = w.owner_id AND t.deleted_at IS NULL
+                AND (t.bank_account_id = acc.account_id OR (acc.account_id IS NULL AND t.bank_account_id IS NULL))
+            GROUP BY w.owner_id, w.role, w.position, u.name, acc.account_id, acc.institution, acc.name, acc.last4, acc.sync_status, acc.created_at, t.currency
+            ORDER BY w.position, u.name, w.owner_id, acc.created_at NULLS LAST, acc.account_id, t.currency";
+        let rows = telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_all(pool)).await?;
+
+        let mut wallets: Vec<WalletOverview> = Vec::new();
+        for row in rows {
+            let owner_id: Uuid = row.try_get("owner_id")?;
+            if wallets
+                .last()
+                .is_none_or(|wallet| wallet.owner_id != owner_id)
+            {
+                let role: Option<&str> = row.try_get("role")?;
+                wallets.push(WalletOverview {
+                    owner_id,
+                    owner_name: row.try_get("owner_name")?,
+                    role: role
+                        .map(|r| WalletRole::from_str(r).map_err(|e| anyhow!(e)))
+                        .transpose()?,
+                    activity: AccountActivity::default(),
+                    accounts: Vec::new(),
+                    unassigned: AccountActivity::default(),
+                });
+            }
+            let Some(wallet) = wallets.last_mut() else {
+                continue;
+            };
+
+            let account_id: Option<Uuid> = row.try_get("account_id")?;
+            let activity = match account_id {
+                Some(id) => {
+                    if wallet
+                        .accounts
+                        .last()
+                        .is_none_or(|account| account.id != id)
+                    {
+                        wallet.accounts.push(AccountOverview {
+                            id,
+                            institution: row.try_get("institution")?,
+                            name: row.try_get("name")?,
+                            last4: row.try_get("last4")?,
+                            sync_status: row.try_get("sync_status")?,
+                            activity: AccountActivity::default(),
+                        });
+                    }
+                    match wallet.accounts.last_mut() {
+                        Some(account) => &mut account.activity,
+                        None => continue,
+                    }
+                }
+                None => &mut wallet.unassigned,
+            };
+
+            // Accounts without transactions come with a single row without a currency
+            let Some(currency) = row.try_get::<Option<&str>, _>("currency")? else {
+                continue;
+            };
+            let currency = Currency::from_str(currency).map_err(|e| anyhow!(e))?;
+            let total = Money::from_decimal_rounded(row.try_get("total")?, currency)
+                .map_err(|e| anyhow!(e))?;
+            let transactions: i64 = row.try_get("transactions")?;
+            let last_transaction_at = row.try_get("last_transaction_at")?;
+            for activity in [activity, &mut wallet.activity] {
+                activity.transactions += transactions;
+                activity.balance.add(total);
+                activity.last_transaction_at =
+                    activity.last_transaction_at.max(last_transaction_at);
+            }
+        }
+        Ok(wallets)
+    }
+
+    pub async fn get_policy(
+        pool: &DbPool,
+        owner_id: Uuid,
+    ) -> anyhow::Result<Option<ApprovalPolicy>> {
+        let sql = "SELECT threshold, currency FROM approval_policies WHERE owner_id = $1";
+        let row =
+            telemetry::observe(sql, sqlx::query(sql).bind(owner_id).fetch_optional(pool)).await?;
+
+        row.map(|row| {
+            let threshold: Decimal = row.try_get("threshold")?;
+            let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+            Ok(ApprovalPolicy {
+                threshold: Money::from_decimal_rounded(threshold, currency)
+                    .map_err(|e| anyhow!(e))?,
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn set_policy(
+        pool: &DbPool,
+        owner_id: Uuid,
+        policy: &ApprovalPolicy,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO approval_policies (owner_id, threshold, currency) VALUES ($1, $2, $3) ON CONFLICT (owner_id) DO UPDATE SET threshold = EXCLUDED.threshold, currency = EXCLUDED.currency, updated_at = NOW()";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(owner_id)
+                .bind(policy.threshold.to_decimal())
+                .bind(policy.threshold.currency.to_string())
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false when the owner had no policy
+    pub async fn delete_policy(pool: &DbPool, owner_id: Uuid) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM approval_policies WHERE owner_id = $1";
+        let result = telemetry::observe(sql, sqlx::query(sql).bind(owner_id).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Hold a transaction an editor recorded in `transaction.user_id`'s wallet for approval
+    pub async fn insert_approval(
+        pool: &DbPool,
+        requested_by: Uuid,
+        transaction: &TransactionCreate,
+    ) -> anyhow::Result<Uuid> {
+        let sql = "INSERT INTO transaction_approvals (owner_id, requested_by, transaction_type, amount, currency, category, description, encrypted_description, source, external_id, account_id) VALUES ($1, $2, $3::transaction_type, $4, $5, $6, $7, $8, $9::transaction_source, $10, $11) RETURNING id";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(transaction.user_id)
+                .bind(requested_by)
+                .bind(transaction.transaction_type.to_string())
+                .bind(transaction.amount.to_decimal())
+                .bind(transaction.amount.currency.to_string())
+                .bind(transaction.category.to_string())
+                .bind(crypto::encrypt_field(&transaction.description)?)
+                .bind(&transaction.encrypted_description)
+                .bind(transaction.source.to_string())
+                .bind(&transaction.external_id)
+                .bind(transaction.account_id)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        Ok(row.try_get("id")?)
+    }
+
+    fn map_row_to_approval(row: PgRow) -> anyhow::Result<TransactionApprovalQuery> {
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let amount: Decimal = row.try_get("amount")?;
+        let category: &str = row.try_get("category")?;
+        let description: Option<String> = row.try_get("description")?;
+        let status: &str = row.try_get("status")?;
+
+        Ok(TransactionApprovalQuery {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            requested_by: row.try_get("requested_by")?,
+            transaction_type: row.try_get("transaction_type")?,
+            amount: Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+            category: TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?,
+            description: description
+                .map(crypto::decrypt_field)
+                .transpose()?
+                .filter(|d| !d.is_empty()),
+            encrypted_description: row.try_get("encrypted_description")?,
+            source: row.try_get("source")?,
+            external_id: row.try_get("external_id")?,
+            account_id: row.try_get("account_id")?,
+            status: ApprovalStatus::from_str(status).map_err(|e| anyhow!(e))?,
+            reason: row.try_get("reason")?,
+            transaction_id: row.try_get("transaction_id")?,
+            created_at: row.try_get("created_at")?,
+            resolved_at: row.try_get("resolved_at")?,
+        })
+    }
+
+    /// Transactions held for the owner's approval in one status, newest first
+    pub async fn get_approvals(
+        pool: &DbPool,
+        owner_id: Uuid,
+        status: ApprovalStatus,
+    ) -> anyhow::Result<Vec<TransactionApprovalQuery>> {
+        let sql = format!(
+            "SELECT {APPROVAL_COLUMNS} FROM transaction_approvals WHERE owner_id = $1 AND status = $2 ORDER BY created_at DESC"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(owner_id)
+                .bind(status.to_string())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_approval).collect()
+    }
+
+    pub async fn get_approval_by_id(
+        pool: &DbPool,
+        id: Uuid,
+    ) -> anyhow::Result<Option<TransactionApprovalQuery>> {
+        let sql = format!("SELECT {APPROVAL_COLUMNS} FROM transaction_approvals WHERE id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_approval).transpose()
+    }
+
+    /// Approve a pending transaction, recording it in the owner's wallet as of when the editor did
+    /// Returns None when it was no longer pending, e.g. approved by a concurrent request, and
+    /// Some(None) when the owner already has a transaction with its external id
+    pub async fn approve(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Option<Uuid>>> {
+        let mut tx = pool.begin().await?;
+        let sql = "UPDATE transaction_approvals SET status = 'approved', resolved_at = NOW() WHERE id = $1 AND status = 'pending'";
+        let claimed = telemetry::observe(sql, sqlx::query(sql).bind(id).execute(&mut *tx)).await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let sql = "INSERT INTO transactions (user_id, transaction_type, amount, currency, category, description, encrypted_description, source, external_id, account_id, created_at) SELECT owner_id, transaction_type, amount, currency, category, description, encrypted_description, source, external_id, account_id, created_at FROM transaction_approvals WHERE id = $1 ON CONFLICT (user_id, source, external_id) DO NOTHING RETURNING id";
+        let transaction_id: Option<Uuid> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql).bind(id).fetch_optional(&mut *tx),
+        )
+        .await?;
+
+        let sql = "UPDATE transaction_approvals SET transaction_id = $2 WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(transaction_id)
+                .execute(&mut *tx),
+        )
+        .await?;
+        tx.commit().await?;
+
+        if let Some(transaction_id) = transaction_id
+            && let Some(approval) = get_approval_by_id(pool, id).await?
+        {
+            let event = ChangeEvent::new(
+                ChangeKind::Created,
+                transaction_id,
+                approval.owner_id,
+                json!({
+                    "id": transaction_id,
+                    "transaction_type": approval.transaction_type,
+                    "amount": approval.amount,
+                    "category": approval.category,
+                    "source": approval.source,
+                }),
+            )
+            .source(approval.source);
+            events::publish(pool, event).await;
+        }
+        Ok(Some(transaction_id))
+    }
+
+    /// Reject a pending transaction
+    /// Returns false when it was no longer pending
+    pub async fn reject(pool: &DbPool, id: Uuid, reason: Option<&str>) -> anyhow::Result<bool> {
+        let sql = "UPDATE transaction_approvals SET status = 'rejected', reason = $2, resolved_at = NOW() WHERE id = $1 AND status = 'pending'";
+        let result =
+            telemetry::observe(sql, sqlx::query(sql).bind(id).bind(reason).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+pub mod limit_queries {
+    use crate::database::DbPool;
+    use crate::models::limit_models::{LimitPeriod, SpendingLimitQuery};
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::transaction_models::{TransactionCategory, local_midnight};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Duration, NaiveDate, Utc};
+    use chrono_tz::Tz;
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const LIMIT_COLUMNS: &str =
+        "id, user_id, category, period, amount, currency, allow_override, created_at, updated_at";
+
+    fn map_row_to_limit(row: PgRow) -> anyhow::Result<SpendingLimitQuery> {
+        let category: Option<&str> = row.try_get("category")?;
+        let period: &str = row.try_get("period")?;
+        let amount: Decimal = row.try_get("amount")?;
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+
+        Ok(SpendingLimitQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            category: category
+                .map(TransactionCategory::from_str)
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            period: LimitPeriod::from_str(period).map_err(|e| anyhow!(e))?,
+            amount: Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+            allow_override: row.try_get("allow_override")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Set the user's limit for a category (or all expenses), period and currency
+    pub async fn upsert_limit(
+        pool: &DbPool,
+        user_id: Uuid,
+        category: Option<&TransactionCategory>,
+        period: LimitPeriod,
+        amount: Money,
+        allow_override: bool,
+    ) -> anyhow::Result<SpendingLimitQuery> {
+        let sql = format!(
+            "INSERT INTO spending_limits (user_id, category, period, amount, currency, allow_override) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (user_id, category, period, currency) DO UPDATE SET amount = EXCLUDED.amount, allow_override = EXCLUDED.allow_override, updated_at = NOW() RETURNING {LIMIT_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(category.map(|c| c.to_string()))
+                .bind(period.to_string())
+                .bind(amount.to_decimal())
+                .bind(amount.currency.to_string())
+                .bind(allow_override)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_limit(row)
+    }
+
+    pub async fn get_limits(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Vec<SpendingLimitQuery>> {
+        let sql = format!(
+            "SELECT {LIMIT_COLUMNS} FROM spending_limits WHERE user_id = $1 ORDER BY created_at"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_limit).collect()
+    }
+
+    /// Limits an expense of the user in this category and currency counts towards
+    pub async fn get_applicable_limits(
+        pool: &DbPool,
+        user_id: Uuid,
+        category: &TransactionCategory,
+        currency: Currency,
+    ) -> anyhow::Result<Vec<SpendingLimitQuery>> {
+        let sql = format!(
+            "SELECT {LIMIT_COLUMNS} FROM spending_limits WHERE user_id = $1 AND (category IS NULL OR category = $2) AND currency = $3"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(category.to_string())
+                .bind(currency.to_string())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_limit).collect()
+    }
+
+    /// Remove a limit, returning the user it belonged to, None when it did not exist
+    pub async fn delete_limit(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let sql = "DELETE FROM spending_limits WHERE id = $1 RETURNING user_id";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(|r| r.try_get("user_id"))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// What the user spent since `since` in this currency, in one category or all of them
+    pub async fn get_spent(
+        pool: &DbPool,
+        user_id: Uuid,
+        category: Option<&TransactionCategory>,
+        currency: Currency,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Money> {
+        let sql = "SELECT COALESCE(-SUM(amount), 0) AS spent FROM transactions WHERE user_id = $1 AND deleted_at IS NULL AND transaction_type = 'Expense' AND ($2::VARCHAR IS NULL OR category = $2) AND currency = $3 AND created_at >= $4";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(category.map(|c| c.to_string()))
+                .bind(currency.to_string())
+                .bind(since)
+                .fetch_one(pool),
+        )
+        .await?;
+        let spent: Decimal = row.try_get("spent")?;
+
+        Money::from_decimal_rounded(spent, currency).map_err(|e| anyhow!(e))
+    }
+
+    /// What the user spent in this currency each day from `first_day` to `last_day` in `tz`,
+    /// in one category or all of them, days without spending left out
+    pub async fn get_daily_spent(
+        pool: &DbPool,
+        user_id: Uuid,
+        category: Option<&TransactionCategory>,
+        currency: Currency,
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+        tz: Tz,
+    ) -> anyhow::Result<HashMap<NaiveDate, Money>> {
+        let sql = "SELECT (created_at AT TIME ZONE $6)::DATE AS day, -SUM(amount) AS spent FROM transactions WHERE user_id = $1 AND deleted_at IS NULL AND transaction_type = 'Expense' AND ($2::VARCHAR IS NULL OR category = $2) AND currency = $3 AND created_at >= $4 AND created_at < $5 GROUP BY day";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(category.map(|c| c.to_string()))
+                .bind(currency.to_string())
+                .bind(local_midnight(first_day, tz))
+                .bind(local_midnight(last_day + Duration::days(1), tz))
+                .bind(tz.name())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let spent: Decimal = row.try_get("spent")?;
+                let spent = Money::from_decimal_rounded(spent, currency).map_err(|e| anyhow!(e))?;
+                Ok((row.try_get("day")?, spent))
+            })
+            .collect()
+    }
+}
+
+pub mod family_queries {
+    use crate::crypto;
+    use crate::database::DbPool;
+    use crate::models::family_models::ChildAccountQuery;
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const CHILD_COLUMNS: &str = "c.child_id, c.parent_id, u.email, c.allowance, c.currency, c.allowed_categories, c.created_at, c.updated_at";
+
+    fn map_row_to_child(row: PgRow) -> anyhow::Result<ChildAccountQuery> {
+        let allowance: Option<Decimal> = row.try_get("allowance")?;
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let allowed_categories: Option<Vec<String>> = row.try_get("allowed_categories")?;
+
+        Ok(ChildAccountQuery {
+            child_id: row.try_get("child_id")?,
+            parent_id: row.try_get("parent_id")?,
+            email: crypto::decrypt_field(row.try_get("email")?)?,
+            allowance: allowance
+                .map(|a| Money::from_decimal_rounded(a, currency))
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            allowed_categories: allowed_categories
+                .map(|categories| {
+                    categories
+                        .iter()
+                        .map(|c| TransactionCategory::from_str(c))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Link a child to the parent, or update their allowance and categories
+    /// Returns false when the child is already linked to another parent
+    pub async fn upsert_child(
+        pool: &DbPool,
+        parent_id: Uuid,
+        child_id: Uuid,
+        allowance: Option<Money>,
+        allowed_categories: Option<&[TransactionCategory]>,
+    ) -> anyhow::Result<bool> {
+        let sql = "INSERT INTO child_accounts (child_id, parent_id, allowance, currency, allowed_categories) VALUES ($1, $2, $3, COALESCE($4, 'USD'), $5) ON CONFLICT (child_id) DO UPDATE SET allowance = EXCLUDED.allowance, currency = EXCLUDED.currency, allowed_categories = EXCLUDED.allowed_categories, updated_at = NOW() WHERE child_accounts.parent_id = EXCLUDED.parent_id";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(child_id)
+                .bind(parent_id)
+                .bind(allowance.map(Money::to_decimal))
+                .bind(allowance.map(|a| a.currency.to_string()))
+                .bind(allowed_categories.map(|categories| {
+                    categories
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                }))
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Unlink a child, returns false when it was not a child of the parent
+    pub async fn delete_child(
+        pool: &DbPool,
+        parent_id: Uuid,
+        child_id: Uuid,
+    ) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM child_accounts WHERE parent_id = $1 AND child_id = $2";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(parent_id)
+                .bind(child_id)
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn get_children(
+        pool: &DbPool,
+        parent_id: Uuid,
+    ) -> anyhow::Result<Vec<ChildAccountQuery>> {
+        let sql = format!(
+            "SELECT {CHILD_COLUMNS} FROM child_accounts c JOIN users u ON u.id = c.child_id WHERE c.parent_id = $1 ORDER BY c.created_at"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(parent_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_child).collect()
+    }
+
+    /// The user's link to their parent, None when they are not a child account
+    pub async fn get_child(
+        pool: &DbPool,
+        child_id: Uuid,
+    ) -> anyhow::Result<Option<ChildAccountQuery>> {
+        let sql = format!(
+            "SELECT {CHILD_COLUMNS} FROM child_accounts c JOIN users u ON u.id = c.child_id WHERE c.child_id = $1"
+        );
+        let row =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(child_id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_child).transpose()
+    }
+
+    /// Children with an allowance
+    pub async fn get_allowances(pool: &DbPool) -> anyhow::Result<Vec<ChildAccountQuery>> {
+        let sql = format!(
+            "SELECT {CHILD_COLUMNS} FROM child_accounts c JOIN users u ON u.id = c.child_id WHERE c.allowance IS NOT NULL"
+        );
+        let rows = telemetry::observe(&sql, sqlx::query(&sql).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_child).collect()
+    }
+}
+
+pub mod savings_queries {
+    use crate::database::DbPool;
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::savings_models::{RoundUpMonth, RoundUpRuleQuery, SavingsGoalQuery};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const GOAL_COLUMNS: &str = "g.id, g.user_id, g.name, g.target, g.currency, g.created_at, g.updated_at, (SELECT COALESCE(SUM(u.amount), 0) FROM round_ups u WHERE u.goal_id = g.id) AS balance";
+
+    fn map_row_to_goal(row: PgRow) -> anyhow::Result<SavingsGoalQuery> {
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let target: Option<Decimal> = row.try_get("target")?;
+        let balance: Decimal = row.try_get("balance")?;
+
+        Ok(SavingsGoalQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            target: target
+                .map(|t| Money::from_decimal_rounded(t, currency))
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            balance: Money::from_decimal_rounded(balance, currency).map_err(|e| anyhow!(e))?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    pub async fn create_goal(
+        pool: &DbPool,
+        user_id: Uuid,
+        name: &str,
+        currency: Currency,
+        target: Option<Decimal>,
+    ) -> anyhow::Result<SavingsGoalQuery> {
+        let sql = format!(
+            "WITH g AS (INSERT INTO savings_goals (user_id, name, currency, target) VALUES ($1, $2, $3, $4) RETURNING *) SELECT {GOAL_COLUMNS} FROM g"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(name)
+                .bind(currency.to_string())
+                .bind(target)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_goal(row)
+    }
+
+    pub async fn get_goals(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Vec<SavingsGoalQuery>> {
+        let sql = format!(
+            "SELECT {GOAL_COLUMNS} FROM savings_goals g WHERE g.user_id = $1 ORDER BY g.created_at"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_goal).collect()
+    }
+
+    pub async fn get_goal(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<SavingsGoalQuery>> {
+        let sql = format!("SELECT {GOAL_COLUMNS} FROM savings_goals g WHERE g.id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_goal).transpose()
+    }
+
+    fn map_row_to_rule(row: PgRow) -> anyhow::Result<RoundUpRuleQuery> {
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let unit: Decimal = row.try_get("unit")?;
+
+        Ok(RoundUpRuleQuery {
+            goal_id: row.try_get("goal_id")?,
+            unit: Money::from_decimal_rounded(unit, currency).map_err(|e| anyhow!(e))?,
+            starts_at: row.try_get("starts_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Round up the user's expenses into one of their goals from now on, or change the goal
+    /// or unit of their rule. Returns None when the goal is not one of the user's
+    pub async fn upsert_rule(
+        pool: &DbPool,
+        user_id: Uuid,
+        goal_id: Uuid,
+        unit: Decimal,
+    ) -> anyhow::Result<Option<RoundUpRuleQuery>> {
+        let sql = "WITH r AS (INSERT INTO round_up_rules (user_id, goal_id, unit) SELECT user_id, id, $3 FROM savings_goals WHERE id = $2 AND user_id = $1 ON CONFLICT (user_id) DO UPDATE SET goal_id = EXCLUDED.goal_id, unit = EXCLUDED.unit, updated_at = NOW() RETURNING *) SELECT r.goal_id, r.unit, g.currency, r.starts_at, r.updated_at FROM r JOIN savings_goals g ON g.id = r.goal_id";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(goal_id)
+                .bind(unit)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_rule).transpose()
+    }
+
+    pub async fn get_rule(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<RoundUpRuleQuery>> {
+        let sql = "SELECT r.goal_id, r.unit, g.currency, r.starts_at, r.updated_at FROM round_up_rules r JOIN savings_goals g ON g.id = r.goal_id WHERE r.user_id = $1";
+        let row =
+            telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_rule).transpose()
+    }
+
+    /// Stop rounding up, returns false when the user had no rule
+    pub async fn delete_rule(pool: &DbPool, user_id: Uuid) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM round_up_rules WHERE user_id = $1";
+        let result = telemetry::observe(sql, sqlx::query(sql).bind(user_id).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Set aside the round-up of every expense covered by a rule that has none yet,
+    /// returning how many were recorded. Expenses on a whole multiple of the unit have none
+    pub async fn record_round_ups(pool: &DbPool) -> anyhow::Result<u64> {
+        let sql = "INSERT INTO round_ups (transaction_id, goal_id, user_id, amount, currency, occurred_at) SELECT t.id, r.goal_id, t.user_id, CEIL(-t.amount / r.unit) * r.unit + t.amount, t.currency, t.created_at FROM transactions t JOIN round_up_rules r ON r.user_id = t.user_id JOIN savings_goals g ON g.id = r.goal_id WHERE t.deleted_at IS NULL AND t.transaction_type = 'Expense' AND t.currency = g.currency AND t.created_at >= r.starts_at AND CEIL(-t.amount / r.unit) * r.unit + t.amount > 0 AND NOT EXISTS (SELECT 1 FROM round_ups u WHERE u.transaction_id = t.id) ON CONFLICT (transaction_id) DO NOTHING";
+        let result = telemetry::observe(sql, sqlx::query(sql).execute(pool)).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Round-ups of the goal per month of `year`, months in the user's time zone
+    pub async fn get_monthly_round_ups(
+        pool: &DbPool,
+        goal_id: Uuid,
+        currency: Currency,
+        timezone: &str,
+        year: i32,
+    ) -> anyhow::Result<Vec<RoundUpMonth>> {
+        let sql = "SELECT date_trunc('month', occurred_at AT TIME ZONE $2)::DATE AS month, COUNT(*) AS transfers, SUM(amount) AS amount FROM round_ups WHERE goal_id = $1 AND EXTRACT(YEAR FROM occurred_at AT TIME ZONE $2) = $3 GROUP BY 1 ORDER BY 1";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(goal_id)
+                .bind(timezone)
+                .bind(year)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let amount: Decimal = row.try_get("amount")?;
+                Ok(RoundUpMonth {
+                    month: row.try_get("month")?,
+                    transfers: row.try_get("transfers")?,
+                    amount: Money::from_decimal_rounded(amount, currency)
+                        .map_err(|e| anyhow!(e))?,
+                })
+            })
+            .collect()
+    }
+}
+
+pub mod reward_queries {
+    use crate::database::DbPool;
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::reward_models::RewardRuleQuery;
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const RULE_COLUMNS: &str =
+        "id, user_id, bank_account_id, category, rate, created_at, updated_at";
+
+    fn map_row_to_rule(row: PgRow) -> anyhow::Result<RewardRuleQuery> {
+        let category: Option<&str> = row.try_get("category")?;
+        Ok(RewardRuleQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            bank_account_id: row.try_get("bank_account_id")?,
+            category: category
+                .map(TransactionCategory::from_str)
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            rate: row.try_get("rate")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Set the rate a card earns in a category (or on everything else)
+    /// Returns None when the card is not one of the user's
+    pub async fn upsert_rule(
+        pool: &DbPool,
+        user_id: Uuid,
+        bank_account_id: Uuid,
+        category: Option<&TransactionCategory>,
+        rate: Decimal,
+    ) -> anyhow::Result<Option<RewardRuleQuery>> {
+        let sql = format!(
+            "INSERT INTO reward_rules (user_id, bank_account_id, category, rate) SELECT user_id, id, $3, $4 FROM bank_accounts WHERE id = $2 AND user_id = $1 ON CONFLICT (bank_account_id, category) DO UPDATE SET rate = EXCLUDED.rate, updated_at = NOW() RETURNING {RULE_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(bank_account_id)
+                .bind(category.map(|c| c.to_string()))
+                .bind(rate)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_rule).transpose()
+    }
+
+    pub async fn get_rules(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Vec<RewardRuleQuery>> {
+        let sql = format!(
+            "SELECT {RULE_COLUMNS} FROM reward_rules WHERE user_id = $1 ORDER BY created_at"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_rule).collect()
+    }
+
+    /// Remove a rule, returning the user it belonged to, None when it did not exist
+    pub async fn delete_rule(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let sql = "DELETE FROM reward_rules WHERE id = $1 RETURNING user_id";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(|r| r.try_get("user_id"))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Category and amount (positive) of the card's expenses between `start` and `end`
+    pub async fn get_card_expenses(
+        pool: &DbPool,
+        bank_account_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<(TransactionCategory, Money)>> {
+        let sql = "SELECT category, -amount AS amount, currency FROM transactions WHERE bank_account_id = $1 AND deleted_at IS NULL AND transaction_type = 'Expense' AND created_at >= $2 AND created_at < $3";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(bank_account_id)
+                .bind(start)
+                .bind(end)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let category: &str = row.try_get("category")?;
+                let amount: Decimal = row.try_get("amount")?;
+                let currency =
+                    Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+                Ok((
+                    TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?,
+                    Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+                ))
+            })
+            .collect()
+    }
+}
+
+pub mod attachment_queries {
+    use crate::database::DbPool;
+    use crate::models::attachment_models::{
+        AttachmentCreate, AttachmentQuery, ScanStatus, ThumbnailStatus,
+    };
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const COLUMNS: &str = "id, transaction_id, user_id, file_name, content_type, size_bytes, sha256, thumbnail_status, scan_status, malware_signature, storage_key, created_at";
+
+    fn map_row_to_attachment(row: PgRow) -> anyhow::Result<AttachmentQuery> {
+        let thumbnail_status: Option<String> = row.try_get("thumbnail_status")?;
+        let scan_status: Option<String> = row.try_get("scan_status")?;
+
+        Ok(AttachmentQuery {
+            id: row.try_get("id")?,
+            transaction_id: row.try_get("transaction_id")?,
+            user_id: row.try_get("user_id")?,
+            file_name: row.try_get("file_name")?,
+            content_type: row.try_get("content_type")?,
+            size_bytes: row.try_get("size_bytes")?,
+            sha256: row.try_get("sha256")?,
+            thumbnail_status: thumbnail_status
+                .map(|s| ThumbnailStatus::from_str(&s))
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            scan_status: scan_status
+                .map(|s| ScanStatus::from_str(&s))
+                .transpose()
+                .map_err(|e| anyhow!(e))?,
+            malware_signature: row.try_get("malware_signature")?,
+            storage_key: row.try_get("storage_key")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    /// Record an attachment unless it takes the user's attachments over `storage_limit` bytes,
+    /// checked in the same statement. None when over the limit
+    pub async fn insert(
+        pool: &DbPool,
+        attachment: &AttachmentCreate,
+        storage_limit: Option<i64>,
+    ) -> anyhow::Result<Option<AttachmentQuery>> {
+        let sql = format!(
+            "INSERT INTO attachments (id, transaction_id, user_id, file_name, content_type, size_bytes, sha256, storage_key, thumbnail_status, scan_status, malware_signature) SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $11, $12 WHERE $10::bigint IS NULL OR (SELECT COALESCE(SUM(size_bytes), 0) FROM attachments WHERE user_id = $3) + $6 <= $10 RETURNING {COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(attachment.id)
+                .bind(attachment.transaction_id)
+                .bind(attachment.user_id)
+                .bind(&attachment.file_name)
+                .bind(&attachment.content_type)
+                .bind(attachment.size_bytes)
+                .bind(&attachment.sha256)
+                .bind(&attachment.storage_key)
+                .bind(attachment.thumbnail_status().map(|s| s.to_string()))
+                .bind(storage_limit)
+                .bind(attachment.scan_status.map(|s| s.to_string()))
+                .bind(&attachment.malware_signature)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_attachment).transpose()
+    }
+
+    pub async fn get_attachments(
+        pool: &DbPool,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<Vec<AttachmentQuery>> {
+        let sql = format!(
+            "SELECT {COLUMNS} FROM attachments WHERE transaction_id = $1 ORDER BY created_at"
+        );
+        let rows = telemetry::observe(&sql, sqlx::query(&sql).bind(transaction_id).fetch_all(pool))
+            .await?;
+
+        rows.into_iter().map(map_row_to_attachment).collect()
+    }
+
+    pub async fn get_attachment(
+        pool: &DbPool,
+        id: Uuid,
+    ) -> anyhow::Result<Option<AttachmentQuery>> {
+        let sql = format!("SELECT {COLUMNS} FROM attachments WHERE id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_attachment).transpose()
+    }
+
+    /// Contents of an attachment uploaded before the blob store, None for newer ones
+    pub async fn get_data(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Vec<u8>>> {
+        let sql = "SELECT data FROM attachments WHERE id = $1 AND data IS NOT NULL";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+
+        Ok(row.map(|row| row.try_get("data")).transpose()?)
+    }
+
+    /// The generated thumbnail, None until it is ready and for quarantined attachments
+    pub async fn get_thumbnail(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Vec<u8>>> {
+        let sql = "SELECT thumbnail FROM attachments WHERE id = $1 AND thumbnail IS NOT NULL AND scan_status IS DISTINCT FROM 'quarantined'";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+
+        Ok(row.map(|row| row.try_get("thumbnail")).transpose()?)
+    }
+
+    /// Store a generated thumbnail, or only the status when generating it failed
+    pub async fn set_thumbnail(
+        pool: &DbPool,
+        id: Uuid,
+        thumbnail: Option<&[u8]>,
+        status: ThumbnailStatus,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE attachments SET thumbnail = COALESCE($2, thumbnail), thumbnail_status = $3 WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(thumbnail)
+                .bind(status.to_string())
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod import_queries {
+    use crate::database::DbPool;
+    use crate::models::import_models::{ImportCreate, ImportProgress, ImportQuery, ImportStatus};
+    use crate::models::money_models::Currency;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use serde_json::Value;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const COLUMNS: &str = "id, user_id, format, currency, bank_account_id, storage_key, status, total_rows, rows_processed, inserted, duplicates_skipped, transfers_skipped, other_skipped, errors, rejected_rows, failure, created_at, started_at, finished_at";
+
+    fn map_row_to_import(row: PgRow) -> anyhow::Result<ImportQuery> {
+        let currency: &str = row.try_get("currency")?;
+        let status: &str = row.try_get("status")?;
+
+        Ok(ImportQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            format: row.try_get("format")?,
+            currency: Currency::from_str(currency).map_err(|e| anyhow!(e))?,
+            bank_account_id: row.try_get("bank_account_id")?,
+            storage_key: row.try_get("storage_key")?,
+            status: ImportStatus::from_str(status).map_err(|e| anyhow!(e))?,
+            total_rows: row.try_get("total_rows")?,
+            rows_processed: row.try_get("rows_processed")?,
+            inserted: row.try_get("inserted")?,
+            duplicates_skipped: row.try_get("duplicates_skipped")?,
+            transfers_skipped: row.try_get("transfers_skipped")?,
+            other_skipped: row.try_get("other_skipped")?,
+            errors: row.try_get("errors")?,
+            rejected_rows: row.try_get("rejected_rows")?,
+            failure: row.try_get("failure")?,
+            created_at: row.try_get("created_at")?,
+            started_at: row.try_get("started_at")?,
+            finished_at: row.try_get("finished_at")?,
+        })
+    }
+
+    pub async fn insert(pool: &DbPool, import: &ImportCreate) -> anyhow::Result<ImportQuery> {
+        let sql = format!(
+            "INSERT INTO imports (id, user_id, format, currency, bank_account_id, storage_key) VALUES ($1, $2, $3, $4, $5, $6) RETURNING {COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(import.id)
+                .bind(import.user_id)
+                .bind(&import.format)
+                .bind(import.currency.to_string())
+                .bind(import.bank_account_id)
+                .bind(&import.storage_key)
+                .fetch_optional(pool),
+        )
+        .await?
+        .ok_or_else(|| anyhow!("Import {} was not recorded", import.id))?;
+
+        map_row_to_import(row)
+    }
+
+    pub async fn get_import(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<ImportQuery>> {
+        let sql = format!("SELECT {COLUMNS} FROM imports WHERE id = $1");
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_import).transpose()
+    }
+
+    /// Mark an import running, starting its counts over if it is run again
+    pub async fn start(pool: &DbPool, id: Uuid) -> anyhow::Result<()> {
+        let sql = "UPDATE imports SET status = 'running', started_at = NOW(), finished_at = NULL, failure = NULL, total_rows = NULL, rows_processed = 0, inserted = 0, duplicates_skipped = 0, transfers_skipped = 0, other_skipped = 0, errors = '[]', rejected_rows = 0 WHERE id = $1";
+        telemetry::observe(sql, sqlx::query(sql).bind(id).execute(pool)).await?;
+        Ok(())
+    }
+
+    /// Record how far an import got, and the rows that could not be read once known
+    pub async fn set_progress(
+        pool: &DbPool,
+        id: Uuid,
+        progress: &ImportProgress,
+        errors: Option<&Value>,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE imports SET total_rows = $2, rows_processed = $3, inserted = $4, duplicates_skipped = $5, transfers_skipped = $6, other_skipped = $7, errors = COALESCE($8, errors) WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(progress.total_rows)
+                .bind(progress.rows_processed)
+                .bind(progress.inserted)
+                .bind(progress.duplicates_skipped)
+                .bind(progress.transfers_skipped)
+                .bind(progress.other_skipped)
+                .bind(errors)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Record how many rows went into the import's rejected rows report
+    pub async fn set_rejected_rows(
+        pool: &DbPool,
+        id: Uuid,
+        rejected_rows: i32,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE imports SET rejected_rows = $2 WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql).bind(id).bind(rejected_rows).execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Mark an import succeeded, or failed for `failure`
+    pub async fn finish(pool: &DbPool, id: Uuid, failure: Option<&str>) -> anyhow::Result<()> {
+        let status = match failure {
+            Some(_) => ImportStatus::Failed,
+            None => ImportStatus::Succeeded,
+        };
+        let sql = "UPDATE imports SET status = $2, failure = $3, finished_at = NOW() WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(status.to_string())
+                .bind(failure)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod notification_queries {
+    use crate::database::DbPool;
+    use crate::models::money_models::Currency;
+    use crate::models::notification_models::{
+        DigestRecipient, NotificationChannel, NotificationPreferences,
+        PutNotificationPreferencesRequest, WalletActivityRow,
+    };
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, NaiveDate, Utc};
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const PREFERENCE_COLUMNS: &str =
+        "user_id, weekly_digest, channel, last_digest_week, updated_at";
+
+    fn map_row_to_preferences(row: PgRow) -> anyhow::Result<NotificationPreferences> {
+        Ok(NotificationPreferences {
+            user_id: row.try_get("user_id")?,
+            weekly_digest: row.try_get("weekly_digest")?,
+            channel: NotificationChannel::from_str(row.try_get("channel")?)
+                .map_err(|e| anyhow!(e))?,
+            last_digest_week: row.try_get("last_digest_week")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// The user's preferences, the defaults when they never set any
+    pub async fn get_preferences(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<NotificationPreferences> {
+        let sql =
+            format!("SELECT {PREFERENCE_COLUMNS} FROM notification_preferences WHERE user_id = $1");
+        let row =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_optional(pool)).await?;
+
+        match row {
+            Some(row) => map_row_to_preferences(row),
+            None => Ok(NotificationPreferences {
+                user_id,
+                weekly_digest: false,
+                channel: NotificationChannel::default(),
+                last_digest_week: None,
+                updated_at: None,
+            }),
+        }
+    }
+
+    pub async fn upsert_preferences(
+        pool: &DbPool,
+        user_id: Uuid,
+        request: &PutNotificationPreferencesRequest,
+    ) -> anyhow::Result<NotificationPreferences> {
+        let sql = format!(
+            "INSERT INTO notification_preferences (user_id, weekly_digest, channel) VALUES ($1, $2, $3) ON CONFLICT (user_id) DO UPDATE SET weekly_digest = EXCLUDED.weekly_digest, channel = EXCLUDED.channel, updated_at = NOW() RETURNING {PREFERENCE_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(request.weekly_digest)
+                .bind(request.channel.to_string())
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_preferences(row)
+    }
+
+    /// Users who opted into the weekly digest
+    pub async fn get_digest_recipients(pool: &DbPool) -> anyhow::Result<Vec<DigestRecipient>> {
+        let sql = "SELECT u.id, u.timezone, p.channel, p.last_digest_week FROM notification_preferences p JOIN users u ON u.id = p.user_id WHERE p.weekly_digest ORDER BY u.id";
+        let rows = telemetry::observe(sql, sqlx::query(sql).fetch_all(pool)).await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(DigestRecipient {
+                    user_id: row.try_get("id")?,
+                    timezone: row.try_get("timezone")?,
+                    channel: NotificationChannel::from_str(row.try_get("channel")?)
+                        .map_err(|e| anyhow!(e))?,
+                    last_digest_week: row.try_get("last_digest_week")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Record that the digest of the week starting on `week_start` reached the user
+    pub async fn mark_digest_sent(
+        pool: &DbPool,
+        user_id: Uuid,
+        week_start: NaiveDate,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE notification_preferences SET last_digest_week = $2 WHERE user_id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(week_start)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Owners and names of the shared wallets the user belongs to, their own first when
+    /// they shared it with anyone
+    pub async fn get_shared_wallets(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Vec<(Uuid, String)>> {
+        let sql = "SELECT u.id, u.name FROM users u WHERE u.id = $1 AND EXISTS (SELECT 1 FROM wallet_members m WHERE m.owner_id = u.id)
+             UNION ALL
+             SELECT u.id, u.name FROM wallet_members m JOIN users u ON u.id = m.owner_id WHERE m.member_id = $1";
+        let rows = telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter()
+            .map(|row| Ok((row.try_get("id")?, row.try_get("name")?)))
+            .collect()
+    }
+
+    /// Transactions of the owner's wallet created in [start, end), totalled by the member
+    /// who recorded them, category, type and currency
+    /// Transactions have no author column: it is the editor who requested an approved
+    /// transaction, else the actor of its creation in the audit log, else the owner
+    pub async fn get_wallet_activity(
+        pool: &DbPool,
+        owner_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<WalletActivityRow>> {
+        let sql = "SELECT member_id, COALESCE(u.name, 'Former member') AS member_name, category, transaction_type, currency, transactions, total
+             FROM (
+                 SELECT COALESCE(ta.requested_by, a.actor_id, t.user_id) AS member_id, t.category, t.transaction_type, t.currency, COUNT(*) AS transactions, SUM(t.amount) AS total
+                 FROM transactions t
+                 LEFT JOIN transaction_approvals ta ON ta.transaction_id = t.id
+                 LEFT JOIN LATERAL (
+                     SELECT actor_id FROM audit_log
+                     WHERE entity_type = 'transaction' AND entity_id = t.id AND action = 'create'
+                     ORDER BY seq LIMIT 1
+                 ) a ON TRUE
+                 WHERE t.user_id = $1 AND t.deleted_at IS NULL AND t.created_at >= $2 AND t.created_at < $3
+                 GROUP BY 1, 2, 3, 4
+             ) activity
+             LEFT JOIN users u ON u.id = activity.member_id
+             ORDER BY member_id, category";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(owner_id)
+                .bind(start)
+                .bind(end)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WalletActivityRow {
+                    member_id: row.try_get("member_id")?,
+                    member_name: row.try_get("member_name")?,
+                    category: row.try_get("category")?,
+                    transaction_type: row.try_get("transaction_type")?,
+                    currency: Currency::from_str(row.try_get("currency")?)
+                        .map_err(|e| anyhow!(e))?,
+                    transactions: row.try_get("transactions")?,
+                    total: row.try_get("total")?,
+                })
+            })
+            .collect()
+    }
+}
+
+pub mod merchant_queries {
+    use crate::database::DbPool;
+    use crate::models::merchant_models::Merchant;
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::telemetry;
+    use sqlx::Row;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    /// Cached lookup of a descriptor no older than `max_age`: None when there is none,
+    /// Some(None) when the API knew no merchant for it
+    pub async fn get_lookup(
+        pool: &DbPool,
+        descriptor_hash: &str,
+        max_age: Duration,
+    ) -> anyhow::Result<Option<Option<Merchant>>> {
+        let sql = "SELECT name, logo_url, category FROM merchant_lookups WHERE descriptor_hash = $1 AND fetched_at > NOW() - $2 * INTERVAL '1 second'";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(descriptor_hash)
+                .bind(max_age.as_secs_f64())
+                .fetch_optional(pool),
+        )
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let Some(name) = row.try_get::<Option<String>, _>("name")? else {
+            return Ok(Some(None));
+        };
+        let category: Option<&str> = row.try_get("category")?;
+        Ok(Some(Some(Merchant {
+            name,
+            logo_url: row.try_get("logo_url")?,
+            // A category this version no longer knows is dropped rather than failing
+            category: category.and_then(|c| TransactionCategory::from_str(c).ok()),
+        })))
+    }
+
+    /// Cache the result of looking up a descriptor, None when no merchant was found
+    pub async fn store_lookup(
+        pool: &DbPool,
+        descriptor_hash: &str,
+        merchant: Option<&Merchant>,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO merchant_lookups (descriptor_hash, name, logo_url, category) VALUES ($1, $2, $3, $4) ON CONFLICT (descriptor_hash) DO UPDATE SET name = EXCLUDED.name, logo_url = EXCLUDED.logo_url, category = EXCLUDED.category, fetched_at = NOW()";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(descriptor_hash)
+                .bind(merchant.map(|m| &m.name))
+                .bind(merchant.and_then(|m| m.logo_url.as_ref()))
+                .bind(merchant.and_then(|m| m.category.as_ref().map(ToString::to_string)))
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod reconciliation_queries {
+    use crate::database::DbPool;
+    use crate::models::reconciliation_models::ReconciliationLock;
+    use crate::telemetry;
+    use chrono::{DateTime, Utc};
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use uuid::Uuid;
+
+    const LOCK_COLUMNS: &str =
+        "l.id, l.bank_account_id, l.period_start, l.period_end, l.locked_by, l.created_at";
+
+    fn map_row_to_lock(row: PgRow) -> anyhow::Result<ReconciliationLock> {
+        Ok(ReconciliationLock {
+            id: row.try_get("id")?,
+            bank_account_id: row.try_get("bank_account_id")?,
+            period_start: row.try_get("period_start")?,
+            period_end: row.try_get("period_end")?,
+            locked_by: row.try_get("locked_by")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    pub async fn create_lock(
+        pool: &DbPool,
+        bank_account_id: Uuid,
+        period_start: DateTime<Utc>,
+        period_end: DateTime<Utc>,
+        locked_by: Uuid,
+    ) -> anyhow::Result<ReconciliationLock> {
+        let sql = format!(
+            "INSERT INTO reconciliation_locks AS l (bank_account_id, period_start, period_end, locked_by) VALUES ($1, $2, $3, $4) RETURNING {LOCK_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(bank_account_id)
+                .bind(period_start)
+                .bind(period_end)
+                .bind(locked_by)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_lock(row)
+    }
+
+    /// Locks of the account, oldest period first
+    pub async fn get_locks(
+        pool: &DbPool,
+        bank_account_id: Uuid,
+    ) -> anyhow::Result<Vec<ReconciliationLock>> {
+        let sql = format!(
+            "SELECT {LOCK_COLUMNS} FROM reconciliation_locks l WHERE l.bank_account_id = $1 ORDER BY l.period_start"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql).bind(bank_account_id).fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_lock).collect()
+    }
+
+    /// A lock of the account overlapping [period_start, period_end), if any
+    pub async fn get_overlapping_lock(
+        pool: &DbPool,
+        bank_account_id: Uuid,
+        period_start: DateTime<Utc>,
+        period_end: DateTime<Utc>,
+    ) -> anyhow::Result<Option<ReconciliationLock>> {
+        let sql = format!(
+            "SELECT {LOCK_COLUMNS} FROM reconciliation_locks l WHERE l.bank_account_id = $1 AND l.period_start < $3 AND l.period_end > $2 ORDER BY l.period_start LIMIT 1"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(bank_account_id)
+                .bind(period_start)
+                .bind(period_end)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_lock).transpose()
+    }
+
+    /// Unlock a period of the account, returning the removed lock, None when there is no such lock
+    pub async fn delete_lock(
+        pool: &DbPool,
+        bank_account_id: Uuid,
+        id: Uuid,
+    ) -> anyhow::Result<Option<ReconciliationLock>> {
+        let sql = format!(
+            "DELETE FROM reconciliation_locks l WHERE l.id = $1 AND l.bank_account_id = $2 RETURNING {LOCK_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(bank_account_id)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_lock).transpose()
+    }
+
+    /// The lock covering one of the user's transactions where it is now or, when given,
+    /// where it would be moved to, None when it may be changed
+    pub async fn get_covering_lock(
+        pool: &DbPool,
+        transaction_id: Uuid,
+        user_id: Uuid,
+        moved_to: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Option<ReconciliationLock>> {
+        let sql = format!(
+            "SELECT {LOCK_COLUMNS} FROM transactions t JOIN reconciliation_locks l ON l.bank_account_id = t.bank_account_id
+             WHERE t.id = $1 AND t.user_id = $2
+             AND ((l.period_start <= t.created_at AND t.created_at < l.period_end) OR (l.period_start <= $3 AND $3 < l.period_end))
+             ORDER BY l.period_start LIMIT 1"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(transaction_id)
+                .bind(user_id)
+                .bind(moved_to)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_lock).transpose()
+    }
+}
+
+pub mod fx_queries {
+    use crate::database::DbPool;
+    use crate::models::fx_models::BookedBalance;
+    use crate::models::money_models::Currency;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::NaiveDate;
+    use chrono_tz::Tz;
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Store one day's rates, replacing those already known for the day
+    pub async fn upsert_rates(
+        pool: &DbPool,
+        date: NaiveDate,
+        rates: &BTreeMap<Currency, Decimal>,
+    ) -> anyhow::Result<()> {
+        let currencies: Vec<String> = rates.keys().map(ToString::to_string).collect();
+        let values: Vec<Decimal> = rates.values().copied().collect();
+        let sql = "INSERT INTO exchange_rates (rate_date, currency, rate) SELECT $1, * FROM UNNEST($2::VARCHAR[], $3::DECIMAL[]) ON CONFLICT (currency, rate_date) DO UPDATE SET rate = EXCLUDED.rate, fetched_at = NOW()";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(date)
+                .bind(&currencies)
+                .bind(&values)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Most recent day rates are known for
+    pub async fn get_latest_rate_date(pool: &DbPool) -> anyhow::Result<Option<NaiveDate>> {
+        let sql = "SELECT MAX(rate_date) AS rate_date FROM exchange_rates";
+        let row = telemetry::observe(sql, sqlx::query(sql).fetch_one(pool)).await?;
+        Ok(row.try_get("rate_date")?)
+    }
+
+    /// Day of the oldest transaction of any user, where fetching rates starts from
+    pub async fn get_earliest_transaction_date(pool: &DbPool) -> anyhow::Result<Option<NaiveDate>> {
+        let sql = "SELECT MIN(created_at)::DATE AS created_at FROM transactions";
+        let row = telemetry::observe(sql, sqlx::query(sql).fetch_one(pool)).await?;
+        Ok(row.try_get("created_at")?)
+    }
+
+    /// Latest known rate of every currency, against the reference currency
+    pub async fn get_latest_rates(
+        pool: &DbPool,
+    ) -> anyhow::Result<BTreeMap<Currency, (NaiveDate, Decimal)>> {
+        let sql = "SELECT DISTINCT ON (currency) currency, rate_date, rate FROM exchange_rates ORDER BY currency, rate_date DESC";
+        let rows = telemetry::observe(sql, sqlx::query(sql).fetch_all(pool)).await?;
+
+        let mut rates = BTreeMap::new();
+        for row in rows {
+            // Rates of currencies this version no longer supports are ignored
+            let Ok(currency) = Currency::from_str(row.try_get("currency")?) else {
+                continue;
+            };
+            rates.insert(currency, (row.try_get("rate_date")?, row.try_get("rate")?));
+        }
+        Ok(rates)
+    }
+
+    /// The user's balance in every currency but `base`, and its value in `base` at the rate of
+    /// each transaction's day in the user's timezone, the latest known on or before that day
+    pub async fn get_booked_balances(
+        pool: &DbPool,
+        user_id: Uuid,
+        base: Currency,
+        tz: Tz,
+    ) -> anyhow::Result<Vec<BookedBalance>> {
+        let sql = "SELECT t.currency, COUNT(*) AS transactions,
+                COUNT(*) FILTER (WHERE c.rate IS NULL OR b.rate IS NULL) AS unpriced_transactions,
+                SUM(t.amount) AS balance,
+                COALESCE(SUM(t.amount) FILTER (WHERE c.rate IS NOT NULL AND b.rate IS NOT NULL), 0) AS priced_balance,
+                COALESCE(SUM(t.amount * b.rate / c.rate), 0) AS booked_value
+            FROM transactions t
+            LEFT JOIN LATERAL (
+                SELECT rate FROM exchange_rates
+                WHERE currency = t.currency AND rate_date <= (t.created_at AT TIME ZONE $3)::DATE
+                ORDER BY rate_date DESC LIMIT 1
+            ) c ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT rate FROM exchange_rates
+                WHERE currency = $2 AND rate_date <= (t.created_at AT TIME ZONE $3)::DATE
+                ORDER BY rate_date DESC LIMIT 1
+            ) b ON TRUE
+            WHERE t.user_id = $1 AND t.deleted_at IS NULL AND t.currency <> $2
+            GROUP BY t.currency
+            ORDER BY t.currency";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(base.to_string())
+                .bind(tz.name())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(BookedBalance {
+                    currency: Currency::from_str(row.try_get("currency")?)
+                        .map_err(|e| anyhow!(e))?,
+                    transactions: row.try_get("transactions")?,
+                    unpriced_transactions: row.try_get("unpriced_transactions")?,
+                    balance: row.try_get("balance")?,
+                    priced_balance: row.try_get("priced_balance")?,
+                    booked_value: row.try_get("booked_value")?,
+                })
+            })
+            .collect()
+    }
+}
+
+pub mod refresh_token_queries {
+    use crate::database::DbPool;
+    use crate::models::auth_models::{RefreshTokenCreate, RefreshTokenQuery};
+    use crate::telemetry;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use uuid::Uuid;
+
+    const COLUMNS: &str = "id, user_id, family_id, expires_at, used_at, revoked_at";
+
+    fn map_row_to_refresh_token(row: PgRow) -> anyhow::Result<RefreshTokenQuery> {
+        Ok(RefreshTokenQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            family_id: row.try_get("family_id")?,
+            expires_at: row.try_get("expires_at")?,
+            used_at: row.try_get("used_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+        })
+    }
+
+    pub async fn create(pool: &DbPool, token: &RefreshTokenCreate) -> anyhow::Result<()> {
+        let sql = "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4, $5)";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(token.id)
+                .bind(token.user_id)
+                .bind(token.family_id)
+                .bind(&token.token_hash)
+                .bind(token.expires_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The token with this hash, whether or not it can still be used
+    pub async fn get_by_hash(
+        pool: &DbPool,
+        token_hash: &str,
+    ) -> anyhow::Result<Option<RefreshTokenQuery>> {
+        let sql = format!("SELECT {COLUMNS} FROM refresh_tokens WHERE token_hash = $1");
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql).bind(token_hash).fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_refresh_token).transpose()
+    }
+
+    /// Mark the token used and record its successor in one transaction, false (and nothing
+    /// recorded) when it was used or revoked in the meantime
+    pub async fn rotate(
+        pool: &DbPool,
+        id: Uuid,
+        next: &RefreshTokenCreate,
+    ) -> anyhow::Result<bool> {
+        let mut tx = pool.begin().await?;
+        let sql = "UPDATE refresh_tokens SET used_at = NOW(), replaced_by = $2 WHERE id = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()";
+        let claimed = telemetry::observe(
+            sql,
+            sqlx::query(sql).bind(id).bind(next.id).execute(&mut *tx),
+        )
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        let sql = "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4, $5)";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(next.id)
+                .bind(next.user_id)
+                .bind(next.family_id)
+                .bind(&next.token_hash)
+                .bind(next.expires_at)
+                .execute(&mut *tx),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Revoke every token of a family, returning how many were not revoked yet
+    pub async fn revoke_family(pool: &DbPool, family_id: Uuid) -> anyhow::Result<u64> {
+        let sql = "UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND revoked_at IS NULL";
+        let revoked =
+            telemetry::observe(sql, sqlx::query(sql).bind(family_id).execute(pool)).await?;
+        Ok(revoked.rows_affected())
+    }
+}
+
+pub mod password_setup_queries {
+    use crate::database::DbPool;
+    use crate::telemetry;
+    use chrono::{DateTime, Utc};
+    use uuid::Uuid;
+
+    pub async fn create(
+        pool: &DbPool,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let sql = "INSERT INTO password_setup_tokens (id, user_id, token_hash, expires_at) VALUES ($1, $2, $3, $4)";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(Uuid::new_v4())
+                .bind(user_id)
+                .bind(token_hash)
+                .bind(expires_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Set the password of the token's user and use up all their tokens in one
+    /// transaction, returning the user or None when the token is unknown or expired
+    pub async fn set_password(
+        pool: &DbPool,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let mut tx = pool.begin().await?;
+        let sql = "DELETE FROM password_setup_tokens WHERE token_hash = $1 AND expires_at > NOW() RETURNING user_id";
+        let user_id: Option<Uuid> = telemetry::observe(
+            sql,
+            sqlx::query_scalar(sql)
+                .bind(token_hash)
+                .fetch_optional(&mut *tx),
+        )
+        .await?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        let sql = "UPDATE users SET password = $2, updated_at = NOW() WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(password_hash)
+                .execute(&mut *tx),
+        )
+        .await?;
+        let sql = "DELETE FROM password_setup_tokens WHERE user_id = $1";
+        telemetry::observe(sql, sqlx::query(sql).bind(user_id).execute(&mut *tx)).await?;
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+}
+
+pub mod invitation_queries {
+    use crate::crypto;
+    use crate::database::DbPool;
+    use crate::models::invitation_models::{InvitationCreate, InvitationQuery};
+    use crate::models::user_models::UserCreate;
+    use crate::queries::user_queries;
+    use crate::telemetry;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const COLUMNS: &str =
+        "id, email, wallet_owner_id, role, created_by, created_at, expires_at, used_at, used_by";
+
+    fn map_row_to_invitation(row: PgRow) -> anyhow::Result<InvitationQuery> {
+        let email: Option<String> = row.try_get("email")?;
+        let role: Option<String> = row.try_get("role")?;
+        Ok(InvitationQuery {
+            id: row.try_get("id")?,
+            email: email.map(crypto::decrypt_field).transpose()?,
+            wallet_owner_id: row.try_get("wallet_owner_id")?,
+            role: role
+                .as_deref()
+                .map(FromStr::from_str)
+                .transpose()
+                .map_err(|e: String| anyhow::anyhow!(e))?,
+            created_by: row.try_get("created_by")?,
+            created_at: row.try_get("created_at")?,
+            expires_at: row.try_get("expires_at")?,
+            used_at: row.try_get("used_at")?,
+            used_by: row.try_get("used_by")?,
+        })
+    }
+
+    pub async fn create(
+        pool: &DbPool,
+        invitation: &InvitationCreate,
+    ) -> anyhow::Result<InvitationQuery> {
+        let sql = format!(
+            "INSERT INTO invitations (id, token_hash, email, wallet_owner_id, role, created_by, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {COLUMNS}"
+        );
+        let email = invitation
+            .email
+            .as_deref()
+            .map(crypto::encrypt_field)
+            .transpose()?;
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(Uuid::new_v4())
+                .bind(&invitation.token_hash)
+                .bind(email)
+                .bind(invitation.wallet.map(|(owner_id, _)| owner_id))
+                .bind(invitation.wallet.map(|(_, role)| role.to_string()))
+                .bind(invitation.created_by)
+                .bind(invitation.expires_at)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_invitation(row)
+    }
+
+    /// The invitation with this token hash, whether or not it can still be used
+    pub async fn get_by_hash(
+        pool: &DbPool,
+        token_hash: &str,
+    ) -> anyhow::Result<Option<InvitationQuery>> {
+        let sql = format!("SELECT {COLUMNS} FROM invitations WHERE token_hash = $1");
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql).bind(token_hash).fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_invitation).transpose()
+    }
+
+    /// Invitations not used yet and not expired, newest first, only those to the owner's
+    /// wallet when an owner is given
+    pub async fn get_open(
+        pool: &DbPool,
+        wallet_owner_id: Option<Uuid>,
+    ) -> anyhow::Result<Vec<InvitationQuery>> {
+        let sql = format!(
+            "SELECT {COLUMNS} FROM invitations WHERE used_at IS NULL AND expires_at > NOW() AND ($1::uuid IS NULL OR wallet_owner_id = $1) ORDER BY created_at DESC"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql).bind(wallet_owner_id).fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_invitation).collect()
+    }
+
+    /// Withdraw an invitation that was not used yet, only one to the owner's wallet when an
+    /// owner is given. Returns false when there was none
+    pub async fn delete_open(
+        pool: &DbPool,
+        id: Uuid,
+        wallet_owner_id: Option<Uuid>,
+    ) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM invitations WHERE id = $1 AND used_at IS NULL AND ($2::uuid IS NULL OR wallet_owner_id = $2)";
+        let result = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(id)
+                .bind(wallet_owner_id)
+                .execute(pool),
+        )
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Use up the invitation signing the user up: create them, and add them to the wallet
+    /// of the invitation, in one transaction. Returns the new user's id, or None (and
+    /// nothing created) when the invitation was used or expired in the meantime
+    pub async fn redeem(
+        pool: &DbPool,
+        id: Uuid,
+        user: &UserCreate,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let mut tx = pool.begin().await?;
+        let sql = "UPDATE invitations SET used_at = NOW() WHERE id = $1 AND used_at IS NULL AND expires_at > NOW() RETURNING wallet_owner_id, role";
+        let claimed =
+            telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(&mut *tx)).await?;
+        let Some(claimed) = claimed else {
+            return Ok(None);
+        };
+
+        let user_id = user_queries::create_user(&mut *tx, user).await?;
+        let wallet_owner_id: Option<Uuid> = claimed.try_get("wallet_owner_id")?;
+        let role: Option<String> = claimed.try_get("role")?;
+        if let (Some(owner_id), Some(role)) = (wallet_owner_id, role) {
+            let sql = "INSERT INTO wallet_members (owner_id, member_id, role) VALUES ($1, $2, $3)";
+            telemetry::observe(
+                sql,
+                sqlx::query(sql)
+                    .bind(owner_id)
+                    .bind(user_id)
+                    .bind(role)
+                    .execute(&mut *tx),
+            )
+            .await?;
+        }
+        let sql = "UPDATE invitations SET used_by = $2 WHERE id = $1";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql).bind(id).bind(user_id).execute(&mut *tx),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+}
+
+pub mod account_queries {
+    use crate::database::DbPool;
+    use crate::models::account_models::{AccountQuery, TransferCreate, TransferQuery};
+    use crate::models::money_models::{Currency, Money};
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const ACCOUNT_COLUMNS: &str = "a.id, a.user_id, a.name, a.currency, a.opening_balance, a.created_at, a.updated_at, (SELECT COALESCE(SUM(t.amount), 0) FROM transactions t WHERE t.account_id = a.id AND t.deleted_at IS NULL) AS booked, (SELECT COUNT(*) FROM transactions t WHERE t.account_id = a.id AND t.deleted_at IS NULL) AS transactions, (SELECT COALESCE(SUM(CASE WHEN x.to_account_id = a.id THEN x.to_amount ELSE -x.amount END), 0) FROM transfers x WHERE a.id IN (x.from_account_id, x.to_account_id)) AS transferred";
+
+    const TRANSFER_COLUMNS: &str = "id, user_id, from_account_id, to_account_id, amount, currency, to_amount, to_currency, rate, description, created_at";
+
+    fn map_row_to_account(row: PgRow) -> anyhow::Result<AccountQuery> {
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let opening_balance: Decimal = row.try_get("opening_balance")?;
+        let booked: Decimal = row.try_get("booked")?;
+        let transferred: Decimal = row.try_get("transferred")?;
+
+        Ok(AccountQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            opening_balance: Money::from_decimal_rounded(opening_balance, currency)
+                .map_err(|e| anyhow!(e))?,
+            balance: Money::from_decimal_rounded(opening_balance + booked + transferred, currency)
+                .map_err(|e| anyhow!(e))?,
+            transactions: row.try_get("transactions")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Returns None when the user already has an account of that name
+    pub async fn create_account(
+        pool: &DbPool,
+        user_id: Uuid,
+        name: &str,
+        currency: Currency,
+        opening_balance: Decimal,
+    ) -> anyhow::Result<Option<AccountQuery>> {
+        let sql = format!(
+            "WITH a AS (INSERT INTO accounts (user_id, name, currency, opening_balance) VALUES ($1, $2, $3, $4) ON CONFLICT (user_id, name) DO NOTHING RETURNING *) SELECT {ACCOUNT_COLUMNS} FROM a"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(name)
+                .bind(currency.to_string())
+                .bind(opening_balance)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_account).transpose()
+    }
+
+    pub async fn get_accounts(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Vec<AccountQuery>> {
+        let sql = format!(
+            "SELECT {ACCOUNT_COLUMNS} FROM accounts a WHERE a.user_id = $1 ORDER BY a.created_at"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_account).collect()
+    }
+
+    /// Returns None when the account is not one of the user's
+    pub async fn get_account(
+        pool: &DbPool,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<AccountQuery>> {
+        let sql =
+            format!("SELECT {ACCOUNT_COLUMNS} FROM accounts a WHERE a.id = $1 AND a.user_id = $2");
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(id)
+                .bind(user_id)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_account).transpose()
+    }
+
+    /// Returns false when transactions are booked to the account, deleted ones included, money
+    /// was transferred from or to it, or it is not one of the user's
+    pub async fn delete_account(pool: &DbPool, id: Uuid, user_id: Uuid) -> anyhow::Result<bool> {
+        let sql = "DELETE FROM accounts a WHERE a.id = $1 AND a.user_id = $2 AND NOT EXISTS (SELECT 1 FROM transactions t WHERE t.account_id = a.id) AND NOT EXISTS (SELECT 1 FROM transfers x WHERE a.id IN (x.from_account_id, x.to_account_id))";
+        let result =
+            telemetry::observe(sql, sqlx::query(sql).bind(id).bind(user_id).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    fn map_row_to_transfer(row: PgRow) -> anyhow::Result<TransferQuery> {
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let to_currency =
+            Currency::from_str(row.try_get("to_currency")?).map_err(|e| anyhow!(e))?;
+        let amount: Decimal = row.try_get("amount")?;
+        let to_amount: Decimal = row.try_get("to_amount")?;
+
+        Ok(TransferQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            from_account_id: row.try_get("from_account_id")?,
+            to_account_id: row.try_get("to_account_id")?,
+            amount: Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+            to_amount: Money::from_decimal_rounded(to_amount, to_currency)
+                .map_err(|e| anyhow!(e))?,
+            rate: row.try_get("rate")?,
+            description: row.try_get("description")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    /// Record a transfer in one statement, which also checks both accounts are the user's and
+    /// in the currencies of the amounts
+    /// Returns None when they are not
+    pub async fn create_transfer(
+        pool: &DbPool,
+        transfer: &TransferCreate,
+    ) -> anyhow::Result<Option<TransferQuery>> {
+        let sql = format!(
+            "INSERT INTO transfers (user_id, from_account_id, to_account_id, amount, currency, to_amount, to_currency, rate, description) SELECT $1, f.id, t.id, $4, f.currency, $6, t.currency, $8, $9 FROM accounts f JOIN accounts t ON t.id = $3 AND t.user_id = $1 AND t.currency = $7 WHERE f.id = $2 AND f.user_id = $1 AND f.currency = $5 RETURNING {TRANSFER_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(transfer.user_id)
+                .bind(transfer.from_account_id)
+                .bind(transfer.to_account_id)
+                .bind(transfer.amount.to_decimal())
+                .bind(transfer.amount.currency.to_string())
+                .bind(transfer.to_amount.to_decimal())
+                .bind(transfer.to_amount.currency.to_string())
+                .bind(transfer.rate)
+                .bind(&transfer.description)
+                .fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_transfer).transpose()
+    }
+
+    /// The user's transfers, newest first, only those from or to `account_id` when given
+    pub async fn get_transfers(
+        pool: &DbPool,
+        user_id: Uuid,
+        account_id: Option<Uuid>,
+    ) -> anyhow::Result<Vec<TransferQuery>> {
+        let sql = format!(
+            "SELECT {TRANSFER_COLUMNS} FROM transfers WHERE user_id = $1 AND ($2::UUID IS NULL OR $2 IN (from_account_id, to_account_id)) ORDER BY created_at DESC, id"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(account_id)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_transfer).collect()
+    }
+}
+
+pub mod budget_queries {
+    use crate::database::DbPool;
+    use crate::models::budget_models::{BudgetAlertQuery, BudgetQuery};
+    use crate::models::money_models::{Currency, Money};
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    const BUDGET_COLUMNS: &str =
+        "b.id, b.user_id, b.category, b.amount, b.currency, b.created_at, b.updated_at";
+
+    fn map_row_to_budget(row: &PgRow) -> anyhow::Result<BudgetQuery> {
+        let category: &str = row.try_get("category")?;
+        let amount: Decimal = row.try_get("amount")?;
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+
+        Ok(BudgetQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            category: TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?,
+            amount: Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Set the user's budget for a category and currency
+    pub async fn upsert_budget(
+        pool: &DbPool,
+        user_id: Uuid,
+        category: &TransactionCategory,
+        amount: Money,
+    ) -> anyhow::Result<BudgetQuery> {
+        let sql = format!(
+            "INSERT INTO budgets AS b (user_id, category, amount, currency) VALUES ($1, $2, $3, $4) ON CONFLICT (user_id, category, currency) DO UPDATE SET amount = EXCLUDED.amount, updated_at = NOW() RETURNING {BUDGET_COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(category.to_string())
+                .bind(amount.to_decimal())
+                .bind(amount.currency.to_string())
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_budget(&row)
+    }
+
+    pub async fn get_budgets(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Vec<BudgetQuery>> {
+        let sql = format!(
+            "SELECT {BUDGET_COLUMNS} FROM budgets b WHERE b.user_id = $1 ORDER BY b.category, b.currency"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.iter().map(map_row_to_budget).collect()
+    }
+
+    /// The user's budgets with what was spent on each from `start` to `end`
+    pub async fn get_budgets_spent(
+        pool: &DbPool,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<(BudgetQuery, Money)>> {
+        let sql = format!(
+            "SELECT {BUDGET_COLUMNS}, COALESCE(-SUM(t.amount), 0) AS spent FROM budgets b LEFT JOIN transactions t ON t.user_id = b.user_id AND t.category = b.category AND t.currency = b.currency AND t.transaction_type = 'Expense' AND t.deleted_at IS NULL AND t.created_at >= $2 AND t.created_at < $3 WHERE b.user_id = $1 GROUP BY b.id ORDER BY b.category, b.currency"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(start)
+                .bind(end)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let budget = map_row_to_budget(row)?;
+                let spent: Decimal = row.try_get("spent")?;
+                let spent = Money::from_decimal_rounded(spent, budget.amount.currency)
+                    .map_err(|e| anyhow!(e))?;
+                Ok((budget, spent))
+            })
+            .collect()
+    }
+
+    /// Remove a budget, returning the user it belonged to, None when it did not exist
+    pub async fn delete_budget(pool: &DbPool, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let sql = "DELETE FROM budgets WHERE id = $1 RETURNING user_id";
+        let row = telemetry::observe(sql, sqlx::query(sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(|r| r.try_get("user_id"))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Record an alert for every budget whose spending this month, in its user's time zone,
+    /// crossed one of `thresholds` (percentages) without one being recorded yet
+    /// Returns how many alerts were recorded
+    pub async fn record_alerts(pool: &DbPool, thresholds: &[i16]) -> anyhow::Result<u64> {
+        let sql = "INSERT INTO budget_alerts (user_id, budget_id, category, threshold, period_start, spent, amount, currency) SELECT b.user_id, b.id, b.category, th.threshold, m.month, s.spent, b.amount, b.currency FROM budgets b JOIN users u ON u.id = b.user_id CROSS JOIN LATERAL (SELECT DATE_TRUNC('month', NOW() AT TIME ZONE u.timezone)::DATE AS month) m CROSS JOIN LATERAL (SELECT COALESCE(-SUM(t.amount), 0) AS spent FROM transactions t WHERE t.user_id = b.user_id AND t.category = b.category AND t.currency = b.currency AND t.transaction_type = 'Expense' AND t.deleted_at IS NULL AND t.created_at >= m.month::TIMESTAMP AT TIME ZONE u.timezone) s CROSS JOIN UNNEST($1::SMALLINT[]) AS th(threshold) WHERE s.spent * 100 >= b.amount * th.threshold ON CONFLICT (budget_id, threshold, period_start) DO NOTHING";
+        let result =
+            telemetry::observe(sql, sqlx::query(sql).bind(thresholds).execute(pool)).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    const ALERT_COLUMNS: &str = "id, user_id, budget_id, category, threshold, period_start, spent, amount, currency, created_at, acknowledged_at";
+
+    fn map_row_to_alert(row: PgRow) -> anyhow::Result<BudgetAlertQuery> {
+        let category: &str = row.try_get("category")?;
+        let currency = Currency::from_str(row.try_get("currency")?).map_err(|e| anyhow!(e))?;
+        let spent: Decimal = row.try_get("spent")?;
+        let amount: Decimal = row.try_get("amount")?;
+
+        Ok(BudgetAlertQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            budget_id: row.try_get("budget_id")?,
+            category: TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?,
+            threshold: row.try_get("threshold")?,
+            period_start: row.try_get("period_start")?,
+            spent: Money::from_decimal_rounded(spent, currency).map_err(|e| anyhow!(e))?,
+            budget: Money::from_decimal_rounded(amount, currency).map_err(|e| anyhow!(e))?,
+            created_at: row.try_get("created_at")?,
+            acknowledged_at: row.try_get("acknowledged_at")?,
+        })
+    }
+
+    /// The user's alerts, newest first, acknowledged ones only when asked for
+    pub async fn get_alerts(
+        pool: &DbPool,
+        user_id: Uuid,
+        include_acknowledged: bool,
+    ) -> anyhow::Result<Vec<BudgetAlertQuery>> {
+        let sql = format!(
+            "SELECT {ALERT_COLUMNS} FROM budget_alerts WHERE user_id = $1 AND ($2 OR acknowledged_at IS NULL) ORDER BY created_at DESC, threshold DESC"
+        );
+        let rows = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(user_id)
+                .bind(include_acknowledged)
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter().map(map_row_to_alert).collect()
+    }
+
+    /// Mark an alert as seen, acknowledging it again keeps the first time
+    /// Returns None when it does not exist
+    pub async fn acknowledge_alert(
+        pool: &DbPool,
+        id: Uuid,
+    ) -> anyhow::Result<Option<BudgetAlertQuery>> {
+        let sql = format!(
+            "UPDATE budget_alerts SET acknowledged_at = COALESCE(acknowledged_at, NOW()) WHERE id = $1 RETURNING {ALERT_COLUMNS}"
+        );
+        let row = telemetry::observe(&sql, sqlx::query(&sql).bind(id).fetch_optional(pool)).await?;
+
+        row.map(map_row_to_alert).transpose()
+    }
+}
+
+pub mod share_link_queries {
+    use crate::database::DbPool;
+    use crate::models::share_link_models::{ShareLinkCreate, ShareLinkQuery};
+    use crate::telemetry;
+    use sqlx::Row;
+    use sqlx::postgres::PgRow;
+    use uuid::Uuid;
+
+    const COLUMNS: &str =
+        "id, user_id, title, report, snapshot, created_at, expires_at, revoked_at";
+
+    fn map_row_to_share_link(row: PgRow) -> anyhow::Result<ShareLinkQuery> {
+        Ok(ShareLinkQuery {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            title: row.try_get("title")?,
+            report: serde_json::from_value(row.try_get("report")?)?,
+            snapshot: row.try_get("snapshot")?,
+            created_at: row.try_get("created_at")?,
+            expires_at: row.try_get("expires_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+        })
+    }
+
+    pub async fn create(pool: &DbPool, link: &ShareLinkCreate) -> anyhow::Result<ShareLinkQuery> {
+        let sql = format!(
+            "INSERT INTO share_links (id, user_id, token_hash, title, report, snapshot, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {COLUMNS}"
+        );
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql)
+                .bind(Uuid::new_v4())
+                .bind(link.user_id)
+                .bind(&link.token_hash)
+                .bind(&link.title)
+                .bind(serde_json::to_value(&link.report)?)
+                .bind(&link.snapshot)
+                .bind(link.expires_at)
+                .fetch_one(pool),
+        )
+        .await?;
+
+        map_row_to_share_link(row)
+    }
+
+    /// The share link with this token hash, whether or not it still works
+    pub async fn get_by_hash(
+        pool: &DbPool,
+        token_hash: &str,
+    ) -> anyhow::Result<Option<ShareLinkQuery>> {
+        let sql = format!("SELECT {COLUMNS} FROM share_links WHERE token_hash = $1");
+        let row = telemetry::observe(
+            &sql,
+            sqlx::query(&sql).bind(token_hash).fetch_optional(pool),
+        )
+        .await?;
+
+        row.map(map_row_to_share_link).transpose()
+    }
+
+    /// The user's share links not revoked and not expired, newest first
+    pub async fn get_active(pool: &DbPool, user_id: Uuid) -> anyhow::Result<Vec<ShareLinkQuery>> {
+        let sql = format!(
+            "SELECT {COLUMNS} FROM share_links WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW() ORDER BY created_at DESC"
+        );
+        let rows =
+            telemetry::observe(&sql, sqlx::query(&sql).bind(user_id).fetch_all(pool)).await?;
+
+        rows.into_iter().map(map_row_to_share_link).collect()
+    }
+
+    /// Stop a link of the user from working, kept for the record. Returns false when the
+    /// user has no such link, or it was revoked already
+    pub async fn revoke(pool: &DbPool, id: Uuid, user_id: Uuid) -> anyhow::Result<bool> {
+        let sql = "UPDATE share_links SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL";
+        let result =
+            telemetry::observe(sql, sqlx::query(sql).bind(id).bind(user_id).execute(pool)).await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+pub mod suggestion_queries {
+    use crate::crypto;
+    use crate::database::DbPool;
+    use crate::models::suggestion_models::{CategoryModel, CategoryModelQuery};
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::telemetry;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use sqlx::Row;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// Users with transactions changed since their latest model was trained, or without any
+    pub async fn get_stale_users(pool: &DbPool) -> anyhow::Result<Vec<Uuid>> {
+        let sql = "SELECT DISTINCT t.user_id FROM transactions t LEFT JOIN (SELECT user_id, MAX(trained_at) AS trained_at FROM category_models GROUP BY user_id) m ON m.user_id = t.user_id WHERE m.trained_at IS NULL OR t.last_updated_at > m.trained_at";
+        let user_ids = telemetry::observe(sql, sqlx::query_scalar(sql).fetch_all(pool)).await?;
+        Ok(user_ids)
+    }
+
+    /// The user's categorized transactions to learn from, each the merchant name enrichment
+    /// found or else the description, with its category. Deleted transactions, those still
+    /// in Uncategorized and those with a description encrypted on the client are left out
+    pub async fn get_training_rows(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Vec<(String, TransactionCategory)>> {
+        let sql = "SELECT merchant_name, description, category FROM transactions WHERE user_id = $1 AND deleted_at IS NULL AND encrypted_description IS NULL AND category <> $2";
+        let rows = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(TransactionCategory::Uncategorized.to_string())
+                .fetch_all(pool),
+        )
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let merchant: Option<String> = row.try_get("merchant_name")?;
+                let merchant = match merchant {
+                    Some(merchant) => merchant,
+                    None => crypto::decrypt_field(row.try_get("description")?)?,
+                };
+                let category: &str = row.try_get("category")?;
+                let category = TransactionCategory::from_str(category).map_err(|e| anyhow!(e))?;
+                Ok((merchant, category))
+            })
+            .collect()
+    }
+
+    /// The latest version of the user's model, None when none was trained yet
+    pub async fn get_latest(
+        pool: &DbPool,
+        user_id: Uuid,
+    ) -> anyhow::Result<Option<CategoryModelQuery>> {
+        let sql = "SELECT user_id, version, trained_at, transactions, model FROM category_models WHERE user_id = $1 ORDER BY version DESC LIMIT 1";
+        let row =
+            telemetry::observe(sql, sqlx::query(sql).bind(user_id).fetch_optional(pool)).await?;
+
+        row.map(|row| {
+            Ok(CategoryModelQuery {
+                user_id: row.try_get("user_id")?,
+                version: row.try_get("version")?,
+                trained_at: row.try_get("trained_at")?,
+                transactions: row.try_get("transactions")?,
+                model: serde_json::from_value(row.try_get("model")?)?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Record a new version of the user's model, keeping the latest `kept` versions
+    /// Returns the new version
+    pub async fn store(
+        pool: &DbPool,
+        user_id: Uuid,
+        model: &CategoryModel,
+        transactions: i32,
+        trained_at: DateTime<Utc>,
+        kept: i32,
+    ) -> anyhow::Result<i32> {
+        let mut tx = pool.begin().await?;
+        let sql = "INSERT INTO category_models (user_id, version, trained_at, transactions, model) SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4 FROM category_models WHERE user_id = $1 RETURNING version";
+        let row = telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(trained_at)
+                .bind(transactions)
+                .bind(serde_json::to_value(model)?)
+                .fetch_one(&mut *tx),
+        )
+        .await?;
+        let version: i32 = row.try_get("version")?;
+
+        let sql = "DELETE FROM category_models WHERE user_id = $1 AND version <= $2";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(version - kept)
+                .execute(&mut *tx),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(version)
+    }
+
+    /// Mark the latest version as trained at `trained_at`, for retraining that learned
+    /// nothing new
+    pub async fn touch(
+        pool: &DbPool,
+        user_id: Uuid,
+        version: i32,
+        trained_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let sql = "UPDATE category_models SET trained_at = $3 WHERE user_id = $1 AND version = $2";
+        telemetry::observe(
+            sql,
+            sqlx::query(sql)
+                .bind(user_id)
+                .bind(version)
+                .bind(trained_at)
+                .execute(pool),
+        )
+        .await?;
+        Ok(())
     }
 }
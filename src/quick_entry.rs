@@ -0,0 +1,96 @@
+use crate::ingest::parsers::{parse_number, symbol_currency};
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::{
+    TransactionCategory, TransactionCreate, TransactionSource, TransactionType,
+};
+use crate::validation::{MAX_DESCRIPTION_LEN, Validate, ValidationErrors};
+use regex::Regex;
+use std::str::FromStr;
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+/// Examples shown by the chat integrations' help messages
+pub const EXAMPLES: &str = "12.50 lunch\n\
+    €8 coffee #restaurant\n\
+    30 GBP taxi\n\
+    +2000 salary (a leading + records income)";
+
+static QUICK_ENTRY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?s)^(?P<sign>[+-])?\s*(?P<symbol>[€$£¥])?\s*(?P<number>\d[\d.,']*)\s*(?P<rest>.*)$",
+    )
+    .expect("quick entry regex is valid")
+});
+
+/// A transaction typed in a chat: "12.50 lunch", "€8 coffee #restaurant", "30 GBP taxi" or "+2000 salary"
+#[derive(Debug)]
+pub struct QuickEntry {
+    pub transaction_type: TransactionType,
+    /// Unsigned, in the given currency or USD
+    pub amount: Money,
+    /// Given with a "#category" tag anywhere in the note
+    pub category: Option<TransactionCategory>,
+    pub description: String,
+}
+
+impl QuickEntry {
+    pub fn parse(text: &str) -> Option<Self> {
+        let caps = QUICK_ENTRY.captures(text.trim())?;
+        let amount = parse_number(&caps["number"])?;
+        let mut rest = caps["rest"].trim();
+
+        let mut currency = match caps.name("symbol") {
+            Some(symbol) => symbol_currency(symbol.as_str())?,
+            None => Currency::default(),
+        };
+        if caps.name("symbol").is_none() {
+            let (word, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if let Ok(code) = Currency::from_str(&word.to_ascii_uppercase()) {
+                currency = code;
+                rest = after.trim();
+            }
+        }
+
+        // Unknown tags are kept as part of the note
+        let mut category = None;
+        let mut words = Vec::new();
+        for word in rest.split_whitespace() {
+            match word
+                .strip_prefix('#')
+                .and_then(|tag| TransactionCategory::from_str(tag).ok())
+            {
+                Some(tag) if category.is_none() => category = Some(tag),
+                _ => words.push(word),
+            }
+        }
+
+        let transaction_type = match caps.name("sign").map(|s| s.as_str()) {
+            Some("+") => TransactionType::Income,
+            _ => TransactionType::Expense,
+        };
+        Some(Self {
+            transaction_type,
+            amount: Money::from_decimal(amount, currency).ok()?,
+            category,
+            description: words.join(" "),
+        })
+    }
+
+    pub fn into_transaction(self, user_id: Uuid, source: TransactionSource) -> TransactionCreate {
+        TransactionCreate::new(
+            user_id,
+            self.transaction_type,
+            self.amount,
+            self.category,
+            Some(self.description),
+        )
+        .with_origin(source, None)
+    }
+}
+
+impl Validate for QuickEntry {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check_amount("amount", self.amount);
+        errors.check_length("description", &self.description, MAX_DESCRIPTION_LEN);
+    }
+}
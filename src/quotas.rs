@@ -0,0 +1,440 @@
+// Usage quotas by plan, for hosted deployments
+//
+// Every user is on a plan (users.plan, "free" unless changed). Plans and their limits come
+// from PLANS, quotas are only counted and enforced when it is set. Writes are checked before
+// they happen: going over the monthly transactions or the storage of the plan is answered
+// with 402 Payment Required (a bigger plan helps), going over the daily API calls with
+// 429 Too Many Requests and a Retry-After until the next day. Periods are UTC months and days.
+
+use crate::database::DbPool;
+use crate::problems::ErrorCode;
+use crate::queries::{quota_queries, user_queries, webhook_queries};
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+/// Plan of users whose plan was never changed
+pub const DEFAULT_PLAN: &str = "free";
+
+/// Counter of transactions recorded, per month
+const TRANSACTIONS: &str = "transactions";
+
+/// Counter of write requests, per day
+const API_CALLS: &str = "api_calls";
+
+/// Total size of attachments, not a counter but summed up when a file is uploaded
+const STORAGE: &str = "storage";
+
+/// Share of a limit, in percent, past which the user is warned
+const WARNING_PERCENT: i64 = 80;
+
+static PLANS: OnceLock<HashMap<String, Plan>> = OnceLock::new();
+
+/// Limits of a plan, None is unlimited
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub name: String,
+    pub transactions_per_month: Option<i64>,
+    /// Total size of the user's attachments
+    pub storage_bytes: Option<i64>,
+    pub api_calls_per_day: Option<i64>,
+}
+
+impl Plan {
+    fn unlimited(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            transactions_per_month: None,
+            storage_bytes: None,
+            api_calls_per_day: None,
+        }
+    }
+
+    fn limit(&self, metric: &str) -> Option<i64> {
+        match metric {
+            TRANSACTIONS => self.transactions_per_month,
+            API_CALLS => self.api_calls_per_day,
+            STORAGE => self.storage_bytes,
+            _ => None,
+        }
+    }
+}
+
+/// Parse "plan:limit=value,limit=value;plan:...", e.g.
+/// "free:transactions=500,storage_mb=100,api_calls=1000;pro:api_calls=100000"
+/// Limits are transactions (per month), storage_mb and api_calls (per day), left out is unlimited
+///
+/// # Errors
+/// Returns an error if an entry is malformed or names an unknown limit
+pub fn parse_plans(raw: &str) -> anyhow::Result<Vec<Plan>> {
+    raw.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, limits) = entry.split_once(':').unwrap_or((entry, ""));
+            let mut plan = Plan::unlimited(name.trim());
+            for limit in limits.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+                let (key, value) = limit
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("Plan limits must be limit=value"))?;
+                let value = value.trim().parse::<i64>().map_err(|e| {
+                    anyhow::anyhow!("Invalid {} limit of plan {}: {}", key, plan.name, e)
+                })?;
+                match key.trim() {
+                    "transactions" => plan.transactions_per_month = Some(value),
+                    "storage_mb" => plan.storage_bytes = Some(value * 1024 * 1024),
+                    "api_calls" => plan.api_calls_per_day = Some(value),
+                    other => return Err(anyhow::anyhow!("Unknown plan limit: {}", other)),
+                }
+            }
+            Ok(plan)
+        })
+        .collect()
+}
+
+/// Enable quotas with the configured plans, call once at startup
+pub fn init(plans: Vec<Plan>) {
+    let plans = plans.into_iter().map(|p| (p.name.clone(), p)).collect();
+    if PLANS.set(plans).is_err() {
+        eprintln!("Plans already initialized, ignoring");
+    }
+}
+
+/// The configured plans, None when quotas are disabled
+pub fn plans() -> Option<&'static HashMap<String, Plan>> {
+    PLANS.get()
+}
+
+/// A quota a write would go over
+#[derive(Debug)]
+pub enum QuotaExceeded {
+    Transactions { plan: String, limit: i64 },
+    ApiCalls { plan: String, limit: i64 },
+    Storage { plan: String, limit: i64 },
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaExceeded::Transactions { plan, limit } => write!(
+                f,
+                "The {} plan allows {} transactions per month, upgrade to record more",
+                plan, limit
+            ),
+            QuotaExceeded::ApiCalls { plan, limit } => write!(
+                f,
+                "The {} plan allows {} requests per day, try again tomorrow",
+                plan, limit
+            ),
+            QuotaExceeded::Storage { plan, limit } => write!(
+                f,
+                "The {} plan allows {} MB of attachments, upgrade to store more",
+                plan,
+                limit / (1024 * 1024)
+            ),
+        }
+    }
+}
+
+impl IntoResponse for QuotaExceeded {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        match self {
+            QuotaExceeded::Transactions { plan, limit } => (
+                StatusCode::PAYMENT_REQUIRED,
+                Json(json!({
+                    "message": message,
+                    "code": ErrorCode::QuotaExceeded,
+                    "quota": TRANSACTIONS,
+                    "plan": plan,
+                    "limit": limit
+                })),
+            )
+                .into_response(),
+            QuotaExceeded::ApiCalls { plan, limit } => {
+                let now = Utc::now();
+                let tomorrow = (now.date_naive() + Duration::days(1))
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap_or_default()
+                    .and_utc();
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(
+                        header::RETRY_AFTER,
+                        (tomorrow - now).num_seconds().max(1).to_string(),
+                    )],
+                    Json(json!({
+                        "message": message,
+                        "code": ErrorCode::QuotaExceeded,
+                        "quota": API_CALLS,
+                        "plan": plan,
+                        "limit": limit
+                    })),
+                )
+                    .into_response()
+            }
+            QuotaExceeded::Storage { plan, limit } => (
+                StatusCode::PAYMENT_REQUIRED,
+                Json(json!({
+                    "message": message,
+                    "code": ErrorCode::QuotaExceeded,
+                    "quota": STORAGE,
+                    "plan": plan,
+                    "limit": limit
+                })),
+            )
+                .into_response(),
+        }
+    }
+}
+
+fn month_start(day: NaiveDate) -> NaiveDate {
+    day.with_day(1).unwrap_or(day)
+}
+
+/// Plan of the user, unknown plans (e.g. removed from PLANS) are unlimited
+pub async fn user_plan(db: &DbPool, user_id: Uuid) -> anyhow::Result<Plan> {
+    let name = user_queries::get_plan(db, user_id).await?;
+    Ok(plans()
+        .and_then(|plans| plans.get(&name))
+        .cloned()
+        .unwrap_or_else(|| Plan::unlimited(&name)))
+}
+
+/// Plan of the user when quotas are enforced
+pub async fn enforced_plan(db: &DbPool, user_id: Uuid) -> anyhow::Result<Option<Plan>> {
+    if plans().is_none() {
+        return Ok(None);
+    }
+    user_plan(db, user_id).await.map(Some)
+}
+
+/// Count a write request recording up to `transactions` transactions against the user's quotas
+/// Returns what would be exceeded instead, nothing more is counted then. Transactions that
+/// end up not being recorded (duplicates, failures) are given back with `release_transactions`
+pub async fn reserve_write(
+    db: &DbPool,
+    user_id: Uuid,
+    transactions: i64,
+) -> anyhow::Result<Option<QuotaExceeded>> {
+    if plans().is_none() {
+        return Ok(None);
+    }
+    let plan = user_plan(db, user_id).await?;
+    let today = Utc::now().date_naive();
+    let Some(calls) =
+        quota_queries::add(db, user_id, API_CALLS, today, 1, plan.api_calls_per_day).await?
+    else {
+        return Ok(plan.api_calls_per_day.map(|limit| QuotaExceeded::ApiCalls {
+            plan: plan.name.clone(),
+            limit,
+        }));
+    };
+    warn_if_near_limit(db, user_id, &plan, API_CALLS, today, calls - 1, calls).await;
+    add_transactions(db, user_id, &plan, transactions).await
+}
+
+/// Count `transactions` more transactions of a write already counted with `reserve_write`
+pub async fn reserve_transactions(
+    db: &DbPool,
+    user_id: Uuid,
+    transactions: i64,
+) -> anyhow::Result<Option<QuotaExceeded>> {
+    if plans().is_none() {
+        return Ok(None);
+    }
+    let plan = user_plan(db, user_id).await?;
+    add_transactions(db, user_id, &plan, transactions).await
+}
+
+async fn add_transactions(
+    db: &DbPool,
+    user_id: Uuid,
+    plan: &Plan,
+    transactions: i64,
+) -> anyhow::Result<Option<QuotaExceeded>> {
+    if transactions <= 0 {
+        return Ok(None);
+    }
+    let month = month_start(Utc::now().date_naive());
+    let added = quota_queries::add(
+        db,
+        user_id,
+        TRANSACTIONS,
+        month,
+        transactions,
+        plan.transactions_per_month,
+    )
+    .await?;
+    let Some(count) = added else {
+        return Ok(plan
+            .transactions_per_month
+            .map(|limit| QuotaExceeded::Transactions {
+                plan: plan.name.clone(),
+                limit,
+            }));
+    };
+    warn_if_near_limit(
+        db,
+        user_id,
+        plan,
+        TRANSACTIONS,
+        month,
+        count - transactions,
+        count,
+    )
+    .await;
+    Ok(None)
+}
+
+/// Warn the user about the attachments they just stored taking them close to the storage
+/// limit of their plan
+pub async fn storage_added(db: &DbPool, user_id: Uuid, plan: &Plan, size_bytes: i64) {
+    if plan.storage_bytes.is_none() {
+        return;
+    }
+    match quota_queries::get_storage_bytes(db, user_id).await {
+        Ok(total) => {
+            let month = month_start(Utc::now().date_naive());
+            warn_if_near_limit(db, user_id, plan, STORAGE, month, total - size_bytes, total).await;
+        }
+        Err(e) => eprintln!("Error computing storage usage: {}", e),
+    }
+}
+
+fn near_limit(used: i64, limit: i64) -> bool {
+    used.saturating_mul(100) >= limit.saturating_mul(WARNING_PERCENT)
+}
+
+/// Send the "quota.warning" event when usage went from `before` to `after` past the warning
+/// share of the plan's limit, unless the user was already warned in the period
+/// Failures are logged, the write itself went through
+async fn warn_if_near_limit(
+    db: &DbPool,
+    user_id: Uuid,
+    plan: &Plan,
+    metric: &'static str,
+    period_start: NaiveDate,
+    before: i64,
+    after: i64,
+) {
+    let Some(limit) = plan.limit(metric) else {
+        return;
+    };
+    if near_limit(before, limit) || !near_limit(after, limit) {
+        return;
+    }
+    let warned = async {
+        if !quota_queries::record_warning(db, user_id, metric, period_start).await? {
+            return Ok(());
+        }
+        let data = json!({
+            "quota": metric,
+            "plan": plan.name,
+            "used": after,
+            "limit": limit,
+            "period_start": period_start,
+        });
+        webhook_queries::enqueue(db, user_id, "quota.warning", &data).await?;
+        anyhow::Ok(())
+    };
+    if let Err(e) = warned.await {
+        eprintln!("Error sending the {} quota warning: {}", metric, e);
+    }
+}
+
+/// Give back transactions reserved with `reserve_write` that were not recorded
+/// Failures are logged, at worst the user can record a few transactions less this month
+pub async fn release_transactions(db: &DbPool, user_id: Uuid, transactions: i64) {
+    if plans().is_none() || transactions <= 0 {
+        return;
+    }
+    let month = month_start(Utc::now().date_naive());
+    if let Err(e) = quota_queries::subtract(db, user_id, TRANSACTIONS, month, transactions).await {
+        eprintln!("Error releasing reserved transactions: {}", e);
+    }
+}
+
+/// Where usage stands against a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaStatus {
+    Ok,
+    /// At or past the warning share of the limit
+    Warning,
+    /// At the limit, further writes are refused
+    Exceeded,
+}
+
+/// Usage of one quota in its current period
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaUsage {
+    pub quota: &'static str,
+    pub used: i64,
+    /// None is unlimited
+    pub limit: Option<i64>,
+    pub status: QuotaStatus,
+    /// When the counter starts over, None for storage
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+impl QuotaUsage {
+    fn new(
+        quota: &'static str,
+        used: i64,
+        limit: Option<i64>,
+        resets_at: Option<NaiveDate>,
+    ) -> Self {
+        let status = match limit {
+            Some(limit) if used >= limit => QuotaStatus::Exceeded,
+            Some(limit) if near_limit(used, limit) => QuotaStatus::Warning,
+            _ => QuotaStatus::Ok,
+        };
+        Self {
+            quota,
+            used,
+            limit,
+            status,
+            resets_at: resets_at
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+                .map(|t| t.and_utc()),
+        }
+    }
+}
+
+/// The user's plan and their usage of each of its quotas
+/// Counters only run while quotas are enabled, transactions and API calls are 0 otherwise
+pub async fn usage(db: &DbPool, user_id: Uuid) -> anyhow::Result<(Plan, Vec<QuotaUsage>)> {
+    let plan = user_plan(db, user_id).await?;
+    let today = Utc::now().date_naive();
+    let month = month_start(today);
+
+    let transactions = quota_queries::get_count(db, user_id, TRANSACTIONS, month).await?;
+    let api_calls = quota_queries::get_count(db, user_id, API_CALLS, today).await?;
+    let storage = quota_queries::get_storage_bytes(db, user_id).await?;
+    let usage = vec![
+        QuotaUsage::new(
+            TRANSACTIONS,
+            transactions,
+            plan.transactions_per_month,
+            month.checked_add_months(Months::new(1)),
+        ),
+        QuotaUsage::new(
+            API_CALLS,
+            api_calls,
+            plan.api_calls_per_day,
+            Some(today + Duration::days(1)),
+        ),
+        QuotaUsage::new(STORAGE, storage, plan.storage_bytes, None),
+    ];
+    Ok((plan, usage))
+}
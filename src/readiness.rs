@@ -0,0 +1,70 @@
+// Readiness signal for process supervisors
+//
+// Once the migrations ran and the listener is bound, the server tells whoever started it
+// that it takes requests, so dependent services can be started after it:
+//
+// - systemd, with READY=1 on $NOTIFY_SOCKET (sd_notify), for units with Type=notify
+// - anything else through READY_FILE, a JSON file {"pid": ..., "address": "..."} written
+//   then. It is removed at startup, a file left by a previous run never means ready
+
+use std::net::SocketAddr;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+/// Remove the readiness file of a previous run
+pub fn clear(ready_file: Option<&str>) -> anyhow::Result<()> {
+    let Some(path) = ready_file else {
+        return Ok(());
+    };
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(anyhow::anyhow!("Cannot remove READY_FILE {}: {}", path, e)),
+    }
+}
+
+/// Signal that the server listens on `address`
+pub fn signal(ready_file: Option<&str>, address: SocketAddr) -> anyhow::Result<()> {
+    if let Some(path) = ready_file {
+        let contents = serde_json::json!({
+            "pid": std::process::id(),
+            "address": address.to_string(),
+        });
+        // Written aside and renamed, readers never see a partial file
+        let partial = format!("{}.partial", path);
+        std::fs::write(&partial, contents.to_string())
+            .and_then(|()| std::fs::rename(&partial, path))
+            .map_err(|e| anyhow::anyhow!("Cannot write READY_FILE {}: {}", path, e))?;
+        tracing::info!(path, "Readiness file written");
+    }
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET") {
+        let state = format!("READY=1\nSTATUS=Listening on {}\n", address);
+        sd_notify(&socket.to_string_lossy(), &state)
+            .map_err(|e| anyhow::anyhow!("Cannot notify systemd on {:?}: {}", socket, e))?;
+        tracing::info!("Readiness notified to systemd");
+    }
+    Ok(())
+}
+
+/// Send a state to the service manager's socket, a path or an abstract name starting with @
+fn sd_notify(socket: &str, state: &str) -> std::io::Result<()> {
+    let sender = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(state.as_bytes(), &address)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(std::io::Error::other(
+                "abstract socket names are only supported on Linux",
+            ));
+        }
+        None => {
+            sender.send_to(state.as_bytes(), Path::new(socket))?;
+        }
+    }
+    Ok(())
+}
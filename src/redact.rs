@@ -0,0 +1,125 @@
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// How personal data (emails, amounts, descriptions) is written to logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionPolicy {
+    /// Log values as-is, only meant for local development
+    Off,
+    /// Replace values with a shape-preserving mask (e.g. "j***@example.com")
+    Mask,
+    /// Replace values with a short stable hash so log lines can still be correlated
+    Hash,
+}
+
+impl FromStr for RedactionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(RedactionPolicy::Off),
+            "mask" => Ok(RedactionPolicy::Mask),
+            "hash" => Ok(RedactionPolicy::Hash),
+            _ => Err(format!("Invalid log redaction policy: {}", s)),
+        }
+    }
+}
+
+/// Process-wide policy, defaults to masking when never initialized
+static POLICY: OnceLock<RedactionPolicy> = OnceLock::new();
+
+/// Install the logging policy, must be called once at startup
+pub fn init(policy: RedactionPolicy) {
+    if POLICY.set(policy).is_err() {
+        eprintln!("Log redaction policy already initialized, ignoring");
+    }
+}
+
+fn policy() -> RedactionPolicy {
+    POLICY.get().copied().unwrap_or(RedactionPolicy::Mask)
+}
+
+/// First 8 hex characters of the SHA-256 of a value
+fn short_hash(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    digest[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// An email address that is redacted when formatted
+pub struct Email<'a>(&'a str);
+
+impl fmt::Display for Email<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match policy() {
+            RedactionPolicy::Off => f.write_str(self.0),
+            RedactionPolicy::Hash => write!(f, "email#{}", short_hash(self.0)),
+            RedactionPolicy::Mask => match self.0.split_once('@') {
+                // Keep the first character and the domain, enough to tell test accounts apart
+                Some((local, domain)) => {
+                    let first = local.chars().next().map(String::from).unwrap_or_default();
+                    write!(f, "{}***@{}", first, domain)
+                }
+                None => f.write_str("***"),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Email<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+/// A money amount that is redacted when formatted
+pub struct Amount<T>(T);
+
+impl<T: fmt::Display> fmt::Display for Amount<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match policy() {
+            RedactionPolicy::Off => write!(f, "{}", self.0),
+            RedactionPolicy::Hash => write!(f, "amount#{}", short_hash(&self.0.to_string())),
+            RedactionPolicy::Mask => f.write_str("***"),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Debug for Amount<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Free text (descriptions, notes) that is redacted when formatted
+pub struct Text<'a>(&'a str);
+
+impl fmt::Display for Text<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match policy() {
+            RedactionPolicy::Off => f.write_str(self.0),
+            RedactionPolicy::Hash => write!(f, "text#{}", short_hash(self.0)),
+            // Only the length is kept, which is enough to spot empty or truncated input
+            RedactionPolicy::Mask => write!(f, "<{} chars>", self.0.chars().count()),
+        }
+    }
+}
+
+impl fmt::Debug for Text<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+pub fn email(value: &str) -> Email<'_> {
+    Email(value)
+}
+
+pub fn amount<T: fmt::Display>(value: T) -> Amount<T> {
+    Amount(value)
+}
+
+pub fn text(value: &str) -> Text<'_> {
+    Text(value)
+}
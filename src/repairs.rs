@@ -0,0 +1,113 @@
+// Data repairs run by operators
+//
+// POST /api/admin/repairs/<repair> queues a job doing one of the common fixes, so large ones
+// run outside the request and are retried like any job, their progress listed at
+// /api/admin/jobs. Each repair is audited when it is queued and again, with what it changed,
+// when its job is done.
+//
+// - reassign-transactions moves transactions recorded under the wrong user to the right
+//   one. Account and bank account links, which are the previous user's, are cleared, and
+//   transactions in a reconciled period or with a client-encrypted description (only the
+//   previous user's key reads it) are left where they are. The previous user's synced
+//   clients keep their copies until they sync from scratch.
+// - recategorize queues merchant enrichment again for the transactions still in Other or
+//   Uncategorized, of one user or everyone, e.g. after MERCHANT_API_URL was configured or
+//   the merchant API learned new descriptors. It needs MERCHANT_API_URL.
+//
+// Account balances, budget statuses and reports are computed from the transactions on every
+// read, and transactions have no search index, so there are no snapshots to recompute nor
+// search vectors to rebuild.
+
+use crate::database::DbPool;
+use crate::jobs::{self, JobHandler};
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::job_models::JobCreate;
+use crate::models::transaction_models::{ReassignTransactionsRequest, RecategorizeRequest};
+use crate::queries::{audit_queries, transaction_queries};
+use axum::async_trait;
+use serde_json::{Value, json};
+
+/// Record what a repair changed, a failure to do so does not undo the repair
+async fn audit(db: &DbPool, kind: &str, details: Value) {
+    let entry = AuditEntryCreate::new("repair", "job", None).details(json!({
+        "kind": kind,
+        "result": details,
+    }));
+    if let Err(e) = audit_queries::append(db, &entry).await {
+        tracing::error!(kind, error = %e, "Recording audit entry failed");
+    }
+}
+
+/// Job moving transactions from one user to another
+pub struct ReassignTransactionsJob;
+
+#[async_trait]
+impl JobHandler for ReassignTransactionsJob {
+    fn kind(&self) -> &'static str {
+        "repairs.reassign_transactions"
+    }
+
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()> {
+        let req: ReassignTransactionsRequest = serde_json::from_value(payload.clone())?;
+        let outcome = transaction_queries::reassign_transactions(
+            db,
+            req.from_user_id,
+            req.to_user_id,
+            req.transaction_ids.as_deref(),
+        )
+        .await?;
+        audit(
+            db,
+            self.kind(),
+            json!({
+                "from_user_id": req.from_user_id,
+                "to_user_id": req.to_user_id,
+                "moved": outcome.moved,
+                "left": outcome.left,
+            }),
+        )
+        .await;
+        tracing::info!(
+            moved = outcome.moved,
+            left = outcome.left,
+            "Transactions moved to another user"
+        );
+        Ok(())
+    }
+}
+
+/// Job queueing merchant enrichment again for transactions without a category
+pub struct RecategorizeJob;
+
+#[async_trait]
+impl JobHandler for RecategorizeJob {
+    fn kind(&self) -> &'static str {
+        "repairs.recategorize"
+    }
+
+    async fn run(&self, db: &DbPool, payload: &Value) -> anyhow::Result<()> {
+        let req: RecategorizeRequest = serde_json::from_value(payload.clone())?;
+        let mut queued = 0;
+        for (transaction_id, user_id) in
+            transaction_queries::get_recategorizable(db, req.user_id).await?
+        {
+            // Like the enrichment queued when a transaction is created, at most once at a time
+            let job = JobCreate::new(
+                "transactions.enrich",
+                json!({ "transaction_id": transaction_id, "user_id": user_id }),
+            )
+            .dedupe_key(transaction_id.to_string());
+            if jobs::enqueue(db, job).await?.is_some() {
+                queued += 1;
+            }
+        }
+        audit(
+            db,
+            self.kind(),
+            json!({ "user_id": req.user_id, "queued": queued }),
+        )
+        .await;
+        tracing::info!(queued, "Transaction enrichment queued");
+        Ok(())
+    }
+}
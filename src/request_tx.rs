@@ -0,0 +1,165 @@
+// Database transactions spanning a request
+//
+// Handlers making several writes that belong together take a `Tx` and run their queries on
+// the connection of `Tx::conn` instead of the pool. The transaction is begun on the first
+// call, once the handler has read and checked the request, so a slow or large body never
+// holds a connection of the pool. It is ended by the `commit_on_success` middleware once the
+// handler answered: committed when the answer is a success, rolled back otherwise (an error,
+// a refusal such as 402 or 409, a panic), so a failure halfway leaves nothing behind.
+//
+// Change events of the writes are handed to `Tx::publish_on_commit`, audit entries to
+// `Tx::audit_on_commit` and webhook events to `Tx::enqueue_on_commit`. They are published,
+// appended and queued once the transaction is committed, the audit log and subscribers never
+// hear of rows that were rolled back.
+
+use crate::database::DbPool;
+use crate::events::{self, ChangeEvent};
+use crate::handlers::AppState;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::queries::{audit_queries, webhook_queries};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{StatusCode, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use sqlx::{PgConnection, Postgres, Transaction};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// The transaction of a request, once begun, and what to do once it is committed
+#[derive(Default)]
+struct Pending {
+    tx: Option<Transaction<'static, Postgres>>,
+    events: Vec<ChangeEvent>,
+    audits: Vec<AuditEntryCreate>,
+    /// User, event and data of each webhook event
+    webhooks: Vec<(Uuid, String, Value)>,
+}
+
+/// Where the `Tx` of a request leaves its transaction for the middleware to end
+#[derive(Clone, Default)]
+struct Slot(Arc<Mutex<Option<Pending>>>);
+
+impl Slot {
+    fn put(&self, pending: Pending) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some(pending);
+        }
+    }
+
+    fn take(&self) -> Option<Pending> {
+        self.0.lock().ok().and_then(|mut slot| slot.take())
+    }
+}
+
+/// The request's database transaction, begun by the first `conn`
+pub struct Tx {
+    db: DbPool,
+    slot: Slot,
+    pending: Pending,
+}
+
+impl Tx {
+    /// The connection to run the request's queries on, beginning the transaction if need be
+    ///
+    /// # Errors
+    /// Returns an error if the transaction cannot be begun
+    pub async fn conn(&mut self) -> sqlx::Result<&mut PgConnection> {
+        let tx = match self.pending.tx.take() {
+            Some(tx) => tx,
+            None => self.db.begin().await?,
+        };
+        Ok(&mut **self.pending.tx.insert(tx))
+    }
+
+    /// Publish a change event of a write made in the transaction once it is committed
+    pub fn publish_on_commit(&mut self, event: ChangeEvent) {
+        self.pending.events.push(event);
+    }
+
+    /// Append an entry to the audit log once the transaction is committed
+    pub fn audit_on_commit(&mut self, entry: AuditEntryCreate) {
+        self.pending.audits.push(entry);
+    }
+
+    /// Queue an event for the user's webhook endpoints once the transaction is committed
+    pub fn enqueue_on_commit(&mut self, user_id: Uuid, event: &str, data: Value) {
+        self.pending
+            .webhooks
+            .push((user_id, event.to_string(), data));
+    }
+}
+
+impl Drop for Tx {
+    /// The handler is done with it, hand the transaction over to the middleware
+    fn drop(&mut self) {
+        self.slot.put(std::mem::take(&mut self.pending));
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Tx {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(slot) = parts.extensions.get::<Slot>().cloned() else {
+            eprintln!("Tx extracted on a route without the commit_on_success middleware");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        };
+        Ok(Tx {
+            db: state.db.clone(),
+            slot,
+            pending: Pending::default(),
+        })
+    }
+}
+
+/// Commit the transaction of a handler that took a `Tx` when it answered with a success,
+/// roll it back otherwise. Requests whose handler took none go through untouched
+pub async fn commit_on_success(State(db): State<DbPool>, mut req: Request, next: Next) -> Response {
+    let slot = Slot::default();
+    req.extensions_mut().insert(slot.clone());
+    let response = next.run(req).await;
+
+    let Some(pending) = slot.take() else {
+        return response;
+    };
+    if !response.status().is_success() {
+        if let Some(tx) = pending.tx
+            && let Err(e) = tx.rollback().await
+        {
+            eprintln!("Error rolling back request transaction: {}", e);
+        }
+        return response;
+    }
+    if let Some(tx) = pending.tx
+        && let Err(e) = tx.commit().await
+    {
+        eprintln!("Error committing request transaction: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    for event in pending.events {
+        events::publish(&db, event).await;
+    }
+    // Like record_audit and webhooks::enqueue, a failure is logged but does not fail the request
+    for entry in pending.audits {
+        if let Err(e) = audit_queries::append(&db, &entry).await {
+            eprintln!(
+                "Error recording audit entry {} {}: {}",
+                entry.action, entry.entity_type, e
+            );
+        }
+    }
+    for (user_id, event, data) in pending.webhooks {
+        if let Err(e) = webhook_queries::enqueue(&db, user_id, &event, &data).await {
+            tracing::error!(event, error = %e, "Queueing webhook failed");
+        }
+    }
+    response
+}
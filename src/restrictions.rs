@@ -0,0 +1,345 @@
+// Restrictions on the transactions users may record
+//
+// Transactions are only booked to the user's own accounts, in the account's currency. Child
+// accounts only spend in the categories their parent allowed. Hard spending limits (see
+// limit_models) refuse expenses going over them, limits that allow it only once the user
+// confirms the override. Every path recording new transactions (the
+// API, imports, sync, the chat integrations and the Firefly III API) checks them here first
+// and answers a refusal the way it answers its other errors: a response, a sync conflict, a
+// rejected import row or a chat reply.
+
+use crate::database::DbPool;
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::limit_models::SpendingLimitQuery;
+use crate::models::money_models::{Currency, Money};
+use crate::models::transaction_models::{TransactionCategory, TransactionCreate, TransactionType};
+use crate::problems::ErrorCode;
+use crate::queries::{account_queries, audit_queries, family_queries, limit_queries, user_queries};
+use crate::validation::ValidationErrors;
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+/// Why a new transaction may not be recorded
+#[derive(Debug, Clone)]
+pub enum Refusal {
+    /// Booked to an account that is not the user's, or is in another currency
+    Account { row: usize, message: String },
+    /// An expense of a child account in a category their parent did not allow
+    CategoryNotAllowed {
+        row: usize,
+        category: TransactionCategory,
+        allowed: Vec<TransactionCategory>,
+    },
+    /// The expense goes over a spending limit that does not allow it, or whose override
+    /// was not confirmed. `spent` is what counted against the limit before it
+    SpendingLimitExceeded {
+        row: usize,
+        limit: SpendingLimitQuery,
+        spent: Money,
+    },
+}
+
+impl Refusal {
+    /// Position of the refused transaction among those checked
+    pub fn row(&self) -> usize {
+        match self {
+            Refusal::Account { row, .. }
+            | Refusal::CategoryNotAllowed { row, .. }
+            | Refusal::SpendingLimitExceeded { row, .. } => *row,
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::Account { message, .. } => write!(f, "The account {}", message),
+            Refusal::CategoryNotAllowed { category, .. } => {
+                write!(f, "{} expenses are not allowed for this account", category)
+            }
+            Refusal::SpendingLimitExceeded { limit, .. } if limit.allow_override => f.write_str(
+                "This transaction goes over a spending limit, confirm it to record it anyway",
+            ),
+            Refusal::SpendingLimitExceeded { .. } => {
+                f.write_str("This transaction goes over a spending limit")
+            }
+        }
+    }
+}
+
+impl IntoResponse for Refusal {
+    fn into_response(self) -> Response {
+        let message = self.to_string();
+        match self {
+            Refusal::Account { message, .. } => {
+                let mut errors = ValidationErrors::default();
+                errors.add("account_id", message);
+                errors.into_response()
+            }
+            Refusal::CategoryNotAllowed { row, allowed, .. } => (
+                StatusCode::FORBIDDEN,
+                Json(json!({
+                    "message": message,
+                    "code": ErrorCode::CategoryNotAllowed,
+                    "row": row,
+                    "allowed_categories": allowed
+                })),
+            )
+                .into_response(),
+            Refusal::SpendingLimitExceeded { row, limit, spent } => (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": message,
+                    "code": ErrorCode::SpendingLimitExceeded,
+                    "row": row,
+                    "override_allowed": limit.allow_override,
+                    "spending_limit": limit,
+                    "spent": spent
+                })),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// What checking new transactions found
+#[derive(Debug, Default)]
+pub struct Checked {
+    /// The transactions that may not be recorded, by row
+    pub refused: Vec<Refusal>,
+    /// Spending limits gone over with the override confirmed, with the row going over each
+    pub overridden: Vec<(usize, Uuid)>,
+}
+
+impl Checked {
+    /// The first refusal, for writes recording all of their transactions or none
+    pub fn refusal(&self) -> Option<Refusal> {
+        self.refused.first().cloned()
+    }
+
+    pub fn is_refused(&self, row: usize) -> bool {
+        self.refused.iter().any(|r| r.row() == row)
+    }
+}
+
+/// Check new transactions of one user, in their order, against the restrictions on them
+///
+/// # Errors
+/// Returns an error if the accounts, limits or spending cannot be fetched
+pub async fn check(
+    db: &DbPool,
+    transactions: &[TransactionCreate],
+    confirm_over_limit: bool,
+) -> anyhow::Result<Checked> {
+    let rows: Vec<usize> = (0..transactions.len()).collect();
+    check_rows(db, transactions, &rows, confirm_over_limit).await
+}
+
+/// Check the transactions at `rows` only, e.g. those of an import that are not duplicates
+/// Expenses count against the spending limits whose current period they are in, together
+/// with the earlier ones that were not refused, so a batch cannot go over a limit that
+/// each of its expenses stays under
+///
+/// # Errors
+/// Returns an error if the accounts, limits or spending cannot be fetched
+pub async fn check_rows(
+    db: &DbPool,
+    transactions: &[TransactionCreate],
+    rows: &[usize],
+    confirm_over_limit: bool,
+) -> anyhow::Result<Checked> {
+    let mut checked = Checked::default();
+    let Some(user_id) = transactions.first().map(|t| t.user_id) else {
+        return Ok(checked);
+    };
+
+    let now = Utc::now();
+    // Currency of each account booked to, None when it is not the user's
+    let mut accounts: HashMap<Uuid, Option<Currency>> = HashMap::new();
+    // Categories the user may spend in, fetched with the first expense
+    let mut allowed_categories = None;
+    let mut tz = None;
+    let mut applicable: Vec<(TransactionCategory, Currency, Vec<SpendingLimitQuery>)> = Vec::new();
+    // Spent in the current period of each limit, with the expenses let through so far
+    let mut spent: HashMap<Uuid, i64> = HashMap::new();
+    for &row in rows {
+        let Some(transaction) = transactions.get(row) else {
+            continue;
+        };
+        if let Some(account_id) = transaction.account_id {
+            let account_currency = match accounts.get(&account_id) {
+                Some(currency) => *currency,
+                None => {
+                    let currency = account_queries::get_account(db, account_id, user_id)
+                        .await?
+                        .map(|a| a.balance.currency);
+                    *accounts.entry(account_id).or_insert(currency)
+                }
+            };
+            let message = match account_currency {
+                None => Some("must be one of the user's accounts".to_string()),
+                Some(currency) if currency != transaction.amount.currency => {
+                    Some(format!("is an account in {}", currency))
+                }
+                Some(_) => None,
+            };
+            if let Some(message) = message {
+                checked.refused.push(Refusal::Account { row, message });
+                continue;
+            }
+        }
+        if transaction.transaction_type != TransactionType::Expense {
+            continue;
+        }
+
+        let allowed = match &allowed_categories {
+            Some(allowed) => allowed,
+            None => allowed_categories.insert(
+                family_queries::get_child(db, user_id)
+                    .await?
+                    .and_then(|c| c.allowed_categories),
+            ),
+        };
+        if let Some(allowed) = allowed
+            && !allowed.contains(&transaction.category)
+        {
+            checked.refused.push(Refusal::CategoryNotAllowed {
+                row,
+                category: transaction.category.clone(),
+                allowed: allowed.clone(),
+            });
+            continue;
+        }
+
+        let currency = transaction.amount.currency;
+        let known = applicable
+            .iter()
+            .position(|(category, c, _)| *category == transaction.category && *c == currency);
+        let index = match known {
+            Some(index) => index,
+            None => {
+                let limits = limit_queries::get_applicable_limits(
+                    db,
+                    user_id,
+                    &transaction.category,
+                    currency,
+                )
+                .await?;
+                applicable.push((transaction.category.clone(), currency, limits));
+                applicable.len() - 1
+            }
+        };
+        let limits = &applicable[index].2;
+        if limits.is_empty() {
+            continue;
+        }
+        let tz = match tz {
+            Some(tz) => tz,
+            None => *tz.insert(user_queries::get_user_timezone(db, user_id).await?),
+        };
+
+        let occurred_at = transaction.occurred_at.unwrap_or(now);
+        let amount = transaction.amount.minor_units.saturating_abs();
+        let mut counted = Vec::new();
+        let mut overridden = Vec::new();
+        let mut refusal = None;
+        for limit in limits {
+            let start = limit.period.start(now, tz);
+            // Expenses of past periods, e.g. imported from an old statement, do not count
+            if occurred_at < start {
+                continue;
+            }
+            let so_far = match spent.get(&limit.id) {
+                Some(so_far) => *so_far,
+                None => {
+                    let so_far = limit_queries::get_spent(
+                        db,
+                        user_id,
+                        limit.category.as_ref(),
+                        limit.amount.currency,
+                        start,
+                    )
+                    .await?
+                    .minor_units;
+                    *spent.entry(limit.id).or_insert(so_far)
+                }
+            };
+            counted.push(limit.id);
+            if so_far.saturating_add(amount) <= limit.amount.minor_units {
+                continue;
+            }
+            if limit.allow_override && confirm_over_limit {
+                overridden.push((row, limit.id));
+                continue;
+            }
+            refusal = Some(Refusal::SpendingLimitExceeded {
+                row,
+                limit: limit.clone(),
+                spent: Money::new(so_far, limit.amount.currency),
+            });
+            break;
+        }
+
+        match refusal {
+            Some(refusal) => checked.refused.push(refusal),
+            None => {
+                for id in counted {
+                    if let Some(so_far) = spent.get_mut(&id) {
+                        *so_far = so_far.saturating_add(amount);
+                    }
+                }
+                checked.overridden.extend(overridden);
+            }
+        }
+    }
+    Ok(checked)
+}
+
+/// Audit entries of the spending limits the recorded transactions went over with the
+/// override confirmed. `actor` is who recorded them, the owner or an editor of the wallet
+pub fn override_entries(
+    actor: Uuid,
+    transactions: &[TransactionCreate],
+    checked: &Checked,
+) -> Vec<AuditEntryCreate> {
+    checked
+        .overridden
+        .iter()
+        .filter_map(|&(row, limit_id)| {
+            let transaction = transactions.get(row)?;
+            Some(
+                AuditEntryCreate::new("override", "spending_limit", Some(limit_id))
+                    .actor(actor)
+                    .details(json!({
+                        "amount": transaction.amount,
+                        "category": transaction.category,
+                    })),
+            )
+        })
+        .collect()
+}
+
+/// Audit the spending limits the recorded transactions went over with the override confirmed
+pub async fn record_overrides(
+    db: &DbPool,
+    actor: Uuid,
+    transactions: &[TransactionCreate],
+    checked: &Checked,
+) {
+    for entry in override_entries(actor, transactions, checked) {
+        if let Err(e) = audit_queries::append(db, &entry).await {
+            eprintln!(
+                "Error recording audit entry {} {}: {}",
+                entry.action, entry.entity_type, e
+            );
+        }
+    }
+}
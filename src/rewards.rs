@@ -0,0 +1,89 @@
+// Cashback estimates from card reward rules
+//
+// A card (bank account) earns the rate of its rule for the expense's category, or else the
+// rate of its catch-all rule. Cashback is estimated per expense and rounded down to the
+// currency's minor unit, like most issuers do. Statement periods start on the card's
+// statement day, the first of the month without one, at midnight in the user's time zone.
+
+use crate::models::money_models::Money;
+use crate::models::reward_models::{BestCard, RewardRuleQuery};
+use crate::models::transaction_models::{TransactionCategory, local_midnight};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use uuid::Uuid;
+
+/// Rate the card earns in the category, None when it earns nothing there
+pub fn rate_for(
+    rules: &[RewardRuleQuery],
+    bank_account_id: Uuid,
+    category: &TransactionCategory,
+) -> Option<Decimal> {
+    let card_rules = || {
+        rules
+            .iter()
+            .filter(|r| r.bank_account_id == bank_account_id)
+    };
+    card_rules()
+        .find(|r| r.category.as_ref() == Some(category))
+        .or_else(|| card_rules().find(|r| r.category.is_none()))
+        .map(|r| r.rate)
+}
+
+/// Cashback earned on an expense of `amount` (positive) at `rate` percent
+pub fn cashback(amount: Money, rate: Decimal) -> Money {
+    let minor_units = (Decimal::from(amount.minor_units) * rate / Decimal::ONE_HUNDRED)
+        .floor()
+        .to_i64()
+        .unwrap_or(0);
+    Money::new(minor_units, amount.currency)
+}
+
+/// The last `count` statement periods as of `now`, newest (the current one) first,
+/// each as a start and an exclusive end
+pub fn statement_periods(
+    statement_day: Option<i16>,
+    now: DateTime<Utc>,
+    tz: Tz,
+    count: u32,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let day = statement_day.unwrap_or(1).clamp(1, 28) as u32;
+    let today = now.with_timezone(&tz).date_naive();
+    let this_month = NaiveDate::from_ymd_opt(today.year(), today.month(), day).unwrap_or(today);
+    let mut start = if this_month <= today {
+        this_month
+    } else {
+        this_month - Months::new(1)
+    };
+
+    let mut periods = Vec::new();
+    for _ in 0..count {
+        let end = start + Months::new(1);
+        periods.push((local_midnight(start, tz), local_midnight(end, tz)));
+        start = start - Months::new(1);
+    }
+    periods
+}
+
+/// For every category, the card with the highest rate, categories no card earns in left out
+pub fn best_cards(rules: &[RewardRuleQuery]) -> Vec<BestCard> {
+    let mut cards: Vec<Uuid> = rules.iter().map(|r| r.bank_account_id).collect();
+    cards.sort();
+    cards.dedup();
+
+    TransactionCategory::ALL
+        .into_iter()
+        .filter_map(|category| {
+            cards
+                .iter()
+                .filter_map(|&card| rate_for(rules, card, &category).map(|rate| (card, rate)))
+                .max_by_key(|&(_, rate)| rate)
+                .map(|(bank_account_id, rate)| BestCard {
+                    category,
+                    bank_account_id,
+                    rate,
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,830 @@
+// HTTP routes of the API
+//
+// Every endpoint is mounted here on a single `AppState`, with the handlers living in
+// `handlers` (and the integrations' own modules). main.rs only wires up configuration
+// and serves the router this builds.
+
+use crate::database::health_check;
+use crate::handlers::{self, AppState};
+use crate::{
+    admin, auth, billing, dashboard, debug_capture, firefly, importers, json_format, meta,
+    problems, request_tx, slack, telegram, telemetry, views,
+};
+use axum::{
+    Router,
+    extract::{DefaultBodyLimit, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{delete, get, post, put},
+};
+use serde_json::{Value, json};
+use tower_http::cors::CorsLayer;
+
+/// Health check endpoint - returns 200 OK if the server is running
+/// This is useful for load balancers and monitoring systems
+async fn health() -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "message": "Wallet API is running"
+    }))
+}
+
+/// Metrics endpoint - per-endpoint database usage in Prometheus text format
+/// Shows queries per request, rows returned and time spent in the database for each route
+async fn metrics() -> String {
+    telemetry::render_metrics()
+}
+
+/// Database health check endpoint - verifies database connectivity
+/// Reports pool usage, the last applied migration, replication lag and query latency
+/// Returns 200 OK if database is accessible, 503 Service Unavailable otherwise
+async fn db_health(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
+    match health_check(&state.db).await {
+        Ok(diagnostics) => Ok(Json(json!({
+            "status": "ok",
+            "database": "connected",
+            "diagnostics": diagnostics
+        }))),
+        Err(e) => {
+            eprintln!("Database health check failed: {}", e);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
+
+/// The optional parts of the API, mounted next to the routes every deployment has
+pub struct Integrations {
+    pub meta: meta::Meta,
+    pub json_format: json_format::JsonFormat,
+    pub debug_capture: debug_capture::DebugCapture,
+    pub dashboard: dashboard::Dashboard,
+    pub telegram: Option<telegram::TelegramBot>,
+    pub slack: Option<slack::SlackIntegration>,
+    pub firefly: Option<firefly::FireflyApi>,
+    pub billing: Option<billing::Billing>,
+    pub admin: Option<admin::Admin>,
+    pub auth: Option<auth::Auth>,
+}
+
+/// Build the Axum router
+/// Routes define which handler functions respond to which URL paths
+/// Shared by the standalone server and the Lambda function
+pub fn router(app_state: AppState, integrations: Integrations) -> Router {
+    let Integrations {
+        meta,
+        json_format,
+        debug_capture,
+        dashboard,
+        telegram,
+        slack,
+        firefly,
+        billing,
+        admin,
+        auth,
+    } = integrations;
+    let mut router = Router::new()
+        // Health check endpoint - no database required
+        .route("/health", get(health))
+        // Database health check - tests database connectivity
+        .route("/health/db", get(db_health))
+        // Per-endpoint database metrics for Prometheus
+        .route("/metrics", get(metrics))
+        // What this deployment offers: version, features, limits and formats
+        .merge(meta.router())
+        // Codes of the errors, which are answered as problem details
+        .route("/api/meta/errors", get(problems::list_error_codes))
+        // Create user endpoint
+        .route("/api/users", post(handlers::create_user_handler))
+        .route("/api/users/:email", get(handlers::get_user_handler))
+        // Usage of the plan's quotas, warned about from 80% on
+        .route("/api/users/me/usage", get(handlers::get_usage_handler))
+        .route("/api/users", get(handlers::get_users_handler))
+        .route("/api/users", put(handlers::upsert_user_handler))
+        // Signup forms check an email inline, without a token
+        .route(
+            "/api/users/availability",
+            get(handlers::get_email_availability_handler),
+        )
+        // Invited users choose their password, with the token of their invitation
+        .route(
+            "/api/users/password-setup",
+            post(handlers::password_setup_handler),
+        )
+        .route(
+            "/api/users/:email/encryption-key",
+            get(handlers::get_encryption_key_handler).put(handlers::put_encryption_key_handler),
+        )
+        .route(
+            "/api/transactions",
+            post(handlers::create_transaction_handler),
+        )
+        .route("/api/transactions", get(handlers::get_transactions_handler))
+        .route(
+            "/api/transactions/count",
+            get(handlers::get_transaction_count_handler),
+        )
+        // Imported transactions without a category, waiting to be sorted out
+        .route(
+            "/api/transactions/uncategorized",
+            get(handlers::get_uncategorized_transactions_handler),
+        )
+        .route(
+            "/api/transactions/category-suggestion",
+            get(handlers::get_category_suggestion_handler),
+        )
+        .route(
+            "/api/transactions/batch",
+            post(handlers::batch_create_transactions_handler),
+        )
+        // Bank statements (OFX, QIF or CSV with a column mapping), imported within the request
+        .route(
+            "/api/transactions/import",
+            post(handlers::import_statement_handler)
+                .layer(DefaultBodyLimit::max(importers::MAX_BODY_BYTES)),
+        )
+        // Deleted transactions are kept, left out of listings and sums, until restored
+        .route(
+            "/api/transactions/:id",
+            delete(handlers::delete_transaction_handler),
+        )
+        .route(
+            "/api/transactions/:id/restore",
+            post(handlers::restore_transaction_handler),
+        )
+        // CSV exports of other budgeting apps, which can hold years of history
+        .route(
+            // Exports are posted to /api/imports/<format>, imports read at /api/imports/<id>
+            "/api/imports/:format",
+            post(handlers::import_export_handler)
+                .layer(DefaultBodyLimit::max(importers::MAX_BODY_BYTES))
+                .get(handlers::get_import_handler),
+        )
+        .route(
+            "/api/imports/:id/rejected.csv",
+            get(handlers::get_import_rejected_rows_handler),
+        )
+        .route("/api/exports/:target", get(handlers::export_handler))
+        // Receipts and other files attached to transactions, with thumbnails for list views
+        .route(
+            "/api/transactions/:id/attachments",
+            post(handlers::upload_attachment_handler)
+                .get(handlers::get_attachments_handler)
+                // Each type's size limit is enforced as the upload is streamed
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/attachments/:id",
+            get(handlers::get_attachment_handler),
+        )
+        .route(
+            "/api/attachments/:id/link",
+            get(handlers::get_attachment_link_handler),
+        )
+        .route(
+            "/api/attachments/:id/thumbnail",
+            get(handlers::get_thumbnail_handler),
+        )
+        .route(
+            "/api/transactions/amount",
+            get(handlers::get_amount_handler),
+        )
+        // Several sums at once, one for each filter in the body
+        .route(
+            "/api/transactions/amounts",
+            post(handlers::get_amounts_handler),
+        )
+        // What each category usually costs a month, and in which months more or less
+        .route(
+            "/api/transactions/seasonality",
+            get(handlers::get_seasonality_handler),
+        )
+        // Sums by day, week or month, every bucket listed, for spending-over-time charts
+        .route(
+            "/api/reports/timeseries",
+            get(handlers::get_timeseries_handler),
+        )
+        // Gains and losses on foreign-currency balances since the transactions' days
+        .route(
+            "/api/transactions/fx-revaluation",
+            get(handlers::get_fx_revaluation_handler),
+        )
+        // Hard caps on expenses, checked when transactions are created
+        .route(
+            "/api/spending-limits",
+            put(handlers::put_spending_limit_handler).get(handlers::get_spending_limits_handler),
+        )
+        .route(
+            "/api/spending-limits/burn-down",
+            get(handlers::get_spending_limits_burn_down_handler),
+        )
+        .route(
+            "/api/spending-limits/:id",
+            delete(handlers::delete_spending_limit_handler),
+        )
+        // Monthly budgets per category, advisory unlike spending limits
+        .route(
+            "/api/budgets",
+            post(handlers::create_budget_handler).get(handlers::get_budgets_handler),
+        )
+        .route(
+            "/api/budgets/status",
+            get(handlers::get_budget_status_handler),
+        )
+        .route("/api/budgets/:id", delete(handlers::delete_budget_handler))
+        .route("/api/alerts", get(handlers::get_alerts_handler))
+        .route(
+            "/api/alerts/:id/acknowledge",
+            post(handlers::acknowledge_alert_handler),
+        )
+        // Incremental sync for offline-first mobile clients
+        .route("/api/sync/changes", get(handlers::sync_changes_handler))
+        .route("/api/events", get(handlers::stream_events_handler))
+        .route("/api/sync/push", post(handlers::sync_push_handler))
+        .route(
+            "/api/bank-accounts",
+            post(handlers::create_bank_account_handler).get(handlers::get_bank_accounts_handler),
+        )
+        .route(
+            "/api/bank-accounts/:id/sync",
+            put(handlers::update_bank_account_sync_handler),
+        )
+        .route(
+            "/api/bank-accounts/:id/statement-day",
+            put(handlers::update_statement_day_handler),
+        )
+        // Reconciled statement periods, whose transactions cannot be changed
+        .route(
+            "/api/bank-accounts/:id/reconciliation-locks",
+            get(handlers::get_reconciliation_locks_handler)
+                .post(handlers::create_reconciliation_lock_handler),
+        )
+        .route(
+            "/api/bank-accounts/:id/reconciliation-locks/:lock_id",
+            delete(handlers::delete_reconciliation_lock_handler),
+        )
+        // Card reward rules and the cashback they are expected to earn
+        .route(
+            "/api/bank-accounts/:id/cashback",
+            get(handlers::get_cashback_handler),
+        )
+        .route(
+            "/api/reward-rules",
+            put(handlers::put_reward_rule_handler).get(handlers::get_reward_rules_handler),
+        )
+        .route(
+            "/api/reward-rules/:id",
+            delete(handlers::delete_reward_rule_handler),
+        )
+        .route(
+            "/api/reward-rules/best-cards",
+            get(handlers::get_best_cards_handler),
+        )
+        .route("/api/audit/verify", get(handlers::verify_audit_log_handler))
+        .route(
+            "/api/webhooks",
+            post(handlers::create_webhook_endpoint_handler)
+                .get(handlers::get_webhook_endpoints_handler),
+        )
+        .route(
+            "/api/webhooks/:id",
+            delete(handlers::delete_webhook_endpoint_handler),
+        )
+        .route(
+            "/api/webhooks/deliveries",
+            get(handlers::get_webhook_deliveries_handler),
+        )
+        .route(
+            "/api/webhooks/deliveries/:id/retry",
+            post(handlers::retry_webhook_delivery_handler),
+        )
+        // Transactions parsed from ingested emails, confirmed or rejected by the user
+        .route(
+            "/api/pending-transactions",
+            get(handlers::get_pending_transactions_handler),
+        )
+        .route(
+            "/api/pending-transactions/:id/confirm",
+            post(handlers::confirm_pending_transaction_handler),
+        )
+        .route(
+            "/api/pending-transactions/:id/reject",
+            post(handlers::reject_pending_transaction_handler),
+        )
+        // Home screen overview: wallets, their bank accounts and balances
+        .route(
+            "/api/users/:email/wallets",
+            get(handlers::get_wallets_handler),
+        )
+        // Wallets shared with editors, whose large transactions wait for the owner's approval
+        .route(
+            "/api/users/:email/members",
+            get(handlers::get_wallet_members_handler).put(handlers::put_wallet_member_handler),
+        )
+        .route(
+            "/api/users/:email/members/:member_email",
+            delete(handlers::delete_wallet_member_handler),
+        )
+        // Invitations to sign up and join the wallet
+        .route(
+            "/api/users/:email/invitations",
+            get(handlers::get_wallet_invitations_handler)
+                .post(handlers::create_wallet_invitation_handler),
+        )
+        .route(
+            "/api/users/:email/invitations/:id",
+            delete(handlers::delete_wallet_invitation_handler),
+        )
+        .route(
+            "/api/users/:email/share-links",
+            get(handlers::get_share_links_handler).post(handlers::create_share_link_handler),
+        )
+        .route(
+            "/api/users/:email/share-links/:id",
+            delete(handlers::revoke_share_link_handler),
+        )
+        // Reports shared through a link, no account or token needed
+        .route(
+            "/api/shared/:token",
+            get(handlers::get_shared_report_handler),
+        )
+        .route(
+            "/api/users/:email/approval-policy",
+            get(handlers::get_approval_policy_handler)
+                .put(handlers::put_approval_policy_handler)
+                .delete(handlers::delete_approval_policy_handler),
+        )
+        // Weekly digest of the shared wallets, see digests.rs
+        .route(
+            "/api/users/:email/notification-preferences",
+            get(handlers::get_notification_preferences_handler)
+                .put(handlers::put_notification_preferences_handler),
+        )
+        .route(
+            "/api/transaction-approvals",
+            get(handlers::get_transaction_approvals_handler),
+        )
+        .route(
+            "/api/transaction-approvals/:id/approve",
+            post(handlers::approve_transaction_handler),
+        )
+        .route(
+            "/api/transaction-approvals/:id/reject",
+            post(handlers::reject_transaction_handler),
+        )
+        // Child accounts, visible to their parent, with weekly allowances
+        .route(
+            "/api/users/:email/children",
+            get(handlers::get_children_handler).put(handlers::put_child_handler),
+        )
+        .route(
+            "/api/users/:email/children/:child_email",
+            delete(handlers::delete_child_handler),
+        )
+        .route(
+            "/api/users/:email/children/:child_email/transactions",
+            get(handlers::get_child_transactions_handler),
+        )
+        // Accounts of a user, e.g. Checking, Cash and Savings, transactions are booked to them
+        .route(
+            "/api/users/:email/accounts",
+            get(handlers::get_accounts_handler).post(handlers::create_account_handler),
+        )
+        .route(
+            "/api/users/:email/accounts/:id",
+            get(handlers::get_account_handler).delete(handlers::delete_account_handler),
+        )
+        // Money moved between the user's accounts, converted between currencies
+        .route(
+            "/api/users/:email/transfers",
+            get(handlers::get_transfers_handler).post(handlers::create_transfer_handler),
+        )
+        // Savings goals, fed by rounding expenses up
+        .route(
+            "/api/savings-goals",
+            post(handlers::create_savings_goal_handler).get(handlers::get_savings_goals_handler),
+        )
+        .route(
+            "/api/savings-goals/:id/round-ups",
+            get(handlers::get_round_up_report_handler),
+        )
+        .route(
+            "/api/users/:email/round-up",
+            get(handlers::get_round_up_rule_handler)
+                .put(handlers::put_round_up_rule_handler)
+                .delete(handlers::delete_round_up_rule_handler),
+        )
+        // Minimal server-rendered UI, for setups without the frontend
+        .route("/ui/login", get(views::login_page).post(views::login))
+        .route("/ui/logout", post(views::logout))
+        .route("/ui/transactions", get(views::transactions_page))
+        .route("/ui/report", get(views::report_page));
+    // Optional chat integrations
+    if let Some(bot) = telegram {
+        router = router.merge(bot.router());
+    }
+    if let Some(slack) = slack {
+        router = router.merge(slack.router());
+    }
+    // Firefly III compatible API for existing mobile clients
+    if let Some(firefly) = firefly {
+        router = router.merge(firefly.router());
+    }
+    if let Some(billing) = billing {
+        router = router.merge(billing.router());
+    }
+    if let Some(admin) = admin {
+        router = router.merge(admin.router());
+    }
+    // Access tokens, required on the routes of users' own data once enabled
+    if let Some(auth) = auth {
+        router = router
+            .route_layer(middleware::from_fn_with_state(
+                auth.issuer.clone(),
+                auth::require_token,
+            ))
+            .merge(auth.router());
+    }
+
+    router
+        // Commit the database transaction of handlers taking a request_tx::Tx, on success
+        .route_layer(middleware::from_fn_with_state(
+            app_state.db.clone(),
+            request_tx::commit_on_success,
+        ))
+        // Remember which route is being served, for slow query logs and metrics
+        .route_layer(middleware::from_fn(telemetry::track_endpoint))
+        // Opt-in capture of redacted request/response bodies
+        .route_layer(middleware::from_fn_with_state(
+            debug_capture,
+            debug_capture::capture_bodies,
+        ))
+        // camelCase names and epoch-millis dates, when configured or asked for
+        .route_layer(middleware::from_fn_with_state(
+            json_format,
+            json_format::format_responses,
+        ))
+        // Errors as problem details, unmatched paths and methods included
+        .layer(middleware::from_fn(problems::problem_responses))
+        // Bundled web dashboard (SPA), outside the API metrics and debug capture
+        .nest_service("/app", dashboard.router())
+        // Add CORS middleware to allow cross-origin requests
+        // This is important for web applications making API calls
+        .layer(CorsLayer::permissive())
+        // Attach application state to the router
+        // This makes the database pool available to all handlers
+        .with_state(app_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::transaction_models::TransactionCategory;
+    use crate::storage::LocalStore;
+    use axum::body::Body;
+    use axum::http::Request;
+    use std::sync::Arc;
+    use tower::Service;
+
+    /// The router with every optional integration left out but `auth`, on a pool that never
+    /// connects
+    fn test_router_with(auth: Option<auth::Auth>) -> Router {
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/wallet_router_test")
+            .expect("a valid database URL");
+        test_router_on(db, auth)
+    }
+
+    /// The same on the given pool
+    fn test_router_on(db: sqlx::PgPool, auth: Option<auth::Auth>) -> Router {
+        router(
+            test_state(db.clone()),
+            Integrations {
+                meta: Default::default(),
+                json_format: Default::default(),
+                debug_capture: debug_capture::DebugCapture {
+                    db,
+                    enabled: false,
+                    sample_rate: 0.0,
+                },
+                dashboard: dashboard::Dashboard {
+                    dir: None,
+                    api_base: String::new(),
+                },
+                telegram: None,
+                slack: None,
+                firefly: None,
+                billing: None,
+                admin: None,
+                auth,
+            },
+        )
+    }
+
+    fn test_state(db: sqlx::PgPool) -> AppState {
+        let blob_dir = std::env::temp_dir().join("wallet-router-test");
+        AppState {
+            db,
+            blobs: Arc::new(LocalStore::new(&blob_dir.to_string_lossy()).unwrap()),
+            scanner: None,
+            default_category: TransactionCategory::Uncategorized,
+            public_signup: true,
+        }
+    }
+
+    fn test_router() -> Router {
+        test_router_with(None)
+    }
+
+    async fn send(router: Router, method: &str, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        let request = request.body(Body::from("{}")).unwrap();
+        let mut router = router;
+        router.call(request).await.unwrap().status()
+    }
+
+    async fn status(method: &str, uri: &str, body: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        // The router is always ready, no need to poll it first
+        test_router().call(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn serves_health_without_a_database() {
+        assert_eq!(status("GET", "/health", "").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn mounts_api_handlers() {
+        // Rejected by validation before any query, so no database is needed
+        assert_eq!(
+            status("POST", "/api/users", "{}").await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            status("DELETE", "/api/transactions", "").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_errors_as_problem_details() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/users")
+            .header("content-type", "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let response = test_router().call(request).await.unwrap();
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            problems::CONTENT_TYPE
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["status"], 422);
+        assert_eq!(problem["code"], "validation_failed");
+        assert_eq!(problem["instance"], "/api/users");
+        assert!(problem["errors"].is_array());
+
+        let request = Request::builder()
+            .uri("/api/nope")
+            .body(Body::empty())
+            .unwrap();
+        let response = test_router().call(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "not_found");
+        assert_eq!(problem["type"], "/api/meta/errors#not_found");
+    }
+
+    #[tokio::test]
+    async fn leaves_out_disabled_integrations() {
+        assert_eq!(
+            status("GET", "/api/admin/analytics/users", "").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("POST", "/api/integrations/telegram/webhook", "{}").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(status("GET", "/api/nope", "").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn requires_access_tokens_once_enabled() {
+        let issuer = Arc::new(auth::TokenIssuer::new(
+            "test-secret-of-at-least-32-bytes!",
+            900,
+        ));
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/wallet_router_test")
+            .expect("a valid database URL");
+        let router = test_router_with(Some(auth::Auth {
+            state: test_state(db),
+            issuer: issuer.clone(),
+            refresh_ttl_secs: 3600,
+        }));
+        let user_id = uuid::Uuid::new_v4();
+        let (token, _) = issuer.issue(user_id, "ann@example.com", chrono::Utc::now());
+        let own = format!("/api/transactions?user_id={}", user_id);
+        let other = format!("/api/transactions?user_id={}", uuid::Uuid::new_v4());
+
+        assert_eq!(
+            send(router.clone(), "GET", &own, None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(router.clone(), "GET", &own, Some("not.a.token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(router.clone(), "GET", &other, Some(&token)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(router.clone(), "GET", "/api/users", Some(&token)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(
+                router.clone(),
+                "GET",
+                "/api/users/bob@example.com",
+                Some(&token)
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        // Signing up, logging in and refreshing need no token, rejected by validation here
+        assert_eq!(
+            send(router.clone(), "POST", "/api/users", None).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            send(router.clone(), "POST", "/api/auth/login", None).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            send(router.clone(), "POST", "/api/auth/refresh", None).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(send(router, "GET", "/health", None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn checks_users_named_in_every_body_axum_reads_as_json() {
+        let issuer = Arc::new(auth::TokenIssuer::new(
+            "test-secret-of-at-least-32-bytes!",
+            900,
+        ));
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/wallet_router_test")
+            .expect("a valid database URL");
+        let router = test_router_with(Some(auth::Auth {
+            state: test_state(db),
+            issuer: issuer.clone(),
+            refresh_ttl_secs: 3600,
+        }));
+        let user_id = uuid::Uuid::new_v4();
+        let (token, _) = issuer.issue(user_id, "ann@example.com", chrono::Utc::now());
+
+        for content_type in [
+            "application/json",
+            "Application/JSON; charset=utf-8",
+            "application/cloudevents+json",
+            "application/vnd.api+JSON",
+        ] {
+            let request = Request::builder()
+                .method("POST")
+                .uri(format!("/api/transactions?user_id={}", user_id))
+                .header("content-type", content_type)
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::from(r#"{"user_email":"bob@example.com"}"#))
+                .unwrap();
+            let mut router = router.clone();
+            assert_eq!(
+                router.call(request).await.unwrap().status(),
+                StatusCode::FORBIDDEN,
+                "{}",
+                content_type
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a migrated database, set TEST_DATABASE_URL"]
+    async fn dry_runs_store_nothing_and_return_the_budget_impact() {
+        use crate::models::money_models::{Currency, Money};
+        use crate::models::user_models::UserCreate;
+        use crate::queries::{budget_queries, user_queries};
+
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL to be set");
+        let db = sqlx::PgPool::connect(&url).await.unwrap();
+        let email = format!("dry-run-{}@example.com", uuid::Uuid::new_v4());
+        let user = UserCreate::new(email.clone(), "Dry run".to_string(), String::new());
+        let user_id = user_queries::create_user(&db, &user).await.unwrap();
+        budget_queries::upsert_budget(
+            &db,
+            user_id,
+            &TransactionCategory::Groceries,
+            Money::new(10000, Currency::EUR),
+        )
+        .await
+        .unwrap();
+
+        let body = serde_json::json!({
+            "user_email": email,
+            "transaction_type": "Expense",
+            "amount": "30.00",
+            "currency": "EUR",
+            "category": "Groceries",
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/transactions?dry_run=true")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = test_router_on(db.clone(), None)
+            .call(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let answer: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        let body = serde_json::json!({
+            "user_email": email,
+            "transactions": [{
+                "id": uuid::Uuid::new_v4(),
+                "client_updated_at": "2024-01-01T00:00:00Z",
+                "transaction_type": "Expense",
+                "amount": "30.00",
+                "currency": "EUR",
+                "category": "Groceries",
+            }],
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/sync/push?dry_run=true")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = test_router_on(db.clone(), None)
+            .call(request)
+            .await
+            .unwrap();
+        let pushed_status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let pushed: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        let stored: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM transactions WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        sqlx::query("DELETE FROM budgets WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user_id)
+            .execute(&db)
+            .await
+            .unwrap();
+
+        assert_eq!(status, StatusCode::OK, "{}", answer);
+        assert_eq!(stored, 0);
+        assert_eq!(answer["dry_run"], true);
+        assert_eq!(answer["would_create"], true);
+        let impact = &answer["budget_impact"][0];
+        assert_eq!(impact["spent_after"]["amount"], "30.00");
+        assert_eq!(impact["remaining_after"]["amount"], "70.00");
+
+        assert_eq!(pushed_status, StatusCode::OK, "{}", pushed);
+        assert_eq!(pushed["dry_run"], true);
+        assert_eq!(pushed["applied"][0]["status"], "created");
+    }
+}
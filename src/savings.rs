@@ -0,0 +1,29 @@
+// Round-ups into savings goals
+//
+// Round-ups are recorded by a scheduled job rather than when expenses are created, so
+// expenses from every source (API, imports, bank syncs, chat bots, ...) are rounded up the
+// same way. The job only looks at expenses without a round-up, running it again is harmless.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::queries::savings_queries;
+use axum::async_trait;
+use serde_json::Value;
+
+/// Job setting aside the round-ups of new expenses, scheduled every five minutes by default
+pub struct RecordRoundUpsJob;
+
+#[async_trait]
+impl JobHandler for RecordRoundUpsJob {
+    fn kind(&self) -> &'static str {
+        "savings.round_up"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let recorded = savings_queries::record_round_ups(db).await?;
+        if recorded > 0 {
+            tracing::info!(recorded, "Round-ups recorded");
+        }
+        Ok(())
+    }
+}
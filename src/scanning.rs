@@ -0,0 +1,214 @@
+// Malware scanning of uploaded attachments
+//
+// When MALWARE_SCANNER is set every attachment is scanned before it is recorded, either by
+// a ClamAV daemon (clamav://host:3310, INSTREAM command) or by an ICAP server
+// (icap://host:1344/service, RESPMOD as most antivirus gateways expect). Infected files are
+// kept for review but quarantined: they are never served or thumbnailed. Uploads fail
+// while the scanner cannot be reached, rather than letting unscanned files through. Files
+// are streamed to the scanner while they are uploaded.
+
+use crate::storage::ByteStream;
+use futures_util::StreamExt;
+use reqwest::Url;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Longest the scanner may take to accept a connection, and to answer once it has the
+/// whole file. Sending the file takes as long as the upload does
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Size of the chunks streamed to clamd, below its StreamMaxLength chunking limits
+const CLAMAV_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Most of a scanner's reply read, replies are a status line and a few headers
+const MAX_REPLY_BYTES: u64 = 64 * 1024;
+
+/// Outcome of a scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Clean,
+    /// Name of the signature that matched, as reported by the scanner
+    Infected(String),
+}
+
+/// A configured scanner
+#[derive(Debug, Clone)]
+pub enum Scanner {
+    Clamav { address: String },
+    Icap { address: String, uri: String },
+}
+
+impl Scanner {
+    /// Parse MALWARE_SCANNER, "clamav://host:port" or "icap://host:port/service"
+    ///
+    /// # Errors
+    /// Returns an error for other schemes or URLs without a host
+    pub fn from_url(raw: &str) -> anyhow::Result<Self> {
+        let url = Url::parse(raw)
+            .map_err(|e| anyhow::anyhow!("Invalid MALWARE_SCANNER {}: {}", raw, e))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("MALWARE_SCANNER needs a host: {}", raw))?;
+        match url.scheme() {
+            "clamav" => Ok(Scanner::Clamav {
+                address: format!("{}:{}", host, url.port().unwrap_or(3310)),
+            }),
+            "icap" => {
+                let port = url.port().unwrap_or(1344);
+                Ok(Scanner::Icap {
+                    address: format!("{}:{}", host, port),
+                    uri: format!("icap://{}:{}{}", host, port, url.path()),
+                })
+            }
+            other => Err(anyhow::anyhow!(
+                "Unknown malware scanner {}, use clamav:// or icap://",
+                other
+            )),
+        }
+    }
+
+    /// Scan a file, read from `body` as it arrives
+    ///
+    /// # Errors
+    /// Returns an error when the scanner cannot be reached, times out or fails to scan
+    pub async fn scan(&self, content_type: &str, body: ByteStream) -> anyhow::Result<Verdict> {
+        match self {
+            Scanner::Clamav { address } => scan_clamav(address, body).await,
+            Scanner::Icap { address, uri } => scan_icap(address, uri, content_type, body).await,
+        }
+    }
+}
+
+async fn connect(address: &str) -> anyhow::Result<TcpStream> {
+    tokio::time::timeout(SCAN_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|_| anyhow::anyhow!("Connecting to the malware scanner timed out"))?
+        .map_err(|e| anyhow::anyhow!("Connecting to the malware scanner failed: {}", e))
+}
+
+async fn read_reply(stream: TcpStream) -> anyhow::Result<String> {
+    let mut reply = Vec::new();
+    tokio::time::timeout(
+        SCAN_TIMEOUT,
+        stream.take(MAX_REPLY_BYTES).read_to_end(&mut reply),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("Malware scan timed out"))??;
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
+
+/// clamd INSTREAM: length-prefixed chunks ended by an empty one, answered with
+/// "stream: OK" or "stream: <signature> FOUND"
+async fn scan_clamav(address: &str, mut body: ByteStream) -> anyhow::Result<Verdict> {
+    let mut stream = connect(address).await?;
+    stream.write_all(b"zINSTREAM\0").await?;
+    while let Some(data) = body.next().await {
+        for chunk in data?.chunks(CLAMAV_CHUNK_BYTES) {
+            stream
+                .write_all(&(chunk.len() as u32).to_be_bytes())
+                .await?;
+            stream.write_all(chunk).await?;
+        }
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+
+    let reply = read_reply(stream).await?;
+    let reply = reply.trim_end_matches(['\0', '\n']).trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        Ok(Verdict::Clean)
+    } else if let Some(signature) = result.strip_suffix("FOUND") {
+        Ok(Verdict::Infected(signature.trim().to_string()))
+    } else {
+        Err(anyhow::anyhow!("ClamAV could not scan the file: {}", reply))
+    }
+}
+
+/// ICAP RESPMOD of an HTTP response carrying the file: 204 No Content when clean, the
+/// response modified into a block page (200) when infected
+async fn scan_icap(
+    address: &str,
+    uri: &str,
+    content_type: &str,
+    mut body: ByteStream,
+) -> anyhow::Result<Verdict> {
+    // Without a Content-Length, the size is only known once the upload has been read
+    let http_headers = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\n\r\n",
+        content_type
+    );
+    let host = address.split(':').next().unwrap_or(address);
+    let request = format!(
+        "RESPMOD {} ICAP/1.0\r\nHost: {}\r\nAllow: 204\r\nConnection: close\r\nEncapsulated: res-hdr=0, res-body={}\r\n\r\n{}",
+        uri,
+        host,
+        http_headers.len(),
+        http_headers
+    );
+
+    let mut stream = connect(address).await?;
+    stream.write_all(request.as_bytes()).await?;
+    // The body in HTTP chunked encoding
+    while let Some(data) = body.next().await {
+        let data = data?;
+        if data.is_empty() {
+            continue;
+        }
+        stream
+            .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+            .await?;
+        stream.write_all(&data).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.flush().await?;
+
+    let reply = read_reply(stream).await?;
+    let status = reply
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| {
+            anyhow::anyhow!("Invalid ICAP reply: {}", reply.lines().next().unwrap_or(""))
+        })?;
+    match status {
+        204 => Ok(Verdict::Clean),
+        200 => Ok(Verdict::Infected(icap_threat(&reply))),
+        _ => Err(anyhow::anyhow!(
+            "ICAP server could not scan the file: {}",
+            reply.lines().next().unwrap_or_default()
+        )),
+    }
+}
+
+/// Threat named in the ICAP headers, "X-Infection-Found: Type=0; Resolution=2; Threat=Name;"
+/// or "X-Violations-Found" followed by the violations
+fn icap_threat(reply: &str) -> String {
+    let headers = reply.split("\r\n\r\n").next().unwrap_or_default();
+    let mut lines = headers.lines();
+    while let Some(line) = lines.next() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case("X-Infection-Found") {
+            if let Some(threat) = value
+                .split(';')
+                .filter_map(|field| field.trim().strip_prefix("Threat="))
+                .next()
+            {
+                return threat.trim().to_string();
+            }
+        } else if name.eq_ignore_ascii_case("X-Violations-Found") {
+            // The count, then four lines per violation: file name, threat, ids
+            if let Some(threat) = lines.nth(1) {
+                return threat.trim().to_string();
+            }
+        } else if name.eq_ignore_ascii_case("X-Virus-ID") {
+            return value.trim().to_string();
+        }
+    }
+    "unknown".to_string()
+}
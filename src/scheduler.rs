@@ -0,0 +1,112 @@
+// Cron schedules for periodic jobs
+//
+// Schedules live in the job_schedules table, one per job kind. The built-in ones and
+// those in JOB_SCHEDULES are written there at startup, more can be added to the table
+// directly. Every server checks for due schedules and queues their job, claiming each run
+// first so it is only queued once. A job still queued or running when its next run comes
+// is not queued again, so slow jobs never overlap.
+
+use crate::database::DbPool;
+use crate::jobs;
+use crate::models::job_models::JobCreate;
+use crate::queries::schedule_queries;
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use std::time::Duration;
+
+/// How often due schedules are checked, cron expressions have minute resolution
+const TICK: Duration = Duration::from_secs(15);
+
+/// Schedules of the built-in jobs, overridable in JOB_SCHEDULES
+pub const DEFAULT_SCHEDULES: &[(&str, &str)] = &[
+    ("jobs.prune", "0 * * * *"),
+    ("allowances.credit", "0 6 * * 1"),
+    ("savings.round_up", "*/5 * * * *"),
+    ("budgets.alerts", "*/5 * * * *"),
+    ("digests.weekly", "0 7 * * 1"),
+    ("categories.train", "30 3 * * *"),
+];
+
+fn parse_cron(expression: &str) -> anyhow::Result<Cron> {
+    Cron::new(expression)
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid cron expression '{}': {}", expression, e))
+}
+
+/// First run of the schedule after `after`
+fn next_run(expression: &str, after: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+    parse_cron(expression)?
+        .find_next_occurrence(&after, false)
+        .map_err(|e| anyhow::anyhow!("No next run for '{}': {}", expression, e))
+}
+
+/// Parse "kind=cron;kind=cron", e.g. "jobs.prune=0 3 * * *;reports.weekly=0 8 * * 1"
+///
+/// # Errors
+/// Returns an error if an entry is malformed or its cron expression is invalid
+pub fn parse_schedules(raw: &str) -> anyhow::Result<Vec<(String, String)>> {
+    raw.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (kind, expression) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Job schedules must be kind=cron"))?;
+            let expression = expression.trim();
+            parse_cron(expression)?;
+            Ok((kind.trim().to_string(), expression.to_string()))
+        })
+        .collect()
+}
+
+/// Write the built-in schedules, overridden by the configured ones, to the database
+pub async fn sync_schedules(db: &DbPool, configured: &[(String, String)]) -> anyhow::Result<()> {
+    let defaults = DEFAULT_SCHEDULES
+        .iter()
+        .filter(|(kind, _)| !configured.iter().any(|(k, _)| k == kind))
+        .map(|(kind, cron)| (kind.to_string(), cron.to_string()));
+    let now = Utc::now();
+    for (kind, cron) in defaults.chain(configured.iter().cloned()) {
+        schedule_queries::upsert_schedule(db, &kind, &cron, next_run(&cron, now)?).await?;
+    }
+    Ok(())
+}
+
+/// Queue the jobs of every due schedule
+async fn enqueue_due(db: &DbPool) -> anyhow::Result<()> {
+    for schedule in schedule_queries::get_due(db).await? {
+        // Runs missed while no server was up are not caught up, only the latest one is queued
+        let next = match next_run(&schedule.cron, Utc::now()) {
+            Ok(next) => next,
+            Err(e) => {
+                tracing::warn!(kind = %schedule.kind, error = %e, "Skipping schedule");
+                continue;
+            }
+        };
+        if !schedule_queries::advance(db, &schedule.kind, schedule.next_run_at, next).await? {
+            continue;
+        }
+
+        let job = JobCreate::new(&schedule.kind, schedule.payload).dedupe_key(&schedule.kind);
+        if jobs::enqueue(db, job).await?.is_none() {
+            tracing::info!(
+                kind = %schedule.kind,
+                "Skipping scheduled job, the previous run has not finished"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Check the schedules in the background for as long as the server runs
+pub fn spawn_scheduler(db: DbPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            if let Err(e) = enqueue_due(&db).await {
+                tracing::error!(error = %e, "Scheduling jobs failed");
+            }
+        }
+    });
+}
@@ -0,0 +1,663 @@
+// Startup self-check of the database schema
+//
+// Once the migrations ran, the tables, columns and enum types the queries use are looked up
+// in the database. A mismatch (a migration that was skipped or failed, a column renamed by
+// hand, DATABASE_URL pointing at another database) stops the server with a report of
+// everything missing, instead of requests failing one by one with sqlx errors like
+// `column "desription" does not exist`.
+//
+// A table, column or enum label added by a migration is listed here in the same change.
+// Extra tables, columns and labels in the database are fine.
+
+use crate::database::DbPool;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+
+/// Tables the queries use, with their columns
+const TABLES: &[(&str, &[&str])] = &[
+    (
+        "users",
+        &[
+            "id",
+            "email",
+            "email_hash",
+            "name",
+            "password",
+            "timezone",
+            "plan",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "transactions",
+        &[
+            "id",
+            "user_id",
+            "transaction_type",
+            "amount",
+            "currency",
+            "category",
+            "description",
+            "encrypted_description",
+            "source",
+            "external_id",
+            "bank_account_id",
+            "account_id",
+            "merchant_name",
+            "merchant_logo_url",
+            "created_at",
+            "last_updated_at",
+            "deleted_at",
+        ],
+    ),
+    (
+        "transaction_history",
+        &[
+            "history_id",
+            "id",
+            "user_id",
+            "transaction_type",
+            "amount",
+            "currency",
+            "category",
+            "description",
+            "encrypted_description",
+            "source",
+            "external_id",
+            "bank_account_id",
+            "account_id",
+            "merchant_name",
+            "merchant_logo_url",
+            "created_at",
+            "last_updated_at",
+            "valid_from",
+            "valid_to",
+        ],
+    ),
+    (
+        "transaction_approvals",
+        &[
+            "id",
+            "owner_id",
+            "requested_by",
+            "transaction_type",
+            "amount",
+            "currency",
+            "category",
+            "description",
+            "encrypted_description",
+            "source",
+            "external_id",
+            "account_id",
+            "status",
+            "reason",
+            "transaction_id",
+            "created_at",
+            "resolved_at",
+        ],
+    ),
+    (
+        "accounts",
+        &[
+            "id",
+            "user_id",
+            "name",
+            "currency",
+            "opening_balance",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "transfers",
+        &[
+            "id",
+            "user_id",
+            "from_account_id",
+            "to_account_id",
+            "amount",
+            "currency",
+            "to_amount",
+            "to_currency",
+            "rate",
+            "description",
+            "created_at",
+        ],
+    ),
+    (
+        "bank_accounts",
+        &[
+            "id",
+            "user_id",
+            "institution",
+            "name",
+            "iban",
+            "last4",
+            "sync_status",
+            "sync_error",
+            "last_synced_at",
+            "statement_day",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "budgets",
+        &[
+            "id",
+            "user_id",
+            "category",
+            "amount",
+            "currency",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "budget_alerts",
+        &[
+            "id",
+            "user_id",
+            "budget_id",
+            "category",
+            "threshold",
+            "period_start",
+            "spent",
+            "amount",
+            "currency",
+            "created_at",
+            "acknowledged_at",
+        ],
+    ),
+    (
+        "spending_limits",
+        &[
+            "id",
+            "user_id",
+            "category",
+            "period",
+            "amount",
+            "currency",
+            "allow_override",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "savings_goals",
+        &[
+            "id",
+            "user_id",
+            "name",
+            "target",
+            "currency",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "round_up_rules",
+        &[
+            "user_id",
+            "goal_id",
+            "unit",
+            "starts_at",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "round_ups",
+        &[
+            "transaction_id",
+            "goal_id",
+            "user_id",
+            "amount",
+            "currency",
+            "occurred_at",
+            "created_at",
+        ],
+    ),
+    (
+        "reward_rules",
+        &[
+            "id",
+            "user_id",
+            "bank_account_id",
+            "category",
+            "rate",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "reconciliation_locks",
+        &[
+            "id",
+            "bank_account_id",
+            "period_start",
+            "period_end",
+            "locked_by",
+            "created_at",
+        ],
+    ),
+    (
+        "pending_transactions",
+        &[
+            "id",
+            "user_id",
+            "transaction_type",
+            "amount",
+            "currency",
+            "category",
+            "description",
+            "occurred_at",
+            "source_ref",
+            "parser",
+            "status",
+            "transaction_id",
+            "created_at",
+            "resolved_at",
+        ],
+    ),
+    (
+        "attachments",
+        &[
+            "id",
+            "transaction_id",
+            "user_id",
+            "file_name",
+            "content_type",
+            "size_bytes",
+            "sha256",
+            "data",
+            "thumbnail",
+            "thumbnail_status",
+            "storage_key",
+            "scan_status",
+            "malware_signature",
+            "created_at",
+        ],
+    ),
+    (
+        "imports",
+        &[
+            "id",
+            "user_id",
+            "format",
+            "currency",
+            "bank_account_id",
+            "storage_key",
+            "status",
+            "total_rows",
+            "rows_processed",
+            "inserted",
+            "duplicates_skipped",
+            "transfers_skipped",
+            "other_skipped",
+            "errors",
+            "rejected_rows",
+            "failure",
+            "created_at",
+            "started_at",
+            "finished_at",
+        ],
+    ),
+    (
+        "wallet_members",
+        &["owner_id", "member_id", "role", "created_at", "updated_at"],
+    ),
+    (
+        "approval_policies",
+        &["owner_id", "threshold", "currency", "updated_at"],
+    ),
+    (
+        "child_accounts",
+        &[
+            "child_id",
+            "parent_id",
+            "allowance",
+            "currency",
+            "allowed_categories",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "invitations",
+        &[
+            "id",
+            "token_hash",
+            "email",
+            "wallet_owner_id",
+            "role",
+            "created_by",
+            "created_at",
+            "expires_at",
+            "used_at",
+            "used_by",
+        ],
+    ),
+    (
+        "share_links",
+        &[
+            "id",
+            "user_id",
+            "token_hash",
+            "title",
+            "report",
+            "snapshot",
+            "created_at",
+            "expires_at",
+            "revoked_at",
+        ],
+    ),
+    (
+        "category_models",
+        &[
+            "user_id",
+            "version",
+            "trained_at",
+            "transactions",
+            "model",
+            "created_at",
+        ],
+    ),
+    (
+        "password_setup_tokens",
+        &["id", "user_id", "token_hash", "created_at", "expires_at"],
+    ),
+    (
+        "refresh_tokens",
+        &[
+            "id",
+            "user_id",
+            "family_id",
+            "token_hash",
+            "created_at",
+            "expires_at",
+            "used_at",
+            "replaced_by",
+            "revoked_at",
+        ],
+    ),
+    (
+        "user_encryption_keys",
+        &[
+            "user_id",
+            "wrapped_key",
+            "algorithm",
+            "kdf_params",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "notification_preferences",
+        &[
+            "user_id",
+            "weekly_digest",
+            "channel",
+            "last_digest_week",
+            "updated_at",
+        ],
+    ),
+    (
+        "telegram_links",
+        &[
+            "user_id",
+            "chat_id",
+            "link_code",
+            "link_code_expires_at",
+            "linked_at",
+            "created_at",
+        ],
+    ),
+    (
+        "audit_log",
+        &[
+            "seq",
+            "occurred_at",
+            "actor_id",
+            "action",
+            "entity_type",
+            "entity_id",
+            "details",
+            "prev_hash",
+            "hash",
+        ],
+    ),
+    (
+        "jobs",
+        &[
+            "id",
+            "kind",
+            "payload",
+            "status",
+            "attempts",
+            "max_attempts",
+            "run_at",
+            "locked_until",
+            "dedupe_key",
+            "last_error",
+            "created_at",
+            "started_at",
+            "finished_at",
+        ],
+    ),
+    (
+        "job_schedules",
+        &[
+            "kind",
+            "cron",
+            "payload",
+            "enabled",
+            "next_run_at",
+            "last_enqueued_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "webhook_endpoints",
+        &["id", "user_id", "url", "secret", "created_at"],
+    ),
+    (
+        "webhook_deliveries",
+        &[
+            "id",
+            "endpoint_id",
+            "event",
+            "payload",
+            "status",
+            "attempts",
+            "next_attempt_at",
+            "last_status_code",
+            "last_error",
+            "created_at",
+            "delivered_at",
+        ],
+    ),
+    (
+        "subscriptions",
+        &[
+            "user_id",
+            "stripe_customer_id",
+            "stripe_subscription_id",
+            "plan",
+            "status",
+            "current_period_end",
+            "last_event_at",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "invoices",
+        &[
+            "id",
+            "user_id",
+            "stripe_invoice_id",
+            "stripe_subscription_id",
+            "number",
+            "status",
+            "total",
+            "amount_paid",
+            "currency",
+            "hosted_invoice_url",
+            "invoice_pdf_url",
+            "period_start",
+            "period_end",
+            "paid_at",
+            "issued_at",
+            "last_event_at",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "usage_counters",
+        &["user_id", "metric", "period_start", "count"],
+    ),
+    (
+        "quota_warnings",
+        &["user_id", "metric", "period_start", "created_at"],
+    ),
+    (
+        "exchange_rates",
+        &["rate_date", "currency", "rate", "fetched_at"],
+    ),
+    (
+        "merchant_lookups",
+        &[
+            "descriptor_hash",
+            "name",
+            "logo_url",
+            "category",
+            "fetched_at",
+        ],
+    ),
+    (
+        "analytics_exports",
+        &["sink", "changed_at", "last_id", "exported", "updated_at"],
+    ),
+    (
+        "sync_tombstones",
+        &["entity_type", "entity_id", "user_id", "deleted_at"],
+    ),
+    (
+        "debug_captures",
+        &[
+            "id",
+            "captured_at",
+            "reason",
+            "endpoint",
+            "query",
+            "status",
+            "duration_ms",
+            "user_agent",
+            "request_body",
+            "response_body",
+        ],
+    ),
+];
+
+/// Enum types, with the labels of the matching Rust enums
+const ENUMS: &[(&str, &[&str])] = &[
+    ("transaction_type", &["Expense", "Income"]),
+    (
+        "transaction_source",
+        &["Manual", "Import", "BankSync", "Api"],
+    ),
+    (
+        "bank_sync_status",
+        &["Pending", "Active", "Failed", "Disconnected"],
+    ),
+];
+
+/// Check the schema of the database against what the queries expect
+///
+/// # Errors
+/// Returns the list of everything missing when the schema does not match, or a failed
+/// migration is recorded
+pub async fn check(pool: &DbPool) -> anyhow::Result<()> {
+    let mut columns: HashMap<String, HashSet<String>> = HashMap::new();
+    let rows = sqlx::query(
+        "SELECT table_name::text AS table_name, column_name::text AS column_name
+         FROM information_schema.columns WHERE table_schema = current_schema()",
+    )
+    .fetch_all(pool)
+    .await?;
+    for row in rows {
+        columns
+            .entry(row.try_get("table_name")?)
+            .or_default()
+            .insert(row.try_get("column_name")?);
+    }
+
+    let mut labels: HashMap<String, HashSet<String>> = HashMap::new();
+    let rows = sqlx::query(
+        "SELECT t.typname::text AS type_name, e.enumlabel::text AS label
+         FROM pg_enum e JOIN pg_type t ON t.oid = e.enumtypid",
+    )
+    .fetch_all(pool)
+    .await?;
+    for row in rows {
+        labels
+            .entry(row.try_get("type_name")?)
+            .or_default()
+            .insert(row.try_get("label")?);
+    }
+
+    let failed: Vec<String> = sqlx::query_scalar(
+        "SELECT description FROM _sqlx_migrations WHERE NOT success ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut problems: Vec<String> = failed
+        .iter()
+        .map(|migration| format!("migration {} failed", migration))
+        .collect();
+    problems.extend(missing("table", TABLES, &columns, "column"));
+    problems.extend(missing("enum type", ENUMS, &labels, "label"));
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The database schema does not match this version of the server:\n  - {}\n\
+             Check DATABASE_URL, and the migrations in the migrations directory",
+            problems.join("\n  - ")
+        ));
+    }
+    Ok(())
+}
+
+/// What is missing of `expected` in `actual`, one line per object
+fn missing(
+    kind: &str,
+    expected: &[(&str, &[&str])],
+    actual: &HashMap<String, HashSet<String>>,
+    member: &str,
+) -> Vec<String> {
+    expected
+        .iter()
+        .filter_map(|(name, members)| {
+            let Some(found) = actual.get(*name) else {
+                return Some(format!("{} {} is missing", kind, name));
+            };
+            let absent: Vec<&str> = members
+                .iter()
+                .copied()
+                .filter(|m| !found.contains(*m))
+                .collect();
+            (!absent.is_empty()).then(|| {
+                format!(
+                    "{} {} is missing {}(s) {}",
+                    kind,
+                    name,
+                    member,
+                    absent.join(", ")
+                )
+            })
+        })
+        .collect()
+}
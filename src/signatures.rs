@@ -0,0 +1,483 @@
+// Verification of signed inbound webhooks (Slack, Telegram, Stripe, ...)
+//
+// Each integration has one `WebhookVerifier`, looked up by name in `WebhookVerifiers`.
+// Routes receiving webhooks put `require_signature` in front of their handlers, which
+// checks the raw body before it is parsed.
+
+use crate::config::Config;
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use ed25519_dalek::{Signature, VerifyingKey};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Requests signed longer ago (or further ahead) than this are rejected as possible replays
+const MAX_SIGNATURE_AGE_SECS: u64 = 5 * 60;
+
+/// Webhook payloads are small, anything bigger is rejected before it is hashed
+const MAX_BODY_BYTES: usize = 256 * 1024;
+
+/// Why a webhook request was rejected
+#[derive(Debug)]
+pub enum SignatureError {
+    /// A signature or timestamp header is missing or malformed
+    Missing,
+    /// The signature is too old or too far in the future
+    Expired,
+    /// The signature does not match the body
+    Invalid,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SignatureError::Missing => "missing or malformed signature",
+            SignatureError::Expired => "signature timestamp out of range",
+            SignatureError::Invalid => "signature does not match",
+        };
+        f.write_str(s)
+    }
+}
+
+/// How one integration signs the webhooks it sends
+pub enum WebhookVerifier {
+    /// The shared secret itself in a header, e.g. Telegram's X-Telegram-Bot-Api-Secret-Token
+    SecretToken {
+        header: &'static str,
+        secret: String,
+    },
+    /// X-Slack-Signature: "v0=" + hex HMAC-SHA256 of "v0:<timestamp>:<body>"
+    Slack { signing_secret: String },
+    /// Stripe-Signature: "t=<timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">"
+    Stripe { signing_secret: String },
+    /// X-Signature-256: hex HMAC-SHA256 of the body, optionally prefixed with "sha256="
+    HmacSha256 { secret: String },
+    /// X-Signature-Ed25519: hex signature of "<X-Signature-Timestamp><body>"
+    Ed25519 { public_key: VerifyingKey },
+}
+
+impl WebhookVerifier {
+    /// Verifier for a known integration, keyed with its secret
+    /// Other integrations are configured with an explicit scheme, see `WebhookVerifiers::from_config`
+    pub fn for_integration(name: &str, secret: &str) -> Option<Self> {
+        let secret = secret.to_string();
+        match name {
+            "slack" => Some(Self::Slack {
+                signing_secret: secret,
+            }),
+            "telegram" => Some(Self::SecretToken {
+                header: "x-telegram-bot-api-secret-token",
+                secret,
+            }),
+            "stripe" => Some(Self::Stripe {
+                signing_secret: secret,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Verifier for an explicit scheme: "hmac-sha256" with a secret, "ed25519" with a hex public key
+    pub fn for_scheme(scheme: &str, secret: &str) -> anyhow::Result<Self> {
+        match scheme {
+            "hmac-sha256" => Ok(Self::HmacSha256 {
+                secret: secret.to_string(),
+            }),
+            "ed25519" => {
+                let bytes: [u8; 32] = decode_hex(secret)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| anyhow::anyhow!("ed25519 public keys are 64 hex characters"))?;
+                Ok(Self::Ed25519 {
+                    public_key: VerifyingKey::from_bytes(&bytes)?,
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "Unknown webhook signature scheme: {}",
+                scheme
+            )),
+        }
+    }
+
+    /// Check the request's signature headers against its raw body
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<(), SignatureError> {
+        match self {
+            Self::SecretToken { header, secret } => {
+                let given = header_value(headers, header)?;
+                if constant_time_eq(given.as_bytes(), secret.as_bytes()) {
+                    Ok(())
+                } else {
+                    Err(SignatureError::Invalid)
+                }
+            }
+            Self::Slack { signing_secret } => {
+                let timestamp = header_value(headers, "x-slack-request-timestamp")?;
+                check_timestamp(timestamp)?;
+                let signature = header_value(headers, "x-slack-signature")?
+                    .strip_prefix("v0=")
+                    .and_then(decode_hex)
+                    .ok_or(SignatureError::Missing)?;
+                let mut mac = hmac_sha256(signing_secret);
+                mac.update(format!("v0:{}:", timestamp).as_bytes());
+                mac.update(body);
+                // verify_slice compares in constant time
+                mac.verify_slice(&signature)
+                    .map_err(|_| SignatureError::Invalid)
+            }
+            Self::Stripe { signing_secret } => {
+                let header = header_value(headers, "stripe-signature")?;
+                let mut timestamp = None;
+                let mut signatures = Vec::new();
+                for part in header.split(',') {
+                    match part.trim().split_once('=') {
+                        Some(("t", value)) => timestamp = Some(value),
+                        Some(("v1", value)) => signatures.extend(decode_hex(value)),
+                        _ => {}
+                    }
+                }
+                let timestamp = timestamp.ok_or(SignatureError::Missing)?;
+                if signatures.is_empty() {
+                    return Err(SignatureError::Missing);
+                }
+                check_timestamp(timestamp)?;
+                // Several v1 signatures are sent while the signing secret is being rolled
+                let valid = signatures.iter().any(|signature| {
+                    let mut mac = hmac_sha256(signing_secret);
+                    mac.update(format!("{}.", timestamp).as_bytes());
+                    mac.update(body);
+                    mac.verify_slice(signature).is_ok()
+                });
+                if valid {
+                    Ok(())
+                } else {
+                    Err(SignatureError::Invalid)
+                }
+            }
+            Self::HmacSha256 { secret } => {
+                let given = header_value(headers, "x-signature-256")?;
+                let signature = decode_hex(given.strip_prefix("sha256=").unwrap_or(given))
+                    .ok_or(SignatureError::Missing)?;
+                let mut mac = hmac_sha256(secret);
+                mac.update(body);
+                mac.verify_slice(&signature)
+                    .map_err(|_| SignatureError::Invalid)
+            }
+            Self::Ed25519 { public_key } => {
+                let timestamp = header_value(headers, "x-signature-timestamp")?;
+                check_timestamp(timestamp)?;
+                let signature: [u8; 64] = decode_hex(header_value(headers, "x-signature-ed25519")?)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or(SignatureError::Missing)?;
+                let mut message = timestamp.as_bytes().to_vec();
+                message.extend_from_slice(body);
+                public_key
+                    .verify_strict(&message, &Signature::from_bytes(&signature))
+                    .map_err(|_| SignatureError::Invalid)
+            }
+        }
+    }
+}
+
+/// The verifier of each integration that has a secret configured
+#[derive(Default)]
+pub struct WebhookVerifiers(HashMap<String, Arc<WebhookVerifier>>);
+
+impl WebhookVerifiers {
+    /// Secrets come from WEBHOOK_SECRETS as "name:secret" for known integrations
+    /// (slack, telegram, stripe) and "name:scheme:secret" for others, comma separated.
+    /// SLACK_SIGNING_SECRET and TELEGRAM_WEBHOOK_SECRET take precedence for their integration
+    ///
+    /// # Errors
+    /// Returns an error if an entry is malformed or names an unknown scheme
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let mut verifiers = HashMap::new();
+        for entry in config
+            .webhook_secrets
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, secret) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Webhook secrets must be name:secret"))?;
+            let verifier = match WebhookVerifier::for_integration(name, secret) {
+                Some(verifier) => verifier,
+                None => {
+                    let (scheme, secret) = secret.split_once(':').ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Webhook secret for {} must be {}:scheme:secret",
+                            name,
+                            name
+                        )
+                    })?;
+                    WebhookVerifier::for_scheme(scheme, secret)?
+                }
+            };
+            verifiers.insert(name.to_string(), Arc::new(verifier));
+        }
+
+        for (name, secret) in [
+            ("slack", &config.slack_signing_secret),
+            ("telegram", &config.telegram_webhook_secret),
+        ] {
+            if let Some(verifier) = secret
+                .as_deref()
+                .and_then(|secret| WebhookVerifier::for_integration(name, secret))
+            {
+                verifiers.insert(name.to_string(), Arc::new(verifier));
+            }
+        }
+        Ok(Self(verifiers))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<WebhookVerifier>> {
+        self.0.get(name).cloned()
+    }
+}
+
+/// Middleware rejecting requests whose signature does not match their body with a 401
+pub async fn require_signature(
+    State(verifier): State<Arc<WebhookVerifier>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = req.into_parts();
+    let bytes = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    if let Err(e) = verifier.verify(&parts.headers, &bytes) {
+        eprintln!("Rejected webhook to {}: {}", parts.uri.path(), e);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Compare without leaking where the first difference is
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, SignatureError> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(SignatureError::Missing)
+}
+
+/// Unix timestamp in seconds, within `MAX_SIGNATURE_AGE_SECS` of now
+fn check_timestamp(timestamp: &str) -> Result<(), SignatureError> {
+    let timestamp: i64 = timestamp.parse().map_err(|_| SignatureError::Missing)?;
+    // abs_diff cannot overflow, whatever timestamp the sender made up
+    if Utc::now().timestamp().abs_diff(timestamp) <= MAX_SIGNATURE_AGE_SECS {
+        Ok(())
+    } else {
+        Err(SignatureError::Expired)
+    }
+}
+
+fn hmac_sha256(secret: &str) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length")
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn mac_hex(secret: &str, parts: &[&[u8]]) -> String {
+        let mut mac = hmac_sha256(secret);
+        for part in parts {
+            mac.update(part);
+        }
+        hex(&mac.finalize().into_bytes())
+    }
+
+    fn now() -> String {
+        Utc::now().timestamp().to_string()
+    }
+
+    fn stale() -> String {
+        (Utc::now().timestamp() - 10 * 60).to_string()
+    }
+
+    const BODY: &[u8] = br#"{"ok":true}"#;
+
+    fn slack_headers(secret: &str, timestamp: &str, body: &[u8]) -> HeaderMap {
+        let signature = mac_hex(secret, &[format!("v0:{}:", timestamp).as_bytes(), body]);
+        headers(&[
+            ("x-slack-request-timestamp", timestamp),
+            ("x-slack-signature", &format!("v0={}", signature)),
+        ])
+    }
+
+    #[test]
+    fn verifies_slack_signatures() {
+        let verifier = WebhookVerifier::for_integration("slack", "shh").unwrap();
+        let ts = now();
+        assert!(
+            verifier
+                .verify(&slack_headers("shh", &ts, BODY), BODY)
+                .is_ok()
+        );
+        assert!(matches!(
+            verifier.verify(&slack_headers("other", &ts, BODY), BODY),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verifier.verify(&slack_headers("shh", &ts, BODY), b"{}"),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verifier.verify(&slack_headers("shh", &stale(), BODY), BODY),
+            Err(SignatureError::Expired)
+        ));
+        assert!(matches!(
+            verifier.verify(&HeaderMap::new(), BODY),
+            Err(SignatureError::Missing)
+        ));
+    }
+
+    #[test]
+    fn verifies_stripe_signatures() {
+        let verifier = WebhookVerifier::for_integration("stripe", "whsec").unwrap();
+        let stripe = |timestamp: &str, secrets: &[&str]| {
+            let mut header = format!("t={}", timestamp);
+            for secret in secrets {
+                let signature = mac_hex(secret, &[format!("{}.", timestamp).as_bytes(), BODY]);
+                header.push_str(&format!(",v1={}", signature));
+            }
+            headers(&[("stripe-signature", &header)])
+        };
+        let ts = now();
+        assert!(verifier.verify(&stripe(&ts, &["whsec"]), BODY).is_ok());
+        // While the secret is rolled, one matching v1 signature is enough
+        assert!(
+            verifier
+                .verify(&stripe(&ts, &["old", "whsec"]), BODY)
+                .is_ok()
+        );
+        assert!(matches!(
+            verifier.verify(&stripe(&ts, &["old", "other"]), BODY),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verifier.verify(&stripe(&stale(), &["whsec"]), BODY),
+            Err(SignatureError::Expired)
+        ));
+        assert!(matches!(
+            verifier.verify(&stripe(&ts, &[]), BODY),
+            Err(SignatureError::Missing)
+        ));
+    }
+
+    #[test]
+    fn verifies_telegram_secret_tokens() {
+        let verifier = WebhookVerifier::for_integration("telegram", "token").unwrap();
+        let header = |value| headers(&[("x-telegram-bot-api-secret-token", value)]);
+        assert!(verifier.verify(&header("token"), BODY).is_ok());
+        assert!(matches!(
+            verifier.verify(&header("tokens"), BODY),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verifier.verify(&HeaderMap::new(), BODY),
+            Err(SignatureError::Missing)
+        ));
+    }
+
+    #[test]
+    fn verifies_hmac_sha256_signatures() {
+        let verifier = WebhookVerifier::for_scheme("hmac-sha256", "key").unwrap();
+        let signature = mac_hex("key", &[BODY]);
+        let header = |value: &str| headers(&[("x-signature-256", value)]);
+        assert!(verifier.verify(&header(&signature), BODY).is_ok());
+        assert!(
+            verifier
+                .verify(&header(&format!("sha256={}", signature)), BODY)
+                .is_ok()
+        );
+        assert!(matches!(
+            verifier.verify(&header(&mac_hex("other", &[BODY])), BODY),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verifier.verify(&header("not hex"), BODY),
+            Err(SignatureError::Missing)
+        ));
+    }
+
+    #[test]
+    fn verifies_ed25519_signatures() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let verifier =
+            WebhookVerifier::for_scheme("ed25519", &hex(key.verifying_key().as_bytes())).unwrap();
+        let signed = |key: &SigningKey, timestamp: &str| {
+            let mut message = timestamp.as_bytes().to_vec();
+            message.extend_from_slice(BODY);
+            let signature = hex(&key.sign(&message).to_bytes());
+            headers(&[
+                ("x-signature-timestamp", timestamp),
+                ("x-signature-ed25519", &signature),
+            ])
+        };
+        let ts = now();
+        assert!(verifier.verify(&signed(&key, &ts), BODY).is_ok());
+        assert!(matches!(
+            verifier.verify(&signed(&SigningKey::from_bytes(&[8; 32]), &ts), BODY),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verifier.verify(&signed(&key, &stale()), BODY),
+            Err(SignatureError::Expired)
+        ));
+    }
+
+    #[test]
+    fn rejects_timestamps_out_of_range() {
+        assert!(check_timestamp(&now()).is_ok());
+        assert!(matches!(
+            check_timestamp(&i64::MIN.to_string()),
+            Err(SignatureError::Expired)
+        ));
+        assert!(matches!(
+            check_timestamp(&i64::MAX.to_string()),
+            Err(SignatureError::Expired)
+        ));
+        assert!(matches!(
+            check_timestamp("soon"),
+            Err(SignatureError::Missing)
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::handlers::{self, AppState};
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::transaction_models::{TransactionFilter, TransactionSource};
+use crate::queries::transaction_queries;
+use crate::quick_entry::{self, QuickEntry};
+use crate::quotas;
+use crate::restrictions;
+use crate::signatures::{self, WebhookVerifier};
+use crate::validation::{Validate, ValidationErrors};
+use axum::{Form, Json, Router, extract::State, middleware, routing::post};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Slack slash command ("/wallet 12.50 lunch") recording expenses in a team's wallet
+pub struct SlackIntegration {
+    pub state: AppState,
+    /// Checks every request against the Slack app's signing secret
+    pub verifier: Arc<WebhookVerifier>,
+    /// Wallet user of each Slack workspace, by team id
+    pub teams: HashMap<String, Uuid>,
+}
+
+impl SlackIntegration {
+    /// Parse "T0123:user-uuid,T0456:user-uuid" into a team to wallet user map
+    pub fn parse_teams(raw: &str) -> anyhow::Result<HashMap<String, Uuid>> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (team, user) = entry
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("Slack team entries must be team_id:user_id"))?;
+                let user = user.trim().parse().map_err(|e| {
+                    anyhow::anyhow!("Invalid user id for Slack team {}: {}", team, e)
+                })?;
+                Ok((team.trim().to_string(), user))
+            })
+            .collect()
+    }
+
+    /// Route for the slash command, behind the signature check
+    pub fn router<S>(self) -> Router<S> {
+        let verifier = self.verifier.clone();
+        Router::new()
+            .route("/api/integrations/slack", post(slash_command))
+            .route_layer(middleware::from_fn_with_state(
+                verifier,
+                signatures::require_signature,
+            ))
+            .with_state(Arc::new(self))
+    }
+}
+
+/// The fields of a slash command payload used here
+#[derive(Debug, Deserialize)]
+struct SlashCommand {
+    team_id: String,
+    user_name: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// Only the user who typed the command sees the reply
+fn ephemeral(text: impl Into<String>) -> Json<Value> {
+    Json(json!({ "response_type": "ephemeral", "text": text.into() }))
+}
+
+/// The reply is posted to the channel for everyone to see
+fn in_channel(text: impl Into<String>) -> Json<Value> {
+    Json(json!({ "response_type": "in_channel", "text": text.into() }))
+}
+
+/// POST /api/integrations/slack - "/wallet <amount> <note>", "/wallet balance" or "/wallet help"
+/// Errors are answered with 200 and a message, Slack shows other statuses as a generic failure
+async fn slash_command(
+    State(slack): State<Arc<SlackIntegration>>,
+    Form(command): Form<SlashCommand>,
+) -> Json<Value> {
+    let Some(&user_id) = slack.teams.get(&command.team_id) else {
+        return ephemeral("This Slack workspace is not connected to a wallet");
+    };
+
+    let text = command.text.trim();
+    match text.to_lowercase().as_str() {
+        "" | "help" => ephemeral(help()),
+        "balance" => balance(&slack, user_id).await,
+        _ => record(&slack, user_id, &command.user_name, text).await,
+    }
+}
+
+async fn balance(slack: &SlackIntegration, user_id: Uuid) -> Json<Value> {
+    match transaction_queries::get_user_transaction_sum(
+        &slack.state.db,
+        &TransactionFilter::for_user(user_id),
+    )
+    .await
+    {
+        Ok(totals) => ephemeral(format!("Wallet balance: {}", totals.net)),
+        Err(e) => {
+            eprintln!("Error getting balance for Slack: {}", e);
+            ephemeral("Something went wrong, try again later")
+        }
+    }
+}
+
+async fn record(
+    slack: &SlackIntegration,
+    user_id: Uuid,
+    slack_user: &str,
+    text: &str,
+) -> Json<Value> {
+    let Some(entry) = QuickEntry::parse(text) else {
+        return ephemeral(help());
+    };
+    let mut errors = ValidationErrors::default();
+    entry.validate(&mut errors);
+    if !errors.is_empty() {
+        return ephemeral("That amount or description is out of range");
+    }
+
+    let transaction = entry.into_transaction(user_id, TransactionSource::Slack);
+    let transactions = std::slice::from_ref(&transaction);
+    match restrictions::check(&slack.state.db, transactions, false).await {
+        Ok(checked) => {
+            if let Some(refusal) = checked.refusal() {
+                return ephemeral(refusal.to_string());
+            }
+        }
+        Err(e) => {
+            eprintln!("Error checking restrictions for Slack: {}", e);
+            return ephemeral("Could not save that, try again later");
+        }
+    }
+    match quotas::reserve_write(&slack.state.db, user_id, 1).await {
+        Ok(None) => {}
+        Ok(Some(exceeded)) => return ephemeral(exceeded.to_string()),
+        Err(e) => {
+            eprintln!("Error checking quotas for Slack: {}", e);
+            return ephemeral("Could not save that, try again later");
+        }
+    }
+    let transaction_id =
+        match transaction_queries::create_transaction(&slack.state.db, &transaction).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                quotas::release_transactions(&slack.state.db, user_id, 1).await;
+                return ephemeral("That transaction was already recorded");
+            }
+            Err(e) => {
+                quotas::release_transactions(&slack.state.db, user_id, 1).await;
+                eprintln!("Error creating transaction from Slack: {}", e);
+                return ephemeral("Could not save that, try again later");
+            }
+        };
+
+    handlers::record_audit(
+        &slack.state,
+        AuditEntryCreate::new("create", "transaction", Some(transaction_id))
+            .actor(user_id)
+            .details(json!({
+                "transaction_type": transaction.transaction_type,
+                "amount": transaction.amount,
+                "category": transaction.category,
+                "source": transaction.source,
+                "slack_user": slack_user,
+            })),
+    )
+    .await;
+
+    in_channel(format!(
+        "{} recorded {} {}: {} ({})",
+        slack_user,
+        transaction.transaction_type.to_string().to_lowercase(),
+        transaction.amount.abs(),
+        transaction.description,
+        transaction.category
+    ))
+}
+
+const HELP: &str = "Record an expense with /wallet followed by an amount and a note, e.g.";
+
+fn help() -> String {
+    format!(
+        "{}\n{}\nSee the totals with /wallet balance",
+        HELP,
+        quick_entry::EXAMPLES
+    )
+}
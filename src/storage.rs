@@ -0,0 +1,217 @@
+// Object storage for files: attachments and exports
+//
+// Everything that stores files goes through `BlobStore`, whose backend is picked by
+// BLOB_STORE: a local directory (the default), Amazon S3 or any S3 compatible service,
+// Google Cloud Storage (through its S3 compatible XML API with HMAC keys) or Azure Blob
+// Storage. Blobs are read and written as streams of chunks, remote backends upload large
+// blobs in parts so a file is never held in memory as a whole. Backends that can sign
+// URLs hand out time-limited download links, so clients fetch large files directly.
+
+mod azure;
+mod local;
+mod s3;
+
+use crate::config::Config;
+use axum::async_trait;
+use axum::body::Bytes;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use azure::AzureStore;
+pub use local::LocalStore;
+pub use s3::S3Store;
+
+/// Contents of a blob, chunk by chunk
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// How long download links stay valid
+pub const LINK_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Size of the parts large blobs are uploaded in by remote backends
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Where blobs are kept, keys are paths like "attachments/<user id>/<id>"
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Store a blob, replacing any under the same key, returning its size
+    async fn put(&self, key: &str, content_type: &str, body: ByteStream) -> anyhow::Result<u64>;
+
+    /// Read a blob, None when there is no such blob
+    async fn get(&self, key: &str) -> anyhow::Result<Option<ByteStream>>;
+
+    /// Delete a blob, deleting a missing blob is not an error
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+
+    /// URL the blob can be downloaded from without credentials for `expires_in`
+    /// None when the backend cannot sign URLs
+    fn presigned_url(&self, _key: &str, _expires_in: Duration) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// The storage backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobBackend {
+    Local,
+    S3,
+    Gcs,
+    Azure,
+}
+
+impl fmt::Display for BlobBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            BlobBackend::Local => "local",
+            BlobBackend::S3 => "s3",
+            BlobBackend::Gcs => "gcs",
+            BlobBackend::Azure => "azure",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for BlobBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(BlobBackend::Local),
+            "s3" => Ok(BlobBackend::S3),
+            "gcs" => Ok(BlobBackend::Gcs),
+            "azure" => Ok(BlobBackend::Azure),
+            _ => Err(format!("Unknown blob store: {}", s)),
+        }
+    }
+}
+
+/// The configured blob store
+///
+/// # Errors
+/// Returns an error if BLOB_STORE is unknown or its backend is missing settings
+pub fn from_config(config: &Config) -> anyhow::Result<Arc<dyn BlobStore>> {
+    let backend = config
+        .blob_store
+        .parse::<BlobBackend>()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let required = |value: &Option<String>, name: &str| {
+        value
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("{} is required with BLOB_STORE={}", name, backend))
+    };
+    let store: Arc<dyn BlobStore> = match backend {
+        BlobBackend::Local => Arc::new(LocalStore::new(&config.blob_store_dir)?),
+        BlobBackend::S3 | BlobBackend::Gcs => {
+            let bucket = required(&config.blob_store_bucket, "BLOB_STORE_BUCKET")?;
+            let (region, endpoint) = match backend {
+                // GCS takes any region in signatures, "auto" by convention
+                BlobBackend::Gcs => (
+                    config.blob_store_region.as_deref().unwrap_or("auto"),
+                    Some(
+                        config
+                            .blob_store_endpoint
+                            .as_deref()
+                            .unwrap_or("https://storage.googleapis.com"),
+                    ),
+                ),
+                _ => (
+                    config.blob_store_region.as_deref().unwrap_or("us-east-1"),
+                    config.blob_store_endpoint.as_deref(),
+                ),
+            };
+            Arc::new(S3Store::new(
+                &bucket,
+                region,
+                endpoint,
+                required(&config.blob_store_access_key, "BLOB_STORE_ACCESS_KEY")?,
+                required(&config.blob_store_secret_key, "BLOB_STORE_SECRET_KEY")?,
+            )?)
+        }
+        BlobBackend::Azure => Arc::new(AzureStore::new(
+            &required(&config.blob_store_access_key, "BLOB_STORE_ACCESS_KEY")?,
+            &required(&config.blob_store_secret_key, "BLOB_STORE_SECRET_KEY")?,
+            &required(&config.blob_store_bucket, "BLOB_STORE_BUCKET")?,
+            config.blob_store_endpoint.as_deref(),
+        )?),
+    };
+    Ok(store)
+}
+
+/// A blob already in memory, as a stream
+pub fn bytes_stream(bytes: impl Into<Bytes>) -> ByteStream {
+    stream::once(std::future::ready(Ok(bytes.into()))).boxed()
+}
+
+/// Read a whole blob, failing once it goes over `limit` bytes
+pub async fn read_to_end(mut body: ByteStream, limit: usize) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > limit {
+            return Err(anyhow::anyhow!("Blob is larger than {} bytes", limit));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// Read the next part of an upload from the stream, at most `PART_SIZE` bytes unless a
+/// single chunk is larger. Empty at the end of the stream
+async fn next_part(body: &mut ByteStream, carry: &mut Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    while carry.len() < PART_SIZE {
+        match body.next().await {
+            Some(chunk) => carry.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    let rest = carry.split_off(carry.len().min(PART_SIZE));
+    Ok(std::mem::replace(carry, rest))
+}
+
+/// A response body as a stream, read chunk by chunk
+fn response_stream(response: reqwest::Response) -> ByteStream {
+    stream::unfold(Some(response), |response| async move {
+        let mut response = response?;
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+            Ok(None) => None,
+            Err(e) => Some((Err(std::io::Error::other(e)), None)),
+        }
+    })
+    .boxed()
+}
+
+/// Fail with the status and body of an unsuccessful response
+async fn check_response(
+    response: reqwest::Response,
+    action: &str,
+) -> anyhow::Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(anyhow::anyhow!(
+        "{} failed with {}: {}",
+        action,
+        status,
+        body.chars().take(500).collect::<String>()
+    ))
+}
+
+/// Percent-encode a key for a URL path, keeping its slashes
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| percent_encoding::utf8_percent_encode(segment, URI_UNRESERVED).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Everything but the unreserved characters of RFC 3986 is encoded in signed URLs
+const URI_UNRESERVED: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
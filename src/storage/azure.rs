@@ -0,0 +1,234 @@
+// Azure Blob Storage
+//
+// Requests are signed with the storage account's shared key. Blobs up to one part are
+// uploaded with a single Put Blob, larger ones block by block and committed with a block
+// list. Download links are service SAS URLs.
+
+use super::{BlobStore, ByteStream, check_response, encode_key, next_part, response_stream};
+use axum::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode, Url};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Version of the Blob service REST API requests and SAS URLs are made for
+const API_VERSION: &str = "2021-08-06";
+
+pub struct AzureStore {
+    client: reqwest::Client,
+    account: String,
+    key: Vec<u8>,
+    container: String,
+    /// URL of the container, keys are appended to its path
+    container_url: Url,
+}
+
+impl AzureStore {
+    /// Without an endpoint (e.g. Azurite's http://127.0.0.1:10000/devstoreaccount1) the
+    /// account's public endpoint is used
+    pub fn new(
+        account: &str,
+        key: &str,
+        container: &str,
+        endpoint: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let endpoint = endpoint
+            .map(|e| e.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| format!("https://{}.blob.core.windows.net", account));
+        let container_url = format!("{}/{}", endpoint, container);
+        Ok(Self {
+            client: reqwest::Client::new(),
+            account: account.to_string(),
+            key: BASE64
+                .decode(key)
+                .map_err(|e| anyhow::anyhow!("Invalid Azure storage account key: {}", e))?,
+            container: container.to_string(),
+            container_url: Url::parse(&container_url)
+                .map_err(|e| anyhow::anyhow!("Invalid blob store URL {}: {}", container_url, e))?,
+        })
+    }
+
+    fn sign(&self, string_to_sign: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(string_to_sign.as_bytes());
+        BASE64.encode(mac.finalize().into_bytes())
+    }
+
+    /// Encoded path of a key
+    fn path(&self, key: &str) -> String {
+        format!(
+            "{}/{}",
+            self.container_url.path().trim_end_matches('/'),
+            encode_key(key)
+        )
+    }
+
+    fn url(&self, key: &str) -> String {
+        let mut url = self.container_url.clone();
+        url.set_path(&self.path(key));
+        url.to_string()
+    }
+
+    /// Send a request signed with the shared key, with the whole body in memory
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let mut ms_headers: Vec<(String, String)> = headers
+            .iter()
+            .filter(|(name, _)| name.starts_with("x-ms-"))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ms_headers.push(("x-ms-date".to_string(), date));
+        ms_headers.push(("x-ms-version".to_string(), API_VERSION.to_string()));
+        ms_headers.sort();
+        let content_type = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| *value)
+            .unwrap_or_default();
+        let content_length = if body.is_empty() {
+            String::new()
+        } else {
+            body.len().to_string()
+        };
+
+        let mut sorted_query = query.to_vec();
+        sorted_query.sort();
+        let mut string_to_sign = format!(
+            "{}\n\n\n{}\n\n{}\n\n\n\n\n\n\n",
+            method, content_length, content_type
+        );
+        for (name, value) in &ms_headers {
+            string_to_sign.push_str(&format!("{}:{}\n", name, value));
+        }
+        string_to_sign.push_str(&format!("/{}{}", self.account, self.path(key)));
+        for (name, value) in &sorted_query {
+            string_to_sign.push_str(&format!("\n{}:{}", name.to_lowercase(), value));
+        }
+
+        let mut request = self
+            .client
+            .request(method, self.url(key))
+            .query(query)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("SharedKey {}:{}", self.account, self.sign(&string_to_sign)),
+            );
+        for (name, value) in &ms_headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if !content_type.is_empty() {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        Ok(request.body(body).send().await?)
+    }
+}
+
+#[async_trait]
+impl BlobStore for AzureStore {
+    async fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        mut body: ByteStream,
+    ) -> anyhow::Result<u64> {
+        let mut carry = Vec::new();
+        let mut part = next_part(&mut body, &mut carry).await?;
+        let mut next = next_part(&mut body, &mut carry).await?;
+        if next.is_empty() {
+            let size = part.len() as u64;
+            let headers = [
+                ("x-ms-blob-type", "BlockBlob"),
+                ("content-type", content_type),
+            ];
+            let response = self.send(Method::PUT, key, &[], &headers, part).await?;
+            check_response(response, "Uploading blob").await?;
+            return Ok(size);
+        }
+
+        // Uncommitted blocks are discarded by Azure after a week
+        let mut size = 0u64;
+        let mut block_list = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        let mut number = 0u32;
+        while !part.is_empty() {
+            size += part.len() as u64;
+            // Ids of a blob's blocks must all have the same length
+            let block_id = BASE64.encode(format!("{:08}", number));
+            let query = [("comp", "block".to_string()), ("blockid", block_id.clone())];
+            let response = self.send(Method::PUT, key, &query, &[], part).await?;
+            check_response(response, "Uploading block").await?;
+            block_list.push_str(&format!("<Latest>{}</Latest>", block_id));
+            number += 1;
+            part = std::mem::take(&mut next);
+            if !part.is_empty() {
+                next = next_part(&mut body, &mut carry).await?;
+            }
+        }
+        block_list.push_str("</BlockList>");
+
+        let query = [("comp", "blocklist".to_string())];
+        let headers = [
+            ("x-ms-blob-content-type", content_type),
+            ("content-type", "application/xml"),
+        ];
+        let response = self
+            .send(Method::PUT, key, &query, &headers, block_list.into_bytes())
+            .await?;
+        check_response(response, "Committing block list").await?;
+        Ok(size)
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<ByteStream>> {
+        let response = self.send(Method::GET, key, &[], &[], Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check_response(response, "Downloading blob").await?;
+        Ok(Some(response_stream(response)))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let response = self.send(Method::DELETE, key, &[], &[], Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_response(response, "Deleting blob").await?;
+        Ok(())
+    }
+
+    fn presigned_url(&self, key: &str, expires_in: Duration) -> anyhow::Result<Option<String>> {
+        let expiry = (Utc::now() + chrono::Duration::from_std(expires_in)?)
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+        let protocol = if self.container_url.scheme() == "https" {
+            "https"
+        } else {
+            "https,http"
+        };
+        // Fields: permissions, start, expiry, resource, identifier, IP, protocol, version,
+        // resource type, snapshot time, encryption scope and five response header overrides
+        let string_to_sign = format!(
+            "r\n\n{}\n/blob/{}/{}/{}\n\n\n{}\n{}\nb\n\n\n\n\n\n\n",
+            expiry, self.account, self.container, key, protocol, API_VERSION
+        );
+        let mut url = Url::parse(&self.url(key))?;
+        url.query_pairs_mut()
+            .append_pair("sv", API_VERSION)
+            .append_pair("sr", "b")
+            .append_pair("sp", "r")
+            .append_pair("se", &expiry)
+            .append_pair("spr", protocol)
+            .append_pair("sig", &self.sign(&string_to_sign));
+        Ok(Some(url.to_string()))
+    }
+}
@@ -0,0 +1,87 @@
+// Blobs as files under a directory, for single-server setups
+
+use super::{BlobStore, ByteStream};
+use axum::async_trait;
+use futures_util::StreamExt;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    /// Store blobs under `root`, created if missing
+    pub fn new(root: &str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(root)
+            .map_err(|e| anyhow::anyhow!("Cannot create blob directory {}: {}", root, e))?;
+        Ok(Self {
+            root: PathBuf::from(root),
+        })
+    }
+
+    /// Path of a key, keys climbing out of the root are refused
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(anyhow::anyhow!("Invalid blob key: {}", key));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalStore {
+    async fn put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        mut body: ByteStream,
+    ) -> anyhow::Result<u64> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // Written next to the blob and moved over it, readers never see half a file
+        let temporary = path.with_extension(format!("upload-{}", Uuid::new_v4()));
+        let written = async {
+            let mut file = fs::File::create(&temporary).await?;
+            let mut size = 0u64;
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                file.write_all(&chunk).await?;
+                size += chunk.len() as u64;
+            }
+            file.sync_all().await?;
+            fs::rename(&temporary, &path).await?;
+            anyhow::Ok(size)
+        }
+        .await;
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary).await;
+        }
+        written
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<ByteStream>> {
+        match fs::File::open(self.path(key)?).await {
+            Ok(file) => Ok(Some(ReaderStream::new(file).boxed())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,367 @@
+// Amazon S3 and S3 compatible services (MinIO, Google Cloud Storage, ...)
+//
+// Requests are signed with AWS Signature Version 4. Blobs up to one part are uploaded with
+// a single PUT, larger ones with a multipart upload, one part at a time.
+
+use super::{
+    BlobStore, ByteStream, URI_UNRESERVED, check_response, encode_key, next_part, response_stream,
+};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use reqwest::{Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Payload hash of presigned URLs, whose body is not known when signing
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+pub struct S3Store {
+    client: reqwest::Client,
+    /// URL of the bucket, keys are appended to its path
+    bucket_url: Url,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Query string in the canonical form of signatures, sorted and fully encoded
+fn canonical_query(query: &[(&str, String)]) -> String {
+    let mut pairs: Vec<String> = query
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                percent_encoding::utf8_percent_encode(name, URI_UNRESERVED),
+                percent_encoding::utf8_percent_encode(value, URI_UNRESERVED)
+            )
+        })
+        .collect();
+    pairs.sort();
+    pairs.join("&")
+}
+
+impl S3Store {
+    /// With an endpoint (MinIO, GCS, ...) buckets are addressed by path, without one the
+    /// bucket is a subdomain of the region's AWS endpoint
+    pub fn new(
+        bucket: &str,
+        region: &str,
+        endpoint: Option<&str>,
+        access_key: String,
+        secret_key: String,
+    ) -> anyhow::Result<Self> {
+        let bucket_url = match endpoint {
+            Some(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+            None => format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            bucket_url: Url::parse(&bucket_url)
+                .map_err(|e| anyhow::anyhow!("Invalid blob store URL {}: {}", bucket_url, e))?,
+            region: region.to_string(),
+            access_key,
+            secret_key,
+        })
+    }
+
+    fn host(&self) -> String {
+        let host = self.bucket_url.host_str().unwrap_or_default();
+        match self.bucket_url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    /// Encoded path of a key, as signed
+    fn path(&self, key: &str) -> String {
+        format!(
+            "{}/{}",
+            self.bucket_url.path().trim_end_matches('/'),
+            encode_key(key)
+        )
+    }
+
+    fn url(&self, key: &str, query: &str) -> String {
+        let mut url = format!(
+            "{}://{}{}",
+            self.bucket_url.scheme(),
+            self.host(),
+            self.path(key)
+        );
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+
+    fn scope(&self, now: DateTime<Utc>) -> String {
+        format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), self.region)
+    }
+
+    /// Signature of a canonical request made at `now`
+    fn signature(&self, now: DateTime<Utc>, canonical_request: &str) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            self.scope(now),
+            hex_sha256(canonical_request.as_bytes())
+        );
+        let date = now.format("%Y%m%d").to_string();
+        let key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let key = hmac_sha256(&key, &self.region);
+        let key = hmac_sha256(&key, "s3");
+        let key = hmac_sha256(&key, "aws4_request");
+        hmac_sha256(&key, &string_to_sign)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Send a signed request with the whole body in memory
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex_sha256(&body);
+        let query = canonical_query(query);
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method,
+            self.path(key),
+            query,
+            self.host(),
+            payload_hash,
+            amz_date,
+            payload_hash
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key,
+            self.scope(now),
+            self.signature(now, &canonical_request)
+        );
+
+        let mut request = self
+            .client
+            .request(method, self.url(key, &query))
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(reqwest::header::AUTHORIZATION, authorization);
+        if let Some(content_type) = content_type {
+            request = request.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        Ok(request.body(body).send().await?)
+    }
+
+    /// Presigned GET URL of a key, signed at `now`
+    fn presigned_url_at(&self, key: &str, expires_in: Duration, now: DateTime<Utc>) -> String {
+        let query = [
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            (
+                "X-Amz-Credential",
+                format!("{}/{}", self.access_key, self.scope(now)),
+            ),
+            ("X-Amz-Date", now.format("%Y%m%dT%H%M%SZ").to_string()),
+            // At most a week
+            (
+                "X-Amz-Expires",
+                expires_in.as_secs().clamp(1, 604_800).to_string(),
+            ),
+            ("X-Amz-SignedHeaders", "host".to_string()),
+        ];
+        let query = canonical_query(&query);
+        let canonical_request = format!(
+            "GET\n{}\n{}\nhost:{}\n\nhost\n{}",
+            self.path(key),
+            query,
+            self.host(),
+            UNSIGNED_PAYLOAD
+        );
+        let query = format!(
+            "{}&X-Amz-Signature={}",
+            query,
+            self.signature(now, &canonical_request)
+        );
+        self.url(key, &query)
+    }
+
+    async fn put_parts(
+        &self,
+        key: &str,
+        content_type: &str,
+        first: Vec<u8>,
+        second: Vec<u8>,
+        body: &mut ByteStream,
+        carry: &mut Vec<u8>,
+    ) -> anyhow::Result<u64> {
+        let response = self
+            .send(
+                Method::POST,
+                key,
+                &[("uploads", String::new())],
+                Some(content_type),
+                Vec::new(),
+            )
+            .await?;
+        let created = check_response(response, "Starting multipart upload")
+            .await?
+            .text()
+            .await?;
+        let upload_id = xml_element(&created, "UploadId")
+            .ok_or_else(|| anyhow::anyhow!("No UploadId in {}", created))?;
+
+        let uploaded = async {
+            let mut size = 0u64;
+            let mut completed = String::from("<CompleteMultipartUpload>");
+            let mut part = first;
+            let mut queued = Some(second);
+            let mut number = 1;
+            while !part.is_empty() {
+                size += part.len() as u64;
+                let query = [
+                    ("partNumber", number.to_string()),
+                    ("uploadId", upload_id.clone()),
+                ];
+                let response = self.send(Method::PUT, key, &query, None, part).await?;
+                let response = check_response(response, "Uploading part").await?;
+                let etag = response
+                    .headers()
+                    .get(reqwest::header::ETAG)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| anyhow::anyhow!("No ETag for part {}", number))?;
+                completed.push_str(&format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    number, etag
+                ));
+                number += 1;
+                part = match queued.take() {
+                    Some(part) => part,
+                    None => next_part(body, carry).await?,
+                };
+            }
+            completed.push_str("</CompleteMultipartUpload>");
+
+            let query = [("uploadId", upload_id.clone())];
+            let response = self
+                .send(
+                    Method::POST,
+                    key,
+                    &query,
+                    Some("application/xml"),
+                    completed.into_bytes(),
+                )
+                .await?;
+            // Errors can also come back in a 200 response once the upload has started
+            let text = check_response(response, "Completing multipart upload")
+                .await?
+                .text()
+                .await?;
+            if text.contains("<Error>") {
+                return Err(anyhow::anyhow!(
+                    "Completing multipart upload failed: {}",
+                    text
+                ));
+            }
+            anyhow::Ok(size)
+        }
+        .await;
+
+        if uploaded.is_err() {
+            // Otherwise the parts are kept (and billed) until a lifecycle rule cleans them up
+            let query = [("uploadId", upload_id)];
+            if let Err(e) = self
+                .send(Method::DELETE, key, &query, None, Vec::new())
+                .await
+            {
+                eprintln!("Error aborting multipart upload of {}: {}", key, e);
+            }
+        }
+        uploaded
+    }
+}
+
+/// Text of the first `<name>` element of an XML response
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+    static ELEMENT: OnceLock<Regex> = OnceLock::new();
+    let element = ELEMENT.get_or_init(|| {
+        Regex::new(r"<(?P<name>[A-Za-z]+)>(?P<text>[^<]*)</[A-Za-z]+>").expect("valid regex")
+    });
+    element
+        .captures_iter(xml)
+        .find(|c| &c["name"] == name)
+        .map(|c| c["text"].to_string())
+}
+
+#[async_trait]
+impl BlobStore for S3Store {
+    async fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        mut body: ByteStream,
+    ) -> anyhow::Result<u64> {
+        let mut carry = Vec::new();
+        let first = next_part(&mut body, &mut carry).await?;
+        let second = next_part(&mut body, &mut carry).await?;
+        if !second.is_empty() {
+            return self
+                .put_parts(key, content_type, first, second, &mut body, &mut carry)
+                .await;
+        }
+
+        let size = first.len() as u64;
+        let response = self
+            .send(Method::PUT, key, &[], Some(content_type), first)
+            .await?;
+        check_response(response, "Uploading blob").await?;
+        Ok(size)
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<ByteStream>> {
+        let response = self.send(Method::GET, key, &[], None, Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check_response(response, "Downloading blob").await?;
+        Ok(Some(response_stream(response)))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let response = self
+            .send(Method::DELETE, key, &[], None, Vec::new())
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_response(response, "Deleting blob").await?;
+        Ok(())
+    }
+
+    fn presigned_url(&self, key: &str, expires_in: Duration) -> anyhow::Result<Option<String>> {
+        Ok(Some(self.presigned_url_at(key, expires_in, Utc::now())))
+    }
+}
@@ -0,0 +1,61 @@
+// Category suggestions
+//
+// Every user's own categorizations are learned: how many of the transactions of each
+// merchant (the name merchant enrichment found, else the description) they put in each
+// category. The categories.train job, scheduled nightly by default, rebuilds the model of
+// every user whose transactions changed since their model was trained, so correcting a
+// category shows in suggestions after the next run. Each rebuild learning something new
+// is a new version of the user's model, suggestions name the version they come from and
+// the latest MODEL_VERSIONS_KEPT versions are kept.
+
+use crate::database::DbPool;
+use crate::jobs::JobHandler;
+use crate::models::suggestion_models::CategoryModel;
+use crate::queries::suggestion_queries;
+use axum::async_trait;
+use chrono::Utc;
+use serde_json::Value;
+
+/// Versions of a user's model kept, the latest one is used
+pub const MODEL_VERSIONS_KEPT: i32 = 3;
+
+/// Job retraining the models of the users whose transactions changed
+pub struct TrainCategoryModelsJob;
+
+#[async_trait]
+impl JobHandler for TrainCategoryModelsJob {
+    fn kind(&self) -> &'static str {
+        "categories.train"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let mut trained = 0;
+        for user_id in suggestion_queries::get_stale_users(db).await? {
+            // Transactions changed while training are learned on the next run
+            let trained_at = Utc::now();
+            let rows = suggestion_queries::get_training_rows(db, user_id).await?;
+            let model = CategoryModel::train(&rows);
+            match suggestion_queries::get_latest(db, user_id).await? {
+                Some(latest) if latest.model == model => {
+                    suggestion_queries::touch(db, user_id, latest.version, trained_at).await?;
+                }
+                _ => {
+                    suggestion_queries::store(
+                        db,
+                        user_id,
+                        &model,
+                        i32::try_from(rows.len())?,
+                        trained_at,
+                        MODEL_VERSIONS_KEPT,
+                    )
+                    .await?;
+                    trained += 1;
+                }
+            }
+        }
+        if trained > 0 {
+            tracing::info!(trained, "Category models retrained");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use crate::handlers::{self, AppState};
+use crate::models::audit_models::AuditEntryCreate;
+use crate::models::auth_models::AuthUser;
+use crate::models::reconciliation_models::TransactionLocked;
+use crate::models::transaction_models::{TransactionCategory, TransactionSource, TransactionType};
+use crate::queries::{telegram_queries, transaction_queries, user_queries};
+use crate::quick_entry::{self, QuickEntry};
+use crate::quotas;
+use crate::restrictions;
+use crate::signatures::{self, WebhookVerifier};
+use crate::validation::{Validate, ValidationErrors};
+use axum::{Extension, Json, Router, extract::State, http::StatusCode, middleware, routing::post};
+use chrono::{Duration, Utc};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// How long a link code can be used
+const LINK_CODE_MINUTES: i64 = 15;
+
+/// Callback data of the category buttons: "cat:<transaction id>:<category>"
+const CATEGORY_CALLBACK_PREFIX: &str = "cat:";
+
+/// Telegram bot letting linked users record expenses by sending "12.50 lunch"
+pub struct TelegramBot {
+    pub state: AppState,
+    pub token: String,
+    /// Checks the secret given to setWebhook, without it anyone could forge updates
+    pub verifier: Arc<WebhookVerifier>,
+    pub client: reqwest::Client,
+}
+
+impl TelegramBot {
+    /// Routes for the webhook and for handing out link codes
+    pub fn router<S>(self) -> Router<S> {
+        Router::new()
+            .route("/api/integrations/telegram/webhook", post(webhook))
+            .route_layer(middleware::from_fn_with_state(
+                self.verifier.clone(),
+                signatures::require_signature,
+            ))
+            .route("/api/integrations/telegram/link", post(create_link_code))
+            .with_state(Arc::new(self))
+    }
+
+    /// Call a Bot API method, failures are logged and otherwise ignored
+    async fn call(&self, method: &str, body: Value) {
+        let url = format!("https://api.telegram.org/bot{}/{}", self.token, method);
+        match self.client.post(url).json(&body).send().await {
+            Ok(res) if res.status().is_success() => {}
+            Ok(res) => eprintln!("Telegram {} failed with {}", method, res.status()),
+            // reqwest errors include the URL, which contains the token
+            Err(e) => eprintln!("Telegram {} failed: {}", method, e.without_url()),
+        }
+    }
+
+    async fn reply(&self, chat_id: i64, text: &str) {
+        self.call("sendMessage", json!({ "chat_id": chat_id, "text": text }))
+            .await;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    message: Option<Message>,
+    callback_query: Option<CallbackQuery>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    message_id: i64,
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    id: String,
+    data: Option<String>,
+    message: Option<Message>,
+}
+
+/// POST /api/integrations/telegram/webhook - updates pushed by Telegram
+/// Always answers 200 once authenticated, Telegram would otherwise redeliver the update
+async fn webhook(State(bot): State<Arc<TelegramBot>>, Json(update): Json<Update>) -> StatusCode {
+    if let Some(message) = update.message {
+        // Group members must not be able to write to someone's wallet
+        if message.chat.kind == "private"
+            && let Some(text) = &message.text
+        {
+            handle_message(&bot, message.chat.id, text.trim()).await;
+        }
+    } else if let Some(callback) = update.callback_query {
+        handle_callback(&bot, callback).await;
+    }
+    StatusCode::OK
+}
+
+async fn handle_message(bot: &TelegramBot, chat_id: i64, text: &str) {
+    if let Some(command) = text.strip_prefix('/') {
+        let (command, argument) = command.split_once(' ').unwrap_or((command, ""));
+        // Commands in groups may carry the bot name, e.g. /start@wallet_bot
+        match command.split('@').next().unwrap_or_default() {
+            "start" if !argument.trim().is_empty() => link(bot, chat_id, argument.trim()).await,
+            "start" | "help" => bot.reply(chat_id, &help()).await,
+            _ => bot.reply(chat_id, "Unknown command, send /help").await,
+        }
+        return;
+    }
+
+    let user_id = match telegram_queries::get_chat_user(&bot.state.db, chat_id).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return bot.reply(chat_id, NOT_LINKED).await,
+        Err(e) => {
+            eprintln!("Error looking up Telegram chat: {}", e);
+            return bot
+                .reply(chat_id, "Something went wrong, try again later")
+                .await;
+        }
+    };
+
+    let Some(entry) = QuickEntry::parse(text) else {
+        return bot.reply(chat_id, &help()).await;
+    };
+    let mut errors = ValidationErrors::default();
+    entry.validate(&mut errors);
+    if !errors.is_empty() {
+        return bot
+            .reply(chat_id, "That amount or description is out of range")
+            .await;
+    }
+
+    let category_given = entry.category.is_some();
+    let transaction = entry.into_transaction(user_id, TransactionSource::Telegram);
+    let transactions = std::slice::from_ref(&transaction);
+    let checked = match restrictions::check(&bot.state.db, transactions, false).await {
+        Ok(checked) => checked,
+        Err(e) => {
+            eprintln!("Error checking restrictions for Telegram: {}", e);
+            return bot
+                .reply(chat_id, "Could not save that, try again later")
+                .await;
+        }
+    };
+    if let Some(refusal) = checked.refusal() {
+        return bot.reply(chat_id, &refusal.to_string()).await;
+    }
+    match quotas::reserve_write(&bot.state.db, user_id, 1).await {
+        Ok(None) => {}
+        Ok(Some(exceeded)) => return bot.reply(chat_id, &exceeded.to_string()).await,
+        Err(e) => {
+            eprintln!("Error checking quotas for Telegram: {}", e);
+            return bot
+                .reply(chat_id, "Could not save that, try again later")
+                .await;
+        }
+    }
+    let transaction_id =
+        match transaction_queries::create_transaction(&bot.state.db, &transaction).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                quotas::release_transactions(&bot.state.db, user_id, 1).await;
+                return;
+            }
+            Err(e) => {
+                quotas::release_transactions(&bot.state.db, user_id, 1).await;
+                eprintln!("Error creating transaction from Telegram: {}", e);
+                return bot
+                    .reply(chat_id, "Could not save that, try again later")
+                    .await;
+            }
+        };
+
+    handlers::record_audit(
+        &bot.state,
+        AuditEntryCreate::new("create", "transaction", Some(transaction_id))
+            .actor(user_id)
+            .details(json!({
+                "transaction_type": transaction.transaction_type,
+                "amount": transaction.amount,
+                "category": transaction.category,
+                "source": transaction.source,
+            })),
+    )
+    .await;
+
+    if category_given {
+        return bot
+            .reply(
+                chat_id,
+                &format!(
+                    "Recorded {} of {}: {}\nCategory: {}",
+                    kind(&transaction.transaction_type),
+                    transaction.amount.abs(),
+                    transaction.description,
+                    transaction.category
+                ),
+            )
+            .await;
+    }
+
+    // Saved as Other until a category button is pressed
+    let buttons: Vec<Value> = TransactionCategory::ALL
+        .iter()
+        .map(|category| {
+            json!({
+                "text": category.to_string(),
+                "callback_data": format!("{}{}:{}", CATEGORY_CALLBACK_PREFIX, transaction_id, category),
+            })
+        })
+        .collect();
+    let keyboard: Vec<&[Value]> = buttons.chunks(3).collect();
+    bot.call(
+        "sendMessage",
+        json!({
+            "chat_id": chat_id,
+            "text": format!(
+                "Recorded {} of {}: {}\nPick a category:",
+                kind(&transaction.transaction_type),
+                transaction.amount.abs(),
+                transaction.description
+            ),
+            "reply_markup": { "inline_keyboard": keyboard },
+        }),
+    )
+    .await;
+}
+
+async fn link(bot: &TelegramBot, chat_id: i64, code: &str) {
+    match telegram_queries::link_chat(&bot.state.db, code, chat_id).await {
+        Ok(Some(_)) => {
+            bot.reply(
+                chat_id,
+                "Linked! Send an amount and a note, e.g. 12.50 lunch",
+            )
+            .await
+        }
+        Ok(None) => {
+            bot.reply(chat_id, "That link code is invalid or expired")
+                .await
+        }
+        Err(e) => {
+            eprintln!("Error linking Telegram chat: {}", e);
+            bot.reply(chat_id, "Something went wrong, try again later")
+                .await
+        }
+    }
+}
+
+/// A category button was pressed under a recorded transaction
+async fn handle_callback(bot: &TelegramBot, callback: CallbackQuery) {
+    let answer = match set_category(bot, &callback).await {
+        Some(category) => {
+            if let Some(message) = &callback.message {
+                let text = message.text.as_deref().unwrap_or_default();
+                let text = text.trim_end_matches("Pick a category:").trim_end();
+                bot.call(
+                    "editMessageText",
+                    json!({
+                        "chat_id": message.chat.id,
+                        "message_id": message.message_id,
+                        "text": format!("{}\nCategory: {}", text, category),
+                    }),
+                )
+                .await;
+            }
+            format!("Saved as {}", category)
+        }
+        None => "Could not update that transaction".to_string(),
+    };
+    bot.call(
+        "answerCallbackQuery",
+        json!({ "callback_query_id": callback.id, "text": answer }),
+    )
+    .await;
+}
+
+async fn set_category(bot: &TelegramBot, callback: &CallbackQuery) -> Option<TransactionCategory> {
+    let data = callback
+        .data
+        .as_deref()?
+        .strip_prefix(CATEGORY_CALLBACK_PREFIX)?;
+    let (transaction_id, category) = data.split_once(':')?;
+    let transaction_id: Uuid = transaction_id.parse().ok()?;
+    let category = TransactionCategory::from_str(category).ok()?;
+    let chat_id = callback.message.as_ref()?.chat.id;
+
+    // Only the linked user's own transactions can be changed
+    let user_id = telegram_queries::get_chat_user(&bot.state.db, chat_id)
+        .await
+        .ok()??;
+    match transaction_queries::update_category(&bot.state.db, transaction_id, user_id, &category)
+        .await
+    {
+        Ok(true) => Some(category),
+        Ok(false) => None,
+        Err(e) if e.is::<TransactionLocked>() => None,
+        Err(e) => {
+            eprintln!("Error updating category from Telegram: {}", e);
+            None
+        }
+    }
+}
+
+const HELP: &str = "Send an amount and a note to record an expense, e.g.";
+
+fn kind(transaction_type: &TransactionType) -> &'static str {
+    match transaction_type {
+        TransactionType::Income => "income",
+        TransactionType::Expense => "expense",
+    }
+}
+
+fn help() -> String {
+    format!("{}\n{}", HELP, quick_entry::EXAMPLES)
+}
+
+const NOT_LINKED: &str = "This chat is not linked to a wallet yet. \
+    Get a link code from the app and send /start <code>";
+
+#[derive(Deserialize)]
+struct LinkCodeRequest {
+    /// Only read when access tokens are disabled, the token's user is linked otherwise
+    user_id: Option<Uuid>,
+}
+
+/// POST /api/integrations/telegram/link - one-time code the user sends to the bot as "/start <code>"
+async fn create_link_code(
+    State(bot): State<Arc<TelegramBot>>,
+    auth_user: Option<Extension<AuthUser>>,
+    Json(req): Json<LinkCodeRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let user_id = match (auth_user, req.user_id) {
+        (Some(Extension(user)), _) => user.id,
+        (None, Some(user_id)) => user_id,
+        (None, None) => return Err(StatusCode::BAD_REQUEST),
+    };
+    let exists = user_queries::user_exists(&bot.state.db, user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Error checking user for Telegram link: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let code: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect();
+    let expires_at = Utc::now() + Duration::minutes(LINK_CODE_MINUTES);
+    telegram_queries::set_link_code(&bot.state.db, user_id, &code, expires_at)
+        .await
+        .map_err(|e| {
+            eprintln!("Error storing Telegram link code: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(json!({
+        "message": "Send the command to the bot to link your Telegram account",
+        "command": format!("/start {}", code),
+        "expires_at": expires_at
+    })))
+}
@@ -0,0 +1,337 @@
+use axum::{
+    extract::MatchedPath,
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::postgres::{PgQueryResult, PgRow};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Database work done while serving a single request
+#[derive(Debug, Default, Clone, Copy)]
+struct RequestDbStats {
+    queries: u64,
+    rows: u64,
+    db_time: Duration,
+}
+
+/// Incoming W3C trace context header
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Header returning the server's span to the caller (W3C Trace Context Level 2)
+const TRACERESPONSE_HEADER: &str = "traceresponse";
+
+/// W3C trace context of the span handling the current request
+/// Continues the caller's trace when a valid `traceparent` header was sent,
+/// otherwise starts a new one
+#[derive(Debug, Clone)]
+pub struct TraceContext {
+    /// 32 lowercase hex characters, shared by every span of the trace
+    pub trace_id: String,
+    /// 16 lowercase hex characters identifying this server's span
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+fn random_hex(bytes: usize) -> String {
+    (0..bytes)
+        .map(|_| format!("{:02x}", rand::random::<u8>()))
+        .collect()
+}
+
+fn is_valid_id(id: &str, len: usize) -> bool {
+    id.len() == len
+        && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && id.bytes().any(|b| b != b'0')
+}
+
+impl TraceContext {
+    /// Child of the caller's span, or the root of a new trace when the header
+    /// is missing or malformed (invalid headers must be ignored, not rejected)
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let parent = headers
+            .get(TRACEPARENT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                // version-trace_id-parent_id-flags, future versions may append fields
+                let mut fields = v.trim().split('-');
+                let version = fields.next()?;
+                let trace_id = fields.next()?;
+                let parent_id = fields.next()?;
+                let flags = fields.next()?;
+                let valid = version.len() == 2
+                    && version != "ff"
+                    && (version != "00" || fields.next().is_none())
+                    && is_valid_id(trace_id, 32)
+                    && is_valid_id(parent_id, 16)
+                    && flags.len() == 2;
+                let flags = u8::from_str_radix(flags, 16).ok()?;
+                valid.then(|| (trace_id.to_string(), flags & 1 == 1))
+            });
+
+        match parent {
+            Some((trace_id, sampled)) => TraceContext {
+                trace_id,
+                span_id: random_hex(8),
+                sampled,
+            },
+            None => TraceContext {
+                trace_id: random_hex(16),
+                span_id: random_hex(8),
+                sampled: true,
+            },
+        }
+    }
+
+    /// `traceparent` value naming this span as the parent,
+    /// to be attached to every outbound call made while serving the request
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { "01" } else { "00" }
+        )
+    }
+}
+
+/// Per-request state available to any code running inside the request's task
+struct RequestContext {
+    /// Route being served, e.g. "GET /api/transactions"
+    endpoint: String,
+    trace: TraceContext,
+    db: RefCell<RequestDbStats>,
+}
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+/// Aggregated database metrics for one endpoint since startup
+#[derive(Debug, Default, Clone, Copy)]
+struct EndpointDbMetrics {
+    requests: u64,
+    queries: u64,
+    rows: u64,
+    db_time: Duration,
+    max_queries_per_request: u64,
+}
+
+/// Metrics for every endpoint, keyed by route (sorted for stable output)
+static ENDPOINT_METRICS: Mutex<BTreeMap<String, EndpointDbMetrics>> = Mutex::new(BTreeMap::new());
+
+/// Queries slower than this are logged, installed once at startup
+static SLOW_QUERY_THRESHOLD: OnceLock<Duration> = OnceLock::new();
+
+/// Set the slow query threshold, must be called once at startup
+pub fn init_slow_query_threshold(threshold: Duration) {
+    if SLOW_QUERY_THRESHOLD.set(threshold).is_err() {
+        eprintln!("Slow query threshold already initialized, ignoring");
+    }
+}
+
+/// Middleware remembering which route is being served and which trace it belongs to,
+/// so code deep in the query layer can attribute its work to an endpoint
+/// Once the request is done its database usage is added to the endpoint's metrics
+pub async fn track_endpoint(
+    matched_path: Option<MatchedPath>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // Use the route template rather than the raw URI so ids don't explode cardinality
+    let path = matched_path
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let context = RequestContext {
+        endpoint: format!("{} {}", req.method(), path),
+        trace: TraceContext::from_headers(req.headers()),
+        db: RefCell::new(RequestDbStats::default()),
+    };
+
+    REQUEST_CONTEXT
+        .scope(context, async move {
+            let mut response = next.run(req).await;
+            REQUEST_CONTEXT.with(|ctx| {
+                record_request(&ctx.endpoint, *ctx.db.borrow());
+                // Lets the caller find this server's span in the trace
+                if let Ok(value) = HeaderValue::from_str(&ctx.trace.traceparent()) {
+                    response.headers_mut().insert(TRACERESPONSE_HEADER, value);
+                }
+            });
+            response
+        })
+        .await
+}
+
+fn record_request(endpoint: &str, stats: RequestDbStats) {
+    let mut metrics = ENDPOINT_METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = metrics.entry(endpoint.to_string()).or_default();
+    entry.requests += 1;
+    entry.queries += stats.queries;
+    entry.rows += stats.rows;
+    entry.db_time += stats.db_time;
+    entry.max_queries_per_request = entry.max_queries_per_request.max(stats.queries);
+}
+
+/// The endpoint being served by the current task, if any
+pub fn current_endpoint() -> Option<String> {
+    REQUEST_CONTEXT.try_with(|ctx| ctx.endpoint.clone()).ok()
+}
+
+/// Trace context of the request served by the current task, if any
+/// Outbound HTTP clients should send `traceparent()` in the `traceparent` header
+pub fn current_trace() -> Option<TraceContext> {
+    REQUEST_CONTEXT.try_with(|ctx| ctx.trace.clone()).ok()
+}
+
+/// Number of rows produced by a query result, for metrics
+pub trait RowCount {
+    fn row_count(&self) -> u64;
+}
+
+impl RowCount for PgRow {
+    fn row_count(&self) -> u64 {
+        1
+    }
+}
+
+impl RowCount for PgQueryResult {
+    fn row_count(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> u64 {
+        self.is_some() as u64
+    }
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<A> RowCount for (A,) {
+    fn row_count(&self) -> u64 {
+        1
+    }
+}
+
+/// Run a query future, count it towards the current request's metrics and
+/// log it if it takes longer than the slow query threshold
+///
+/// `sql` should be the statement with its `$n` placeholders, never with values inlined.
+/// `filters` is only evaluated for slow queries and must already be redacted.
+pub async fn observe_query<F, R, E>(
+    sql: &str,
+    filters: impl FnOnce() -> String,
+    query: F,
+) -> Result<R, E>
+where
+    F: Future<Output = Result<R, E>>,
+    R: RowCount,
+{
+    let started = Instant::now();
+    let result = query.await;
+    let elapsed = started.elapsed();
+
+    let rows = result.as_ref().map(RowCount::row_count).unwrap_or(0);
+    let _ = REQUEST_CONTEXT.try_with(|ctx| {
+        let mut stats = ctx.db.borrow_mut();
+        stats.queries += 1;
+        stats.rows += rows;
+        stats.db_time += elapsed;
+    });
+
+    if let Some(threshold) = SLOW_QUERY_THRESHOLD.get()
+        && elapsed >= *threshold
+    {
+        log::warn!(
+            "Slow query ({} ms) on {} [trace {}]: {} | filters: {}",
+            elapsed.as_millis(),
+            current_endpoint().unwrap_or_else(|| "background".to_string()),
+            current_trace()
+                .map(|t| t.trace_id)
+                .unwrap_or_else(|| "-".to_string()),
+            sql,
+            filters()
+        );
+    }
+
+    result
+}
+
+/// `observe_query` for statements without filter arguments worth logging
+pub async fn observe<F, R, E>(sql: &str, query: F) -> Result<R, E>
+where
+    F: Future<Output = Result<R, E>>,
+    R: RowCount,
+{
+    observe_query(sql, String::new, query).await
+}
+
+/// Render the per-endpoint metrics in the Prometheus text exposition format
+pub fn render_metrics() -> String {
+    let metrics = ENDPOINT_METRICS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+
+    type Getter = fn(&EndpointDbMetrics) -> String;
+    let families: [(&str, &str, &str, Getter); 5] = [
+        (
+            "wallet_endpoint_requests_total",
+            "counter",
+            "Requests served per endpoint",
+            |m| m.requests.to_string(),
+        ),
+        (
+            "wallet_endpoint_db_queries_total",
+            "counter",
+            "Database queries issued per endpoint",
+            |m| m.queries.to_string(),
+        ),
+        (
+            "wallet_endpoint_db_rows_total",
+            "counter",
+            "Rows returned or affected by database queries per endpoint",
+            |m| m.rows.to_string(),
+        ),
+        (
+            "wallet_endpoint_db_time_seconds_total",
+            "counter",
+            "Time spent waiting on the database per endpoint",
+            |m| m.db_time.as_secs_f64().to_string(),
+        ),
+        (
+            "wallet_endpoint_db_queries_per_request_max",
+            "gauge",
+            "Most database queries issued by a single request per endpoint",
+            |m| m.max_queries_per_request.to_string(),
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, value) in families {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (endpoint, m) in &metrics {
+            let _ = writeln!(
+                out,
+                "{}{{endpoint=\"{}\"}} {}",
+                name,
+                endpoint.replace('\\', "\\\\").replace('"', "\\\""),
+                value(m)
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,73 @@
+// Throttling of unauthenticated lookups
+//
+// Endpoints anyone may call without a token, such as the email availability check of
+// signup forms, would let a caller probe for data one request at a time. A `Throttle`
+// allows each client address a few calls per window and answers the rest with 429 Too
+// Many Requests and a Retry-After. Counts are kept in memory per server instance, clients
+// whose address is unknown (e.g. on Lambda) share one count.
+
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Clients tracked before the ones whose window is over are dropped
+const PRUNE_AT: usize = 10_000;
+
+/// At most `max` calls per client address in every `window`
+pub struct Throttle {
+    max: u32,
+    window: Duration,
+    /// Start of each client's current window and the calls made in it
+    calls: Mutex<HashMap<Option<IpAddr>, (Instant, u32)>>,
+}
+
+impl Throttle {
+    pub fn new(max: u32, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a call of the client, returning the 429 response to send instead when it
+    /// made too many
+    pub fn check(&self, client: Option<IpAddr>) -> Option<Response> {
+        let now = Instant::now();
+        let mut calls = match self.calls.lock() {
+            Ok(calls) => calls,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if calls.len() >= PRUNE_AT {
+            calls.retain(|_, (started, _)| now.duration_since(*started) < self.window);
+        }
+        let (started, count) = calls.entry(client).or_insert((now, 0));
+        if now.duration_since(*started) >= self.window {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= self.max {
+            let retry_after = self.window.saturating_sub(now.duration_since(*started));
+            return Some(
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(
+                        header::RETRY_AFTER,
+                        retry_after.as_secs().max(1).to_string(),
+                    )],
+                    Json(json!({ "message": "Too many requests, try again later" })),
+                )
+                    .into_response(),
+            );
+        }
+        *count += 1;
+        None
+    }
+}
@@ -0,0 +1,190 @@
+use crate::models::money_models::Money;
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request, rejection::JsonRejection},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt;
+
+/// Longest accepted email address (RFC 5321 allows 254 characters)
+pub const MAX_EMAIL_LEN: usize = 254;
+
+/// Longest accepted user, bank account or institution name
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Longest accepted password, hashing very long inputs is needlessly expensive
+pub const MAX_PASSWORD_LEN: usize = 1024;
+
+/// Longest accepted transaction description
+pub const MAX_DESCRIPTION_LEN: usize = 1000;
+
+/// Longest accepted client-side encrypted value (description or wrapped key), same as the columns
+pub const MAX_CIPHERTEXT_LEN: usize = 8192;
+
+/// Longest accepted category, type or source string before it is parsed
+pub const MAX_CATEGORY_LEN: usize = 50;
+
+/// Longest accepted external id, same as the column
+pub const MAX_EXTERNAL_ID_LEN: usize = 255;
+
+/// Longest accepted webhook URL, same as the column
+pub const MAX_URL_LEN: usize = 2048;
+
+/// Largest accepted amount in major units (e.g. euros), positive or negative
+pub const MAX_AMOUNT: i64 = 1_000_000_000;
+
+/// Most transactions accepted in one batch import
+pub const MAX_BATCH_SIZE: usize = 5000;
+
+/// A problem with one field of a request
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Everything wrong with a request, reported together as a 422
+#[derive(Debug, Default)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// At most `max` characters
+    pub fn check_length(&mut self, field: &str, value: &str, max: usize) {
+        let len = value.chars().count();
+        if len > max {
+            self.add(
+                field,
+                format!("must be at most {} characters, got {}", max, len),
+            );
+        }
+    }
+
+    /// Not empty or whitespace only, and at most `max` characters
+    pub fn check_required(&mut self, field: &str, value: &str, max: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "must not be empty");
+        } else {
+            self.check_length(field, value, max);
+        }
+    }
+
+    pub fn check_email(&mut self, field: &str, value: &str) {
+        self.check_required(field, value, MAX_EMAIL_LEN);
+        if !value.trim().is_empty() && !value.contains('@') {
+            self.add(field, "must be an email address");
+        }
+    }
+
+    /// Opaque client-side ciphertext: printable ASCII without spaces (e.g. base64),
+    /// not empty and at most `max` characters
+    pub fn check_ciphertext(&mut self, field: &str, value: &str, max: usize) {
+        if value.is_empty() {
+            self.add(field, "must not be empty");
+        } else if !value.bytes().all(|b| b.is_ascii_graphic()) {
+            self.add(field, "must be printable ASCII without spaces, e.g. base64");
+        } else {
+            self.check_length(field, value, max);
+        }
+    }
+
+    /// An IANA time zone name such as "Europe/Athens"
+    pub fn check_timezone(&mut self, field: &str, value: &str) {
+        if value.parse::<Tz>().is_err() {
+            self.add(field, "must be an IANA time zone name, e.g. Europe/Athens");
+        }
+    }
+
+    /// Non-zero and within the accepted range
+    pub fn check_amount(&mut self, field: &str, amount: Money) {
+        if amount.is_zero() {
+            self.add(field, "must not be zero");
+        } else if amount.to_decimal().abs() > Decimal::from(MAX_AMOUNT) {
+            self.add(
+                field,
+                format!("must be at most {} {}", MAX_AMOUNT, amount.currency),
+            );
+        }
+    }
+}
+
+/// The problems on one line, e.g. "email must be a valid email address; name is required"
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{} {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({
+                "message": "Validation failed",
+                "errors": self.0
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Request bodies that check their own bounds after deserialization
+pub trait Validate {
+    fn validate(&self, errors: &mut ValidationErrors);
+}
+
+/// JSON body extractor that also runs `Validate`
+/// Bodies that are well-formed JSON but have the wrong shape or values are rejected
+/// with a 422 listing the problems, malformed JSON keeps axum's 400
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    T: Validate,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = match Json::<T>::from_request(req, state).await {
+            Ok(json) => json,
+            Err(JsonRejection::JsonDataError(e)) => {
+                let mut errors = ValidationErrors::default();
+                errors.add("body", e.body_text());
+                return Err(errors.into_response());
+            }
+            Err(rejection) => return Err(rejection.into_response()),
+        };
+
+        let mut errors = ValidationErrors::default();
+        value.validate(&mut errors);
+        if errors.is_empty() {
+            Ok(ValidJson(value))
+        } else {
+            Err(errors.into_response())
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::auth;
+use crate::handlers::AppState;
+use crate::models::money_models::MoneyTotals;
+use crate::models::transaction_models::{
+    Period, TransactionFilter, TransactionSort, TransactionType, local_midnight,
+};
+use crate::queries::{reward_queries, transaction_queries, user_queries};
+use crate::redact;
+use crate::rewards;
+use askama::Template;
+use axum::{
+    Form,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+/// Cookie holding the signed session of a logged in user
+const SESSION_COOKIE: &str = "wallet_session";
+
+/// How long a login lasts
+const SESSION_DAYS: i64 = 7;
+
+/// Key signing session cookies, installed once at startup
+static SESSION_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Set the session signing key, must be called once at startup
+/// Without a configured key a random one is used, so sessions end when the server restarts
+pub fn init_session_key(key: Option<&str>) {
+    let key = match key {
+        Some(key) => key.as_bytes().to_vec(),
+        None => (0..32).map(|_| rand::random::<u8>()).collect(),
+    };
+    if SESSION_KEY.set(key).is_err() {
+        eprintln!("Session key already initialized, ignoring");
+    }
+}
+
+fn session_mac(payload: &str) -> Hmac<Sha256> {
+    let key = SESSION_KEY.get().map(Vec::as_slice).unwrap_or_default();
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Cookie value "user_id.expires_at.signature"
+fn session_cookie(user_id: Uuid) -> String {
+    let expires_at = (Utc::now() + Duration::days(SESSION_DAYS)).timestamp();
+    let payload = format!("{}.{}", user_id, expires_at);
+    format!(
+        "{}={}.{}; Path=/ui; HttpOnly; SameSite=Lax; Max-Age={}",
+        SESSION_COOKIE,
+        payload,
+        BASE64.encode(session_mac(&payload).finalize().into_bytes()),
+        SESSION_DAYS * 24 * 60 * 60
+    )
+}
+
+/// The logged in user, if the request carries a valid, unexpired session cookie
+fn session_user(headers: &HeaderMap) -> Option<Uuid> {
+    let value = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)?;
+
+    let (payload, signature) = value.rsplit_once('.')?;
+    // verify_slice compares in constant time
+    session_mac(payload)
+        .verify_slice(&BASE64.decode(signature).ok()?)
+        .ok()?;
+
+    let (user_id, expires_at) = payload.split_once('.')?;
+    if expires_at.parse::<i64>().ok()? < Utc::now().timestamp() {
+        return None;
+    }
+    user_id.parse().ok()
+}
+
+fn render(template: impl Template) -> Response {
+    match template.render() {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            eprintln!("Failed to render template: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// A calendar month picked with `?month=YYYY-MM`, defaulting to the current one
+struct Month {
+    first_day: NaiveDate,
+}
+
+impl Month {
+    /// The current month is the one it is in `tz`
+    fn parse(month: Option<&str>, tz: Tz) -> Self {
+        let first_day = month
+            .and_then(|m| NaiveDate::parse_from_str(&format!("{}-01", m), "%Y-%m-%d").ok())
+            .unwrap_or_else(|| {
+                let today = Utc::now().with_timezone(&tz).date_naive();
+                today.with_day(1).unwrap_or(today)
+            });
+        Self { first_day }
+    }
+
+    fn next_first_day(&self) -> NaiveDate {
+        self.first_day
+            .checked_add_months(chrono::Months::new(1))
+            .unwrap_or(self.first_day)
+    }
+
+    /// Start and (inclusive) end of the month in `tz`
+    fn range(&self, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = local_midnight(self.first_day, tz);
+        let end = local_midnight(self.next_first_day(), tz) - Duration::microseconds(1);
+        (start, end)
+    }
+
+    fn nav(&self) -> MonthNav {
+        let previous = self
+            .first_day
+            .checked_sub_months(chrono::Months::new(1))
+            .unwrap_or(self.first_day);
+        MonthNav {
+            previous: previous.format("%Y-%m").to_string(),
+            current: self.first_day.format("%Y-%m").to_string(),
+            next: self.next_first_day().format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Links to the neighbouring months
+struct MonthNav {
+    previous: String,
+    current: String,
+    next: String,
+}
+
+#[derive(Deserialize)]
+pub struct MonthParams {
+    month: Option<String>,
+    /// Show a preset range such as `last_30_days` instead of a calendar month
+    period: Option<Period>,
+}
+
+/// The range a page covers, a preset when one was asked for and the month otherwise
+/// Returns the label of the preset, if any, along with the bounds
+async fn page_range(
+    state: &AppState,
+    user_id: Uuid,
+    params: &MonthParams,
+) -> (Month, Option<String>, DateTime<Utc>, DateTime<Utc>) {
+    let tz = user_queries::get_user_timezone(&state.db, user_id)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load time zone for HTML view, using UTC: {}", e);
+            Tz::UTC
+        });
+    let month = Month::parse(params.month.as_deref(), tz);
+    match params.period {
+        Some(period) => {
+            let (start, end) = period.range(Utc::now(), tz);
+            (month, Some(period.to_string()), start, end)
+        }
+        None => {
+            let (start, end) = month.range(tz);
+            (month, None, start, end)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    email: String,
+    password: String,
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginPage {
+    email: String,
+    error: Option<String>,
+}
+
+struct TransactionRow {
+    date: String,
+    category: String,
+    description: String,
+    amount: String,
+    /// "income" or "expense", used for styling
+    kind: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "transactions.html")]
+struct TransactionsPage {
+    month: MonthNav,
+    period: Option<String>,
+    transactions: Vec<TransactionRow>,
+}
+
+struct CategoryTotal {
+    category: String,
+    count: usize,
+    total: String,
+}
+
+#[derive(Template)]
+#[template(path = "report.html")]
+struct ReportPage {
+    month: MonthNav,
+    period: Option<String>,
+    income: String,
+    expenses: String,
+    net: String,
+    /// Expected from card reward rules, None for users without any
+    cashback: Option<String>,
+    categories: Vec<CategoryTotal>,
+}
+
+/// GET /ui/login
+pub async fn login_page() -> Response {
+    render(LoginPage {
+        email: String::new(),
+        error: None,
+    })
+}
+
+/// POST /ui/login - check credentials and start a session
+pub async fn login(State(state): State<AppState>, Form(form): Form<LoginForm>) -> Response {
+    let user = match user_queries::get_user(&state.db, &form.email).await {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!(
+                "Error fetching user '{}': {}",
+                redact::email(&form.email),
+                e
+            );
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    match user {
+        Some(user) if auth::verify_password(&form.password, &user.password) => (
+            [(header::SET_COOKIE, session_cookie(user.id))],
+            Redirect::to("/ui/transactions"),
+        )
+            .into_response(),
+        _ => {
+            eprintln!("Failed login for {}", redact::email(&form.email));
+            let page = render(LoginPage {
+                email: form.email,
+                error: Some("Invalid email or password".to_string()),
+            });
+            (StatusCode::UNAUTHORIZED, page).into_response()
+        }
+    }
+}
+
+/// POST /ui/logout
+pub async fn logout() -> Response {
+    (
+        [(
+            header::SET_COOKIE,
+            format!(
+                "{}=; Path=/ui; HttpOnly; SameSite=Lax; Max-Age=0",
+                SESSION_COOKIE
+            ),
+        )],
+        Redirect::to("/ui/login"),
+    )
+        .into_response()
+}
+
+/// GET /ui/transactions?month=YYYY-MM (or ?period=...) - the user's transactions for one month, newest first
+pub async fn transactions_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<MonthParams>,
+) -> Response {
+    let Some(user_id) = session_user(&headers) else {
+        return Redirect::to("/ui/login").into_response();
+    };
+    let (month, period, start, end) = page_range(&state, user_id, &params).await;
+
+    let filter = TransactionFilter::for_user(user_id)
+        .period(Some(start), Some(end))
+        .sort(TransactionSort::CreatedAtDesc);
+    let transactions = match transaction_queries::get_transactions(&state.db, &filter).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            eprintln!("Failed to load transactions for HTML view: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    render(TransactionsPage {
+        month: month.nav(),
+        period,
+        transactions: transactions
+            .into_iter()
+            .map(|t| TransactionRow {
+                date: t.created_at.format("%Y-%m-%d").to_string(),
+                category: t.category.to_string(),
+                description: t.description,
+                amount: t.amount.to_string(),
+                kind: match t.transaction_type {
+                    TransactionType::Income => "income",
+                    TransactionType::Expense => "expense",
+                },
+            })
+            .collect(),
+    })
+}
+
+/// GET /ui/report?month=YYYY-MM (or ?period=...) - income, expenses and spending per category for one month
+pub async fn report_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<MonthParams>,
+) -> Response {
+    let Some(user_id) = session_user(&headers) else {
+        return Redirect::to("/ui/login").into_response();
+    };
+    let (month, period, start, end) = page_range(&state, user_id, &params).await;
+
+    let filter = TransactionFilter::for_user(user_id).period(Some(start), Some(end));
+    let transactions = match transaction_queries::get_transactions(&state.db, &filter).await {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            eprintln!("Failed to load transactions for HTML report: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let rules = match reward_queries::get_rules(&state.db, user_id).await {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("Failed to load reward rules for HTML report: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // Totals are kept per currency, a month with EUR and USD spending shows both
+    let mut cashback = MoneyTotals::default();
+    let mut income = MoneyTotals::default();
+    let mut expenses = MoneyTotals::default();
+    let mut net = MoneyTotals::default();
+    let mut categories: BTreeMap<String, (usize, MoneyTotals)> = BTreeMap::new();
+    for t in &transactions {
+        net.add(t.amount);
+        match t.transaction_type {
+            // Amounts are signed by type, so expenses only need their sign flipped
+            TransactionType::Income => income.add(t.amount),
+            TransactionType::Expense => {
+                expenses.add(-t.amount);
+                let entry = categories.entry(t.category.to_string()).or_default();
+                entry.0 += 1;
+                entry.1.add(-t.amount);
+                if let Some(rate) = t
+                    .bank_account_id
+                    .and_then(|card| rewards::rate_for(&rules, card, &t.category))
+                {
+                    cashback.add(rewards::cashback(-t.amount, rate));
+                }
+            }
+        }
+    }
+
+    render(ReportPage {
+        month: month.nav(),
+        period,
+        income: income.to_string(),
+        expenses: expenses.to_string(),
+        net: net.to_string(),
+        cashback: (!rules.is_empty()).then(|| cashback.to_string()),
+        categories: categories
+            .into_iter()
+            .map(|(category, (count, total))| CategoryTotal {
+                category,
+                count,
+                total: total.to_string(),
+            })
+            .collect(),
+    })
+}
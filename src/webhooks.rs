@@ -0,0 +1,170 @@
+use crate::database::DbPool;
+use crate::events::{ChangeEvent, Subscriber};
+use crate::handlers::AppState;
+use crate::jobs::JobHandler;
+use crate::models::transaction_models::TransactionSource;
+use crate::models::webhook_models::DueDelivery;
+use crate::queries::webhook_queries;
+use axum::async_trait;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Header carrying "t=<unix timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">"
+pub const SIGNATURE_HEADER: &str = "X-Wallet-Signature";
+
+/// Attempts before a delivery is moved to failed, spread over about eight hours by the backoff below
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry, doubled after every failed attempt
+const BASE_BACKOFF_SECS: i64 = 30;
+
+/// Longest delay between two attempts
+const MAX_BACKOFF_SECS: i64 = 6 * 60 * 60;
+
+/// Most deliveries sent per worker tick
+const BATCH_SIZE: i64 = 50;
+
+/// A claimed delivery is not handed out again for this long, longer than the request timeout
+const LEASE_SECS: i64 = 120;
+
+/// How often due deliveries are sent
+pub const DELIVERY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Endpoints taking longer than this to answer count as failed
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Queue an event for the user's webhook endpoints
+/// A failure is logged but does not fail the request, like the audit log
+pub async fn enqueue(state: &AppState, user_id: Uuid, event: &str, data: Value) {
+    if let Err(e) = webhook_queries::enqueue(&state.db, user_id, event, &data).await {
+        tracing::error!(event, error = %e, "Queueing webhook failed");
+    }
+}
+
+/// Queues transaction.created, .updated, .deleted and .restored for every change of a transaction. Imported transactions are left out, imports announce
+/// themselves once with transactions.imported
+pub struct TransactionWebhooks;
+
+#[async_trait]
+impl Subscriber for TransactionWebhooks {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, db: &DbPool, event: &ChangeEvent) -> anyhow::Result<()> {
+        if event.source == Some(TransactionSource::Import) {
+            return Ok(());
+        }
+        webhook_queries::enqueue(db, event.user_id, &event.name(), &event.data).await?;
+        Ok(())
+    }
+}
+
+/// Delay before the attempt following `attempts` failed ones
+fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(0, 20) as u32;
+    Duration::seconds((BASE_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS))
+}
+
+fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!("t={},v1={:x}", timestamp, mac.finalize().into_bytes())
+}
+
+/// Send one delivery, returning the status code, or the error and the status code if any
+async fn send(
+    client: &reqwest::Client,
+    delivery: &DueDelivery,
+) -> Result<u16, (Option<u16>, String)> {
+    // The delivery id stays the same across retries, receivers can use it to drop duplicates
+    let body = serde_json::to_vec(&json!({
+        "id": delivery.id,
+        "event": delivery.event,
+        "created_at": delivery.created_at,
+        "data": delivery.payload,
+    }))
+    .map_err(|e| (None, e.to_string()))?;
+
+    let response = client
+        .post(&delivery.url)
+        .timeout(REQUEST_TIMEOUT)
+        .header("Content-Type", "application/json")
+        .header(
+            SIGNATURE_HEADER,
+            signature(&delivery.secret, Utc::now().timestamp(), &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err((
+            Some(status.as_u16()),
+            format!("endpoint answered {}", status),
+        ))
+    }
+}
+
+/// Send every due delivery once, returning how many were delivered
+async fn deliver_due(db: &DbPool, client: &reqwest::Client) -> anyhow::Result<usize> {
+    let mut delivered = 0;
+    for delivery in webhook_queries::claim_due(db, BATCH_SIZE, LEASE_SECS).await? {
+        match send(client, &delivery).await {
+            Ok(status_code) => {
+                webhook_queries::mark_delivered(db, delivery.id, status_code).await?;
+                delivered += 1;
+            }
+            Err((status_code, error)) => {
+                let attempts = delivery.attempts + 1;
+                let retry_at = (attempts < MAX_ATTEMPTS).then(|| Utc::now() + backoff(attempts));
+                if retry_at.is_none() {
+                    tracing::warn!(
+                        delivery = %delivery.id,
+                        attempts,
+                        error = %error,
+                        "Webhook delivery failed"
+                    );
+                }
+                webhook_queries::mark_attempt_failed(
+                    db,
+                    delivery.id,
+                    status_code,
+                    &error,
+                    retry_at,
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+/// Job sending every due delivery, queued every few seconds
+pub struct DeliverWebhooksJob {
+    pub client: reqwest::Client,
+}
+
+#[async_trait]
+impl JobHandler for DeliverWebhooksJob {
+    fn kind(&self) -> &'static str {
+        "webhooks.deliver"
+    }
+
+    async fn run(&self, db: &DbPool, _payload: &Value) -> anyhow::Result<()> {
+        let delivered = deliver_due(db, &self.client).await?;
+        if delivered > 0 {
+            tracing::info!(delivered, "Webhooks delivered");
+        }
+        Ok(())
+    }
+}